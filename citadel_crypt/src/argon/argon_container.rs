@@ -1,3 +1,4 @@
+use crate::misc::constant_time_eq;
 use crate::prelude::SecBuffer;
 use argon2::Config;
 use citadel_io::{BlockingSpawn, BlockingSpawnError};
@@ -5,6 +6,7 @@ use futures::Future;
 use rand::rngs::ThreadRng;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::fmt::{Debug, Formatter};
 use std::ops::Deref;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -34,17 +36,22 @@ impl AsyncArgon {
         Self { task }
     }
 
+    /// Hashes the proposed password, then compares it to the stored hash in constant time
     pub fn verify(proposed_password: SecBuffer, settings: ServerArgonContainer) -> Self {
         let task = citadel_io::spawn_blocking(move || {
-            match argon2::verify_raw(
+            match argon2::hash_raw(
                 proposed_password.as_ref(),
                 settings.settings.inner.salt.as_slice(),
-                settings.hashed_password.as_ref(),
                 &settings.settings.as_argon_config(),
             ) {
-                Ok(true) => ArgonStatus::VerificationSuccess,
-
-                Ok(false) => ArgonStatus::VerificationFailed(None),
+                Ok(hashed) => {
+                    let hashed = SecBuffer::from(hashed);
+                    if constant_time_eq(hashed.as_ref(), settings.hashed_password.as_ref()) {
+                        ArgonStatus::VerificationSuccess
+                    } else {
+                        ArgonStatus::VerificationFailed(None)
+                    }
+                }
 
                 Err(err) => ArgonStatus::VerificationFailed(Some(err.to_string())),
             }
@@ -275,7 +282,20 @@ const DEFAULT_TIME_COST: u32 = 10;
 #[cfg(debug_assertions)]
 const DEFAULT_TIME_COST: u32 = 1;
 
-#[derive(Debug, Clone)]
+/// The minimum memory cost (in KiB) accepted for server-configured settings
+#[cfg(not(debug_assertions))]
+pub const MIN_MEM_COST: u32 = 1024 * 19;
+#[cfg(debug_assertions)]
+pub const MIN_MEM_COST: u32 = 1024;
+/// The minimum number of iterations accepted for server-configured settings
+#[cfg(not(debug_assertions))]
+pub const MIN_TIME_COST: u32 = 2;
+#[cfg(debug_assertions)]
+pub const MIN_TIME_COST: u32 = 1;
+/// The minimum hash length accepted for server-configured settings
+pub const MIN_HASH_LENGTH: u32 = 32;
+
+#[derive(Clone)]
 pub struct ArgonDefaultServerSettings {
     pub lanes: u32,
     pub hash_length: u32,
@@ -284,6 +304,127 @@ pub struct ArgonDefaultServerSettings {
     pub secret: Vec<u8>,
 }
 
+impl ArgonDefaultServerSettings {
+    /// Ensures the cost parameters are no weaker than the enforced minimums
+    pub fn validate(&self) -> Result<(), String> {
+        if self.lanes == 0 {
+            return Err("Argon2 requires at least one lane".to_string());
+        }
+
+        if self.hash_length < MIN_HASH_LENGTH {
+            return Err(format!(
+                "Argon2 hash length {} is below the minimum of {}",
+                self.hash_length, MIN_HASH_LENGTH
+            ));
+        }
+
+        // argon2 requires at least 8 blocks per lane
+        let min_mem_cost = MIN_MEM_COST.max(8 * self.lanes);
+        if self.mem_cost < min_mem_cost {
+            return Err(format!(
+                "Argon2 memory cost {} KiB is below the minimum of {} KiB",
+                self.mem_cost, min_mem_cost
+            ));
+        }
+
+        if self.time_cost < MIN_TIME_COST {
+            return Err(format!(
+                "Argon2 time cost {} is below the minimum of {}",
+                self.time_cost, MIN_TIME_COST
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Raises any cost parameter below the enforced minimums to the minimum, warning about each,
+    /// such that weak configurations are strengthened instead of refused
+    pub fn enforce_minimums(mut self) -> Self {
+        if self.lanes == 0 {
+            log::warn!(target: "citadel", "Argon2 requires at least one lane; using 1");
+            self.lanes = 1;
+        }
+
+        if self.hash_length < MIN_HASH_LENGTH {
+            log::warn!(target: "citadel", "Argon2 hash length {} is below the minimum; using {MIN_HASH_LENGTH}", self.hash_length);
+            self.hash_length = MIN_HASH_LENGTH;
+        }
+
+        let min_mem_cost = MIN_MEM_COST.max(8 * self.lanes);
+        if self.mem_cost < min_mem_cost {
+            log::warn!(target: "citadel", "Argon2 memory cost {} KiB is below the minimum; using {min_mem_cost} KiB", self.mem_cost);
+            self.mem_cost = min_mem_cost;
+        }
+
+        if self.time_cost < MIN_TIME_COST {
+            log::warn!(target: "citadel", "Argon2 time cost {} is below the minimum; using {MIN_TIME_COST}", self.time_cost);
+            self.time_cost = MIN_TIME_COST;
+        }
+
+        self
+    }
+
+    /// The cost parameters of these settings, which registering clients are required to meet
+    pub fn policy(&self) -> ArgonCostParameters {
+        ArgonCostParameters {
+            lanes: self.lanes,
+            hash_length: self.hash_length,
+            mem_cost: self.mem_cost,
+            time_cost: self.time_cost,
+        }
+    }
+}
+
+/// The cost parameters of an Argon2 configuration, without its salt, AD or secret. A server
+/// advertises its own as the policy registering clients must hash their passwords under
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ArgonCostParameters {
+    pub lanes: u32,
+    pub hash_length: u32,
+    pub mem_cost: u32,
+    pub time_cost: u32,
+}
+
+impl ArgonCostParameters {
+    /// Ensures these parameters are at least as costly as `policy`. The number of lanes only
+    /// affects parallelism, hence, is not compared
+    pub fn satisfies(&self, policy: &ArgonCostParameters) -> Result<(), String> {
+        if self.hash_length < policy.hash_length
+            || self.mem_cost < policy.mem_cost
+            || self.time_cost < policy.time_cost
+        {
+            return Err(format!(
+                "Argon2 parameters {self:?} do not meet the policy {policy:?}"
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl From<&ArgonSettings> for ArgonCostParameters {
+    fn from(settings: &ArgonSettings) -> Self {
+        Self {
+            lanes: settings.lanes,
+            hash_length: settings.hash_length,
+            mem_cost: settings.mem_cost,
+            time_cost: settings.time_cost,
+        }
+    }
+}
+
+impl Debug for ArgonDefaultServerSettings {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ArgonDefaultServerSettings")
+            .field("lanes", &self.lanes)
+            .field("hash_length", &self.hash_length)
+            .field("mem_cost", &self.mem_cost)
+            .field("time_cost", &self.time_cost)
+            .field("has_secret", &!self.secret.is_empty())
+            .finish()
+    }
+}
+
 impl From<ArgonDefaultServerSettings> for ArgonSettings {
     fn from(settings: ArgonDefaultServerSettings) -> Self {
         // AD gets created when deriving a new settings container for the specific user during registration
@@ -0,0 +1,578 @@
+use crate::delta_sync::{read_up_to, rewind_source};
+use crate::misc::{CryptError, TransferType};
+use crate::streaming_crypt_scrambler::{FixedSizedSource, ObjectSource};
+use sha3::{Digest, Sha3_256};
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+/// The size of each content-addressed chunk of a deduplicated transfer
+pub const DEFAULT_CACHE_CHUNK_SIZE: usize = 64 * 1024;
+/// The largest chunk size accepted from the sender of a deduplicated transfer
+pub const MAX_CACHE_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+/// The content address of a single chunk
+pub type ChunkHash = [u8; 32];
+
+/// Prefixes a chunk the receiver already holds in its cache, followed by its hash
+const CHUNK_CACHED: u8 = 0;
+/// Prefixes a chunk transmitted verbatim, followed by its length and bytes
+const CHUNK_LITERAL: u8 = 1;
+
+/// An object alongside the hashes of its fixed-size chunks. The hashes are sent to the receiver,
+/// which replies with the chunks it already holds in its cache. Only the hashes are held in
+/// memory; the object itself is read once more when the transfer begins
+pub struct ChunkedObject {
+    pub name: String,
+    pub chunk_size: usize,
+    pub length: u64,
+    pub hash: [u8; 32],
+    pub hashes: Vec<ChunkHash>,
+    source: Box<dyn ObjectSource>,
+}
+
+impl ChunkedObject {
+    pub fn read<S: ObjectSource>(mut source: S, chunk_size: usize) -> Result<Self, CryptError> {
+        if chunk_size == 0 || chunk_size > MAX_CACHE_CHUNK_SIZE {
+            return Err(CryptError::Encrypt(format!(
+                "Chunk size must be between 1 and {MAX_CACHE_CHUNK_SIZE}"
+            )));
+        }
+
+        let name = source.get_source_name()?;
+        let mut stream = source.try_get_stream()?;
+        let mut hasher = Sha3_256::default();
+        let mut length = 0;
+        let mut hashes = Vec::new();
+        let mut chunk = vec![0u8; chunk_size];
+        loop {
+            let len = read_up_to(&mut stream, &mut chunk).map_err(encrypt_error)?;
+            if len == 0 {
+                break;
+            }
+
+            hasher.update(&chunk[..len]);
+            length += len as u64;
+            hashes.push(hash_chunk(&chunk[..len]));
+            if len < chunk_size {
+                break;
+            }
+        }
+
+        Ok(Self {
+            source: rewind_source(name.clone(), source, stream),
+            name,
+            chunk_size,
+            length,
+            hash: hasher.finalize().into(),
+            hashes,
+        })
+    }
+}
+
+/// Hashes each chunk of `content`. The last chunk may be shorter than `chunk_size`
+pub fn chunk_hashes(content: &[u8], chunk_size: usize) -> Vec<ChunkHash> {
+    content.chunks(chunk_size).map(hash_chunk).collect()
+}
+
+pub fn hash_chunk(chunk: &[u8]) -> ChunkHash {
+    Sha3_256::digest(chunk).into()
+}
+
+/// Splits a stream of bytes into chunks of a fixed size, such that the chunks of an object can be
+/// hashed while it streams. No more than one chunk is buffered at once
+pub struct ChunkSplitter {
+    chunk_size: usize,
+    buf: Vec<u8>,
+}
+
+impl ChunkSplitter {
+    pub fn new(chunk_size: usize) -> Self {
+        Self {
+            chunk_size,
+            buf: Vec::with_capacity(chunk_size),
+        }
+    }
+
+    /// Passes each chunk completed by `bytes` to `on_chunk`
+    pub fn push(&mut self, mut bytes: &[u8], mut on_chunk: impl FnMut(&[u8])) {
+        while !bytes.is_empty() {
+            // whole chunks are passed through without being copied
+            if self.buf.is_empty() && bytes.len() >= self.chunk_size {
+                let (chunk, rest) = bytes.split_at(self.chunk_size);
+                on_chunk(chunk);
+                bytes = rest;
+                continue;
+            }
+
+            let len = (self.chunk_size - self.buf.len()).min(bytes.len());
+            self.buf.extend_from_slice(&bytes[..len]);
+            bytes = &bytes[len..];
+            if self.buf.len() == self.chunk_size {
+                on_chunk(&self.buf);
+                self.buf.clear();
+            }
+        }
+    }
+
+    /// Passes the trailing partial chunk, if any, to `on_chunk`
+    pub fn finish(self, on_chunk: impl FnOnce(&[u8])) {
+        if !self.buf.is_empty() {
+            on_chunk(&self.buf)
+        }
+    }
+}
+
+/// Precedes the chunks of an encoded deduplicated object
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct DeduplicatedHeader {
+    chunk_size: u32,
+    target_length: u64,
+    target_hash: [u8; 32],
+}
+
+impl DeduplicatedHeader {
+    const LEN: usize = 4 + 8 + 32;
+
+    fn encode(&self) -> [u8; Self::LEN] {
+        let mut encoded = [0u8; Self::LEN];
+        encoded[..4].copy_from_slice(&self.chunk_size.to_le_bytes());
+        encoded[4..12].copy_from_slice(&self.target_length.to_le_bytes());
+        encoded[12..].copy_from_slice(&self.target_hash);
+        encoded
+    }
+
+    fn decode(encoded: &[u8; Self::LEN]) -> Self {
+        let mut chunk_size = [0u8; 4];
+        let mut target_length = [0u8; 8];
+        let mut target_hash = [0u8; 32];
+        chunk_size.copy_from_slice(&encoded[..4]);
+        target_length.copy_from_slice(&encoded[4..12]);
+        target_hash.copy_from_slice(&encoded[12..]);
+        Self {
+            chunk_size: u32::from_le_bytes(chunk_size),
+            target_length: u64::from_le_bytes(target_length),
+            target_hash,
+        }
+    }
+
+    fn chunk_count(&self) -> u64 {
+        self.target_length.div_ceil(self.chunk_size as u64)
+    }
+
+    /// Only the last chunk may be shorter than the chunk size
+    fn chunk_len(&self, index: u64) -> usize {
+        let offset = index * self.chunk_size as u64;
+        (self.target_length - offset).min(self.chunk_size as u64) as usize
+    }
+}
+
+/// Encodes `object` such that the chunks the receiver reported as `cached` are replaced by their
+/// hashes. The encoding is produced while the object streams, such that no more than one chunk
+/// is held in memory. If none of the chunks are cached, the object is returned as-is alongside
+/// [`TransferType::FileTransfer`]
+pub fn encode_deduplicated_source(
+    object: ChunkedObject,
+    cached: &[bool],
+) -> Result<(Box<dyn ObjectSource>, TransferType), CryptError> {
+    if cached.len() != object.hashes.len() {
+        return Err(CryptError::Encrypt(
+            "The chunk cache response does not match the object".to_string(),
+        ));
+    }
+
+    if !cached.iter().any(|cached| *cached) {
+        log::trace!(target: "citadel", "No chunks of {} are cached by the receiver; sending in full", object.name);
+        return Ok((object.source, TransferType::FileTransfer));
+    }
+
+    let header = DeduplicatedHeader {
+        chunk_size: object.chunk_size as u32,
+        target_length: object.length,
+        target_hash: object.hash,
+    };
+    let chunks: Arc<[(ChunkHash, bool)]> = object
+        .hashes
+        .into_iter()
+        .zip(cached.iter().copied())
+        .collect();
+    let literal_len: u64 = (0..chunks.len())
+        .filter(|index| !chunks[*index].1)
+        .map(|index| header.chunk_len(index as u64) as u64)
+        .sum();
+    log::trace!(target: "citadel", "Deduplicated {}: {literal_len} of {} bytes are literal", object.name, object.length);
+
+    Ok((
+        Box::new(DeduplicatedSource {
+            name: object.name,
+            header,
+            chunks,
+            inner: object.source,
+        }),
+        TransferType::Deduplicated {
+            target_hash: object.hash,
+        },
+    ))
+}
+
+/// Reconstructs an object encoded via [`encode_deduplicated_source`] while it streams from
+/// `encoded` into `output`. Cached chunks are loaded through `lookup` and verified against their
+/// hashes, while each literal chunk is passed to `on_literal`, e.g., to be cached. Returns the
+/// number of literal bytes. Since the object is only verified once fully written, `output` must be
+/// discarded if an error is returned
+pub fn apply_deduplicated<R: Read, W: Write>(
+    mut encoded: R,
+    mut output: W,
+    mut lookup: impl FnMut(&ChunkHash) -> Option<Vec<u8>>,
+    mut on_literal: impl FnMut(&ChunkHash, &[u8]),
+) -> Result<u64, CryptError> {
+    let mut header = [0u8; DeduplicatedHeader::LEN];
+    encoded.read_exact(&mut header).map_err(decrypt_error)?;
+    let header = DeduplicatedHeader::decode(&header);
+    if header.chunk_size == 0 || header.chunk_size as usize > MAX_CACHE_CHUNK_SIZE {
+        return Err(CryptError::Decrypt(format!(
+            "Invalid chunk size {}",
+            header.chunk_size
+        )));
+    }
+
+    let mut hasher = Sha3_256::default();
+    let mut literal_len = 0;
+    let mut chunk = Vec::new();
+    for index in 0..header.chunk_count() {
+        let len = header.chunk_len(index);
+        let mut tag = [0u8; 1];
+        encoded.read_exact(&mut tag).map_err(decrypt_error)?;
+        match tag[0] {
+            CHUNK_CACHED => {
+                let mut hash = [0u8; 32];
+                encoded.read_exact(&mut hash).map_err(decrypt_error)?;
+                chunk = lookup(&hash)
+                    .filter(|bytes| bytes.len() == len && hash_chunk(bytes) == hash)
+                    .ok_or_else(|| {
+                        CryptError::Decrypt(
+                            "A chunk is missing from, or corrupt in, the local cache".to_string(),
+                        )
+                    })?;
+            }
+
+            CHUNK_LITERAL => {
+                let mut encoded_len = [0u8; 4];
+                encoded
+                    .read_exact(&mut encoded_len)
+                    .map_err(decrypt_error)?;
+                if u32::from_le_bytes(encoded_len) as usize != len {
+                    return Err(CryptError::Decrypt(format!(
+                        "Chunk {index} has an unexpected length"
+                    )));
+                }
+
+                chunk.resize(len, 0);
+                encoded.read_exact(&mut chunk).map_err(decrypt_error)?;
+                on_literal(&hash_chunk(&chunk), &chunk);
+                literal_len += len as u64;
+            }
+
+            tag => {
+                return Err(CryptError::Decrypt(format!(
+                    "Chunk {index} has an unknown tag {tag}"
+                )))
+            }
+        }
+
+        hasher.update(&chunk);
+        output.write_all(&chunk).map_err(decrypt_error)?;
+    }
+
+    if read_up_to(&mut encoded, &mut [0u8; 1]).map_err(decrypt_error)? != 0 {
+        return Err(CryptError::Decrypt(
+            "The deduplicated transfer contains trailing bytes".to_string(),
+        ));
+    }
+
+    output.flush().map_err(decrypt_error)?;
+    if <[u8; 32]>::from(hasher.finalize()) != header.target_hash {
+        return Err(CryptError::Decrypt(
+            "The reconstructed object does not match the deduplicated transfer".to_string(),
+        ));
+    }
+
+    Ok(literal_len)
+}
+
+/// Yields the encoding of a deduplicated object, reading the object anew each time a stream is
+/// requested
+struct DeduplicatedSource {
+    name: String,
+    header: DeduplicatedHeader,
+    chunks: Arc<[(ChunkHash, bool)]>,
+    inner: Box<dyn ObjectSource>,
+}
+
+impl ObjectSource for DeduplicatedSource {
+    fn try_get_stream(&mut self) -> Result<Box<dyn FixedSizedSource>, CryptError> {
+        Ok(Box::new(DeduplicatedReader {
+            inner: self.inner.try_get_stream()?,
+            header: self.header,
+            chunks: self.chunks.clone(),
+            next_chunk: 0,
+            record: Vec::new(),
+            pos: 0,
+            started: false,
+        }))
+    }
+
+    fn get_source_name(&self) -> Result<String, CryptError> {
+        Ok(self.name.clone())
+    }
+}
+
+/// Encodes one chunk of the object at a time
+struct DeduplicatedReader {
+    inner: Box<dyn FixedSizedSource>,
+    header: DeduplicatedHeader,
+    chunks: Arc<[(ChunkHash, bool)]>,
+    next_chunk: usize,
+    // the encoded header or chunk being read, of which the bytes before `pos` were read
+    record: Vec<u8>,
+    pos: usize,
+    started: bool,
+}
+
+impl DeduplicatedReader {
+    /// Encodes the next record, returning false once the object is fully encoded
+    fn next_record(&mut self) -> std::io::Result<bool> {
+        self.record.clear();
+        self.pos = 0;
+        if !self.started {
+            self.started = true;
+            self.record.extend_from_slice(&self.header.encode());
+            return Ok(true);
+        }
+
+        let (hash, cached) = match self.chunks.get(self.next_chunk) {
+            Some(chunk) => *chunk,
+            None => return Ok(false),
+        };
+
+        let len = self.header.chunk_len(self.next_chunk as u64);
+        if cached {
+            // the receiver holds the chunk, though, it must still be read past
+            let skipped = std::io::copy(
+                &mut (&mut self.inner).take(len as u64),
+                &mut std::io::sink(),
+            )?;
+            if skipped != len as u64 {
+                return Err(std::io::ErrorKind::UnexpectedEof.into());
+            }
+
+            self.record.push(CHUNK_CACHED);
+            self.record.extend_from_slice(&hash);
+        } else {
+            self.record.push(CHUNK_LITERAL);
+            self.record.extend_from_slice(&(len as u32).to_le_bytes());
+            let start = self.record.len();
+            self.record.resize(start + len, 0);
+            self.inner.read_exact(&mut self.record[start..])?;
+        }
+
+        self.next_chunk += 1;
+        Ok(true)
+    }
+}
+
+impl Read for DeduplicatedReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.pos == self.record.len() && !self.next_record()? {
+            return Ok(0);
+        }
+
+        let len = buf.len().min(self.record.len() - self.pos);
+        buf[..len].copy_from_slice(&self.record[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+impl FixedSizedSource for DeduplicatedReader {
+    fn length(&self) -> std::io::Result<u64> {
+        Ok(DeduplicatedHeader::LEN as u64
+            + self
+                .chunks
+                .iter()
+                .enumerate()
+                .map(|(index, (_, cached))| {
+                    if *cached {
+                        1 + 32
+                    } else {
+                        1 + 4 + self.header.chunk_len(index as u64) as u64
+                    }
+                })
+                .sum::<u64>())
+    }
+
+    fn rewind(&mut self) -> std::io::Result<()> {
+        self.inner.rewind()?;
+        self.next_chunk = 0;
+        self.record.clear();
+        self.pos = 0;
+        self.started = false;
+        Ok(())
+    }
+}
+
+fn encrypt_error(err: std::io::Error) -> CryptError {
+    CryptError::Encrypt(err.to_string())
+}
+
+fn decrypt_error(err: std::io::Error) -> CryptError {
+    CryptError::Decrypt(err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk_dedup::{
+        apply_deduplicated, chunk_hashes, encode_deduplicated_source, ChunkSplitter, ChunkedObject,
+        DeduplicatedHeader,
+    };
+    use crate::misc::TransferType;
+    use crate::streaming_crypt_scrambler::BytesSource;
+    use std::collections::HashMap;
+    use std::io::Read;
+
+    fn pseudo_random(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_streamed_round_trip() {
+        let chunk_size = 1024;
+        let content = pseudo_random(chunk_size * 10 + 100, 1);
+        let cache: HashMap<_, _> = content
+            .chunks(chunk_size)
+            .step_by(2)
+            .map(|chunk| (crate::chunk_dedup::hash_chunk(chunk), chunk.to_vec()))
+            .collect();
+
+        let object = ChunkedObject::read(BytesSource::from(content.clone()), chunk_size).unwrap();
+        assert_eq!(object.hashes, chunk_hashes(&content, chunk_size));
+        let cached: Vec<bool> = object
+            .hashes
+            .iter()
+            .map(|hash| cache.contains_key(hash))
+            .collect();
+        let (mut source, transfer_type) = encode_deduplicated_source(object, &cached).unwrap();
+        assert!(matches!(transfer_type, TransferType::Deduplicated { .. }));
+
+        let mut stream = source.try_get_stream().unwrap();
+        let mut encoded = Vec::new();
+        let _ = stream.read_to_end(&mut encoded).unwrap();
+        assert_eq!(stream.length().unwrap(), encoded.len() as u64);
+        assert!(encoded.len() < content.len() / 2 + chunk_size);
+
+        // the stream may be rewound, e.g., to compute its manifest
+        stream.rewind().unwrap();
+        let mut reread = Vec::new();
+        let _ = stream.read_to_end(&mut reread).unwrap();
+        assert_eq!(reread, encoded);
+
+        let mut literals = Vec::new();
+        let mut output = Vec::new();
+        let literal_len = apply_deduplicated(
+            encoded.as_slice(),
+            &mut output,
+            |hash| cache.get(hash).cloned(),
+            |hash, bytes| literals.push((*hash, bytes.len())),
+        )
+        .unwrap();
+        assert_eq!(output, content);
+        assert_eq!(literals.len(), 5);
+        assert_eq!(literal_len, (chunk_size * 5) as u64);
+    }
+
+    #[test]
+    fn test_uncached_object_is_sent_in_full() {
+        let content = pseudo_random(5000, 2);
+        let object = ChunkedObject::read(BytesSource::from(content.clone()), 1024).unwrap();
+        let cached = vec![false; object.hashes.len()];
+        let (mut source, transfer_type) = encode_deduplicated_source(object, &cached).unwrap();
+        assert!(matches!(transfer_type, TransferType::FileTransfer));
+
+        let mut sent = Vec::new();
+        let _ = source
+            .try_get_stream()
+            .unwrap()
+            .read_to_end(&mut sent)
+            .unwrap();
+        assert_eq!(sent, content);
+    }
+
+    #[test]
+    fn test_apply_rejects_corrupt_cache() {
+        let content = pseudo_random(4096, 3);
+        let object = ChunkedObject::read(BytesSource::from(content), 1024).unwrap();
+        let (mut source, _) = encode_deduplicated_source(object, &[true; 4]).unwrap();
+        let mut encoded = Vec::new();
+        let _ = source
+            .try_get_stream()
+            .unwrap()
+            .read_to_end(&mut encoded)
+            .unwrap();
+
+        let res = apply_deduplicated(
+            encoded.as_slice(),
+            std::io::sink(),
+            |_| Some(vec![0u8; 1024]),
+            |_, _| {},
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_apply_rejects_malformed_header() {
+        // a peer-controlled length must not cause the receiver to allocate for it
+        let header = DeduplicatedHeader {
+            chunk_size: 1024,
+            target_length: u64::MAX,
+            target_hash: [0u8; 32],
+        };
+        let res = apply_deduplicated(
+            header.encode().as_slice(),
+            std::io::sink(),
+            |_| None,
+            |_, _| {},
+        );
+        assert!(res.is_err());
+
+        let header = DeduplicatedHeader {
+            chunk_size: 0,
+            ..header
+        };
+        let res = apply_deduplicated(
+            header.encode().as_slice(),
+            std::io::sink(),
+            |_| None,
+            |_, _| {},
+        );
+        assert!(res.is_err());
+    }
+
+    #[test]
+    fn test_splitter_matches_chunk_hashes() {
+        let content = pseudo_random(10_000, 4);
+        let mut splitter = ChunkSplitter::new(1024);
+        let mut chunks = Vec::new();
+        for piece in content.chunks(777).chain(std::iter::once(&content[..0])) {
+            splitter.push(piece, |chunk| chunks.push(chunk.to_vec()));
+        }
+        splitter.finish(|chunk| chunks.push(chunk.to_vec()));
+
+        let expected: Vec<Vec<u8>> = content.chunks(1024).map(|chunk| chunk.to_vec()).collect();
+        assert_eq!(chunks, expected);
+    }
+}
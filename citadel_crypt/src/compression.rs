@@ -0,0 +1,165 @@
+use crate::misc::CryptError;
+use crate::streaming_crypt_scrambler::MAX_BYTES_PER_GROUP;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+
+/// The number of leading bytes of a group that are trial-compressed before committing to
+/// compressing the whole group
+pub const COMPRESSION_SAMPLE_LEN: usize = 64 * 1024;
+/// The number of bytes [`GroupCompressor::compress`] prefixes to every group
+pub const COMPRESSION_FRAME_LEN: usize = 1;
+/// The largest group that may be compressed, such that the framed output, even if sent raw, never
+/// exceeds [`MAX_BYTES_PER_GROUP`]
+pub const MAX_COMPRESSIBLE_GROUP_LEN: usize = MAX_BYTES_PER_GROUP - COMPRESSION_FRAME_LEN;
+/// If the sample does not shrink below this fraction of its original size, the group is sent raw
+const MAX_SAMPLE_RATIO: f32 = 0.9;
+const ZSTD_LEVEL: i32 = 3;
+
+const FRAME_RAW: u8 = 0;
+const FRAME_ZSTD: u8 = 1;
+
+/// Compression applied to each group of a transfer prior to encryption. Offered by the sender in
+/// the file header, and only used if the receiver accepts it in the file header ack
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CompressionAlgorithm {
+    Zstd,
+}
+
+/// Compresses the groups of an object as a single stream, such that each group benefits from the
+/// history of those before it. Each group is flushed, and is therefore decompressible as soon as
+/// it arrives, so long as groups are decompressed in order by a [`GroupDecompressor`]
+pub struct GroupCompressor {
+    algorithm: CompressionAlgorithm,
+    // lazily started; a raw group ends the stream on both ends
+    encoder: Option<zstd::stream::write::Encoder<'static, Vec<u8>>>,
+}
+
+impl GroupCompressor {
+    pub fn new(algorithm: CompressionAlgorithm) -> Self {
+        Self {
+            algorithm,
+            encoder: None,
+        }
+    }
+
+    /// Compresses the next group, prefixing the output with a marker byte declaring whether or not
+    /// the payload was compressed. Content that is already compressed or encrypted is detected by
+    /// compressing a sample first, and is sent as-is. The input may not exceed
+    /// [`MAX_COMPRESSIBLE_GROUP_LEN`]
+    pub fn compress(&mut self, input: &[u8]) -> Result<Vec<u8>, CryptError> {
+        if input.len() > MAX_COMPRESSIBLE_GROUP_LEN {
+            return Err(CryptError::Encrypt(format!(
+                "Compressed groups cannot be larger than {MAX_COMPRESSIBLE_GROUP_LEN} bytes"
+            )));
+        }
+
+        match self.algorithm {
+            CompressionAlgorithm::Zstd => {
+                let sample = &input[..std::cmp::min(input.len(), COMPRESSION_SAMPLE_LEN)];
+                let compressible = match zstd::bulk::compress(sample, ZSTD_LEVEL) {
+                    Ok(compressed) => {
+                        (compressed.len() as f32) < (sample.len() as f32 * MAX_SAMPLE_RATIO)
+                    }
+                    Err(_) => false,
+                };
+
+                if compressible {
+                    if let Ok(compressed) = self.compress_streaming(input) {
+                        if compressed.len() < input.len() {
+                            return Ok(frame(FRAME_ZSTD, &compressed));
+                        }
+                    }
+                }
+
+                // the receiver ends its stream upon a raw group, so the next compressed group
+                // starts a new one
+                self.encoder = None;
+                Ok(frame(FRAME_RAW, input))
+            }
+        }
+    }
+
+    fn compress_streaming(&mut self, input: &[u8]) -> std::io::Result<Vec<u8>> {
+        let encoder = match self.encoder.as_mut() {
+            Some(encoder) => encoder,
+            None => self
+                .encoder
+                .insert(zstd::stream::write::Encoder::new(Vec::new(), ZSTD_LEVEL)?),
+        };
+
+        encoder.write_all(input)?;
+        encoder.flush()?;
+        Ok(std::mem::take(encoder.get_mut()))
+    }
+}
+
+/// Reverses [`GroupCompressor`]. Groups must be passed in the order they were compressed. The output
+/// of each group is bounded to [`MAX_BYTES_PER_GROUP`] to prevent a malicious sender from
+/// exhausting memory
+#[derive(Default)]
+pub struct GroupDecompressor {
+    decoder: Option<zstd::stream::write::Decoder<'static, BoundedSink>>,
+}
+
+impl GroupDecompressor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn decompress(&mut self, input: &[u8]) -> Result<Vec<u8>, CryptError> {
+        match input.split_first() {
+            Some((&FRAME_RAW, payload)) => {
+                self.decoder = None;
+                Ok(payload.to_vec())
+            }
+            Some((&FRAME_ZSTD, payload)) => self
+                .decompress_streaming(payload)
+                .map_err(|err| CryptError::Decrypt(err.to_string())),
+            _ => Err(CryptError::Decrypt(
+                "Invalid compression frame on group".to_string(),
+            )),
+        }
+    }
+
+    fn decompress_streaming(&mut self, payload: &[u8]) -> std::io::Result<Vec<u8>> {
+        let decoder = match self.decoder.as_mut() {
+            Some(decoder) => decoder,
+            None => self
+                .decoder
+                .insert(zstd::stream::write::Decoder::new(BoundedSink::default())?),
+        };
+
+        decoder.write_all(payload)?;
+        decoder.flush()?;
+        Ok(std::mem::take(&mut decoder.get_mut().0))
+    }
+}
+
+/// Collects the output of a single group, failing once it exceeds [`MAX_BYTES_PER_GROUP`]
+#[derive(Default)]
+struct BoundedSink(Vec<u8>);
+
+impl Write for BoundedSink {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        if self.0.len() + buf.len() > MAX_BYTES_PER_GROUP {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "Decompressed group exceeds the maximum group size",
+            ));
+        }
+
+        self.0.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+fn frame(marker: u8, payload: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(payload.len() + COMPRESSION_FRAME_LEN);
+    output.push(marker);
+    output.extend_from_slice(payload);
+    output
+}
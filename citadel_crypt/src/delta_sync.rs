@@ -0,0 +1,878 @@
+use crate::misc::{CryptError, TransferType};
+use crate::streaming_crypt_scrambler::{FixedSizedSource, ObjectSource};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::collections::HashMap;
+use std::io::{Read, Seek, SeekFrom, Write};
+
+/// The block size used when the receiver computes the signature of its copy of an object
+pub const DEFAULT_DELTA_BLOCK_SIZE: usize = 4096;
+/// The number of bytes read from an object at once, beyond those of the block being matched
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The weak (rolling) and strong checksums of a single block of the basis
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub struct BlockSignature {
+    pub weak: u32,
+    pub strong: [u8; 32],
+}
+
+/// Computed by the receiver over the copy of an object it already holds (the basis), and sent
+/// to the sender so that only the blocks that changed need to be transmitted
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct FileSignature {
+    pub block_size: usize,
+    pub basis_length: u64,
+    pub basis_hash: [u8; 32],
+    pub blocks: Vec<BlockSignature>,
+}
+
+impl FileSignature {
+    /// Only full blocks are indexed. A trailing partial block is always sent as a literal
+    pub fn generate(basis: &[u8], block_size: usize) -> Result<Self, CryptError> {
+        Self::generate_from_reader(basis, block_size)
+    }
+
+    /// Like [`Self::generate`], yet reads the basis one block at a time
+    pub fn generate_from_reader<R: Read>(
+        mut basis: R,
+        block_size: usize,
+    ) -> Result<Self, CryptError> {
+        if block_size == 0 {
+            return Err(CryptError::Encrypt(
+                "Block size must be non-zero".to_string(),
+            ));
+        }
+
+        let mut basis_hasher = Sha3_256::default();
+        let mut basis_length = 0;
+        let mut blocks = Vec::new();
+        let mut block = vec![0u8; block_size];
+        loop {
+            let len = read_up_to(&mut basis, &mut block).map_err(encrypt_error)?;
+            basis_hasher.update(&block[..len]);
+            basis_length += len as u64;
+            if len < block_size {
+                break;
+            }
+
+            blocks.push(BlockSignature {
+                weak: RollingChecksum::new(&block).digest(),
+                strong: hash(&block),
+            });
+        }
+
+        Ok(Self {
+            block_size,
+            basis_length,
+            basis_hash: basis_hasher.finalize().into(),
+            blocks,
+        })
+    }
+}
+
+/// The largest block size accepted from the sender of a delta
+pub const MAX_DELTA_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Prefixes a run of basis blocks, followed by the index of the first block and the block count
+const OP_COPY: u8 = 0;
+/// Prefixes bytes that do not exist in the basis, followed by their length and the bytes
+const OP_LITERAL: u8 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum DeltaOp {
+    /// Copies `block_count` contiguous blocks of the basis, starting at `block_index`
+    Copy { block_index: u32, block_count: u32 },
+    /// The next `len` bytes of the target, which do not exist anywhere in the basis. The bytes
+    /// are only read from the target once the delta is encoded
+    Literal { len: u64 },
+}
+
+/// The instructions for reconstructing the target object from the receiver's basis. The ops
+/// cover the target in order, such that the delta can be encoded while the target streams
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct FileDelta {
+    pub block_size: usize,
+    pub basis_hash: [u8; 32],
+    pub target_length: u64,
+    pub target_hash: [u8; 32],
+    pub ops: Vec<DeltaOp>,
+}
+
+impl FileDelta {
+    /// Computes the delta between the basis described by `signature` and `target`
+    pub fn compute(signature: &FileSignature, target: &[u8]) -> Self {
+        Self::compute_from_reader(signature, target).expect("Reading from a slice never fails")
+    }
+
+    /// Like [`Self::compute`], yet reads the target in chunks, holding no more of it in memory
+    /// than the chunk being matched
+    pub fn compute_from_reader<R: Read>(
+        signature: &FileSignature,
+        mut target: R,
+    ) -> std::io::Result<Self> {
+        let block_size = signature.block_size;
+        let mut index: HashMap<u32, Vec<usize>> = HashMap::new();
+        // empty blocks never advance the window, hence are never matched
+        if block_size != 0 {
+            for (block_index, block) in signature.blocks.iter().enumerate() {
+                index.entry(block.weak).or_default().push(block_index);
+            }
+        }
+
+        let mut ops = Vec::new();
+        // the number of unmatched bytes found since the last match
+        let mut literal = 0u64;
+        let mut target_hasher = Sha3_256::default();
+        let mut target_length = 0;
+        // the unmatched bytes of the target read so far, of which those before `pos` are processed
+        let mut buf = Vec::new();
+        let mut pos = 0;
+        let mut exhausted = false;
+        let mut rolling: Option<RollingChecksum> = None;
+        // the byte that left the window since the checksum was last rolled
+        let mut outgoing = None;
+
+        loop {
+            if !exhausted && (pos == buf.len() || buf.len() - pos < block_size) {
+                let _ = buf.drain(..pos);
+                pos = 0;
+                let start = buf.len();
+                buf.resize(start + block_size + READ_CHUNK_SIZE, 0);
+                let len = read_up_to(&mut target, &mut buf[start..])?;
+                buf.truncate(start + len);
+                target_hasher.update(&buf[start..]);
+                target_length += len as u64;
+                exhausted = len < block_size + READ_CHUNK_SIZE;
+            }
+
+            // without any blocks left to match, the rest of the target is literal
+            if index.is_empty() || buf.len() - pos < block_size {
+                literal += (buf.len() - pos) as u64;
+                pos = buf.len();
+                if exhausted {
+                    break;
+                }
+
+                continue;
+            }
+
+            let window = &buf[pos..pos + block_size];
+            let checksum = match (&mut rolling, outgoing.take()) {
+                (Some(checksum), Some(outgoing)) => {
+                    checksum.roll(outgoing, window[block_size - 1]);
+                    checksum
+                }
+                (checksum, _) => checksum.insert(RollingChecksum::new(window)),
+            };
+
+            let matched = index.get(&checksum.digest()).and_then(|candidates| {
+                let strong = hash(window);
+                candidates
+                    .iter()
+                    .find(|idx| signature.blocks[**idx].strong == strong)
+                    .copied()
+            });
+
+            if let Some(block_index) = matched {
+                if literal != 0 {
+                    ops.push(DeltaOp::Literal {
+                        len: std::mem::take(&mut literal),
+                    });
+                }
+
+                push_copy(&mut ops, block_index as u32);
+                pos += block_size;
+                rolling = None;
+                continue;
+            }
+
+            literal += 1;
+            outgoing = Some(buf[pos]);
+            pos += 1;
+        }
+
+        if literal != 0 {
+            ops.push(DeltaOp::Literal { len: literal });
+        }
+
+        Ok(Self {
+            block_size,
+            basis_hash: signature.basis_hash,
+            target_length,
+            target_hash: target_hasher.finalize().into(),
+            ops,
+        })
+    }
+
+    /// The number of bytes that must be transmitted verbatim
+    pub fn literal_len(&self) -> u64 {
+        self.ops
+            .iter()
+            .map(|op| match op {
+                DeltaOp::Literal { len } => *len,
+                DeltaOp::Copy { .. } => 0,
+            })
+            .sum()
+    }
+
+    /// The length of the encoding produced by [`Self::into_encoder`]
+    pub fn encoded_len(&self) -> u64 {
+        DeltaHeader::LEN as u64
+            + self
+                .ops
+                .iter()
+                .map(|op| match op {
+                    DeltaOp::Copy { .. } => 1 + 4 + 4,
+                    DeltaOp::Literal { len } => 1 + 8 + *len,
+                })
+                .sum::<u64>()
+    }
+
+    /// Encodes the delta while `target`, the object the delta was computed over, streams. No more
+    /// than one read of the target is held in memory at once. Decode via [`apply_delta`]
+    pub fn into_encoder<R: Read>(self, target: R) -> DeltaEncoder<R> {
+        DeltaEncoder {
+            header: DeltaHeader {
+                block_size: self.block_size as u64,
+                basis_hash: self.basis_hash,
+                target_length: self.target_length,
+                target_hash: self.target_hash,
+            },
+            encoded_len: self.encoded_len(),
+            ops: self.ops,
+            target,
+            next_op: 0,
+            record: Vec::new(),
+            pos: 0,
+            literal_remaining: 0,
+            started: false,
+        }
+    }
+}
+
+/// Precedes the ops of an encoded delta
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct DeltaHeader {
+    block_size: u64,
+    basis_hash: [u8; 32],
+    target_length: u64,
+    target_hash: [u8; 32],
+}
+
+impl DeltaHeader {
+    const LEN: usize = 8 + 32 + 8 + 32;
+
+    fn encode(&self) -> [u8; Self::LEN] {
+        let mut encoded = [0u8; Self::LEN];
+        encoded[..8].copy_from_slice(&self.block_size.to_le_bytes());
+        encoded[8..40].copy_from_slice(&self.basis_hash);
+        encoded[40..48].copy_from_slice(&self.target_length.to_le_bytes());
+        encoded[48..].copy_from_slice(&self.target_hash);
+        encoded
+    }
+
+    fn decode(encoded: &[u8; Self::LEN]) -> Self {
+        let mut block_size = [0u8; 8];
+        let mut basis_hash = [0u8; 32];
+        let mut target_length = [0u8; 8];
+        let mut target_hash = [0u8; 32];
+        block_size.copy_from_slice(&encoded[..8]);
+        basis_hash.copy_from_slice(&encoded[8..40]);
+        target_length.copy_from_slice(&encoded[40..48]);
+        target_hash.copy_from_slice(&encoded[48..]);
+        Self {
+            block_size: u64::from_le_bytes(block_size),
+            basis_hash,
+            target_length: u64::from_le_bytes(target_length),
+            target_hash,
+        }
+    }
+}
+
+/// Encodes one op of a [`FileDelta`] at a time. Literal bytes are passed through from the target
+/// without being buffered
+pub struct DeltaEncoder<R> {
+    header: DeltaHeader,
+    encoded_len: u64,
+    ops: Vec<DeltaOp>,
+    target: R,
+    next_op: usize,
+    // the encoded header or op prefix being read, of which the bytes before `pos` were read
+    record: Vec<u8>,
+    pos: usize,
+    // the literal bytes of the current op yet to be passed through from the target
+    literal_remaining: u64,
+    started: bool,
+}
+
+impl<R: Read> DeltaEncoder<R> {
+    /// Encodes the next record, returning false once the delta is fully encoded
+    fn next_record(&mut self) -> std::io::Result<bool> {
+        self.record.clear();
+        self.pos = 0;
+        if !self.started {
+            self.started = true;
+            self.record.extend_from_slice(&self.header.encode());
+            return Ok(true);
+        }
+
+        let op = match self.ops.get(self.next_op) {
+            Some(op) => *op,
+            None => return Ok(false),
+        };
+
+        match op {
+            DeltaOp::Copy {
+                block_index,
+                block_count,
+            } => {
+                // the receiver holds the blocks, though, they must still be read past
+                let len = block_count as u64 * self.header.block_size;
+                let skipped =
+                    std::io::copy(&mut (&mut self.target).take(len), &mut std::io::sink())?;
+                if skipped != len {
+                    return Err(std::io::ErrorKind::UnexpectedEof.into());
+                }
+
+                self.record.push(OP_COPY);
+                self.record.extend_from_slice(&block_index.to_le_bytes());
+                self.record.extend_from_slice(&block_count.to_le_bytes());
+            }
+
+            DeltaOp::Literal { len } => {
+                self.record.push(OP_LITERAL);
+                self.record.extend_from_slice(&len.to_le_bytes());
+                self.literal_remaining = len;
+            }
+        }
+
+        self.next_op += 1;
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for DeltaEncoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if self.pos < self.record.len() {
+                let len = buf.len().min(self.record.len() - self.pos);
+                buf[..len].copy_from_slice(&self.record[self.pos..self.pos + len]);
+                self.pos += len;
+                return Ok(len);
+            }
+
+            if self.literal_remaining != 0 {
+                let len = (buf.len() as u64).min(self.literal_remaining) as usize;
+                let read = self.target.read(&mut buf[..len])?;
+                if read == 0 && len != 0 {
+                    return Err(std::io::ErrorKind::UnexpectedEof.into());
+                }
+
+                self.literal_remaining -= read as u64;
+                return Ok(read);
+            }
+
+            if !self.next_record()? {
+                return Ok(0);
+            }
+        }
+    }
+}
+
+impl FixedSizedSource for DeltaEncoder<Box<dyn FixedSizedSource>> {
+    fn length(&self) -> std::io::Result<u64> {
+        Ok(self.encoded_len)
+    }
+
+    fn rewind(&mut self) -> std::io::Result<()> {
+        self.target.rewind()?;
+        self.next_op = 0;
+        self.record.clear();
+        self.pos = 0;
+        self.literal_remaining = 0;
+        self.started = false;
+        Ok(())
+    }
+}
+
+/// Reconstructs an object encoded via [`FileDelta::into_encoder`] from `basis` while it streams
+/// from `encoded` into `output`, reading only the copied blocks of `basis` besides one pass to
+/// verify it. Returns the number of literal bytes. Since the reconstructed object is only
+/// verified once fully written, `output` must be discarded if an error is returned
+pub fn apply_delta<R: Read, B: Read + Seek, W: Write>(
+    mut encoded: R,
+    mut basis: B,
+    output: W,
+) -> Result<u64, CryptError> {
+    let mut header = [0u8; DeltaHeader::LEN];
+    encoded.read_exact(&mut header).map_err(decrypt_error)?;
+    let header = DeltaHeader::decode(&header);
+    if header.block_size == 0 || header.block_size > MAX_DELTA_BLOCK_SIZE as u64 {
+        return Err(CryptError::Decrypt(format!(
+            "Invalid block size {}",
+            header.block_size
+        )));
+    }
+
+    let mut basis_hasher = Sha3_256::default();
+    let mut chunk = vec![0u8; READ_CHUNK_SIZE];
+    loop {
+        let len = read_up_to(&mut basis, &mut chunk).map_err(decrypt_error)?;
+        basis_hasher.update(&chunk[..len]);
+        if len < chunk.len() {
+            break;
+        }
+    }
+
+    if <[u8; 32]>::from(basis_hasher.finalize()) != header.basis_hash {
+        return Err(CryptError::Decrypt(
+            "The local copy does not match the basis of the delta".to_string(),
+        ));
+    }
+
+    let mut output = HashingWriter {
+        inner: output,
+        hasher: Sha3_256::default(),
+        written: 0,
+    };
+    let mut literal_len = 0;
+
+    loop {
+        let mut tag = [0u8; 1];
+        if read_up_to(&mut encoded, &mut tag).map_err(decrypt_error)? == 0 {
+            break;
+        }
+
+        match tag[0] {
+            OP_COPY => {
+                let mut block_index = [0u8; 4];
+                let mut block_count = [0u8; 4];
+                encoded
+                    .read_exact(&mut block_index)
+                    .map_err(decrypt_error)?;
+                encoded
+                    .read_exact(&mut block_count)
+                    .map_err(decrypt_error)?;
+                let start = (u32::from_le_bytes(block_index) as u64)
+                    .checked_mul(header.block_size)
+                    .ok_or(CryptError::OutOfBoundsError)?;
+                let len = (u32::from_le_bytes(block_count) as u64)
+                    .checked_mul(header.block_size)
+                    .ok_or(CryptError::OutOfBoundsError)?;
+                let _ = basis.seek(SeekFrom::Start(start)).map_err(decrypt_error)?;
+                let copied = std::io::copy(&mut (&mut basis).take(len), &mut output)
+                    .map_err(decrypt_error)?;
+                if copied != len {
+                    return Err(CryptError::OutOfBoundsError);
+                }
+            }
+
+            OP_LITERAL => {
+                let mut len = [0u8; 8];
+                encoded.read_exact(&mut len).map_err(decrypt_error)?;
+                let len = u64::from_le_bytes(len);
+                let copied = std::io::copy(&mut (&mut encoded).take(len), &mut output)
+                    .map_err(decrypt_error)?;
+                if copied != len {
+                    return Err(CryptError::Decrypt(
+                        "The delta ended within a literal".to_string(),
+                    ));
+                }
+
+                literal_len += len;
+            }
+
+            tag => {
+                return Err(CryptError::Decrypt(format!(
+                    "The delta contains an unknown op {tag}"
+                )))
+            }
+        }
+
+        // a delta may not expand beyond the object it declares
+        if output.written > header.target_length {
+            return Err(CryptError::OutOfBoundsError);
+        }
+    }
+
+    output.flush().map_err(decrypt_error)?;
+    if output.written != header.target_length
+        || <[u8; 32]>::from(output.hasher.finalize()) != header.target_hash
+    {
+        return Err(CryptError::Decrypt(
+            "The reconstructed object does not match the delta".to_string(),
+        ));
+    }
+
+    Ok(literal_len)
+}
+
+/// Streams `source` and, if transmitting a delta against `signature` is cheaper than transmitting
+/// the whole object, returns a source encoding the delta as the object is read once more.
+/// Otherwise, the object is returned to be sent as-is alongside [`TransferType::FileTransfer`]
+pub fn encode_delta_source<S: ObjectSource>(
+    mut source: S,
+    signature: &FileSignature,
+) -> Result<(Box<dyn ObjectSource>, TransferType), CryptError> {
+    let name = source.get_source_name()?;
+    let mut stream = source.try_get_stream()?;
+    let delta = FileDelta::compute_from_reader(signature, &mut stream).map_err(encrypt_error)?;
+    let source = rewind_source(name.clone(), source, stream);
+
+    // the copy instructions are small, so, only the literal bytes are weighed
+    if delta.literal_len() >= delta.target_length {
+        log::trace!(target: "citadel", "Delta for {name} saves nothing; sending in full");
+        return Ok((source, TransferType::FileTransfer));
+    }
+
+    let basis_hash = delta.basis_hash;
+    log::trace!(target: "citadel", "Encoded delta for {name}: {} of {} bytes are literal", delta.literal_len(), delta.target_length);
+    Ok((
+        Box::new(DeltaSource {
+            name,
+            delta,
+            inner: source,
+        }),
+        TransferType::DeltaSync { basis_hash },
+    ))
+}
+
+/// Yields the encoding of a delta, reading the target anew each time a stream is requested
+struct DeltaSource {
+    name: String,
+    delta: FileDelta,
+    inner: Box<dyn ObjectSource>,
+}
+
+impl ObjectSource for DeltaSource {
+    fn try_get_stream(&mut self) -> Result<Box<dyn FixedSizedSource>, CryptError> {
+        Ok(Box::new(
+            self.delta
+                .clone()
+                .into_encoder(self.inner.try_get_stream()?),
+        ))
+    }
+
+    fn get_source_name(&self) -> Result<String, CryptError> {
+        Ok(self.name.clone())
+    }
+}
+
+/// Returns a source yielding `stream`, which was read through, once rewound. Sources whose streams
+/// cannot be rewound are read anew
+pub(crate) fn rewind_source<S: ObjectSource>(
+    name: String,
+    source: S,
+    mut stream: Box<dyn FixedSizedSource>,
+) -> Box<dyn ObjectSource> {
+    match stream.rewind() {
+        Ok(()) => Box::new(RewoundSource {
+            name,
+            delete_path: source.delete_path(),
+            stream: citadel_io::Mutex::new(Some(stream)),
+        }),
+        Err(_) => Box::new(source),
+    }
+}
+
+/// A source whose stream was read through, then rewound to be read once more
+struct RewoundSource {
+    name: String,
+    delete_path: Option<std::path::PathBuf>,
+    // locked, since sources are shared across threads, unlike their streams
+    stream: citadel_io::Mutex<Option<Box<dyn FixedSizedSource>>>,
+}
+
+impl ObjectSource for RewoundSource {
+    fn try_get_stream(&mut self) -> Result<Box<dyn FixedSizedSource>, CryptError> {
+        self.stream
+            .lock()
+            .take()
+            .ok_or_else(|| CryptError::Encrypt("Source has already been exhausted".into()))
+    }
+
+    fn get_source_name(&self) -> Result<String, CryptError> {
+        Ok(self.name.clone())
+    }
+
+    fn delete_path(&self) -> Option<std::path::PathBuf> {
+        self.delete_path.clone()
+    }
+}
+
+fn push_copy(ops: &mut Vec<DeltaOp>, block_index: u32) {
+    if let Some(DeltaOp::Copy {
+        block_index: start,
+        block_count,
+    }) = ops.last_mut()
+    {
+        if *start + *block_count == block_index {
+            *block_count += 1;
+            return;
+        }
+    }
+
+    ops.push(DeltaOp::Copy {
+        block_index,
+        block_count: 1,
+    });
+}
+
+/// An rsync-style weak checksum that can be rolled forward one byte at a time
+struct RollingChecksum {
+    a: u16,
+    b: u16,
+    // wider than the sums, since blocks may exceed 64 KiB
+    len: u32,
+}
+
+impl RollingChecksum {
+    fn new(block: &[u8]) -> Self {
+        let mut a: u16 = 0;
+        let mut b: u16 = 0;
+        for byte in block {
+            a = a.wrapping_add(*byte as u16);
+            b = b.wrapping_add(a);
+        }
+
+        Self {
+            a,
+            b,
+            len: block.len() as u32,
+        }
+    }
+
+    fn roll(&mut self, outgoing: u8, incoming: u8) {
+        self.a = self
+            .a
+            .wrapping_sub(outgoing as u16)
+            .wrapping_add(incoming as u16);
+        self.b = self
+            .b
+            .wrapping_sub(self.len.wrapping_mul(outgoing as u32) as u16)
+            .wrapping_add(self.a);
+    }
+
+    fn digest(&self) -> u32 {
+        (self.a as u32) | ((self.b as u32) << 16)
+    }
+}
+
+/// Hashes the bytes written through it
+struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha3_256,
+    written: u64,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        self.hasher.update(&buf[..len]);
+        self.written += len as u64;
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Fills `buf` unless `reader` ends first, returning the number of bytes read
+pub(crate) fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(len) => filled += len,
+            Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err),
+        }
+    }
+
+    Ok(filled)
+}
+
+fn encrypt_error(err: std::io::Error) -> CryptError {
+    CryptError::Encrypt(err.to_string())
+}
+
+fn decrypt_error(err: std::io::Error) -> CryptError {
+    CryptError::Decrypt(err.to_string())
+}
+
+fn hash(input: &[u8]) -> [u8; 32] {
+    Sha3_256::digest(input).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::delta_sync::{
+        apply_delta, encode_delta_source, FileDelta, FileSignature, RollingChecksum,
+        READ_CHUNK_SIZE,
+    };
+    use crate::misc::TransferType;
+    use crate::streaming_crypt_scrambler::BytesSource;
+    use std::io::{Cursor, Read};
+
+    fn pseudo_random(len: usize, seed: u32) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (state >> 16) as u8
+            })
+            .collect()
+    }
+
+    fn encode(delta: &FileDelta, target: &[u8]) -> Vec<u8> {
+        let mut encoded = Vec::new();
+        let _ = delta
+            .clone()
+            .into_encoder(Cursor::new(target))
+            .read_to_end(&mut encoded)
+            .unwrap();
+        assert_eq!(encoded.len() as u64, delta.encoded_len());
+        encoded
+    }
+
+    fn reconstruct(encoded: &[u8], basis: &[u8]) -> Vec<u8> {
+        let mut output = Vec::new();
+        let _ = apply_delta(encoded, Cursor::new(basis), &mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_streamed_round_trip_across_chunks() {
+        let block_size = 512;
+        // several read chunks, so that matches straddle the boundaries between them
+        let basis = pseudo_random(READ_CHUNK_SIZE * 3 + 123, 7);
+        let mut target = basis.clone();
+        let _ = target.splice(1000..1000, pseudo_random(37, 8));
+        let _ = target.splice(READ_CHUNK_SIZE..READ_CHUNK_SIZE + 700, pseudo_random(11, 9));
+        target.truncate(target.len() - 300);
+
+        let signature =
+            FileSignature::generate_from_reader(Cursor::new(&basis), block_size).unwrap();
+        assert_eq!(
+            signature,
+            FileSignature::generate(&basis, block_size).unwrap()
+        );
+
+        let delta = FileDelta::compute_from_reader(&signature, Cursor::new(&target)).unwrap();
+        assert_eq!(delta, FileDelta::compute(&signature, &target));
+        assert!(delta.literal_len() < target.len() as u64 / 50);
+
+        let encoded = encode(&delta, &target);
+        assert!(encoded.len() < target.len() / 50);
+        assert_eq!(reconstruct(&encoded, &basis), target);
+    }
+
+    #[test]
+    fn test_streamed_round_trip_against_empty_basis() {
+        let target = pseudo_random(READ_CHUNK_SIZE + 1, 1);
+        let signature = FileSignature::generate_from_reader(std::io::empty(), 512).unwrap();
+        assert!(signature.blocks.is_empty());
+
+        let delta = FileDelta::compute_from_reader(&signature, Cursor::new(&target)).unwrap();
+        assert_eq!(delta.literal_len(), target.len() as u64);
+        assert_eq!(reconstruct(&encode(&delta, &target), &[]), target);
+    }
+
+    #[test]
+    fn test_round_trip_with_large_blocks() {
+        let block_size = 96 * 1024;
+        let basis = pseudo_random(block_size * 4, 5);
+        let mut target = basis.clone();
+        // shifts every following block, such that they are only found by rolling the checksum
+        let _ = target.splice(block_size + 10..block_size + 10, pseudo_random(3, 6));
+
+        let signature = FileSignature::generate(&basis, block_size).unwrap();
+        let delta = FileDelta::compute(&signature, &target);
+        assert_eq!(delta.literal_len(), block_size as u64 + 3);
+        assert_eq!(reconstruct(&encode(&delta, &target), &basis), target);
+    }
+
+    #[test]
+    fn test_rolled_checksum_matches_fresh_checksum() {
+        let window = 70_000;
+        let input = pseudo_random(window + 100, 10);
+        let mut rolling = RollingChecksum::new(&input[..window]);
+        for start in 1..=100 {
+            rolling.roll(input[start - 1], input[start + window - 1]);
+            assert_eq!(
+                rolling.digest(),
+                RollingChecksum::new(&input[start..start + window]).digest()
+            );
+        }
+    }
+
+    #[test]
+    fn test_apply_rejects_wrong_basis() {
+        let basis = pseudo_random(4096, 2);
+        let signature = FileSignature::generate(&basis, 512).unwrap();
+        let encoded = encode(&FileDelta::compute(&signature, &basis), &basis);
+
+        let mut other = basis.clone();
+        other[0] ^= 1;
+        let mut output = Vec::new();
+        assert!(apply_delta(encoded.as_slice(), Cursor::new(&other), &mut output).is_err());
+    }
+
+    #[test]
+    fn test_apply_rejects_truncated_delta() {
+        let basis = pseudo_random(4096, 2);
+        let signature = FileSignature::generate(&basis, 512).unwrap();
+        let mut target = basis.clone();
+        target[100] ^= 1;
+        let encoded = encode(&FileDelta::compute(&signature, &target), &target);
+
+        let mut output = Vec::new();
+        assert!(apply_delta(
+            &encoded[..encoded.len() - 1],
+            Cursor::new(&basis),
+            &mut output
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_encode_delta_source() {
+        let basis = pseudo_random(READ_CHUNK_SIZE * 2, 3);
+        let signature = FileSignature::generate(&basis, 1024).unwrap();
+
+        // mostly unchanged: a delta is sent
+        let mut target = basis.clone();
+        target[5000] ^= 0xFF;
+        let (mut source, transfer_type) =
+            encode_delta_source(BytesSource::from(target.clone()), &signature).unwrap();
+        assert!(
+            matches!(transfer_type, TransferType::DeltaSync { basis_hash } if basis_hash == signature.basis_hash)
+        );
+        let mut stream = source.try_get_stream().unwrap();
+        let mut encoded = Vec::new();
+        let _ = stream.read_to_end(&mut encoded).unwrap();
+        assert_eq!(encoded.len() as u64, stream.length().unwrap());
+        assert_eq!(reconstruct(&encoded, &basis), target);
+
+        // the stream may be read once more after being rewound, as done to compute a manifest
+        stream.rewind().unwrap();
+        let mut reread = Vec::new();
+        let _ = stream.read_to_end(&mut reread).unwrap();
+        assert_eq!(reread, encoded);
+
+        // entirely different: the rewound object is sent in full
+        let unrelated = pseudo_random(READ_CHUNK_SIZE, 4);
+        let (mut source, transfer_type) =
+            encode_delta_source(BytesSource::from(unrelated.clone()), &signature).unwrap();
+        assert!(matches!(transfer_type, TransferType::FileTransfer));
+        let mut sent = Vec::new();
+        let _ = source
+            .try_get_stream()
+            .unwrap()
+            .read_to_end(&mut sent)
+            .unwrap();
+        assert_eq!(sent, unrelated);
+    }
+}
@@ -4,7 +4,8 @@ use crate::misc::CryptError;
 use crate::prelude::SecurityLevel;
 use crate::stacked_ratchet::constructor::{AliceToBobTransferType, BobToAliceTransferType};
 use crate::stacked_ratchet::{Ratchet, StackedRatchet};
-use crate::toolset::{Toolset, UpdateStatus};
+use crate::toolset::{Toolset, UpdateStatus, MAX_HYPER_RATCHETS_IN_MEMORY};
+use citadel_io::Mutex;
 use citadel_pqcrypto::algorithm_dictionary::CryptoParameters;
 use citadel_pqcrypto::constructor_opts::ConstructorOpts;
 use serde::{Deserialize, Serialize};
@@ -27,6 +28,55 @@ pub struct PeerSessionCrypto<R: Ratchet = StackedRatchet> {
     /// Alice sends to Bob, then bob updates internally the toolset. However. Bob can't send packets to Alice quite yet using that newest version. He must first wait from Alice to commit on her end and wait for an ACK.
     /// If alice sends a packet using the latest version, that's okay since we already have that drill version on Bob's side; it's just that Bob can't send packets using the latest version until AFTER receiving the ACK
     pub latest_usable_version: u32,
+    /// Bounds the versions accepted for inbound packets relative to `latest_usable_version`
+    #[serde(default)]
+    pub drift_tolerance: DriftTolerance,
+    /// If true, the alice constructor of the next re-key is computed in the background against the
+    /// latest usable version, such that initiating the re-key need not wait on key generation
+    #[serde(default)]
+    pub precompute_constructor: bool,
+    #[serde(skip, default = "new_precomputed_constructor")]
+    precomputed_constructor: Arc<Mutex<PrecomputedConstructor<R>>>,
+}
+
+/// The alice constructor precomputed ahead of the next re-key. Since the constructor options chain
+/// from the ratchet they were derived from, the constructor is only valid against that single
+/// version, and gets discarded once the latest usable version advances. Only a single constructor
+/// is kept, since each re-key consumes exactly one
+struct PrecomputedConstructor<R: Ratchet> {
+    base_version: u32,
+    constructor: Option<R::Constructor>,
+}
+
+fn new_precomputed_constructor<R: Ratchet>() -> Arc<Mutex<PrecomputedConstructor<R>>> {
+    Arc::new(Mutex::new(PrecomputedConstructor {
+        base_version: 0,
+        constructor: None,
+    }))
+}
+
+/// Bounds how far the ratchet version pinned to an inbound packet may drift from the latest usable
+/// version at the receiver before the packet is rejected.
+///
+/// During a re-key, the endpoints briefly disagree on the latest usable version: the initiator
+/// begins using the new version before the receiver is permitted to, and packets sent prior to
+/// the re-key may still be in flight once it completes
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+pub struct DriftTolerance {
+    /// How many versions an inbound packet may trail the latest usable version
+    pub max_lag: u32,
+    /// How many versions an inbound packet may lead the latest usable version. Since only a
+    /// single re-key may be in progress at a time, a peer never leads by more than one version
+    pub max_lead: u32,
+}
+
+impl Default for DriftTolerance {
+    fn default() -> Self {
+        Self {
+            max_lag: MAX_HYPER_RATCHETS_IN_MEMORY as u32,
+            max_lead: 1,
+        }
+    }
 }
 
 impl<R: Ratchet> PeerSessionCrypto<R> {
@@ -43,6 +93,9 @@ impl<R: Ratchet> PeerSessionCrypto<R> {
             rolling_group_id: 0,
             lock_set_by_alice: None,
             latest_usable_version: 0,
+            drift_tolerance: DriftTolerance::default(),
+            precompute_constructor: false,
+            precomputed_constructor: new_precomputed_constructor(),
         }
     }
 
@@ -57,6 +110,9 @@ impl<R: Ratchet> PeerSessionCrypto<R> {
             rolling_group_id: self.rolling_group_id,
             lock_set_by_alice: self.lock_set_by_alice,
             latest_usable_version: self.latest_usable_version,
+            drift_tolerance: self.drift_tolerance,
+            precompute_constructor: self.precompute_constructor,
+            precomputed_constructor: new_precomputed_constructor(),
         }
     }
 
@@ -66,6 +122,154 @@ impl<R: Ratchet> PeerSessionCrypto<R> {
             .get_hyper_ratchet(version.unwrap_or(self.latest_usable_version))
     }
 
+    /// Returns the latest usable version alongside its ratchet. Senders should inscribe the returned
+    /// version into the header of the packet encrypted by the returned ratchet, such that a
+    /// concurrent re-key cannot cause the two to diverge. Returns None if the toolset does not hold
+    /// the exact version
+    pub fn pin_hyper_ratchet(&self) -> Option<(u32, &R)> {
+        let version = self.latest_usable_version;
+        self.toolset
+            .get_hyper_ratchet(version)
+            .filter(|ratchet| ratchet.version() == version)
+            .map(|ratchet| (version, ratchet))
+    }
+
+    /// Gets the ratchet for the version pinned to an inbound packet, failing if the version drifted
+    /// beyond the [`DriftTolerance`] or has since been truncated from the toolset
+    pub fn get_hyper_ratchet_within_drift(&self, version: u32) -> Result<&R, CryptError> {
+        let latest = self.latest_usable_version;
+        let lead = version.wrapping_sub(latest);
+        let lag = latest.wrapping_sub(version);
+
+        if lead <= lag {
+            if lead > self.drift_tolerance.max_lead {
+                return Err(CryptError::Decrypt(format!(
+                    "Packet version {version} leads the latest usable version {latest} by more than {}",
+                    self.drift_tolerance.max_lead
+                )));
+            }
+        } else if lag > self.drift_tolerance.max_lag {
+            return Err(CryptError::Decrypt(format!(
+                "Packet version {version} trails the latest usable version {latest} by more than {}",
+                self.drift_tolerance.max_lag
+            )));
+        }
+
+        match self.toolset.get_hyper_ratchet(version) {
+            Some(ratchet) if ratchet.version() == version => Ok(ratchet),
+            Some(ratchet) => Err(CryptError::Decrypt(format!(
+                "Toolset returned v{} for pinned version {version}",
+                ratchet.version()
+            ))),
+            None => Err(CryptError::Decrypt(format!(
+                "Packet version {version} is no longer in the toolset (oldest: {})",
+                self.toolset.get_oldest_hyper_ratchet_version()
+            ))),
+        }
+    }
+
+    pub fn set_drift_tolerance(&mut self, drift_tolerance: DriftTolerance) {
+        self.drift_tolerance = drift_tolerance;
+    }
+
+    /// Sets the number of ratchet versions retained by the toolset. Both endpoints must use the
+    /// same value, since the truncation of the oldest version is synchronized between them.
+    /// Lowering the window trades the tolerance for delayed packets for a smaller footprint
+    pub fn set_max_toolset_history(&mut self, max: usize) {
+        self.toolset.set_max_hyper_ratchets_in_memory(max);
+    }
+
+    /// Enables computing the alice constructor of the next re-key in the background once the
+    /// latest usable version advances. In Perfect secrecy mode, where every message awaits a
+    /// re-key, this overlaps key generation with the time between messages
+    pub fn set_precompute_constructor(&mut self, enabled: bool) {
+        self.precompute_constructor = enabled;
+        self.precompute_next_constructor();
+    }
+
+    /// Returns true if a precomputed constructor is ready for the next re-key
+    pub fn has_precomputed_constructor(&self) -> bool {
+        let precomputed = self.precomputed_constructor.lock();
+        precomputed.base_version == self.latest_usable_version && precomputed.constructor.is_some()
+    }
+
+    /// Computes the constructor of the next re-key against the latest usable version on a blocking
+    /// thread. Does nothing if disabled, if one is already computed for this version, or if not
+    /// called from within a runtime
+    pub fn precompute_next_constructor(&self) {
+        if !self.precompute_constructor {
+            return;
+        }
+
+        #[cfg(not(target_family = "wasm"))]
+        {
+            if tokio::runtime::Handle::try_current().is_err() {
+                return;
+            }
+
+            let ratchet = match self.get_hyper_ratchet(None) {
+                Some(ratchet) => ratchet.clone(),
+                None => return,
+            };
+
+            let base_version = ratchet.version();
+            {
+                let mut precomputed = self.precomputed_constructor.lock();
+                if precomputed.base_version == base_version && precomputed.constructor.is_some() {
+                    return;
+                }
+
+                precomputed.base_version = base_version;
+                precomputed.constructor = None;
+            }
+
+            let precomputed = self.precomputed_constructor.clone();
+            std::mem::drop(citadel_io::spawn_blocking(move || {
+                let constructor = ratchet.next_alice_constructor();
+                let mut precomputed = precomputed.lock();
+                // a newer version superseded this one while the constructor was generated
+                if precomputed.base_version == base_version && precomputed.constructor.is_none() {
+                    precomputed.constructor = constructor;
+                }
+            }));
+        }
+    }
+
+    /// Takes a precomputed constructor derived from `ratchet`, if one is ready
+    fn take_precomputed_constructor(&self, ratchet: &R) -> Option<R::Constructor> {
+        let mut precomputed = self.precomputed_constructor.lock();
+        if precomputed.base_version == ratchet.version() {
+            precomputed.constructor.take()
+        } else {
+            None
+        }
+    }
+
+    /// Immediately drops every ratchet version older than `peer_version`, returning the number of
+    /// versions dropped. `peer_version` must be a version the peer confirmed it advanced to (e.g.,
+    /// the version pinned to a packet it sent). Inbound packets pinned to a dropped version will
+    /// fail to decrypt, so this should only be used to reclaim memory on long-lived sessions
+    ///
+    /// Fails if a re-key is in progress, or if `peer_version` is ahead of the latest usable version
+    pub fn truncate_toolset_history(&mut self, peer_version: u32) -> Result<usize, CryptError> {
+        if self.update_in_progress.load(Ordering::SeqCst) {
+            return Err(CryptError::DrillUpdateError(
+                "Cannot truncate the toolset while a re-key is in progress".to_string(),
+            ));
+        }
+
+        let latest = self.latest_usable_version;
+        if peer_version != latest
+            && peer_version.wrapping_sub(latest) <= latest.wrapping_sub(peer_version)
+        {
+            return Err(CryptError::DrillUpdateError(format!(
+                "Peer version {peer_version} is ahead of the latest usable version {latest}"
+            )));
+        }
+
+        Ok(self.toolset.truncate_older_than(peer_version))
+    }
+
     /// This should only be called when Bob receives the new DOU during the ReKey phase (will receive transfer), or, when Alice receives confirmation
     /// that the endpoint updated the ratchet (no transfer received, since none needed)
     pub fn commit_next_hyper_ratchet_version(
@@ -193,6 +397,7 @@ impl<R: Ratchet> PeerSessionCrypto<R> {
     /// For bob: this should be called AFTER receiving the TRUNCATE_STATUS/ACK packet
     pub fn post_alice_stage1_or_post_stage1_bob(&mut self) {
         self.latest_usable_version = self.latest_usable_version.wrapping_add(1);
+        self.precompute_next_constructor();
     }
 
     ///
@@ -213,7 +418,9 @@ impl<R: Ratchet> PeerSessionCrypto<R> {
         let set_lock = move |this: &mut Self| {
             this.update_in_progress.store(true, Ordering::SeqCst);
             this.lock_set_by_alice = Some(true);
-            this.get_hyper_ratchet(None)?.next_alice_constructor()
+            let ratchet = this.get_hyper_ratchet(None)?;
+            this.take_precomputed_constructor(ratchet)
+                .or_else(|| ratchet.next_alice_constructor())
         };
 
         if force {
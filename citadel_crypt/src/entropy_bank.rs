@@ -1,15 +1,15 @@
+use crate::entropy_source;
 use crate::misc::{create_port_mapping, CryptError};
 use byteorder::{BigEndian, ByteOrder};
-use rand::{thread_rng, Rng, RngCore};
 use serde::{Deserialize, Serialize};
 use std::convert::TryFrom;
 use std::fmt::Debug;
 use std::fmt::Error;
 use std::fmt::Formatter;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 
+use citadel_pqcrypto::key_domain::KeyDomain;
 use citadel_pqcrypto::{PostQuantumContainer, LARGEST_NONCE_LEN};
-use rand::prelude::ThreadRng;
 
 pub const PORT_RANGE: usize = 14;
 pub const BYTES_PER_STORE: usize = LARGEST_NONCE_LEN;
@@ -34,6 +34,7 @@ impl EntropyBank {
                 entropy: bytes.into(),
                 scramble_mappings: port_mappings.into(),
                 transient_counter,
+                memory_locked: AtomicBool::new(false),
             }
         })
     }
@@ -41,14 +42,11 @@ impl EntropyBank {
     /// For generating a random nonce, independent to any drill
     pub fn generate_public_nonce(
         enx_algorithm: EncryptionAlgorithm,
-    ) -> ArrayVec<u8, LARGEST_NONCE_LEN> {
-        let mut base: ArrayVec<u8, LARGEST_NONCE_LEN> = Default::default();
-        let mut rng = ThreadRng::default();
+    ) -> Result<ArrayVec<u8, LARGEST_NONCE_LEN>, CryptError<String>> {
+        let mut base = [0u8; LARGEST_NONCE_LEN];
         let amt = enx_algorithm.nonce_len();
-        for _ in 0..amt {
-            base.push(rng.gen())
-        }
-        base
+        entropy_source::fill_bytes(&mut base[..amt]).map_err(CryptError::EntropyFailure)?;
+        Ok(base[..amt].iter().copied().collect())
     }
 
     #[inline]
@@ -101,10 +99,26 @@ impl EntropyBank {
         quantum_container: &PostQuantumContainer,
         header_len_bytes: usize,
         full_packet: &mut T,
+    ) -> Result<(), CryptError<String>> {
+        self.protect_packet_in_domain(
+            KeyDomain::Primary,
+            quantum_container,
+            header_len_bytes,
+            full_packet,
+        )
+    }
+
+    /// Protects the packet using the subkeys of `domain`
+    pub fn protect_packet_in_domain<T: EzBuffer>(
+        &self,
+        domain: KeyDomain,
+        quantum_container: &PostQuantumContainer,
+        header_len_bytes: usize,
+        full_packet: &mut T,
     ) -> Result<(), CryptError<String>> {
         self.wrap_with_unique_nonce_enx(full_packet, move |full_packet, nonce| {
             quantum_container
-                .protect_packet_in_place(header_len_bytes, full_packet, nonce)
+                .protect_packet_in_place_in_domain(domain, header_len_bytes, full_packet, nonce)
                 .map_err(|err| CryptError::Encrypt(err.to_string()))
         })
     }
@@ -115,11 +129,27 @@ impl EntropyBank {
         quantum_container: &PostQuantumContainer,
         header: H,
         payload: &mut T,
+    ) -> Result<(), CryptError<String>> {
+        self.validate_packet_in_place_split_in_domain(
+            KeyDomain::Primary,
+            quantum_container,
+            header,
+            payload,
+        )
+    }
+
+    /// Validates the split packet using the subkeys of `domain`
+    pub fn validate_packet_in_place_split_in_domain<H: AsRef<[u8]>, T: EzBuffer>(
+        &self,
+        domain: KeyDomain,
+        quantum_container: &PostQuantumContainer,
+        header: H,
+        payload: &mut T,
     ) -> Result<(), CryptError<String>> {
         let header = header.as_ref();
         self.wrap_with_unique_nonce_dex(payload, move |payload, nonce| {
             quantum_container
-                .validate_packet_in_place(header, payload, nonce)
+                .validate_packet_in_place_in_domain(domain, header, payload, nonce)
                 .map_err(|err| CryptError::Encrypt(err.to_string()))
         })
     }
@@ -231,11 +261,23 @@ impl EntropyBank {
         self.version
     }
 
+    /// Locks the pages holding the entropy into memory, returning true if successful. This must
+    /// only be called once the entropy bank is at its final location in memory (e.g., behind an [`Arc`](std::sync::Arc))
+    pub(crate) fn lock_memory(&self) -> bool {
+        let locked = unsafe { crate::misc::mlock(self.entropy.as_ptr(), BYTES_PER_STORE) };
+        self.memory_locked.store(locked, Ordering::Relaxed);
+        locked
+    }
+
+    /// Returns true if the pages holding the entropy are locked into memory
+    pub fn is_memory_locked(&self) -> bool {
+        self.memory_locked.load(Ordering::Relaxed)
+    }
+
     /// Downloads the data necessary to create a drill
     fn generate_raw_3d_array() -> Result<[u8; BYTES_PER_STORE], CryptError<String>> {
         let mut bytes: [u8; BYTES_PER_STORE] = [0u8; BYTES_PER_STORE];
-        let mut trng = thread_rng();
-        trng.fill_bytes(&mut bytes);
+        entropy_source::fill_bytes(&mut bytes).map_err(CryptError::EntropyFailure)?;
 
         Ok(bytes)
     }
@@ -306,7 +348,7 @@ use bytes::BufMut;
 use citadel_pqcrypto::algorithm_dictionary::EncryptionAlgorithm;
 use citadel_pqcrypto::bytes_in_place::EzBuffer;
 use sha3::Digest;
-use zeroize::Zeroizing;
+use zeroize::{Zeroize, Zeroizing};
 
 /// A entropy bank is a fundamental dataset that continually morphs into new future sets
 #[derive(Serialize, Deserialize)]
@@ -317,6 +359,17 @@ pub struct EntropyBank {
     pub(super) entropy: Zeroizing<[u8; BYTES_PER_STORE]>,
     pub(super) scramble_mappings: Zeroizing<Vec<(u16, u16)>>,
     pub(super) transient_counter: AtomicU64,
+    #[serde(skip)]
+    pub(super) memory_locked: AtomicBool,
+}
+
+impl Drop for EntropyBank {
+    fn drop(&mut self) {
+        if self.memory_locked.load(Ordering::Relaxed) {
+            self.entropy.zeroize();
+            unsafe { crate::misc::munlock(self.entropy.as_ptr(), BYTES_PER_STORE) }
+        }
+    }
 }
 
 /// Returns the approximate number of bytes needed to serialize a Drill
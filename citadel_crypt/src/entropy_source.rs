@@ -0,0 +1,339 @@
+//! The source of entropy used by the [`EntropyBank`](crate::entropy_bank::EntropyBank)
+//!
+//! By default, entropy is drawn from the thread-local CSPRNG. Nodes may instead install an
+//! external source (e.g., an HSM, or an OS-specific RNG) via [`set_entropy_source`]. Every sample
+//! drawn from the installed source is subject to the continuous health tests of NIST SP 800-90B
+//! (the repetition count test and the adaptive proportion test). Once a health test fails, the
+//! source is considered broken for the remainder of the process: every further request for
+//! entropy fails with an [`EntropyHealthError`] instead of silently returning weak entropy
+use rand::RngCore;
+use std::fmt::{Debug, Display, Formatter};
+use std::sync::Mutex;
+
+/// The number of samples that must pass the health tests when a source is installed
+const STARTUP_SAMPLES: usize = 1024;
+
+/// A source of entropy. Each byte is treated as a single sample by the health tests
+pub trait EntropySource: Send + 'static {
+    /// Fills `dest` with entropy
+    fn fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), String>;
+}
+
+impl EntropySource for Box<dyn EntropySource> {
+    fn fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), String> {
+        (**self).fill_bytes(dest)
+    }
+}
+
+/// The default source, backed by the thread-local CSPRNG
+#[derive(Default)]
+pub struct ThreadRngSource;
+
+impl EntropySource for ThreadRngSource {
+    fn fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), String> {
+        rand::thread_rng()
+            .try_fill_bytes(dest)
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// The cutoffs of the continuous health tests. The defaults assume a source with full entropy
+/// (8 bits per byte), and yield a false positive rate of roughly 2^-40 per test
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct HealthTestConfig {
+    /// The test fails once the same sample repeats this many times in a row
+    pub repetition_cutoff: u32,
+    /// The number of samples in each window of the adaptive proportion test
+    pub adaptive_proportion_window: u32,
+    /// The test fails once the first sample of a window occurs this many times within it
+    pub adaptive_proportion_cutoff: u32,
+}
+
+impl Default for HealthTestConfig {
+    fn default() -> Self {
+        Self {
+            repetition_cutoff: 6,
+            adaptive_proportion_window: 512,
+            adaptive_proportion_cutoff: 20,
+        }
+    }
+}
+
+/// Returned when the entropy source fails, or, has previously failed
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EntropyHealthError {
+    /// The same sample repeated `run` times in a row
+    RepetitionCount { sample: u8, run: u32 },
+    /// The first sample of a window occurred `count` times within the window
+    AdaptiveProportion { sample: u8, count: u32, window: u32 },
+    /// The source itself returned an error
+    Source(String),
+}
+
+impl Display for EntropyHealthError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::RepetitionCount { sample, run } => write!(
+                f,
+                "Entropy source failed the repetition count test: {sample:#04x} repeated {run} times"
+            ),
+            Self::AdaptiveProportion {
+                sample,
+                count,
+                window,
+            } => write!(
+                f,
+                "Entropy source failed the adaptive proportion test: {sample:#04x} occurred {count} times in {window} samples"
+            ),
+            Self::Source(err) => write!(f, "Entropy source failed: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for EntropyHealthError {}
+
+/// The continuous health tests of NIST SP 800-90B, section 4.4
+pub struct HealthTests {
+    config: HealthTestConfig,
+    // repetition count test
+    last_sample: Option<u8>,
+    run: u32,
+    // adaptive proportion test
+    window_sample: u8,
+    window_count: u32,
+    window_idx: u32,
+}
+
+impl HealthTests {
+    pub fn new(config: HealthTestConfig) -> Self {
+        Self {
+            config,
+            last_sample: None,
+            run: 0,
+            window_sample: 0,
+            window_count: 0,
+            window_idx: 0,
+        }
+    }
+
+    /// Feeds each sample through both tests, failing on the first sample that trips either
+    pub fn check(&mut self, samples: &[u8]) -> Result<(), EntropyHealthError> {
+        for sample in samples.iter().copied() {
+            self.repetition_count(sample)?;
+            self.adaptive_proportion(sample)?;
+        }
+
+        Ok(())
+    }
+
+    fn repetition_count(&mut self, sample: u8) -> Result<(), EntropyHealthError> {
+        if self.last_sample == Some(sample) {
+            self.run += 1;
+            if self.run >= self.config.repetition_cutoff {
+                return Err(EntropyHealthError::RepetitionCount {
+                    sample,
+                    run: self.run,
+                });
+            }
+        } else {
+            self.last_sample = Some(sample);
+            self.run = 1;
+        }
+
+        Ok(())
+    }
+
+    fn adaptive_proportion(&mut self, sample: u8) -> Result<(), EntropyHealthError> {
+        if self.window_idx == 0 {
+            self.window_sample = sample;
+            self.window_count = 1;
+        } else if self.window_sample == sample {
+            self.window_count += 1;
+            if self.window_count >= self.config.adaptive_proportion_cutoff {
+                return Err(EntropyHealthError::AdaptiveProportion {
+                    sample,
+                    count: self.window_count,
+                    window: self.config.adaptive_proportion_window,
+                });
+            }
+        }
+
+        self.window_idx = (self.window_idx + 1) % self.config.adaptive_proportion_window.max(1);
+        Ok(())
+    }
+}
+
+/// An entropy source whose output is continuously health tested. A failure is latched, such
+/// that a broken source is never used again
+pub struct HealthTestedSource {
+    source: Box<dyn EntropySource>,
+    tests: HealthTests,
+    failure: Option<EntropyHealthError>,
+}
+
+impl HealthTestedSource {
+    /// Wraps `source`, running the start-up health tests before returning
+    pub fn new<S: EntropySource>(
+        source: S,
+        config: HealthTestConfig,
+    ) -> Result<Self, EntropyHealthError> {
+        let mut this = Self {
+            source: Box::new(source),
+            tests: HealthTests::new(config),
+            failure: None,
+        };
+
+        let mut startup = [0u8; STARTUP_SAMPLES];
+        this.fill_bytes(&mut startup)?;
+        Ok(this)
+    }
+
+    pub fn fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), EntropyHealthError> {
+        if let Some(failure) = self.failure.as_ref() {
+            return Err(failure.clone());
+        }
+
+        let result = self
+            .source
+            .fill_bytes(dest)
+            .map_err(EntropyHealthError::Source)
+            .and_then(|_| self.tests.check(dest));
+
+        if let Err(err) = result {
+            log::error!(target: "citadel", "{err}. No further entropy will be drawn from this source");
+            dest.iter_mut().for_each(|byte| *byte = 0);
+            self.failure = Some(err.clone());
+            return Err(err);
+        }
+
+        Ok(())
+    }
+}
+
+impl Debug for HealthTestedSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HealthTestedSource {{ failure: {:?} }}", self.failure)
+    }
+}
+
+static ENTROPY_SOURCE: Mutex<Option<HealthTestedSource>> = Mutex::new(None);
+
+/// Installs `source` as the process-wide entropy source, replacing the previous source. Fails
+/// if `source` does not pass the start-up health tests, in which case the previous source remains
+/// installed
+pub fn set_entropy_source<S: EntropySource>(
+    source: S,
+    config: HealthTestConfig,
+) -> Result<(), EntropyHealthError> {
+    let source = HealthTestedSource::new(source, config)?;
+    *ENTROPY_SOURCE.lock().unwrap_or_else(|err| err.into_inner()) = Some(source);
+    Ok(())
+}
+
+/// Fills `dest` with entropy from the process-wide entropy source, installing the default
+/// source if none is installed
+pub fn fill_bytes(dest: &mut [u8]) -> Result<(), EntropyHealthError> {
+    let mut source = ENTROPY_SOURCE.lock().unwrap_or_else(|err| err.into_inner());
+    if source.is_none() {
+        *source = Some(HealthTestedSource::new(
+            ThreadRngSource,
+            HealthTestConfig::default(),
+        )?);
+    }
+
+    source.as_mut().unwrap().fill_bytes(dest)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::entropy_source::{
+        EntropyHealthError, EntropySource, HealthTestConfig, HealthTestedSource, HealthTests,
+        ThreadRngSource,
+    };
+
+    /// Returns good entropy until `fail_after` bytes were produced, then a constant
+    struct StuckSource {
+        produced: usize,
+        fail_after: usize,
+    }
+
+    impl EntropySource for StuckSource {
+        fn fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), String> {
+            ThreadRngSource.fill_bytes(dest)?;
+            for byte in dest.iter_mut() {
+                if self.produced >= self.fail_after {
+                    *byte = 0xAA;
+                }
+                self.produced += 1;
+            }
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_healthy_source_passes() {
+        let mut source =
+            HealthTestedSource::new(ThreadRngSource, HealthTestConfig::default()).unwrap();
+        let mut buf = [0u8; 4096];
+        for _ in 0..64 {
+            source.fill_bytes(&mut buf).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_repetition_count() {
+        let mut tests = HealthTests::new(HealthTestConfig::default());
+        assert!(tests.check(&[1, 2, 2, 2, 2, 2, 3]).is_ok());
+        assert_eq!(
+            tests.check(&[4; 6]),
+            Err(EntropyHealthError::RepetitionCount { sample: 4, run: 6 })
+        );
+    }
+
+    #[test]
+    fn test_adaptive_proportion() {
+        let config = HealthTestConfig::default();
+        let mut tests = HealthTests::new(config);
+        // interleaving defeats the repetition count test, but not the adaptive proportion test
+        let samples = (0..64u8)
+            .flat_map(|idx| [7, idx.wrapping_add(100)])
+            .collect::<Vec<u8>>();
+        assert_eq!(
+            tests.check(&samples),
+            Err(EntropyHealthError::AdaptiveProportion {
+                sample: 7,
+                count: config.adaptive_proportion_cutoff,
+                window: config.adaptive_proportion_window,
+            })
+        );
+    }
+
+    #[test]
+    fn test_failure_is_latched() {
+        assert!(HealthTestedSource::new(
+            StuckSource {
+                produced: 0,
+                fail_after: 0
+            },
+            HealthTestConfig::default()
+        )
+        .is_err());
+
+        let mut source = HealthTestedSource::new(
+            StuckSource {
+                produced: 0,
+                fail_after: 2048,
+            },
+            HealthTestConfig::default(),
+        )
+        .unwrap();
+        let mut buf = [0u8; 1024];
+        assert!(source.fill_bytes(&mut buf).is_ok());
+        assert!(source.fill_bytes(&mut buf).is_err());
+        assert_eq!(buf, [0u8; 1024]);
+        // the source recovered, but is never trusted again
+        source.source = Box::new(ThreadRngSource);
+        assert!(source.fill_bytes(&mut buf).is_err());
+    }
+}
@@ -217,7 +217,7 @@ impl ThinRatchetConstructor {
             params,
             pqc,
             drill: None,
-            nonce: EntropyBank::generate_public_nonce(params.encryption_algorithm),
+            nonce: EntropyBank::generate_public_nonce(params.encryption_algorithm).ok()?,
             cid,
             version,
         })
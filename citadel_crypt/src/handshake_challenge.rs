@@ -0,0 +1,176 @@
+use citadel_io::Mutex;
+use rand::RngCore;
+use sha3::{Digest, Sha3_256};
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The most leading zero bits a client agrees to search for. Protects clients from servers
+/// demanding an unbounded amount of work
+pub const MAX_DIFFICULTY: u8 = 28;
+
+/// A puzzle the client must solve before the server performs the key exchange
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Challenge {
+    /// Binds the puzzle to the client's address and the current epoch
+    pub cookie: u128,
+    /// The number of leading zero bits the hash of the cookie and the client's nonce must have
+    pub difficulty: u8,
+}
+
+impl Challenge {
+    /// Searches for a nonce solving the puzzle. Each additional bit of difficulty doubles the
+    /// expected number of hashes computed. The search starts at a random nonce, since a solution
+    /// is only redeemed once, yet a client may be issued the same cookie for several handshakes
+    pub fn solve(&self) -> u64 {
+        let start = rand::random::<u64>();
+        (start..=u64::MAX)
+            .chain(0..start)
+            .find(|nonce| is_solution(self.cookie, *nonce, self.difficulty))
+            .unwrap_or_default()
+    }
+}
+
+/// Issues and verifies [`Challenge`]s without storing any state per client. Each cookie is a keyed
+/// hash of the client's address and the epoch it was issued in, hence the server recognizes its
+/// own cookies until the epoch after the one they were issued in ends. Only the solutions redeemed
+/// while their cookies remain valid are stored, such that each solution admits a single handshake
+pub struct ChallengeIssuer {
+    secret: [u8; 32],
+    difficulty: u8,
+    epoch: Duration,
+    // the (cookie, nonce) pairs redeemed, keyed by the epoch of verification
+    redeemed: Mutex<HashMap<u64, HashSet<(u128, u64)>>>,
+}
+
+impl ChallengeIssuer {
+    /// Creates an issuer with a random secret. Cookies expire after one to two `epoch`s
+    pub fn new(difficulty: u8, epoch: Duration) -> Self {
+        let mut secret = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut secret);
+        Self {
+            secret,
+            difficulty: difficulty.min(MAX_DIFFICULTY),
+            epoch: epoch.max(Duration::from_secs(1)),
+            redeemed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn issue(&self, client_addr: IpAddr) -> Challenge {
+        self.issue_at(client_addr, self.current_epoch())
+    }
+
+    /// Returns true if `cookie` was issued to `client_addr` during the current or previous epoch,
+    /// and `nonce` solves the corresponding puzzle without having been redeemed before
+    pub fn verify(&self, client_addr: IpAddr, cookie: u128, nonce: u64) -> bool {
+        self.verify_at(client_addr, cookie, nonce, self.current_epoch())
+    }
+
+    fn issue_at(&self, client_addr: IpAddr, epoch: u64) -> Challenge {
+        Challenge {
+            cookie: self.cookie(client_addr, epoch),
+            difficulty: self.difficulty,
+        }
+    }
+
+    fn verify_at(&self, client_addr: IpAddr, cookie: u128, nonce: u64, epoch: u64) -> bool {
+        let issued = [epoch, epoch.saturating_sub(1)]
+            .into_iter()
+            .any(|epoch| self.cookie(client_addr, epoch) == cookie);
+        if !issued || !is_solution(cookie, nonce, self.difficulty) {
+            return false;
+        }
+
+        let mut redeemed = self.redeemed.lock();
+        // a cookie expires once the epoch after its own ends, so older redemptions need no record
+        redeemed.retain(|redeemed_at, _| *redeemed_at + 2 > epoch);
+        let replayed = redeemed
+            .iter()
+            .any(|(_, solutions)| solutions.contains(&(cookie, nonce)));
+        !replayed && redeemed.entry(epoch).or_default().insert((cookie, nonce))
+    }
+
+    fn current_epoch(&self) -> u64 {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        now.as_secs() / self.epoch.as_secs()
+    }
+
+    fn cookie(&self, client_addr: IpAddr, epoch: u64) -> u128 {
+        let mut hasher = Sha3_256::default();
+        hasher.update(self.secret);
+        match client_addr {
+            IpAddr::V4(addr) => hasher.update(addr.octets()),
+            IpAddr::V6(addr) => hasher.update(addr.octets()),
+        }
+        hasher.update(epoch.to_be_bytes());
+        let digest = hasher.finalize();
+        u128::from_be_bytes(digest[..16].try_into().unwrap())
+    }
+}
+
+/// Returns true if the hash of `cookie` and `nonce` has at least `difficulty` leading zero bits
+pub fn is_solution(cookie: u128, nonce: u64, difficulty: u8) -> bool {
+    let mut hasher = Sha3_256::default();
+    hasher.update(cookie.to_be_bytes());
+    hasher.update(nonce.to_be_bytes());
+    let digest = hasher.finalize();
+    let leading = u128::from_be_bytes(digest[..16].try_into().unwrap()).leading_zeros();
+    leading >= difficulty as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handshake_challenge::{is_solution, ChallengeIssuer, MAX_DIFFICULTY};
+    use std::net::IpAddr;
+    use std::time::Duration;
+
+    #[test]
+    fn test_challenge_is_bound_to_address_and_epoch() {
+        let issuer = ChallengeIssuer::new(8, Duration::from_secs(30));
+        let client: IpAddr = "10.0.0.1".parse().unwrap();
+        let spoofed: IpAddr = "10.0.0.2".parse().unwrap();
+
+        let challenge = issuer.issue_at(client, 100);
+        let nonce = challenge.solve();
+        assert!(is_solution(challenge.cookie, nonce, 8));
+
+        // expired
+        assert!(!issuer.verify_at(client, challenge.cookie, nonce, 102));
+        // issued to another address
+        assert!(!issuer.verify_at(spoofed, challenge.cookie, nonce, 100));
+        // issued by another server
+        let other = ChallengeIssuer::new(8, Duration::from_secs(30));
+        assert!(!other.verify_at(client, challenge.cookie, nonce, 100));
+
+        let unsolved = (0..).find(|nonce| !is_solution(challenge.cookie, *nonce, 8));
+        assert!(!issuer.verify_at(client, challenge.cookie, unsolved.unwrap(), 100));
+
+        let greedy = ChallengeIssuer::new(u8::MAX, Duration::from_secs(30));
+        assert_eq!(greedy.issue_at(client, 0).difficulty, MAX_DIFFICULTY);
+
+        assert!(issuer.verify_at(client, challenge.cookie, nonce, 100));
+        // valid through the next epoch, yet for a fresh solution only
+        let next_nonce = challenge.solve();
+        assert_ne!(nonce, next_nonce);
+        assert!(issuer.verify_at(client, challenge.cookie, next_nonce, 101));
+    }
+
+    #[test]
+    fn test_solution_is_redeemed_once() {
+        let issuer = ChallengeIssuer::new(8, Duration::from_secs(30));
+        let client: IpAddr = "10.0.0.1".parse().unwrap();
+
+        let challenge = issuer.issue_at(client, 100);
+        let nonce = challenge.solve();
+        assert!(issuer.verify_at(client, challenge.cookie, nonce, 100));
+        assert!(!issuer.verify_at(client, challenge.cookie, nonce, 100));
+        assert!(!issuer.verify_at(client, challenge.cookie, nonce, 101));
+
+        // redemptions are forgotten once their cookies expire
+        let later = issuer.issue_at(client, 102);
+        assert!(issuer.verify_at(client, later.cookie, later.solve(), 102));
+        assert_eq!(issuer.redeemed.lock().len(), 1);
+    }
+}
@@ -18,6 +18,7 @@ pub mod prelude {
     };
 
     pub use crate::entropy_bank::{EntropyBank, SecurityLevel};
+    pub use crate::entropy_source::{EntropyHealthError, EntropySource, HealthTestConfig};
     pub use crate::misc::CryptError;
     pub use crate::packet_vector::PacketVector;
     pub use crate::secure_buffer::sec_bytes::SecBuffer;
@@ -25,14 +26,26 @@ pub mod prelude {
     pub use crate::toolset::Toolset;
 }
 
+/// Tokens binding a client to the node of a load-balanced deployment that holds its state
+pub mod affinity_token;
 /// For argon-related functionality
 pub mod argon;
+/// Content-addressed chunks for transfers that short-circuit to the receiver's chunk cache
+pub mod chunk_dedup;
+/// Per-group compression applied to object transfers prior to encryption
+pub mod compression;
+/// rsync-style block signatures and deltas for re-transmitting objects the receiver already holds
+pub mod delta_sync;
 /// An abstraction binding the drill and the PQC
 pub mod endpoint_crypto_container;
 /// Organizes the different types of drills that can be used. Currently, there is only one: The Standard Drill
 pub mod entropy_bank;
+/// Injectable, continuously health-tested entropy sources for the entropy bank
+pub mod entropy_source;
 /// Contains the cryptographic primitives for handling FCM interactions on the network
 pub mod fcm;
+/// Stateless cookies and proof-of-work puzzles guarding the unauthenticated stages of a handshake
+pub mod handshake_challenge;
 /// Error type
 pub mod misc;
 /// For endowing packets with coordinates
@@ -49,3 +62,5 @@ pub mod streaming_crypt_scrambler;
 pub mod sync_toggle;
 /// Provides drill management, update, and versioning. This is what's exposed to the citadel_user api. The drills themselves are abstracted beneath
 pub mod toolset;
+/// Per-chunk integrity manifests for object transfers
+pub mod transfer_manifest;
@@ -1,4 +1,5 @@
 use crate::entropy_bank::PORT_RANGE;
+use crate::entropy_source::EntropyHealthError;
 use crate::prelude::SecurityLevel;
 use rand::prelude::SliceRandom;
 use rand::thread_rng;
@@ -7,6 +8,7 @@ use std::fmt::{Display, Formatter};
 #[cfg(target_family = "unix")]
 use std::os::raw::c_void;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU16, Ordering};
 
 /// Default Error type for this crate
 pub enum CryptError<T = String> {
@@ -20,6 +22,8 @@ pub enum CryptError<T = String> {
     OutOfBoundsError,
     /// This occurs if the byte-valued security level desired does not correspond to an actual [SecurityLevel]
     BadSecuritySetting,
+    /// The entropy source failed its health tests. This is fatal: no further entropy is drawn
+    EntropyFailure(EntropyHealthError),
 }
 
 impl<T> CryptError<T> {
@@ -34,6 +38,7 @@ impl<T> CryptError<T> {
             CryptError::DrillUpdateError(s) => s.into(),
             CryptError::OutOfBoundsError => "[CryptError] Out of bounds exception".to_string(),
             CryptError::BadSecuritySetting => "[CryptError] Bad security setting".to_string(),
+            CryptError::EntropyFailure(err) => err.to_string(),
         }
     }
 
@@ -47,13 +52,17 @@ impl<T> CryptError<T> {
             CryptError::DrillUpdateError(s) => s.as_ref(),
             CryptError::OutOfBoundsError => "[CryptError] Out of bounds exception",
             CryptError::BadSecuritySetting => "[CryptError] Bad security setting",
+            CryptError::EntropyFailure(_) => "[CryptError] Entropy source failed",
         }
     }
 }
 
 impl<T: AsRef<str>> std::fmt::Debug for CryptError<T> {
     fn fmt(&self, f: &mut Formatter) -> Result<(), std::fmt::Error> {
-        write!(f, "{}", self.as_str())
+        match self {
+            CryptError::EntropyFailure(err) => write!(f, "[CryptError] {err}"),
+            _ => write!(f, "{}", self.as_str()),
+        }
     }
 }
 
@@ -85,30 +94,66 @@ pub fn create_port_mapping() -> Vec<(u16, u16)> {
     output_vec
 }
 
-/// Locks-down the memory location, preventing it from being read until unlocked
-/// For linux, returns zero if successful
+/// Locks-down the memory location, preventing it from being swapped to disk until unlocked.
+/// Returns true if the pages were locked. Locking may fail if the process exceeds its limit of
+/// locked memory (e.g., `RLIMIT_MEMLOCK`), in which case the memory remains usable, but swappable
 /// # Safety
 ///
 /// uses libc functions with proper len and start ptr idx
 #[cfg(target_family = "unix")]
 #[allow(unused_results)]
-pub unsafe fn mlock(ptr: *const u8, len: usize) {
-    libc::mlock(ptr as *const c_void, len);
+pub unsafe fn mlock(ptr: *const u8, len: usize) -> bool {
+    let locked = libc::mlock(ptr as *const c_void, len) == 0;
     #[cfg(any(target_os = "freebsd", target_os = "dragonfly"))]
     libc::madvise(ptr as *mut c_void, len, libc::MADV_NOCORE);
     #[cfg(target_os = "linux")]
     libc::madvise(ptr as *mut c_void, len, libc::MADV_DONTDUMP);
+    locked
 }
 
 #[cfg(target_family = "wasm")]
-pub unsafe fn mlock(_ptr: *const u8, _len: usize) {}
+pub unsafe fn mlock(_ptr: *const u8, _len: usize) -> bool {
+    false
+}
 
 #[cfg(target_family = "windows")]
-#[allow(unused_results)]
-/// Locks-down the memory location, preventing it from being read until unlocked
-/// For windows, returns nonzero if successful
-pub unsafe fn mlock(ptr: *const u8, len: usize) {
-    kernel32::VirtualLock(ptr as _, len as u64);
+/// Locks-down the memory location, preventing it from being swapped to disk until unlocked.
+/// Returns true if the pages were locked
+pub unsafe fn mlock(ptr: *const u8, len: usize) -> bool {
+    kernel32::VirtualLock(ptr as _, len as u64) != 0
+}
+
+/// Determines which ratchets have the pages holding their entropy banks locked into memory.
+/// [`SecBuffer`](crate::secure_buffer::sec_bytes::SecBuffer)s are always locked, regardless of
+/// the policy
+#[derive(Copy, Clone, Debug, Default)]
+pub enum MemoryLockPolicy {
+    /// Ratchets are never locked
+    #[default]
+    Disabled,
+    /// Ratchets whose default security level is at, or above, the given level are locked
+    AtOrAbove(SecurityLevel),
+}
+
+// zero implies disabled, otherwise, the minimum security level plus one
+static MEMORY_LOCK_THRESHOLD: AtomicU16 = AtomicU16::new(0);
+
+/// Sets the process-wide [`MemoryLockPolicy`]. Only affects ratchets created afterwards
+pub fn set_memory_lock_policy(policy: MemoryLockPolicy) {
+    let threshold = match policy {
+        MemoryLockPolicy::Disabled => 0,
+        MemoryLockPolicy::AtOrAbove(level) => level.value() as u16 + 1,
+    };
+
+    MEMORY_LOCK_THRESHOLD.store(threshold, Ordering::Relaxed)
+}
+
+/// Returns true if key material at `security_level` should be locked into memory
+pub fn should_lock_memory(security_level: SecurityLevel) -> bool {
+    match MEMORY_LOCK_THRESHOLD.load(Ordering::Relaxed) {
+        0 => false,
+        threshold => security_level.value() as u16 + 1 >= threshold,
+    }
 }
 
 /// Locks-down the memory location, preventing it from being read until unlocked
@@ -138,6 +183,22 @@ pub unsafe fn munlock(ptr: *const u8, len: usize) {
     kernel32::VirtualUnlock(ptr as _, len as u64);
 }
 
+/// Compares two byte slices in time dependent only on their lengths, not their contents.
+/// Use when comparing secrets (e.g., password hashes) to prevent timing side channels
+#[inline(never)]
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let diff = a
+        .iter()
+        .zip(b.iter())
+        .fold(0u8, |diff, (x, y)| diff | (x ^ y));
+    // prevent the compiler from short-circuiting the fold
+    unsafe { std::ptr::read_volatile(&diff) == 0 }
+}
+
 /// General `memset`.
 #[inline(never)]
 unsafe fn memset(s: *mut u8, c: u8, n: usize) {
@@ -175,4 +236,15 @@ pub enum TransferType {
         virtual_path: PathBuf,
         security_level: SecurityLevel,
     },
+    /// The payload is a [`FileDelta`](crate::delta_sync::FileDelta) against the copy of the object
+    /// the receiver already holds, encoded while the object streams
+    DeltaSync {
+        basis_hash: [u8; 32],
+    },
+    /// The payload is encoded via
+    /// [`encode_deduplicated_source`](crate::chunk_dedup::encode_deduplicated_source), such that
+    /// its cached chunks are loaded from the receiver's chunk cache
+    Deduplicated {
+        target_hash: [u8; 32],
+    },
 }
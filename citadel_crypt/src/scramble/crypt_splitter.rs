@@ -17,6 +17,9 @@ use rayon::{iter::IndexedParallelIterator, prelude::*};
 
 /// The maximum bytes per group
 pub const MAX_BYTES_PER_GROUP: usize = 1024 * 1024 * 10;
+/// The upper bound on the ciphertext a receiver allocates for a single group. Encryption only adds
+/// a small, per-wave overhead to the plaintext, so legitimate groups stay well below this bound
+const MAX_GROUP_CIPHERTEXT_LEN: usize = MAX_BYTES_PER_GROUP * 2;
 ///
 pub const MAX_WAVEFORM_PACKET_SIZE: usize = 480;
 
@@ -234,7 +237,12 @@ where
     debug_assert_ne!(cfg.last_plaintext_wave_length, 0);
 
     if msg_pqc.params.encryption_algorithm != EncryptionAlgorithm::Kyber
-        && matches!(&transfer_type, TransferType::FileTransfer)
+        && matches!(
+            &transfer_type,
+            TransferType::FileTransfer
+                | TransferType::DeltaSync { .. }
+                | TransferType::Deduplicated { .. }
+        )
     {
         debug_assert_eq!(cfg.packets_needed, packets.len());
     } else {
@@ -460,6 +468,53 @@ impl GroupReceiverConfig {
         }
     }
 
+    /// Ensures a configuration received from the network is bounded, such that constructing a
+    /// [`GroupReceiver`] from it can neither overflow nor over-allocate
+    pub fn validate(&self) -> Result<(), CryptError> {
+        let invalid = |reason: &str| {
+            Err(CryptError::Decrypt(format!(
+                "Invalid group config: {reason}"
+            )))
+        };
+
+        if self.plaintext_length > MAX_BYTES_PER_GROUP {
+            return invalid("oversized plaintext");
+        }
+
+        if self.max_packets_per_wave == 0 || self.max_payload_size == 0 || self.wave_count == 0 {
+            return invalid("zero-sized wave or payload");
+        }
+
+        if self.packets_in_last_wave > self.max_packets_per_wave
+            || self.last_payload_size > self.max_payload_size
+        {
+            return invalid("inconsistent last wave");
+        }
+
+        // Mirrors the allocations performed by GroupReceiver::new
+        let ciphertext_capacity = self
+            .max_payload_size
+            .checked_mul(self.max_packets_per_wave)
+            .and_then(|per_wave| per_wave.checked_mul(self.wave_count - 1))
+            .and_then(|full_waves| {
+                self.packets_in_last_wave
+                    .saturating_sub(1)
+                    .checked_mul(self.max_payload_size)?
+                    .checked_add(self.last_payload_size)?
+                    .checked_add(full_waves)
+            });
+
+        match ciphertext_capacity {
+            Some(capacity)
+                if capacity <= MAX_GROUP_CIPHERTEXT_LEN
+                    && self.packets_needed <= MAX_GROUP_CIPHERTEXT_LEN =>
+            {
+                Ok(())
+            }
+            _ => invalid("oversized ciphertext"),
+        }
+    }
+
     pub fn get_packet_count_in_wave(&self, wave_id: usize) -> usize {
         if wave_id == self.wave_count - 1 {
             self.packets_in_last_wave
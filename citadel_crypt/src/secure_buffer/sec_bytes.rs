@@ -4,10 +4,12 @@ use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt::Debug;
 use std::fmt::Formatter;
 use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 /// A memory-secure wrapper for shipping around Bytes
 pub struct SecBuffer {
     inner: BytesMut,
+    locked: AtomicBool,
 }
 
 impl SecBuffer {
@@ -36,12 +38,21 @@ impl SecBuffer {
         self.inner.len()
     }
 
+    /// Returns true if the pages holding the contents are locked into memory, preventing them
+    /// from being swapped to disk. Locking fails gracefully, e.g., if the process exceeds its
+    /// limit of locked memory, in which case the contents remain usable
+    pub fn is_memory_locked(&self) -> bool {
+        self.locked.load(Ordering::Relaxed)
+    }
+
     fn lock(&self) {
-        unsafe { crate::misc::mlock(self.slice().as_ptr(), self.inner.len()) }
+        let locked = unsafe { crate::misc::mlock(self.slice().as_ptr(), self.inner.len()) };
+        self.locked.store(locked, Ordering::Relaxed)
     }
 
     fn unlock(&self) {
         unsafe { crate::misc::munlock(self.slice().as_ptr(), self.inner.len()) }
+        self.locked.store(false, Ordering::Relaxed)
     }
 
     fn zeroize(&mut self) {
@@ -114,7 +125,10 @@ impl From<SecString> for SecBuffer {
 
 impl From<BytesMut> for SecBuffer {
     fn from(inner: BytesMut) -> Self {
-        let this = Self { inner };
+        let this = Self {
+            inner,
+            locked: AtomicBool::new(false),
+        };
         this.lock();
         this
     }
@@ -152,8 +166,9 @@ impl Debug for SecBuffer {
 }
 
 impl<T: AsRef<[u8]>> PartialEq<T> for SecBuffer {
+    /// Compares in constant time, since the contents are secret
     fn eq(&self, other: &T) -> bool {
-        self.as_ref() == other.as_ref()
+        crate::misc::constant_time_eq(self.as_ref(), other.as_ref())
     }
 }
 
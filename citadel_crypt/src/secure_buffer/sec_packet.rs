@@ -102,6 +102,17 @@ impl<const N: usize> SecureMessagePacket<N> {
         Ok(payload)
     }
 
+    /// The length of the payload written by the user, excluding its length field. Zero until
+    /// the payload is written
+    pub fn message_len(&self) -> usize {
+        match self {
+            Self::PayloadNext(_) => 0,
+            Self::HeaderNext(packet) | Self::FinalPayloadExt(packet) => {
+                (packet.inner.layout()[PAYLOAD_PART] as usize).saturating_sub(4)
+            }
+        }
+    }
+
     /// The first write to the buffer should be the payload
     pub fn write_payload(
         &mut self,
@@ -54,7 +54,7 @@ impl SecString {
 
     fn lock(&self) {
         let (ptr, len) = decompose(&self.inner);
-        unsafe { crate::misc::mlock(ptr, len) }
+        let _ = unsafe { crate::misc::mlock(ptr, len) };
     }
 
     fn unlock(&self) {
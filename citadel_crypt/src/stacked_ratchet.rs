@@ -6,6 +6,7 @@ use crate::stacked_ratchet::constructor::StackedRatchetConstructor;
 use bytes::BytesMut;
 use citadel_pqcrypto::bytes_in_place::EzBuffer;
 use citadel_pqcrypto::constructor_opts::{ConstructorOpts, RecursiveChain};
+use citadel_pqcrypto::key_domain::KeyDomain;
 use citadel_pqcrypto::PostQuantumContainer;
 use serde::{Deserialize, Serialize};
 use sha3::Digest;
@@ -252,12 +253,29 @@ impl StackedRatchet {
         security_level: Option<SecurityLevel>,
         header_len_bytes: usize,
         packet: &mut T,
+    ) -> Result<(), CryptError<String>> {
+        self.protect_message_packet_in_domain(
+            KeyDomain::Primary,
+            security_level,
+            header_len_bytes,
+            packet,
+        )
+    }
+
+    /// Protects the packet using the subkeys of `domain`. Packets protected in one domain can
+    /// only be validated in the same domain
+    pub fn protect_message_packet_in_domain<T: EzBuffer>(
+        &self,
+        domain: KeyDomain,
+        security_level: Option<SecurityLevel>,
+        header_len_bytes: usize,
+        packet: &mut T,
     ) -> Result<(), CryptError<String>> {
         let idx = self.verify_level(security_level)?;
 
         for n in 0..=idx {
             let (pqc, drill) = self.message_pqc_drill(Some(n));
-            drill.protect_packet(pqc, header_len_bytes, packet)?;
+            drill.protect_packet_in_domain(domain, pqc, header_len_bytes, packet)?;
         }
 
         Ok(())
@@ -285,16 +303,53 @@ impl StackedRatchet {
         security_level: Option<SecurityLevel>,
         header: H,
         packet: &mut BytesMut,
+    ) -> Result<(), CryptError<String>> {
+        self.validate_message_packet_in_place_split_in_domain(
+            KeyDomain::Primary,
+            security_level,
+            header,
+            packet,
+        )
+    }
+
+    /// Validates in-place using the subkeys of `domain` when the header + payload have already
+    /// been split
+    pub fn validate_message_packet_in_place_split_in_domain<H: AsRef<[u8]>>(
+        &self,
+        domain: KeyDomain,
+        security_level: Option<SecurityLevel>,
+        header: H,
+        packet: &mut BytesMut,
     ) -> Result<(), CryptError<String>> {
         let idx = self.verify_level(security_level)?;
         for n in (0..=idx).rev() {
             let (pqc, drill) = self.message_pqc_drill(Some(n));
-            drill.validate_packet_in_place_split(pqc, &header, packet)?;
+            drill.validate_packet_in_place_split_in_domain(domain, pqc, &header, packet)?;
         }
 
         Ok(())
     }
 
+    /// Returns the fingerprint of the subkeys of `domain` for each layer of the message ratchet,
+    /// such that auditors may verify that the domains are separated. See
+    /// [`PostQuantumContainer::key_domain_fingerprint`]
+    pub fn key_domain_fingerprints(&self, domain: KeyDomain) -> Vec<[u8; 32]> {
+        self.inner
+            .message
+            .inner
+            .iter()
+            .filter_map(|container| container.pqc.key_domain_fingerprint(domain))
+            .collect()
+    }
+
+    /// Computes a tag over `message` under the keys of this ratchet version, binding the message
+    /// to the session. See [`PostQuantumContainer::mac`]
+    pub fn authenticate(&self, context: &[u8], message: &[u8]) -> Result<[u8; 32], CryptError> {
+        self.get_message_pqc(None)
+            .mac(context, message)
+            .ok_or_else(|| CryptError::Encrypt("Symmetric keys are not loaded".to_string()))
+    }
+
     /// Encrypts the data into a Vec<u8>
     pub fn encrypt<T: AsRef<[u8]>>(&self, contents: T) -> Result<Vec<u8>, CryptError<String>> {
         let (pqc, drill) = self.message_pqc_drill(None);
@@ -335,6 +390,17 @@ impl StackedRatchet {
         &self.inner.message.inner[idx.unwrap_or(0)].pqc
     }
 
+    /// Returns true if every entropy bank of this ratchet is locked into memory. See
+    /// [`MemoryLockPolicy`](crate::misc::MemoryLockPolicy)
+    pub fn is_memory_locked(&self) -> bool {
+        self.inner
+            .message
+            .inner
+            .iter()
+            .all(|container| container.drill.is_memory_locked())
+            && self.inner.scramble.drill.is_memory_locked()
+    }
+
     /// Returns the scramble drill
     pub fn get_scramble_drill(&self) -> &EntropyBank {
         &self.inner.scramble.drill
@@ -385,9 +451,26 @@ pub(crate) struct ScrambleRatchet {
 
 impl From<StackedRatchetInner> for StackedRatchet {
     fn from(inner: StackedRatchetInner) -> Self {
-        Self {
+        let this = Self {
             inner: Arc::new(inner),
+        };
+
+        // the entropy banks are now behind the Arc, and thus will not move
+        if crate::misc::should_lock_memory(this.inner.default_security_level) {
+            let locked = this
+                .inner
+                .message
+                .inner
+                .iter()
+                .map(|container| &container.drill)
+                .chain(std::iter::once(&this.inner.scramble.drill))
+                .fold(true, |locked, drill| drill.lock_memory() && locked);
+            if !locked {
+                log::warn!(target: "citadel", "Unable to lock ratchet v{} into memory. It may be swapped to disk", this.version());
+            }
         }
+
+        this
     }
 }
 
@@ -647,8 +730,10 @@ pub mod constructor {
                     pqc: PostQuantumContainer::new_alice(ConstructorOpts::new_init(Some(params)))
                         .ok()?,
                 },
-                nonce_message: EntropyBank::generate_public_nonce(params.encryption_algorithm),
-                nonce_scramble: EntropyBank::generate_public_nonce(params.encryption_algorithm),
+                nonce_message: EntropyBank::generate_public_nonce(params.encryption_algorithm)
+                    .ok()?,
+                nonce_scramble: EntropyBank::generate_public_nonce(params.encryption_algorithm)
+                    .ok()?,
                 cid,
                 new_version,
                 security_level,
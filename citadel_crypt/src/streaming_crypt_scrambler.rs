@@ -6,12 +6,14 @@ use tokio::macros::support::Pin;
 use tokio::sync::mpsc::Sender as GroupChanneler;
 use tokio::sync::oneshot::Receiver;
 
+use crate::compression::{CompressionAlgorithm, GroupCompressor, MAX_COMPRESSIBLE_GROUP_LEN};
 use crate::entropy_bank::{EntropyBank, SecurityLevel};
 use crate::packet_vector::PacketVector;
 use crate::scramble::crypt_splitter::{par_scramble_encrypt_group, GroupSenderDevice};
 
 use crate::misc::{CryptError, TransferType};
 use crate::stacked_ratchet::StackedRatchet;
+use crate::transfer_manifest::TransferManifest;
 use citadel_io::Mutex;
 use citadel_io::{BlockingSpawn, BlockingSpawnError};
 use futures::Future;
@@ -28,6 +30,14 @@ const DEFAULT_BYTES_PER_GROUP: usize = 1024 * 1024 * 3;
 /// Used for streaming sources of a fixed size
 pub trait FixedSizedSource: Read + Send + 'static {
     fn length(&self) -> std::io::Result<u64>;
+    /// Seeks back to the beginning of the source. Sources that cannot be read twice
+    /// are transferred without a [`TransferManifest`]
+    fn rewind(&mut self) -> std::io::Result<()> {
+        Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "Source cannot be rewound",
+        ))
+    }
 }
 
 #[cfg(feature = "filesystem")]
@@ -35,6 +45,10 @@ impl FixedSizedSource for std::fs::File {
     fn length(&self) -> std::io::Result<u64> {
         self.metadata().map(|r| r.len())
     }
+
+    fn rewind(&mut self) -> std::io::Result<()> {
+        std::io::Seek::rewind(self)
+    }
 }
 
 /// Generic function for inscribing headers on packets
@@ -121,6 +135,11 @@ impl ObjectSource for BytesSource {
             fn length(&self) -> std::io::Result<u64> {
                 Ok(self.len as u64)
             }
+
+            fn rewind(&mut self) -> std::io::Result<()> {
+                self.cursor.set_position(0);
+                Ok(())
+            }
         }
 
         let inner = self
@@ -153,6 +172,9 @@ impl<T: Into<Vec<u8>>> From<T> for BytesSource {
 ///
 /// `header_inscriber`: the feed order for u64's is first the target_cid, and then the object-ID
 ///
+/// `manifest_tx`: If present, a [`TransferManifest`] is computed on the blocking pool and sent through
+/// before the first group is rendered. None is sent if the source cannot be rewound
+///
 /// This is ran on a separate thread on the threadpool. Returns the number of bytes and number of groups
 #[allow(clippy::too_many_arguments)]
 pub fn scramble_encrypt_source<S: ObjectSource, F: HeaderInscriberFn, const N: usize>(
@@ -169,6 +191,8 @@ pub fn scramble_encrypt_source<S: ObjectSource, F: HeaderInscriberFn, const N: u
     group_id: u64,
     transfer_type: TransferType,
     header_inscriber: F,
+    manifest_tx: Option<ManifestSender>,
+    compression_rx: Option<CompressionReceiver>,
 ) -> Result<(usize, usize, usize), CryptError> {
     let source = source.try_get_stream()?;
     let object_len = source
@@ -182,43 +206,76 @@ pub fn scramble_encrypt_source<S: ObjectSource, F: HeaderInscriberFn, const N: u
         )));
     }
 
+    // the group count is fixed before the receiver answers the offer, so room for the compression
+    // frame is left whenever compression may be applied
+    let max_bytes_per_group = if compression_rx.is_some() {
+        std::cmp::min(max_bytes_per_group, MAX_COMPRESSIBLE_GROUP_LEN)
+    } else {
+        max_bytes_per_group
+    };
+
     let total_groups = Integer::div_ceil(&object_len, &max_bytes_per_group);
 
     log::trace!(target: "citadel", "Will parallel_scramble_encrypt file object {}, which is {} bytes or {} MB. {} groups total", object_id, object_len, (object_len as f32)/(1024f32*1024f32), total_groups);
-    let reader = BufReader::with_capacity(std::cmp::min(object_len, max_bytes_per_group), source);
-
-    let buffer = Arc::new(Mutex::new(vec![
-        0u8;
-        std::cmp::min(
-            object_len,
-            max_bytes_per_group
-        )
-    ]));
-    let file_scrambler = AsyncCryptScrambler {
-        total_groups,
-        buffer,
-        groups_rendered: 0,
-        object_id,
-        header_size_bytes,
-        target_cid,
-        group_id,
-        security_level,
-        hyper_ratchet,
-        static_aux_ratchet,
-        reader,
-        transfer_type,
-        file_len: object_len,
-        max_bytes_per_group,
-        read_cursor: 0,
-        header_inscriber: Arc::new(header_inscriber),
-        poll_amt: 0,
-        cur_task: None,
+
+    let streamer_group_sender = group_sender.clone();
+    let streamer = async move {
+        let source = if let Some(manifest_tx) = manifest_tx {
+            let (source, manifest) =
+                compute_manifest(source, object_len, max_bytes_per_group).await?;
+            let _ = manifest_tx.send(manifest);
+            source
+        } else {
+            source
+        };
+
+        // the compression offered in the file header is only applied if the receiver accepts it
+        let compression = if let Some(compression_rx) = compression_rx {
+            compression_rx.await.ok().flatten()
+        } else {
+            None
+        };
+
+        let reader =
+            BufReader::with_capacity(std::cmp::min(object_len, max_bytes_per_group), source);
+
+        let buffer = Arc::new(Mutex::new(vec![
+            0u8;
+            std::cmp::min(
+                object_len,
+                max_bytes_per_group
+            )
+        ]));
+        let file_scrambler = AsyncCryptScrambler {
+            total_groups,
+            buffer,
+            groups_rendered: 0,
+            object_id,
+            header_size_bytes,
+            target_cid,
+            group_id,
+            security_level,
+            hyper_ratchet,
+            static_aux_ratchet,
+            reader,
+            transfer_type,
+            compressor: compression
+                .map(|algorithm| Arc::new(Mutex::new(GroupCompressor::new(algorithm)))),
+            file_len: object_len,
+            max_bytes_per_group,
+            read_cursor: 0,
+            header_inscriber: Arc::new(header_inscriber),
+            poll_amt: 0,
+            cur_task: None,
+        };
+
+        file_streamer(streamer_group_sender, file_scrambler).await
     };
 
     let handle = citadel_io::spawn(async move {
         let res = tokio::select! {
             res0 = stopper(stop) => res0,
-            res1 = file_streamer(group_sender.clone(), file_scrambler) => res1
+            res1 = streamer => res1
         };
 
         if let Err(err) = res {
@@ -232,6 +289,33 @@ pub fn scramble_encrypt_source<S: ObjectSource, F: HeaderInscriberFn, const N: u
     Ok((object_len, total_groups, max_bytes_per_group))
 }
 
+/// Sends the manifest of an object, or None if the source does not support manifests
+pub type ManifestSender = tokio::sync::oneshot::Sender<Option<TransferManifest>>;
+/// Receives the compression algorithm negotiated for an object, or None if it is to be sent uncompressed
+pub type CompressionReceiver = Receiver<Option<CompressionAlgorithm>>;
+
+async fn compute_manifest(
+    mut source: Box<dyn FixedSizedSource>,
+    object_len: usize,
+    chunk_size: usize,
+) -> Result<(Box<dyn FixedSizedSource>, Option<TransferManifest>), CryptError> {
+    citadel_io::spawn_blocking(move || {
+        // probe first so that sources which cannot be re-read are not consumed
+        if source.rewind().is_err() {
+            return Ok((source, None));
+        }
+
+        let manifest = TransferManifest::from_reader(&mut source, object_len, chunk_size)
+            .map_err(|err| CryptError::Encrypt(err.to_string()))?;
+        source
+            .rewind()
+            .map_err(|err| CryptError::Encrypt(err.to_string()))?;
+        Ok((source, Some(manifest)))
+    })
+    .await
+    .map_err(|err| CryptError::Encrypt(err.message))?
+}
+
 async fn stopper(stop: Receiver<()>) -> Result<(), CryptError> {
     stop.await
         .map_err(|err| CryptError::Encrypt(err.to_string()))
@@ -258,6 +342,8 @@ struct AsyncCryptScrambler<F: HeaderInscriberFn, R: Read, const N: usize> {
     static_aux_ratchet: StackedRatchet,
     security_level: SecurityLevel,
     transfer_type: TransferType,
+    // groups are rendered one at a time, in order, sharing a single compression stream
+    compressor: Option<Arc<Mutex<GroupCompressor>>>,
     file_len: usize,
     read_cursor: usize,
     object_id: u32,
@@ -319,6 +405,7 @@ impl<F: HeaderInscriberFn, R: Read, const N: usize> AsyncCryptScrambler<F, R, N>
             max_bytes_per_group,
             cur_task,
             transfer_type,
+            compressor,
             poll_amt,
             ..
         } = &mut *self;
@@ -344,10 +431,16 @@ impl<F: HeaderInscriberFn, R: Read, const N: usize> AsyncCryptScrambler<F, R, N>
                 let target_cid = *target_cid;
                 let object_id = *object_id;
                 let transfer_type = transfer_type.clone();
+                let compressor = compressor.clone();
 
                 let task = citadel_io::spawn_blocking(move || {
+                    let lock = buffer.lock();
+                    let plaintext = &lock[..poll_len];
+                    let compressed = compressor
+                        .map(|compressor| compressor.lock().compress(plaintext))
+                        .transpose()?;
                     par_scramble_encrypt_group(
-                        &buffer.lock()[..poll_len],
+                        compressed.as_deref().unwrap_or(plaintext),
                         security_level,
                         &hyper_ratchet,
                         &static_aux_ratchet,
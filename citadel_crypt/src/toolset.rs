@@ -12,6 +12,11 @@ pub const MAX_HYPER_RATCHETS_IN_MEMORY: usize = 6;
 #[cfg(not(debug_assertions))]
 pub const MAX_HYPER_RATCHETS_IN_MEMORY: usize = 128;
 
+/// The fewest ratchets a toolset may be configured to retain. At least two versions must be
+/// retained, otherwise the previous version would be dropped while packets pinned to it may
+/// still be in flight during a re-key
+pub const MIN_HYPER_RATCHETS_IN_MEMORY: usize = 2;
+
 /// The reserved version for the static aux ratchet
 pub const STATIC_AUX_VERSION: u32 = 0;
 
@@ -25,6 +30,9 @@ pub struct Toolset<R: Ratchet> {
     oldest_hyper_ratchet_version: u32,
     #[serde(bound = "")]
     map: VecDeque<R>,
+    /// The number of ratchets retained before the oldest must be deregistered
+    #[serde(default = "default_max_hyper_ratchets_in_memory")]
+    max_hyper_ratchets_in_memory: usize,
     /// The static auxiliary drill was made to cover a unique situation that is consequence of dropping-off the back of the VecDeque upon upgrade:
     /// As the back gets dropped, any data encrypted using that version now becomes undecipherable forever. The solution to this is having a static drill, but this
     /// does indeed compromise safety. This should NEVER be used for network data transmission (except for first packets), and should only
@@ -44,6 +52,7 @@ impl<R: Ratchet> Clone for Toolset<R> {
             most_recent_hyper_ratchet_version: self.most_recent_hyper_ratchet_version,
             oldest_hyper_ratchet_version: self.oldest_hyper_ratchet_version,
             map: self.map.clone(),
+            max_hyper_ratchets_in_memory: self.max_hyper_ratchets_in_memory,
             static_auxiliary_hyper_ratchet: self.static_auxiliary_hyper_ratchet.clone(),
         }
     }
@@ -69,6 +78,7 @@ impl<R: Ratchet> Toolset<R> {
             most_recent_hyper_ratchet_version: 0,
             oldest_hyper_ratchet_version: 0,
             map,
+            max_hyper_ratchets_in_memory: MAX_HYPER_RATCHETS_IN_MEMORY,
             static_auxiliary_hyper_ratchet: hyper_ratchet,
         }
     }
@@ -86,6 +96,7 @@ impl<R: Ratchet> Toolset<R> {
             most_recent_hyper_ratchet_version,
             oldest_hyper_ratchet_version,
             map,
+            max_hyper_ratchets_in_memory: MAX_HYPER_RATCHETS_IN_MEMORY,
             static_auxiliary_hyper_ratchet: hyper_ratchet,
         }
     }
@@ -113,7 +124,7 @@ impl<R: Ratchet> Toolset<R> {
         self.most_recent_hyper_ratchet_version = cur_version;
 
         let prev_version = self.most_recent_hyper_ratchet_version.wrapping_sub(1);
-        log::trace!(target: "citadel", "[{}] Upgraded {} to {}. Adjusted index of current: {}. Adjusted index of (current - 1): {} || OLDEST: {} || LEN: {}", MAX_HYPER_RATCHETS_IN_MEMORY, prev_version, cur_version, self.get_adjusted_index(cur_version), self.get_adjusted_index(prev_version), self.get_oldest_hyper_ratchet_version(), self.map.len());
+        log::trace!(target: "citadel", "[{}] Upgraded {} to {}. Adjusted index of current: {}. Adjusted index of (current - 1): {} || OLDEST: {} || LEN: {}", self.max_hyper_ratchets_in_memory, prev_version, cur_version, self.get_adjusted_index(cur_version), self.get_adjusted_index(prev_version), self.get_oldest_hyper_ratchet_version(), self.map.len());
         Some(update_status)
     }
 
@@ -126,7 +137,7 @@ impl<R: Ratchet> Toolset<R> {
         let new_version = hyper_ratchet.version();
         //println!("max hypers: {} @ {} bytes ea", MAX_HYPER_RATCHETS_IN_MEMORY, get_approx_bytes_per_hyper_ratchet());
         self.map.push_front(hyper_ratchet);
-        if self.map.len() > MAX_HYPER_RATCHETS_IN_MEMORY {
+        if self.map.len() > self.max_hyper_ratchets_in_memory {
             let old_version = self.get_oldest_hyper_ratchet_version();
             log::trace!(target: "citadel", "[Toolset Update] Needs Truncation. Old version: {}", old_version);
             UpdateStatus::CommittedNeedsSynchronization {
@@ -145,7 +156,7 @@ impl<R: Ratchet> Toolset<R> {
     /// this function last. By doing this, Alice no longer sends packets that may be no longer be valid
     #[allow(unused_results)]
     pub fn deregister_oldest_hyper_ratchet(&mut self, version: u32) -> Result<(), CryptError> {
-        if self.map.len() <= MAX_HYPER_RATCHETS_IN_MEMORY {
+        if self.map.len() <= self.max_hyper_ratchets_in_memory {
             return Err(CryptError::DrillUpdateError(
                 "Cannot call for deregistration unless the map len is maxed out".to_string(),
             ));
@@ -164,6 +175,46 @@ impl<R: Ratchet> Toolset<R> {
         }
     }
 
+    /// Drops every ratchet older than `version`, irrespective of the configured maximum. The most
+    /// recent ratchet is always retained. Returns the number of ratchets dropped
+    ///
+    /// Unlike [`Self::deregister_oldest_hyper_ratchet`], this does not synchronize with the peer.
+    /// The caller must first ensure that neither endpoint will send nor receive packets pinned to
+    /// any dropped version
+    pub fn truncate_older_than(&mut self, version: u32) -> usize {
+        let max_idx = self.get_adjusted_index(version);
+        let mut dropped = 0;
+        while self.map.len() > 1 && self.map.len() > max_idx + 1 {
+            if self.map.pop_back().is_none() {
+                break;
+            }
+
+            self.oldest_hyper_ratchet_version = self.oldest_hyper_ratchet_version.wrapping_add(1);
+            dropped += 1;
+        }
+
+        if dropped != 0 {
+            log::trace!(target: "citadel", "[Toolset] Truncated {} versions. New oldest: {} | LEN: {}", dropped, self.oldest_hyper_ratchet_version, self.len());
+        }
+
+        dropped
+    }
+
+    /// Returns the number of ratchets retained before the oldest must be deregistered
+    pub fn max_hyper_ratchets_in_memory(&self) -> usize {
+        self.max_hyper_ratchets_in_memory
+    }
+
+    /// Sets the number of ratchets retained before the oldest must be deregistered. Both endpoints
+    /// of a session must use the same value. Values are clamped to
+    /// [`MIN_HYPER_RATCHETS_IN_MEMORY`]..=[`MAX_HYPER_RATCHETS_IN_MEMORY`]. Lowering the value below
+    /// the number of currently-retained ratchets does not drop any ratchets; see
+    /// [`Self::truncate_older_than`]
+    pub fn set_max_hyper_ratchets_in_memory(&mut self, max: usize) {
+        self.max_hyper_ratchets_in_memory =
+            max.clamp(MIN_HYPER_RATCHETS_IN_MEMORY, MAX_HYPER_RATCHETS_IN_MEMORY);
+    }
+
     /// Returns the number of StackedRatchets internally
     #[allow(clippy::len_without_is_empty)]
     pub fn len(&self) -> usize {
@@ -255,6 +306,10 @@ impl<R: Ratchet> Toolset<R> {
     }
 }
 
+fn default_max_hyper_ratchets_in_memory() -> usize {
+    MAX_HYPER_RATCHETS_IN_MEMORY
+}
+
 /// Makes replacing/synchronizing toolsets easier
 /// input: (static_aux_ratchet, f(0))
 pub type StaticAuxRatchet = StackedRatchet;
@@ -269,6 +324,7 @@ impl From<(StaticAuxRatchet, StackedRatchet)> for Toolset<StackedRatchet> {
             oldest_hyper_ratchet_version,
             most_recent_hyper_ratchet_version,
             map,
+            max_hyper_ratchets_in_memory: MAX_HYPER_RATCHETS_IN_MEMORY,
             static_auxiliary_hyper_ratchet: drill.0,
         }
     }
@@ -0,0 +1,196 @@
+use crate::misc::{constant_time_eq, CryptError};
+use crate::stacked_ratchet::StackedRatchet;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::io::Read;
+
+const MANIFEST_TAG_CONTEXT: &[u8] = b"citadel/v1/transfer-manifest";
+
+/// Describes the plaintext of an object before it is transferred. The manifest is sent to the
+/// receiver ahead of the first group alongside a tag binding it to the session keys, the sender
+/// and the object, and allows the receiver to verify each chunk as it arrives
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct TransferManifest {
+    pub total_size: u64,
+    pub chunk_size: usize,
+    pub chunk_hashes: Vec<[u8; 32]>,
+    pub content_hash: [u8; 32],
+}
+
+impl TransferManifest {
+    /// Reads `total_size` bytes from `reader`, hashing every `chunk_size` bytes. The chunk size
+    /// must match the group size used by the scrambler for the chunk indices to line-up with
+    /// the relative group IDs
+    pub fn from_reader<R: Read>(
+        mut reader: R,
+        total_size: usize,
+        chunk_size: usize,
+    ) -> std::io::Result<Self> {
+        if chunk_size == 0 {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Chunk size must be non-zero",
+            ));
+        }
+
+        let mut content_hasher = Sha3_256::default();
+        let mut chunk_hashes =
+            Vec::with_capacity(num_integer::Integer::div_ceil(&total_size, &chunk_size));
+        let mut buf = vec![0u8; std::cmp::min(total_size, chunk_size)];
+        let mut remaining = total_size;
+
+        while remaining != 0 {
+            let chunk = &mut buf[..std::cmp::min(remaining, chunk_size)];
+            reader.read_exact(chunk)?;
+            content_hasher.update(&*chunk);
+            chunk_hashes.push(hash(chunk));
+            remaining -= chunk.len();
+        }
+
+        Ok(Self {
+            total_size: total_size as u64,
+            chunk_size,
+            chunk_hashes,
+            content_hash: content_hasher.finalize().into(),
+        })
+    }
+
+    /// Computes the tag binding this manifest to the session of `ratchet`, the sending CID and the
+    /// object ID, such that a manifest cannot be forged, nor replayed for another object
+    pub fn tag(
+        &self,
+        ratchet: &StackedRatchet,
+        sender_cid: u64,
+        object_id: u32,
+    ) -> Result<[u8; 32], CryptError> {
+        let mut message = Vec::with_capacity(60 + (self.chunk_hashes.len() * 32));
+        message.extend_from_slice(&sender_cid.to_be_bytes());
+        message.extend_from_slice(&object_id.to_be_bytes());
+        message.extend_from_slice(&self.total_size.to_be_bytes());
+        message.extend_from_slice(&(self.chunk_size as u64).to_be_bytes());
+        message.extend_from_slice(&self.content_hash);
+        for chunk_hash in &self.chunk_hashes {
+            message.extend_from_slice(chunk_hash);
+        }
+
+        ratchet.authenticate(MANIFEST_TAG_CONTEXT, &message)
+    }
+
+    /// Ensures that `tag` was computed by [`Self::tag`] for the same session, sender and object
+    pub fn verify_tag(
+        &self,
+        ratchet: &StackedRatchet,
+        sender_cid: u64,
+        object_id: u32,
+        tag: &[u8; 32],
+    ) -> Result<(), CryptError> {
+        let expected = self.tag(ratchet, sender_cid, object_id)?;
+        if constant_time_eq(&expected, tag) {
+            Ok(())
+        } else {
+            Err(CryptError::Decrypt(format!(
+                "Invalid tag on the transfer manifest of object {object_id}"
+            )))
+        }
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunk_hashes.len()
+    }
+
+    /// Returns true if `chunk` matches the hash declared for `index`
+    pub fn verify_chunk(&self, index: usize, chunk: &[u8]) -> bool {
+        self.chunk_hashes
+            .get(index)
+            .map(|expected| *expected == hash(chunk))
+            .unwrap_or(false)
+    }
+
+    /// Returns the number of leading chunks in `reader` that match this manifest. Useful for
+    /// determining where a partially-received object may resume from
+    pub fn verified_prefix<R: Read>(&self, mut reader: R) -> std::io::Result<usize> {
+        let mut buf = vec![0u8; self.chunk_size];
+        let mut remaining = self.total_size as usize;
+
+        for (index, expected) in self.chunk_hashes.iter().enumerate() {
+            let chunk = &mut buf[..std::cmp::min(remaining, self.chunk_size)];
+            match reader.read_exact(chunk) {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::UnexpectedEof => return Ok(index),
+                Err(err) => return Err(err),
+            }
+
+            if hash(chunk) != *expected {
+                return Ok(index);
+            }
+
+            remaining -= chunk.len();
+        }
+
+        Ok(self.chunk_count())
+    }
+}
+
+/// Incrementally verifies the chunks of an inbound object against its [`TransferManifest`]
+pub struct ManifestVerifier {
+    manifest: TransferManifest,
+    next_chunk: usize,
+    bytes_verified: u64,
+    content_hasher: Sha3_256,
+}
+
+impl ManifestVerifier {
+    pub fn new(manifest: TransferManifest) -> Self {
+        Self {
+            manifest,
+            next_chunk: 0,
+            bytes_verified: 0,
+            content_hasher: Sha3_256::default(),
+        }
+    }
+
+    /// Verifies the next chunk in sequence
+    pub fn verify_next(&mut self, chunk: &[u8]) -> Result<(), CryptError> {
+        if !self.manifest.verify_chunk(self.next_chunk, chunk) {
+            return Err(CryptError::Decrypt(format!(
+                "Chunk {} does not match the transfer manifest",
+                self.next_chunk
+            )));
+        }
+
+        self.content_hasher.update(chunk);
+        self.bytes_verified += chunk.len() as u64;
+        self.next_chunk += 1;
+        Ok(())
+    }
+
+    /// The number of chunks verified thus far
+    pub fn chunks_verified(&self) -> usize {
+        self.next_chunk
+    }
+
+    /// Ensures that every chunk was received and that the content hash matches
+    pub fn finish(self) -> Result<(), CryptError> {
+        if self.next_chunk != self.manifest.chunk_count()
+            || self.bytes_verified != self.manifest.total_size
+        {
+            return Err(CryptError::Decrypt(format!(
+                "Transfer truncated: received {} of {} bytes",
+                self.bytes_verified, self.manifest.total_size
+            )));
+        }
+
+        let content_hash: [u8; 32] = self.content_hasher.finalize().into();
+        if content_hash != self.manifest.content_hash {
+            return Err(CryptError::Decrypt(
+                "Content hash does not match the transfer manifest".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn hash(input: &[u8]) -> [u8; 32] {
+    Sha3_256::digest(input).into()
+}
@@ -2,11 +2,15 @@
 mod tests {
     use bytes::{BufMut, BytesMut};
     use citadel_crypt::argon::argon_container::{
-        ArgonSettings, ArgonStatus, AsyncArgon, ServerArgonContainer,
+        ArgonCostParameters, ArgonDefaultServerSettings, ArgonSettings, ArgonStatus, AsyncArgon,
+        ServerArgonContainer, MIN_HASH_LENGTH, MIN_MEM_COST, MIN_TIME_COST,
     };
     use citadel_crypt::endpoint_crypto_container::EndpointRatchetConstructor;
     use citadel_crypt::entropy_bank::{EntropyBank, SecurityLevel};
-    use citadel_crypt::misc::TransferType;
+    use citadel_crypt::misc::{
+        constant_time_eq, set_memory_lock_policy, should_lock_memory, MemoryLockPolicy,
+        TransferType,
+    };
     use citadel_crypt::packet_vector::PacketVector;
     use citadel_crypt::scramble::crypt_splitter::{par_scramble_encrypt_group, GroupReceiver};
     use citadel_crypt::secure_buffer::sec_bytes::SecBuffer;
@@ -121,6 +125,90 @@ mod tests {
         panic!("Failed somewhere");
     }
 
+    #[tokio::test]
+    async fn argon_verify_rejects_wrong_password() {
+        citadel_logging::setup_log();
+        let settings = ArgonSettings::new_defaults(b"user".to_vec());
+        let hashed = match AsyncArgon::hash(SecBuffer::from("password"), settings.clone())
+            .await
+            .unwrap()
+        {
+            ArgonStatus::HashSuccess(hashed) => hashed,
+            n => panic!("{n:?}"),
+        };
+
+        let container = ServerArgonContainer::new(settings, hashed);
+        assert!(matches!(
+            AsyncArgon::verify(SecBuffer::from("passwore"), container.clone())
+                .await
+                .unwrap(),
+            ArgonStatus::VerificationFailed(None)
+        ));
+        assert!(matches!(
+            AsyncArgon::verify(SecBuffer::from("password"), container)
+                .await
+                .unwrap(),
+            ArgonStatus::VerificationSuccess
+        ));
+    }
+
+    #[test]
+    fn argon_server_settings_minimums() {
+        assert!(ArgonDefaultServerSettings::default().validate().is_ok());
+        let weak = [
+            ArgonDefaultServerSettings {
+                lanes: 0,
+                ..Default::default()
+            },
+            ArgonDefaultServerSettings {
+                hash_length: MIN_HASH_LENGTH - 1,
+                ..Default::default()
+            },
+            ArgonDefaultServerSettings {
+                mem_cost: MIN_MEM_COST - 1,
+                ..Default::default()
+            },
+            ArgonDefaultServerSettings {
+                time_cost: MIN_TIME_COST - 1,
+                ..Default::default()
+            },
+        ];
+
+        for settings in weak {
+            assert!(settings.validate().is_err(), "{settings:?}");
+            // weak configurations are strengthened rather than refused
+            assert!(settings.enforce_minimums().validate().is_ok());
+        }
+
+        // registrations must be at least as costly as the policy
+        let policy = ArgonDefaultServerSettings::default().policy();
+        assert!(policy.satisfies(&policy).is_ok());
+        let weaker = ArgonCostParameters {
+            time_cost: policy.time_cost - 1,
+            ..policy
+        };
+        assert!(weaker.satisfies(&policy).is_err());
+        let fewer_lanes = ArgonCostParameters { lanes: 1, ..policy };
+        assert!(fewer_lanes.satisfies(&policy).is_ok());
+
+        // the secret is never printed
+        let with_secret = ArgonDefaultServerSettings {
+            secret: b"hunter2".to_vec(),
+            ..Default::default()
+        };
+        assert!(!format!("{with_secret:?}").contains("104"));
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"abc", b"abc"));
+        assert!(!constant_time_eq(b"abc", b"abd"));
+        assert!(!constant_time_eq(b"abc", b"abcd"));
+        assert!(constant_time_eq(b"", b""));
+        assert_eq!(SecBuffer::from("abc"), b"abc");
+        assert_ne!(SecBuffer::from("abc"), b"abd");
+    }
+
     #[test]
     fn test_sec_buffer() {
         let buf = SecBuffer::from("Hello, world!");
@@ -140,6 +228,30 @@ mod tests {
         assert_eq!(&*retrieved, b"Hello, world!");
     }
 
+    #[test]
+    fn test_memory_lock_policy() {
+        citadel_logging::setup_log();
+        // only the ultra level and above are locked
+        set_memory_lock_policy(MemoryLockPolicy::AtOrAbove(SecurityLevel::Ultra));
+        assert!(!should_lock_memory(SecurityLevel::High));
+        assert!(should_lock_memory(SecurityLevel::Ultra));
+        assert!(should_lock_memory(SecurityLevel::Custom(10)));
+
+        let (alice, _bob) =
+            gen::<StackedRatchet>(10, 0, SecurityLevel::Standard, CryptoParameters::default());
+        assert!(!alice.is_memory_locked());
+
+        // locking degrades gracefully: the ratchet is usable regardless of whether locking succeeded
+        let (alice, bob) =
+            gen::<StackedRatchet>(10, 0, SecurityLevel::Ultra, CryptoParameters::default());
+        log::trace!(target: "citadel", "Ultra ratchet locked: {}", alice.is_memory_locked());
+        let ciphertext = alice.encrypt(b"Hello, world!").unwrap();
+        assert_eq!(bob.decrypt(ciphertext).unwrap(), b"Hello, world!");
+
+        set_memory_lock_policy(MemoryLockPolicy::Disabled);
+        assert!(!should_lock_memory(SecurityLevel::Custom(u8::MAX)));
+    }
+
     #[test]
     fn test_sec_string() {
         let mut val = SecString::new();
@@ -465,6 +577,43 @@ mod tests {
         assert_eq!(toolset.len(), MAX_HYPER_RATCHETS_IN_MEMORY);
     }
 
+    #[test]
+    fn toolset_history() {
+        citadel_logging::setup_log();
+        let params = EncryptionAlgorithm::AES_GCM_256_SIV + KemAlgorithm::Kyber;
+        let security_level = SecurityLevel::Standard;
+        let (alice, _bob) = gen::<StackedRatchet>(0, 0, security_level, params);
+        let mut toolset = Toolset::new(0, alice);
+        toolset.set_max_hyper_ratchets_in_memory(0);
+        assert_eq!(toolset.max_hyper_ratchets_in_memory(), 2);
+        toolset.set_max_hyper_ratchets_in_memory(3);
+
+        for x in 1..10 {
+            match toolset
+                .update_from(gen::<StackedRatchet>(0, x, security_level, params).0)
+                .unwrap()
+            {
+                UpdateStatus::Committed { .. } => assert!(x < 3),
+                UpdateStatus::CommittedNeedsSynchronization { old_version, .. } => {
+                    toolset
+                        .deregister_oldest_hyper_ratchet(old_version)
+                        .unwrap();
+                }
+            }
+
+            assert!(toolset.len() <= 3);
+        }
+
+        assert_eq!(toolset.get_oldest_hyper_ratchet_version(), 7);
+        assert_eq!(toolset.truncate_older_than(9), 2);
+        assert_eq!(toolset.len(), 1);
+        assert_eq!(toolset.get_oldest_hyper_ratchet_version(), 9);
+        assert!(toolset.get_hyper_ratchet(9).is_some());
+        // the most recent version is always retained
+        assert_eq!(toolset.truncate_older_than(10), 0);
+        assert_eq!(toolset.len(), 1);
+    }
+
     fn gen<R: Ratchet>(
         cid: u64,
         version: u32,
@@ -702,6 +851,7 @@ mod tests {
                 .unwrap();
 
             let config = scramble_transmitter.get_receiver_config();
+            assert!(config.validate().is_ok());
             let mut receiver = GroupReceiver::new(config.clone(), 0, 0);
             log::trace!(target: "citadel", "{:?}", &config);
 
@@ -728,6 +878,49 @@ mod tests {
         }
     }
 
+    #[test]
+    fn group_receiver_config_rejects_malformed() {
+        citadel_logging::setup_log();
+        const HEADER_SIZE_BYTES: usize = 44;
+        let params = CryptoParameters::default();
+        let (ratchet, _) = gen::<StackedRatchet>(10, 0, SecurityLevel::Standard, params);
+        let (pseudo_static_aux_ratchet, _) =
+            gen::<StackedRatchet>(10, 0, SecurityLevel::Standard, params);
+        let transmitter = par_scramble_encrypt_group::<_, _, _, HEADER_SIZE_BYTES>(
+            &[0u8; 1000],
+            SecurityLevel::Standard,
+            &ratchet,
+            &pseudo_static_aux_ratchet,
+            HEADER_SIZE_BYTES,
+            0,
+            0,
+            0,
+            TransferType::FileTransfer,
+            |_vec, _drill, _target_cid, _, buffer| buffer.put_bytes(0, HEADER_SIZE_BYTES),
+        )
+        .unwrap();
+
+        let config = transmitter.get_receiver_config();
+        assert!(config.validate().is_ok());
+
+        let mut oversized = config.clone();
+        oversized.wave_count = usize::MAX;
+        assert!(oversized.validate().is_err());
+
+        let mut overflowing = config.clone();
+        overflowing.max_payload_size = usize::MAX / 2;
+        overflowing.last_payload_size = 1;
+        assert!(overflowing.validate().is_err());
+
+        let mut empty_waves = config.clone();
+        empty_waves.wave_count = 0;
+        assert!(empty_waves.validate().is_err());
+
+        let mut inconsistent = config;
+        inconsistent.last_payload_size = inconsistent.max_payload_size + 1;
+        assert!(inconsistent.validate().is_err());
+    }
+
     const HEADER_LEN: usize = 52;
     fn header_inscribe(_: &PacketVector, _: &EntropyBank, _: u32, _: u64, packet: &mut BytesMut) {
         for x in 0..HEADER_LEN {
@@ -858,6 +1051,7 @@ mod tests {
             0,
             transfer_type,
             header_inscribe,
+            None,
         )
         .unwrap();
 
@@ -1028,4 +1222,205 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn transfer_manifest() {
+        use citadel_crypt::transfer_manifest::{ManifestVerifier, TransferManifest};
+        citadel_logging::setup_log();
+        let data = (0..1000u32).map(|r| r as u8).collect::<Vec<u8>>();
+        let manifest = TransferManifest::from_reader(data.as_slice(), data.len(), 300).unwrap();
+        assert_eq!(manifest.chunk_count(), 4);
+
+        let mut verifier = ManifestVerifier::new(manifest.clone());
+        for chunk in data.chunks(300) {
+            verifier.verify_next(chunk).unwrap();
+        }
+        verifier.finish().unwrap();
+
+        // out-of-order or tampered chunks are rejected
+        let mut verifier = ManifestVerifier::new(manifest.clone());
+        assert!(verifier.verify_next(&data[300..600]).is_err());
+        let mut tampered = data.clone();
+        tampered[700] ^= 1;
+        assert_eq!(manifest.verified_prefix(tampered.as_slice()).unwrap(), 2);
+        assert_eq!(manifest.verified_prefix(&data[..650]).unwrap(), 2);
+
+        // truncation is detected
+        let mut verifier = ManifestVerifier::new(manifest);
+        verifier.verify_next(&data[..300]).unwrap();
+        assert!(verifier.finish().is_err());
+    }
+
+    #[test]
+    fn transfer_manifest_tag() {
+        use citadel_crypt::transfer_manifest::TransferManifest;
+        citadel_logging::setup_log();
+        let params = CryptoParameters::default();
+        let (alice, bob) = gen::<StackedRatchet>(10, 0, SecurityLevel::Standard, params);
+        let (other_session, _) = gen::<StackedRatchet>(10, 0, SecurityLevel::Standard, params);
+
+        let data = (0..1000u32).map(|r| r as u8).collect::<Vec<u8>>();
+        let manifest = TransferManifest::from_reader(data.as_slice(), data.len(), 300).unwrap();
+        let tag = manifest.tag(&alice, 10, 1).unwrap();
+        manifest.verify_tag(&bob, 10, 1, &tag).unwrap();
+
+        // a forged manifest, e.g., declaring the hashes of other content, is rejected
+        let forged_data = vec![0u8; 1000];
+        let forged = TransferManifest::from_reader(forged_data.as_slice(), 1000, 300).unwrap();
+        assert!(forged.verify_tag(&bob, 10, 1, &tag).is_err());
+        let mut forged = manifest.clone();
+        forged.chunk_hashes[1][0] ^= 1;
+        assert!(forged.verify_tag(&bob, 10, 1, &tag).is_err());
+
+        // as is a manifest replayed for another object or sender, or tagged by another session
+        assert!(manifest.verify_tag(&bob, 10, 2, &tag).is_err());
+        assert!(manifest.verify_tag(&bob, 11, 1, &tag).is_err());
+        let foreign_tag = manifest.tag(&other_session, 10, 1).unwrap();
+        assert!(manifest.verify_tag(&bob, 10, 1, &foreign_tag).is_err());
+    }
+
+    #[test]
+    fn delta_sync() {
+        use citadel_crypt::delta_sync::{apply_delta, FileDelta, FileSignature};
+        use std::io::{Cursor, Read};
+        citadel_logging::setup_log();
+        let apply = |delta: &FileDelta, target: &[u8], basis: &[u8]| {
+            let mut encoded = Vec::new();
+            let _ = delta
+                .clone()
+                .into_encoder(Cursor::new(target))
+                .read_to_end(&mut encoded)
+                .unwrap();
+            let mut output = Vec::new();
+            apply_delta(encoded.as_slice(), Cursor::new(basis), &mut output).map(|_| output)
+        };
+        let mut seed = 7u32;
+        let basis = (0..10_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect::<Vec<u8>>();
+        let signature = FileSignature::generate(&basis, 512).unwrap();
+        assert_eq!(signature.blocks.len(), 19);
+
+        // an unchanged object is sent as copies, save for the trailing partial block
+        let delta = FileDelta::compute(&signature, &basis);
+        assert_eq!(delta.literal_len(), 10_000 % 512);
+        assert_eq!(apply(&delta, &basis, &basis).unwrap(), basis);
+
+        // insertions shift the remaining blocks, which must still be matched
+        let mut target = basis.clone();
+        target.splice(3000..3000, b"inserted bytes".iter().copied());
+        target[8000] ^= 1;
+        let delta = FileDelta::compute(&signature, &target);
+        assert!(delta.literal_len() <= 2 * 512 + 10_000 % 512 + 14);
+        assert_eq!(apply(&delta, &target, &basis).unwrap(), target);
+
+        // the delta may only be applied to the basis it was computed against
+        let mut other_basis = basis.clone();
+        other_basis[0] ^= 1;
+        assert!(apply(&delta, &target, &other_basis).is_err());
+
+        // without any basis blocks, everything is literal
+        let empty = FileSignature::generate(&[], 512).unwrap();
+        let delta = FileDelta::compute(&empty, &target);
+        assert_eq!(delta.literal_len(), target.len() as u64);
+        assert_eq!(apply(&delta, &target, &[]).unwrap(), target);
+    }
+
+    fn pseudo_random_bytes(len: usize, mut seed: u32) -> Vec<u8> {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+                (seed >> 16) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn compression() {
+        use citadel_crypt::compression::{
+            CompressionAlgorithm, GroupCompressor, GroupDecompressor,
+        };
+        citadel_logging::setup_log();
+        let mut compressor = GroupCompressor::new(CompressionAlgorithm::Zstd);
+        let mut decompressor = GroupDecompressor::new();
+
+        let compressible = b"citadel protocol ".repeat(10_000);
+        let first = compressor.compress(&compressible).unwrap();
+        assert!(first.len() < compressible.len() / 10);
+        // the stream spans groups, so a repeated group compresses against the one before it
+        let second = compressor.compress(&compressible).unwrap();
+        assert!(second.len() < first.len());
+
+        // incompressible content bypasses compression, costing only the marker byte, and restarts
+        // the stream
+        let random = pseudo_random_bytes(100_000, 11);
+        let raw = compressor.compress(&random).unwrap();
+        assert_eq!(raw.len(), random.len() + 1);
+        let third = compressor.compress(&compressible).unwrap();
+
+        assert_eq!(decompressor.decompress(&first).unwrap(), compressible);
+        assert_eq!(decompressor.decompress(&second).unwrap(), compressible);
+        assert_eq!(decompressor.decompress(&raw).unwrap(), random);
+        assert_eq!(decompressor.decompress(&third).unwrap(), compressible);
+
+        assert_eq!(
+            decompressor
+                .decompress(&compressor.compress(&[]).unwrap())
+                .unwrap(),
+            Vec::<u8>::new()
+        );
+        assert!(decompressor.decompress(&[]).is_err());
+        assert!(decompressor.decompress(&[2, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn compression_max_group_size() {
+        use citadel_crypt::compression::{
+            CompressionAlgorithm, GroupCompressor, GroupDecompressor, MAX_COMPRESSIBLE_GROUP_LEN,
+        };
+        use citadel_crypt::streaming_crypt_scrambler::MAX_BYTES_PER_GROUP;
+        citadel_logging::setup_log();
+        const HEADER_SIZE_BYTES: usize = 44;
+        let mut compressor = GroupCompressor::new(CompressionAlgorithm::Zstd);
+
+        // a raw group of the largest compressible size fills, but does not exceed, a group
+        let random = pseudo_random_bytes(MAX_COMPRESSIBLE_GROUP_LEN, 13);
+        let framed = compressor.compress(&random).unwrap();
+        assert_eq!(framed.len(), MAX_BYTES_PER_GROUP);
+        assert_eq!(
+            GroupDecompressor::new().decompress(&framed).unwrap(),
+            random
+        );
+        assert!(compressor
+            .compress(&vec![0u8; MAX_BYTES_PER_GROUP])
+            .is_err());
+
+        let params = CryptoParameters::default();
+        let (ratchet, _) = gen::<StackedRatchet>(10, 0, SecurityLevel::Standard, params);
+        let (pseudo_static_aux_ratchet, _) =
+            gen::<StackedRatchet>(10, 0, SecurityLevel::Standard, params);
+        let transmitter = par_scramble_encrypt_group::<_, _, _, HEADER_SIZE_BYTES>(
+            &framed,
+            SecurityLevel::Standard,
+            &ratchet,
+            &pseudo_static_aux_ratchet,
+            HEADER_SIZE_BYTES,
+            0,
+            0,
+            0,
+            TransferType::FileTransfer,
+            |_vec, _drill, _target_cid, _, buffer| buffer.put_bytes(0, HEADER_SIZE_BYTES),
+        )
+        .unwrap();
+        assert!(transmitter.get_receiver_config().validate().is_ok());
+
+        // decompression may not expand a group beyond the maximum group size
+        let bomb = zstd::bulk::compress(&vec![0u8; MAX_BYTES_PER_GROUP + 1], 3).unwrap();
+        let mut framed_bomb = vec![1u8];
+        framed_bomb.extend_from_slice(&bomb);
+        assert!(GroupDecompressor::new().decompress(&framed_bomb).is_err());
+    }
 }
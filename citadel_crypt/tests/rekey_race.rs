@@ -0,0 +1,384 @@
+#[cfg(test)]
+mod tests {
+    use bytes::{BufMut, BytesMut};
+    use citadel_crypt::endpoint_crypto_container::{
+        DriftTolerance, KemTransferStatus, PeerSessionCrypto,
+    };
+    use citadel_crypt::entropy_bank::SecurityLevel;
+    use citadel_crypt::stacked_ratchet::constructor::{
+        BobToAliceTransferType, StackedRatchetConstructor,
+    };
+    use citadel_crypt::stacked_ratchet::{Ratchet, StackedRatchet};
+    use citadel_crypt::toolset::Toolset;
+    use citadel_pqcrypto::algorithm_dictionary::CryptoParameters;
+    use citadel_pqcrypto::constructor_opts::ConstructorOpts;
+    use proptest::prelude::*;
+    use std::collections::VecDeque;
+
+    const CID: u64 = 10;
+    const HEADER_LEN: usize = 8;
+    const SECURITY_LEVEL: SecurityLevel = SecurityLevel::Standard;
+
+    /// A single step of the model. Alice always initiates the re-key, and each stage of the
+    /// re-key is a separate step, such that packets may be sent and delivered between any two
+    /// stages, exactly as they may be in the protocol
+    #[derive(Copy, Clone, Debug)]
+    enum Op {
+        Send { from_alice: bool },
+        Deliver { to_alice: bool },
+        RekeyStage0,
+        RekeyStage1,
+        RekeyTruncate,
+        RekeyTruncateAck,
+    }
+
+    fn op() -> impl Strategy<Value = Op> {
+        prop_oneof![
+            3 => any::<bool>().prop_map(|from_alice| Op::Send { from_alice }),
+            3 => any::<bool>().prop_map(|to_alice| Op::Deliver { to_alice }),
+            1 => Just(Op::RekeyStage0),
+            1 => Just(Op::RekeyStage1),
+            1 => Just(Op::RekeyTruncate),
+            1 => Just(Op::RekeyTruncateAck),
+        ]
+    }
+
+    enum Rekey {
+        Idle,
+        AwaitingStage1 {
+            constructor: StackedRatchetConstructor,
+            transfer: BobToAliceTransferType,
+            truncate: Option<u32>,
+        },
+        AwaitingTruncate {
+            truncate: Option<u32>,
+        },
+        AwaitingTruncateAck,
+    }
+
+    struct InFlight {
+        version: u32,
+        packet: BytesMut,
+        plaintext: Vec<u8>,
+    }
+
+    struct Model {
+        alice: PeerSessionCrypto,
+        bob: PeerSessionCrypto,
+        to_alice: VecDeque<InFlight>,
+        to_bob: VecDeque<InFlight>,
+        rekey: Rekey,
+        completed_rekeys: u32,
+        sent: u64,
+    }
+
+    impl Model {
+        fn new(drift_tolerance: DriftTolerance) -> Self {
+            let count = SECURITY_LEVEL.value() as usize + 1;
+            let mut alice = StackedRatchetConstructor::new_alice(
+                ConstructorOpts::new_vec_init(Some(CryptoParameters::default()), count),
+                CID,
+                0,
+                Some(SECURITY_LEVEL),
+            )
+            .unwrap();
+            let bob = StackedRatchetConstructor::new_bob(
+                CID,
+                0,
+                ConstructorOpts::new_vec_init(Some(CryptoParameters::default()), count),
+                alice.stage0_alice().unwrap(),
+            )
+            .unwrap();
+            alice
+                .stage1_alice(BobToAliceTransferType::Default(bob.stage0_bob().unwrap()))
+                .unwrap();
+
+            let crypt = |ratchet: StackedRatchet, local_is_initiator| {
+                let mut crypt =
+                    PeerSessionCrypto::new(Toolset::new(CID, ratchet), local_is_initiator);
+                crypt.set_drift_tolerance(drift_tolerance);
+                crypt
+            };
+
+            Self {
+                alice: crypt(alice.finish().unwrap(), true),
+                bob: crypt(bob.finish().unwrap(), false),
+                to_alice: VecDeque::new(),
+                to_bob: VecDeque::new(),
+                rekey: Rekey::Idle,
+                completed_rekeys: 0,
+                sent: 0,
+            }
+        }
+
+        fn apply(&mut self, op: Op) {
+            match op {
+                Op::Send { from_alice } => self.send(from_alice),
+                Op::Deliver { to_alice } => {
+                    let _ = self.deliver(to_alice);
+                }
+                Op::RekeyStage0 => self.rekey_stage0(),
+                Op::RekeyStage1 => self.rekey_stage1(),
+                Op::RekeyTruncate => self.rekey_truncate(),
+                Op::RekeyTruncateAck => self.rekey_truncate_ack(),
+            }
+        }
+
+        fn send(&mut self, from_alice: bool) {
+            let (sender, queue) = if from_alice {
+                (&self.alice, &mut self.to_bob)
+            } else {
+                (&self.bob, &mut self.to_alice)
+            };
+
+            let (version, ratchet) = sender.pin_hyper_ratchet().unwrap();
+            assert_eq!(version, ratchet.version());
+
+            let plaintext = self.sent.to_be_bytes().to_vec();
+            self.sent += 1;
+            let mut packet = BytesMut::with_capacity(HEADER_LEN + plaintext.len());
+            packet.put_bytes(0, HEADER_LEN);
+            packet.put_slice(&plaintext);
+            ratchet
+                .protect_message_packet(Some(SECURITY_LEVEL), HEADER_LEN, &mut packet)
+                .unwrap();
+
+            queue.push_back(InFlight {
+                version,
+                packet,
+                plaintext,
+            });
+        }
+
+        /// Delivers the oldest packet in flight. Returns false if the receiver rejected it
+        fn deliver(&mut self, to_alice: bool) -> bool {
+            let (receiver, queue) = if to_alice {
+                (&self.alice, &mut self.to_alice)
+            } else {
+                (&self.bob, &mut self.to_bob)
+            };
+
+            let InFlight {
+                version,
+                mut packet,
+                plaintext,
+            } = match queue.pop_front() {
+                Some(in_flight) => in_flight,
+                None => return true,
+            };
+
+            let latest = receiver.latest_usable_version;
+            let lead = version.wrapping_sub(latest);
+            let lag = latest.wrapping_sub(version);
+            // only a single re-key may be in progress, so no packet may lead by more than one version
+            if lead <= lag {
+                assert!(lead <= 1);
+            }
+
+            match receiver.get_hyper_ratchet_within_drift(version) {
+                Ok(ratchet) => {
+                    let mut payload = packet.split_off(HEADER_LEN);
+                    ratchet
+                        .validate_message_packet(Some(SECURITY_LEVEL), &packet[..], &mut payload)
+                        .unwrap();
+                    assert_eq!(&payload[..], &plaintext[..]);
+                    true
+                }
+
+                Err(_) => {
+                    // a rejected packet must either exceed the tolerance, or have been truncated
+                    let tolerance = receiver.drift_tolerance;
+                    let exceeds = if lead <= lag {
+                        lead > tolerance.max_lead
+                    } else {
+                        lag > tolerance.max_lag
+                    };
+                    assert!(exceeds || receiver.toolset.get_hyper_ratchet(version).is_none());
+                    false
+                }
+            }
+        }
+
+        fn rekey_stage0(&mut self) {
+            if !matches!(self.rekey, Rekey::Idle) {
+                return;
+            }
+
+            let constructor = self.alice.get_next_constructor(false).unwrap();
+            let transfer = constructor.stage0_alice().unwrap();
+            let opts = self
+                .bob
+                .get_hyper_ratchet(None)
+                .unwrap()
+                .get_next_constructor_opts();
+            let bob_constructor = StackedRatchetConstructor::new_bob(
+                CID,
+                transfer.get_declared_new_version(),
+                opts,
+                transfer,
+            )
+            .unwrap();
+
+            let status = self
+                .bob
+                .update_sync_safe(bob_constructor, false, CID)
+                .unwrap();
+            let truncate = status.requires_truncation();
+            let transfer = match status {
+                KemTransferStatus::Some(transfer, _) => transfer,
+                _ => panic!("Bob should always accept a re-key initiated by Alice"),
+            };
+
+            self.rekey = Rekey::AwaitingStage1 {
+                constructor,
+                transfer,
+                truncate,
+            };
+        }
+
+        fn rekey_stage1(&mut self) {
+            let (mut constructor, transfer, truncate) =
+                match std::mem::replace(&mut self.rekey, Rekey::Idle) {
+                    Rekey::AwaitingStage1 {
+                        constructor,
+                        transfer,
+                        truncate,
+                    } => (constructor, transfer, truncate),
+                    other => {
+                        self.rekey = other;
+                        return;
+                    }
+                };
+
+            constructor.stage1_alice(transfer).unwrap();
+            let _ = self.alice.update_sync_safe(constructor, true, CID).unwrap();
+            if let Some(version) = truncate {
+                self.alice.deregister_oldest_hyper_ratchet(version).unwrap();
+            }
+
+            self.alice.post_alice_stage1_or_post_stage1_bob();
+            if truncate.is_none() {
+                let _ = self.alice.maybe_unlock(true).unwrap();
+            }
+
+            self.rekey = Rekey::AwaitingTruncate { truncate };
+        }
+
+        fn rekey_truncate(&mut self) {
+            let truncate = match self.rekey {
+                Rekey::AwaitingTruncate { truncate } => truncate,
+                _ => return,
+            };
+
+            if let Some(version) = truncate {
+                self.bob.deregister_oldest_hyper_ratchet(version).unwrap();
+            }
+
+            self.bob.post_alice_stage1_or_post_stage1_bob();
+            let _ = self.bob.maybe_unlock(false).unwrap();
+
+            if truncate.is_some() {
+                self.rekey = Rekey::AwaitingTruncateAck;
+            } else {
+                self.on_rekey_complete();
+            }
+        }
+
+        fn rekey_truncate_ack(&mut self) {
+            if !matches!(self.rekey, Rekey::AwaitingTruncateAck) {
+                return;
+            }
+
+            let _ = self.alice.maybe_unlock(true).unwrap();
+            self.on_rekey_complete();
+        }
+
+        fn on_rekey_complete(&mut self) {
+            self.rekey = Rekey::Idle;
+            self.completed_rekeys += 1;
+            // once the re-key completes, both endpoints must pin every new packet to the new version
+            assert_eq!(self.alice.latest_usable_version, self.completed_rekeys);
+            assert_eq!(self.bob.latest_usable_version, self.completed_rekeys);
+        }
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(32))]
+
+        #[test]
+        fn rekey_race_never_decrypts_with_wrong_version(ops in proptest::collection::vec(op(), 1..64)) {
+            citadel_logging::setup_log();
+            let mut model = Model::new(DriftTolerance::default());
+            for op in ops {
+                model.apply(op);
+            }
+
+            // drain whatever is still in flight
+            while !model.to_alice.is_empty() || !model.to_bob.is_empty() {
+                model.apply(Op::Deliver { to_alice: true });
+                model.apply(Op::Deliver { to_alice: false });
+            }
+        }
+    }
+
+    #[test]
+    fn packets_sent_across_rekey_use_pinned_version() {
+        citadel_logging::setup_log();
+        let mut model = Model::new(DriftTolerance::default());
+        model.send(false);
+        model.rekey_stage0();
+        model.rekey_stage1();
+        // alice now pins the new version, while bob still pins the old one
+        model.send(true);
+        assert_eq!(model.to_bob.back().unwrap().version, 1);
+        assert_eq!(model.to_alice.back().unwrap().version, 0);
+        // bob receives a version one ahead of his latest usable version, alice one behind
+        assert!(model.deliver(false));
+        assert!(model.deliver(true));
+        model.rekey_truncate();
+        model.rekey_truncate_ack();
+        assert!(matches!(model.rekey, Rekey::Idle));
+    }
+
+    #[test]
+    fn drift_beyond_tolerance_is_rejected() {
+        citadel_logging::setup_log();
+        let mut model = Model::new(DriftTolerance {
+            max_lag: 0,
+            max_lead: 0,
+        });
+        // a packet pinned to v0 is in flight to alice while the re-key completes
+        model.send(false);
+        model.rekey_stage0();
+        model.rekey_stage1();
+        // alice leads bob by one version, exceeding the tolerance
+        model.send(true);
+        assert!(!model.deliver(false));
+        // and bob's stale packet trails alice by one version
+        assert!(!model.deliver(true));
+    }
+
+    #[tokio::test]
+    async fn precomputed_constructor_follows_latest_version() {
+        citadel_logging::setup_log();
+        let mut model = Model::new(DriftTolerance::default());
+        model.alice.set_precompute_constructor(true);
+
+        for _ in 0..3 {
+            while !model.alice.has_precomputed_constructor() {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+            }
+
+            // the precomputed constructor is consumed by the re-key, and must produce the next version
+            model.rekey_stage0();
+            assert!(!model.alice.has_precomputed_constructor());
+            model.rekey_stage1();
+            model.rekey_truncate();
+            model.rekey_truncate_ack();
+            model.send(true);
+            assert!(model.deliver(false));
+        }
+
+        assert_eq!(model.completed_rekeys, 3);
+    }
+}
@@ -0,0 +1,132 @@
+use crate::config::LoadConfig;
+use crate::stats::{Operation, Stats};
+use citadel_sdk::prefabs::client::single_connection::SingleClientServerConnectionKernel;
+use citadel_sdk::prefabs::ClientServerRemote;
+use citadel_sdk::prelude::*;
+use futures::StreamExt;
+use rand::{Rng, RngCore};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::time::MissedTickBehavior;
+
+/// The length of the sequence number prefixed to each message, used to match echoes
+const SEQUENCE_LEN: usize = 8;
+
+/// Registers and connects a single virtual client, then runs the workload until the configured
+/// duration elapses
+pub async fn run_client(idx: usize, config: Arc<LoadConfig>, stats: Arc<Stats>) {
+    tokio::time::sleep(config.start_delay(idx)).await;
+
+    let connected = Arc::new(AtomicBool::new(false));
+    let start = Instant::now();
+    let username = format!("loadgen.{}", uuid::Uuid::new_v4());
+    let kernel = SingleClientServerConnectionKernel::new_register(
+        format!("Loadgen Client {idx}"),
+        username,
+        "loadgen-password",
+        config.server_addr,
+        UdpMode::Disabled,
+        Default::default(),
+        {
+            let config = config.clone();
+            let stats = stats.clone();
+            let connected = connected.clone();
+            move |connection, remote| async move {
+                connected.store(true, Ordering::SeqCst);
+                stats.record::<String>(Operation::Connect, Ok(start.elapsed()));
+                run_workload(connection, remote, &config, &stats).await
+            }
+        },
+    );
+
+    let kernel = match kernel {
+        Ok(kernel) => kernel,
+        Err(err) => {
+            stats.record(Operation::Connect, Err(format!("{err:?}")));
+            return;
+        }
+    };
+
+    let result = match NodeBuilder::default()
+        .with_node_type(NodeType::Peer)
+        .with_backend(BackendType::InMemory)
+        .build(kernel)
+    {
+        Ok(node) => node.await.map(|_| ()),
+        Err(err) => Err(NetworkError::Generic(err.to_string())),
+    };
+
+    if let Err(err) = result {
+        if connected.load(Ordering::SeqCst) {
+            log::warn!(target: "citadel", "[Loadgen] Client {idx} ended with an error: {err:?}");
+        } else {
+            stats.record(Operation::Connect, Err(format!("{err:?}")));
+        }
+    }
+}
+
+async fn run_workload(
+    connection: ConnectionSuccess,
+    mut remote: ClientServerRemote,
+    config: &LoadConfig,
+    stats: &Stats,
+) -> Result<(), NetworkError> {
+    let (tx, mut rx) = connection.channel.split();
+    let deadline = Instant::now() + config.duration;
+    let mut interval = tokio::time::interval(config.interval());
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let mut sequence = 0u64;
+
+    while Instant::now() < deadline {
+        let _ = interval.tick().await;
+        let is_file_transfer = rand::thread_rng().gen_bool(config.file_ratio);
+        let start = Instant::now();
+
+        if is_file_transfer {
+            let file = random_bytes(config.file_size);
+            let result =
+                tokio::time::timeout(config.op_timeout, remote.send_file(BytesSource::from(file)))
+                    .await;
+            let result = match result {
+                Ok(Ok(())) => Ok(start.elapsed()),
+                Ok(Err(err)) => Err(format!("{err:?}")),
+                Err(_) => Err("Timed out".to_string()),
+            };
+
+            stats.record(Operation::FileTransfer, result);
+        } else {
+            let mut message = random_bytes(config.message_size.max(SEQUENCE_LEN));
+            message[..SEQUENCE_LEN].copy_from_slice(&sequence.to_be_bytes());
+
+            let round_trip = async {
+                tx.send_message(message.into()).await?;
+                // echoes of messages that previously timed out are skipped
+                while let Some(echo) = rx.next().await {
+                    if echo.as_ref().get(..SEQUENCE_LEN) == Some(&sequence.to_be_bytes()[..]) {
+                        return Ok(());
+                    }
+                }
+
+                Err(NetworkError::msg("The channel closed"))
+            };
+
+            let result = match tokio::time::timeout(config.op_timeout, round_trip).await {
+                Ok(Ok(())) => Ok(start.elapsed()),
+                Ok(Err(err)) => Err(format!("{err:?}")),
+                Err(_) => Err("Timed out".to_string()),
+            };
+
+            stats.record(Operation::Message, result);
+            sequence = sequence.wrapping_add(1);
+        }
+    }
+
+    remote.shutdown_kernel().await
+}
+
+fn random_bytes(len: usize) -> Vec<u8> {
+    let mut bytes = vec![0u8; len];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
@@ -0,0 +1,204 @@
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::time::Duration;
+
+pub const USAGE: &str = "\
+Usage:
+    citadel-loadgen server [--bind <addr>]
+    citadel-loadgen client --server <addr> [options]
+    citadel-loadgen soak [--bind <addr>] [--cycles <n>] [--settle <secs>] [options]
+
+Client options:
+    --clients <n>          Number of virtual clients (default: 10)
+    --rate <n>             Operations per second, per client (default: 1)
+    --duration <secs>      How long each client runs its workload once connected (default: 30)
+    --ramp-up <secs>       Spreads the start of the clients across this window (default: 0)
+    --message-size <n>     Bytes per message (default: 1024)
+    --file-ratio <f>       Fraction of operations that are file transfers, from 0 to 1 (default: 0)
+    --file-size <n>        Bytes per file transfer (default: 65536)
+    --op-timeout <secs>    Operations taking longer than this count as errors (default: 30)
+
+Soak options:
+    --cycles <n>           Number of times the clients connect, run and disconnect (default: 10)
+    --settle <secs>        Time given to the server to clean up after each cycle (default: 5)
+
+The messages are echoed by the server, and their latency is measured as a round trip. Run the
+server with `citadel-loadgen server` to provide the echo and to accept the file transfers.
+
+The soak mode runs the server in-process, and compares its resource counters after each cycle
+against those sampled before the first. Resources that remain held after the clients disconnect,
+and that grow across cycles, are reported as leaks";
+
+/// The mode the tool runs in
+pub enum Mode {
+    /// Runs a server that echoes messages and accepts file transfers
+    Server { bind_addr: SocketAddr },
+    /// Runs the virtual clients against a server
+    Client(LoadConfig),
+    /// Repeatedly runs the virtual clients against an in-process server, checking for leaks
+    Soak(SoakConfig),
+}
+
+/// The workload of the virtual clients
+#[derive(Clone, Debug)]
+pub struct LoadConfig {
+    pub server_addr: SocketAddr,
+    pub clients: usize,
+    pub rate: f64,
+    pub duration: Duration,
+    pub ramp_up: Duration,
+    pub message_size: usize,
+    pub file_ratio: f64,
+    pub file_size: usize,
+    pub op_timeout: Duration,
+}
+
+/// The cycles of a soak test. The clients of each cycle connect to `load.server_addr`, which the
+/// in-process server binds to
+#[derive(Clone, Debug)]
+pub struct SoakConfig {
+    pub load: LoadConfig,
+    pub cycles: usize,
+    pub settle: Duration,
+}
+
+impl LoadConfig {
+    /// The time between each operation of a single client
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs_f64(1f64 / self.rate)
+    }
+
+    /// The delay before client `idx` starts, such that the starts are spread across the ramp-up
+    pub fn start_delay(&self, idx: usize) -> Duration {
+        self.ramp_up.mul_f64(idx as f64 / self.clients as f64)
+    }
+}
+
+impl Mode {
+    /// Parses the mode from the arguments, excluding the program name
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut args = args.into_iter();
+        let mode = args.next().ok_or("Expected a mode")?;
+        let mut options = Vec::new();
+        while let Some(key) = args.next() {
+            let key = key
+                .strip_prefix("--")
+                .ok_or_else(|| format!("Unexpected argument {key}"))?
+                .to_string();
+            let value = args
+                .next()
+                .ok_or_else(|| format!("Expected a value for --{key}"))?;
+            options.push((key, value));
+        }
+
+        let get = |key: &str| {
+            options
+                .iter()
+                .rev()
+                .find(|(option, _)| option == key)
+                .map(|(_, value)| value.as_str())
+        };
+
+        const LOAD_OPTIONS: &[&str] = &[
+            "clients",
+            "rate",
+            "duration",
+            "ramp-up",
+            "message-size",
+            "file-ratio",
+            "file-size",
+            "op-timeout",
+        ];
+
+        for (key, _) in &options {
+            let key = key.as_str();
+            let known = match mode.as_str() {
+                "server" => key == "bind",
+                "soak" => {
+                    ["bind", "cycles", "settle"].contains(&key) || LOAD_OPTIONS.contains(&key)
+                }
+                _ => key == "server" || LOAD_OPTIONS.contains(&key),
+            };
+
+            if !known {
+                return Err(format!("Unknown option --{key}"));
+            }
+        }
+
+        match mode.as_str() {
+            "server" => Ok(Mode::Server {
+                bind_addr: parse_addr(get("bind").unwrap_or("0.0.0.0:25021"))?,
+            }),
+
+            "client" => Ok(Mode::Client(parse_load_config(
+                parse_addr(get("server").ok_or("Expected --server")?)?,
+                get,
+            )?)),
+
+            "soak" => {
+                let config = SoakConfig {
+                    load: parse_load_config(
+                        parse_addr(get("bind").unwrap_or("127.0.0.1:25022"))?,
+                        get,
+                    )?,
+                    cycles: parse(get("cycles"), "cycles", 10)?,
+                    settle: Duration::from_secs_f64(parse(get("settle"), "settle", 5f64)?),
+                };
+
+                if config.cycles == 0 {
+                    return Err("--cycles must be at least 1".to_string());
+                }
+
+                Ok(Mode::Soak(config))
+            }
+
+            other => Err(format!("Unknown mode {other}")),
+        }
+    }
+}
+
+fn parse_load_config<'a>(
+    server_addr: SocketAddr,
+    get: impl Fn(&str) -> Option<&'a str>,
+) -> Result<LoadConfig, String> {
+    let config = LoadConfig {
+        server_addr,
+        clients: parse(get("clients"), "clients", 10)?,
+        rate: parse(get("rate"), "rate", 1f64)?,
+        duration: Duration::from_secs_f64(parse(get("duration"), "duration", 30f64)?),
+        ramp_up: Duration::from_secs_f64(parse(get("ramp-up"), "ramp-up", 0f64)?),
+        message_size: parse(get("message-size"), "message-size", 1024)?,
+        file_ratio: parse(get("file-ratio"), "file-ratio", 0f64)?,
+        file_size: parse(get("file-size"), "file-size", 65536)?,
+        op_timeout: Duration::from_secs_f64(parse(get("op-timeout"), "op-timeout", 30f64)?),
+    };
+
+    if config.clients == 0 {
+        return Err("--clients must be at least 1".to_string());
+    }
+
+    if !(config.rate > 0f64 && config.rate.is_finite()) {
+        return Err("--rate must be positive".to_string());
+    }
+
+    if !(0f64..=1f64).contains(&config.file_ratio) {
+        return Err("--file-ratio must be between 0 and 1".to_string());
+    }
+
+    Ok(config)
+}
+
+fn parse<T: std::str::FromStr>(value: Option<&str>, key: &str, default: T) -> Result<T, String> {
+    match value {
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("Invalid value for --{key}: {value}")),
+        None => Ok(default),
+    }
+}
+
+fn parse_addr(addr: &str) -> Result<SocketAddr, String> {
+    addr.to_socket_addrs()
+        .map_err(|err| format!("Invalid address {addr}: {err}"))?
+        .next()
+        .ok_or_else(|| format!("Address {addr} did not resolve"))
+}
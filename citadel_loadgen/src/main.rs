@@ -0,0 +1,90 @@
+//! Spins up simulated clients against a Citadel server and reports the latency percentiles and
+//! error rates of each operation, allowing operators to size their deployments. The soak mode
+//! additionally checks the server for resources that leak across repeated runs. See [`USAGE`]
+mod client;
+mod config;
+mod server;
+mod soak;
+mod stats;
+
+use crate::config::{Mode, USAGE};
+use crate::server::EchoServerKernel;
+use crate::stats::Stats;
+use citadel_sdk::prelude::*;
+use std::sync::Arc;
+use std::time::Instant;
+
+#[tokio::main]
+async fn main() {
+    citadel_logging::setup_log();
+
+    let mode = match Mode::parse(std::env::args().skip(1)) {
+        Ok(mode) => mode,
+        Err(err) => {
+            eprintln!("{err}\n\n{USAGE}");
+            std::process::exit(2);
+        }
+    };
+
+    match mode {
+        Mode::Server { bind_addr } => {
+            let result = match NodeBuilder::default()
+                .with_node_type(NodeType::Server(bind_addr))
+                .with_backend(BackendType::InMemory)
+                .build(EchoServerKernel::default())
+            {
+                Ok(node) => node.await.map(|_| ()),
+                Err(err) => Err(NetworkError::Generic(err.to_string())),
+            };
+
+            if let Err(err) = result {
+                eprintln!("Server failed: {err:?}");
+                std::process::exit(1);
+            }
+        }
+
+        Mode::Client(config) => {
+            println!(
+                "Running {} clients against {} for {:.2}s ...",
+                config.clients,
+                config.server_addr,
+                config.duration.as_secs_f64()
+            );
+
+            let config = Arc::new(config);
+            let stats = Arc::new(Stats::default());
+            let start = Instant::now();
+            // the node futures are not Send, so the clients are polled concurrently on this task
+            let _ = futures::future::join_all(
+                (0..config.clients)
+                    .map(|idx| client::run_client(idx, config.clone(), stats.clone())),
+            )
+            .await;
+
+            print!("{}", stats.report(start.elapsed()));
+        }
+
+        Mode::Soak(config) => {
+            println!(
+                "Soaking {} cycles of {} clients for {:.2}s each ...",
+                config.cycles,
+                config.load.clients,
+                config.load.duration.as_secs_f64()
+            );
+
+            match soak::run_soak(config).await {
+                Ok(leaks) if leaks.is_empty() => println!("No leaks detected"),
+                Ok(leaks) => {
+                    for leak in leaks {
+                        eprintln!("Leak detected: {leak}");
+                    }
+                    std::process::exit(1);
+                }
+                Err(err) => {
+                    eprintln!("Soak test failed: {err:?}");
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
+}
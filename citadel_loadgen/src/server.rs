@@ -0,0 +1,73 @@
+use citadel_sdk::prelude::*;
+use futures::StreamExt;
+
+/// A server kernel that echoes each message back through the channel it arrived on, and accepts
+/// every file transfer
+#[derive(Default)]
+pub struct EchoServerKernel {
+    remote_tx: Option<tokio::sync::oneshot::Sender<NodeRemote>>,
+}
+
+impl EchoServerKernel {
+    /// Creates a kernel that hands its [`NodeRemote`] to the returned receiver once loaded
+    pub fn with_remote_receiver() -> (Self, tokio::sync::oneshot::Receiver<NodeRemote>) {
+        let (remote_tx, remote_rx) = tokio::sync::oneshot::channel();
+        (
+            Self {
+                remote_tx: Some(remote_tx),
+            },
+            remote_rx,
+        )
+    }
+}
+
+#[async_trait]
+impl NetKernel for EchoServerKernel {
+    fn load_remote(&mut self, node_remote: NodeRemote) -> Result<(), NetworkError> {
+        if let Some(remote_tx) = self.remote_tx.take() {
+            let _ = remote_tx.send(node_remote);
+        }
+
+        Ok(())
+    }
+
+    async fn on_start(&self) -> Result<(), NetworkError> {
+        log::info!(target: "citadel", "[Loadgen] Echo server started");
+        Ok(())
+    }
+
+    async fn on_node_event_received(&self, message: NodeResult) -> Result<(), NetworkError> {
+        match message {
+            NodeResult::ConnectSuccess(ConnectSuccess {
+                implicated_cid,
+                channel,
+                ..
+            }) => {
+                drop(tokio::task::spawn(async move {
+                    let (tx, mut rx) = channel.split();
+                    while let Some(message) = rx.next().await {
+                        if let Err(err) = tx.send_message(message.into()).await {
+                            log::warn!(target: "citadel", "[Loadgen] Unable to echo to {implicated_cid}: {err:?}");
+                            break;
+                        }
+                    }
+                }));
+            }
+
+            NodeResult::ObjectTransferHandle(mut handle) => {
+                handle
+                    .handle
+                    .accept()
+                    .map_err(|err| NetworkError::Generic(err.into_string()))?;
+            }
+
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    async fn on_stop(&mut self) -> Result<(), NetworkError> {
+        Ok(())
+    }
+}
@@ -0,0 +1,198 @@
+use crate::client;
+use crate::config::SoakConfig;
+use crate::server::EchoServerKernel;
+use crate::stats::Stats;
+use citadel_sdk::prelude::*;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Runs the soak test against an in-process server, returning the leaks detected
+pub async fn run_soak(config: SoakConfig) -> Result<Vec<Leak>, NetworkError> {
+    let (kernel, remote_rx) = EchoServerKernel::with_remote_receiver();
+    let server = NodeBuilder::default()
+        .with_node_type(NodeType::Server(config.load.server_addr))
+        .with_backend(BackendType::InMemory)
+        .build(kernel)
+        .map_err(|err| NetworkError::Generic(err.to_string()))?;
+
+    // the node futures are not Send, so the server and the clients are polled on this task
+    tokio::select! {
+        result = server => {
+            result?;
+            Err(NetworkError::msg("The server stopped before the soak test completed"))
+        }
+
+        result = run_cycles(config, remote_rx) => result,
+    }
+}
+
+async fn run_cycles(
+    config: SoakConfig,
+    remote_rx: tokio::sync::oneshot::Receiver<NodeRemote>,
+) -> Result<Vec<Leak>, NetworkError> {
+    let mut remote = remote_rx
+        .await
+        .map_err(|_| NetworkError::msg("The server did not load its remote"))?;
+    let baseline = query_counters(&mut remote).await?;
+    let load = Arc::new(config.load);
+    let stats = Arc::new(Stats::default());
+    let start = Instant::now();
+    let mut residuals = Vec::with_capacity(config.cycles);
+
+    println!("{:<6} {}", "cycle", Residual::HEADER);
+    for cycle in 1..=config.cycles {
+        let _ = futures::future::join_all(
+            (0..load.clients).map(|idx| client::run_client(idx, load.clone(), stats.clone())),
+        )
+        .await;
+
+        tokio::time::sleep(config.settle).await;
+        let residual = Residual::between(&baseline, &query_counters(&mut remote).await?);
+        println!("{cycle:<6} {residual}");
+        residuals.push(residual);
+    }
+
+    print!("{}", stats.report(start.elapsed()));
+    Ok(Leak::detect(&residuals))
+}
+
+async fn query_counters(remote: &mut NodeRemote) -> Result<ResourceCounters, NetworkError> {
+    match remote
+        .send_callback(NodeRequest::GetResourceCounters)
+        .await?
+    {
+        NodeResult::ResourceCounters(result) => Ok(result.counters),
+        other => Err(NetworkError::Generic(format!(
+            "Unexpected response to the resource counter query: {other:?}"
+        ))),
+    }
+}
+
+/// The resources held by the server after a cycle, beyond those held before the first cycle
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Residual {
+    pub live_sessions: u64,
+    pub untracked_sessions: u64,
+    pub tracked_tickets: u64,
+    pub state_container_entries: u64,
+    pub toolset_versions_retained: u64,
+}
+
+impl Residual {
+    const HEADER: &'static str =
+        "live sessions  untracked sessions  tracked tickets  state entries  toolset versions";
+
+    fn between(baseline: &ResourceCounters, counters: &ResourceCounters) -> Self {
+        Self {
+            live_sessions: counters
+                .live_sessions()
+                .saturating_sub(baseline.live_sessions()),
+            untracked_sessions: counters
+                .untracked_sessions()
+                .saturating_sub(baseline.untracked_sessions()),
+            tracked_tickets: counters
+                .tracked_tickets
+                .saturating_sub(baseline.tracked_tickets),
+            state_container_entries: counters
+                .state_container_entries
+                .saturating_sub(baseline.state_container_entries),
+            toolset_versions_retained: counters
+                .toolset_versions_retained
+                .saturating_sub(baseline.toolset_versions_retained),
+        }
+    }
+
+    fn fields(&self) -> [(&'static str, u64); 5] {
+        [
+            ("live sessions", self.live_sessions),
+            ("untracked sessions", self.untracked_sessions),
+            ("tracked tickets", self.tracked_tickets),
+            ("state container entries", self.state_container_entries),
+            ("toolset versions", self.toolset_versions_retained),
+        ]
+    }
+}
+
+impl Display for Residual {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>13}  {:>18}  {:>15}  {:>13}  {:>16}",
+            self.live_sessions,
+            self.untracked_sessions,
+            self.tracked_tickets,
+            self.state_container_entries,
+            self.toolset_versions_retained
+        )
+    }
+}
+
+/// A resource still held after the final cycle, which grew since the first cycle
+#[derive(Debug, Eq, PartialEq)]
+pub struct Leak {
+    pub resource: &'static str,
+    pub first: u64,
+    pub last: u64,
+}
+
+impl Leak {
+    /// Resources that are cleaned up late show a constant residual, whereas leaked resources
+    /// accumulate with each cycle
+    pub fn detect(residuals: &[Residual]) -> Vec<Leak> {
+        let (first, last) = match (residuals.first(), residuals.last()) {
+            (Some(first), Some(last)) => (first, last),
+            _ => return Vec::new(),
+        };
+
+        first
+            .fields()
+            .into_iter()
+            .zip(last.fields())
+            .filter(|((_, first), (_, last))| last > first)
+            .map(|((resource, first), (_, last))| Leak {
+                resource,
+                first,
+                last,
+            })
+            .collect()
+    }
+}
+
+impl Display for Leak {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} grew from {} after the first cycle to {} after the last",
+            self.resource, self.first, self.last
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::soak::{Leak, Residual};
+
+    #[test]
+    fn test_leak_detection() {
+        let stable = Residual {
+            tracked_tickets: 1,
+            ..Default::default()
+        };
+        assert!(Leak::detect(&[stable, stable, stable]).is_empty());
+        assert!(Leak::detect(&[]).is_empty());
+
+        let growing = Residual {
+            state_container_entries: 4,
+            ..stable
+        };
+        assert_eq!(
+            Leak::detect(&[stable, growing]),
+            vec![Leak {
+                resource: "state container entries",
+                first: 0,
+                last: 4,
+            }]
+        );
+    }
+}
@@ -0,0 +1,193 @@
+use citadel_io::Mutex;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+/// The operations measured by the load generator
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Operation {
+    /// Registration followed by a connection to the server
+    Connect,
+    /// A message, echoed back by the server
+    Message,
+    /// A file transfer to the server
+    FileTransfer,
+}
+
+impl Operation {
+    const ALL: [Operation; 3] = [
+        Operation::Connect,
+        Operation::Message,
+        Operation::FileTransfer,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Operation::Connect => "register+connect",
+            Operation::Message => "message rtt",
+            Operation::FileTransfer => "file transfer",
+        }
+    }
+
+    fn idx(&self) -> usize {
+        *self as usize
+    }
+}
+
+#[derive(Default)]
+struct Metric {
+    samples: Vec<Duration>,
+    errors: usize,
+}
+
+/// Collects the latency and errors of each [`Operation`] across every client
+#[derive(Default)]
+pub struct Stats {
+    metrics: Mutex<[Metric; 3]>,
+}
+
+impl Stats {
+    /// Records the outcome of an operation
+    pub fn record<E: Display>(&self, operation: Operation, result: Result<Duration, E>) {
+        let mut metrics = self.metrics.lock();
+        let metric = &mut metrics[operation.idx()];
+        match result {
+            Ok(latency) => metric.samples.push(latency),
+            Err(err) => {
+                log::warn!(target: "citadel", "[Loadgen] {} failed: {err}", operation.name());
+                metric.errors += 1;
+            }
+        }
+    }
+
+    /// Summarizes the operations recorded over `elapsed`
+    pub fn report(&self, elapsed: Duration) -> Report {
+        let mut metrics = self.metrics.lock();
+        let rows = Operation::ALL
+            .iter()
+            .map(|operation| {
+                let metric = &mut metrics[operation.idx()];
+                metric.samples.sort_unstable();
+                ReportRow {
+                    operation: *operation,
+                    ok: metric.samples.len(),
+                    errors: metric.errors,
+                    p50: percentile(&metric.samples, 50f64),
+                    p90: percentile(&metric.samples, 90f64),
+                    p99: percentile(&metric.samples, 99f64),
+                    max: metric.samples.last().copied(),
+                }
+            })
+            .collect();
+
+        Report { rows, elapsed }
+    }
+}
+
+/// Returns the nearest-rank percentile of the sorted `samples`
+pub fn percentile(samples: &[Duration], percentile: f64) -> Option<Duration> {
+    if samples.is_empty() {
+        return None;
+    }
+
+    let rank = (percentile / 100f64 * samples.len() as f64).ceil() as usize;
+    samples.get(rank.clamp(1, samples.len()) - 1).copied()
+}
+
+pub struct ReportRow {
+    pub operation: Operation,
+    pub ok: usize,
+    pub errors: usize,
+    pub p50: Option<Duration>,
+    pub p90: Option<Duration>,
+    pub p99: Option<Duration>,
+    pub max: Option<Duration>,
+}
+
+impl ReportRow {
+    /// The fraction of operations that failed
+    pub fn error_rate(&self) -> f64 {
+        let total = self.ok + self.errors;
+        if total == 0 {
+            0f64
+        } else {
+            self.errors as f64 / total as f64
+        }
+    }
+}
+
+pub struct Report {
+    pub rows: Vec<ReportRow>,
+    pub elapsed: Duration,
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let fmt_latency = |latency: Option<Duration>| {
+            latency
+                .map(|latency| format!("{:.2}ms", latency.as_secs_f64() * 1000f64))
+                .unwrap_or_else(|| "-".to_string())
+        };
+
+        writeln!(f, "Elapsed: {:.2}s", self.elapsed.as_secs_f64())?;
+        writeln!(
+            f,
+            "{:<18} {:>8} {:>8} {:>8} {:>10} {:>10} {:>10} {:>10} {:>10}",
+            "operation", "ok", "errors", "err %", "ops/s", "p50", "p90", "p99", "max"
+        )?;
+
+        for row in &self.rows {
+            writeln!(
+                f,
+                "{:<18} {:>8} {:>8} {:>8.2} {:>10.2} {:>10} {:>10} {:>10} {:>10}",
+                row.operation.name(),
+                row.ok,
+                row.errors,
+                row.error_rate() * 100f64,
+                row.ok as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON),
+                fmt_latency(row.p50),
+                fmt_latency(row.p90),
+                fmt_latency(row.p99),
+                fmt_latency(row.max),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stats::{percentile, Operation, Stats};
+    use std::time::Duration;
+
+    #[test]
+    fn test_percentile() {
+        let samples = (1..=100).map(Duration::from_millis).collect::<Vec<_>>();
+        assert_eq!(percentile(&samples, 50f64), Some(Duration::from_millis(50)));
+        assert_eq!(percentile(&samples, 99f64), Some(Duration::from_millis(99)));
+        assert_eq!(
+            percentile(&samples, 100f64),
+            Some(Duration::from_millis(100))
+        );
+        assert_eq!(percentile(&samples, 0f64), Some(Duration::from_millis(1)));
+        assert_eq!(percentile(&[], 50f64), None);
+    }
+
+    #[test]
+    fn test_report() {
+        let stats = Stats::default();
+        for latency in [3, 1, 2] {
+            stats.record::<String>(Operation::Message, Ok(Duration::from_millis(latency)));
+        }
+        stats.record(Operation::Message, Err("timeout"));
+
+        let report = stats.report(Duration::from_secs(1));
+        let row = &report.rows[Operation::Message as usize];
+        assert_eq!(row.ok, 3);
+        assert_eq!(row.errors, 1);
+        assert_eq!(row.error_rate(), 0.25);
+        assert_eq!(row.p50, Some(Duration::from_millis(2)));
+        assert_eq!(row.max, Some(Duration::from_millis(3)));
+        assert_eq!(report.rows[Operation::Connect as usize].p50, None);
+    }
+}
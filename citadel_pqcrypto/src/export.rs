@@ -4,6 +4,7 @@ use crate::encryption::ascon_impl::AsconModule;
 use crate::encryption::chacha_impl::ChaChaModule;
 use crate::encryption::kyber_module::KyberModule;
 use crate::encryption::AeadModule;
+use crate::key_domain::KeyDomain;
 use crate::{CryptoParameters, KeyStore, PQNode, PostQuantumMetaKex, PostQuantumMetaSig};
 use aes_gcm::KeyInit;
 use generic_array::GenericArray;
@@ -57,7 +58,10 @@ pub(crate) mod custom_serde {
 
 impl From<KeyStoreIntermediate> for KeyStore {
     fn from(int: KeyStoreIntermediate) -> Self {
-        let (alice_symmetric_key, bob_symmetric_key) = keys_to_aead_store(
+        let (
+            (alice_symmetric_key, bob_symmetric_key),
+            (unordered_alice_symmetric_key, unordered_bob_symmetric_key),
+        ) = keys_to_domain_aead_stores(
             &int.alice_key,
             &int.bob_key,
             &int.kex,
@@ -69,6 +73,8 @@ impl From<KeyStoreIntermediate> for KeyStore {
         KeyStore {
             alice_module: alice_symmetric_key,
             bob_module: bob_symmetric_key,
+            unordered_alice_module: unordered_alice_symmetric_key,
+            unordered_bob_module: unordered_bob_symmetric_key,
             alice_key: int.alice_key,
             bob_key: int.bob_key,
             kex: int.kex,
@@ -81,6 +87,33 @@ impl From<KeyStoreIntermediate> for KeyStore {
 
 pub type AeadStore = (Option<Box<dyn AeadModule>>, Option<Box<dyn AeadModule>>);
 
+/// Creates the [`AeadStore`] of the [`KeyDomain::Primary`] and [`KeyDomain::Unordered`] domains,
+/// respectively, each keyed by the subkeys of `alice` and `bob` derived for the domain
+pub(crate) fn keys_to_domain_aead_stores(
+    alice: &GenericArray<u8, generic_array::typenum::U32>,
+    bob: &GenericArray<u8, generic_array::typenum::U32>,
+    kex: &PostQuantumMetaKex,
+    params: CryptoParameters,
+    sig: Option<&PostQuantumMetaSig>,
+    pq_node: PQNode,
+) -> (AeadStore, AeadStore) {
+    let domain_store = |domain: KeyDomain| {
+        keys_to_aead_store(
+            &domain.derive_subkey(alice),
+            &domain.derive_subkey(bob),
+            kex,
+            params,
+            sig,
+            pq_node,
+        )
+    };
+
+    (
+        domain_store(KeyDomain::Primary),
+        domain_store(KeyDomain::Unordered),
+    )
+}
+
 pub(crate) fn keys_to_aead_store(
     alice: &GenericArray<u8, generic_array::typenum::U32>,
     bob: &GenericArray<u8, generic_array::typenum::U32>,
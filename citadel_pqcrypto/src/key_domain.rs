@@ -0,0 +1,42 @@
+use generic_array::GenericArray;
+use serde::{Deserialize, Serialize};
+use sha3::Digest;
+
+pub type SymmetricKey = GenericArray<u8, generic_array::typenum::U32>;
+
+/// The domains of symmetric key material derived from each key exchange. Each domain has its own
+/// subkeys and anti-replay window, such that a compromised key, or a misused nonce, on one
+/// transport cannot affect another
+///
+/// The subkey of a domain is derived as `SHA3-256(len(label) || label || key)`, where `key` is
+/// the Alice or Bob key produced by the recursive keystore, and `len(label)` is a single byte
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum KeyDomain {
+    /// The ordered, reliable primary stream
+    Primary,
+    /// The unordered, unreliable stream (i.e., UDP)
+    Unordered,
+}
+
+impl KeyDomain {
+    /// Every domain
+    pub const ALL: [KeyDomain; 2] = [KeyDomain::Primary, KeyDomain::Unordered];
+
+    /// The label used to derive the subkeys of this domain
+    pub const fn label(&self) -> &'static [u8] {
+        match self {
+            KeyDomain::Primary => b"citadel/v1/primary-stream",
+            KeyDomain::Unordered => b"citadel/v1/unordered-stream",
+        }
+    }
+
+    /// Derives the subkey of this domain from `key`
+    pub fn derive_subkey(&self, key: &SymmetricKey) -> SymmetricKey {
+        let label = self.label();
+        let mut hasher = sha3::Sha3_256::default();
+        hasher.update([label.len() as u8]);
+        hasher.update(label);
+        hasher.update(key.as_slice());
+        hasher.finalize()
+    }
+}
@@ -7,8 +7,9 @@ use crate::algorithm_dictionary::{
 use crate::bytes_in_place::{EzBuffer, InPlaceBuffer};
 use crate::constructor_opts::{ConstructorOpts, RecursiveChain};
 use crate::encryption::AeadModule;
-use crate::export::keys_to_aead_store;
+use crate::export::keys_to_domain_aead_stores;
 use crate::ez_error::Error;
+use crate::key_domain::KeyDomain;
 use crate::wire::{AliceToBobTransferParameters, BobToAliceTransferParameters};
 use generic_array::GenericArray;
 use rand::rngs::ThreadRng;
@@ -26,6 +27,7 @@ use crate::functions::AsSlice;
 pub use crate::replay_attack_container::AntiReplayAttackContainer;
 
 pub mod prelude {
+    pub use crate::key_domain::KeyDomain;
     pub use crate::{algorithm_dictionary, PQNode, PostQuantumContainer, PostQuantumMeta};
 }
 
@@ -52,8 +54,14 @@ pub mod encryption;
 
 pub mod constructor_opts;
 
+/// For separating the key material of each transport
+pub mod key_domain;
+
 pub mod wire;
 
+/// A lightweight hybrid Noise handshake for constrained peers
+pub mod noise;
+
 /// For debug purposes
 #[cfg(not(feature = "unordered"))]
 pub const fn build_tag() -> &'static str {
@@ -205,6 +213,8 @@ pub struct PostQuantumContainer {
     // the first pqc won't have a chain
     pub(crate) chain: Option<RecursiveChain>,
     pub(crate) anti_replay_attack: AntiReplayAttackContainer,
+    #[serde(default)]
+    pub(crate) unordered_anti_replay_attack: AntiReplayAttackContainer,
     pub(crate) key_store: Option<KeyStore>,
     pub(crate) node: PQNode,
 }
@@ -225,6 +235,8 @@ pub enum PQNode {
 pub(crate) struct KeyStore {
     alice_module: Option<Box<dyn AeadModule>>,
     bob_module: Option<Box<dyn AeadModule>>,
+    unordered_alice_module: Option<Box<dyn AeadModule>>,
+    unordered_bob_module: Option<Box<dyn AeadModule>>,
     alice_key: GenericArray<u8, generic_array::typenum::U32>,
     bob_key: GenericArray<u8, generic_array::typenum::U32>,
     kex: PostQuantumMetaKex,
@@ -253,6 +265,7 @@ impl PostQuantumContainer {
             chain: previous_symmetric_key,
             key_store,
             anti_replay_attack: AntiReplayAttackContainer::default(),
+            unordered_anti_replay_attack: AntiReplayAttackContainer::default(),
             node: PQNode::Alice,
         })
     }
@@ -298,6 +311,7 @@ impl PostQuantumContainer {
             key_store: keys,
             data,
             anti_replay_attack: AntiReplayAttackContainer::default(),
+            unordered_anti_replay_attack: AntiReplayAttackContainer::default(),
             node: PQNode::Bob,
         })
     }
@@ -410,14 +424,18 @@ impl PostQuantumContainer {
             (chain, alice_key, bob_key)
         };
 
-        let (alice_symmetric_key, bob_symmetric_key) =
-            keys_to_aead_store(&alice_key, &bob_key, &kex, params, sig.as_ref(), pq_node);
+        let (
+            (alice_symmetric_key, bob_symmetric_key),
+            (unordered_alice_symmetric_key, unordered_bob_symmetric_key),
+        ) = keys_to_domain_aead_stores(&alice_key, &bob_key, &kex, params, sig.as_ref(), pq_node);
 
         Ok((
             chain,
             KeyStore {
                 alice_module: alice_symmetric_key,
                 bob_module: bob_symmetric_key,
+                unordered_alice_module: unordered_alice_symmetric_key,
+                unordered_bob_module: unordered_bob_symmetric_key,
                 alice_key,
                 bob_key,
                 sig,
@@ -429,29 +447,88 @@ impl PostQuantumContainer {
     }
 
     fn get_encryption_key(&self) -> Option<&dyn AeadModule> {
+        self.get_domain_encryption_key(KeyDomain::Primary)
+    }
+
+    fn get_decryption_key(&self) -> Option<&dyn AeadModule> {
+        self.get_domain_decryption_key(KeyDomain::Primary)
+    }
+
+    fn get_domain_modules(
+        &self,
+        domain: KeyDomain,
+    ) -> Option<(&Option<Box<dyn AeadModule>>, &Option<Box<dyn AeadModule>>)> {
+        let key_store = self.key_store.as_ref()?;
+        match domain {
+            KeyDomain::Primary => Some((&key_store.alice_module, &key_store.bob_module)),
+            KeyDomain::Unordered => Some((
+                &key_store.unordered_alice_module,
+                &key_store.unordered_bob_module,
+            )),
+        }
+    }
+
+    fn get_domain_encryption_key(&self, domain: KeyDomain) -> Option<&dyn AeadModule> {
+        let (alice_module, bob_module) = self.get_domain_modules(domain)?;
         match self.node {
-            PQNode::Alice => Some(self.key_store.as_ref()?.alice_module.as_deref()?),
-            PQNode::Bob => Some(self.key_store.as_ref()?.bob_module.as_deref()?),
+            PQNode::Alice => Some(alice_module.as_deref()?),
+            PQNode::Bob => Some(bob_module.as_deref()?),
         }
     }
 
-    fn get_decryption_key(&self) -> Option<&dyn AeadModule> {
+    fn get_domain_decryption_key(&self, domain: KeyDomain) -> Option<&dyn AeadModule> {
         if let EncryptionAlgorithm::Kyber = self.params.encryption_algorithm {
             // use multi-modal asymmetric + symmetric ratcheted encryption
             // alice's key is in alice, bob's key is in bob. Thus, use encryption key
-            self.get_encryption_key()
+            self.get_domain_encryption_key(domain)
         } else {
             // use symmetric encryption only (NOT post quantum, only quantum-resistant, but faster)
+            let (alice_module, bob_module) = self.get_domain_modules(domain)?;
             match self.node {
-                PQNode::Alice => Some(self.key_store.as_ref()?.bob_module.as_deref()?),
-                PQNode::Bob => Some(self.key_store.as_ref()?.alice_module.as_deref()?),
+                PQNode::Alice => Some(bob_module.as_deref()?),
+                PQNode::Bob => Some(alice_module.as_deref()?),
             }
         }
     }
 
+    fn get_domain_anti_replay_attack(&self, domain: KeyDomain) -> &AntiReplayAttackContainer {
+        match domain {
+            KeyDomain::Primary => &self.anti_replay_attack,
+            KeyDomain::Unordered => &self.unordered_anti_replay_attack,
+        }
+    }
+
+    /// Returns a fingerprint of the subkeys of `domain`, or None if the keys are not yet loaded.
+    /// Both endpoints compute the same fingerprint, and the fingerprints of distinct domains differ,
+    /// allowing the separation of domains to be audited without revealing any key material
+    pub fn key_domain_fingerprint(&self, domain: KeyDomain) -> Option<[u8; 32]> {
+        let key_store = self.key_store.as_ref()?;
+        let mut hasher = sha3::Sha3_256::new();
+        hasher.update(b"citadel/v1/key-domain-fingerprint");
+        hasher.update(domain.derive_subkey(&key_store.alice_key));
+        hasher.update(domain.derive_subkey(&key_store.bob_key));
+        Some(hasher.finalize().into())
+    }
+
+    /// Computes a keyed hash of `message` under the symmetric keys of this container, or returns
+    /// None if the keys are not yet loaded. Both endpoints compute the same tag. `context`
+    /// separates the tags of distinct uses, and must be at most 255 bytes
+    pub fn mac(&self, context: &[u8], message: &[u8]) -> Option<[u8; 32]> {
+        let key_store = self.key_store.as_ref()?;
+        let mut hasher = sha3::Sha3_256::new();
+        hasher.update(b"citadel/v1/mac");
+        hasher.update(key_store.alice_key);
+        hasher.update(key_store.bob_key);
+        hasher.update([context.len() as u8]);
+        hasher.update(context);
+        hasher.update(message);
+        Some(hasher.finalize().into())
+    }
+
     /// Resets the counters to zero, as well as reset any additional stateful resources
     pub fn reset_counters(&self) {
         self.anti_replay_attack.reset();
+        self.unordered_anti_replay_attack.reset();
     }
 
     /// This should always be called after deserialization
@@ -486,6 +563,7 @@ impl PostQuantumContainer {
     /// if resetting the state is necessary)
     pub fn has_verified_packets(&self) -> bool {
         self.anti_replay_attack.has_tracked_packets()
+            || self.unordered_anti_replay_attack.has_tracked_packets()
     }
 
     /// Returns the previous symmetric chain key. If this is the first in the series, then returns the shared
@@ -597,18 +675,29 @@ impl PostQuantumContainer {
         header_len: usize,
         full_packet: &mut T,
         nonce: R,
+    ) -> Result<(), Error> {
+        self.protect_packet_in_place_in_domain(KeyDomain::Primary, header_len, full_packet, nonce)
+    }
+
+    /// Protects the packet using the subkey and anti-replay window of `domain`
+    pub fn protect_packet_in_place_in_domain<T: EzBuffer, R: AsRef<[u8]>>(
+        &self,
+        domain: KeyDomain,
+        header_len: usize,
+        full_packet: &mut T,
+        nonce: R,
     ) -> Result<(), Error> {
         let nonce = nonce.as_ref();
         let mut payload = full_packet.split_off(header_len);
         let header = full_packet;
 
         // next, push the ARA-generated PID
-        payload.put_u64(self.anti_replay_attack.get_next_pid());
+        payload.put_u64(self.get_domain_anti_replay_attack(domain).get_next_pid());
         let payload_len = payload.len();
 
         let mut in_place_payload = InPlaceBuffer::new(&mut payload, 0..payload_len)
             .ok_or(Error::Generic("Bad window range"))?;
-        if let Some(symmetric_key) = self.get_encryption_key() {
+        if let Some(symmetric_key) = self.get_domain_encryption_key(domain) {
             symmetric_key
                 .encrypt_in_place(nonce, header.subset(0..header_len), &mut in_place_payload)
                 .map_err(|_| Error::EncryptionFailure)?;
@@ -625,6 +714,17 @@ impl PostQuantumContainer {
         header: H,
         payload: &mut T,
         nonce: R,
+    ) -> Result<(), Error> {
+        self.validate_packet_in_place_in_domain(KeyDomain::Primary, header, payload, nonce)
+    }
+
+    /// Validates the packet using the subkey and anti-replay window of `domain`
+    pub fn validate_packet_in_place_in_domain<T: EzBuffer, H: AsRef<[u8]>, R: AsRef<[u8]>>(
+        &self,
+        domain: KeyDomain,
+        header: H,
+        payload: &mut T,
+        nonce: R,
     ) -> Result<(), Error> {
         let nonce = nonce.as_ref();
         let header = header.as_ref();
@@ -632,7 +732,7 @@ impl PostQuantumContainer {
 
         let mut in_place_payload = InPlaceBuffer::new(payload, 0..payload_len)
             .ok_or(Error::Generic("Bad window range"))?;
-        if let Some(symmetric_key) = self.get_decryption_key() {
+        if let Some(symmetric_key) = self.get_domain_decryption_key(domain) {
             symmetric_key
                 .decrypt_in_place(nonce, header, &mut in_place_payload)
                 .and_then(|_| {
@@ -643,7 +743,7 @@ impl PostQuantumContainer {
                         let mut array: [u8; 8] = Default::default();
                         array.copy_from_slice(payload.subset(start_idx..end_idx));
                         if self
-                            .anti_replay_attack
+                            .get_domain_anti_replay_attack(domain)
                             .on_pid_received(u64::from_be_bytes(array))
                         {
                             // remove the PID from the payload
@@ -722,6 +822,10 @@ pub mod algorithm_dictionary {
 
     pub const KEM_ALGORITHM_COUNT: u8 = KemAlgorithm::COUNT as u8;
 
+    /// True if this build was compiled with the `fips` feature, restricting every choice of
+    /// algorithm to the approved subset (see [`CryptoParameters::is_fips_approved`])
+    pub const FIPS_MODE: bool = cfg!(feature = "fips");
+
     #[derive(PackedStruct, Default, Serialize, Deserialize, Copy, Clone, Debug)]
     #[packed_struct(bit_numbering = "msb0")]
     pub struct CryptoParameters {
@@ -733,6 +837,17 @@ pub mod algorithm_dictionary {
         pub sig_algorithm: SigAlgorithm,
     }
 
+    impl CryptoParameters {
+        /// Returns true if every algorithm is in the approved subset. AES-GCM is approved for
+        /// symmetric encryption, and Kyber (standardized as ML-KEM) for key encapsulation. Since
+        /// Falcon has not yet been standardized, no signature scheme may be used
+        pub fn is_fips_approved(&self) -> bool {
+            matches!(self.encryption_algorithm, EncryptionAlgorithm::AES_GCM_256)
+                && matches!(self.kem_algorithm, KemAlgorithm::Kyber)
+                && matches!(self.sig_algorithm, SigAlgorithm::None)
+        }
+    }
+
     impl From<CryptoParameters> for u8 {
         fn from(val: CryptoParameters) -> Self {
             let bytes: [u8; 1] = val.pack().unwrap();
@@ -1340,5 +1455,11 @@ pub fn validate_crypto_params(params: &CryptoParameters) -> Result<(), Error> {
 
     // NOTE: it's okay to have a sig scheme defined with no Kyber. That just means every packet gets non-repudiation endowed onto its security
 
+    if algorithm_dictionary::FIPS_MODE && !params.is_fips_approved() {
+        return Err(Error::Generic(
+            "Invalid crypto parameters. Only AES-GCM-256 with the Kyber KEM (and no signature scheme) is permitted in FIPS mode",
+        ));
+    }
+
     Ok(())
 }
@@ -0,0 +1,531 @@
+//! A lightweight handshake for constrained peers
+//!
+//! Embedded peers may be unable to afford the stacked ratchet construction of the standard
+//! handshake. This module provides a [Noise](https://noiseprotocol.org/noise.html) XX handshake
+//! extended with the hybrid forward secrecy (`hfs`) modifier, mixing a Kyber key encapsulation into
+//! the X25519 key agreement:
+//!
+//! ```text
+//! -> e, e1
+//! <- e, ee, ekem1, s, es
+//! -> s, se
+//! ```
+//!
+//! Both endpoints learn the static key of the other, and the resulting transport keys remain
+//! secret unless both X25519 and Kyber are broken. Authentication relies on the X25519 static
+//! keys alone, hence the application must check [`NoiseHandshake::remote_static`] against the
+//! key it expects
+//!
+//! Fields of variable length, namely the Kyber public key and ciphertext, are prefixed with their
+//! length as a big-endian u16
+use crate::ez_error::Error;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key};
+use hkdf::Hkdf;
+use rand::rngs::ThreadRng;
+use rand::RngCore;
+use sha3::{Digest, Sha3_256};
+use x25519_dalek::{PublicKey, StaticSecret};
+use zeroize::Zeroizing;
+
+pub const PROTOCOL_NAME: &[u8] = b"Noise_XXhfs_25519+Kyber_ChaChaPoly_SHA3/256";
+
+const DH_LEN: usize = 32;
+const HASH_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+
+/// The long-term X25519 identity of an endpoint
+pub struct StaticKeypair {
+    secret: StaticSecret,
+    public: PublicKey,
+}
+
+impl StaticKeypair {
+    /// Generates a new keypair
+    pub fn generate() -> Self {
+        Self::from_secret(random_secret())
+    }
+
+    /// Restores a keypair from its secret
+    pub fn from_secret(secret: [u8; DH_LEN]) -> Self {
+        let secret = StaticSecret::from(secret);
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+
+    pub fn public_key(&self) -> [u8; DH_LEN] {
+        self.public.to_bytes()
+    }
+}
+
+struct CipherState {
+    key: Option<Zeroizing<[u8; 32]>>,
+    nonce: u64,
+}
+
+impl CipherState {
+    fn new(key: Option<[u8; 32]>) -> Self {
+        Self {
+            key: key.map(Zeroizing::new),
+            nonce: 0,
+        }
+    }
+
+    fn next_nonce(&mut self) -> Result<[u8; 12], Error> {
+        if self.nonce == u64::MAX {
+            return Err(Error::Generic("Noise nonce exhausted"));
+        }
+
+        let mut nonce = [0u8; 12];
+        nonce[4..].copy_from_slice(&self.nonce.to_le_bytes());
+        self.nonce += 1;
+        Ok(nonce)
+    }
+
+    fn encrypt(&mut self, ad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        let key = match self.key.as_ref() {
+            Some(key) => ChaCha20Poly1305::new(Key::from_slice(&key[..])),
+            None => return Ok(plaintext.to_vec()),
+        };
+
+        let nonce = self.next_nonce()?;
+        key.encrypt(
+            &nonce.into(),
+            Payload {
+                msg: plaintext,
+                aad: ad,
+            },
+        )
+        .map_err(|_| Error::EncryptionFailure)
+    }
+
+    fn decrypt(&mut self, ad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        let key = match self.key.as_ref() {
+            Some(key) => ChaCha20Poly1305::new(Key::from_slice(&key[..])),
+            None => return Ok(ciphertext.to_vec()),
+        };
+
+        // the nonce only advances once the ciphertext authenticates
+        let nonce = self.nonce;
+        let plaintext = key
+            .decrypt(
+                &self.next_nonce()?.into(),
+                Payload {
+                    msg: ciphertext,
+                    aad: ad,
+                },
+            )
+            .map_err(|_| Error::DecryptionFailure);
+        if plaintext.is_err() {
+            self.nonce = nonce;
+        }
+        plaintext
+    }
+}
+
+struct SymmetricState {
+    chaining_key: Zeroizing<[u8; HASH_LEN]>,
+    hash: [u8; HASH_LEN],
+    cipher: CipherState,
+}
+
+impl SymmetricState {
+    fn new(prologue: &[u8]) -> Self {
+        let hash: [u8; HASH_LEN] = Sha3_256::digest(PROTOCOL_NAME).into();
+        let mut this = Self {
+            chaining_key: Zeroizing::new(hash),
+            hash,
+            cipher: CipherState::new(None),
+        };
+        this.mix_hash(prologue);
+        this
+    }
+
+    fn mix_hash(&mut self, data: &[u8]) {
+        let mut hasher = Sha3_256::default();
+        hasher.update(self.hash);
+        hasher.update(data);
+        self.hash = hasher.finalize().into();
+    }
+
+    fn hkdf(&self, input_key_material: &[u8]) -> Result<([u8; 32], [u8; 32]), Error> {
+        let mut output = Zeroizing::new([0u8; 64]);
+        Hkdf::<Sha3_256>::new(Some(&self.chaining_key[..]), input_key_material)
+            .expand(&[], &mut output[..])
+            .map_err(|_| Error::Generic("Invalid HKDF output length"))?;
+        let mut first = [0u8; 32];
+        let mut second = [0u8; 32];
+        first.copy_from_slice(&output[..32]);
+        second.copy_from_slice(&output[32..]);
+        Ok((first, second))
+    }
+
+    fn mix_key(&mut self, input_key_material: &[u8]) -> Result<(), Error> {
+        let (chaining_key, key) = self.hkdf(input_key_material)?;
+        self.chaining_key = Zeroizing::new(chaining_key);
+        self.cipher = CipherState::new(Some(key));
+        Ok(())
+    }
+
+    fn encrypt_and_hash(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        let ciphertext = self.cipher.encrypt(&self.hash, plaintext)?;
+        self.mix_hash(&ciphertext);
+        Ok(ciphertext)
+    }
+
+    fn decrypt_and_hash(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        let plaintext = self.cipher.decrypt(&self.hash, ciphertext)?;
+        self.mix_hash(ciphertext);
+        Ok(plaintext)
+    }
+
+    fn has_key(&self) -> bool {
+        self.cipher.key.is_some()
+    }
+}
+
+/// Drives one endpoint through the three messages of the handshake
+pub struct NoiseHandshake {
+    initiator: bool,
+    symmetric: SymmetricState,
+    local_static: StaticKeypair,
+    local_ephemeral: Option<StaticSecret>,
+    local_kem_secret: Option<Zeroizing<Vec<u8>>>,
+    remote_kem_public: Option<Vec<u8>>,
+    remote_ephemeral: Option<PublicKey>,
+    remote_static: Option<PublicKey>,
+    // the number of messages written or read so far
+    step: u8,
+}
+
+impl NoiseHandshake {
+    /// Creates the endpoint that sends the first message. The prologue is authenticated by the
+    /// handshake, and must be identical on both endpoints
+    pub fn initiator(local_static: StaticKeypair, prologue: &[u8]) -> Self {
+        Self::new(true, local_static, prologue)
+    }
+
+    /// Creates the endpoint that receives the first message
+    pub fn responder(local_static: StaticKeypair, prologue: &[u8]) -> Self {
+        Self::new(false, local_static, prologue)
+    }
+
+    fn new(initiator: bool, local_static: StaticKeypair, prologue: &[u8]) -> Self {
+        Self {
+            initiator,
+            symmetric: SymmetricState::new(prologue),
+            local_static,
+            local_ephemeral: None,
+            local_kem_secret: None,
+            remote_kem_public: None,
+            remote_ephemeral: None,
+            remote_static: None,
+            step: 0,
+        }
+    }
+
+    /// Returns true once all three messages were exchanged
+    pub fn is_finished(&self) -> bool {
+        self.step == 3
+    }
+
+    /// Returns the static key of the remote endpoint, once received
+    pub fn remote_static(&self) -> Option<[u8; DH_LEN]> {
+        self.remote_static.map(|key| key.to_bytes())
+    }
+
+    /// Uniquely identifies the handshake, e.g., for binding it to a higher-level authentication
+    pub fn handshake_hash(&self) -> [u8; HASH_LEN] {
+        self.symmetric.hash
+    }
+
+    fn is_our_turn(&self) -> bool {
+        self.initiator == (self.step % 2 == 0)
+    }
+
+    /// Writes the next handshake message, carrying `payload`. The payload of the first message
+    /// is neither encrypted nor authenticated
+    pub fn write_message(&mut self, payload: &[u8]) -> Result<Vec<u8>, Error> {
+        if self.is_finished() || !self.is_our_turn() {
+            return Err(Error::Generic("Not this endpoint's turn to write"));
+        }
+
+        let mut message = Vec::new();
+        match self.step {
+            0 => {
+                self.write_ephemeral(&mut message);
+                let (kem_public, kem_secret) = kyber_pke::kem_keypair();
+                self.local_kem_secret = Some(Zeroizing::new(kem_secret.to_vec()));
+                let kem_public = self.symmetric.encrypt_and_hash(&kem_public)?;
+                write_prefixed(&mut message, &kem_public)?;
+            }
+
+            1 => {
+                self.write_ephemeral(&mut message);
+                let ee = self.dh_ephemeral_remote(self.remote_ephemeral)?;
+                self.symmetric.mix_key(ee.as_ref())?;
+                // the KEM public key was mixed into the hash when read
+                let remote_kem_public = self
+                    .remote_kem_public
+                    .take()
+                    .ok_or(Error::Generic("Missing remote KEM public key"))?;
+                let (ciphertext, shared_secret) =
+                    kyber_pke::encapsulate(&remote_kem_public, &mut ThreadRng::default())
+                        .map_err(|_| Error::Generic("Failed to encapsulate"))?;
+                let ciphertext = self.symmetric.encrypt_and_hash(&ciphertext)?;
+                write_prefixed(&mut message, &ciphertext)?;
+                self.symmetric.mix_key(&shared_secret)?;
+                self.write_static(&mut message)?;
+                let es = dh(&self.local_static.secret, self.remote_ephemeral)?;
+                self.symmetric.mix_key(es.as_ref())?;
+            }
+
+            _ => {
+                self.write_static(&mut message)?;
+                let se = dh(&self.local_static.secret, self.remote_ephemeral)?;
+                self.symmetric.mix_key(se.as_ref())?;
+            }
+        }
+
+        message.extend(self.symmetric.encrypt_and_hash(payload)?);
+        self.step += 1;
+        Ok(message)
+    }
+
+    /// Reads the next handshake message, returning its payload
+    pub fn read_message(&mut self, message: &[u8]) -> Result<Vec<u8>, Error> {
+        if self.is_finished() || self.is_our_turn() {
+            return Err(Error::Generic("Not this endpoint's turn to read"));
+        }
+
+        let mut reader = Reader(message);
+        match self.step {
+            0 => {
+                self.read_ephemeral(&mut reader)?;
+                let kem_public = reader.take_prefixed()?;
+                let kem_public = self.symmetric.decrypt_and_hash(kem_public)?;
+                self.remote_kem_public = Some(kem_public);
+            }
+
+            1 => {
+                self.read_ephemeral(&mut reader)?;
+                let ee = self.dh_ephemeral_remote(self.remote_ephemeral)?;
+                self.symmetric.mix_key(ee.as_ref())?;
+                let ciphertext = reader.take_prefixed()?;
+                let ciphertext = self.symmetric.decrypt_and_hash(ciphertext)?;
+                let kem_secret = self
+                    .local_kem_secret
+                    .take()
+                    .ok_or(Error::Generic("Missing KEM secret"))?;
+                let shared_secret = kyber_pke::decapsulate(&ciphertext, &kem_secret)
+                    .map_err(|_| Error::Generic("Failed to decapsulate"))?;
+                self.symmetric.mix_key(&shared_secret)?;
+                self.read_static(&mut reader)?;
+                let es = self.dh_ephemeral_remote(self.remote_static)?;
+                self.symmetric.mix_key(es.as_ref())?;
+            }
+
+            _ => {
+                self.read_static(&mut reader)?;
+                let se = self.dh_ephemeral_remote(self.remote_static)?;
+                self.symmetric.mix_key(se.as_ref())?;
+            }
+        }
+
+        let payload = self.symmetric.decrypt_and_hash(reader.0)?;
+        self.step += 1;
+        Ok(payload)
+    }
+
+    /// Derives the transport keys once the handshake is finished
+    pub fn into_transport(self) -> Result<NoiseTransport, Error> {
+        if !self.is_finished() {
+            return Err(Error::Generic("The handshake is not finished"));
+        }
+
+        let (initiator_to_responder, responder_to_initiator) = self.symmetric.hkdf(&[])?;
+        let (send, recv) = if self.initiator {
+            (initiator_to_responder, responder_to_initiator)
+        } else {
+            (responder_to_initiator, initiator_to_responder)
+        };
+
+        Ok(NoiseTransport {
+            send: CipherState::new(Some(send)),
+            recv: CipherState::new(Some(recv)),
+            handshake_hash: self.symmetric.hash,
+        })
+    }
+
+    fn write_ephemeral(&mut self, message: &mut Vec<u8>) {
+        let ephemeral = StaticSecret::from(random_secret());
+        let public = PublicKey::from(&ephemeral);
+        message.extend_from_slice(public.as_bytes());
+        self.symmetric.mix_hash(public.as_bytes());
+        self.local_ephemeral = Some(ephemeral);
+    }
+
+    fn read_ephemeral(&mut self, reader: &mut Reader) -> Result<(), Error> {
+        let public = reader.take_key()?;
+        self.symmetric.mix_hash(&public);
+        self.remote_ephemeral = Some(PublicKey::from(public));
+        Ok(())
+    }
+
+    fn write_static(&mut self, message: &mut Vec<u8>) -> Result<(), Error> {
+        let public = self.local_static.public_key();
+        message.extend(self.symmetric.encrypt_and_hash(&public)?);
+        Ok(())
+    }
+
+    fn read_static(&mut self, reader: &mut Reader) -> Result<(), Error> {
+        let len = if self.symmetric.has_key() {
+            DH_LEN + TAG_LEN
+        } else {
+            DH_LEN
+        };
+        let encrypted = reader.take(len)?;
+        let public = self.symmetric.decrypt_and_hash(encrypted)?;
+        let mut key = [0u8; DH_LEN];
+        key.copy_from_slice(&public);
+        self.remote_static = Some(PublicKey::from(key));
+        Ok(())
+    }
+
+    fn dh_ephemeral_remote(
+        &self,
+        remote: Option<PublicKey>,
+    ) -> Result<Zeroizing<[u8; DH_LEN]>, Error> {
+        let ephemeral = self
+            .local_ephemeral
+            .as_ref()
+            .ok_or(Error::Generic("Missing local ephemeral key"))?;
+        dh(ephemeral, remote)
+    }
+}
+
+/// Encrypts and decrypts messages once the handshake is finished. Messages must be decrypted in
+/// the order they were encrypted
+pub struct NoiseTransport {
+    send: CipherState,
+    recv: CipherState,
+    handshake_hash: [u8; HASH_LEN],
+}
+
+impl NoiseTransport {
+    pub fn encrypt(&mut self, plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+        self.send.encrypt(&[], plaintext)
+    }
+
+    pub fn decrypt(&mut self, ciphertext: &[u8]) -> Result<Vec<u8>, Error> {
+        self.recv.decrypt(&[], ciphertext)
+    }
+
+    pub fn handshake_hash(&self) -> [u8; HASH_LEN] {
+        self.handshake_hash
+    }
+}
+
+fn random_secret() -> [u8; DH_LEN] {
+    let mut secret = [0u8; DH_LEN];
+    ThreadRng::default().fill_bytes(&mut secret);
+    secret
+}
+
+fn dh(local: &StaticSecret, remote: Option<PublicKey>) -> Result<Zeroizing<[u8; DH_LEN]>, Error> {
+    let remote = remote.ok_or(Error::Generic("Missing remote key"))?;
+    let shared = local.diffie_hellman(&remote);
+    // low-order remote keys yield an all-zero secret
+    if !shared.was_contributory() {
+        return Err(Error::Generic("Non-contributory key agreement"));
+    }
+
+    Ok(Zeroizing::new(shared.to_bytes()))
+}
+
+fn write_prefixed(message: &mut Vec<u8>, field: &[u8]) -> Result<(), Error> {
+    let len = u16::try_from(field.len()).map_err(|_| Error::InvalidLength)?;
+    message.extend_from_slice(&len.to_be_bytes());
+    message.extend_from_slice(field);
+    Ok(())
+}
+
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if self.0.len() < len {
+            return Err(Error::InvalidLength);
+        }
+
+        let (field, rest) = self.0.split_at(len);
+        self.0 = rest;
+        Ok(field)
+    }
+
+    fn take_key(&mut self) -> Result<[u8; DH_LEN], Error> {
+        let mut key = [0u8; DH_LEN];
+        key.copy_from_slice(self.take(DH_LEN)?);
+        Ok(key)
+    }
+
+    fn take_prefixed(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.take(2)?;
+        let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+        self.take(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::noise::{NoiseHandshake, StaticKeypair};
+
+    #[test]
+    fn test_handshake_and_transport() {
+        let initiator_static = StaticKeypair::generate();
+        let responder_static = StaticKeypair::generate();
+        let initiator_public = initiator_static.public_key();
+        let responder_public = responder_static.public_key();
+
+        let mut initiator = NoiseHandshake::initiator(initiator_static, b"citadel");
+        let mut responder = NoiseHandshake::responder(responder_static, b"citadel");
+
+        // endpoints may only write in turn
+        assert!(responder.write_message(&[]).is_err());
+
+        let message = initiator.write_message(b"hello").unwrap();
+        assert_eq!(responder.read_message(&message).unwrap(), b"hello");
+        let message = responder.write_message(b"from responder").unwrap();
+        assert_eq!(initiator.read_message(&message).unwrap(), b"from responder");
+        assert_eq!(initiator.remote_static(), Some(responder_public));
+        let message = initiator.write_message(b"from initiator").unwrap();
+        assert_eq!(responder.read_message(&message).unwrap(), b"from initiator");
+        assert_eq!(responder.remote_static(), Some(initiator_public));
+
+        assert_eq!(initiator.handshake_hash(), responder.handshake_hash());
+        let mut initiator = initiator.into_transport().unwrap();
+        let mut responder = responder.into_transport().unwrap();
+
+        let ciphertext = initiator.encrypt(b"ping").unwrap();
+        assert_eq!(responder.decrypt(&ciphertext).unwrap(), b"ping");
+        let ciphertext = responder.encrypt(b"pong").unwrap();
+        assert_eq!(initiator.decrypt(&ciphertext).unwrap(), b"pong");
+
+        // tampered messages fail without advancing the nonce
+        let mut tampered = initiator.encrypt(b"data").unwrap();
+        tampered[0] ^= 1;
+        assert!(responder.decrypt(&tampered).is_err());
+        tampered[0] ^= 1;
+        assert_eq!(responder.decrypt(&tampered).unwrap(), b"data");
+    }
+
+    #[test]
+    fn test_mismatched_prologue_fails() {
+        let mut initiator = NoiseHandshake::initiator(StaticKeypair::generate(), b"a");
+        let mut responder = NoiseHandshake::responder(StaticKeypair::generate(), b"b");
+        let message = initiator.write_message(&[]).unwrap();
+        let _ = responder.read_message(&message).unwrap();
+        let message = responder.write_message(&[]).unwrap();
+        assert!(initiator.read_message(&message).is_err());
+    }
+}
@@ -6,10 +6,11 @@ mod tests {
 
     use citadel_logging::setup_log;
     use citadel_pqcrypto::algorithm_dictionary::{
-        AlgorithmsExt, CryptoParameters, EncryptionAlgorithm, KemAlgorithm, SigAlgorithm,
+        AlgorithmsExt, CryptoParameters, EncryptionAlgorithm, KemAlgorithm, SigAlgorithm, FIPS_MODE,
     };
     use citadel_pqcrypto::bytes_in_place::EzBuffer;
     use citadel_pqcrypto::constructor_opts::ConstructorOpts;
+    use citadel_pqcrypto::key_domain::KeyDomain;
     use citadel_pqcrypto::replay_attack_container::HISTORY_LEN;
     use citadel_pqcrypto::{validate_crypto_params, PostQuantumContainer};
     use std::convert::TryFrom;
@@ -220,6 +221,53 @@ mod tests {
         log::trace!(target: "citadel", "[ {} ] {:?}", buf.len(), buf.as_ref());
     }
 
+    #[test]
+    fn key_domain_separation() {
+        const HEADER_LEN: usize = 50;
+        let (alice_container, bob_container) = gen(
+            KemAlgorithm::Kyber,
+            EncryptionAlgorithm::AES_GCM_256,
+            SigAlgorithm::None,
+        );
+
+        let primary = alice_container
+            .key_domain_fingerprint(KeyDomain::Primary)
+            .unwrap();
+        let unordered = alice_container
+            .key_domain_fingerprint(KeyDomain::Unordered)
+            .unwrap();
+        assert_ne!(primary, unordered);
+        assert_eq!(
+            Some(primary),
+            bob_container.key_domain_fingerprint(KeyDomain::Primary)
+        );
+        assert_eq!(
+            Some(unordered),
+            bob_container.key_domain_fingerprint(KeyDomain::Unordered)
+        );
+        assert_ne!(KeyDomain::Primary.label(), KeyDomain::Unordered.label());
+
+        let nonce =
+            Vec::from_iter(0..alice_container.params.encryption_algorithm.nonce_len() as u8);
+        let mut buf = BytesMut::from(&[7u8; HEADER_LEN + 64][..]);
+        let original = buf.clone();
+        alice_container
+            .protect_packet_in_place_in_domain(KeyDomain::Unordered, HEADER_LEN, &mut buf, &nonce)
+            .unwrap();
+        let header = buf.split_to(HEADER_LEN);
+
+        // a packet protected in one domain cannot be validated in another
+        let mut wrong_domain = buf.clone();
+        assert!(bob_container
+            .validate_packet_in_place(&header, &mut wrong_domain, &nonce)
+            .is_err());
+
+        bob_container
+            .validate_packet_in_place_in_domain(KeyDomain::Unordered, &header, &mut buf, &nonce)
+            .unwrap();
+        assert_eq!(&buf[..], &original[HEADER_LEN..]);
+    }
+
     #[test]
     fn in_place_out_of_order_for_unordered_mode() {
         setup_log();
@@ -493,4 +541,20 @@ mod tests {
         let bad_params = EncryptionAlgorithm::Kyber + KemAlgorithm::Kyber;
         assert!(validate_crypto_params(&bad_params).is_err());
     }
+
+    #[test]
+    fn test_fips_params() {
+        assert!(CryptoParameters::default().is_fips_approved());
+        for enx in EncryptionAlgorithm::list() {
+            for sig in SigAlgorithm::list() {
+                let params = enx + KemAlgorithm::Kyber + sig;
+                let approved = enx == EncryptionAlgorithm::AES_GCM_256 && sig == SigAlgorithm::None;
+                assert_eq!(params.is_fips_approved(), approved);
+                // outside of FIPS mode, approval does not affect validation
+                if FIPS_MODE && !approved {
+                    assert!(validate_crypto_params(&params).is_err());
+                }
+            }
+        }
+    }
 }
@@ -15,6 +15,12 @@ pub enum AuthenticationRequest {
         username: String,
         server_addr: SocketAddr,
     },
+    /// An ID token issued by an OpenID Connect provider trusted by the node. Registers the account
+    /// if it does not yet exist locally
+    Oidc {
+        id_token: String,
+        server_addr: SocketAddr,
+    },
 }
 
 impl AuthenticationRequest {
@@ -33,4 +39,13 @@ impl AuthenticationRequest {
             server_addr,
         }
     }
+
+    /// The ID token obtained from an OpenID Connect provider will be used for login. The account
+    /// is keyed by the issuer and subject of the token, and is created the first time it is used
+    pub fn oidc<T: Into<String>>(id_token: T, server_addr: SocketAddr) -> Self {
+        Self::Oidc {
+            id_token: id_token.into(),
+            server_addr,
+        }
+    }
 }
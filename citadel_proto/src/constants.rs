@@ -66,6 +66,10 @@ pub const DO_DEREGISTER_EXPIRE_TIME_NS: i64 = KEEP_ALIVE_TIMEOUT_NS;
 
 /// The frequency at which KEEP_ALIVES need to be sent through the system
 pub const FIREWALL_KEEP_ALIVE_UDP: std::time::Duration = std::time::Duration::from_secs(60);
+/// The number of consecutive times a failed raw UDP socket is rebound before the UDP channel closes
+pub const MAX_UDP_REBINDS: u32 = 5;
+/// The delay before rebinding a failed raw UDP socket, multiplied by the number of consecutive rebinds
+pub const UDP_REBIND_BACKOFF: std::time::Duration = std::time::Duration::from_millis(500);
 /// The largest size, in bytes, that a single group can hold (~8 Megs)
 pub const MAX_GROUP_SIZE_BYTES: usize = 1_000_000 * 8;
 /// How many bytes are stored
@@ -108,6 +112,12 @@ pub const LOGIN_EXPIRATION_TIME: std::time::Duration = std::time::Duration::from
 pub const NTP_RESYNC_FREQUENCY: std::time::Duration = std::time::Duration::from_secs(60 * 30);
 ///
 pub const TCP_CONN_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(4);
+/// How long an endpoint waits for the adjacent node to begin NAT traversal once UDP is enabled after connecting
+pub const DEFERRED_UDP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
 
 pub const MAX_OUTGOING_UNPROCESSED_REQUESTS: usize = 512;
 pub const MAX_INCOMING_UNPROCESSED_REQUESTS: usize = 512;
+/// The number of bytes a receiver lets each peer have in flight before its application reads them
+pub const FLOW_CONTROL_WINDOW: u64 = 16 * 1024 * 1024;
+/// The number of independently locked shards the sessions and the peer layer's message groups are split into
+pub const SESSION_MAP_SHARDS: usize = 64;
@@ -0,0 +1,55 @@
+//! Entry points for the `cargo-fuzz` targets under `citadel_proto/fuzz`
+//!
+//! Each function feeds arbitrary bytes through the same parsing and validation path that an
+//! inbound packet from the network takes. None of these functions may panic, regardless of input.
+//!
+//! Run a target from the `citadel_proto` directory with `cargo +nightly fuzz run header_parse`
+use crate::proto::packet::{HdpHeader, HdpPacket};
+use crate::proto::peer::peer_layer::PeerSignal;
+use crate::proto::validation::group;
+use bytes::BytesMut;
+use citadel_crypt::scramble::crypt_splitter::GroupReceiver;
+use citadel_user::serialization::SyncIO;
+use std::net::{Ipv4Addr, SocketAddr};
+use zerocopy::LayoutVerified;
+
+/// Parses the header of a raw primary-port packet, then splits it into its header and payload
+pub fn parse_header(data: &[u8]) {
+    let remote_peer = SocketAddr::from((Ipv4Addr::LOCALHOST, 0));
+    let packet = HdpPacket::new_recv(BytesMut::from(data), remote_peer, 0);
+
+    if let Ok((header, payload)) = packet.try_parse() {
+        let _ = (
+            header.cmd_primary,
+            header.cmd_aux,
+            header.group.get(),
+            header.wave_id.get(),
+            header.session_cid.get(),
+            header.drill_version.get(),
+            header.target_cid.get(),
+            payload.len(),
+        );
+    }
+
+    let (header, _payload, _, _) = packet.decompose();
+    let _ = LayoutVerified::<_, HdpHeader>::new(&header[..]);
+}
+
+/// Decodes a group payload as each of the group packet types
+pub fn decode_group_payload(data: &[u8]) {
+    if let Some(group::GroupHeader::Standard(cfg, _)) =
+        group::validate_header(&mut BytesMut::from(data))
+    {
+        // a header which passes validation must be safe to allocate a receiver for
+        let _ = GroupReceiver::new(cfg, 0, 0);
+    }
+
+    let _ = group::validate_header_ack(data);
+    let _ = group::validate_wave_ack(data);
+    let _ = group::validate_message(&mut BytesMut::from(data));
+}
+
+/// Deserializes a peer signal, as received by the peer command processor
+pub fn deserialize_peer_signal(data: &[u8]) {
+    let _ = PeerSignal::deserialize_from_vector(data);
+}
@@ -64,6 +64,11 @@ impl KernelAsyncCallbackHandler {
         this.map.remove(&ticket);
     }
 
+    /// Returns the number of tickets awaiting a callback
+    pub fn tracked_tickets(&self) -> usize {
+        self.inner.lock().map.len()
+    }
+
     // If a notification occurred, returns None. Else, returns the result
     fn maybe_notify(&self, result: NodeResult) -> Option<NodeResult> {
         match result.ticket() {
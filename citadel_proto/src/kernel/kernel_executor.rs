@@ -4,22 +4,27 @@ use futures::TryStreamExt;
 use tokio::runtime::Handle;
 use tokio::task::LocalSet;
 
+use citadel_io::Mutex;
 use citadel_user::account_manager::AccountManager;
 
 use crate::error::NetworkError;
 use crate::kernel::kernel_communicator::KernelAsyncCallbackHandler;
 use crate::kernel::kernel_trait::NetKernel;
-use crate::kernel::{KernelExecutorArguments, KernelExecutorSettings, RuntimeFuture};
+use crate::kernel::{
+    KernelExecutorArguments, KernelExecutorSettings, KernelPanicPolicy, RuntimeFuture,
+};
+use crate::proto::misc::panic_future::CatchPanicFuture;
 use crate::proto::node::HdpServer;
-use crate::proto::node_result::NodeResult;
-use crate::proto::outbound_sender::{unbounded, UnboundedReceiver};
+use crate::proto::node_result::{EventMetadata, KernelPanicked, NodeResult};
+use crate::proto::outbound_sender::{kernel_event_channel, EventStamp, UnboundedReceiver};
 use crate::proto::packet_processor::includes::Duration;
 use crate::proto::remote::NodeRemote;
+use std::time::SystemTime;
 
 /// Creates a [KernelExecutor]
 pub struct KernelExecutor<K: NetKernel> {
     server_remote: Option<NodeRemote>,
-    server_to_kernel_rx: Option<UnboundedReceiver<NodeResult>>,
+    server_to_kernel_rx: Option<UnboundedReceiver<(NodeResult, EventStamp)>>,
     shutdown_alerter_rx: Option<tokio::sync::oneshot::Receiver<()>>,
     callback_handler: Option<KernelAsyncCallbackHandler>,
     context: Option<KernelContext>,
@@ -43,8 +48,16 @@ impl<K: NetKernel> KernelExecutor<K> {
             client_config,
             kernel_executor_settings,
             stun_servers,
+            hole_punch_settings,
+            packet_filter,
+            account_hook,
+            abuse_detector,
+            server_key_pinning,
+            cert_bootstrap_code,
+            key_rotation,
+            embedded_stun_server,
         } = args;
-        let (server_to_kernel_tx, server_to_kernel_rx) = unbounded();
+        let (server_to_kernel_tx, server_to_kernel_rx) = kernel_event_channel();
         let (server_shutdown_alerter_tx, server_shutdown_alerter_rx) =
             tokio::sync::oneshot::channel();
         // After this gets called, the server starts running and we get a remote
@@ -56,6 +69,14 @@ impl<K: NetKernel> KernelExecutor<K> {
             underlying_proto,
             client_config,
             stun_servers,
+            hole_punch_settings,
+            packet_filter,
+            account_hook,
+            abuse_detector,
+            server_key_pinning,
+            cert_bootstrap_code,
+            key_rotation,
+            embedded_stun_server,
         )
         .await
         .map_err(|err| NetworkError::Generic(err.to_string()))?;
@@ -124,20 +145,85 @@ impl<K: NetKernel> KernelExecutor<K> {
     #[allow(unused_must_use)]
     async fn kernel_inner_loop(
         kernel: &mut K,
-        mut server_to_kernel_rx: UnboundedReceiver<NodeResult>,
+        mut server_to_kernel_rx: UnboundedReceiver<(NodeResult, EventStamp)>,
         ref hdp_server_remote: NodeRemote,
         shutdown: tokio::sync::oneshot::Receiver<()>,
         ref callback_handler: KernelAsyncCallbackHandler,
         kernel_settings: KernelExecutorSettings,
     ) -> Result<(), NetworkError> {
         log::trace!(target: "citadel", "Kernel multithreaded environment executed ...");
-        // Load the remote into the kernel
-        kernel.load_remote(hdp_server_remote.clone())?;
+        let mut restarts = 0;
+        let mut restarted_after = None;
 
+        let exec_res = loop {
+            // Load the remote into the kernel
+            kernel.load_remote(hdp_server_remote.clone())?;
+
+            let restart_request = &Mutex::new(None);
+            let exec_res = Self::run_kernel(
+                &*kernel,
+                &mut server_to_kernel_rx,
+                hdp_server_remote,
+                callback_handler,
+                &kernel_settings,
+                restarts,
+                restart_request,
+                restarted_after.take(),
+            )
+            .await;
+
+            match restart_request.lock().take() {
+                Some(panicked) => {
+                    restarts += 1;
+                    log::warn!(target: "citadel", "Restarting kernel ({restarts} restart(s) so far)");
+                    if let Err(err) = kernel.on_stop().await {
+                        log::warn!(target: "citadel", "Kernel threw an error while stopping for restart: {:?}", err);
+                    }
+
+                    restarted_after = Some(panicked);
+                }
+
+                None => break exec_res,
+            }
+        };
+
+        log::trace!(target: "citadel", "Calling kernel on_stop, but first awaiting HdpServer for clean shutdown ...");
+        tokio::time::timeout(Duration::from_millis(300), shutdown).await;
+        log::trace!(target: "citadel", "KernelExecutor confirmed HdpServer has been shut down");
+        let stop_res = kernel.on_stop().await;
+        // give precedence to the execution res
+        exec_res.and(stop_res.map(|_| ()))
+    }
+
+    /// Runs the kernel until the node shuts down, or, until the kernel panics and must be restarted,
+    /// in which case `restart_request` is populated
+    #[allow(clippy::too_many_arguments)]
+    async fn run_kernel(
+        kernel_ref: &K,
+        server_to_kernel_rx: &mut UnboundedReceiver<(NodeResult, EventStamp)>,
+        hdp_server_remote: &NodeRemote,
+        callback_handler: &KernelAsyncCallbackHandler,
+        kernel_settings: &KernelExecutorSettings,
+        restarts: usize,
+        restart_request: &Mutex<Option<KernelPanicked>>,
+        restarted_after: Option<KernelPanicked>,
+    ) -> Result<(), NetworkError> {
         let (ref clean_stop_tx, mut clean_stop_rx) = tokio::sync::mpsc::channel::<()>(1);
-        let kernel_ref = &*kernel;
+        let panic_policy = kernel_settings.panic_policy;
+
+        let init = async move {
+            CatchPanicFuture::new(kernel_ref.on_start())
+                .await
+                .map_err(|message| {
+                    NetworkError::Generic(format!("Kernel panicked during on_start: {message}"))
+                })??;
+
+            if let Some(panicked) = restarted_after {
+                Self::deliver_panic_event(kernel_ref, panicked).await;
+            }
 
-        let init = async move { kernel_ref.on_start().await };
+            Ok(())
+        };
 
         let inbound_stream = async move {
             let reader = async_stream::try_stream! {
@@ -146,7 +232,7 @@ impl<K: NetKernel> KernelExecutor<K> {
                 }
             };
 
-            reader.try_for_each_concurrent(kernel_settings.max_concurrency, |message: NodeResult| async move {
+            reader.try_for_each_concurrent(kernel_settings.max_concurrency, |(message, stamp): (NodeResult, EventStamp)| async move {
                 log::trace!(target: "citadel", "[KernelExecutor] Received message {:?}", message);
                 match message {
                     NodeResult::Shutdown => {
@@ -156,14 +242,61 @@ impl<K: NetKernel> KernelExecutor<K> {
                     }
 
                     message => {
-                        callback_handler.on_message_received(message, |message| async move {
-                            if let Err(err) = kernel_ref.on_node_event_received(message).await {
-                                log::error!(target: "citadel", "Kernel threw an error: {:?}. Will end", &err);
-                                // calling this will cause server_to_kernel_rx to receive a shutdown message
-                                hdp_server_remote.clone().shutdown().await?;
-                                Err(err)
-                            } else {
-                                Ok(())
+                        callback_handler.on_message_received(message, |mut message| async move {
+                            let ticket = message.ticket();
+                            let event = message.variant_name();
+
+                            let handled = CatchPanicFuture::new(async move {
+                                if let NodeResult::ObjectTransferHandle(transfer) = &mut message {
+                                    if let Some(proposal) = transfer.handle.proposal().cloned() {
+                                        let decision = kernel_ref.on_inbound_transfer_proposed(&proposal).await;
+                                        log::trace!(target: "citadel", "Inbound transfer policy for {proposal:?}: {decision:?}");
+                                        if let Err(err) = transfer.handle.respond(decision) {
+                                            log::warn!(target: "citadel", "Unable to apply inbound transfer policy: {:?}", err);
+                                        }
+                                    }
+                                }
+
+                                let metadata = EventMetadata {
+                                    session_id: stamp.session_id,
+                                    sequence: stamp.sequence,
+                                    emitted_at: stamp.emitted_at,
+                                    delivered_at: SystemTime::now(),
+                                };
+                                kernel_ref.on_node_event_received_with_metadata(message, metadata).await
+                            }).await;
+
+                            match handled {
+                                Ok(Ok(())) => Ok(()),
+
+                                Ok(Err(err)) => {
+                                    log::error!(target: "citadel", "Kernel threw an error: {:?}. Will end", &err);
+                                    // calling this will cause server_to_kernel_rx to receive a shutdown message
+                                    hdp_server_remote.clone().shutdown().await?;
+                                    Err(err)
+                                }
+
+                                Err(message) => {
+                                    log::error!(target: "citadel", "Kernel panicked while handling {event}: {message}");
+                                    let panicked = KernelPanicked { ticket, event, message };
+                                    match panic_policy {
+                                        KernelPanicPolicy::DropEvent => {
+                                            Self::deliver_panic_event(kernel_ref, panicked).await;
+                                            Ok(())
+                                        }
+
+                                        KernelPanicPolicy::RestartKernel { max_restarts } if restarts < max_restarts => {
+                                            *restart_request.lock() = Some(panicked);
+                                            // ends this run of the kernel, dropping all events being handled concurrently
+                                            Err(NetworkError::InternalError("Kernel restart requested"))
+                                        }
+
+                                        _ => {
+                                            hdp_server_remote.clone().shutdown().await?;
+                                            Err(NetworkError::Generic(format!("Kernel panicked: {}", panicked.message)))
+                                        }
+                                    }
+                                }
                             }
                         }).await
                     }
@@ -173,17 +306,27 @@ impl<K: NetKernel> KernelExecutor<K> {
 
         let base_execution = futures::future::try_join(init, inbound_stream);
 
-        let exec_res = tokio::select! {
+        tokio::select! {
             base_res = base_execution => base_res.map(|_| ()),
             _stopper = clean_stop_rx.recv() => Ok(())
-        };
+        }
+    }
 
-        log::trace!(target: "citadel", "Calling kernel on_stop, but first awaiting HdpServer for clean shutdown ...");
-        tokio::time::timeout(Duration::from_millis(300), shutdown).await;
-        log::trace!(target: "citadel", "KernelExecutor confirmed HdpServer has been shut down");
-        let stop_res = kernel.on_stop().await;
-        // give precedence to the execution res
-        exec_res.and(stop_res.map(|_| ()))
+    /// A panic while handling the notification itself is logged, but otherwise ignored
+    async fn deliver_panic_event(kernel: &K, panicked: KernelPanicked) {
+        match CatchPanicFuture::new(
+            kernel.on_node_event_received(NodeResult::KernelPanicked(panicked)),
+        )
+        .await
+        {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => {
+                log::warn!(target: "citadel", "Kernel threw an error while handling a panic notification: {:?}", err)
+            }
+            Err(message) => {
+                log::error!(target: "citadel", "Kernel panicked while handling a panic notification: {message}")
+            }
+        }
     }
 
     pub fn account_manager(&self) -> &AccountManager {
@@ -1,9 +1,10 @@
 use async_trait::async_trait;
 
 use crate::error::NetworkError;
-use crate::proto::node_result::NodeResult;
+use crate::proto::node_result::{EventMetadata, NodeResult};
 use crate::proto::remote::NodeRemote;
 use auto_impl::auto_impl;
+use citadel_user::backend::utils::{InboundTransferDecision, InboundTransferProposal};
 
 /// The [NetKernel] is the thread-safe interface between the single-threaded OR multi-threaded async
 /// protocol and your network application
@@ -18,6 +19,26 @@ pub trait NetKernel: Send + Sync {
     /// *concurrently* (but NOT in *parallel*). This allows code inside this function to await without blocking new incoming
     /// messages
     async fn on_node_event_received(&self, message: NodeResult) -> Result<(), NetworkError>;
+    /// Like [`NetKernel::on_node_event_received`], but also receives when and in which order the
+    /// event was emitted. See [`EventMetadata`]. The executor calls this instead of
+    /// [`NetKernel::on_node_event_received`], to which the default implementation delegates
+    async fn on_node_event_received_with_metadata(
+        &self,
+        message: NodeResult,
+        _metadata: EventMetadata,
+    ) -> Result<(), NetworkError> {
+        self.on_node_event_received(message).await
+    }
+    /// Called when an adjacent node proposes sending an object to this node, before the corresponding
+    /// [`NodeResult::ObjectTransferHandle`] is passed to [`NetKernel::on_node_event_received`]. Unless
+    /// [`InboundTransferDecision::Defer`] is returned, the decision is applied on behalf of the kernel,
+    /// and the handle should only be used for observing progress
+    async fn on_inbound_transfer_proposed(
+        &self,
+        _proposal: &InboundTransferProposal,
+    ) -> InboundTransferDecision {
+        InboundTransferDecision::Defer
+    }
     /// When the system is ready to shutdown, this is called
     async fn on_stop(&mut self) -> Result<(), NetworkError>;
 }
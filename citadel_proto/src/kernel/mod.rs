@@ -1,6 +1,10 @@
 use citadel_user::account_manager::AccountManager;
 use citadel_wire::exports::ClientConfig;
 use citadel_wire::hypernode_type::NodeType;
+use citadel_wire::server_identity::KeyRotationAnnouncement;
+use citadel_wire::server_key_pinning::ServerKeyPinning;
+use citadel_wire::udp_traversal::linear::encrypted_config_container::HolePunchConfigContainer;
+use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::macros::support::Future;
 use tokio::runtime::Handle;
@@ -8,6 +12,9 @@ use tokio::runtime::Handle;
 use crate::error::NetworkError;
 use crate::macros::ContextRequirements;
 use crate::prelude::ServerUnderlyingProtocol;
+use crate::proto::misc::abuse_detection::AbuseDetector;
+use crate::proto::misc::account_hook::AccountHook;
+use crate::proto::misc::packet_filter::PacketFilter;
 
 /// for handling easy asynchronous callbacks
 pub mod kernel_communicator;
@@ -24,6 +31,7 @@ impl<T: Future<Output = Result<(), NetworkError>> + ContextRequirements> Runtime
 /// Used for fine-tuning parameters within the [`KernelExecutor`]
 pub struct KernelExecutorSettings {
     max_concurrency: Option<usize>,
+    panic_policy: KernelPanicPolicy,
 }
 
 impl KernelExecutorSettings {
@@ -33,6 +41,28 @@ impl KernelExecutorSettings {
         self.max_concurrency = max_concurrency.into();
         self
     }
+
+    /// Determines how the [`KernelExecutor`] reacts when the kernel panics while handling an event.
+    /// Default is [`KernelPanicPolicy::DropEvent`]
+    pub fn with_panic_policy(mut self, panic_policy: KernelPanicPolicy) -> Self {
+        self.panic_policy = panic_policy;
+        self
+    }
+}
+
+/// Determines how the [`KernelExecutor`] reacts when the kernel panics while handling an event.
+/// In all cases, the kernel is notified via [`NodeResult::KernelPanicked`](crate::prelude::NodeResult::KernelPanicked)
+/// unless the node shuts down
+#[derive(Default, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum KernelPanicPolicy {
+    /// The event that triggered the panic is dropped, and the kernel continues handling subsequent events
+    #[default]
+    DropEvent,
+    /// Events being handled concurrently are dropped, then, the kernel is stopped, re-loaded and
+    /// started anew. Once `max_restarts` is exceeded, the node shuts down gracefully
+    RestartKernel { max_restarts: usize },
+    /// The node shuts down gracefully
+    Shutdown,
 }
 
 pub struct KernelExecutorArguments<K> {
@@ -44,4 +74,12 @@ pub struct KernelExecutorArguments<K> {
     pub client_config: Option<Arc<ClientConfig>>,
     pub kernel_executor_settings: KernelExecutorSettings,
     pub stun_servers: Option<Vec<String>>,
+    pub hole_punch_settings: Option<HolePunchConfigContainer>,
+    pub packet_filter: Option<Arc<dyn PacketFilter>>,
+    pub account_hook: Option<Arc<dyn AccountHook>>,
+    pub abuse_detector: Option<Arc<dyn AbuseDetector>>,
+    pub server_key_pinning: Option<ServerKeyPinning>,
+    pub cert_bootstrap_code: Option<String>,
+    pub key_rotation: Option<KeyRotationAnnouncement>,
+    pub embedded_stun_server: Option<SocketAddr>,
 }
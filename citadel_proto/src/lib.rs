@@ -170,6 +170,15 @@ pub mod macros {
             }
         }
     }
+
+    macro_rules! return_if_illegal {
+        ($transition:expr) => {
+            if let Err(err) = $transition {
+                log::warn!(target: "citadel", "Dropping packet: {}", err);
+                return Ok(PrimaryProcessorResult::Void);
+            }
+        };
+    }
 }
 
 #[cfg(feature = "multi-threaded")]
@@ -323,6 +332,15 @@ pub mod macros {
             }
         }
     }
+
+    macro_rules! return_if_illegal {
+        ($transition:expr) => {
+            if let Err(err) = $transition {
+                log::warn!(target: "citadel", "Dropping packet: {}", err);
+                return Ok(PrimaryProcessorResult::Void);
+            }
+        };
+    }
 }
 
 #[cfg(not(target_host = "wasm"))]
@@ -332,11 +350,15 @@ pub mod re_imports {
     pub use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
     pub use futures::future::try_join3;
 
+    pub use citadel_crypt::entropy_source::set_entropy_source;
+    pub use citadel_crypt::misc::set_memory_lock_policy;
     pub use citadel_pqcrypto::build_tag;
     pub use citadel_wire::exports::openssl;
     pub use citadel_wire::exports::rustls_pemfile;
     pub use citadel_wire::exports::ClientConfig as RustlsClientConfig;
     pub use citadel_wire::hypernode_type::NodeType;
+    #[cfg(feature = "lan-discovery")]
+    pub use citadel_wire::lan_discovery;
     pub use citadel_wire::quic::insecure;
     pub use citadel_wire::tls::{
         cert_vec_to_secure_client_config, create_rustls_client_config, load_native_certs_async,
@@ -347,28 +369,79 @@ pub mod prelude {
     pub use citadel_crypt::argon::argon_container::ArgonDefaultServerSettings;
     #[cfg(not(coverage))]
     pub use citadel_crypt::argon::autotuner::calculate_optimal_argon_params;
+    pub use citadel_crypt::endpoint_crypto_container::DriftTolerance;
+    pub use citadel_crypt::entropy_source::{EntropyHealthError, EntropySource, HealthTestConfig};
     pub use citadel_crypt::fcm::keys::FcmKeys;
+    pub use citadel_crypt::misc::MemoryLockPolicy;
     pub use citadel_crypt::secure_buffer::{sec_bytes::SecBuffer, sec_string::SecString};
     pub use citadel_pqcrypto::algorithm_dictionary::{
         AlgorithmsExt, EncryptionAlgorithm, KemAlgorithm, SigAlgorithm,
     };
+    pub use citadel_pqcrypto::key_domain::KeyDomain;
     pub use citadel_user::account_manager::AccountManager;
+    pub use citadel_user::auth::credential_validator::{
+        AccountPolicy, CredentialValidator, GroupPolicies, ValidatedUser,
+    };
     pub use citadel_user::auth::proposed_credentials::ProposedCredentials;
     pub use citadel_user::backend::BackendType;
-    pub use citadel_user::external_services::{RtdbConfig, ServicesConfig, ServicesObject};
+    pub use citadel_user::external_services::{
+        LegalHoldNotice, RtdbConfig, ServicesConfig, ServicesObject,
+    };
+    pub use citadel_user::misc::AccountError;
+    pub use citadel_user::permissions::AccountPermissions;
     pub use citadel_user::prelude::{ConnectProtocol, UserIdentifier};
-    pub use citadel_user::server_misc_settings::ServerMiscSettings;
+    pub use citadel_user::server_misc_settings::{
+        CryptoOffloadSettings, HandshakeChallengeSettings, LegalHoldSettings, OidcSettings,
+        PacketProcessingLimits, ProvisionalTimeouts, RelaySettings, ServerMiscSettings,
+        SessionAffinitySettings, SessionWatchdogSettings,
+    };
+    pub use citadel_wire::server_identity::{
+        verify_signature, KeyRotationAnnouncement, ServerIdentity,
+    };
+    pub use citadel_wire::server_key_pinning::{
+        FilePinnedCertStore, InMemoryPinnedCertStore, PinnedCertStore, ServerKeyChangePolicy,
+        ServerKeyPinning,
+    };
+    pub use citadel_wire::udp_traversal::candidate_priority::{CandidatePolicy, DualStackPolicy};
+    pub use citadel_wire::udp_traversal::linear::encrypted_config_container::HolePunchConfigContainer;
+    pub use citadel_wire::udp_traversal::linear::method3::Method3Config;
+    pub use citadel_wire::udp_traversal::linear::LinearUdpHolePunchImpl;
+    pub use citadel_wire::udp_traversal::progress::HolePunchEvent;
 
     pub use crate::error::NetworkError;
     pub use crate::functional::*;
     pub use crate::kernel::RuntimeFuture;
     pub use crate::kernel::{
         kernel_executor::KernelExecutor, kernel_trait::NetKernel, KernelExecutorSettings,
+        KernelPanicPolicy,
+    };
+    pub use crate::proto::misc::abuse_detection::{AbuseAction, AbuseDetector, SessionRateSample};
+    pub use crate::proto::misc::account_hook::{AccountEvent, AccountHook};
+    pub use crate::proto::misc::connect_timings::ConnectTimings;
+    pub use crate::proto::misc::crypto_offload::CryptoOffloadMetrics;
+    pub use crate::proto::misc::deduplication::DeduplicationMetrics;
+    pub use crate::proto::misc::handshake_failures::{
+        HandshakeFailureCounts, HandshakeFailureReason,
+    };
+    pub use crate::proto::misc::multipath::MultipathMetrics;
+    pub use crate::proto::misc::packet_filter::{
+        PacketCommand, PacketFilter, PacketMetadata, PacketVerdict,
     };
     pub use crate::proto::misc::panic_future::ExplicitPanicFuture;
+    pub use crate::proto::misc::protocol_capabilities::{
+        features as protocol_features, HandshakeProfile, ProtocolCapabilities,
+    };
+    pub use crate::proto::misc::provisional_reaper::{ProvisionalStage, ReapedSessionCounts};
+    pub use crate::proto::misc::relay::RelayUsage;
+    pub use crate::proto::misc::resource_counters::ResourceCounters;
+    pub use crate::proto::misc::scheduler::{Schedule, ScheduledTask};
+    pub use crate::proto::misc::security_downgrade::{DowngradePolicy, DowngradeReason};
+    pub use crate::proto::misc::security_info::{SecurityInfo, TransportSecurity};
     pub use crate::proto::misc::session_security_settings::{
         SessionSecuritySettings, SessionSecuritySettingsBuilder,
     };
+    pub use crate::proto::misc::stats_history::ChannelStatsSnapshot;
+    pub use crate::proto::misc::udp_keep_alive::UdpKeepAlive;
     pub use crate::proto::misc::underlying_proto::ServerUnderlyingProtocol;
     pub use crate::proto::node::ConnectMode;
     pub use crate::proto::node::HdpServer;
@@ -383,14 +456,18 @@ pub mod prelude {
     pub use crate::proto::peer::message_group::MessageGroupKey;
     pub use crate::proto::peer::message_group::{GroupType, MessageGroupOptions};
     pub use crate::proto::peer::peer_layer::HypernodeConnectionType;
+    pub use crate::proto::peer::peer_layer::PeerListDelta;
     pub use crate::proto::peer::peer_layer::PeerResponse;
     pub use crate::proto::peer::peer_layer::{PeerConnectionType, PeerSignal, UdpMode};
+    pub use crate::proto::peer::security_epoch::{RekeyEvent, SecurityEpoch};
     pub use crate::proto::remote::Ticket;
     pub use crate::proto::state_container::VirtualTargetType;
     pub use crate::re_imports::{async_trait, NodeType};
     pub use citadel_user::backend::utils::{
+        InboundTransferDecision, InboundTransferProposal, ObjectScanner, ObjectTransferControl,
         ObjectTransferHandler, ObjectTransferOrientation, ObjectTransferStatus,
-        VirtualObjectMetadata,
+        ReVFSDirectoryOperation, SharedObjectNotification, SharedObjectOperation,
+        SharedObjectPermission, TransferProgress, VirtualDirEntry, VirtualObjectMetadata,
     };
     pub use citadel_user::serialization::SyncIO;
 
@@ -400,10 +477,14 @@ pub mod prelude {
     pub use crate::proto::node_result::*;
     pub use crate::proto::remote::*;
 
-    pub use citadel_crypt::misc::TransferType;
+    pub use citadel_crypt::misc::{CryptError, TransferType};
     pub use citadel_crypt::prelude::SecurityLevel;
-    pub use citadel_crypt::streaming_crypt_scrambler::{BytesSource, ObjectSource};
-    pub use citadel_user::misc::{prepare_virtual_path, validate_virtual_path};
+    pub use citadel_crypt::streaming_crypt_scrambler::{
+        BytesSource, FixedSizedSource, ObjectSource,
+    };
+    pub use citadel_user::misc::{
+        prepare_virtual_path, validate_virtual_directory, validate_virtual_path,
+    };
 }
 
 pub mod auth;
@@ -413,6 +494,10 @@ pub mod constants;
 mod error;
 /// Functional extras
 mod functional;
+/// Entry points for fuzzing the parsers of inbound packets
+#[cfg(feature = "fuzzing")]
+#[doc(hidden)]
+pub mod fuzzing;
 /// For handling differential function input types between single/multi-threaded modes
 mod inner_arg;
 /// Contains the streams for creating connections
@@ -0,0 +1,310 @@
+//! Rate sampling of client sessions for abuse detection
+//!
+//! A server samples the traffic each connected client sends over fixed windows: the messages and
+//! bytes per second, along with the number of distinct peers the client sent to. Once a window
+//! closes, its [`SessionRateSample`] is handed to the [`AbuseDetector`], and the returned
+//! [`AbuseAction`] is enforced on the session. Every action besides [`AbuseAction::Allow`] is
+//! reported to the kernel via [`NodeResult::AbuseDetected`], such that operators may contain
+//! abusive clients automatically while keeping a record of each intervention
+//!
+//! [`NodeResult::AbuseDetected`]: crate::prelude::NodeResult::AbuseDetected
+use citadel_io::Mutex;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const DEFAULT_SAMPLE_INTERVAL: Duration = Duration::from_secs(1);
+const THROTTLE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Decides how the server treats a client, given the traffic the client sent during a window
+pub trait AbuseDetector: Send + Sync + 'static {
+    fn evaluate(&self, sample: &SessionRateSample) -> AbuseAction;
+
+    /// The length of each sampling window
+    fn sample_interval(&self) -> Duration {
+        DEFAULT_SAMPLE_INTERVAL
+    }
+}
+
+impl<F: Fn(&SessionRateSample) -> AbuseAction + Send + Sync + 'static> AbuseDetector for F {
+    fn evaluate(&self, sample: &SessionRateSample) -> AbuseAction {
+        (self)(sample)
+    }
+}
+
+/// The traffic a client sent during a single window
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SessionRateSample {
+    /// The CID of the client
+    pub cid: u64,
+    /// The messages sent per second, whether to this node or to peers
+    pub messages_per_second: f64,
+    /// The bytes sent per second, headers included
+    pub bytes_per_second: f64,
+    /// The number of distinct peers the client sent packets to
+    pub fanout: usize,
+    /// The length of the window
+    pub window: Duration,
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum AbuseAction {
+    /// The client is left untouched
+    #[default]
+    Allow,
+    /// The client is left untouched, yet the sample is reported to the kernel
+    Warn,
+    /// Messages beyond `max_messages_per_second` are dropped until `duration` elapses
+    Throttle {
+        max_messages_per_second: u32,
+        duration: Duration,
+    },
+    /// The session of the client is ended
+    Disconnect,
+}
+
+/// The fate of a packet, as decided by the [`AbuseMonitor`]
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum AbuseVerdict {
+    Accept,
+    Drop,
+    Disconnect,
+}
+
+struct Throttle {
+    max_messages_per_second: u32,
+    until: Instant,
+    window_start: Instant,
+    window_messages: u32,
+}
+
+struct SessionMeter {
+    window_start: Instant,
+    messages: u64,
+    bytes: u64,
+    peers: HashSet<u64>,
+    throttle: Option<Throttle>,
+}
+
+impl SessionMeter {
+    fn new(now: Instant) -> Self {
+        Self {
+            window_start: now,
+            messages: 0,
+            bytes: 0,
+            peers: HashSet::new(),
+            throttle: None,
+        }
+    }
+
+    /// Returns false if the throttle of the session drops the message
+    fn admit_message(&mut self, now: Instant) -> bool {
+        let throttle = match self.throttle.as_mut() {
+            Some(throttle) if now < throttle.until => throttle,
+            _ => {
+                self.throttle = None;
+                return true;
+            }
+        };
+
+        if now.duration_since(throttle.window_start) >= THROTTLE_WINDOW {
+            throttle.window_start = now;
+            throttle.window_messages = 0;
+        }
+
+        if throttle.window_messages >= throttle.max_messages_per_second {
+            return false;
+        }
+
+        throttle.window_messages += 1;
+        true
+    }
+}
+
+/// Samples the traffic of each session on behalf of the node-wide [`AbuseDetector`]
+pub struct AbuseMonitor {
+    detector: Arc<dyn AbuseDetector>,
+    sessions: Mutex<HashMap<u64, SessionMeter>>,
+}
+
+impl AbuseMonitor {
+    pub fn new(detector: Arc<dyn AbuseDetector>) -> Self {
+        Self {
+            detector,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Samples a packet of `len` bytes that `cid` sent to `target_cid`, which is zero if the packet
+    /// is meant for this node. `is_message` is true for the first packet of each message. Returns
+    /// the verdict for the packet, along with the sample and action to report, if any
+    pub(crate) fn on_packet(
+        &self,
+        cid: u64,
+        target_cid: u64,
+        len: usize,
+        is_message: bool,
+    ) -> (AbuseVerdict, Option<(SessionRateSample, AbuseAction)>) {
+        self.on_packet_at(cid, target_cid, len as u64, is_message, Instant::now())
+    }
+
+    fn on_packet_at(
+        &self,
+        cid: u64,
+        target_cid: u64,
+        len: u64,
+        is_message: bool,
+        now: Instant,
+    ) -> (AbuseVerdict, Option<(SessionRateSample, AbuseAction)>) {
+        let mut sessions = self.sessions.lock();
+        let meter = sessions
+            .entry(cid)
+            .or_insert_with(|| SessionMeter::new(now));
+
+        // dropped messages are sampled too, such that a client ignoring its throttle may be
+        // escalated
+        meter.messages += is_message as u64;
+        meter.bytes += len;
+        if target_cid != 0 {
+            let _ = meter.peers.insert(target_cid);
+        }
+
+        let admitted = !is_message || meter.admit_message(now);
+        let verdict = if admitted {
+            AbuseVerdict::Accept
+        } else {
+            AbuseVerdict::Drop
+        };
+
+        let window = now.duration_since(meter.window_start);
+        if window < self.detector.sample_interval() {
+            return (verdict, None);
+        }
+
+        let secs = window.as_secs_f64();
+        let sample = SessionRateSample {
+            cid,
+            messages_per_second: meter.messages as f64 / secs,
+            bytes_per_second: meter.bytes as f64 / secs,
+            fanout: meter.peers.len(),
+            window,
+        };
+
+        meter.window_start = now;
+        meter.messages = 0;
+        meter.bytes = 0;
+        meter.peers.clear();
+        // the detector is foreign code, and thus must not run while the sessions are locked
+        std::mem::drop(sessions);
+
+        let action = self.detector.evaluate(&sample);
+        let verdict = match action {
+            AbuseAction::Allow | AbuseAction::Warn => verdict,
+
+            AbuseAction::Throttle {
+                max_messages_per_second,
+                duration,
+            } => {
+                if let Some(meter) = self.sessions.lock().get_mut(&cid) {
+                    meter.throttle = Some(Throttle {
+                        max_messages_per_second,
+                        until: now + duration,
+                        window_start: now,
+                        window_messages: 0,
+                    });
+                }
+
+                verdict
+            }
+
+            AbuseAction::Disconnect => AbuseVerdict::Disconnect,
+        };
+
+        let report = (action != AbuseAction::Allow).then_some((sample, action));
+        (verdict, report)
+    }
+
+    /// Stops sampling the session of `cid`. Called once the session ends
+    pub fn remove(&self, cid: u64) {
+        let _ = self.sessions.lock().remove(&cid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::abuse_detection::{
+        AbuseAction, AbuseMonitor, AbuseVerdict, SessionRateSample,
+    };
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_sampling_and_actions() {
+        // throttles clients sending more than 2 messages per second, and disconnects clients
+        // messaging more than 2 peers
+        let detector = |sample: &SessionRateSample| {
+            if sample.fanout > 2 {
+                AbuseAction::Disconnect
+            } else if sample.messages_per_second > 2.0 {
+                AbuseAction::Throttle {
+                    max_messages_per_second: 1,
+                    duration: Duration::from_secs(10),
+                }
+            } else {
+                AbuseAction::Allow
+            }
+        };
+
+        let monitor = AbuseMonitor::new(Arc::new(detector));
+        let start = Instant::now();
+        let second = Duration::from_secs(1);
+
+        for _ in 0..4 {
+            assert_eq!(
+                monitor.on_packet_at(1, 0, 100, true, start),
+                (AbuseVerdict::Accept, None)
+            );
+        }
+
+        // the window closes, throttling the client
+        let (verdict, report) = monitor.on_packet_at(1, 0, 100, true, start + second);
+        assert_eq!(verdict, AbuseVerdict::Accept);
+        let (sample, action) = report.unwrap();
+        assert_eq!(sample.messages_per_second, 5.0);
+        assert_eq!(sample.bytes_per_second, 500.0);
+        assert!(matches!(action, AbuseAction::Throttle { .. }));
+
+        let next = start + second + Duration::from_millis(1);
+        assert_eq!(
+            monitor.on_packet_at(1, 0, 100, true, next).0,
+            AbuseVerdict::Accept
+        );
+        assert_eq!(
+            monitor.on_packet_at(1, 0, 100, true, next).0,
+            AbuseVerdict::Drop
+        );
+        // packets besides messages are never throttled
+        assert_eq!(
+            monitor.on_packet_at(1, 0, 100, false, next).0,
+            AbuseVerdict::Accept
+        );
+        // other sessions are unaffected
+        assert_eq!(
+            monitor.on_packet_at(2, 0, 100, true, next).0,
+            AbuseVerdict::Accept
+        );
+
+        for peer in 10..13 {
+            let _ = monitor.on_packet_at(2, peer, 100, false, next);
+        }
+        let (verdict, report) = monitor.on_packet_at(2, 0, 100, false, next + second);
+        assert_eq!(verdict, AbuseVerdict::Disconnect);
+        assert_eq!(report.unwrap().0.fanout, 3);
+
+        monitor.remove(1);
+        assert_eq!(
+            monitor.on_packet_at(1, 0, 100, true, next),
+            (AbuseVerdict::Accept, None)
+        );
+    }
+}
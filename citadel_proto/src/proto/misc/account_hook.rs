@@ -0,0 +1,56 @@
+//! A hook notified as accounts are registered to, and deregistered from, a server
+//!
+//! Operators may provision external systems, such as billing or a directory of shadow accounts,
+//! as accounts come and go instead of polling the backend. Each event is delivered on a task of its
+//! own once the backend has committed the change, hence a slow hook delays neither the client nor
+//! the node. Events are thus delivered concurrently, and may arrive out of order
+use crate::error::NetworkError;
+use async_trait::async_trait;
+use citadel_user::misc::CNACMetadata;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+/// Invoked by a server once an account is registered or deregistered. Errors are logged, and never
+/// affect the outcome of the registration or deregistration
+#[async_trait]
+pub trait AccountHook: Send + Sync + 'static {
+    async fn on_register(&self, event: AccountEvent) -> Result<(), NetworkError> {
+        let _ = event;
+        Ok(())
+    }
+
+    async fn on_deregister(&self, event: AccountEvent) -> Result<(), NetworkError> {
+        let _ = event;
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+pub struct AccountEvent {
+    pub account: CNACMetadata,
+    /// The address of the client that registered or deregistered the account
+    pub remote_addr: SocketAddr,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub(crate) enum AccountEventKind {
+    Registered,
+    Deregistered,
+}
+
+/// Delivers `event` to `hook` on a task of its own
+pub(crate) fn dispatch(hook: Arc<dyn AccountHook>, kind: AccountEventKind, event: AccountEvent) {
+    let task = async move {
+        let cid = event.account.cid;
+        let res = match kind {
+            AccountEventKind::Registered => hook.on_register(event).await,
+            AccountEventKind::Deregistered => hook.on_deregister(event).await,
+        };
+
+        if let Err(err) = res {
+            log::warn!(target: "citadel", "Account hook failed for {kind:?} account {cid}: {err:?}");
+        }
+    };
+
+    spawn!(task);
+}
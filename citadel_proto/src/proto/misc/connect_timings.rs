@@ -0,0 +1,84 @@
+//! Per-stage timings of the connect pipeline, returned inside [`ConnectSuccess`](crate::prelude::ConnectSuccess)
+//!
+//! The [`ConnectTimer`] is started just before the transport is opened (or, for the accepting side,
+//! once the session is created), and laps once each stage completes. Each lap measures the time since
+//! the previous lap, so the stages add up to the total
+use std::time::{Duration, Instant};
+
+/// The time spent in each stage of establishing a connection. Stages that did not take place, such
+/// as registration for credentialed connects or UDP traversal with UDP disabled, are `None`
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ConnectTimings {
+    /// Opening the TCP connection and receiving the first packet of the server
+    pub transport: Option<Duration>,
+    /// The TLS or QUIC handshake. `None` for plain TCP connections
+    pub tls: Option<Duration>,
+    /// Registering with the server, for passwordless connects
+    pub register: Option<Duration>,
+    /// The pre-connect key exchange, up until the first ratchet is constructed
+    pub first_ratchet: Option<Duration>,
+    /// Traversing the NAT for the UDP channel
+    pub udp_traversal: Option<Duration>,
+    /// The remainder of the pipeline, including authentication, until the connection succeeded
+    pub connect: Option<Duration>,
+    /// The total time since the timer started
+    pub total: Duration,
+}
+
+/// A stage of the connect pipeline
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ConnectTimingStage {
+    Transport,
+    Tls,
+    Register,
+    FirstRatchet,
+    UdpTraversal,
+    Connect,
+}
+
+pub struct ConnectTimer {
+    start: Instant,
+    last_lap: Instant,
+    timings: ConnectTimings,
+}
+
+impl Default for ConnectTimer {
+    fn default() -> Self {
+        Self::starting_at(Instant::now())
+    }
+}
+
+impl ConnectTimer {
+    pub fn starting_at(start: Instant) -> Self {
+        Self {
+            start,
+            last_lap: start,
+            timings: ConnectTimings::default(),
+        }
+    }
+
+    /// Attributes the time since the previous lap to `stage`
+    pub fn lap(&mut self, stage: ConnectTimingStage) {
+        let now = Instant::now();
+        let elapsed = now.saturating_duration_since(self.last_lap);
+        self.last_lap = now;
+
+        let timing = match stage {
+            ConnectTimingStage::Transport => &mut self.timings.transport,
+            ConnectTimingStage::Tls => &mut self.timings.tls,
+            ConnectTimingStage::Register => &mut self.timings.register,
+            ConnectTimingStage::FirstRatchet => &mut self.timings.first_ratchet,
+            ConnectTimingStage::UdpTraversal => &mut self.timings.udp_traversal,
+            ConnectTimingStage::Connect => &mut self.timings.connect,
+        };
+
+        *timing = Some(timing.unwrap_or_default() + elapsed);
+    }
+
+    /// Laps the connect stage and returns the timings of every stage
+    pub fn finish(&mut self) -> ConnectTimings {
+        self.lap(ConnectTimingStage::Connect);
+        self.timings.total = self.last_lap.saturating_duration_since(self.start);
+        self.timings
+    }
+}
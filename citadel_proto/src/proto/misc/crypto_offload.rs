@@ -0,0 +1,158 @@
+//! Moves the key exchanges of unauthenticated handshakes off the async workers
+//!
+//! Encapsulating and decapsulating post-quantum keys takes long enough that a burst of
+//! registrations or connections computed inline stalls every other task scheduled onto the same
+//! workers, including the keep-alives of sessions that are already connected. Once offloaded, each
+//! key exchange runs on the blocking thread pool while holding a permit of the node-wide budget,
+//! such that a handshake storm can neither starve the reactor nor flood the blocking pool. The
+//! [`CryptoOffloadMetrics`] reveal whether the budget suffices for the observed load
+use crate::error::NetworkError;
+use citadel_user::server_misc_settings::CryptoOffloadSettings;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+/// A snapshot of the key exchanges computed by the node
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct CryptoOffloadMetrics {
+    /// The number of key exchanges waiting for a permit
+    pub queued: u64,
+    /// The number of key exchanges being computed
+    pub running: u64,
+    /// The number of key exchanges computed since the node started
+    pub completed: u64,
+    /// The total time key exchanges waited for a permit since the node started
+    pub total_queue_wait: Duration,
+    /// The longest time a single key exchange waited for a permit
+    pub max_queue_wait: Duration,
+}
+
+impl CryptoOffloadMetrics {
+    /// The average time a completed key exchange waited for a permit
+    pub fn average_queue_wait(&self) -> Duration {
+        if self.completed == 0 {
+            Duration::ZERO
+        } else {
+            Duration::from_nanos((self.total_queue_wait.as_nanos() / self.completed as u128) as u64)
+        }
+    }
+}
+
+pub struct CryptoOffload {
+    /// If `None`, key exchanges are computed inline
+    permits: Option<Semaphore>,
+    queued: AtomicU64,
+    running: AtomicU64,
+    completed: AtomicU64,
+    total_queue_wait_ns: AtomicU64,
+    max_queue_wait_ns: AtomicU64,
+}
+
+impl CryptoOffload {
+    pub fn new(settings: Option<&CryptoOffloadSettings>) -> Self {
+        Self {
+            permits: settings.map(|settings| Semaphore::new(settings.max_concurrency.max(1))),
+            queued: AtomicU64::new(0),
+            running: AtomicU64::new(0),
+            completed: AtomicU64::new(0),
+            total_queue_wait_ns: AtomicU64::new(0),
+            max_queue_wait_ns: AtomicU64::new(0),
+        }
+    }
+
+    /// Computes `task` on the blocking thread pool once a permit is available, or inline if
+    /// offloading is disabled
+    pub async fn run<T: Send + 'static>(
+        &self,
+        task: impl FnOnce() -> T + Send + 'static,
+    ) -> Result<T, NetworkError> {
+        let permits = match self.permits.as_ref() {
+            Some(permits) => permits,
+            None => {
+                let _ = self.running.fetch_add(1, Ordering::Relaxed);
+                let output = task();
+                self.on_completed(Duration::ZERO);
+                return Ok(output);
+            }
+        };
+
+        let queued_at = Instant::now();
+        let _ = self.queued.fetch_add(1, Ordering::Relaxed);
+        // the semaphore is never closed
+        let permit = permits.acquire().await;
+        let _ = self.queued.fetch_sub(1, Ordering::Relaxed);
+        let waited = queued_at.elapsed();
+        let _ = self.running.fetch_add(1, Ordering::Relaxed);
+
+        let output = citadel_io::spawn_blocking(task).await;
+        std::mem::drop(permit);
+        self.on_completed(waited);
+        output.map_err(|err| NetworkError::Generic(err.message))
+    }
+
+    fn on_completed(&self, waited: Duration) {
+        let waited = waited.as_nanos() as u64;
+        let _ = self.running.fetch_sub(1, Ordering::Relaxed);
+        let _ = self.completed.fetch_add(1, Ordering::Relaxed);
+        let _ = self
+            .total_queue_wait_ns
+            .fetch_add(waited, Ordering::Relaxed);
+        let _ = self.max_queue_wait_ns.fetch_max(waited, Ordering::Relaxed);
+    }
+
+    pub fn metrics(&self) -> CryptoOffloadMetrics {
+        CryptoOffloadMetrics {
+            queued: self.queued.load(Ordering::Relaxed),
+            running: self.running.load(Ordering::Relaxed),
+            completed: self.completed.load(Ordering::Relaxed),
+            total_queue_wait: Duration::from_nanos(
+                self.total_queue_wait_ns.load(Ordering::Relaxed),
+            ),
+            max_queue_wait: Duration::from_nanos(self.max_queue_wait_ns.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::crypto_offload::CryptoOffload;
+    use citadel_user::server_misc_settings::CryptoOffloadSettings;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_offload_bounds_concurrency_and_tracks_queue() {
+        let offload = &CryptoOffload::new(Some(&CryptoOffloadSettings { max_concurrency: 2 }));
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let tasks = (0..8).map(|idx| {
+            let (in_flight, peak) = (in_flight.clone(), peak.clone());
+            offload.run(move || {
+                let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                let _ = peak.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(Duration::from_millis(20));
+                let _ = in_flight.fetch_sub(1, Ordering::SeqCst);
+                idx
+            })
+        });
+
+        let outputs = futures::future::try_join_all(tasks).await.unwrap();
+        assert_eq!(outputs, (0..8).collect::<Vec<_>>());
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+
+        let metrics = offload.metrics();
+        assert_eq!(metrics.completed, 8);
+        assert_eq!(metrics.queued, 0);
+        assert_eq!(metrics.running, 0);
+        // the last pair of tasks waited for the three pairs before them
+        assert!(metrics.max_queue_wait >= Duration::from_millis(40));
+        assert!(metrics.average_queue_wait() <= metrics.max_queue_wait);
+
+        let inline = CryptoOffload::new(None);
+        assert_eq!(inline.run(|| 1).await.unwrap(), 1);
+        assert_eq!(inline.metrics().completed, 1);
+        assert_eq!(inline.metrics().max_queue_wait, Duration::ZERO);
+    }
+}
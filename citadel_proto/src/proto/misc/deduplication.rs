@@ -0,0 +1,67 @@
+//! Counts the chunks of deduplicated transfers that the receiver already held in its chunk cache
+//!
+//! Before a deduplicated transfer, the receiver reports which chunks of the object it holds. Each
+//! reported chunk is a cache hit, and is sent as its hash alone. The [`DeduplicationMetrics`]
+//! reveal how much of the traffic of the node the chunk caches of its peers spared
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A snapshot of the chunks of the deduplicated transfers sent by the node
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct DeduplicationMetrics {
+    /// The number of deduplicated transfers whose chunks were queried
+    pub transfers: u64,
+    /// The number of chunks queried
+    pub chunks: u64,
+    /// The number of queried chunks the receiver held in its cache
+    pub cached_chunks: u64,
+}
+
+/// The node-wide sums behind [`DeduplicationMetrics`]
+#[derive(Default)]
+pub struct DeduplicationCounters {
+    transfers: AtomicU64,
+    chunks: AtomicU64,
+    cached_chunks: AtomicU64,
+}
+
+impl DeduplicationCounters {
+    /// Adds the chunks of a transfer, given whether the receiver reported each as cached
+    pub fn record(&self, cached: &[bool]) {
+        let hits = cached.iter().filter(|cached| **cached).count() as u64;
+        let _ = self.transfers.fetch_add(1, Ordering::Relaxed);
+        let _ = self
+            .chunks
+            .fetch_add(cached.len() as u64, Ordering::Relaxed);
+        let _ = self.cached_chunks.fetch_add(hits, Ordering::Relaxed);
+    }
+
+    pub fn metrics(&self) -> DeduplicationMetrics {
+        DeduplicationMetrics {
+            transfers: self.transfers.load(Ordering::Relaxed),
+            chunks: self.chunks.load(Ordering::Relaxed),
+            cached_chunks: self.cached_chunks.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::deduplication::{DeduplicationCounters, DeduplicationMetrics};
+
+    #[test]
+    fn test_counters_sum_cache_hits() {
+        let counters = DeduplicationCounters::default();
+        assert_eq!(counters.metrics(), DeduplicationMetrics::default());
+
+        counters.record(&[false, false, false]);
+        counters.record(&[true, false, true]);
+        assert_eq!(
+            counters.metrics(),
+            DeduplicationMetrics {
+                transfers: 2,
+                chunks: 6,
+                cached_chunks: 2,
+            }
+        );
+    }
+}
@@ -0,0 +1,281 @@
+//! Receiver-advertised flow control for the ordered channel of each virtual connection
+//!
+//! Messages delivered to a [`PeerChannel`](crate::proto::peer::channel::PeerChannel) are buffered
+//! until the application reads them. Without flow control, a producer outpacing its consumer grows
+//! the consumer's buffer without bound. Instead, each receiver advertises a window alongside the
+//! total number of bytes its application has read, and the sender suspends once the bytes it sent
+//! beyond those read would exceed the window. The receiver first advertises once the first message
+//! arrives, and again each time its application reads a quarter of the window. Since both counters
+//! are cumulative, advertisements may arrive out of order or be lost without desynchronizing the
+//! endpoints. A receiver that never advertises, e.g., one predating flow control, never suspends
+//! the sender
+use crate::proto::state_container::VirtualConnectionType;
+use citadel_io::Mutex;
+use serde::{Deserialize, Serialize};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use tokio::sync::Notify;
+
+/// Sent by the receiver of a channel to grant the sender more room
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct WindowUpdate {
+    /// The maximum number of bytes the sender may have in flight
+    pub window: u64,
+    /// The total number of bytes the receiver's application has read
+    pub consumed: u64,
+}
+
+#[derive(Default)]
+struct SendWindowState {
+    sent: u64,
+    consumed: u64,
+    /// `None` until the receiver first advertises
+    window: Option<u64>,
+    closed: bool,
+}
+
+impl SendWindowState {
+    fn admits(&self, len: u64) -> bool {
+        let in_flight = self.sent.saturating_sub(self.consumed);
+        match self.window {
+            // a message larger than the window is admitted once nothing else is in flight
+            Some(window) => self.closed || in_flight == 0 || in_flight + len <= window,
+            None => true,
+        }
+    }
+}
+
+/// The room the receiver granted the local sender
+#[derive(Default)]
+pub struct SendWindow {
+    state: Mutex<SendWindowState>,
+    notify: Notify,
+}
+
+impl SendWindow {
+    /// Waits until the receiver has room for another `len` bytes, then counts them as in flight
+    pub async fn reserve(&self, len: u64) {
+        loop {
+            // registered before checking, such that an update arriving in between is not missed
+            let notified = self.notify.notified();
+            {
+                let mut state = self.state.lock();
+                if state.admits(len) {
+                    state.sent += len;
+                    return;
+                }
+            }
+
+            notified.await;
+        }
+    }
+
+    /// Counts `len` bytes as in flight if the receiver has room for them without waiting
+    pub fn try_reserve(&self, len: u64) -> bool {
+        let mut state = self.state.lock();
+        if state.admits(len) {
+            state.sent += len;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns the room reserved for a message that was never sent
+    pub(crate) fn unreserve(&self, len: u64) {
+        {
+            let mut state = self.state.lock();
+            state.sent = state.sent.saturating_sub(len);
+        }
+
+        self.notify.notify_waiters();
+    }
+
+    pub(crate) fn on_window_update(&self, update: WindowUpdate) {
+        {
+            let mut state = self.state.lock();
+            state.window = Some(update.window);
+            state.consumed = state.consumed.max(update.consumed);
+        }
+
+        self.notify.notify_waiters();
+    }
+
+    /// Releases every suspended sender once the virtual connection ends
+    pub(crate) fn close(&self) {
+        self.state.lock().closed = true;
+        self.notify.notify_waiters();
+    }
+
+    /// The number of bytes sent that the receiver's application has yet to read
+    pub fn in_flight(&self) -> u64 {
+        let state = self.state.lock();
+        state.sent.saturating_sub(state.consumed)
+    }
+}
+
+/// The room the local receiver grants the remote sender
+pub struct ReceiveWindow {
+    window: u64,
+    consumed: AtomicU64,
+    advertised: AtomicU64,
+    announced: AtomicBool,
+}
+
+impl ReceiveWindow {
+    pub fn new(window: u64) -> Self {
+        Self {
+            window: window.max(1),
+            consumed: AtomicU64::new(0),
+            advertised: AtomicU64::new(0),
+            announced: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns the initial advertisement once the first message arrives, and `None` thereafter
+    pub(crate) fn announce(&self) -> Option<WindowUpdate> {
+        if self.announced.swap(true, Ordering::Relaxed) {
+            None
+        } else {
+            Some(self.update())
+        }
+    }
+
+    /// Records that the application read `len` bytes. Returns an advertisement each time the
+    /// application reads another quarter of the window
+    pub(crate) fn on_consumed(&self, len: u64) -> Option<WindowUpdate> {
+        let consumed = self.consumed.fetch_add(len, Ordering::Relaxed) + len;
+        let advertised = self.advertised.load(Ordering::Relaxed);
+        if consumed.saturating_sub(advertised) < self.window / 4 {
+            return None;
+        }
+
+        self.advertised
+            .compare_exchange(advertised, consumed, Ordering::Relaxed, Ordering::Relaxed)
+            .ok()
+            .map(|_| WindowUpdate {
+                window: self.window,
+                consumed,
+            })
+    }
+
+    /// Called if an advertisement could not be sent, such that the next read advertises again
+    pub(crate) fn on_advertisement_failed(&self, update: WindowUpdate) {
+        let _ = self.advertised.fetch_min(
+            update.consumed.saturating_sub(self.window / 4),
+            Ordering::Relaxed,
+        );
+    }
+
+    fn update(&self) -> WindowUpdate {
+        WindowUpdate {
+            window: self.window,
+            consumed: self.consumed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The flow control of a single virtual connection, held by the state container. Suspended
+/// senders are released once dropped
+pub(crate) struct FlowControl {
+    pub(crate) v_target: VirtualConnectionType,
+    pub(crate) send: Arc<SendWindow>,
+    pub(crate) receive: Arc<ReceiveWindow>,
+}
+
+impl FlowControl {
+    pub(crate) fn new(v_target: VirtualConnectionType, window: u64) -> Self {
+        Self {
+            v_target,
+            send: Arc::new(SendWindow::default()),
+            receive: Arc::new(ReceiveWindow::new(window)),
+        }
+    }
+}
+
+impl Drop for FlowControl {
+    fn drop(&mut self) {
+        self.send.close();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::flow_control::{ReceiveWindow, SendWindow, WindowUpdate};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_sender_suspends_until_receiver_consumes() {
+        let sender = Arc::new(SendWindow::default());
+        let receiver = ReceiveWindow::new(100);
+
+        // no advertisement yet, hence the sender is never suspended
+        sender.reserve(60).await;
+        let announcement = receiver.announce().unwrap();
+        assert_eq!(
+            announcement,
+            WindowUpdate {
+                window: 100,
+                consumed: 0
+            }
+        );
+        assert!(receiver.announce().is_none());
+        sender.on_window_update(announcement);
+
+        sender.reserve(40).await;
+        assert_eq!(sender.in_flight(), 100);
+
+        let suspended = tokio::spawn({
+            let sender = sender.clone();
+            async move { sender.reserve(10).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!suspended.is_finished());
+
+        assert!(receiver.on_consumed(10).is_none());
+        let update = receiver.on_consumed(20).unwrap();
+        assert_eq!(update.consumed, 30);
+        // stale advertisements never shrink the room granted
+        sender.on_window_update(update);
+        sender.on_window_update(announcement);
+        suspended.await.unwrap();
+        assert_eq!(sender.in_flight(), 80);
+
+        // a lost advertisement is retried on the next read
+        let lost = receiver.on_consumed(30).unwrap();
+        receiver.on_advertisement_failed(lost);
+        assert_eq!(receiver.on_consumed(1).unwrap().consumed, 61);
+
+        // a message larger than the window passes once nothing is in flight
+        sender.on_window_update(WindowUpdate {
+            window: 100,
+            consumed: 110,
+        });
+        sender.reserve(500).await;
+
+        let suspended = tokio::spawn({
+            let sender = sender.clone();
+            async move { sender.reserve(10).await }
+        });
+        sender.close();
+        suspended.await.unwrap();
+    }
+
+    #[test]
+    fn test_try_reserve_does_not_wait() {
+        let sender = SendWindow::default();
+        sender.on_window_update(WindowUpdate {
+            window: 100,
+            consumed: 0,
+        });
+
+        assert!(sender.try_reserve(70));
+        assert!(!sender.try_reserve(40));
+        assert_eq!(sender.in_flight(), 70);
+
+        sender.unreserve(70);
+        assert!(sender.try_reserve(100));
+        assert_eq!(sender.in_flight(), 100);
+    }
+}
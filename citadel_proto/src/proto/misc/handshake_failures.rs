@@ -0,0 +1,127 @@
+//! Accounting for inbound handshakes that failed before a session was established
+//!
+//! Each failure is reported to the kernel of the server via [`NodeResult::HandshakeFailed`],
+//! carrying the addr of the client along with the reason, and is counted towards the node-wide
+//! [`HandshakeFailureCounts`]. Failures occurring within the listeners, before any session exists,
+//! travel to the listener loop inside the [`std::io::Error`] of the failed stream
+//!
+//! [`NodeResult::HandshakeFailed`]: crate::prelude::NodeResult::HandshakeFailed
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum HandshakeFailureReason {
+    /// The TLS handshake failed, for instance due to an alert sent by the client
+    Tls,
+    /// The QUIC handshake failed, or the client closed the connection before opening its stream
+    Quic,
+    /// The protocol version of the client is incompatible with the version of this node
+    ProtocolVersion,
+    /// The client is unregistered, or its credentials were rejected
+    Authentication,
+    /// The client exceeded the connections it may hold concurrently
+    Quota,
+}
+
+/// The number of inbound handshakes that failed for each reason since the node started
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct HandshakeFailureCounts {
+    pub tls: u64,
+    pub quic: u64,
+    pub protocol_version: u64,
+    pub authentication: u64,
+    pub quota: u64,
+}
+
+#[derive(Default)]
+pub struct HandshakeFailureTracker {
+    tls: AtomicU64,
+    quic: AtomicU64,
+    protocol_version: AtomicU64,
+    authentication: AtomicU64,
+    quota: AtomicU64,
+}
+
+impl HandshakeFailureTracker {
+    pub fn record(&self, reason: HandshakeFailureReason) {
+        let counter = match reason {
+            HandshakeFailureReason::Tls => &self.tls,
+            HandshakeFailureReason::Quic => &self.quic,
+            HandshakeFailureReason::ProtocolVersion => &self.protocol_version,
+            HandshakeFailureReason::Authentication => &self.authentication,
+            HandshakeFailureReason::Quota => &self.quota,
+        };
+
+        let _ = counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn counts(&self) -> HandshakeFailureCounts {
+        HandshakeFailureCounts {
+            tls: self.tls.load(Ordering::Relaxed),
+            quic: self.quic.load(Ordering::Relaxed),
+            protocol_version: self.protocol_version.load(Ordering::Relaxed),
+            authentication: self.authentication.load(Ordering::Relaxed),
+            quota: self.quota.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A stream the listeners dropped since its handshake failed
+#[derive(Debug)]
+pub(crate) struct InboundHandshakeError {
+    pub peer_addr: SocketAddr,
+    pub reason: HandshakeFailureReason,
+    pub message: String,
+}
+
+impl Display for InboundHandshakeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:?} handshake with {} failed: {}",
+            self.reason, self.peer_addr, self.message
+        )
+    }
+}
+
+impl std::error::Error for InboundHandshakeError {}
+
+impl InboundHandshakeError {
+    pub(crate) fn into_io(self) -> io::Error {
+        io::Error::new(io::ErrorKind::ConnectionAborted, self)
+    }
+
+    /// Returns the failed handshake carried by `err`, if `err` was created by [`Self::into_io`]
+    pub(crate) fn from_io(err: &io::Error) -> Option<&Self> {
+        err.get_ref()?.downcast_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::handshake_failures::{
+        HandshakeFailureReason, HandshakeFailureTracker, InboundHandshakeError,
+    };
+
+    #[test]
+    fn test_record_and_carry() {
+        let tracker = HandshakeFailureTracker::default();
+        tracker.record(HandshakeFailureReason::Tls);
+        tracker.record(HandshakeFailureReason::Tls);
+        tracker.record(HandshakeFailureReason::Quota);
+        let counts = tracker.counts();
+        assert_eq!((counts.tls, counts.quota, counts.quic), (2, 1, 0));
+
+        let err = InboundHandshakeError {
+            peer_addr: "127.0.0.1:25000".parse().unwrap(),
+            reason: HandshakeFailureReason::Tls,
+            message: "received fatal alert: UnknownCA".to_string(),
+        }
+        .into_io();
+        let carried = InboundHandshakeError::from_io(&err).unwrap();
+        assert_eq!(carried.reason, HandshakeFailureReason::Tls);
+        assert!(InboundHandshakeError::from_io(&std::io::Error::other("other")).is_none());
+    }
+}
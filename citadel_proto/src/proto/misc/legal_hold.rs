@@ -0,0 +1,124 @@
+//! Archives the messages of accounts under legal hold
+//!
+//! Once [`LegalHoldSettings`] are configured, the metadata of every message sent or received by
+//! an account under hold is archived, whether the message is sent to the node itself or relayed
+//! between peers. Messages between peers are end-to-end encrypted, hence only their metadata is
+//! available to the node; plaintext is archived only for messages sent to the node, and only if
+//! enabled, in which case it is encrypted at rest under a key derived from the seal key. Records are handed to a single writer task, which appends them to the sealed archive
+//! of each account under hold in the order they were handled
+use citadel_user::external_services::LegalHoldNotice;
+use citadel_user::server_misc_settings::LegalHoldSettings;
+use std::collections::HashSet;
+use tokio::sync::mpsc::UnboundedSender;
+
+// a message to be appended to the archive of `cid`
+struct HeldMessage {
+    cid: u64,
+    timestamp: i64,
+    sender: u64,
+    recipient: u64,
+    len: u64,
+    plaintext: Option<Vec<u8>>,
+}
+
+pub struct LegalHold {
+    accounts: HashSet<u64>,
+    archive_plaintext: bool,
+    notify_clients: bool,
+    tx: UnboundedSender<HeldMessage>,
+}
+
+impl LegalHold {
+    /// Starts the writer task of the archives
+    pub fn new(settings: &LegalHoldSettings) -> Self {
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        spawn_writer(settings, rx);
+        Self {
+            accounts: settings.accounts.clone(),
+            archive_plaintext: settings.archive_plaintext,
+            notify_clients: settings.notify_clients,
+            tx,
+        }
+    }
+
+    pub fn is_held(&self, cid: u64) -> bool {
+        self.accounts.contains(&cid)
+    }
+
+    /// Returns the notice sent to the client of `cid` once connected, if any
+    pub fn notice(&self, cid: u64) -> Option<LegalHoldNotice> {
+        (self.notify_clients && self.is_held(cid)).then_some(LegalHoldNotice {
+            archives_plaintext: self.archive_plaintext,
+        })
+    }
+
+    /// Archives a message handled by the node if either party is under hold. `plaintext` is only
+    /// archived if enabled
+    pub fn record(
+        &self,
+        timestamp: i64,
+        sender: u64,
+        recipient: u64,
+        len: usize,
+        plaintext: Option<&[u8]>,
+    ) {
+        for cid in [sender, recipient] {
+            // a message sent by a held account to itself is archived once
+            if !self.is_held(cid) || (cid == recipient && sender == recipient) {
+                continue;
+            }
+
+            let message = HeldMessage {
+                cid,
+                timestamp,
+                sender,
+                recipient,
+                len: len as u64,
+                plaintext: plaintext
+                    .filter(|_| self.archive_plaintext)
+                    .map(|plaintext| plaintext.to_vec()),
+            };
+
+            if self.tx.send(message).is_err() {
+                log::error!(target: "citadel", "The legal hold archive of {cid} is unavailable");
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "filesystem", not(target_family = "wasm")))]
+fn spawn_writer(
+    settings: &LegalHoldSettings,
+    mut rx: tokio::sync::mpsc::UnboundedReceiver<HeldMessage>,
+) {
+    let mut archive = citadel_user::legal_hold::LegalHoldArchive::new(
+        settings.directory.clone(),
+        settings.seal_key.clone(),
+    );
+    let task = async move {
+        while let Some(message) = rx.recv().await {
+            let cid = message.cid;
+            let record = citadel_user::legal_hold::ArchiveRecord {
+                timestamp: message.timestamp,
+                sender: message.sender,
+                recipient: message.recipient,
+                len: message.len,
+                plaintext: message.plaintext,
+            };
+
+            if let Err(err) = archive.append(cid, record).await {
+                log::error!(target: "citadel", "Unable to append to the legal hold archive of {cid}: {err:?}");
+            }
+        }
+    };
+
+    spawn!(task);
+}
+
+#[cfg(not(all(feature = "filesystem", not(target_family = "wasm"))))]
+fn spawn_writer(
+    _settings: &LegalHoldSettings,
+    _rx: tokio::sync::mpsc::UnboundedReceiver<HeldMessage>,
+) {
+    log::error!(target: "citadel", "Legal hold requires the filesystem feature. No messages will be archived");
+}
@@ -7,17 +7,40 @@ use tokio::io::{AsyncRead, AsyncWrite};
 use tokio_stream::StreamExt;
 use tokio_util::codec::LengthDelimitedCodec;
 
+pub mod abuse_detection;
+pub mod account_hook;
 pub mod clean_shutdown;
+pub mod connect_timings;
+pub mod crypto_offload;
+pub mod deduplication;
 pub mod dual_cell;
 pub mod dual_late_init;
 pub mod dual_rwlock;
+pub mod flow_control;
+pub mod handshake_failures;
+pub mod legal_hold;
 pub mod lock_holder;
+pub mod multipath;
 pub mod net;
 pub mod ordered_channel;
+pub mod packet_filter;
 pub mod panic_future;
+pub mod processing_budget;
+pub mod protocol_capabilities;
+pub mod provisional_reaper;
+pub mod relay;
+pub mod resource_counters;
+pub mod scheduler;
+pub mod security_downgrade;
+pub mod security_info;
+pub mod session_affinity;
 pub mod session_security_settings;
+pub mod sharded_map;
+pub mod stats_history;
 pub mod udp_internal_interface;
+pub mod udp_keep_alive;
 pub mod underlying_proto;
+pub mod watchdog;
 
 pub async fn read_one_packet_as_framed<S: AsyncRead + Unpin, D: DeserializeOwned + Serialize>(
     io: S,
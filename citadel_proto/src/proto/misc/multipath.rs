@@ -0,0 +1,370 @@
+//! Striping of file transfers across both the primary stream and the UDP stream
+//!
+//! When a UDP path to the adjacent node exists, a share of each group's payload packets are sent
+//! through it alongside the primary (TCP) stream. Since the primary stream is reliable, every packet
+//! entrusted to UDP is retained until the wave it belongs to is acknowledged. If it is presumed lost,
+//! it is retransmitted through the primary stream, and the share of packets sent through UDP shrinks.
+//! The receiver reassembles the group the same way regardless of which path a packet took. The
+//! [`MultipathMetrics`] of the node reveal how the packets of its transfers were split across paths
+use bytes::BytesMut;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+/// Objects smaller than this are always sent exclusively through the primary stream
+pub const MIN_MULTIPATH_OBJECT_LEN: usize = 1024 * 1024;
+/// Packets larger than this are never striped, since they risk fragmentation along the UDP path
+pub const MAX_STRIPED_PACKET_LEN: usize = 1024;
+/// If no wave is acknowledged within this duration, every unacknowledged UDP packet of the group
+/// is presumed lost
+pub const MULTIPATH_LOSS_TIMEOUT: Duration = Duration::from_millis(750);
+
+const INITIAL_UDP_SHARE: f32 = 0.25;
+const MAX_UDP_SHARE: f32 = 0.75;
+const MIN_UDP_SHARE: f32 = 0.03;
+const UDP_SHARE_INCREASE: f32 = 0.05;
+/// A wave is presumed lost once a wave sent this many waves later has been acknowledged
+const REORDER_THRESHOLD: u32 = 3;
+
+/// Congestion control for the UDP path of a multipath transfer. The primary stream regulates itself,
+/// so only the share of packets entrusted to UDP is controlled here: the share grows additively each
+/// time a wave containing UDP packets is acknowledged, and is halved each time a loss is detected.
+/// Once the share decays below the minimum, UDP is abandoned for the remainder of the transfer
+#[derive(Debug)]
+pub struct MultipathCongestion {
+    udp_share: f32,
+    credit: f32,
+    abandoned: bool,
+    packets_via_primary: u64,
+    packets_via_udp: u64,
+    packets_retransmitted: u64,
+}
+
+impl Default for MultipathCongestion {
+    fn default() -> Self {
+        Self {
+            udp_share: INITIAL_UDP_SHARE,
+            credit: 0f32,
+            abandoned: false,
+            packets_via_primary: 0,
+            packets_via_udp: 0,
+            packets_retransmitted: 0,
+        }
+    }
+}
+
+impl MultipathCongestion {
+    /// Returns true if the next packet should be sent through the UDP path. Packets are
+    /// interleaved evenly across both paths according to the current share
+    pub fn next_is_udp(&mut self) -> bool {
+        if self.abandoned {
+            return false;
+        }
+
+        self.credit += self.udp_share;
+        if self.credit >= 1f32 {
+            self.credit -= 1f32;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn on_sent_via_primary(&mut self) {
+        self.packets_via_primary += 1;
+    }
+
+    pub fn on_sent_via_udp(&mut self) {
+        self.packets_via_udp += 1;
+    }
+
+    pub fn on_udp_delivered(&mut self) {
+        if self.abandoned {
+            return;
+        }
+
+        self.udp_share = (self.udp_share + UDP_SHARE_INCREASE).min(MAX_UDP_SHARE);
+    }
+
+    pub fn on_udp_loss(&mut self, packets_retransmitted: usize) {
+        self.packets_retransmitted += packets_retransmitted as u64;
+        self.udp_share /= 2f32;
+        if self.udp_share < MIN_UDP_SHARE {
+            log::warn!(target: "citadel", "UDP path is too lossy; abandoning it for the remainder of the transfer");
+            self.abandon();
+        }
+    }
+
+    /// Stops striping packets across the UDP path
+    pub fn abandon(&mut self) {
+        self.abandoned = true;
+        self.udp_share = 0f32;
+    }
+
+    pub fn udp_share(&self) -> f32 {
+        self.udp_share
+    }
+
+    /// Returns the packets of this transfer sent through each path
+    pub fn metrics(&self) -> MultipathMetrics {
+        MultipathMetrics {
+            transfers: 1,
+            packets_via_primary: self.packets_via_primary,
+            packets_via_udp: self.packets_via_udp,
+            packets_retransmitted: self.packets_retransmitted,
+        }
+    }
+}
+
+/// A snapshot of the payload packets of striped groups, summed over the multipath transfers
+/// completed by the node
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct MultipathMetrics {
+    /// The number of completed transfers eligible for striping
+    pub transfers: u64,
+    /// The number of packets sent through the primary stream, excluding retransmissions
+    pub packets_via_primary: u64,
+    /// The number of packets sent through the UDP stream
+    pub packets_via_udp: u64,
+    /// The number of packets sent through the UDP stream that were presumed lost, and thus
+    /// retransmitted through the primary stream
+    pub packets_retransmitted: u64,
+}
+
+/// The node-wide sums behind [`MultipathMetrics`]
+#[derive(Default)]
+pub struct MultipathCounters {
+    transfers: AtomicU64,
+    packets_via_primary: AtomicU64,
+    packets_via_udp: AtomicU64,
+    packets_retransmitted: AtomicU64,
+}
+
+impl MultipathCounters {
+    /// Adds the packets of a completed transfer
+    pub fn record(&self, congestion: &MultipathCongestion) {
+        let metrics = congestion.metrics();
+        let _ = self
+            .transfers
+            .fetch_add(metrics.transfers, Ordering::Relaxed);
+        let _ = self
+            .packets_via_primary
+            .fetch_add(metrics.packets_via_primary, Ordering::Relaxed);
+        let _ = self
+            .packets_via_udp
+            .fetch_add(metrics.packets_via_udp, Ordering::Relaxed);
+        let _ = self
+            .packets_retransmitted
+            .fetch_add(metrics.packets_retransmitted, Ordering::Relaxed);
+    }
+
+    pub fn metrics(&self) -> MultipathMetrics {
+        MultipathMetrics {
+            transfers: self.transfers.load(Ordering::Relaxed),
+            packets_via_primary: self.packets_via_primary.load(Ordering::Relaxed),
+            packets_via_udp: self.packets_via_udp.load(Ordering::Relaxed),
+            packets_retransmitted: self.packets_retransmitted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The UDP packets of a group that have not yet been acknowledged, keyed by wave
+pub struct StripedGroup {
+    in_flight: HashMap<u32, Vec<BytesMut>>,
+    last_progress: Instant,
+}
+
+impl Default for StripedGroup {
+    fn default() -> Self {
+        Self {
+            in_flight: HashMap::new(),
+            last_progress: Instant::now(),
+        }
+    }
+}
+
+impl StripedGroup {
+    pub fn on_sent(&mut self, wave_id: u32, packet: BytesMut) {
+        self.in_flight.entry(wave_id).or_default().push(packet);
+    }
+
+    /// Returns whether the acknowledged wave contained UDP packets, along with the UDP packets of
+    /// earlier waves that are presumed lost since they were overtaken
+    pub fn on_wave_ack(&mut self, wave_id: u32) -> (bool, Vec<BytesMut>) {
+        self.last_progress = Instant::now();
+        let delivered = self.in_flight.remove(&wave_id).is_some();
+        let overtaken = self
+            .in_flight
+            .keys()
+            .copied()
+            .filter(|wave| wave.saturating_add(REORDER_THRESHOLD) <= wave_id)
+            .collect::<Vec<_>>();
+
+        let lost = overtaken
+            .into_iter()
+            .flat_map(|wave| self.in_flight.remove(&wave).unwrap_or_default())
+            .collect();
+
+        (delivered, lost)
+    }
+
+    /// Takes every packet still in flight if no wave was acknowledged within [`MULTIPATH_LOSS_TIMEOUT`]
+    pub fn take_stalled(&mut self) -> Option<Vec<BytesMut>> {
+        if self.in_flight.is_empty() || self.last_progress.elapsed() < MULTIPATH_LOSS_TIMEOUT {
+            return None;
+        }
+
+        self.last_progress = Instant::now();
+        Some(
+            self.in_flight
+                .drain()
+                .flat_map(|(_, packets)| packets)
+                .collect(),
+        )
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::multipath::{
+        MultipathCongestion, MultipathCounters, MultipathMetrics, StripedGroup, INITIAL_UDP_SHARE,
+        MAX_UDP_SHARE, MULTIPATH_LOSS_TIMEOUT,
+    };
+    use bytes::BytesMut;
+    use std::time::Instant;
+
+    fn send(congestion: &mut MultipathCongestion, packets: usize) -> Vec<bool> {
+        (0..packets)
+            .map(|_| {
+                let via_udp = congestion.next_is_udp();
+                if via_udp {
+                    congestion.on_sent_via_udp();
+                } else {
+                    congestion.on_sent_via_primary();
+                }
+                via_udp
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_packets_are_interleaved_by_share() {
+        let mut congestion = MultipathCongestion::default();
+        let paths = send(&mut congestion, 100);
+
+        // a quarter of the packets are sent via UDP, never two in a row
+        let expected = (100f32 * INITIAL_UDP_SHARE) as usize;
+        assert_eq!(paths.iter().filter(|via_udp| **via_udp).count(), expected);
+        assert!(paths.windows(2).all(|pair| !(pair[0] && pair[1])));
+
+        let metrics = congestion.metrics();
+        assert_eq!(metrics.packets_via_primary, 100 - expected as u64);
+        assert_eq!(metrics.packets_via_udp, expected as u64);
+        assert_eq!(metrics.packets_retransmitted, 0);
+    }
+
+    #[test]
+    fn test_share_grows_additively_and_halves_on_loss() {
+        let mut congestion = MultipathCongestion::default();
+        for _ in 0..100 {
+            congestion.on_udp_delivered();
+        }
+        assert_eq!(congestion.udp_share(), MAX_UDP_SHARE);
+        let paths = send(&mut congestion, 100);
+        assert_eq!(paths.iter().filter(|via_udp| **via_udp).count(), 75);
+
+        congestion.on_udp_loss(3);
+        assert_eq!(congestion.udp_share(), MAX_UDP_SHARE / 2f32);
+
+        // repeated losses abandon the UDP path entirely
+        for _ in 0..4 {
+            congestion.on_udp_loss(1);
+        }
+        assert_eq!(congestion.udp_share(), 0f32);
+        assert!(send(&mut congestion, 100).iter().all(|via_udp| !via_udp));
+        // abandoning is permanent for the transfer
+        congestion.on_udp_delivered();
+        assert_eq!(congestion.udp_share(), 0f32);
+        assert!(!congestion.next_is_udp());
+
+        let metrics = congestion.metrics();
+        assert_eq!(metrics.packets_via_primary, 125);
+        assert_eq!(metrics.packets_via_udp, 75);
+        assert_eq!(metrics.packets_retransmitted, 7);
+    }
+
+    #[test]
+    fn test_overtaken_waves_are_lost() {
+        let mut group = StripedGroup::default();
+        for wave_id in 0..5 {
+            group.on_sent(wave_id, BytesMut::from(&[wave_id as u8][..]));
+        }
+
+        let (delivered, lost) = group.on_wave_ack(1);
+        assert!(delivered);
+        assert!(lost.is_empty());
+
+        // wave 0 was overtaken by three waves
+        let (delivered, lost) = group.on_wave_ack(3);
+        assert!(delivered);
+        assert_eq!(lost, vec![BytesMut::from(&[0u8][..])]);
+
+        let (delivered, lost) = group.on_wave_ack(4);
+        assert!(delivered);
+        assert!(lost.is_empty());
+
+        // a wave sent only through the primary stream still overtakes those sent via UDP
+        let (delivered, lost) = group.on_wave_ack(7);
+        assert!(!delivered);
+        assert_eq!(lost, vec![BytesMut::from(&[2u8][..])]);
+        assert!(group.is_empty());
+    }
+
+    #[test]
+    fn test_stalled_packets_are_taken_once() {
+        let mut group = StripedGroup::default();
+        assert!(group.take_stalled().is_none());
+
+        group.on_sent(0, BytesMut::from(&b"a"[..]));
+        group.on_sent(1, BytesMut::from(&b"b"[..]));
+        assert!(group.take_stalled().is_none());
+
+        group.last_progress = Instant::now().checked_sub(MULTIPATH_LOSS_TIMEOUT).unwrap();
+        let mut stalled = group.take_stalled().unwrap();
+        stalled.sort();
+        assert_eq!(
+            stalled,
+            vec![BytesMut::from(&b"a"[..]), BytesMut::from(&b"b"[..])]
+        );
+        assert!(group.is_empty());
+        assert!(group.take_stalled().is_none());
+    }
+
+    #[test]
+    fn test_counters_sum_transfers() {
+        let counters = MultipathCounters::default();
+        assert_eq!(counters.metrics(), MultipathMetrics::default());
+
+        let mut first = MultipathCongestion::default();
+        let _ = send(&mut first, 8);
+        let mut second = MultipathCongestion::default();
+        let _ = send(&mut second, 4);
+        second.on_udp_loss(1);
+
+        counters.record(&first);
+        counters.record(&second);
+        assert_eq!(
+            counters.metrics(),
+            MultipathMetrics {
+                transfers: 2,
+                packets_via_primary: 9,
+                packets_via_udp: 3,
+                packets_retransmitted: 1,
+            }
+        );
+    }
+}
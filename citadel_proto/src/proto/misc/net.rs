@@ -3,14 +3,17 @@ use crate::macros::{ContextRequirements, SyncContextRequirements};
 use crate::proto::misc::clean_shutdown::{
     clean_framed_shutdown, CleanShutdownSink, CleanShutdownStream,
 };
+use crate::proto::misc::handshake_failures::{HandshakeFailureReason, InboundHandshakeError};
 use crate::proto::node::TlsDomain;
 use crate::proto::peer::p2p_conn_handler::generic_error;
 use bytes::Bytes;
 use citadel_user::re_exports::__private::Formatter;
 use citadel_user::serialization::SyncIO;
+use citadel_wire::cert_bootstrap::CertBootstrapResponder;
 use citadel_wire::exports::tokio_rustls::{server::TlsStream, TlsAcceptor};
 use citadel_wire::exports::{Connection, Endpoint, RecvStream, SendStream};
-use citadel_wire::quic::{QuicEndpointListener, QuicNode};
+use citadel_wire::quic::{QuicAcceptError, QuicEndpointListener, QuicNode};
+use citadel_wire::server_identity::KeyRotationAnnouncement;
 use citadel_wire::tls::TLSQUICInterop;
 use futures::{Future, TryStreamExt};
 use serde::{Deserialize, Serialize};
@@ -200,7 +203,7 @@ impl GenericNetworkListener {
 
     pub fn new_tcp(
         listener: TcpListener,
-        redirect_to_quic: Option<(TlsDomain, bool)>,
+        redirect_to_quic: Option<(TlsDomain, bool, Option<KeyRotationAnnouncement>)>,
     ) -> std::io::Result<Self> {
         let (send, recv) = tokio::sync::mpsc::channel(1024);
         let local_addr = listener.local_addr()?;
@@ -216,20 +219,22 @@ impl GenericNetworkListener {
                 async fn handle_stream_non_terminating(
                     stream: TcpStream,
                     addr: SocketAddr,
-                    redirect_to_quic: &Option<(TlsDomain, bool)>,
+                    redirect_to_quic: &Option<(TlsDomain, bool, Option<KeyRotationAnnouncement>)>,
                 ) -> std::io::Result<(GenericNetworkStream, SocketAddr)> {
-                    let first_packet = if let Some((domain, is_self_signed)) = redirect_to_quic {
-                        stream.set_nodelay(true)?;
-                        FirstPacket::Quic {
-                            domain: domain.clone(),
-                            external_addr: addr,
-                            is_self_signed: *is_self_signed,
-                        }
-                    } else {
-                        FirstPacket::Tcp {
-                            external_addr: addr,
-                        }
-                    };
+                    let first_packet =
+                        if let Some((domain, is_self_signed, key_rotation)) = redirect_to_quic {
+                            stream.set_nodelay(true)?;
+                            FirstPacket::Quic {
+                                domain: domain.clone(),
+                                external_addr: addr,
+                                is_self_signed: *is_self_signed,
+                                key_rotation: key_rotation.clone(),
+                            }
+                        } else {
+                            FirstPacket::Tcp {
+                                external_addr: addr,
+                            }
+                        };
 
                     let conn = super::write_one_packet(
                         stream,
@@ -265,12 +270,21 @@ impl GenericNetworkListener {
 
         let future = async move {
             loop {
-                let (stream, addr) = listener
+                let res = match listener
                     .next()
                     .await
-                    .ok_or_else(|| generic_error("TLS listener died"))??;
-                log::trace!(target: "citadel", "Received raw TLS stream from {:?}: {:?}", addr, stream);
-                send.send(Ok((GenericNetworkStream::Tls(stream.into()), addr)))
+                    .ok_or_else(|| generic_error("TLS listener died"))?
+                {
+                    Ok((stream, addr)) => {
+                        log::trace!(target: "citadel", "Received raw TLS stream from {:?}: {:?}", addr, stream);
+                        Ok((GenericNetworkStream::Tls(stream.into()), addr))
+                    }
+                    // a failed handshake only drops its own stream
+                    Err(err) if InboundHandshakeError::from_io(&err).is_some() => Err(err),
+                    Err(err) => return Err(err),
+                };
+
+                send.send(res)
                     .await
                     .map_err(|err| generic_error(err.to_string()))?;
             }
@@ -335,6 +349,8 @@ impl TlsListener {
         tls_acceptor: TlsAcceptor,
         domain: TlsDomain,
         is_self_signed: bool,
+        cert_bootstrap: Option<CertBootstrapResponder>,
+        key_rotation: Option<KeyRotationAnnouncement>,
     ) -> std::io::Result<Self> {
         // TODO: add channel capacity for acceptors
         let (send, recv) = tokio::sync::mpsc::channel(1024);
@@ -345,6 +361,8 @@ impl TlsListener {
             let tls_acceptor = &tls_acceptor;
             let domain = &domain;
             let send = &send;
+            let cert_bootstrap = &cert_bootstrap;
+            let key_rotation = &key_rotation;
 
             let acceptor_stream = async_stream::stream! {
                     loop {
@@ -356,14 +374,24 @@ impl TlsListener {
                 log::trace!(target: "citadel", "TLs-listener RECV Raw TCP stream from {:?} : {:?}",addr, stream);
                 let domain = domain.clone();
 
-                async fn handle_stream_non_terminating(stream: TcpStream, addr: SocketAddr, domain: TlsDomain, is_self_signed: bool, tls_acceptor: &TlsAcceptor) -> std::io::Result<(TlsStream<TcpStream>, SocketAddr)> {
-                    let serialized_first_packet = FirstPacket::Tls { domain, external_addr: addr, is_self_signed }.serialize_to_vector().map_err(|err| generic_error(err.into_string()))?;
-                    let stream = super::write_one_packet(stream, serialized_first_packet).await.map_err(|err| generic_error(err.into_string()))?;
+                async fn handle_stream_non_terminating(stream: TcpStream, addr: SocketAddr, domain: TlsDomain, is_self_signed: bool, cert_bootstrap: Option<&CertBootstrapResponder>, key_rotation: Option<KeyRotationAnnouncement>, tls_acceptor: &TlsAcceptor) -> std::io::Result<(TlsStream<TcpStream>, SocketAddr)> {
+                    let serialized_first_packet = FirstPacket::Tls { domain, external_addr: addr, is_self_signed, cert_bootstrap: cert_bootstrap.is_some(), key_rotation }.serialize_to_vector().map_err(|err| generic_error(err.into_string()))?;
+                    let mut stream = super::write_one_packet(stream, serialized_first_packet).await.map_err(|err| generic_error(err.into_string()))?;
+                    if let Some(cert_bootstrap) = cert_bootstrap {
+                        stream = respond_to_cert_bootstrap(stream, addr, cert_bootstrap).await?;
+                    }
                     // Upgrade TCP stream to TLS stream
-                    tls_acceptor.accept(stream).await.map(|r| (r, addr))
+                    tls_acceptor.accept(stream).await.map(|r| (r, addr)).map_err(|err| {
+                        InboundHandshakeError {
+                            peer_addr: addr,
+                            reason: HandshakeFailureReason::Tls,
+                            message: err.to_string(),
+                        }
+                        .into_io()
+                    })
                 }
 
-                send.send(handle_stream_non_terminating(stream, addr, domain, is_self_signed, tls_acceptor).await).await.map_err(|err| generic_error(err.to_string()))
+                send.send(handle_stream_non_terminating(stream, addr, domain, is_self_signed, cert_bootstrap.as_ref(), key_rotation.clone(), tls_acceptor).await).await.map_err(|err| generic_error(err.to_string()))
             }).await
         };
 
@@ -388,6 +416,47 @@ impl TlsListener {
     }
 }
 
+/// Answers the [`CertBootstrapRequest`] of a client. A response is always written, such that the
+/// client never sends the TLS handshake before the request is consumed
+async fn respond_to_cert_bootstrap(
+    stream: TcpStream,
+    addr: SocketAddr,
+    cert_bootstrap: &CertBootstrapResponder,
+) -> std::io::Result<TcpStream> {
+    let into_handshake_error = |message: String| {
+        InboundHandshakeError {
+            peer_addr: addr,
+            reason: HandshakeFailureReason::Tls,
+            message,
+        }
+        .into_io()
+    };
+
+    let (stream, request): (_, CertBootstrapRequest) = super::read_one_packet_as_framed(stream)
+        .await
+        .map_err(|err| into_handshake_error(err.into_string()))?;
+    let response = match request {
+        CertBootstrapRequest::Skip => CertBootstrapResponse::Skipped,
+        CertBootstrapRequest::Spake2 { client_share } => {
+            let (server_share, confirmation) = cert_bootstrap
+                .respond(&client_share)
+                .map_err(|err| into_handshake_error(err.to_string()))?;
+            CertBootstrapResponse::Accepted {
+                server_share,
+                cert_der: cert_bootstrap.cert_der().to_vec(),
+                confirmation,
+            }
+        }
+    };
+
+    let response = response
+        .serialize_to_vector()
+        .map_err(|err| generic_error(err.into_string()))?;
+    super::write_one_packet(stream, response)
+        .await
+        .map_err(|err| generic_error(err.into_string()))
+}
+
 impl Stream for TlsListener {
     type Item = std::io::Result<(TlsStream<TcpStream>, SocketAddr)>;
 
@@ -425,27 +494,37 @@ impl QuicListener {
         let future = async move {
             loop {
                 let server = &mut server;
+                let send = &send;
 
                 let acceptor_stream = async_stream::stream! {
                     loop {
-                        let res = server.next_connection().await.map_err(|err| generic_error(err.to_string()));
-                        match res {
-                            Err(res) => {
-                                if res.to_string().contains(citadel_wire::quic::QUIC_LISTENER_DIED) {
+                        match server.next_connection().await {
+                            Err(err) => {
+                                if let Some(err) = err.downcast_ref::<QuicAcceptError>() {
+                                    log::warn!(target: "citadel", "{err}");
+                                    let failure = InboundHandshakeError {
+                                        peer_addr: err.peer_addr,
+                                        reason: HandshakeFailureReason::Quic,
+                                        message: err.err.to_string(),
+                                    };
+
+                                    if send.send(Err(failure.into_io())).await.is_err() {
+                                        yield Err(generic_error("QUIC listener receiver dropped"))
+                                    }
+                                } else if err.to_string().contains(citadel_wire::quic::QUIC_LISTENER_DIED) {
                                     // terminate by yielding error
-                                    yield Err(res)
+                                    yield Err(generic_error(err.to_string()))
                                 } else {
-                                    log::warn!(target: "citadel", "QUIC accept err: {:?}", res);
+                                    log::warn!(target: "citadel", "QUIC accept err: {:?}", err);
                                 }
                             }
 
-                            res => yield res
+                            Ok(res) => yield Ok(res)
                         }
                     }
                 };
 
                 let endpoint = &endpoint;
-                let send = &send;
 
                 acceptor_stream
                     .try_for_each_concurrent(None, |(conn, tx, rx)| async move {
@@ -499,11 +578,36 @@ pub enum FirstPacket {
         domain: TlsDomain,
         external_addr: SocketAddr,
         is_self_signed: bool,
+        /// If true, the server expects a [`CertBootstrapRequest`] before the TLS handshake
+        cert_bootstrap: bool,
+        /// The upcoming certificate of a self-signed server, signed by its current key
+        key_rotation: Option<KeyRotationAnnouncement>,
     },
     Quic {
         domain: TlsDomain,
         external_addr: SocketAddr,
         is_self_signed: bool,
+        key_rotation: Option<KeyRotationAnnouncement>,
+    },
+}
+
+/// Sent by the client after a [`FirstPacket::Tls`] advertising the certificate bootstrap
+#[derive(Serialize, Deserialize)]
+pub enum CertBootstrapRequest {
+    /// The client already trusts the certificate of the server, or holds no code
+    Skip,
+    Spake2 {
+        client_share: Vec<u8>,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum CertBootstrapResponse {
+    Skipped,
+    Accepted {
+        server_share: Vec<u8>,
+        cert_der: Vec<u8>,
+        confirmation: Vec<u8>,
     },
 }
 
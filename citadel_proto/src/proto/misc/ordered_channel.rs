@@ -1,47 +1,102 @@
 use crate::error::NetworkError;
-use crate::proto::outbound_sender::UnboundedSender;
+use crate::proto::outbound_sender::{unbounded, UnboundedReceiver, UnboundedSender};
+use crate::proto::peer::channel::{closed_channel_id, is_channel_close, is_named_channel};
+use crate::proto::remote::Ticket;
 use citadel_crypt::prelude::SecBuffer;
 use std::collections::HashMap;
 use std::time::Instant;
 
+/// Delivers the messages of a virtual connection in order. Messages sent through a named channel
+/// are delivered to the sink of that channel, and all other messages to the default sink. Since
+/// all channels share a single sequence, each is ordered relative to itself. Once the sender
+/// closes a channel, its sink is dropped after all prior messages are delivered
 pub struct OrderedChannel {
-    sink: UnboundedSender<SecBuffer>,
-    map: HashMap<u64, SecBuffer>,
+    // None once the sender closes the default channel
+    sink: Option<UnboundedSender<SecBuffer>>,
+    named_sinks: HashMap<Ticket, NamedChannelSink>,
+    map: HashMap<u64, (Ticket, SecBuffer)>,
     last_message_received: Option<u64>,
     #[allow(dead_code)]
     last_message_received_instant: Option<Instant>,
 }
 
+struct NamedChannelSink {
+    // None once the sender closes the channel
+    sink: Option<UnboundedSender<SecBuffer>>,
+    // holds the messages that arrive before the local application opens the channel
+    unopened: Option<UnboundedReceiver<SecBuffer>>,
+}
+
+impl NamedChannelSink {
+    fn new() -> Self {
+        let (sink, rx) = unbounded();
+        Self {
+            sink: Some(sink),
+            unopened: Some(rx),
+        }
+    }
+}
+
 impl OrderedChannel {
     pub fn new(sink: UnboundedSender<SecBuffer>) -> Self {
         Self {
-            sink,
+            sink: Some(sink),
+            named_sinks: HashMap::new(),
             map: HashMap::new(),
             last_message_received: None,
             last_message_received_instant: None,
         }
     }
 
+    /// Returns the receiver of the named channel `channel_id`, including any messages that
+    /// arrived before the channel was opened. Returns `None` if the channel is already open
+    pub fn open_named_channel(
+        &mut self,
+        channel_id: Ticket,
+    ) -> Option<UnboundedReceiver<SecBuffer>> {
+        let named_sink = self
+            .named_sinks
+            .entry(channel_id)
+            .or_insert_with(NamedChannelSink::new);
+        let is_closed = named_sink
+            .sink
+            .as_ref()
+            .map(|sink| sink.0.is_closed())
+            .unwrap_or(true);
+        if named_sink.unopened.is_none() && is_closed {
+            // the previous handle to the channel was dropped, or, the sender closed the
+            // channel, so the channel may be reopened
+            *named_sink = NamedChannelSink::new();
+        }
+
+        named_sink.unopened.take()
+    }
+
     #[allow(unused_results)]
-    pub fn on_packet_received(&mut self, id: u64, packet: SecBuffer) -> Result<(), NetworkError> {
+    pub fn on_packet_received(
+        &mut self,
+        id: u64,
+        channel_id: Ticket,
+        packet: SecBuffer,
+    ) -> Result<(), NetworkError> {
         let next_expected_message_id = self
             .last_message_received
             .map(|r| r.wrapping_add(1))
             .unwrap_or(0);
         if next_expected_message_id == id {
             // we send this packet, then scan sequentially for any other packets that may have been delivered until hitting discontinuity
-            self.send_then_scan(id, packet)?;
+            self.send_then_scan(id, channel_id, packet)?;
             Ok(())
         } else {
             // we store. Since the next needed packet in order is not yet received, we store and return
-            self.store_received_packet(id, packet);
+            self.store_received_packet(id, channel_id, packet);
             Ok(())
         }
     }
 
     #[allow(unused_results)]
-    fn store_received_packet(&mut self, id: u64, packet: SecBuffer) {
-        self.map.insert(id, packet);
+    fn store_received_packet(&mut self, id: u64, channel_id: Ticket, packet: SecBuffer) {
+        self.map.insert(id, (channel_id, packet));
         self.set_last_message_received_instant();
     }
 
@@ -53,8 +108,13 @@ impl OrderedChannel {
         self.last_message_received = Some(id)
     }
 
-    fn send_then_scan(&mut self, new_id: u64, packet: SecBuffer) -> Result<(), NetworkError> {
-        self.send_unconditional(new_id, packet)?;
+    fn send_then_scan(
+        &mut self,
+        new_id: u64,
+        channel_id: Ticket,
+        packet: SecBuffer,
+    ) -> Result<(), NetworkError> {
+        self.send_unconditional(new_id, channel_id, packet)?;
         if !self.map.is_empty() {
             self.scan_send(new_id)
         } else {
@@ -65,28 +125,72 @@ impl OrderedChannel {
     // Assumes `last_arrived_id` has already been sent through the sink. This function will scan the elements in the hashmap sequentially, sending each enqueued packet, stopping once discontinuity occurs
     fn scan_send(&mut self, last_arrived_id: u64) -> Result<(), NetworkError> {
         let mut cur_scan_id = last_arrived_id.wrapping_add(1);
-        while let Some(next) = self.map.remove(&cur_scan_id) {
-            self.send_unconditional(cur_scan_id, next)?;
+        while let Some((channel_id, next)) = self.map.remove(&cur_scan_id) {
+            self.send_unconditional(cur_scan_id, channel_id, next)?;
             cur_scan_id = cur_scan_id.wrapping_add(1);
         }
 
         Ok(())
     }
 
-    fn send_unconditional(&mut self, new_id: u64, packet: SecBuffer) -> Result<(), NetworkError> {
-        self.sink
-            .unbounded_send(packet)
-            .map_err(|err| NetworkError::Generic(err.to_string()))?;
+    fn send_unconditional(
+        &mut self,
+        new_id: u64,
+        channel_id: Ticket,
+        packet: SecBuffer,
+    ) -> Result<(), NetworkError> {
+        if is_channel_close(channel_id) {
+            self.close_channel(closed_channel_id(channel_id));
+        } else if is_named_channel(channel_id) {
+            let named_sink = self
+                .named_sinks
+                .entry(channel_id)
+                .or_insert_with(NamedChannelSink::new);
+            if named_sink.sink.is_none() {
+                // the sender reopened a channel it previously closed
+                *named_sink = NamedChannelSink::new();
+            }
+
+            // a closed named channel does not close the virtual connection
+            if let Some(Err(_)) = named_sink
+                .sink
+                .as_ref()
+                .map(|sink| sink.unbounded_send(packet))
+            {
+                log::warn!(target: "citadel", "Dropping message for closed named channel {channel_id}");
+            }
+        } else {
+            self.sink
+                .as_ref()
+                .ok_or(NetworkError::InternalError("The channel is closed"))?
+                .unbounded_send(packet)
+                .map_err(|err| NetworkError::Generic(err.to_string()))?;
+        }
+
         self.set_last_message_received(new_id);
         self.set_last_message_received_instant();
         Ok(())
     }
+
+    // Since messages are delivered in order, every message sent prior to the close has already
+    // been passed to the sink, and the stream of the receiver ends once it yields them
+    fn close_channel(&mut self, channel_id: Ticket) {
+        if is_named_channel(channel_id) {
+            if let Some(named_sink) = self.named_sinks.get_mut(&channel_id) {
+                named_sink.sink = None;
+            }
+        } else {
+            self.sink = None;
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::proto::misc::ordered_channel::OrderedChannel;
     use crate::proto::outbound_sender::unbounded;
+    use crate::proto::peer::channel::{close_channel_id, named_channel_id};
+    use crate::proto::remote::Ticket;
     use citadel_crypt::prelude::SecBuffer;
     use futures::StreamExt;
     use rand::prelude::SliceRandom;
@@ -122,7 +226,7 @@ mod tests {
         let recv_handle = citadel_io::spawn(recv_task);
 
         for (id, packet) in values_ordered {
-            ordered_channel.on_packet_received(id, packet)?;
+            ordered_channel.on_packet_received(id, Ticket(0), packet)?;
         }
 
         recv_handle.await?;
@@ -165,7 +269,7 @@ mod tests {
         let recv_handle = citadel_io::spawn(recv_task);
 
         for (id, packet) in values_unordered {
-            ordered_channel.on_packet_received(id, packet)?;
+            ordered_channel.on_packet_received(id, Ticket(0), packet)?;
         }
 
         recv_handle.await?;
@@ -215,7 +319,7 @@ mod tests {
                 ordered_channel
                     .write()
                     .await
-                    .on_packet_received(id, packet)
+                    .on_packet_received(id, Ticket(0), packet)
                     .unwrap();
             })
             .await;
@@ -224,4 +328,55 @@ mod tests {
 
         Ok(())
     }
+
+    #[tokio::test]
+    async fn named_channels_are_demultiplexed() -> Result<(), Box<dyn Error>> {
+        let (tx, mut rx) = unbounded();
+        let mut ordered_channel = OrderedChannel::new(tx);
+        let telemetry = named_channel_id("telemetry");
+        let control = named_channel_id("control");
+
+        // messages may arrive before the named channel is opened
+        ordered_channel.on_packet_received(1, telemetry, SecBuffer::from(&[1u8] as &[u8]))?;
+        ordered_channel.on_packet_received(0, Ticket(0), SecBuffer::from(&[0u8] as &[u8]))?;
+        let mut telemetry_rx = ordered_channel.open_named_channel(telemetry).unwrap();
+        let mut control_rx = ordered_channel.open_named_channel(control).unwrap();
+        assert!(ordered_channel.open_named_channel(telemetry).is_none());
+
+        ordered_channel.on_packet_received(3, control, SecBuffer::from(&[3u8] as &[u8]))?;
+        ordered_channel.on_packet_received(2, telemetry, SecBuffer::from(&[2u8] as &[u8]))?;
+
+        assert_eq!(rx.recv().await.unwrap().as_ref(), &[0]);
+        assert_eq!(telemetry_rx.recv().await.unwrap().as_ref(), &[1]);
+        assert_eq!(telemetry_rx.recv().await.unwrap().as_ref(), &[2]);
+        assert_eq!(control_rx.recv().await.unwrap().as_ref(), &[3]);
+
+        // once dropped, a named channel may be reopened
+        drop(control_rx);
+        assert!(ordered_channel.open_named_channel(control).is_some());
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn closed_channels_end_after_prior_messages() -> Result<(), Box<dyn Error>> {
+        let (tx, mut rx) = unbounded();
+        let mut ordered_channel = OrderedChannel::new(tx);
+        let telemetry = named_channel_id("telemetry");
+        let mut telemetry_rx = ordered_channel.open_named_channel(telemetry).unwrap();
+
+        // the close arrives before the message sent prior to it
+        ordered_channel.on_packet_received(1, close_channel_id(telemetry), SecBuffer::empty())?;
+        ordered_channel.on_packet_received(2, close_channel_id(Ticket(0)), SecBuffer::empty())?;
+        ordered_channel.on_packet_received(0, telemetry, SecBuffer::from(&[0u8] as &[u8]))?;
+
+        assert_eq!(telemetry_rx.recv().await.unwrap().as_ref(), &[0]);
+        assert!(telemetry_rx.recv().await.is_none());
+        assert!(rx.recv().await.is_none());
+        assert!(ordered_channel
+            .on_packet_received(3, Ticket(0), SecBuffer::from(&[3u8] as &[u8]))
+            .is_err());
+
+        Ok(())
+    }
 }
@@ -0,0 +1,166 @@
+//! A hook deciding the fate of each inbound packet before its payload is decrypted
+//!
+//! Decrypting a payload is far more expensive than reading its header. Under a flood of packets,
+//! e.g., a client spamming peer commands at a single target, a [`PacketFilter`] may drop or
+//! deprioritize packets using only their unauthenticated header fields. Since the header is not
+//! yet validated, a filter should only ever reduce the work done for a packet, never grant it
+//! anything
+use crate::proto::packet::{packet_flags, HdpHeader};
+use std::net::SocketAddr;
+use zerocopy::LayoutVerified;
+
+/// Decides how each inbound packet on the primary stream is handled
+pub trait PacketFilter: Send + Sync + 'static {
+    fn filter(&self, packet: &PacketMetadata) -> PacketVerdict;
+}
+
+impl<F: Fn(&PacketMetadata) -> PacketVerdict + Send + Sync + 'static> PacketFilter for F {
+    fn filter(&self, packet: &PacketMetadata) -> PacketVerdict {
+        (self)(packet)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum PacketVerdict {
+    /// The packet is handled as usual
+    #[default]
+    Accept,
+    /// The packet is handled once one of the few slots reserved for deprioritized packets frees
+    /// up, such that deprioritized packets only contend with each other
+    Deprioritize,
+    /// The packet is dropped before decryption
+    Drop,
+}
+
+/// The command of a packet, as claimed by its header
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PacketCommand {
+    KeepAlive,
+    Connect,
+    Group,
+    Register,
+    Disconnect,
+    Rekey,
+    Deregister,
+    PreConnect,
+    PeerCommand,
+    File,
+    HolePunch,
+    Capabilities,
+    Challenge,
+    Unknown(u8),
+}
+
+impl From<u8> for PacketCommand {
+    fn from(cmd_primary: u8) -> Self {
+        use packet_flags::cmd::primary;
+        match cmd_primary {
+            primary::KEEP_ALIVE => Self::KeepAlive,
+            primary::DO_CONNECT => Self::Connect,
+            primary::GROUP_PACKET => Self::Group,
+            primary::DO_REGISTER => Self::Register,
+            primary::DO_DISCONNECT => Self::Disconnect,
+            primary::DO_DRILL_UPDATE => Self::Rekey,
+            primary::DO_DEREGISTER => Self::Deregister,
+            primary::DO_PRE_CONNECT => Self::PreConnect,
+            primary::PEER_CMD => Self::PeerCommand,
+            primary::FILE => Self::File,
+            primary::HOLE_PUNCH => Self::HolePunch,
+            primary::CAPABILITIES => Self::Capabilities,
+            primary::CHALLENGE => Self::Challenge,
+            other => Self::Unknown(other),
+        }
+    }
+}
+
+/// The unauthenticated header fields of an inbound packet
+#[derive(Copy, Clone, Debug)]
+pub struct PacketMetadata {
+    pub command: PacketCommand,
+    pub cmd_aux: u8,
+    /// The CID of the sender
+    pub session_cid: u64,
+    /// The CID of the peer the packet is proxied to, or zero if the packet is meant for this node
+    pub target_cid: u64,
+    pub remote_peer: SocketAddr,
+    /// The length of the packet, header included
+    pub len: usize,
+}
+
+impl PacketMetadata {
+    /// Returns `None` if the packet is too short to hold a header
+    pub(crate) fn parse(packet: &[u8], remote_peer: SocketAddr) -> Option<Self> {
+        let (header, _) = LayoutVerified::<_, HdpHeader>::new_from_prefix(packet)?;
+        Some(Self {
+            command: header.cmd_primary.into(),
+            cmd_aux: header.cmd_aux,
+            session_cid: header.session_cid.get(),
+            target_cid: header.target_cid.get(),
+            remote_peer,
+            len: packet.len(),
+        })
+    }
+}
+
+/// Applies `filter` onto `packet`. Packets too short to hold a header are accepted, since they
+/// are discarded before decryption regardless
+pub(crate) fn filter_packet(
+    filter: Option<&dyn PacketFilter>,
+    packet: &[u8],
+    remote_peer: SocketAddr,
+) -> PacketVerdict {
+    match (filter, PacketMetadata::parse(packet, remote_peer)) {
+        (Some(filter), Some(metadata)) => filter.filter(&metadata),
+        _ => PacketVerdict::Accept,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::packet_filter::{
+        filter_packet, PacketCommand, PacketMetadata, PacketVerdict,
+    };
+    use crate::proto::packet::{packet_flags, HdpHeader};
+    use bytes::BytesMut;
+    use zerocopy::FromBytes;
+
+    #[test]
+    fn test_filter_reads_header_fields() {
+        let mut header = HdpHeader::new_zeroed();
+        header.cmd_primary = packet_flags::cmd::primary::PEER_CMD;
+        header.session_cid.set(10);
+        header.target_cid.set(20);
+        let mut packet = BytesMut::new();
+        header.inscribe_into(&mut packet);
+        packet.extend_from_slice(&[0u8; 64]);
+        let remote_peer = "127.0.0.1:25000".parse().unwrap();
+
+        // drops every peer command aimed at CID 20
+        let filter = |packet: &PacketMetadata| {
+            if packet.command == PacketCommand::PeerCommand && packet.target_cid == 20 {
+                PacketVerdict::Drop
+            } else {
+                PacketVerdict::Accept
+            }
+        };
+
+        assert_eq!(
+            filter_packet(Some(&filter), &packet, remote_peer),
+            PacketVerdict::Drop
+        );
+        assert_eq!(
+            filter_packet(None, &packet, remote_peer),
+            PacketVerdict::Accept
+        );
+        // truncated packets never reach the filter
+        assert_eq!(
+            filter_packet(Some(&filter), &packet[..8], remote_peer),
+            PacketVerdict::Accept
+        );
+
+        let metadata = PacketMetadata::parse(&packet, remote_peer).unwrap();
+        assert_eq!(metadata.session_cid, 10);
+        assert_eq!(metadata.len, packet.len());
+        assert_eq!(PacketCommand::from(200), PacketCommand::Unknown(200));
+    }
+}
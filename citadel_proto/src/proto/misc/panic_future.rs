@@ -1,5 +1,7 @@
 use futures::task::Context;
+use std::any::Any;
 use std::future::Future;
+use std::panic::AssertUnwindSafe;
 use tokio::macros::support::{Pin, Poll};
 use tokio::task::{JoinError, JoinHandle};
 
@@ -31,3 +33,40 @@ impl<F> Future for ExplicitPanicFuture<F> {
         }
     }
 }
+
+/// Catches a panic raised while polling the inner future, returning the panic's message instead
+/// of unwinding through the caller
+pub struct CatchPanicFuture<F> {
+    future: Pin<Box<F>>,
+}
+
+impl<F: Future> CatchPanicFuture<F> {
+    pub fn new(future: F) -> Self {
+        Self {
+            future: Box::pin(future),
+        }
+    }
+}
+
+impl<F: Future> Future for CatchPanicFuture<F> {
+    type Output = Result<F::Output, String>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let future = &mut self.future;
+        match std::panic::catch_unwind(AssertUnwindSafe(|| future.as_mut().poll(cx))) {
+            Ok(Poll::Ready(res)) => Poll::Ready(Ok(res)),
+            Ok(Poll::Pending) => Poll::Pending,
+            Err(payload) => Poll::Ready(Err(panic_message(payload))),
+        }
+    }
+}
+
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => payload
+            .downcast_ref::<&'static str>()
+            .map(|message| message.to_string())
+            .unwrap_or_else(|| "Unknown panic payload".to_string()),
+    }
+}
@@ -0,0 +1,98 @@
+//! The node-wide budget of inbound packets handled concurrently
+//!
+//! Each session handles at most [`PacketProcessingLimits::max_concurrency_per_session`] packets at
+//! once, and every packet must additionally hold a permit of the node-wide budget while it is
+//! handled. Permits are granted in the order they were requested, hence once the budget is
+//! exhausted, a session flooding the node with packets waits its turn behind the other sessions
+//! instead of starving them. Packets deprioritized by the node's
+//! [`PacketFilter`](crate::proto::misc::packet_filter::PacketFilter) first queue for one of a few
+//! dedicated permits, hence only contend with each other until admitted
+use citadel_user::server_misc_settings::PacketProcessingLimits;
+use tokio::sync::{Semaphore, SemaphorePermit};
+
+pub struct ProcessingBudget {
+    node: Option<Semaphore>,
+    deprioritized: Semaphore,
+    per_session: usize,
+}
+
+impl ProcessingBudget {
+    pub fn new(limits: &PacketProcessingLimits) -> Self {
+        Self {
+            node: limits
+                .max_concurrency
+                .map(|permits| Semaphore::new(permits.max(1))),
+            deprioritized: Semaphore::new(limits.max_deprioritized_concurrency.max(1)),
+            per_session: limits.max_concurrency_per_session.max(1),
+        }
+    }
+
+    /// The maximum number of packets a single session may handle concurrently
+    pub fn per_session(&self) -> usize {
+        self.per_session
+    }
+
+    /// Waits until the node may handle another packet. The returned permit must be held until the
+    /// packet is handled
+    pub async fn acquire(&self) -> Option<SemaphorePermit<'_>> {
+        match self.node.as_ref() {
+            // the semaphore is never closed
+            Some(node) => node.acquire().await.ok(),
+            None => None,
+        }
+    }
+
+    /// Waits until the node may handle another deprioritized packet. The returned permit must be
+    /// held, alongside the permit of [`Self::acquire`], until the packet is handled
+    pub async fn acquire_deprioritized(&self) -> Option<SemaphorePermit<'_>> {
+        self.deprioritized.acquire().await.ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::processing_budget::ProcessingBudget;
+    use citadel_user::server_misc_settings::PacketProcessingLimits;
+    use futures::StreamExt;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_budget_bounds_concurrency_across_sessions() {
+        let budget = &ProcessingBudget::new(&PacketProcessingLimits {
+            max_concurrency_per_session: 4,
+            max_concurrency: Some(6),
+            max_deprioritized_concurrency: 1,
+        });
+        let in_flight = &AtomicUsize::new(0);
+        let peak = &AtomicUsize::new(0);
+
+        // each session handles its packets the same way the session's primary stream reader does
+        let session = |packets: usize| {
+            futures::stream::iter(0..packets).for_each_concurrent(
+                Some(budget.per_session()),
+                move |_| async move {
+                    let _permit = budget.acquire().await;
+                    let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    let _ = peak.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    let _ = in_flight.fetch_sub(1, Ordering::SeqCst);
+                },
+            )
+        };
+
+        let _ = tokio::join!(session(100), session(10), session(10));
+        assert_eq!(peak.load(Ordering::SeqCst), 6);
+
+        let unbounded = ProcessingBudget::new(&PacketProcessingLimits {
+            max_concurrency_per_session: 0,
+            max_concurrency: None,
+            max_deprioritized_concurrency: 0,
+        });
+        assert_eq!(unbounded.per_session(), 1);
+        assert!(unbounded.acquire().await.is_none());
+        let deprioritized = unbounded.acquire_deprioritized().await;
+        assert!(deprioritized.is_some());
+        assert_eq!(unbounded.deprioritized.available_permits(), 0);
+    }
+}
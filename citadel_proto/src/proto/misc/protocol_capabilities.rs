@@ -0,0 +1,190 @@
+//! Capabilities exchanged between two connected nodes, allowing them to agree on a feature set
+//! without reconnecting
+//!
+//! During a rolling upgrade, one endpoint may begin supporting features its adjacent node has yet
+//! to learn about. Once the upgraded endpoint advertises its new [`ProtocolCapabilities`], both
+//! endpoints adopt the intersection of their capabilities. A node running a version that predates
+//! this exchange drops the advertisement, leaving the session on the features common to every
+//! version, hence an upgrade never requires both sides to restart at once
+use crate::constants::{MAJOR_VERSION, MINOR_VERSION, PATCH_VERSION};
+use serde::{Deserialize, Serialize};
+
+/// Optional features of the protocol. Features may only be used once negotiated by both endpoints
+pub mod features {
+    /// Compression of file transfer groups
+    pub const GROUP_COMPRESSION: u64 = 1 << 0;
+    /// Per-chunk integrity manifests for file transfers
+    pub const TRANSFER_MANIFESTS: u64 = 1 << 1;
+    /// Sending only the changed blocks of previously transferred files
+    pub const DELTA_SYNC: u64 = 1 << 2;
+    /// Skipping chunks already held inside the receiver's chunk cache
+    pub const CHUNK_DEDUPLICATION: u64 = 1 << 3;
+    /// Striping file transfers across the primary and UDP streams
+    pub const STRIPED_TRANSFERS: u64 = 1 << 4;
+    /// The lightweight handshake profile (see [`HandshakeProfile`](super::HandshakeProfile))
+    pub const LIGHTWEIGHT_HANDSHAKE: u64 = 1 << 5;
+
+    /// Every feature supported by this build
+    pub const ALL: u64 = GROUP_COMPRESSION
+        | TRANSFER_MANIFESTS
+        | DELTA_SYNC
+        | CHUNK_DEDUPLICATION
+        | STRIPED_TRANSFERS
+        | LIGHTWEIGHT_HANDSHAKE;
+}
+
+/// The handshake used to key sessions between two endpoints
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub enum HandshakeProfile {
+    /// The stacked ratchet construction
+    #[default]
+    Standard,
+    /// A hybrid X25519 and Kyber Noise XX handshake (see `citadel_pqcrypto::noise`), for
+    /// constrained peers unable to afford the standard profile
+    Lightweight,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ProtocolCapabilities {
+    /// The major, minor and patch version of the protocol
+    pub version: (u8, u8, u8),
+    /// A bitmask of [`features`]
+    pub features: u64,
+    /// The handshake profile this endpoint prefers. Supporting the lightweight profile does not
+    /// imply preferring it
+    #[serde(default)]
+    pub handshake_profile: HandshakeProfile,
+}
+
+impl ProtocolCapabilities {
+    /// The capabilities of this build
+    pub fn current() -> Self {
+        Self {
+            version: (MAJOR_VERSION, MINOR_VERSION, PATCH_VERSION),
+            features: features::ALL,
+            handshake_profile: HandshakeProfile::Standard,
+        }
+    }
+
+    /// Returns true if every feature inside `features` is supported
+    pub fn supports(&self, features: u64) -> bool {
+        self.features & features == features
+    }
+
+    /// The capabilities common to both endpoints: the older of the two versions, and the
+    /// features supported by both. The lightweight handshake profile is adopted if both endpoints
+    /// support it and either prefers it, such that a constrained peer may use it against any
+    /// node supporting it, while the standard profile remains the default
+    pub fn negotiate(&self, adjacent: &Self) -> Self {
+        let features = self.features & adjacent.features;
+        let prefers_lightweight = self.handshake_profile == HandshakeProfile::Lightweight
+            || adjacent.handshake_profile == HandshakeProfile::Lightweight;
+        let handshake_profile =
+            if prefers_lightweight && features & features::LIGHTWEIGHT_HANDSHAKE != 0 {
+                HandshakeProfile::Lightweight
+            } else {
+                HandshakeProfile::Standard
+            };
+
+        Self {
+            version: self.version.min(adjacent.version),
+            features,
+            handshake_profile,
+        }
+    }
+}
+
+/// The capabilities of a single session
+pub(crate) struct CapabilityState {
+    /// The capabilities this endpoint advertises
+    pub(crate) local: ProtocolCapabilities,
+    /// `None` until the first exchange completes, in which case only the features common to
+    /// every version may be used
+    pub(crate) negotiated: Option<ProtocolCapabilities>,
+}
+
+impl Default for CapabilityState {
+    fn default() -> Self {
+        Self {
+            local: ProtocolCapabilities::current(),
+            negotiated: None,
+        }
+    }
+}
+
+impl CapabilityState {
+    /// Adopts the capabilities common to this endpoint and the adjacent node
+    pub(crate) fn on_adjacent_capabilities(
+        &mut self,
+        adjacent: &ProtocolCapabilities,
+    ) -> ProtocolCapabilities {
+        let negotiated = self.local.negotiate(adjacent);
+        self.negotiated = Some(negotiated);
+        negotiated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::protocol_capabilities::{
+        features, CapabilityState, HandshakeProfile, ProtocolCapabilities,
+    };
+
+    #[test]
+    fn test_negotiation_adopts_common_capabilities() {
+        let older = ProtocolCapabilities {
+            version: (0, 2, 9),
+            features: features::GROUP_COMPRESSION | features::DELTA_SYNC,
+            handshake_profile: HandshakeProfile::Standard,
+        };
+        let newer = ProtocolCapabilities {
+            version: (0, 3, 0),
+            features: features::ALL,
+            handshake_profile: HandshakeProfile::Standard,
+        };
+
+        let negotiated = newer.negotiate(&older);
+        assert_eq!(negotiated, older.negotiate(&newer));
+        assert_eq!(negotiated.version, (0, 2, 9));
+        assert!(negotiated.supports(features::DELTA_SYNC));
+        assert!(!negotiated.supports(features::DELTA_SYNC | features::STRIPED_TRANSFERS));
+
+        let mut state = CapabilityState {
+            local: newer,
+            negotiated: None,
+        };
+        assert_eq!(state.on_adjacent_capabilities(&older), negotiated);
+        // once the older side upgrades, renegotiation unlocks the new features
+        assert_eq!(state.on_adjacent_capabilities(&newer), newer);
+        assert_eq!(state.negotiated, Some(newer));
+    }
+
+    #[test]
+    fn test_lightweight_handshake_requires_support_and_preference() {
+        let constrained = ProtocolCapabilities {
+            handshake_profile: HandshakeProfile::Lightweight,
+            ..ProtocolCapabilities::current()
+        };
+        let standard = ProtocolCapabilities::current();
+        let legacy = ProtocolCapabilities {
+            features: features::ALL & !features::LIGHTWEIGHT_HANDSHAKE,
+            ..ProtocolCapabilities::current()
+        };
+
+        // the standard profile remains the default
+        assert_eq!(
+            standard.negotiate(&standard).handshake_profile,
+            HandshakeProfile::Standard
+        );
+        // a node supporting the lightweight profile accommodates a constrained peer
+        assert_eq!(
+            standard.negotiate(&constrained).handshake_profile,
+            HandshakeProfile::Lightweight
+        );
+        // unless it lacks support
+        assert_eq!(
+            constrained.negotiate(&legacy).handshake_profile,
+            HandshakeProfile::Standard
+        );
+    }
+}
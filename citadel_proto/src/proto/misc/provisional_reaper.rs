@@ -0,0 +1,68 @@
+//! Accounting for sessions, and peer key exchanges, that were reaped before becoming connected
+//!
+//! Every session periodically checks how long it has been stalled in its current pre-connect
+//! stage against the [`ProvisionalTimeouts`](citadel_user::server_misc_settings::ProvisionalTimeouts)
+//! of the node. Once a stage exceeds its timeout, the session (or, for peer key exchanges, the
+//! pending exchange) is dropped, and the node-wide counter for that stage is incremented
+use citadel_user::server_misc_settings::ProvisionalTimeouts;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+const MIN_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ProvisionalStage {
+    Register,
+    Connect,
+    PeerKeyExchange,
+}
+
+/// The number of sessions reaped in each stage since the node started
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ReapedSessionCounts {
+    pub register: u64,
+    pub connect: u64,
+    pub peer_key_exchange: u64,
+}
+
+#[derive(Default)]
+pub struct ProvisionalReaper {
+    register: AtomicU64,
+    connect: AtomicU64,
+    peer_key_exchange: AtomicU64,
+}
+
+impl ProvisionalReaper {
+    pub fn record(&self, stage: ProvisionalStage) {
+        let counter = match stage {
+            ProvisionalStage::Register => &self.register,
+            ProvisionalStage::Connect => &self.connect,
+            ProvisionalStage::PeerKeyExchange => &self.peer_key_exchange,
+        };
+
+        let _ = counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn counts(&self) -> ReapedSessionCounts {
+        ReapedSessionCounts {
+            register: self.register.load(Ordering::Relaxed),
+            connect: self.connect.load(Ordering::Relaxed),
+            peer_key_exchange: self.peer_key_exchange.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Returns how often a session should check its stages against `timeouts`, such that a stalled
+/// stage is reaped no later than a quarter of its timeout after expiring
+pub fn check_interval(timeouts: &ProvisionalTimeouts) -> Duration {
+    [
+        timeouts.register,
+        timeouts.connect,
+        timeouts.peer_key_exchange,
+    ]
+    .into_iter()
+    .min()
+    .map(|timeout| timeout / 4)
+    .unwrap_or(MIN_CHECK_INTERVAL)
+    .max(MIN_CHECK_INTERVAL)
+}
@@ -0,0 +1,134 @@
+//! Bandwidth accounting for nodes serving as relays
+//!
+//! A relay forwards the peer-to-peer traffic of clients whose peers are unreachable directly,
+//! allowing operators to scale relay capacity independently of the server holding the accounts.
+//! Each packet forwarded on behalf of a session is charged to that session's meter within the
+//! node-wide [`RelayLedger`]. Packets that would exceed the per-second or per-session limit of the
+//! [`RelaySettings`] are dropped instead of forwarded, and counted as such in the [`RelayUsage`]
+use citadel_io::Mutex;
+use citadel_user::server_misc_settings::RelaySettings;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// The traffic relayed on behalf of a single session
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct RelayUsage {
+    /// The number of bytes relayed since the session connected
+    pub bytes_relayed: u64,
+    /// The number of packets relayed since the session connected
+    pub packets_relayed: u64,
+    /// The number of packets dropped for exceeding a limit
+    pub packets_dropped: u64,
+}
+
+struct RelayMeter {
+    usage: RelayUsage,
+    window_start: Instant,
+    window_bytes: u64,
+}
+
+pub struct RelayLedger {
+    settings: RelaySettings,
+    sessions: Mutex<HashMap<u64, RelayMeter>>,
+}
+
+impl RelayLedger {
+    pub fn new(settings: RelaySettings) -> Self {
+        Self {
+            settings,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Charges a packet of `len` bytes to the session of `cid`. Returns false if the packet
+    /// exceeds a limit, in which case the packet must be dropped
+    pub fn admit(&self, cid: u64, len: usize) -> bool {
+        self.admit_at(cid, len as u64, Instant::now())
+    }
+
+    fn admit_at(&self, cid: u64, len: u64, now: Instant) -> bool {
+        let mut sessions = self.sessions.lock();
+        let meter = sessions.entry(cid).or_insert_with(|| RelayMeter {
+            usage: RelayUsage::default(),
+            window_start: now,
+            window_bytes: 0,
+        });
+
+        if now.duration_since(meter.window_start) >= RATE_WINDOW {
+            meter.window_start = now;
+            meter.window_bytes = 0;
+        }
+
+        let exceeds_rate = self
+            .settings
+            .max_bytes_per_second
+            .map(|max| meter.window_bytes + len > max)
+            .unwrap_or(false);
+        let exceeds_total = self
+            .settings
+            .max_bytes_per_session
+            .map(|max| meter.usage.bytes_relayed + len > max)
+            .unwrap_or(false);
+
+        if exceeds_rate || exceeds_total {
+            meter.usage.packets_dropped += 1;
+            return false;
+        }
+
+        meter.window_bytes += len;
+        meter.usage.bytes_relayed += len;
+        meter.usage.packets_relayed += 1;
+        true
+    }
+
+    /// Stops tracking the session of `cid`. Called once the session ends
+    pub fn remove(&self, cid: u64) {
+        let _ = self.sessions.lock().remove(&cid);
+    }
+
+    /// Returns the usage of each session that relayed traffic, keyed by the CID of the session
+    pub fn usage(&self) -> HashMap<u64, RelayUsage> {
+        self.sessions
+            .lock()
+            .iter()
+            .map(|(cid, meter)| (*cid, meter.usage))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::relay::{RelayLedger, RATE_WINDOW};
+    use citadel_user::server_misc_settings::RelaySettings;
+    use std::time::Instant;
+
+    #[test]
+    fn test_limits_drop_excess_traffic() {
+        let ledger = RelayLedger::new(RelaySettings {
+            max_bytes_per_second: Some(100),
+            max_bytes_per_session: Some(250),
+        });
+        let start = Instant::now();
+
+        assert!(ledger.admit_at(1, 60, start));
+        assert!(!ledger.admit_at(1, 60, start));
+        // other sessions are unaffected
+        assert!(ledger.admit_at(2, 100, start));
+
+        // the rate resets each window, while the total does not
+        let next = start + RATE_WINDOW;
+        assert!(ledger.admit_at(1, 100, next));
+        assert!(ledger.admit_at(1, 90, next + RATE_WINDOW));
+        assert!(!ledger.admit_at(1, 1, next + RATE_WINDOW * 2));
+
+        let usage = ledger.usage()[&1];
+        assert_eq!(usage.bytes_relayed, 250);
+        assert_eq!(usage.packets_relayed, 3);
+        assert_eq!(usage.packets_dropped, 2);
+
+        ledger.remove(1);
+        assert_eq!(ledger.usage().len(), 1);
+    }
+}
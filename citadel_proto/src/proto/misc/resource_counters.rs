@@ -0,0 +1,105 @@
+//! Instrumentation for detecting slow leaks in the session and state maps
+//!
+//! Every [`HdpSession`](crate::proto::session::HdpSession) holds a [`SessionLifecycleGuard`] which
+//! increments the node-wide created counter once constructed, and the destroyed counter once the
+//! session is finally dropped. Comparing the sessions still alive against those tracked by the
+//! session manager reveals sessions that are kept alive by a stray reference. The remaining
+//! counters are sampled from the live maps whenever the [`ResourceCounters`] are queried
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A snapshot of the resources held by the node. A counter that keeps growing across otherwise
+/// identical workloads is indicative of a leak
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct ResourceCounters {
+    /// The number of sessions constructed since the node started
+    pub sessions_created: u64,
+    /// The number of sessions dropped since the node started
+    pub sessions_destroyed: u64,
+    /// The number of connected sessions tracked by the session manager
+    pub active_sessions: u64,
+    /// The number of sessions tracked by the session manager that have yet to connect
+    pub provisional_sessions: u64,
+    /// The number of tickets awaiting a callback from the protocol
+    pub tracked_tickets: u64,
+    /// The total number of entries across the state containers of every tracked session
+    pub state_container_entries: u64,
+    /// The total number of ratchet versions retained across every tracked session and virtual connection
+    pub toolset_versions_retained: u64,
+}
+
+impl ResourceCounters {
+    /// The number of sessions that have been constructed yet not dropped
+    pub fn live_sessions(&self) -> u64 {
+        self.sessions_created
+            .saturating_sub(self.sessions_destroyed)
+    }
+
+    /// The number of live sessions that are no longer tracked by the session manager. This is
+    /// briefly non-zero while a session shuts down, but should otherwise remain at zero
+    pub fn untracked_sessions(&self) -> u64 {
+        self.live_sessions()
+            .saturating_sub(self.active_sessions + self.provisional_sessions)
+    }
+}
+
+#[derive(Default)]
+pub struct SessionLifecycle {
+    created: AtomicU64,
+    destroyed: AtomicU64,
+}
+
+impl SessionLifecycle {
+    /// Records the construction of a session. The destruction is recorded once the returned guard drops
+    pub fn track(self: &Arc<Self>) -> SessionLifecycleGuard {
+        let _ = self.created.fetch_add(1, Ordering::Relaxed);
+        SessionLifecycleGuard {
+            lifecycle: self.clone(),
+        }
+    }
+
+    /// Returns the number of sessions created and destroyed, respectively
+    pub fn counts(&self) -> (u64, u64) {
+        (
+            self.created.load(Ordering::Relaxed),
+            self.destroyed.load(Ordering::Relaxed),
+        )
+    }
+}
+
+pub struct SessionLifecycleGuard {
+    lifecycle: Arc<SessionLifecycle>,
+}
+
+impl Drop for SessionLifecycleGuard {
+    fn drop(&mut self) {
+        let _ = self.lifecycle.destroyed.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::resource_counters::{ResourceCounters, SessionLifecycle};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_session_lifecycle() {
+        let lifecycle = Arc::new(SessionLifecycle::default());
+        let first = lifecycle.track();
+        let second = lifecycle.track();
+        drop(first);
+        assert_eq!(lifecycle.counts(), (2, 1));
+        drop(second);
+        assert_eq!(lifecycle.counts(), (2, 2));
+
+        let counters = ResourceCounters {
+            sessions_created: 5,
+            sessions_destroyed: 2,
+            active_sessions: 1,
+            provisional_sessions: 1,
+            ..Default::default()
+        };
+        assert_eq!(counters.live_sessions(), 3);
+        assert_eq!(counters.untracked_sessions(), 1);
+    }
+}
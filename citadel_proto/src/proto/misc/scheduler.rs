@@ -0,0 +1,311 @@
+//! Recurring and one-off tasks run on behalf of a kernel
+//!
+//! Kernels commonly need periodic work, e.g., sweeping idle sessions or sending digests. Instead
+//! of each kernel spawning its own timers, tasks are scheduled through the [`NodeRemote`], which
+//! runs them until they are cancelled or the node shuts down. Each task is identified by name, and
+//! scheduling a task under an existing name replaces the prior task
+//!
+//! Closures cannot outlive the process, hence a task must be scheduled again after a restart. A
+//! task persisted for a client stores its next due time inside the byte map of that client's
+//! account. Once the same task is scheduled again, it resumes from the stored time instead of
+//! starting over: an interval task that was due during the downtime runs immediately, and a
+//! one-off task that already ran does not run again. A persistent backend is required for the
+//! state to survive the restart
+use crate::error::NetworkError;
+use crate::prelude::NodeRequest;
+use crate::proto::remote::NodeRemote;
+use citadel_io::Mutex;
+use citadel_user::serialization::SyncIO;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::task::JoinHandle;
+
+const SCHEDULED_TASKS: &str = "scheduled_tasks";
+
+/// Determines when a [`ScheduledTask`] runs
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Schedule {
+    /// Runs each time the duration elapses, starting one duration after being scheduled
+    Interval(Duration),
+    /// Runs once at the given time. A time in the past runs immediately
+    At(SystemTime),
+}
+
+type TaskClosure = Arc<dyn Fn(NodeRemote) -> BoxFuture<'static, ()> + Send + Sync>;
+type RequestFactory = Arc<dyn Fn() -> NodeRequest + Send + Sync>;
+
+enum ScheduledAction {
+    Closure(TaskClosure),
+    Request(RequestFactory),
+}
+
+/// A task to be run by the node according to its [`Schedule`]
+pub struct ScheduledTask {
+    name: String,
+    schedule: Schedule,
+    action: ScheduledAction,
+    persist_for: Option<u64>,
+}
+
+impl ScheduledTask {
+    /// Runs the closure with a handle to the node each time the task is due. The next run is not
+    /// due until the prior run completes
+    pub fn closure<T, F, Fut>(name: T, schedule: Schedule, closure: F) -> Self
+    where
+        T: Into<String>,
+        F: Fn(NodeRemote) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        Self {
+            name: name.into(),
+            schedule,
+            action: ScheduledAction::Closure(Arc::new(move |remote| closure(remote).boxed())),
+            persist_for: None,
+        }
+    }
+
+    /// Submits the request returned by `request` to the node each time the task is due. The
+    /// result of the request is delivered to the kernel like any other
+    pub fn request<T, F>(name: T, schedule: Schedule, request: F) -> Self
+    where
+        T: Into<String>,
+        F: Fn() -> NodeRequest + Send + Sync + 'static,
+    {
+        Self {
+            name: name.into(),
+            schedule,
+            action: ScheduledAction::Request(Arc::new(request)),
+            persist_for: None,
+        }
+    }
+
+    /// Stores the progress of the task inside the byte map of `cid`, allowing the task to resume
+    /// once scheduled again after a restart
+    pub fn persist_for(mut self, cid: u64) -> Self {
+        self.persist_for = Some(cid);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+// The progress of a persisted task
+#[derive(Serialize, Deserialize)]
+struct TaskRecord {
+    schedule: Schedule,
+    // None once a one-off task runs
+    next_due: Option<SystemTime>,
+}
+
+/// Runs the [`ScheduledTask`]s of a node. Tasks are scheduled through [`NodeRemote::schedule`]
+#[derive(Clone, Default)]
+pub struct Scheduler {
+    tasks: Arc<Mutex<HashMap<String, JoinHandle<()>>>>,
+}
+
+impl Scheduler {
+    /// Starts running the task, replacing any task previously scheduled under the same name
+    pub(crate) async fn schedule(
+        &self,
+        remote: NodeRemote,
+        task: ScheduledTask,
+    ) -> Result<(), NetworkError> {
+        let ScheduledTask {
+            name,
+            schedule,
+            action,
+            persist_for,
+        } = task;
+
+        let fresh_due = match schedule {
+            Schedule::Interval(interval) => SystemTime::now() + interval,
+            Schedule::At(time) => time,
+        };
+
+        let next_due = if let Some(cid) = persist_for {
+            let stored = remote
+                .account_manager()
+                .get_persistence_handler()
+                .get_byte_map_value(cid, 0, SCHEDULED_TASKS, &name)
+                .await?
+                .and_then(|serialized| TaskRecord::deserialize_from_vector(&serialized).ok());
+            match stored {
+                // a changed schedule starts over
+                Some(record) if record.schedule == schedule => record.next_due,
+                _ => Some(fresh_due),
+            }
+        } else {
+            Some(fresh_due)
+        };
+
+        // a one-off task that already ran is not run again
+        let next_due = if let Some(next_due) = next_due {
+            next_due
+        } else {
+            log::trace!(target: "citadel", "Scheduled task {name} already ran");
+            let _ = self.cancel(&name);
+            return Ok(());
+        };
+
+        let task = run_task(
+            remote,
+            name.clone(),
+            schedule,
+            action,
+            persist_for,
+            next_due,
+        );
+        if let Some(prior) = self.tasks.lock().insert(name, citadel_io::spawn(task)) {
+            prior.abort();
+        }
+
+        Ok(())
+    }
+
+    /// Stops the task scheduled under `name`. Returns false if no such task is running
+    pub fn cancel(&self, name: &str) -> bool {
+        if let Some(task) = self.tasks.lock().remove(name) {
+            let running = !task.is_finished();
+            task.abort();
+            running
+        } else {
+            false
+        }
+    }
+
+    /// Returns the names of the tasks that have yet to finish
+    pub fn scheduled(&self) -> Vec<String> {
+        let mut tasks = self.tasks.lock();
+        tasks.retain(|_, task| !task.is_finished());
+        tasks.keys().cloned().collect()
+    }
+
+    /// Stops every task. Called once the node shuts down
+    pub(crate) fn cancel_all(&self) {
+        for (_, task) in self.tasks.lock().drain() {
+            task.abort();
+        }
+    }
+}
+
+async fn run_task(
+    mut remote: NodeRemote,
+    name: String,
+    schedule: Schedule,
+    action: ScheduledAction,
+    persist_for: Option<u64>,
+    mut next_due: SystemTime,
+) {
+    loop {
+        let delay = next_due
+            .duration_since(SystemTime::now())
+            .unwrap_or_default();
+        tokio::time::sleep(delay).await;
+
+        match &action {
+            ScheduledAction::Closure(closure) => (closure)(remote.clone()).await,
+            ScheduledAction::Request(request) => {
+                if let Err(err) = remote.send((request)()).await {
+                    log::warn!(target: "citadel", "Stopping scheduled task {name}, since the node is unreachable: {err:?}");
+                    return;
+                }
+            }
+        }
+
+        let next = match schedule {
+            Schedule::Interval(interval) => Some(SystemTime::now() + interval),
+            Schedule::At(_) => None,
+        };
+
+        if let Some(cid) = persist_for {
+            if let Err(err) = store_record(&remote, cid, &name, schedule, next).await {
+                log::warn!(target: "citadel", "Unable to persist the progress of scheduled task {name}: {err:?}");
+            }
+        }
+
+        if let Some(next) = next {
+            next_due = next;
+        } else {
+            return;
+        }
+    }
+}
+
+async fn store_record(
+    remote: &NodeRemote,
+    cid: u64,
+    name: &str,
+    schedule: Schedule,
+    next_due: Option<SystemTime>,
+) -> Result<(), NetworkError> {
+    let serialized = TaskRecord { schedule, next_due }
+        .serialize_to_vector()
+        .map_err(|err| NetworkError::Generic(err.into_string()))?;
+    let _ = remote
+        .account_manager()
+        .get_persistence_handler()
+        .store_byte_map_value(cid, 0, SCHEDULED_TASKS, name, serialized)
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::kernel::kernel_communicator::KernelAsyncCallbackHandler;
+    use crate::prelude::NodeRequest;
+    use crate::proto::misc::scheduler::{Schedule, ScheduledTask};
+    use crate::proto::outbound_sender::BoundedSender;
+    use crate::proto::remote::NodeRemote;
+    use citadel_user::account_manager::AccountManager;
+    use citadel_user::backend::BackendType;
+    use citadel_wire::hypernode_type::NodeType;
+    use futures::StreamExt;
+    use std::time::{Duration, SystemTime};
+
+    #[tokio::test]
+    async fn test_interval_tasks_run_until_cancelled() {
+        let account_manager = AccountManager::new(BackendType::InMemory, None, None, None)
+            .await
+            .unwrap();
+        let (tx, mut rx) = BoundedSender::new(16);
+        let remote = NodeRemote::new(
+            tx,
+            KernelAsyncCallbackHandler::default(),
+            account_manager,
+            NodeType::default(),
+        );
+
+        let task = ScheduledTask::request(
+            "shutdown",
+            Schedule::Interval(Duration::from_millis(10)),
+            || NodeRequest::Shutdown,
+        );
+        remote.schedule(task).await.unwrap();
+        for _ in 0..3 {
+            let (request, _ticket) = rx.next().await.unwrap();
+            assert!(matches!(request, NodeRequest::Shutdown));
+        }
+
+        assert_eq!(remote.scheduled_tasks(), vec!["shutdown".to_string()]);
+        assert!(remote.cancel_scheduled("shutdown"));
+        assert!(!remote.cancel_scheduled("shutdown"));
+        assert!(remote.scheduled_tasks().is_empty());
+
+        // a one-off task in the past runs immediately, then finishes
+        let task = ScheduledTask::request("once", Schedule::At(SystemTime::UNIX_EPOCH), || {
+            NodeRequest::Shutdown
+        });
+        remote.schedule(task).await.unwrap();
+        while !remote.scheduled_tasks().is_empty() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(rx.next().await.is_some());
+    }
+}
@@ -0,0 +1,176 @@
+//! Detection of connections negotiated to weaker security than locally preferred
+//!
+//! The settings of a peer-to-peer session are proposed by one endpoint and adopted by the other.
+//! A misbehaving or outdated endpoint may therefore lead a session to weaker settings than those
+//! configured locally. Rather than adopting them silently, each endpoint compares the settings it
+//! is about to adopt against its own, and reports each weakened parameter to the kernel as a
+//! [`SecurityDowngrade`](crate::prelude::SecurityDowngrade). Depending on the
+//! [`DowngradePolicy`] of the local settings, the connection then proceeds or is refused
+//!
+//! The endpoint that requested the connection compares against the settings it requested, while
+//! the endpoint that accepted compares against the settings of its session with the server
+use crate::proto::misc::session_security_settings::SessionSecuritySettings;
+use crate::proto::node::SecrecyMode;
+use crate::proto::node_result::{NodeResult, SecurityDowngrade};
+use crate::proto::remote::Ticket;
+use crate::proto::session::HdpSession;
+use citadel_crypt::entropy_bank::SecurityLevel;
+use citadel_pqcrypto::algorithm_dictionary::{EncryptionAlgorithm, SigAlgorithm};
+use serde::{Deserialize, Serialize};
+
+/// Determines how a connection negotiated to weaker security than locally preferred is handled
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Default, Eq, PartialEq)]
+pub enum DowngradePolicy {
+    /// The kernel is alerted, and the connection proceeds
+    #[default]
+    Alert,
+    /// The kernel is alerted, and the connection is refused
+    Refuse,
+}
+
+/// A single parameter weakened relative to the local preference
+#[derive(Serialize, Deserialize, Debug, Copy, Clone)]
+pub enum DowngradeReason {
+    SecurityLevel {
+        preferred: SecurityLevel,
+        negotiated: SecurityLevel,
+    },
+    SecrecyMode {
+        preferred: SecrecyMode,
+        negotiated: SecrecyMode,
+    },
+    /// Encryption algorithms have no total order, hence any deviation from the preferred
+    /// algorithm is reported
+    EncryptionAlgorithm {
+        preferred: EncryptionAlgorithm,
+        negotiated: EncryptionAlgorithm,
+    },
+    SignatureAlgorithm {
+        preferred: SigAlgorithm,
+        negotiated: SigAlgorithm,
+    },
+}
+
+/// Returns each parameter of `negotiated` weaker than in `preferred`
+pub fn detect_downgrade(
+    preferred: &SessionSecuritySettings,
+    negotiated: &SessionSecuritySettings,
+) -> Vec<DowngradeReason> {
+    let mut reasons = Vec::new();
+
+    if negotiated.security_level.value() < preferred.security_level.value() {
+        reasons.push(DowngradeReason::SecurityLevel {
+            preferred: preferred.security_level,
+            negotiated: negotiated.security_level,
+        });
+    }
+
+    if preferred.secrecy_mode == SecrecyMode::Perfect
+        && negotiated.secrecy_mode == SecrecyMode::BestEffort
+    {
+        reasons.push(DowngradeReason::SecrecyMode {
+            preferred: preferred.secrecy_mode,
+            negotiated: negotiated.secrecy_mode,
+        });
+    }
+
+    let (preferred_params, negotiated_params) =
+        (&preferred.crypto_params, &negotiated.crypto_params);
+    if preferred_params.encryption_algorithm != negotiated_params.encryption_algorithm {
+        reasons.push(DowngradeReason::EncryptionAlgorithm {
+            preferred: preferred_params.encryption_algorithm,
+            negotiated: negotiated_params.encryption_algorithm,
+        });
+    }
+
+    if preferred_params.sig_algorithm != SigAlgorithm::None
+        && negotiated_params.sig_algorithm == SigAlgorithm::None
+    {
+        reasons.push(DowngradeReason::SignatureAlgorithm {
+            preferred: preferred_params.sig_algorithm,
+            negotiated: negotiated_params.sig_algorithm,
+        });
+    }
+
+    reasons
+}
+
+/// Alerts the kernel if `negotiated` is weaker than `preferred`. Returns true if the connection
+/// must be refused
+pub(crate) fn check_for_downgrade(
+    session: &HdpSession,
+    ticket: Ticket,
+    implicated_cid: u64,
+    peer_cid: u64,
+    preferred: &SessionSecuritySettings,
+    negotiated: &SessionSecuritySettings,
+) -> bool {
+    let reasons = detect_downgrade(preferred, negotiated);
+    if reasons.is_empty() {
+        return false;
+    }
+
+    let refused = preferred.downgrade_policy == DowngradePolicy::Refuse;
+    log::warn!(target: "citadel", "SECURITY DOWNGRADE on the connection between {implicated_cid} and {peer_cid}: {reasons:?} (refused: {refused})");
+    if let Err(err) = session.send_to_kernel(NodeResult::SecurityDowngrade(SecurityDowngrade {
+        ticket,
+        implicated_cid,
+        peer_cid,
+        preferred: *preferred,
+        negotiated: *negotiated,
+        reasons,
+        refused,
+    })) {
+        log::error!(target: "citadel", "Unable to alert the kernel of a security downgrade: {err:?}");
+    }
+
+    refused
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::security_downgrade::{detect_downgrade, DowngradeReason};
+    use crate::proto::misc::session_security_settings::SessionSecuritySettingsBuilder;
+    use crate::proto::node::SecrecyMode;
+    use citadel_crypt::entropy_bank::SecurityLevel;
+
+    #[test]
+    fn test_only_weaker_parameters_are_reported() {
+        let preferred = SessionSecuritySettingsBuilder::default()
+            .with_security_level(SecurityLevel::High)
+            .with_secrecy_mode(SecrecyMode::Perfect)
+            .build()
+            .unwrap();
+        assert!(detect_downgrade(&preferred, &preferred).is_empty());
+
+        // stronger settings are not a downgrade
+        let stronger = SessionSecuritySettingsBuilder::default()
+            .with_security_level(SecurityLevel::Extreme)
+            .with_secrecy_mode(SecrecyMode::Perfect)
+            .build()
+            .unwrap();
+        assert!(detect_downgrade(&preferred, &stronger).is_empty());
+
+        let weaker = SessionSecuritySettingsBuilder::default()
+            .with_security_level(SecurityLevel::Standard)
+            .with_secrecy_mode(SecrecyMode::BestEffort)
+            .build()
+            .unwrap();
+        let reasons = detect_downgrade(&preferred, &weaker);
+        assert_eq!(reasons.len(), 2);
+        assert!(matches!(
+            reasons[0],
+            DowngradeReason::SecurityLevel {
+                negotiated: SecurityLevel::Standard,
+                ..
+            }
+        ));
+        assert!(matches!(
+            reasons[1],
+            DowngradeReason::SecrecyMode {
+                negotiated: SecrecyMode::BestEffort,
+                ..
+            }
+        ));
+    }
+}
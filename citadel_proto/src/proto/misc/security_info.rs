@@ -0,0 +1,131 @@
+//! Introspection of the effective security of a connection
+//!
+//! Since the settings of a connection are negotiated, and the transport depends on how the session
+//! was established, the security in effect may differ from that requested. [`SecurityInfo`]
+//! reports what was actually negotiated, such that applications may display or log it
+use crate::proto::misc::net::GenericNetworkStream;
+use crate::proto::misc::session_security_settings::SessionSecuritySettings;
+use crate::proto::node::SecrecyMode;
+use citadel_crypt::entropy_bank::SecurityLevel;
+use citadel_pqcrypto::algorithm_dictionary::{EncryptionAlgorithm, KemAlgorithm, SigAlgorithm};
+use citadel_wire::exports::QuicHandshakeData;
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+/// The security of the transport beneath the encryption of the protocol
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub enum TransportSecurity {
+    /// Plain TCP. Confidentiality rests solely on the encryption of the protocol
+    Tcp,
+    Tls {
+        protocol_version: Option<String>,
+        cipher_suite: Option<String>,
+    },
+    /// QUIC, which always runs TLS 1.3
+    Quic {
+        alpn_protocol: Option<String>,
+        server_name: Option<String>,
+    },
+}
+
+impl TransportSecurity {
+    pub(crate) fn of(stream: &GenericNetworkStream) -> Self {
+        match stream {
+            GenericNetworkStream::Tcp(_) => Self::Tcp,
+            GenericNetworkStream::Tls(stream) => {
+                let (_, state) = stream.get_ref();
+                Self::Tls {
+                    protocol_version: state
+                        .protocol_version()
+                        .map(|version| format!("{version:?}")),
+                    cipher_suite: state
+                        .negotiated_cipher_suite()
+                        .map(|suite| format!("{:?}", suite.suite())),
+                }
+            }
+            GenericNetworkStream::Quic(_, _, _, conn, _) => {
+                let handshake_data = conn
+                    .as_ref()
+                    .and_then(|conn| conn.handshake_data())
+                    .and_then(|data| data.downcast::<QuicHandshakeData>().ok());
+                Self::Quic {
+                    alpn_protocol: handshake_data
+                        .as_ref()
+                        .and_then(|data| data.protocol.as_ref())
+                        .map(|protocol| String::from_utf8_lossy(protocol).into_owned()),
+                    server_name: handshake_data.and_then(|data| data.server_name),
+                }
+            }
+        }
+    }
+}
+
+/// The effective security of a connection. See [`PeerChannel::security_info`](crate::prelude::PeerChannel::security_info)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SecurityInfo {
+    pub kem_algorithm: KemAlgorithm,
+    pub encryption_algorithm: EncryptionAlgorithm,
+    pub sig_algorithm: SigAlgorithm,
+    pub security_level: SecurityLevel,
+    pub secrecy_mode: SecrecyMode,
+    /// The transport of the session with the server. Peer-to-peer connections are relayed over
+    /// it unless upgraded to a direct connection
+    pub transport: TransportSecurity,
+    /// The ratchet version used to encrypt outbound messages, advancing with each re-key
+    pub ratchet_version: u32,
+}
+
+impl SecurityInfo {
+    pub(crate) fn new(settings: &SessionSecuritySettings, transport: TransportSecurity) -> Self {
+        Self {
+            kem_algorithm: settings.crypto_params.kem_algorithm,
+            encryption_algorithm: settings.crypto_params.encryption_algorithm,
+            sig_algorithm: settings.crypto_params.sig_algorithm,
+            security_level: settings.security_level,
+            secrecy_mode: settings.secrecy_mode,
+            transport,
+            ratchet_version: 0,
+        }
+    }
+}
+
+impl Display for SecurityInfo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "KEM: {:?} | Encryption: {:?} | Signature: {:?} | Security level: {:?} | Secrecy mode: {:?} | Transport: {:?} | Ratchet version: {}",
+            self.kem_algorithm,
+            self.encryption_algorithm,
+            self.sig_algorithm,
+            self.security_level,
+            self.secrecy_mode,
+            self.transport,
+            self.ratchet_version
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::security_info::{SecurityInfo, TransportSecurity};
+    use crate::proto::misc::session_security_settings::SessionSecuritySettingsBuilder;
+    use crate::proto::node::SecrecyMode;
+    use citadel_crypt::entropy_bank::SecurityLevel;
+
+    #[test]
+    fn test_security_info_reflects_settings() {
+        let settings = SessionSecuritySettingsBuilder::default()
+            .with_security_level(SecurityLevel::High)
+            .with_secrecy_mode(SecrecyMode::Perfect)
+            .build()
+            .unwrap();
+        let info = SecurityInfo::new(&settings, TransportSecurity::Tcp);
+        assert!(matches!(info.security_level, SecurityLevel::High));
+        assert_eq!(info.secrecy_mode, SecrecyMode::Perfect);
+        assert_eq!(
+            info.encryption_algorithm,
+            settings.crypto_params.encryption_algorithm
+        );
+        assert!(info.to_string().contains("Transport: Tcp"));
+    }
+}
@@ -0,0 +1,82 @@
+//! Affinity between clients and the node holding their account within a load-balanced deployment
+//!
+//! Once [`SessionAffinitySettings`] are configured, each client registering to the node receives a
+//! token naming the node inside the header of the register SUCCESS packet. The client stores the
+//! token inside the byte map of its account, and presents it in the header of each pre-connect SYN
+//! it sends thereafter. A load balancer sharing the secret of the deployment may route by the
+//! token, since the header is not encrypted. Otherwise, the node the client lands on acts as a
+//! router: if the token names another node of the deployment, the client is redirected to it
+use crate::error::NetworkError;
+use citadel_crypt::affinity_token::AffinityTokenIssuer;
+use citadel_user::backend::PersistenceHandler;
+use citadel_user::server_misc_settings::SessionAffinitySettings;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+
+const AFFINITY: &str = "session_affinity";
+const AFFINITY_SUB_KEY: &str = "token";
+
+pub struct SessionAffinity {
+    issuer: AffinityTokenIssuer,
+    nodes: HashMap<u32, SocketAddr>,
+}
+
+impl SessionAffinity {
+    pub fn new(settings: &SessionAffinitySettings) -> Self {
+        Self {
+            issuer: AffinityTokenIssuer::new(settings.node_id, &settings.secret),
+            nodes: settings.nodes.clone(),
+        }
+    }
+
+    /// Issues the token binding `cid` to this node
+    pub fn issue(&self, cid: u64) -> u128 {
+        self.issuer.issue(cid)
+    }
+
+    /// Returns the address of the node `cid` should be redirected to, if `token` names another node
+    /// of the deployment. Clients presenting no token, an invalid token, or a token naming an
+    /// unknown node are served locally
+    pub fn redirect(&self, token: u128, cid: u64) -> Option<SocketAddr> {
+        let node_id = self.issuer.verify(token, cid)?;
+        if node_id == self.issuer.node_id() {
+            return None;
+        }
+
+        let addr = self.nodes.get(&node_id).copied();
+        if addr.is_none() {
+            log::warn!(target: "citadel", "Client {cid} presented the affinity token of unknown node {node_id}");
+        }
+
+        addr
+    }
+}
+
+/// Stores the affinity token received by the client of `cid` while registering
+pub async fn store_token(
+    pers: &PersistenceHandler,
+    cid: u64,
+    token: u128,
+) -> Result<(), NetworkError> {
+    let _ = pers
+        .store_byte_map_value(
+            cid,
+            0,
+            AFFINITY,
+            AFFINITY_SUB_KEY,
+            token.to_be_bytes().to_vec(),
+        )
+        .await?;
+    Ok(())
+}
+
+/// Loads the affinity token of the client of `cid`, or zero if the node it registered to issued none
+pub async fn load_token(pers: &PersistenceHandler, cid: u64) -> Result<u128, NetworkError> {
+    let token = pers
+        .get_byte_map_value(cid, 0, AFFINITY, AFFINITY_SUB_KEY)
+        .await?
+        .and_then(|bytes| bytes.try_into().ok())
+        .map(u128::from_be_bytes)
+        .unwrap_or_default();
+    Ok(token)
+}
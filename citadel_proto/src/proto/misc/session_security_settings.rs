@@ -1,4 +1,7 @@
+use crate::proto::misc::security_downgrade::DowngradePolicy;
+use crate::proto::misc::udp_keep_alive::UdpKeepAlive;
 use crate::proto::node::SecrecyMode;
+use citadel_crypt::endpoint_crypto_container::DriftTolerance;
 use citadel_crypt::entropy_bank::SecurityLevel;
 use citadel_pqcrypto::algorithm_dictionary::CryptoParameters;
 use serde::{Deserialize, Serialize};
@@ -8,6 +11,16 @@ pub struct SessionSecuritySettings {
     pub security_level: SecurityLevel,
     pub secrecy_mode: SecrecyMode,
     pub crypto_params: CryptoParameters,
+    #[serde(default)]
+    pub drift_tolerance: DriftTolerance,
+    #[serde(default)]
+    pub max_toolset_history: Option<usize>,
+    #[serde(default)]
+    pub udp_keep_alive: UdpKeepAlive,
+    #[serde(default)]
+    pub downgrade_policy: DowngradePolicy,
+    #[serde(default)]
+    pub precompute_constructor: bool,
 }
 
 #[derive(Default)]
@@ -15,6 +28,11 @@ pub struct SessionSecuritySettingsBuilder {
     security_level: Option<SecurityLevel>,
     secrecy_mode: Option<SecrecyMode>,
     crypto_params: Option<CryptoParameters>,
+    drift_tolerance: Option<DriftTolerance>,
+    max_toolset_history: Option<usize>,
+    udp_keep_alive: Option<UdpKeepAlive>,
+    downgrade_policy: Option<DowngradePolicy>,
+    precompute_constructor: Option<bool>,
 }
 
 impl SessionSecuritySettingsBuilder {
@@ -59,12 +77,95 @@ impl SessionSecuritySettingsBuilder {
         self
     }
 
-    /// Constructs the [`SessionSecuritySettings`]
+    /// Sets how far the ratchet version of an inbound packet may trail, or lead, the latest usable
+    /// version before the packet is dropped. Packets sent across a re-key are pinned to the version
+    /// they were encrypted with, so a larger lag tolerates packets that stay in flight longer
+    /// (default: lag up to the number of ratchets held in memory, lead up to one version)
+    /// ```
+    /// use citadel_proto::prelude::{SessionSecuritySettingsBuilder, DriftTolerance};
+    /// SessionSecuritySettingsBuilder::default()
+    /// .with_drift_tolerance(DriftTolerance { max_lag: 2, max_lead: 1 })
+    /// .build();
+    /// ```
+    pub fn with_drift_tolerance(mut self, drift_tolerance: DriftTolerance) -> Self {
+        self.drift_tolerance = Some(drift_tolerance);
+        self
+    }
+
+    /// Sets the number of ratchet versions retained in memory per session. A smaller window bounds
+    /// the memory used by long-lived sessions at the cost of dropping packets that were in flight
+    /// across more re-keys than the window retains. Clamped to at least two versions, and at most
+    /// the default (default: 6 in debug builds, 128 in release builds)
+    /// ```
+    /// use citadel_proto::prelude::SessionSecuritySettingsBuilder;
+    /// SessionSecuritySettingsBuilder::default()
+    /// .with_max_toolset_history(8)
+    /// .build();
+    /// ```
+    pub fn with_max_toolset_history(mut self, max: usize) -> Self {
+        self.max_toolset_history = Some(max);
+        self
+    }
+
+    /// Sets how often keep-alives are sent through an idle raw UDP path, keeping the NAT mapping of
+    /// a hole-punched flow open. Optionally, the interval is probed upwards to find the UDP timeout
+    /// of the NAT. Should [`UdpKeepAlive::repunch_after`] be set, a hole-punched path whose
+    /// keep-alives go unanswered is re-punched (default: every 20 seconds, without probing)
+    /// ```
+    /// use citadel_proto::prelude::{SessionSecuritySettingsBuilder, UdpKeepAlive};
+    /// use std::time::Duration;
+    /// SessionSecuritySettingsBuilder::default()
+    /// .with_udp_keep_alive(UdpKeepAlive { interval: Duration::from_secs(15), max_interval: Some(Duration::from_secs(120)), repunch_after: Some(3) })
+    /// .build();
+    /// ```
+    pub fn with_udp_keep_alive(mut self, udp_keep_alive: UdpKeepAlive) -> Self {
+        self.udp_keep_alive = Some(udp_keep_alive);
+        self
+    }
+
+    /// Sets how a peer connection negotiated to weaker settings than these is handled. Either way,
+    /// the kernel receives a [`SecurityDowngrade`](crate::prelude::SecurityDowngrade) event (default: alert)
+    /// ```
+    /// use citadel_proto::prelude::{DowngradePolicy, SessionSecuritySettingsBuilder};
+    /// SessionSecuritySettingsBuilder::default()
+    /// .with_downgrade_policy(DowngradePolicy::Refuse)
+    /// .build();
+    /// ```
+    pub fn with_downgrade_policy(mut self, downgrade_policy: DowngradePolicy) -> Self {
+        self.downgrade_policy = Some(downgrade_policy);
+        self
+    }
+
+    /// Sets whether the constructor of the next re-key is precomputed in the background after each
+    /// re-key, such that initiating the next re-key skips key generation. This hides the re-key
+    /// latency behind the time between messages, which matters most in Perfect secrecy mode since
+    /// each message awaits a re-key (default: enabled in Perfect mode, disabled in BestEffort mode)
+    /// ```
+    /// use citadel_proto::prelude::SessionSecuritySettingsBuilder;
+    /// SessionSecuritySettingsBuilder::default()
+    /// .with_constructor_precomputation(false)
+    /// .build();
+    /// ```
+    pub fn with_constructor_precomputation(mut self, enabled: bool) -> Self {
+        self.precompute_constructor = Some(enabled);
+        self
+    }
+
+    /// Constructs the [`SessionSecuritySettings`]. When compiled with the `fips` feature, fails if
+    /// the crypto parameters include an algorithm outside the approved subset
     pub fn build(self) -> Result<SessionSecuritySettings, anyhow::Error> {
+        let secrecy_mode = self.secrecy_mode.unwrap_or(SecrecyMode::BestEffort);
         let settings = SessionSecuritySettings {
             security_level: self.security_level.unwrap_or(SecurityLevel::Standard),
-            secrecy_mode: self.secrecy_mode.unwrap_or(SecrecyMode::BestEffort),
+            secrecy_mode,
             crypto_params: self.crypto_params.unwrap_or_default(),
+            drift_tolerance: self.drift_tolerance.unwrap_or_default(),
+            max_toolset_history: self.max_toolset_history,
+            udp_keep_alive: self.udp_keep_alive.unwrap_or_default(),
+            downgrade_policy: self.downgrade_policy.unwrap_or_default(),
+            precompute_constructor: self
+                .precompute_constructor
+                .unwrap_or(secrecy_mode == SecrecyMode::Perfect),
         };
 
         citadel_pqcrypto::validate_crypto_params(&settings.crypto_params)?;
@@ -0,0 +1,188 @@
+//! A map keyed by CID, split into independently locked shards
+//!
+//! Servers route nearly every packet through a CID lookup. Behind a single lock, every session that
+//! connects or disconnects stalls the lookups of every other session, which becomes the bottleneck
+//! past a few thousand concurrent sessions. Sharding confines the contention to the CIDs that map
+//! onto the same shard. Values are cloned out of the map, such that no shard lock is held while
+//! the caller operates on the value
+use citadel_io::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::collections::HashMap;
+
+pub struct ShardedMap<V> {
+    shards: Box<[RwLock<HashMap<u64, V>>]>,
+    mask: usize,
+}
+
+impl<V> ShardedMap<V> {
+    /// Creates a map with `shards` shards, rounded up to the next power of two
+    pub fn new(shards: usize) -> Self {
+        let shards = shards.max(1).next_power_of_two();
+        Self {
+            shards: (0..shards).map(|_| RwLock::new(HashMap::new())).collect(),
+            mask: shards - 1,
+        }
+    }
+
+    fn shard(&self, cid: &u64) -> &RwLock<HashMap<u64, V>> {
+        // fibonacci hashing spreads sequential CIDs evenly across the shards
+        let hash = cid.wrapping_mul(0x9E37_79B9_7F4A_7C15) >> 32;
+        &self.shards[hash as usize & self.mask]
+    }
+
+    /// Locks the shard holding `cid` for reading
+    pub fn read_shard(&self, cid: &u64) -> RwLockReadGuard<'_, HashMap<u64, V>> {
+        self.shard(cid).read()
+    }
+
+    /// Locks the shard holding `cid` for writing
+    pub fn write_shard(&self, cid: &u64) -> RwLockWriteGuard<'_, HashMap<u64, V>> {
+        self.shard(cid).write()
+    }
+
+    pub fn contains_key(&self, cid: &u64) -> bool {
+        self.read_shard(cid).contains_key(cid)
+    }
+
+    pub fn insert(&self, cid: u64, value: V) -> Option<V> {
+        self.write_shard(&cid).insert(cid, value)
+    }
+
+    pub fn remove(&self, cid: &u64) -> Option<V> {
+        self.write_shard(cid).remove(cid)
+    }
+
+    /// Returns the number of entries. Each shard is locked in turn, hence the count may be stale
+    /// if entries are concurrently inserted or removed
+    pub fn len(&self) -> usize {
+        self.shards.iter().map(|shard| shard.read().len()).sum()
+    }
+
+    pub fn keys(&self) -> Vec<u64> {
+        self.shards
+            .iter()
+            .flat_map(|shard| shard.read().keys().copied().collect::<Vec<_>>())
+            .collect()
+    }
+
+    /// Runs `f` on every entry, locking each shard in turn
+    pub fn for_each(&self, mut f: impl FnMut(&u64, &V)) {
+        for shard in self.shards.iter() {
+            shard.read().iter().for_each(|(cid, value)| f(cid, value));
+        }
+    }
+}
+
+impl<V: Clone> ShardedMap<V> {
+    pub fn get(&self, cid: &u64) -> Option<V> {
+        self.read_shard(cid).get(cid).cloned()
+    }
+
+    pub fn values(&self) -> Vec<V> {
+        let mut values = Vec::new();
+        self.for_each(|_, value| values.push(value.clone()));
+        values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::sharded_map::ShardedMap;
+    use citadel_io::RwLock;
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_sharded_map() {
+        let map = ShardedMap::new(6);
+        assert_eq!(map.shards.len(), 8);
+        assert_eq!(map.len(), 0);
+
+        for cid in 0..100u64 {
+            assert!(map.insert(cid, cid * 2).is_none());
+        }
+
+        assert_eq!(map.len(), 100);
+        assert_eq!(map.get(&7), Some(14));
+        assert!(map.contains_key(&99));
+        assert_eq!(map.remove(&7), Some(14));
+        assert!(!map.contains_key(&7));
+
+        let mut keys = map.keys();
+        keys.sort_unstable();
+        assert_eq!(keys, (0..100).filter(|cid| *cid != 7).collect::<Vec<_>>());
+        // sequential CIDs must not pile up inside a single shard
+        assert!(map.shards.iter().all(|shard| !shard.read().is_empty()));
+    }
+
+    /// The workload of each server thread: mostly routing lookups, interleaved with sessions
+    /// being accepted
+    fn run_workload(
+        threads: u64,
+        ops: u64,
+        lookup: impl Fn(u64) -> bool + Send + Sync + 'static,
+        accept: impl Fn(u64) + Send + Sync + 'static,
+    ) -> Duration {
+        let (lookup, accept) = (Arc::new(lookup), Arc::new(accept));
+        let start = Instant::now();
+        let handles = (0..threads)
+            .map(|thread| {
+                let (lookup, accept) = (lookup.clone(), accept.clone());
+                std::thread::spawn(move || {
+                    for op in 0..ops {
+                        let cid = thread * ops + op;
+                        if op % 16 == 0 {
+                            accept(cid);
+                        } else {
+                            let _ = lookup(cid / 2);
+                        }
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        start.elapsed()
+    }
+
+    /// Compares the throughput of a single lock against the shards. Run with
+    /// `cargo test --release -p citadel_proto bench_sharded_map -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_sharded_map() {
+        const THREADS: u64 = 8;
+        const OPS: u64 = 1_000_000;
+
+        let single = Arc::new(RwLock::new(HashMap::<u64, u64>::new()));
+        let (lookups, accepts) = (single.clone(), single);
+        let single = run_workload(
+            THREADS,
+            OPS,
+            move |cid| lookups.read().contains_key(&cid),
+            move |cid| {
+                let _ = accepts.write().insert(cid, cid);
+            },
+        );
+
+        let sharded = Arc::new(ShardedMap::<u64>::new(64));
+        let (lookups, accepts) = (sharded.clone(), sharded);
+        let sharded = run_workload(
+            THREADS,
+            OPS,
+            move |cid| lookups.contains_key(&cid),
+            move |cid| {
+                let _ = accepts.insert(cid, cid);
+            },
+        );
+
+        let total = (THREADS * OPS) as f64;
+        println!(
+            "single lock: {:.0} ops/s | sharded: {:.0} ops/s",
+            total / single.as_secs_f64(),
+            total / sharded.as_secs_f64()
+        );
+    }
+}
@@ -0,0 +1,113 @@
+//! A short history of the [`ChannelMetrics`] of each channel, retrievable on demand
+//!
+//! Each send records a snapshot of the metrics of its channel, at most one per second of wall-clock
+//! time: a later snapshot within the same second replaces the earlier one. Snapshots older than
+//! [`STATS_HISTORY_WINDOW`] are evicted, keeping the history bounded. Seconds during which the
+//! channel sent nothing have no snapshot
+use crate::proto::peer::channel::ChannelMetrics;
+use citadel_io::Mutex;
+use std::collections::VecDeque;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The span of time covered by the history
+pub const STATS_HISTORY_WINDOW: Duration = Duration::from_secs(300);
+// one snapshot per second of the window
+const MAX_SNAPSHOTS: usize = STATS_HISTORY_WINDOW.as_secs() as usize;
+
+/// The metrics of a channel as of `at`
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ChannelStatsSnapshot {
+    pub at: SystemTime,
+    pub metrics: ChannelMetrics,
+}
+
+#[derive(Default)]
+pub(crate) struct StatsHistory {
+    snapshots: Mutex<VecDeque<ChannelStatsSnapshot>>,
+}
+
+impl StatsHistory {
+    pub(crate) fn record(&self, metrics: ChannelMetrics) {
+        self.record_at(SystemTime::now(), metrics)
+    }
+
+    fn record_at(&self, at: SystemTime, metrics: ChannelMetrics) {
+        let mut snapshots = self.snapshots.lock();
+        if snapshots
+            .back()
+            .map(|last| second_of(last.at) == second_of(at))
+            .unwrap_or(false)
+        {
+            let _ = snapshots.pop_back();
+        }
+
+        snapshots.push_back(ChannelStatsSnapshot { at, metrics });
+        evict(&mut snapshots, at);
+    }
+
+    /// Returns the snapshots of the last [`STATS_HISTORY_WINDOW`], oldest first
+    pub(crate) fn snapshots(&self) -> Vec<ChannelStatsSnapshot> {
+        let mut snapshots = self.snapshots.lock();
+        evict(&mut snapshots, SystemTime::now());
+        snapshots.iter().copied().collect()
+    }
+}
+
+fn evict(snapshots: &mut VecDeque<ChannelStatsSnapshot>, now: SystemTime) {
+    while snapshots.len() > MAX_SNAPSHOTS
+        || snapshots
+            .front()
+            .map(|first| now.duration_since(first.at).unwrap_or_default() > STATS_HISTORY_WINDOW)
+            .unwrap_or(false)
+    {
+        let _ = snapshots.pop_front();
+    }
+}
+
+fn second_of(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::stats_history::{second_of, StatsHistory, STATS_HISTORY_WINDOW};
+    use crate::proto::peer::channel::ChannelMetrics;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    fn metrics(queue_depth: usize) -> ChannelMetrics {
+        ChannelMetrics {
+            queue_depth,
+            bytes_in_flight: 0,
+            last_send_latency: None,
+        }
+    }
+
+    #[test]
+    fn test_stats_history() {
+        let history = StatsHistory::default();
+        // aligned to a second, such that each offset below falls in the intended second
+        let start = UNIX_EPOCH
+            + Duration::from_secs(second_of(SystemTime::now()) - STATS_HISTORY_WINDOW.as_secs());
+        let second = |secs: u64| start + Duration::from_secs(secs);
+
+        // the snapshot of each second replaces the earlier ones of the same second
+        history.record_at(second(0), metrics(1));
+        history.record_at(second(1), metrics(2));
+        history.record_at(second(1) + Duration::from_millis(1), metrics(3));
+        history.record_at(second(2), metrics(4));
+        {
+            let snapshots = history.snapshots.lock();
+            let depths: Vec<_> = snapshots.iter().map(|s| s.metrics.queue_depth).collect();
+            assert_eq!(depths, vec![1, 3, 4]);
+        }
+
+        // snapshots older than the window are evicted
+        history.record_at(second(1 + STATS_HISTORY_WINDOW.as_secs()), metrics(5));
+        let depths: Vec<_> = history
+            .snapshots()
+            .iter()
+            .map(|s| s.metrics.queue_depth)
+            .collect();
+        assert_eq!(depths, vec![3, 4, 5]);
+    }
+}
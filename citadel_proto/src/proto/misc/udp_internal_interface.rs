@@ -3,13 +3,18 @@ use crate::error::NetworkError;
 use crate::functional::PairMap;
 use crate::macros::ContextRequirements;
 use crate::proto::codec::BytesCodec;
+use crate::proto::misc::dual_cell::DualCell;
 use crate::proto::peer::p2p_conn_handler::generic_error;
 use bytes::{Bytes, BytesMut};
 use citadel_wire::exports::Connection;
-use citadel_wire::udp_traversal::targetted_udp_socket_addr::TargettedSocketAddr;
+use citadel_wire::udp_traversal::repunch::UdpRepuncher;
+use citadel_wire::udp_traversal::targetted_udp_socket_addr::{
+    HolePunchedUdpSocket, TargettedSocketAddr,
+};
 use futures::stream::{SplitSink, SplitStream};
 use futures::{Sink, Stream, StreamExt};
-use std::net::SocketAddr;
+use std::io::ErrorKind;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use tokio::net::UdpSocket;
@@ -68,7 +73,16 @@ impl UdpSplittableTypes {
     pub fn peer_addr(&self) -> TargettedSocketAddr {
         match self {
             Self::Quic(quic) => TargettedSocketAddr::new_invariant(quic.sink.sink.remote_address()),
-            Self::Raw(raw) => TargettedSocketAddr::new_invariant(raw.sink.peer_addr),
+            Self::Raw(raw) => TargettedSocketAddr::new_invariant(raw.sink.peer_addr.get()),
+        }
+    }
+
+    /// The address a raw socket sends to, shared such that the address may migrate once the
+    /// adjacent node rebinds its socket. QUIC handles migration on its own, hence returns `None`
+    pub(crate) fn shared_peer_addr(&self) -> Option<DualCell<SocketAddr>> {
+        match self {
+            Self::Quic(..) => None,
+            Self::Raw(raw) => Some(raw.sink.peer_addr.clone()),
         }
     }
 
@@ -76,6 +90,19 @@ impl UdpSplittableTypes {
     pub(crate) fn needs_manual_ka(&self) -> bool {
         matches!(self, UdpSplittableTypes::Raw(..))
     }
+
+    /// Returns true if the socket was hole-punched, and may be re-punched once its NAT binding dies
+    pub(crate) fn can_repunch(&self) -> bool {
+        matches!(self, Self::Raw(raw) if raw.repuncher.is_some())
+    }
+
+    /// Takes the [`UdpRepuncher`] of a hole-punched socket, which outlives the socket it punched
+    pub(crate) fn take_repuncher(&mut self) -> Option<UdpRepuncher> {
+        match self {
+            Self::Quic(..) => None,
+            Self::Raw(raw) => raw.repuncher.take(),
+        }
+    }
 }
 
 impl UdpSplittable for QuicUdpSocketConnector {
@@ -181,10 +208,15 @@ pub(crate) struct RawUdpSocketConnector {
     sink: RawUdpSocketSink,
     stream: RawUdpSocketStream,
     local_addr: std::io::Result<SocketAddr>,
+    repuncher: Option<UdpRepuncher>,
 }
 
 impl RawUdpSocketConnector {
     pub fn new(socket: UdpSocket, peer_addr: SocketAddr) -> Self {
+        Self::with_shared_peer_addr(socket, DualCell::new(peer_addr))
+    }
+
+    fn with_shared_peer_addr(socket: UdpSocket, peer_addr: DualCell<SocketAddr>) -> Self {
         let local_addr = socket.local_addr();
         let framed = UdpFramed::new(
             socket,
@@ -196,13 +228,64 @@ impl RawUdpSocketConnector {
             sink: RawUdpSocketSink { sink, peer_addr },
             stream: RawUdpSocketStream { stream },
             local_addr,
+            repuncher: None,
         }
     }
+
+    /// Wraps a hole-punched socket, which `repuncher` replaces once its NAT binding dies
+    pub(crate) fn hole_punched(socket: HolePunchedUdpSocket, repuncher: UdpRepuncher) -> Self {
+        let mut this = Self::new(socket.socket, socket.addr.send_address);
+        this.repuncher = Some(repuncher);
+        this
+    }
+
+    /// Wraps a socket punched in place of one whose NAT binding died, sending to the same shared
+    /// peer address, which is updated to the address of the fresh hole
+    pub(crate) fn repunched(socket: HolePunchedUdpSocket, peer_addr: DualCell<SocketAddr>) -> Self {
+        peer_addr.set(socket.addr.send_address);
+        Self::with_shared_peer_addr(socket.socket, peer_addr)
+    }
+
+    /// Binds a new socket in place of one that failed, sending to the same shared peer address.
+    /// The interface of the failed socket is preferred, falling back onto any interface should
+    /// its address no longer be assigned to this host (e.g., once a VPN toggles)
+    pub(crate) async fn rebind(
+        previous_local_addr: SocketAddr,
+        peer_addr: DualCell<SocketAddr>,
+    ) -> std::io::Result<Self> {
+        let socket = match UdpSocket::bind(SocketAddr::new(previous_local_addr.ip(), 0)).await {
+            Ok(socket) => socket,
+            Err(err) => {
+                log::warn!(target: "citadel", "Unable to rebind UDP socket on {}: {err:?}. Binding on any interface", previous_local_addr.ip());
+                let unspecified: IpAddr = if previous_local_addr.is_ipv4() {
+                    Ipv4Addr::UNSPECIFIED.into()
+                } else {
+                    Ipv6Addr::UNSPECIFIED.into()
+                };
+                UdpSocket::bind(SocketAddr::new(unspecified, 0)).await?
+            }
+        };
+
+        Ok(Self::with_shared_peer_addr(socket, peer_addr))
+    }
+}
+
+/// Returns true if a UDP socket may keep receiving after `err`. ICMP errors triggered by earlier
+/// sends surface as resets or refusals, and do not imply that the socket itself failed
+pub(crate) fn is_transient_udp_error(err: &std::io::Error) -> bool {
+    matches!(
+        err.kind(),
+        ErrorKind::ConnectionReset
+            | ErrorKind::ConnectionRefused
+            | ErrorKind::Interrupted
+            | ErrorKind::WouldBlock
+            | ErrorKind::TimedOut
+    )
 }
 
 pub(crate) struct RawUdpSocketSink {
     sink: SplitSink<UdpFramed<BytesCodec>, (Bytes, SocketAddr)>,
-    peer_addr: SocketAddr,
+    peer_addr: DualCell<SocketAddr>,
 }
 
 pub(crate) struct RawUdpSocketStream {
@@ -219,7 +302,7 @@ impl Sink<Bytes> for RawUdpSocketSink {
     }
 
     fn start_send(mut self: Pin<&mut Self>, item: Bytes) -> Result<(), Self::Error> {
-        let addr = self.peer_addr;
+        let addr = self.peer_addr.get();
         Pin::new(&mut self.sink)
             .start_send((item, addr))
             .map_err(|err| NetworkError::Generic(err.to_string()))
@@ -247,3 +330,50 @@ impl Stream for RawUdpSocketStream {
             .map_err(generic_error)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::udp_internal_interface::{
+        is_transient_udp_error, RawUdpSocketConnector, UdpSplittable,
+    };
+    use bytes::Bytes;
+    use futures::SinkExt;
+    use std::io::{Error, ErrorKind};
+    use tokio::net::UdpSocket;
+
+    #[tokio::test]
+    async fn test_rebind_shares_migrating_peer_addr() {
+        let peer_a = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let peer_b = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let previous_local_addr = socket.local_addr().unwrap();
+
+        let failed = RawUdpSocketConnector::new(socket, peer_a.local_addr().unwrap());
+        let peer_addr = failed.sink.peer_addr.clone();
+        let rebound = RawUdpSocketConnector::rebind(previous_local_addr, peer_addr.clone())
+            .await
+            .unwrap();
+        let rebound_local_addr = rebound.local_addr().unwrap();
+        assert_eq!(rebound_local_addr.ip(), previous_local_addr.ip());
+        assert_ne!(rebound_local_addr, previous_local_addr);
+
+        let (mut sink, _stream) = rebound.split_sink_stream();
+        let mut buf = [0u8; 16];
+        sink.send(Bytes::from_static(b"first")).await.unwrap();
+        let (len, src) = peer_a.recv_from(&mut buf).await.unwrap();
+        assert_eq!((&buf[..len], src), (&b"first"[..], rebound_local_addr));
+
+        // migrating the shared address redirects the rebound socket
+        peer_addr.set(peer_b.local_addr().unwrap());
+        sink.send(Bytes::from_static(b"second")).await.unwrap();
+        let (len, _) = peer_b.recv_from(&mut buf).await.unwrap();
+        assert_eq!(&buf[..len], b"second");
+
+        assert!(is_transient_udp_error(&Error::from(
+            ErrorKind::ConnectionReset
+        )));
+        assert!(!is_transient_udp_error(&Error::from(
+            ErrorKind::PermissionDenied
+        )));
+    }
+}
@@ -0,0 +1,214 @@
+//! Keep-alives for the raw UDP path of a session
+//!
+//! NATs drop the mapping of a hole-punched UDP flow once it idles past the NAT's UDP timeout,
+//! which may be as short as thirty seconds. Independently of the keep-alives of the primary
+//! stream, a minimal keep-alive is sent through the UDP path each time it idles for the configured
+//! interval. QUIC handles its own keep-alives, hence only raw UDP sockets are affected
+//!
+//! When adaptive probing is enabled, each keep-alive asks the adjacent node whether it arrived
+//! from the same address as the packets before it. So long as the mapping survives, the interval
+//! is lengthened by the initial interval up to the configured bound. Once a probe arrives from a
+//! new address, or goes unanswered, the mapping is assumed to have expired: the interval reverts
+//! to the longest interval the mapping survived, and probing stops
+//!
+//! When re-punching is enabled, every keep-alive asks for an answer. Once the configured number of
+//! consecutive keep-alives go unanswered, the NAT binding of a hole-punched path is presumed dead,
+//! and the session punches a fresh hole alongside the adjacent node
+use crate::proto::packet::packet_flags;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Configures the keep-alives sent through the UDP path of a session
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, Eq, PartialEq)]
+pub struct UdpKeepAlive {
+    /// The idle time after which a keep-alive is sent (default: 20 seconds)
+    pub interval: Duration,
+    /// When set, the interval is probed upwards until reaching this bound, or, until the NAT
+    /// mapping expires (default: None)
+    pub max_interval: Option<Duration>,
+    /// When set, a hole-punched path whose keep-alives go unanswered this many times in a row is
+    /// re-punched over the primary stream (default: None)
+    #[serde(default)]
+    pub repunch_after: Option<u32>,
+}
+
+impl Default for UdpKeepAlive {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(20),
+            max_interval: None,
+            repunch_after: None,
+        }
+    }
+}
+
+/// Tracks the keep-alive interval of a single UDP path
+pub(crate) struct KeepAliveTuner {
+    step: Duration,
+    max_interval: Duration,
+    interval: Duration,
+    last_good: Duration,
+    settled: bool,
+    awaiting_ack: bool,
+    repunch_after: Option<u32>,
+    unanswered: u32,
+}
+
+impl KeepAliveTuner {
+    pub(crate) fn new(config: UdpKeepAlive) -> Self {
+        let max_interval = config.max_interval.unwrap_or(config.interval);
+        Self {
+            step: config.interval,
+            max_interval,
+            interval: config.interval,
+            last_good: config.interval,
+            settled: max_interval <= config.interval,
+            awaiting_ack: false,
+            repunch_after: config.repunch_after,
+            unanswered: 0,
+        }
+    }
+
+    /// The idle time after which the next keep-alive is due
+    pub(crate) fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    /// Called once the path idled for the interval. Returns the aux command of the keep-alive
+    pub(crate) fn on_idle(&mut self) -> u8 {
+        if std::mem::take(&mut self.awaiting_ack) {
+            log::trace!(target: "citadel", "UDP keep-alive probe at {:?} went unanswered", self.interval);
+            self.unanswered += 1;
+            self.tune(false);
+        }
+
+        if self.settled && self.repunch_after.is_none() {
+            packet_flags::cmd::aux::udp::KEEP_ALIVE
+        } else {
+            self.awaiting_ack = true;
+            packet_flags::cmd::aux::udp::KEEP_ALIVE_PROBE
+        }
+    }
+
+    /// Called once the adjacent node answers a probe. `preserved` is false if the probe arrived
+    /// from a new address, implying the mapping expired during the interval
+    pub(crate) fn on_ack(&mut self, preserved: bool) {
+        if std::mem::take(&mut self.awaiting_ack) {
+            self.unanswered = 0;
+            self.tune(preserved);
+        }
+    }
+
+    /// Returns true once enough keep-alives went unanswered to presume the NAT binding dead
+    pub(crate) fn binding_dead(&self) -> bool {
+        self.repunch_after
+            .map(|limit| self.unanswered >= limit)
+            .unwrap_or(false)
+    }
+
+    /// Called once a fresh hole replaced the dead path. The interval the old path settled on is
+    /// kept, since the NAT likely times out the new binding alike
+    pub(crate) fn on_repunch(&mut self) {
+        self.awaiting_ack = false;
+        self.unanswered = 0;
+    }
+
+    fn tune(&mut self, preserved: bool) {
+        if self.settled {
+            return;
+        }
+
+        if preserved {
+            self.last_good = self.interval;
+            self.interval = (self.interval + self.step).min(self.max_interval);
+            self.settled = self.interval == self.last_good;
+        } else {
+            self.interval = self.last_good;
+            self.settled = true;
+        }
+
+        if self.settled {
+            log::trace!(target: "citadel", "UDP keep-alive interval settled at {:?}", self.interval);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::udp_keep_alive::{KeepAliveTuner, UdpKeepAlive};
+    use crate::proto::packet::packet_flags::cmd::aux::udp;
+    use std::time::Duration;
+
+    #[test]
+    fn test_interval_settles_below_nat_timeout() {
+        let secs = Duration::from_secs;
+        let mut tuner = KeepAliveTuner::new(UdpKeepAlive {
+            interval: secs(10),
+            max_interval: Some(secs(60)),
+            repunch_after: None,
+        });
+
+        // the mapping survives 10 and 20 seconds, but not 30
+        assert_eq!(tuner.on_idle(), udp::KEEP_ALIVE_PROBE);
+        tuner.on_ack(true);
+        assert_eq!(tuner.interval(), secs(20));
+        assert_eq!(tuner.on_idle(), udp::KEEP_ALIVE_PROBE);
+        tuner.on_ack(true);
+        assert_eq!(tuner.interval(), secs(30));
+        assert_eq!(tuner.on_idle(), udp::KEEP_ALIVE_PROBE);
+        tuner.on_ack(false);
+        assert_eq!(tuner.interval(), secs(20));
+        assert_eq!(tuner.on_idle(), udp::KEEP_ALIVE);
+
+        // an unanswered probe reverts the interval as well
+        let mut tuner = KeepAliveTuner::new(UdpKeepAlive {
+            interval: secs(10),
+            max_interval: Some(secs(15)),
+            repunch_after: None,
+        });
+        assert_eq!(tuner.on_idle(), udp::KEEP_ALIVE_PROBE);
+        tuner.on_ack(true);
+        assert_eq!(tuner.interval(), secs(15));
+        assert_eq!(tuner.on_idle(), udp::KEEP_ALIVE_PROBE);
+        assert_eq!(tuner.on_idle(), udp::KEEP_ALIVE);
+        assert_eq!(tuner.interval(), secs(10));
+
+        // without a bound, the interval is fixed
+        let mut tuner = KeepAliveTuner::new(UdpKeepAlive::default());
+        assert_eq!(tuner.on_idle(), udp::KEEP_ALIVE);
+        assert_eq!(tuner.interval(), UdpKeepAlive::default().interval);
+    }
+
+    #[test]
+    fn test_unanswered_keep_alives_kill_binding() {
+        let mut tuner = KeepAliveTuner::new(UdpKeepAlive {
+            repunch_after: Some(2),
+            ..Default::default()
+        });
+
+        // settled keep-alives still ask for an answer, and any answer resets the count
+        assert_eq!(tuner.on_idle(), udp::KEEP_ALIVE_PROBE);
+        assert_eq!(tuner.on_idle(), udp::KEEP_ALIVE_PROBE);
+        assert!(!tuner.binding_dead());
+        tuner.on_ack(true);
+        assert_eq!(tuner.interval(), UdpKeepAlive::default().interval);
+        assert_eq!(tuner.on_idle(), udp::KEEP_ALIVE_PROBE);
+        assert_eq!(tuner.on_idle(), udp::KEEP_ALIVE_PROBE);
+        assert!(!tuner.binding_dead());
+        assert_eq!(tuner.on_idle(), udp::KEEP_ALIVE_PROBE);
+        assert!(tuner.binding_dead());
+
+        // the fresh hole starts over
+        tuner.on_repunch();
+        assert!(!tuner.binding_dead());
+        assert_eq!(tuner.on_idle(), udp::KEEP_ALIVE_PROBE);
+        assert!(!tuner.binding_dead());
+
+        // without re-punching, the binding is never presumed dead
+        let mut tuner = KeepAliveTuner::new(UdpKeepAlive::default());
+        for _ in 0..4 {
+            let _ = tuner.on_idle();
+        }
+        assert!(!tuner.binding_dead());
+    }
+}
@@ -2,6 +2,7 @@ use crate::error::NetworkError;
 use crate::proto::node::TlsDomain;
 use citadel_user::re_exports::__private::Formatter;
 use citadel_wire::exports::{Certificate, PrivateKey};
+use citadel_wire::server_identity::ServerIdentity;
 use citadel_wire::tls::TLSQUICInterop;
 use std::fmt::Debug;
 use std::net::SocketAddr;
@@ -73,6 +74,27 @@ impl ServerUnderlyingProtocol {
         Ok(Self::Tls(interop, None, true))
     }
 
+    /// Presents the persistent self-signed `identity` instead of a certificate generated per run,
+    /// such that clients pinning the certificate keep trusting the server across restarts
+    pub fn new_tls_from_identity(identity: &ServerIdentity) -> Result<Self, NetworkError> {
+        let interop = identity
+            .tls_interop()
+            .map_err(|err| NetworkError::Generic(err.to_string()))?;
+        Ok(Self::Tls(interop, None, true))
+    }
+
+    /// Like [`Self::new_tls_from_identity`], yet for QUIC
+    pub fn new_quic_from_identity(identity: &ServerIdentity) -> Self {
+        Self::Quic(
+            Some((
+                vec![Certificate(identity.cert_der.clone())],
+                PrivateKey(identity.priv_key_der.clone()),
+            )),
+            None,
+            true,
+        )
+    }
+
     /// The self-signed cert will be created internally automatically
     pub fn new_quic_self_signed() -> Self {
         Self::Quic(None, None, true)
@@ -0,0 +1,130 @@
+//! Detection of sessions whose event loop stopped making progress
+//!
+//! Each inbound packet handler of a session registers itself with the session's
+//! [`EventLoopProgress`] for as long as it runs. Packets are handled concurrently, so a single
+//! handler awaiting forever (e.g., on a kernel callback) does not halt the session, yet, it holds
+//! whatever it was working on hostage. The watchdog periodically checks for the oldest handler
+//! still running, and reports the session once that handler exceeds the stall timeout
+//!
+//! Since every inbound packet passes through the tracker, it only touches per-session atomics:
+//! each running handler claims a slot holding its start time, and the watchdog scans the slots
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+/// The number of concurrently running handlers whose start times are tracked. Handlers beyond
+/// this are counted, but are not considered when searching for the oldest handler
+const TRACKED_HANDLERS: usize = 64;
+const FREE_SLOT: u64 = 0;
+
+pub struct EventLoopProgress {
+    // the start time of each tracked handler, in microseconds since `epoch` plus one, such that
+    // a handler starting at the epoch is distinguishable from a free slot
+    started: [AtomicU64; TRACKED_HANDLERS],
+    in_flight: AtomicUsize,
+    next_slot: AtomicUsize,
+    epoch: Instant,
+}
+
+impl Default for EventLoopProgress {
+    fn default() -> Self {
+        Self {
+            started: std::array::from_fn(|_| AtomicU64::new(FREE_SLOT)),
+            in_flight: AtomicUsize::new(0),
+            next_slot: AtomicUsize::new(0),
+            epoch: Instant::now(),
+        }
+    }
+}
+
+impl EventLoopProgress {
+    /// Registers a handler as running until the returned guard drops
+    pub fn begin(&self) -> ProgressGuard<'_> {
+        let _ = self.in_flight.fetch_add(1, Ordering::Relaxed);
+        let started = self.epoch.elapsed().as_micros() as u64 + 1;
+        let first = self.next_slot.fetch_add(1, Ordering::Relaxed);
+        let slot = (0..TRACKED_HANDLERS)
+            .map(|offset| (first + offset) % TRACKED_HANDLERS)
+            .find(|slot| {
+                self.started[*slot]
+                    .compare_exchange(FREE_SLOT, started, Ordering::Relaxed, Ordering::Relaxed)
+                    .is_ok()
+            });
+
+        ProgressGuard {
+            progress: self,
+            slot,
+        }
+    }
+
+    /// Returns an identifier and the elapsed time of the longest-running tracked handler, along
+    /// with the total number of handlers running
+    pub fn oldest_in_flight(&self) -> Option<(u64, Duration, usize)> {
+        let oldest = self
+            .started
+            .iter()
+            .map(|started| started.load(Ordering::Relaxed))
+            .filter(|started| *started != FREE_SLOT)
+            .min()?;
+        let stalled_for = self
+            .epoch
+            .elapsed()
+            .saturating_sub(Duration::from_micros(oldest - 1));
+        Some((oldest, stalled_for, self.in_flight.load(Ordering::Relaxed)))
+    }
+}
+
+pub struct ProgressGuard<'a> {
+    progress: &'a EventLoopProgress,
+    slot: Option<usize>,
+}
+
+impl Drop for ProgressGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(slot) = self.slot {
+            self.progress.started[slot].store(FREE_SLOT, Ordering::Relaxed);
+        }
+
+        let _ = self.progress.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::watchdog::{EventLoopProgress, TRACKED_HANDLERS};
+
+    #[test]
+    fn test_oldest_in_flight() {
+        let progress = EventLoopProgress::default();
+        assert!(progress.oldest_in_flight().is_none());
+
+        let first = progress.begin();
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let second = progress.begin();
+
+        let (first_id, elapsed, in_flight) = progress.oldest_in_flight().unwrap();
+        assert!(elapsed >= std::time::Duration::from_millis(10));
+        assert_eq!(in_flight, 2);
+
+        drop(first);
+        let (id, elapsed, in_flight) = progress.oldest_in_flight().unwrap();
+        assert_ne!(id, first_id);
+        assert!(elapsed < std::time::Duration::from_millis(10));
+        assert_eq!(in_flight, 1);
+
+        drop(second);
+        assert!(progress.oldest_in_flight().is_none());
+    }
+
+    #[test]
+    fn test_untracked_handlers_are_counted() {
+        let progress = EventLoopProgress::default();
+        let guards = (0..TRACKED_HANDLERS + 1)
+            .map(|_| progress.begin())
+            .collect::<Vec<_>>();
+        let (_, _, in_flight) = progress.oldest_in_flight().unwrap();
+        assert_eq!(in_flight, TRACKED_HANDLERS + 1);
+
+        drop(guards);
+        assert!(progress.oldest_in_flight().is_none());
+    }
+}
@@ -2,18 +2,26 @@ use std::fmt::Debug;
 use std::io;
 use std::net::SocketAddr;
 use std::net::ToSocketAddrs;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::pin::Pin;
 use std::sync::Arc;
 
 use futures::StreamExt;
 use serde::{Deserialize, Serialize};
 use tokio::io::AsyncRead;
+use tokio::net::TcpStream;
 use tokio::task::LocalSet;
 
 use citadel_crypt::entropy_bank::SecurityLevel;
 use citadel_user::account_manager::AccountManager;
+use citadel_user::serialization::SyncIO;
+use citadel_wire::cert_bootstrap::{CertBootstrapClient, CertBootstrapResponder};
 use citadel_wire::hypernode_type::NodeType;
 use citadel_wire::nat_identification::NatType;
+use citadel_wire::server_identity::KeyRotationAnnouncement;
+use citadel_wire::server_key_pinning::ServerKeyPinning;
+use citadel_wire::stun_server::StunServer;
+use citadel_wire::udp_traversal::linear::encrypted_config_container::HolePunchConfigContainer;
 use netbeam::time_tracker::TimeTracker;
 
 use crate::constants::{MAX_OUTGOING_UNPROCESSED_REQUESTS, TCP_CONN_TIMEOUT};
@@ -21,17 +29,33 @@ use crate::error::NetworkError;
 use crate::functional::PairMap;
 use crate::kernel::kernel_communicator::KernelAsyncCallbackHandler;
 use crate::kernel::RuntimeFuture;
-use crate::prelude::{DeleteObject, PullObject};
+use crate::prelude::{
+    DeleteObject, OpenNamedChannel, Ping, PullObject, ReVFSDirectory, RenegotiateProtocol,
+    SendObjectDeduplicated, SendObjectDelta, SharedObject,
+};
+use crate::proto::misc::abuse_detection::AbuseDetector;
+use crate::proto::misc::account_hook::AccountHook;
+use crate::proto::misc::connect_timings::{ConnectTimer, ConnectTimingStage};
+use crate::proto::misc::handshake_failures::InboundHandshakeError;
 use crate::proto::misc::net::{
-    DualListener, FirstPacket, GenericNetworkListener, GenericNetworkStream, TlsListener,
+    CertBootstrapRequest, CertBootstrapResponse, DualListener, FirstPacket, GenericNetworkListener,
+    GenericNetworkStream, TlsListener,
 };
+use crate::proto::misc::packet_filter::PacketFilter;
+use crate::proto::misc::scheduler::Scheduler;
 use crate::proto::misc::underlying_proto::ServerUnderlyingProtocol;
 use crate::proto::node_request::{
     ConnectToHypernode, DeregisterFromHypernode, DisconnectFromHypernode, GroupBroadcastCommand,
     NodeRequest, PeerCommand, ReKey, RegisterToHypernode, SendObject,
 };
-use crate::proto::node_result::{InternalServerError, NodeResult, SessionList};
-use crate::proto::outbound_sender::{unbounded, BoundedReceiver, BoundedSender, UnboundedSender};
+use crate::proto::node_result::{
+    CryptoOffloadMetricsResult, DeduplicationMetricsResult, HandshakeFailed,
+    HandshakeFailuresResult, InternalServerError, MultipathMetricsResult, NodeResult,
+    ReapedSessions, RelayUsageResult, ResourceCountersResult, SessionList, VirtualConnections,
+};
+use crate::proto::outbound_sender::{
+    unbounded, BoundedReceiver, BoundedSender, KernelEventSender, UnboundedSender,
+};
 use crate::proto::packet_processor::includes::Duration;
 use crate::proto::peer::p2p_conn_handler::generic_error;
 use crate::proto::remote::{NodeRemote, Ticket};
@@ -54,7 +78,7 @@ pub struct HdpServerInner {
     primary_socket: Option<DualListener>,
     /// Key: cid (to account for multiple clients from the same node)
     session_manager: HdpSessionManager,
-    to_kernel: UnboundedSender<NodeResult>,
+    to_kernel: KernelEventSender,
     local_node_type: NodeType,
     // Applies only to listeners, not outgoing connections
     underlying_proto: ServerUnderlyingProtocol,
@@ -65,14 +89,23 @@ pub struct HdpServerInner {
 
 impl HdpServer {
     /// Creates a new [HdpServer]
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn init(
         local_node_type: NodeType,
-        to_kernel: UnboundedSender<NodeResult>,
+        to_kernel: KernelEventSender,
         account_manager: AccountManager,
         shutdown: tokio::sync::oneshot::Sender<()>,
         underlying_proto: ServerUnderlyingProtocol,
         client_config: Option<Arc<ClientConfig>>,
         stun_servers: Option<Vec<String>>,
+        hole_punch_settings: Option<HolePunchConfigContainer>,
+        packet_filter: Option<Arc<dyn PacketFilter>>,
+        account_hook: Option<Arc<dyn AccountHook>>,
+        abuse_detector: Option<Arc<dyn AbuseDetector>>,
+        server_key_pinning: Option<ServerKeyPinning>,
+        cert_bootstrap_code: Option<String>,
+        key_rotation: Option<KeyRotationAnnouncement>,
+        embedded_stun_server: Option<SocketAddr>,
     ) -> io::Result<(
         NodeRemote,
         Pin<Box<dyn RuntimeFuture>>,
@@ -80,11 +113,14 @@ impl HdpServer {
         KernelAsyncCallbackHandler,
     )> {
         let (primary_socket, bind_addr) = match local_node_type {
-            NodeType::Server(bind_addr) => {
-                Self::server_create_primary_listen_socket(underlying_proto.clone(), bind_addr)?
-                    .map_left(Some)
-                    .map_right(Some)
-            }
+            NodeType::Server(bind_addr) => Self::server_create_primary_listen_socket(
+                underlying_proto.clone(),
+                cert_bootstrap_code.clone(),
+                key_rotation,
+                bind_addr,
+            )?
+            .map_left(Some)
+            .map_right(Some),
 
             NodeType::Peer => (None, None),
         };
@@ -105,6 +141,30 @@ impl HdpServer {
             )
         };
 
+        let stun_server = embedded_stun_server
+            .filter(|_| local_node_type.is_server())
+            .map(StunServer::bind)
+            .transpose()?;
+
+        // unless told otherwise, a node serving STUN identifies its own NAT using its responder
+        let stun_servers = match (&stun_server, stun_servers) {
+            (Some(stun_server), None) => {
+                let mut addr = stun_server.local_addr()?;
+                if addr.ip().is_unspecified() {
+                    addr.set_ip(if addr.is_ipv4() {
+                        Ipv4Addr::LOCALHOST.into()
+                    } else {
+                        Ipv6Addr::LOCALHOST.into()
+                    });
+                }
+
+                log::trace!(target: "citadel", "Serving STUN on {}", addr);
+                Some(vec![addr.to_string(); 3])
+            }
+
+            (_, stun_servers) => stun_servers,
+        };
+
         let time_tracker = TimeTracker::new();
         let session_manager = HdpSessionManager::new(
             local_node_type,
@@ -113,11 +173,36 @@ impl HdpServer {
             time_tracker,
             client_config.clone(),
             stun_servers.clone(),
+            hole_punch_settings,
+            packet_filter,
+            account_hook,
+            abuse_detector,
+            server_key_pinning,
+            cert_bootstrap_code,
         );
 
-        let nat_type = NatType::identify(stun_servers)
-            .await
-            .map_err(|err| err.std())?;
+        if local_node_type.is_server() && account_manager.get_misc_settings().snapshot_peer_layer {
+            match session_manager.restore_peer_layer_snapshot().await {
+                Ok(restored) => {
+                    log::info!(target: "citadel", "Restored the peer layer state of {restored} clients")
+                }
+                Err(err) => {
+                    log::error!(target: "citadel", "Unable to restore the peer layer snapshot: {err:?}")
+                }
+            }
+        }
+
+        let identify_nat_type = NatType::identify(stun_servers);
+        let nat_type = if let Some(stun_server) = stun_server.as_ref() {
+            // the responder must answer while the node identifies its own NAT
+            tokio::select! {
+                nat_type = identify_nat_type => nat_type,
+                Err(err) = stun_server.serve() => return Err(err),
+            }
+        } else {
+            identify_nat_type.await
+        }
+        .map_err(|err| err.std())?;
 
         let inner = HdpServerInner {
             underlying_proto,
@@ -130,7 +215,12 @@ impl HdpServer {
         };
 
         let this = Self::from(inner);
-        Ok(HdpServer::load(this, account_manager, shutdown))
+        Ok(HdpServer::load(
+            this,
+            account_manager,
+            shutdown,
+            stun_server,
+        ))
     }
 
     /// Note: spawning via handle is more efficient than joining futures. Source: https://cafbit.com/post/tokio_internals/
@@ -144,6 +234,7 @@ impl HdpServer {
         this: HdpServer,
         account_manager: AccountManager,
         shutdown: tokio::sync::oneshot::Sender<()>,
+        stun_server: Option<StunServer>,
     ) -> (
         NodeRemote,
         Pin<Box<dyn RuntimeFuture>>,
@@ -157,6 +248,8 @@ impl HdpServer {
         let sess_mgr = read.session_manager.clone();
         let kernel_tx = read.to_kernel.clone();
         let node_type = read.local_node_type;
+        let snapshot_peer_layer =
+            node_type.is_server() && account_manager.get_misc_settings().snapshot_peer_layer;
 
         let (session_spawner_tx, session_spawner_rx) = unbounded();
         let session_spawner = HdpSession::session_future_receiver(session_spawner_rx);
@@ -170,6 +263,7 @@ impl HdpServer {
             account_manager,
             node_type,
         );
+        let scheduler = remote.scheduler().clone();
         let tt = read
             .session_manager
             .load_server_remote_get_tt(remote.clone());
@@ -240,6 +334,16 @@ impl HdpServer {
             }
         };
 
+        let stun_server = async move {
+            match stun_server {
+                Some(stun_server) => stun_server
+                    .serve()
+                    .await
+                    .map_err(|err| NetworkError::Generic(err.to_string())),
+                None => futures::future::pending().await,
+            }
+        };
+
         let server_future = async move {
             let res = if let Some(primary_stream_listener) = primary_stream_listener {
                 tokio::select! {
@@ -250,7 +354,8 @@ impl HdpServer {
 
                     res1 = primary_stream_listener => res1,
                     res2 = peer_container => res2,
-                    res3 = session_spawner => res3
+                    res3 = session_spawner => res3,
+                    res4 = stun_server => res4
                 }
             } else {
                 tokio::select! {
@@ -264,6 +369,20 @@ impl HdpServer {
                 }
             };
 
+            scheduler.cancel_all();
+
+            // the snapshot is taken before the sessions shut down, since their groups and postings are removed on shutdown
+            if snapshot_peer_layer {
+                match sess_mgr.save_peer_layer_snapshot().await {
+                    Ok(saved) => {
+                        log::info!(target: "citadel", "Saved the peer layer state of {saved} clients")
+                    }
+                    Err(err) => {
+                        log::error!(target: "citadel", "Unable to save the peer layer snapshot: {err:?}")
+                    }
+                }
+            }
+
             if kernel_tx.unbounded_send(NodeResult::Shutdown).is_err() {
                 log::warn!(target: "citadel", "Unable to send shutdown result to kernel (kernel died prematurely?)");
             }
@@ -290,14 +409,27 @@ impl HdpServer {
         )
     }
 
+    /// If `cert_bootstrap_code` is specified and the server uses a self-signed TLS certificate,
+    /// clients holding the code may authenticate the certificate via a [`CertBootstrapClient`].
+    /// If `key_rotation` is specified, it is published to each client ahead of the TLS or QUIC
+    /// handshake
     pub fn server_create_primary_listen_socket<T: ToSocketAddrs>(
         underlying_proto: ServerUnderlyingProtocol,
+        cert_bootstrap_code: Option<String>,
+        key_rotation: Option<KeyRotationAnnouncement>,
         full_bind_addr: T,
     ) -> io::Result<(DualListener, SocketAddr)> {
         match &underlying_proto {
             ServerUnderlyingProtocol::Tls(..) | ServerUnderlyingProtocol::Tcp => {
-                Self::create_listen_socket(underlying_proto, None, None, full_bind_addr)
-                    .map(|r| (DualListener::new(r.0, None), r.1))
+                Self::create_listen_socket(
+                    underlying_proto,
+                    None,
+                    None,
+                    cert_bootstrap_code,
+                    key_rotation,
+                    full_bind_addr,
+                )
+                .map(|r| (DualListener::new(r.0, None), r.1))
             }
 
             ServerUnderlyingProtocol::Quic(_, domain, is_self_signed) => {
@@ -306,10 +438,18 @@ impl HdpServer {
                     ServerUnderlyingProtocol::Tcp,
                     Some((domain.clone(), *is_self_signed)),
                     None,
+                    None,
+                    key_rotation,
                     full_bind_addr,
                 )?;
-                let (quic_listener, _bind_addr_quic) =
-                    Self::create_listen_socket(underlying_proto, None, None, bind_addr)?;
+                let (quic_listener, _bind_addr_quic) = Self::create_listen_socket(
+                    underlying_proto,
+                    None,
+                    None,
+                    None,
+                    None,
+                    bind_addr,
+                )?;
                 Ok((
                     DualListener::new(tcp_listener, Some(quic_listener)),
                     bind_addr,
@@ -322,21 +462,34 @@ impl HdpServer {
         underlying_proto: ServerUnderlyingProtocol,
         redirect_to_quic: Option<(TlsDomain, bool)>,
         quic_endpoint_opt: Option<QuicNode>,
+        cert_bootstrap_code: Option<String>,
+        key_rotation: Option<KeyRotationAnnouncement>,
         full_bind_addr: T,
     ) -> io::Result<(GenericNetworkListener, SocketAddr)> {
         let bind: SocketAddr = full_bind_addr
             .to_socket_addrs()?
             .next()
             .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::AddrNotAvailable, "bad addr"))?;
-        Self::bind_defaults(underlying_proto, redirect_to_quic, quic_endpoint_opt, bind)
+        Self::bind_defaults(
+            underlying_proto,
+            redirect_to_quic,
+            quic_endpoint_opt,
+            cert_bootstrap_code,
+            key_rotation,
+            bind,
+        )
     }
 
     /// redirect_to_quic is only applicable when using TCP
     /// - quic_endpoint_opt is only relevant (yet optional) when the underlying proto specified is quic
+    /// - cert_bootstrap_code is only relevant when using TLS with a self-signed certificate
+    /// - key_rotation is only relevant when using TLS, or TCP redirecting to QUIC
     fn bind_defaults(
         underlying_proto: ServerUnderlyingProtocol,
         redirect_to_quic: Option<(TlsDomain, bool)>,
         quic_endpoint_opt: Option<QuicNode>,
+        cert_bootstrap_code: Option<String>,
+        key_rotation: Option<KeyRotationAnnouncement>,
         bind: SocketAddr,
     ) -> io::Result<(GenericNetworkListener, SocketAddr)> {
         match underlying_proto {
@@ -347,11 +500,16 @@ impl HdpServer {
                         let bind = listener.local_addr()?;
                         match underlying_proto {
                             ServerUnderlyingProtocol::Tcp => {
+                                let redirect_to_quic = redirect_to_quic.map(|(domain, is_self_signed)| (domain, is_self_signed, key_rotation));
                                 Ok((GenericNetworkListener::new_tcp(listener, redirect_to_quic)?, bind))
                             }
 
                             ServerUnderlyingProtocol::Tls(interop, domain, is_self_signed) => {
-                                let tls_listener = TlsListener::new(listener, interop.tls_acceptor, domain, is_self_signed)?;
+                                let cert_bootstrap = cert_bootstrap_code
+                                    .filter(|_| is_self_signed)
+                                    .zip(interop.quic_chain.first())
+                                    .map(|(code, cert)| CertBootstrapResponder::new(code, cert.0.clone()));
+                                let tls_listener = TlsListener::new(listener, interop.tls_acceptor, domain, is_self_signed, cert_bootstrap, key_rotation)?;
                                 Ok((GenericNetworkListener::new_tls(tls_listener)?, bind))
                             }
 
@@ -387,13 +545,23 @@ impl HdpServer {
     /// The remote is usually the central server. Then the P2P listener binds to it to allow NATs to keep the hole punched
     ///
     /// It is expected that the listener_underlying_proto is QUIC here since this is called for p2p connections!
-    pub(crate) async fn create_session_transport_init<R: ToSocketAddrs>(
-        remote: R,
+    pub(crate) async fn create_session_transport_init(
+        remote: SocketAddr,
         default_client_config: &Arc<ClientConfig>,
+        server_key_pinning: Option<&ServerKeyPinning>,
+        cert_bootstrap_code: Option<&str>,
+        timer: &mut ConnectTimer,
     ) -> io::Result<GenericNetworkStream> {
         // We start by creating a client to server connection
-        let (stream, _quic_endpoint_generated_during_connect) =
-            Self::create_c2s_connect_socket(remote, None, default_client_config).await?;
+        let (stream, _quic_endpoint_generated_during_connect) = Self::c2s_connect_timed(
+            None,
+            remote,
+            default_client_config,
+            server_key_pinning,
+            cert_bootstrap_code,
+            timer,
+        )
+        .await?;
 
         log::trace!(target: "citadel", "[Client] Finished connecting to server {} w/ proto {:?}", stream.peer_addr()?, &stream);
         Ok(stream)
@@ -441,6 +609,16 @@ impl HdpServer {
             citadel_wire::quic::insecure::configure_client()
         };
 
+        Self::quic_connect_with_config(quic_endpoint, timeout, domain, remote, cfg).await
+    }
+
+    async fn quic_connect_with_config(
+        quic_endpoint: Endpoint,
+        timeout: Option<Duration>,
+        domain: TlsDomain,
+        remote: SocketAddr,
+        cfg: citadel_wire::exports::QuinnClientConfig,
+    ) -> io::Result<GenericNetworkStream> {
         log::trace!(target: "citadel", "Using cfg={:?} to connect to {:?}", cfg, remote);
 
         // we MUST use the connect_biconn_WITH below since we are using the server quic instance to make this outgoing connection
@@ -480,6 +658,30 @@ impl HdpServer {
         timeout: Option<Duration>,
         remote: SocketAddr,
         default_client_config: &Arc<ClientConfig>,
+    ) -> io::Result<(GenericNetworkStream, Option<QuicNode>)> {
+        Self::c2s_connect_timed(
+            timeout,
+            remote,
+            default_client_config,
+            None,
+            None,
+            &mut ConnectTimer::default(),
+        )
+        .await
+    }
+
+    /// Laps the transport stage of `timer` once the first packet is received, and the TLS stage
+    /// once the TLS or QUIC handshake completes. If `server_key_pinning` is specified, the
+    /// certificate of a self-signed server is pinned instead of left unverified. If
+    /// `cert_bootstrap_code` is additionally specified, the certificate of a self-signed TLS server
+    /// not yet pinned is first authenticated via the code
+    async fn c2s_connect_timed(
+        timeout: Option<Duration>,
+        remote: SocketAddr,
+        default_client_config: &Arc<ClientConfig>,
+        server_key_pinning: Option<&ServerKeyPinning>,
+        cert_bootstrap_code: Option<&str>,
+        timer: &mut ConnectTimer,
     ) -> io::Result<(GenericNetworkStream, Option<QuicNode>)> {
         log::trace!(target: "citadel", "C2S connect defaults to {:?}", remote);
         let mut stream = citadel_wire::socket_helpers::get_tcp_stream(
@@ -491,6 +693,7 @@ impl HdpServer {
         let bind_addr = stream.local_addr()?;
         log::trace!(target: "citadel", "C2S Bind addr: {:?}", bind_addr);
         let first_packet = Self::read_first_packet(&mut stream, timeout).await?;
+        timer.lap(ConnectTimingStage::Transport);
 
         match first_packet {
             FirstPacket::Tcp { external_addr } => {
@@ -502,13 +705,23 @@ impl HdpServer {
                 domain,
                 external_addr,
                 is_self_signed,
+                cert_bootstrap,
+                key_rotation,
             } => {
                 log::trace!(target: "citadel", "Host claims TLS CONNECTION (domain: {:?}) | External ADDR: {:?} | self-signed? {}", &domain, external_addr, is_self_signed);
+                if cert_bootstrap {
+                    let bootstrap = server_key_pinning
+                        .zip(cert_bootstrap_code)
+                        .filter(|(pinning, _)| is_self_signed && !pinning.is_pinned(remote));
+                    Self::bootstrap_server_cert(&mut stream, remote, bootstrap, timeout).await?;
+                }
 
-                let connector = if is_self_signed {
-                    citadel_wire::tls::create_client_dangerous_config()
-                } else {
-                    client_config_to_tls_connector(default_client_config.clone())
+                let connector = match (is_self_signed, server_key_pinning) {
+                    (true, Some(pinning)) => client_config_to_tls_connector(Arc::new(
+                        pinning.rustls_client_config_with_rotation(remote, key_rotation),
+                    )),
+                    (true, None) => citadel_wire::tls::create_client_dangerous_config(),
+                    (false, _) => client_config_to_tls_connector(default_client_config.clone()),
                 };
 
                 let stream = connector
@@ -521,12 +734,14 @@ impl HdpServer {
                     .map_err(|err| {
                         std::io::Error::new(std::io::ErrorKind::ConnectionRefused, err)
                     })?;
+                timer.lap(ConnectTimingStage::Tls);
                 Ok((GenericNetworkStream::Tls(stream.into()), None))
             }
             FirstPacket::Quic {
                 domain,
                 external_addr,
                 is_self_signed,
+                key_rotation,
             } => {
                 log::trace!(target: "citadel", "Host claims QUIC CONNECTION (domain: {:?}) | External ADDR: {:?} | self-signed: {}", &domain, external_addr, is_self_signed);
                 let udp_socket = citadel_wire::socket_helpers::get_udp_socket(bind_addr)
@@ -544,16 +759,95 @@ impl HdpServer {
 
                 quic_endpoint.tls_domain_opt = domain.clone();
 
-                Self::quic_p2p_connect_defaults(
-                    quic_endpoint.endpoint.clone(),
-                    timeout,
-                    domain,
-                    remote,
-                    default_client_config.clone(),
-                )
-                .await
-                .map(|r| (r, Some(quic_endpoint)))
+                let stream = match server_key_pinning.filter(|_| is_self_signed) {
+                    Some(pinning) => {
+                        let cfg =
+                            citadel_wire::quic::rustls_client_config_to_quinn_config(Arc::new(
+                                pinning.rustls_client_config_with_rotation(remote, key_rotation),
+                            ));
+                        Self::quic_connect_with_config(
+                            quic_endpoint.endpoint.clone(),
+                            timeout,
+                            domain,
+                            remote,
+                            cfg,
+                        )
+                        .await?
+                    }
+
+                    None => {
+                        Self::quic_p2p_connect_defaults(
+                            quic_endpoint.endpoint.clone(),
+                            timeout,
+                            domain,
+                            remote,
+                            default_client_config.clone(),
+                        )
+                        .await?
+                    }
+                };
+                timer.lap(ConnectTimingStage::Tls);
+                Ok((stream, Some(quic_endpoint)))
+            }
+        }
+    }
+
+    /// Answers the certificate bootstrap advertised by the server. If `bootstrap` is specified,
+    /// the certificate of the server is authenticated via the code, then pinned
+    async fn bootstrap_server_cert(
+        stream: &mut TcpStream,
+        remote: SocketAddr,
+        bootstrap: Option<(&ServerKeyPinning, &str)>,
+        timeout: Option<Duration>,
+    ) -> io::Result<()> {
+        let refused = |err: String| io::Error::new(io::ErrorKind::ConnectionRefused, err);
+        let (client, request) = match bootstrap {
+            Some((_, code)) => {
+                let (client, client_share) =
+                    CertBootstrapClient::start(code).map_err(|err| refused(err.to_string()))?;
+                (Some(client), CertBootstrapRequest::Spake2 { client_share })
+            }
+
+            None => (None, CertBootstrapRequest::Skip),
+        };
+
+        let request = request
+            .serialize_to_vector()
+            .map_err(|err| generic_error(err.into_string()))?;
+        let _ = super::misc::write_one_packet(&mut *stream, request)
+            .await
+            .map_err(|err| generic_error(err.into_string()))?;
+        let (_stream, response) = tokio::time::timeout(
+            timeout.unwrap_or(TCP_CONN_TIMEOUT),
+            super::misc::read_one_packet_as_framed::<_, CertBootstrapResponse>(&mut *stream),
+        )
+        .await
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::TimedOut, err.to_string()))?
+        .map_err(|err| generic_error(err.into_string()))?;
+
+        match (bootstrap.zip(client), response) {
+            (
+                Some(((pinning, _), client)),
+                CertBootstrapResponse::Accepted {
+                    server_share,
+                    cert_der,
+                    confirmation,
+                },
+            ) => {
+                client
+                    .finish(&server_share, &cert_der, &confirmation)
+                    .map_err(|err| refused(err.to_string()))?;
+                log::info!(target: "citadel", "Authenticated the certificate of {} via the bootstrap code", remote);
+                pinning
+                    .pin(remote, cert_der)
+                    .map_err(|err| refused(err.to_string()))
             }
+
+            (None, CertBootstrapResponse::Skipped) => Ok(()),
+
+            _ => Err(refused(
+                "Unexpected certificate bootstrap response".to_string(),
+            )),
         }
     }
 
@@ -581,7 +875,7 @@ impl HdpServer {
     async fn listen_primary(
         server: HdpServer,
         _tt: TimeTracker,
-        to_kernel: UnboundedSender<NodeResult>,
+        to_kernel: KernelEventSender,
         session_spawner: UnboundedSender<Pin<Box<dyn RuntimeFuture>>>,
     ) -> Result<(), NetworkError> {
         let primary_port_future = {
@@ -603,7 +897,7 @@ impl HdpServer {
     }
 
     async fn primary_session_creator_loop(
-        to_kernel: UnboundedSender<NodeResult>,
+        to_kernel: KernelEventSender,
         local_nat_type: NatType,
         session_manager: HdpSessionManager,
         mut socket: DualListener,
@@ -644,7 +938,14 @@ impl HdpServer {
 
                 Some(Err(err)) => {
                     const WSACCEPT_ERROR: i32 = 10093;
-                    if err.raw_os_error().unwrap_or(-1) != WSACCEPT_ERROR {
+                    if let Some(failure) = InboundHandshakeError::from_io(&err) {
+                        session_manager.report_handshake_failure(HandshakeFailed {
+                            peer_addr: failure.peer_addr,
+                            cid_opt: None,
+                            reason: failure.reason,
+                            message: failure.message.clone(),
+                        });
+                    } else if err.raw_os_error().unwrap_or(-1) != WSACCEPT_ERROR {
                         log::error!(target: "citadel", "Error accepting stream: {}", err.to_string());
                     }
                 }
@@ -659,7 +960,7 @@ impl HdpServer {
 
     async fn outbound_kernel_request_handler(
         this: HdpServer,
-        ref to_kernel_tx: UnboundedSender<NodeResult>,
+        ref to_kernel_tx: KernelEventSender,
         mut outbound_send_request_rx: BoundedReceiver<(NodeRequest, Ticket)>,
         session_spawner: UnboundedSender<Pin<Box<dyn RuntimeFuture>>>,
     ) -> Result<(), NetworkError> {
@@ -860,10 +1161,47 @@ impl HdpServer {
                     }
                 }
 
+                NodeRequest::SendObjectDelta(SendObjectDelta {
+                    source,
+                    chunk_size,
+                    implicated_cid,
+                    v_conn_type: virtual_target,
+                }) => {
+                    if let Err(err) = session_manager.process_outbound_file_delta(
+                        ticket_id,
+                        chunk_size,
+                        source,
+                        implicated_cid,
+                        virtual_target,
+                        SecurityLevel::Standard,
+                    ) {
+                        send_error(ticket_id, err)?;
+                    }
+                }
+
+                NodeRequest::SendObjectDeduplicated(SendObjectDeduplicated {
+                    source,
+                    chunk_size,
+                    implicated_cid,
+                    v_conn_type: virtual_target,
+                }) => {
+                    if let Err(err) = session_manager.process_outbound_file_deduplicated(
+                        ticket_id,
+                        chunk_size,
+                        source,
+                        implicated_cid,
+                        virtual_target,
+                        SecurityLevel::Standard,
+                    ) {
+                        send_error(ticket_id, err)?;
+                    }
+                }
+
                 NodeRequest::PullObject(PullObject {
                     v_conn,
                     virtual_dir,
                     delete_on_pull,
+                    owner_cid,
                     transfer_security_level,
                 }) => {
                     if let Err(err) = session_manager.revfs_pull(
@@ -872,6 +1210,7 @@ impl HdpServer {
                         v_conn,
                         virtual_dir,
                         delete_on_pull,
+                        owner_cid,
                         transfer_security_level,
                     ) {
                         send_error(ticket_id, err)?;
@@ -881,6 +1220,7 @@ impl HdpServer {
                 NodeRequest::DeleteObject(DeleteObject {
                     v_conn,
                     virtual_dir,
+                    owner_cid,
                     security_level,
                 }) => {
                     if let Err(err) = session_manager.revfs_delete(
@@ -888,12 +1228,81 @@ impl HdpServer {
                         v_conn.get_implicated_cid(),
                         v_conn,
                         virtual_dir,
+                        owner_cid,
+                        security_level,
+                    ) {
+                        send_error(ticket_id, err)?;
+                    }
+                }
+
+                NodeRequest::ReVFSDirectory(ReVFSDirectory {
+                    v_conn,
+                    operation,
+                    security_level,
+                }) => {
+                    if let Err(err) = session_manager.revfs_directory(
+                        ticket_id,
+                        v_conn.get_implicated_cid(),
+                        v_conn,
+                        operation,
                         security_level,
                     ) {
                         send_error(ticket_id, err)?;
                     }
                 }
 
+                NodeRequest::SharedObject(SharedObject {
+                    implicated_cid,
+                    operation,
+                    security_level,
+                }) => {
+                    if let Err(err) = session_manager.shared_object(
+                        ticket_id,
+                        implicated_cid,
+                        operation,
+                        security_level,
+                    ) {
+                        send_error(ticket_id, err)?;
+                    }
+                }
+
+                NodeRequest::RenegotiateProtocol(RenegotiateProtocol {
+                    implicated_cid,
+                    capabilities,
+                }) => {
+                    if let Err(err) = session_manager.renegotiate_protocol(
+                        ticket_id,
+                        implicated_cid,
+                        capabilities,
+                    ) {
+                        send_error(ticket_id, err)?;
+                    }
+                }
+
+                NodeRequest::Ping(Ping {
+                    implicated_cid,
+                    v_conn_type,
+                }) => {
+                    if let Err(err) = session_manager.ping(ticket_id, implicated_cid, v_conn_type) {
+                        send_error(ticket_id, err)?;
+                    }
+                }
+
+                NodeRequest::OpenNamedChannel(OpenNamedChannel {
+                    implicated_cid,
+                    v_conn_type,
+                    name,
+                }) => {
+                    if let Err(err) = session_manager.open_named_channel(
+                        ticket_id,
+                        implicated_cid,
+                        v_conn_type,
+                        name,
+                    ) {
+                        send_error(ticket_id, err)?;
+                    }
+                }
+
                 NodeRequest::GetActiveSessions => {
                     if let Err(err) =
                         to_kernel_tx.unbounded_send(NodeResult::SessionList(SessionList {
@@ -905,6 +1314,94 @@ impl HdpServer {
                     }
                 }
 
+                NodeRequest::GetReapedSessions => {
+                    if let Err(err) =
+                        to_kernel_tx.unbounded_send(NodeResult::ReapedSessions(ReapedSessions {
+                            ticket: ticket_id,
+                            counts: session_manager.get_reaped_session_counts(),
+                        }))
+                    {
+                        send_error(ticket_id, NetworkError::Generic(err.to_string()))?;
+                    }
+                }
+
+                NodeRequest::GetResourceCounters => {
+                    if let Err(err) = to_kernel_tx.unbounded_send(NodeResult::ResourceCounters(
+                        ResourceCountersResult {
+                            ticket: ticket_id,
+                            counters: session_manager.get_resource_counters(),
+                        },
+                    )) {
+                        send_error(ticket_id, NetworkError::Generic(err.to_string()))?;
+                    }
+                }
+
+                NodeRequest::GetVirtualConnections => {
+                    if let Err(err) = to_kernel_tx.unbounded_send(NodeResult::VirtualConnections(
+                        VirtualConnections {
+                            ticket: ticket_id,
+                            connections: session_manager.get_virtual_connections(),
+                        },
+                    )) {
+                        send_error(ticket_id, NetworkError::Generic(err.to_string()))?;
+                    }
+                }
+
+                NodeRequest::GetCryptoOffloadMetrics => {
+                    if let Err(err) = to_kernel_tx.unbounded_send(NodeResult::CryptoOffloadMetrics(
+                        CryptoOffloadMetricsResult {
+                            ticket: ticket_id,
+                            metrics: session_manager.get_crypto_offload_metrics(),
+                        },
+                    )) {
+                        send_error(ticket_id, NetworkError::Generic(err.to_string()))?;
+                    }
+                }
+
+                NodeRequest::GetMultipathMetrics => {
+                    if let Err(err) = to_kernel_tx.unbounded_send(NodeResult::MultipathMetrics(
+                        MultipathMetricsResult {
+                            ticket: ticket_id,
+                            metrics: session_manager.get_multipath_metrics(),
+                        },
+                    )) {
+                        send_error(ticket_id, NetworkError::Generic(err.to_string()))?;
+                    }
+                }
+
+                NodeRequest::GetDeduplicationMetrics => {
+                    if let Err(err) = to_kernel_tx.unbounded_send(NodeResult::DeduplicationMetrics(
+                        DeduplicationMetricsResult {
+                            ticket: ticket_id,
+                            metrics: session_manager.get_deduplication_metrics(),
+                        },
+                    )) {
+                        send_error(ticket_id, NetworkError::Generic(err.to_string()))?;
+                    }
+                }
+
+                NodeRequest::GetRelayUsage => {
+                    if let Err(err) =
+                        to_kernel_tx.unbounded_send(NodeResult::RelayUsage(RelayUsageResult {
+                            ticket: ticket_id,
+                            usage: session_manager.get_relay_usage(),
+                        }))
+                    {
+                        send_error(ticket_id, NetworkError::Generic(err.to_string()))?;
+                    }
+                }
+
+                NodeRequest::GetHandshakeFailures => {
+                    if let Err(err) = to_kernel_tx.unbounded_send(NodeResult::HandshakeFailures(
+                        HandshakeFailuresResult {
+                            ticket: ticket_id,
+                            counts: session_manager.get_handshake_failure_counts(),
+                        },
+                    )) {
+                        send_error(ticket_id, NetworkError::Generic(err.to_string()))?;
+                    }
+                }
+
                 NodeRequest::Shutdown => {
                     break;
                 }
@@ -919,6 +1416,7 @@ pub(crate) struct HdpServerRemoteInner {
     pub callback_handler: KernelAsyncCallbackHandler,
     pub node_type: NodeType,
     pub account_manager: AccountManager,
+    pub scheduler: Scheduler,
 }
 
 #[derive(Copy, Clone, Serialize, Deserialize, Debug)]
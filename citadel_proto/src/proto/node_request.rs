@@ -2,11 +2,13 @@ use crate::auth::AuthenticationRequest;
 use crate::prelude::{
     ConnectMode, GroupBroadcast, PeerSignal, SessionSecuritySettings, UdpMode, VirtualTargetType,
 };
+use crate::proto::misc::protocol_capabilities::ProtocolCapabilities;
 use crate::proto::state_container::VirtualConnectionType;
 use citadel_crypt::misc::TransferType;
 use citadel_crypt::prelude::SecurityLevel;
 use citadel_crypt::streaming_crypt_scrambler::ObjectSource;
 use citadel_user::auth::proposed_credentials::ProposedCredentials;
+use citadel_user::backend::utils::{ReVFSDirectoryOperation, SharedObjectOperation};
 use std::net::SocketAddr;
 use std::path::PathBuf;
 
@@ -47,16 +49,52 @@ pub struct SendObject {
     pub transfer_type: TransferType,
 }
 
+/// Sends an object, transmitting only the blocks that changed if the receiver already holds a
+/// previous version of it under the same name. Otherwise, the object is sent in full
+pub struct SendObjectDelta {
+    pub source: Box<dyn ObjectSource>,
+    pub chunk_size: Option<usize>,
+    pub implicated_cid: u64,
+    pub v_conn_type: VirtualTargetType,
+}
+
+/// Sends an object, transmitting only the hashes of the chunks the receiver already holds in its
+/// chunk cache. If the receiver does not cache chunks, the object is sent in full
+pub struct SendObjectDeduplicated {
+    pub source: Box<dyn ObjectSource>,
+    pub chunk_size: Option<usize>,
+    pub implicated_cid: u64,
+    pub v_conn_type: VirtualTargetType,
+}
+
 pub struct PullObject {
     pub v_conn: VirtualConnectionType,
     pub virtual_dir: PathBuf,
     pub delete_on_pull: bool,
+    /// The owner of the RE-VFS pulled from. If None, the local node's RE-VFS is used. Pulling
+    /// from another owner requires the owner to have granted access to `virtual_dir`
+    pub owner_cid: Option<u64>,
     pub transfer_security_level: SecurityLevel,
 }
 
 pub struct DeleteObject {
     pub v_conn: VirtualConnectionType,
     pub virtual_dir: PathBuf,
+    /// The owner of the RE-VFS deleted from. If None, the local node's RE-VFS is used. Deleting
+    /// from another owner requires the owner to have granted access to `virtual_dir`
+    pub owner_cid: Option<u64>,
+    pub security_level: SecurityLevel,
+}
+
+pub struct ReVFSDirectory {
+    pub v_conn: VirtualConnectionType,
+    pub operation: ReVFSDirectoryOperation,
+    pub security_level: SecurityLevel,
+}
+
+pub struct SharedObject {
+    pub implicated_cid: u64,
+    pub operation: SharedObjectOperation,
     pub security_level: SecurityLevel,
 }
 
@@ -65,6 +103,27 @@ pub struct GroupBroadcastCommand {
     pub command: GroupBroadcast,
 }
 
+/// Advertises new capabilities to the adjacent node, such that both renegotiate the protocol
+/// features used by the session without reconnecting
+pub struct RenegotiateProtocol {
+    pub implicated_cid: u64,
+    pub capabilities: ProtocolCapabilities,
+}
+
+/// Sends an authenticated probe to the server or a peer, measuring the round-trip time
+pub struct Ping {
+    pub implicated_cid: u64,
+    pub v_conn_type: VirtualTargetType,
+}
+
+/// Opens a named channel to the server or a peer. Both endpoints must open a channel of the
+/// same name in order to communicate through it
+pub struct OpenNamedChannel {
+    pub implicated_cid: u64,
+    pub v_conn_type: VirtualTargetType,
+    pub name: String,
+}
+
 pub struct DisconnectFromHypernode {
     pub implicated_cid: u64,
     pub v_conn_type: VirtualConnectionType,
@@ -86,16 +145,46 @@ pub enum NodeRequest {
     ReKey(ReKey),
     /// Sends or updates a file
     SendObject(SendObject),
+    /// Sends a file, transmitting only the changes if the receiver has a previous version
+    SendObjectDelta(SendObjectDelta),
+    /// Sends a file, transmitting only the hashes of chunks the receiver has cached
+    SendObjectDeduplicated(SendObjectDeduplicated),
     /// Pulls a file from the remote virtual encrypted filesystem
     PullObject(PullObject),
     /// Deletes a file from the remote virtual encrypted filesystem
     DeleteObject(DeleteObject),
+    /// Creates, lists or shares directories inside the remote virtual encrypted filesystem
+    ReVFSDirectory(ReVFSDirectory),
+    /// Shares, or accesses, objects stored on the server under the server's access control
+    SharedObject(SharedObject),
     /// A group-message related command
     GroupBroadcastCommand(GroupBroadcastCommand),
+    /// Renegotiates the protocol capabilities of a connected session
+    RenegotiateProtocol(RenegotiateProtocol),
+    /// Measures the round-trip time to the server or a peer
+    Ping(Ping),
+    /// Opens a named channel over an existing connection
+    OpenNamedChannel(OpenNamedChannel),
     /// Tells the server to disconnect a session (implicated cid, target_cid)
     DisconnectFromHypernode(DisconnectFromHypernode),
     /// Returns a list of connected sessions
     GetActiveSessions,
+    /// Returns the number of sessions reaped in each pre-connect stage
+    GetReapedSessions,
+    /// Returns a snapshot of the resources held by the node, used to detect leaks
+    GetResourceCounters,
+    /// Returns the virtual connections held by each session, used to detect leaked connections
+    GetVirtualConnections,
+    /// Returns the queue and throughput of the key exchanges computed on behalf of unauthenticated handshakes
+    GetCryptoOffloadMetrics,
+    /// Returns how the packets of the multipath transfers sent by the node were split across paths
+    GetMultipathMetrics,
+    /// Returns how many chunks of the deduplicated transfers sent by the node were cached by their receivers
+    GetDeduplicationMetrics,
+    /// Returns the traffic relayed on behalf of each session. Empty unless the node serves as a relay
+    GetRelayUsage,
+    /// Returns the number of inbound handshakes that failed for each reason
+    GetHandshakeFailures,
     /// shutdown signal
     Shutdown,
 }
@@ -1,12 +1,28 @@
-use crate::prelude::{GroupBroadcast, GroupChannel, PeerChannel, PeerSignal, UdpChannel};
+use crate::prelude::{
+    AbuseAction, GroupBroadcast, GroupChannel, PeerChannel, PeerSignal, SessionRateSample,
+    UdpChannel,
+};
+use crate::proto::misc::connect_timings::ConnectTimings;
+use crate::proto::misc::crypto_offload::CryptoOffloadMetrics;
+use crate::proto::misc::deduplication::DeduplicationMetrics;
+use crate::proto::misc::handshake_failures::{HandshakeFailureCounts, HandshakeFailureReason};
+use crate::proto::misc::multipath::MultipathMetrics;
+use crate::proto::misc::protocol_capabilities::ProtocolCapabilities;
+use crate::proto::misc::provisional_reaper::ReapedSessionCounts;
+use crate::proto::misc::relay::RelayUsage;
+use crate::proto::misc::resource_counters::ResourceCounters;
+use crate::proto::misc::security_downgrade::DowngradeReason;
+use crate::proto::misc::session_security_settings::SessionSecuritySettings;
 use crate::proto::peer::peer_layer::MailboxTransfer;
 use crate::proto::remote::Ticket;
 use crate::proto::state_container::VirtualConnectionType;
 
-use citadel_user::backend::utils::ObjectTransferHandler;
+use citadel_user::backend::utils::{ObjectTransferHandler, VirtualDirEntry};
 use citadel_user::client_account::ClientNetworkAccount;
+use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
 
 #[derive(Debug)]
 pub struct RegisterOkay {
@@ -39,6 +55,7 @@ pub struct ConnectSuccess {
     pub welcome_message: String,
     pub channel: PeerChannel,
     pub udp_rx_opt: Option<tokio::sync::oneshot::Receiver<UdpChannel>>,
+    pub timings: ConnectTimings,
 }
 
 #[derive(Debug)]
@@ -46,6 +63,8 @@ pub struct ConnectFail {
     pub ticket: Ticket,
     pub cid_opt: Option<u64>,
     pub error_message: String,
+    /// If set, the node is load-balanced, and the account is held by the node at this address
+    pub redirect: Option<SocketAddr>,
 }
 
 #[derive(Debug)]
@@ -121,19 +140,180 @@ pub struct PeerChannelCreated {
     pub udp_rx_opt: Option<tokio::sync::oneshot::Receiver<UdpChannel>>,
 }
 
+/// The kernel panicked while handling an event
+#[derive(Debug)]
+pub struct KernelPanicked {
+    /// The ticket of the event that triggered the panic, if any
+    pub ticket: Option<Ticket>,
+    /// The variant of the event that triggered the panic
+    pub event: &'static str,
+    /// The message the kernel panicked with
+    pub message: String,
+}
+
+/// A peer connection was negotiated to weaker security settings than those configured locally
+#[derive(Debug)]
+pub struct SecurityDowngrade {
+    pub ticket: Ticket,
+    pub implicated_cid: u64,
+    pub peer_cid: u64,
+    /// The settings configured locally
+    pub preferred: SessionSecuritySettings,
+    /// The settings the connection was negotiated to
+    pub negotiated: SessionSecuritySettings,
+    /// Each parameter weaker than preferred
+    pub reasons: Vec<DowngradeReason>,
+    /// True if the connection was refused, per the [`DowngradePolicy`](crate::prelude::DowngradePolicy)
+    pub refused: bool,
+}
+
+/// The [`AbuseDetector`](crate::prelude::AbuseDetector) took action against a client
+#[derive(Debug)]
+pub struct AbuseDetected {
+    pub implicated_cid: u64,
+    pub remote_addr: SocketAddr,
+    /// The traffic sent by the client during the window that triggered the action
+    pub sample: SessionRateSample,
+    pub action: AbuseAction,
+}
+
+/// An inbound handshake failed before a session was established
+#[derive(Debug)]
+pub struct HandshakeFailed {
+    pub peer_addr: SocketAddr,
+    /// The CID the client attempted to connect as, if the handshake progressed far enough to tell
+    pub cid_opt: Option<u64>,
+    pub reason: HandshakeFailureReason,
+    pub message: String,
+}
+
+#[derive(Debug)]
+pub struct HandshakeFailuresResult {
+    pub ticket: Ticket,
+    pub counts: HandshakeFailureCounts,
+}
+
 #[derive(Debug)]
 pub struct SessionList {
     pub ticket: Ticket,
     pub sessions: Vec<u64>,
 }
 
+#[derive(Debug)]
+pub struct ReapedSessions {
+    pub ticket: Ticket,
+    pub counts: ReapedSessionCounts,
+}
+
+#[derive(Debug)]
+pub struct ResourceCountersResult {
+    pub ticket: Ticket,
+    pub counters: ResourceCounters,
+}
+
+#[derive(Debug)]
+pub struct CryptoOffloadMetricsResult {
+    pub ticket: Ticket,
+    pub metrics: CryptoOffloadMetrics,
+}
+
+#[derive(Debug)]
+pub struct MultipathMetricsResult {
+    pub ticket: Ticket,
+    pub metrics: MultipathMetrics,
+}
+
+#[derive(Debug)]
+pub struct DeduplicationMetricsResult {
+    pub ticket: Ticket,
+    pub metrics: DeduplicationMetrics,
+}
+
+#[derive(Debug)]
+pub struct RelayUsageResult {
+    pub ticket: Ticket,
+    /// The traffic relayed on behalf of each session, keyed by the CID of the session
+    pub usage: HashMap<u64, RelayUsage>,
+}
+
+#[derive(Debug)]
+pub struct VirtualConnections {
+    pub ticket: Ticket,
+    /// The virtual connections of each session, keyed by the CID of the session
+    pub connections: HashMap<u64, Vec<VirtualConnectionType>>,
+}
+
+#[derive(Debug)]
+pub struct ProtocolRenegotiated {
+    pub ticket: Ticket,
+    pub implicated_cid: u64,
+    /// The capabilities common to both endpoints
+    pub capabilities: ProtocolCapabilities,
+}
+
+#[derive(Debug)]
+pub struct PingResult {
+    pub ticket: Ticket,
+    pub implicated_cid: u64,
+    /// Zero if the ping was sent to the server
+    pub peer_cid: u64,
+    /// The time between sending the probe and receiving its response
+    pub rtt: Duration,
+    /// The time the remote endpoint spent processing the probe, which is included in the `rtt`
+    pub remote_processing_time: Duration,
+}
+
+#[derive(Debug)]
+pub struct NamedChannelOpened {
+    pub ticket: Ticket,
+    pub implicated_cid: u64,
+    pub channel: PeerChannel,
+}
+
+#[derive(Debug)]
+pub struct UdpChannelCreated {
+    pub ticket: Ticket,
+    pub implicated_cid: u64,
+    /// The UDP channel of a session that was enabled after the session connected
+    pub channel: UdpChannel,
+}
+
 #[derive(Debug)]
 pub struct ReVFSResult {
     pub error_message: Option<String>,
     pub data: Option<PathBuf>,
+    /// Present when the request listed a directory
+    pub entries: Option<Vec<VirtualDirEntry>>,
+    pub ticket: Ticket,
+}
+
+#[derive(Debug)]
+pub struct SharedObjectResult {
+    pub error_message: Option<String>,
     pub ticket: Ticket,
 }
 
+/// When and in which order an event was emitted, passed alongside each event to
+/// [`NetKernel::on_node_event_received_with_metadata`]. Since the kernel handles events
+/// concurrently, events may be handled out of order, in which case `sequence` restores the order
+/// among the events sharing a `session_id`
+///
+/// [`NetKernel::on_node_event_received_with_metadata`]: crate::prelude::NetKernel::on_node_event_received_with_metadata
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct EventMetadata {
+    /// Identifies the session that emitted the event, unique among the sessions of the node. `None`
+    /// for events not belonging to any session, which are numbered by the node
+    pub session_id: Option<u64>,
+    /// Increases by one with each event emitted under `session_id`, starting at zero
+    pub sequence: u64,
+    /// When the protocol emitted the event, i.e., once it finished processing whatever caused the
+    /// event. This excludes the time the inbound packet, if any, spent queued before processing
+    pub emitted_at: SystemTime,
+    /// When the event was passed to the kernel. The difference to `emitted_at` is the time the
+    /// event waited on the kernel
+    pub delivered_at: SystemTime,
+}
+
 /// This type is for relaying results between the lower-level protocol and the higher-level kernel
 #[derive(Debug)]
 pub enum NodeResult {
@@ -149,6 +329,7 @@ pub enum NodeResult {
     ConnectFail(ConnectFail),
     ReKeyResult(ReKeyResult),
     ReVFS(ReVFSResult),
+    SharedObject(SharedObjectResult),
     /// The outbound request was rejected
     OutboundRequestRejected(OutboundRequestRejected),
     /// For file transfers. Implicated CID, Peer/Target CID, object ID
@@ -169,6 +350,39 @@ pub enum NodeResult {
     PeerChannelCreated(PeerChannelCreated),
     /// A list of running sessions
     SessionList(SessionList),
+    /// The number of sessions reaped in each pre-connect stage
+    ReapedSessions(ReapedSessions),
+    /// A snapshot of the resources held by the node, used to detect leaks
+    ResourceCounters(ResourceCountersResult),
+    /// The virtual connections held by each session
+    VirtualConnections(VirtualConnections),
+    /// The key exchanges computed on behalf of unauthenticated handshakes
+    CryptoOffloadMetrics(CryptoOffloadMetricsResult),
+    /// How the packets of multipath transfers were split across paths
+    MultipathMetrics(MultipathMetricsResult),
+    /// How many chunks of deduplicated transfers were cached by their receivers
+    DeduplicationMetrics(DeduplicationMetricsResult),
+    /// The traffic relayed on behalf of each session
+    RelayUsage(RelayUsageResult),
+    /// The connected nodes renegotiated their protocol capabilities
+    ProtocolRenegotiated(ProtocolRenegotiated),
+    /// UDP was enabled for a session that connected without UDP
+    UdpChannelCreated(UdpChannelCreated),
+    /// A ping sent to the server or a peer was answered
+    PingResult(PingResult),
+    /// A named channel was opened over an existing connection
+    NamedChannelOpened(NamedChannelOpened),
+    /// The kernel panicked while handling an event, and the [`KernelPanicPolicy`](crate::prelude::KernelPanicPolicy)
+    /// allowed the kernel to continue
+    KernelPanicked(KernelPanicked),
+    /// A peer connection was negotiated to weaker security than locally preferred
+    SecurityDowngrade(SecurityDowngrade),
+    /// The abuse detector warned about, throttled or disconnected a client
+    AbuseDetected(AbuseDetected),
+    /// An inbound handshake failed
+    HandshakeFailed(HandshakeFailed),
+    /// The number of inbound handshakes that failed for each reason
+    HandshakeFailures(HandshakeFailuresResult),
     /// For shutdowns
     Shutdown,
 }
@@ -178,6 +392,47 @@ impl NodeResult {
         matches!(self, NodeResult::ConnectSuccess(ConnectSuccess { .. }))
     }
 
+    /// The name of the variant, used to describe an event without formatting its contents
+    pub fn variant_name(&self) -> &'static str {
+        match self {
+            NodeResult::RegisterOkay(_) => "RegisterOkay",
+            NodeResult::RegisterFailure(_) => "RegisterFailure",
+            NodeResult::DeRegistration(_) => "DeRegistration",
+            NodeResult::ConnectSuccess(_) => "ConnectSuccess",
+            NodeResult::ConnectFail(_) => "ConnectFail",
+            NodeResult::ReKeyResult(_) => "ReKeyResult",
+            NodeResult::ReVFS(_) => "ReVFS",
+            NodeResult::SharedObject(_) => "SharedObject",
+            NodeResult::OutboundRequestRejected(_) => "OutboundRequestRejected",
+            NodeResult::ObjectTransferHandle(_) => "ObjectTransferHandle",
+            NodeResult::MailboxDelivery(_) => "MailboxDelivery",
+            NodeResult::PeerEvent(_) => "PeerEvent",
+            NodeResult::GroupChannelCreated(_) => "GroupChannelCreated",
+            NodeResult::GroupEvent(_) => "GroupEvent",
+            NodeResult::Disconnect(_) => "Disconnect",
+            NodeResult::InternalServerError(_) => "InternalServerError",
+            NodeResult::PeerChannelCreated(_) => "PeerChannelCreated",
+            NodeResult::SessionList(_) => "SessionList",
+            NodeResult::ReapedSessions(_) => "ReapedSessions",
+            NodeResult::ResourceCounters(_) => "ResourceCounters",
+            NodeResult::VirtualConnections(_) => "VirtualConnections",
+            NodeResult::CryptoOffloadMetrics(_) => "CryptoOffloadMetrics",
+            NodeResult::MultipathMetrics(_) => "MultipathMetrics",
+            NodeResult::DeduplicationMetrics(_) => "DeduplicationMetrics",
+            NodeResult::RelayUsage(_) => "RelayUsage",
+            NodeResult::ProtocolRenegotiated(_) => "ProtocolRenegotiated",
+            NodeResult::UdpChannelCreated(_) => "UdpChannelCreated",
+            NodeResult::PingResult(_) => "PingResult",
+            NodeResult::NamedChannelOpened(_) => "NamedChannelOpened",
+            NodeResult::KernelPanicked(_) => "KernelPanicked",
+            NodeResult::SecurityDowngrade(_) => "SecurityDowngrade",
+            NodeResult::AbuseDetected(_) => "AbuseDetected",
+            NodeResult::HandshakeFailed(_) => "HandshakeFailed",
+            NodeResult::HandshakeFailures(_) => "HandshakeFailures",
+            NodeResult::Shutdown => "Shutdown",
+        }
+    }
+
     pub fn ticket(&self) -> Option<Ticket> {
         match self {
             NodeResult::RegisterOkay(RegisterOkay {
@@ -199,6 +454,7 @@ impl NodeResult {
                 ticket: t,
                 cid_opt: _,
                 error_message: _,
+                redirect: _,
             }) => Some(*t),
             NodeResult::OutboundRequestRejected(OutboundRequestRejected {
                 ticket: t,
@@ -239,9 +495,30 @@ impl NodeResult {
                 ticket: t,
                 sessions: _,
             }) => Some(*t),
+            NodeResult::ReapedSessions(ReapedSessions { ticket, .. }) => Some(*ticket),
+            NodeResult::ResourceCounters(ResourceCountersResult { ticket, .. }) => Some(*ticket),
+            NodeResult::VirtualConnections(VirtualConnections { ticket, .. }) => Some(*ticket),
+            NodeResult::CryptoOffloadMetrics(CryptoOffloadMetricsResult { ticket, .. }) => {
+                Some(*ticket)
+            }
+            NodeResult::MultipathMetrics(MultipathMetricsResult { ticket, .. }) => Some(*ticket),
+            NodeResult::DeduplicationMetrics(DeduplicationMetricsResult { ticket, .. }) => {
+                Some(*ticket)
+            }
+            NodeResult::RelayUsage(RelayUsageResult { ticket, .. }) => Some(*ticket),
+            NodeResult::ProtocolRenegotiated(ProtocolRenegotiated { ticket, .. }) => Some(*ticket),
+            NodeResult::UdpChannelCreated(UdpChannelCreated { ticket, .. }) => Some(*ticket),
+            NodeResult::PingResult(PingResult { ticket, .. }) => Some(*ticket),
+            NodeResult::NamedChannelOpened(NamedChannelOpened { ticket, .. }) => Some(*ticket),
+            NodeResult::KernelPanicked(KernelPanicked { ticket, .. }) => *ticket,
+            NodeResult::SecurityDowngrade(SecurityDowngrade { ticket, .. }) => Some(*ticket),
+            NodeResult::AbuseDetected(_) => None,
+            NodeResult::HandshakeFailed(_) => None,
+            NodeResult::HandshakeFailures(HandshakeFailuresResult { ticket, .. }) => Some(*ticket),
             NodeResult::Shutdown => None,
             NodeResult::ReKeyResult(ReKeyResult { ticket, .. }) => Some(*ticket),
             NodeResult::ReVFS(ReVFSResult { ticket, .. }) => Some(*ticket),
+            NodeResult::SharedObject(SharedObjectResult { ticket, .. }) => Some(*ticket),
         }
     }
 }
@@ -1,5 +1,6 @@
 //use futures::channel::mpsc::{UnboundedSender, SendError, UnboundedReceiver, TrySendError};
 use crate::error::NetworkError;
+use crate::proto::node_result::NodeResult;
 use crate::proto::packet::packet_flags;
 use bytes::BytesMut;
 use citadel_user::re_exports::__private::Formatter;
@@ -7,6 +8,9 @@ use futures::task::{Context, Poll};
 use futures::Sink;
 use std::net::SocketAddr;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::SystemTime;
 pub use tokio::sync::mpsc::{
     error::SendError, error::TrySendError, Receiver, Sender, UnboundedReceiver,
     UnboundedSender as UnboundedSenderInner,
@@ -32,6 +36,75 @@ impl<T> UnboundedSender<T> {
     }
 }
 
+/// The session that emitted an event, the position of the event among the events of that
+/// session, and when it was emitted
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct EventStamp {
+    pub(crate) session_id: Option<u64>,
+    pub(crate) sequence: u64,
+    pub(crate) emitted_at: SystemTime,
+}
+
+/// Sends events to the kernel, stamping each with the time it was emitted and its position among
+/// the events of its session. Clones share the numbering, whereas [`Self::for_session`] begins anew
+/// under a new session id
+pub struct KernelEventSender {
+    inner: UnboundedSenderInner<(NodeResult, EventStamp)>,
+    session_id: Option<u64>,
+    sequence: Arc<AtomicU64>,
+    next_session_id: Arc<AtomicU64>,
+}
+
+impl Clone for KernelEventSender {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            session_id: self.session_id,
+            sequence: self.sequence.clone(),
+            next_session_id: self.next_session_id.clone(),
+        }
+    }
+}
+
+pub(crate) fn kernel_event_channel() -> (
+    KernelEventSender,
+    UnboundedReceiver<(NodeResult, EventStamp)>,
+) {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let tx = KernelEventSender {
+        inner: tx,
+        session_id: None,
+        sequence: Arc::new(AtomicU64::new(0)),
+        next_session_id: Arc::new(AtomicU64::new(0)),
+    };
+    (tx, rx)
+}
+
+impl KernelEventSender {
+    #[inline]
+    pub fn unbounded_send(&self, item: NodeResult) -> Result<(), SendError<NodeResult>> {
+        let stamp = EventStamp {
+            session_id: self.session_id,
+            sequence: self.sequence.fetch_add(1, Ordering::Relaxed),
+            emitted_at: SystemTime::now(),
+        };
+        self.inner
+            .send((item, stamp))
+            .map_err(|SendError((item, _))| SendError(item))
+    }
+
+    /// Returns a sender to the same kernel whose events are numbered independently of this one,
+    /// under a session id unique to the node
+    pub fn for_session(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            session_id: Some(self.next_session_id.fetch_add(1, Ordering::Relaxed)),
+            sequence: Arc::new(AtomicU64::new(0)),
+            next_session_id: self.next_session_id.clone(),
+        }
+    }
+}
+
 pub fn channel<T>(len: usize) -> (Sender<T>, Receiver<T>) {
     tokio::sync::mpsc::channel(len)
 }
@@ -70,7 +143,6 @@ pub struct OutboundUdpSender {
     sender: UnboundedSender<(u8, BytesMut)>,
     local_addr: SocketAddr,
     remote_addr: SocketAddr,
-    pub(crate) needs_manual_ka: bool,
 }
 
 impl OutboundUdpSender {
@@ -78,13 +150,11 @@ impl OutboundUdpSender {
         sender: UnboundedSender<(u8, BytesMut)>,
         local_addr: SocketAddr,
         remote_addr: SocketAddr,
-        needs_manual_ka: bool,
     ) -> Self {
         Self {
             sender,
             local_addr,
             remote_addr,
-            needs_manual_ka,
         }
     }
 
@@ -94,6 +164,13 @@ impl OutboundUdpSender {
             .map_err(|err| NetworkError::Generic(err.to_string()))
     }
 
+    /// Sends an already-crafted group payload packet of a multipath transfer
+    pub fn send_group_stripe(&self, packet: BytesMut) -> Result<(), NetworkError> {
+        self.sender
+            .unbounded_send((packet_flags::cmd::aux::udp::GROUP_STRIPE, packet))
+            .map_err(|err| NetworkError::Generic(err.to_string()))
+    }
+
     pub fn send_keep_alive(&self) -> bool {
         self.sender
             .unbounded_send((
@@ -219,3 +296,45 @@ impl<T> Sink<T> for UnboundedSender<T> {
         Poll::Ready(Ok(()))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::node_result::NodeResult;
+    use crate::proto::outbound_sender::kernel_event_channel;
+
+    #[tokio::test]
+    async fn test_kernel_event_sequence() {
+        let (node_tx, mut rx) = kernel_event_channel();
+        let session_tx = node_tx.for_session();
+        let other_session_tx = node_tx.for_session();
+
+        node_tx.unbounded_send(NodeResult::Shutdown).unwrap();
+        session_tx.unbounded_send(NodeResult::Shutdown).unwrap();
+        session_tx
+            .clone()
+            .unbounded_send(NodeResult::Shutdown)
+            .unwrap();
+        node_tx.unbounded_send(NodeResult::Shutdown).unwrap();
+        other_session_tx
+            .unbounded_send(NodeResult::Shutdown)
+            .unwrap();
+
+        let mut stamps = vec![];
+        while let Ok((_, stamp)) = rx.try_recv() {
+            stamps.push((stamp.session_id, stamp.sequence));
+        }
+
+        // clones share the numbering of the session, whereas each session is numbered anew under
+        // its own id
+        assert_eq!(
+            stamps,
+            vec![
+                (None, 0),
+                (Some(0), 0),
+                (Some(0), 1),
+                (None, 1),
+                (Some(1), 0)
+            ]
+        );
+    }
+}
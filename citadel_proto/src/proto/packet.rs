@@ -22,6 +22,11 @@ pub(crate) mod packet_flags {
             pub(crate) const FILE: u8 = 9;
             pub(crate) const UDP: u8 = 10;
             pub(crate) const HOLE_PUNCH: u8 = 11;
+            pub(crate) const CAPABILITIES: u8 = 12;
+            /// Guards the key exchange of registrations and connections against floods
+            pub(crate) const CHALLENGE: u8 = 13;
+            /// Measures the round-trip time to the adjacent endpoint
+            pub(crate) const PING: u8 = 14;
         }
 
         pub(crate) mod aux {
@@ -34,6 +39,8 @@ pub(crate) mod packet_flags {
                 pub(crate) const GROUP_PAYLOAD: u8 = 2;
                 /// Bob sends this to Alice once he reconstructs a wave. This allows alice to free memory on her side
                 pub(crate) const WAVE_ACK: u8 = 3;
+                /// Bob sends this to Alice each time his application reads a portion of the flow control window
+                pub(crate) const WINDOW_UPDATE: u8 = 4;
             }
 
             pub(crate) mod do_connect {
@@ -85,6 +92,8 @@ pub(crate) mod packet_flags {
                 pub(crate) const FAILURE: u8 = 7;
                 pub(crate) const BEGIN_CONNECT: u8 = 8;
                 pub(crate) const HALT: u8 = 10;
+                // a node of a load-balanced deployment sends this to redirect alice to the node holding her account
+                pub(crate) const REDIRECT: u8 = 11;
             }
 
             /*
@@ -111,12 +120,49 @@ pub(crate) mod packet_flags {
                 pub(crate) const REVFS_DELETE: u8 = 3;
                 pub(crate) const REVFS_ACK: u8 = 4;
                 pub(crate) const REVFS_PULL_ACK: u8 = 5;
+                /// Pause, resume or cancel an in-flight transfer
+                pub(crate) const TRANSFER_CONTROL: u8 = 6;
+                /// Declares the per-chunk hashes of an outbound object
+                pub(crate) const TRANSFER_MANIFEST: u8 = 7;
+                pub(crate) const REVFS_DIR: u8 = 8;
+                pub(crate) const REVFS_DIR_ACK: u8 = 9;
+                pub(crate) const SHARED_OBJECT: u8 = 10;
+                pub(crate) const SHARED_OBJECT_ACK: u8 = 11;
+                pub(crate) const DELTA_SIGNATURE_REQUEST: u8 = 12;
+                pub(crate) const DELTA_SIGNATURE: u8 = 13;
+                pub(crate) const CHUNK_QUERY: u8 = 14;
+                pub(crate) const CHUNK_QUERY_ACK: u8 = 15;
             }
 
             pub(crate) mod udp {
                 pub(crate) const STREAM: u8 = 0;
                 pub(crate) const KEEP_ALIVE: u8 = 1;
                 pub(crate) const HOLE_PUNCH: u8 = 2;
+                pub(crate) const GROUP_STRIPE: u8 = 3;
+                /// A keep-alive asking the receiver whether the source address changed
+                pub(crate) const KEEP_ALIVE_PROBE: u8 = 4;
+                /// Answers a KEEP_ALIVE_PROBE
+                pub(crate) const KEEP_ALIVE_ACK: u8 = 5;
+            }
+
+            pub(crate) mod capabilities {
+                /// Declares the capabilities of the sender, prompting the receiver to renegotiate
+                pub(crate) const ADVERTISE: u8 = 0;
+                /// Returns the capabilities of the receiver of an ADVERTISE
+                pub(crate) const ACK: u8 = 1;
+            }
+
+            pub(crate) mod challenge {
+                /// Bob demands a solved puzzle before handling Alice's key exchange
+                pub(crate) const ISSUE: u8 = 0;
+                /// Alice returns the solution, alongside the packet that prompted the challenge
+                pub(crate) const RESPONSE: u8 = 1;
+            }
+
+            pub(crate) mod ping {
+                pub(crate) const REQUEST: u8 = 0;
+                /// Returns the time the receiver of a REQUEST spent processing it
+                pub(crate) const RESPONSE: u8 = 1;
             }
         }
     }
@@ -198,6 +244,24 @@ pub struct HdpPacket<B: HdpBuffer = BytesMut> {
 
 pub type ParsedPacket<'a> = (LayoutVerified<&'a [u8], HdpHeader>, &'a [u8]);
 
+/// The reasons an inbound packet may fail to parse
+#[derive(Debug, Eq, PartialEq)]
+pub enum PacketParseError {
+    /// The packet is shorter than the header
+    Truncated { len: usize },
+}
+
+impl std::fmt::Display for PacketParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Truncated { len } => write!(
+                f,
+                "Packet of {len} bytes is shorter than the {HDP_HEADER_BYTE_LEN} byte header"
+            ),
+        }
+    }
+}
+
 impl<B: HdpBuffer> HdpPacket<B> {
     /// When a packet comes inbound, this should be used to wrap the packet
     pub fn new_recv(packet: B, remote_peer: SocketAddr, local_port: u16) -> Self {
@@ -210,7 +274,14 @@ impl<B: HdpBuffer> HdpPacket<B> {
 
     /// Parses the zerocopy header
     pub fn parse(&self) -> Option<ParsedPacket> {
-        LayoutVerified::new_from_prefix(self.packet.as_ref())
+        self.try_parse().ok()
+    }
+
+    /// Parses the zerocopy header, returning the reason upon failure
+    pub fn try_parse(&self) -> Result<ParsedPacket, PacketParseError> {
+        LayoutVerified::new_from_prefix(self.packet.as_ref()).ok_or(PacketParseError::Truncated {
+            len: self.packet.len(),
+        })
     }
 
     /// Creates a packet out of the inner device
@@ -225,7 +296,9 @@ impl<B: HdpBuffer> HdpPacket<B> {
 
     /// Splits the header's bytes and the header's in Bytes/Mut form
     pub fn decompose(mut self) -> (B::Immutable, B, SocketAddr, u16) {
-        let header_bytes = self.packet.split_to(HDP_HEADER_BYTE_LEN).freeze();
+        // a truncated packet yields a truncated header, which then fails to parse downstream
+        let header_len = HDP_HEADER_BYTE_LEN.min(self.packet.len());
+        let header_bytes = self.packet.split_to(header_len).freeze();
         let payload_bytes = self.packet;
         let remote_peer = self.remote_peer;
         let local_port = self.local_port;
@@ -6,7 +6,8 @@ use netbeam::time_tracker::TimeTracker;
 
 use crate::constants::HDP_HEADER_BYTE_LEN;
 use crate::error::NetworkError;
-use crate::proto::outbound_sender::OutboundPrimaryStreamSender;
+use crate::proto::misc::multipath::{MultipathCongestion, StripedGroup, MAX_STRIPED_PACKET_LEN};
+use crate::proto::outbound_sender::{OutboundPrimaryStreamSender, OutboundUdpSender};
 use crate::proto::remote::Ticket;
 use crate::proto::state_container::VirtualTargetType;
 use citadel_crypt::scramble::crypt_splitter::oneshot_unencrypted_group_unified;
@@ -33,6 +34,11 @@ impl SecureProtocolPacket {
     pub(crate) fn from_inner(inner: SecureMessagePacket<HDP_HEADER_BYTE_LEN>) -> Self {
         Self { inner }
     }
+
+    /// The length of the message, as delivered to the receiver
+    pub(crate) fn message_len(&self) -> usize {
+        self.inner.message_len()
+    }
 }
 
 impl<T: AsRef<[u8]>> From<T> for SecureProtocolPacket {
@@ -62,7 +68,7 @@ pub struct GroupTransmitter {
     pub(crate) group_transmitter: GroupSenderDevice<HDP_HEADER_BYTE_LEN>,
     /// Contained within Self::group_transmitter, but is here for convenience
     group_config: GroupReceiverConfig,
-    object_id: u32,
+    pub(crate) object_id: u32,
     pub group_id: u64,
     /// For interfacing with the higher-level kernel
     ticket: Ticket,
@@ -218,6 +224,57 @@ impl GroupTransmitter {
 
         true
     }
+
+    /// Like [`Self::transmit_tcp_file_transfer`], but entrusts a share of the payload packets to the
+    /// UDP stream as dictated by `congestion`. The returned [`StripedGroup`] retains the packets sent
+    /// through UDP until acknowledged, so that they may be retransmitted through the primary stream
+    pub(crate) fn transmit_multipath_file_transfer(
+        &mut self,
+        to_udp_stream: &OutboundUdpSender,
+        congestion: &mut MultipathCongestion,
+    ) -> StripedGroup {
+        let mut striped = StripedGroup::default();
+        let mut packets = self.group_transmitter.take_all_packets();
+        // earlier waves are sent first so that overtaken waves may be detected as lost
+        packets.sort_by_key(|packet| packet.vector.wave_id);
+
+        log::trace!(target: "citadel", "[MULTIPATH] Will transfer {} packets w/ UDP share {}", packets.len(), congestion.udp_share());
+        for packet in packets {
+            if packet.packet.len() <= MAX_STRIPED_PACKET_LEN && congestion.next_is_udp() {
+                match to_udp_stream.send_group_stripe(packet.packet.clone()) {
+                    Ok(_) => {
+                        congestion.on_sent_via_udp();
+                        striped.on_sent(packet.vector.wave_id, packet.packet);
+                        continue;
+                    }
+
+                    Err(err) => {
+                        log::warn!(target: "citadel", "[MULTIPATH] UDP stream died ({:?}); falling back to the primary stream", err);
+                        congestion.abandon();
+                    }
+                }
+            }
+
+            congestion.on_sent_via_primary();
+            if let Err(err) = self.to_primary_stream.unbounded_send(packet.packet) {
+                log::error!(target: "citadel", "[FILE] to_primary_stream died {:?}", err);
+            }
+        }
+
+        log::trace!(target: "citadel", "Group {} has finished transmission", self.group_id);
+
+        striped
+    }
+
+    /// Resends payload packets presumed lost along the UDP path through the primary stream
+    pub(crate) fn retransmit_through_primary_stream(&self, packets: Vec<BytesMut>) {
+        log::trace!(target: "citadel", "[MULTIPATH] Retransmitting {} packets of group {} through the primary stream", packets.len(), self.group_id);
+        for packet in packets {
+            if let Err(err) = self.to_primary_stream.unbounded_send(packet) {
+                log::error!(target: "citadel", "[FILE] to_primary_stream died {:?}", err);
+            }
+        }
+    }
 }
 
 pub(crate) mod group {
@@ -229,6 +286,7 @@ pub(crate) mod group {
     use citadel_crypt::prelude::*;
 
     use crate::constants::HDP_HEADER_BYTE_LEN;
+    use crate::proto::misc::flow_control::WindowUpdate;
     use crate::proto::packet::packet_sizes;
     use crate::proto::packet::packet_sizes::GROUP_HEADER_ACK_LEN;
     use crate::proto::packet::{packet_flags, HdpHeader};
@@ -429,6 +487,39 @@ pub(crate) mod group {
             .unwrap();
         packet
     }
+
+    pub(crate) fn craft_window_update(
+        hyper_ratchet: &StackedRatchet,
+        target_cid: u64,
+        timestamp: i64,
+        update: WindowUpdate,
+        security_level: SecurityLevel,
+    ) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::GROUP_PACKET,
+            cmd_aux: packet_flags::cmd::aux::group::WINDOW_UPDATE,
+            algorithm: 0,
+            security_level: security_level.value(),
+            context_info: U128::new(0),
+            group: U64::new(0),
+            wave_id: U32::new(0),
+            session_cid: U64::new(hyper_ratchet.get_cid()),
+            drill_version: U32::new(hyper_ratchet.version()),
+            timestamp: I64::new(timestamp),
+            target_cid: U64::new(target_cid),
+        };
+
+        let mut packet =
+            BytesMut::with_capacity(HDP_HEADER_BYTE_LEN + update.serialized_size().unwrap());
+        header.inscribe_into(&mut packet);
+        update.serialize_into_buf(&mut packet).unwrap();
+
+        hyper_ratchet
+            .protect_message_packet(Some(security_level), HDP_HEADER_BYTE_LEN, &mut packet)
+            .unwrap();
+        packet
+    }
 }
 
 pub(crate) mod do_connect {
@@ -636,6 +727,7 @@ pub(crate) mod do_register {
 
     use crate::constants::HDP_HEADER_BYTE_LEN;
     use crate::proto::packet::{packet_flags, HdpHeader};
+    use citadel_crypt::argon::argon_container::ArgonCostParameters;
     use citadel_crypt::prelude::SecurityLevel;
     use citadel_crypt::stacked_ratchet::constructor::{AliceToBobTransfer, BobToAliceTransfer};
     use citadel_crypt::stacked_ratchet::StackedRatchet;
@@ -689,11 +781,19 @@ pub(crate) mod do_register {
         packet
     }
 
-    /// Bob crafts a packet with the ciphertext
+    #[derive(Serialize, Deserialize)]
+    pub(crate) struct DoRegisterStage1 {
+        pub(crate) transfer: BobToAliceTransfer,
+        /// The cost parameters Alice must hash her password under
+        pub(crate) argon_policy: ArgonCostParameters,
+    }
+
+    /// Bob crafts a packet with the ciphertext, alongside the password hashing policy of the node
     pub(crate) fn craft_stage1(
         algorithm: u8,
         timestamp: i64,
         transfer: BobToAliceTransfer,
+        argon_policy: ArgonCostParameters,
         proposed_cid: u64,
     ) -> BytesMut {
         let header = HdpHeader {
@@ -714,7 +814,12 @@ pub(crate) mod do_register {
         let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
 
         header.inscribe_into(&mut packet);
-        transfer.serialize_into(&mut packet).unwrap();
+        DoRegisterStage1 {
+            transfer,
+            argon_policy,
+        }
+        .serialize_into_buf(&mut packet)
+        .unwrap();
 
         packet
     }
@@ -722,6 +827,8 @@ pub(crate) mod do_register {
     #[derive(Serialize, Deserialize)]
     pub struct DoRegisterStage2Packet {
         pub credentials: ProposedCredentials,
+        /// The cost parameters the password was hashed under, if password-based
+        pub argon_parameters: Option<ArgonCostParameters>,
     }
 
     /// Alice sends this. The stage 3 packet contains the encrypted username, password, and full name of the registering client
@@ -752,6 +859,7 @@ pub(crate) mod do_register {
         let mut packet = BytesMut::with_capacity(total_len);
         let payload = DoRegisterStage2Packet {
             credentials: credentials.clone(),
+            argon_parameters: credentials.registration_argon_parameters(),
         };
         header.inscribe_into(&mut packet);
         payload.serialize_into_buf(&mut packet).unwrap();
@@ -764,12 +872,14 @@ pub(crate) mod do_register {
     }
 
     /// `success_message`: This is NOT encrypted in this closure. Make sure to encrypt it beforehand if necessary
+    /// `affinity_token` is zero unless the node is load-balanced
     pub(crate) fn craft_success<T: AsRef<[u8]>>(
         hyper_ratchet: &StackedRatchet,
         algorithm: u8,
         timestamp: i64,
         success_message: T,
         security_level: SecurityLevel,
+        affinity_token: u128,
     ) -> BytesMut {
         let success_message = success_message.as_ref();
         let success_message_len = success_message.len();
@@ -779,7 +889,7 @@ pub(crate) mod do_register {
             cmd_aux: packet_flags::cmd::aux::do_register::SUCCESS,
             algorithm,
             security_level: security_level.value(),
-            context_info: U128::new(0),
+            context_info: U128::new(affinity_token),
             group: U64::new(0),
             wave_id: U32::new(0),
             session_cid: U64::new(hyper_ratchet.get_cid()),
@@ -1163,10 +1273,12 @@ pub(crate) mod pre_connect {
     use citadel_crypt::stacked_ratchet::constructor::{AliceToBobTransfer, BobToAliceTransfer};
     use citadel_crypt::stacked_ratchet::StackedRatchet;
     use citadel_crypt::toolset::StaticAuxRatchet;
+    use citadel_pqcrypto::algorithm_dictionary::FIPS_MODE;
     use citadel_user::prelude::ConnectProtocol;
     use citadel_user::serialization::SyncIO;
     use citadel_wire::nat_identification::NatType;
     use serde::{Deserialize, Serialize};
+    use std::net::SocketAddr;
 
     #[derive(Serialize, Deserialize)]
     pub struct SynPacket {
@@ -1177,8 +1289,11 @@ pub(crate) mod pre_connect {
         pub nat_type: NatType,
         pub udp_mode: UdpMode,
         pub keep_alive_timeout: i64,
+        /// Whether the sender only permits FIPS-approved algorithms
+        pub restricted_mode: bool,
     }
 
+    /// The affinity token is placed in the header, such that load balancers may route by it
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn craft_syn(
         static_aux_hr: &StaticAuxRatchet,
@@ -1191,6 +1306,7 @@ pub(crate) mod pre_connect {
         session_security_settings: SessionSecuritySettings,
         peer_only_connect_protocol: ConnectProtocol,
         connect_mode: ConnectMode,
+        affinity_token: u128,
     ) -> BytesMut {
         let header = HdpHeader {
             protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
@@ -1198,7 +1314,7 @@ pub(crate) mod pre_connect {
             cmd_aux: packet_flags::cmd::aux::do_preconnect::SYN,
             algorithm: 0,
             security_level: security_level.value(),
-            context_info: U128::new(0),
+            context_info: U128::new(affinity_token),
             group: U64::new(0),
             wave_id: U32::new(0),
             session_cid: U64::new(static_aux_hr.get_cid()),
@@ -1218,6 +1334,7 @@ pub(crate) mod pre_connect {
             udp_mode,
             keep_alive_timeout,
             nat_type,
+            restricted_mode: FIPS_MODE,
         }
         .serialize_into_buf(&mut packet)
         .unwrap();
@@ -1232,6 +1349,8 @@ pub(crate) mod pre_connect {
     pub struct SynAckPacket {
         pub transfer: BobToAliceTransfer,
         pub nat_type: NatType,
+        /// Whether the sender only permits FIPS-approved algorithms
+        pub restricted_mode: bool,
     }
 
     pub(crate) fn craft_syn_ack(
@@ -1259,9 +1378,13 @@ pub(crate) mod pre_connect {
         let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
         header.inscribe_into(&mut packet);
 
-        SynAckPacket { transfer, nat_type }
-            .serialize_into_buf(&mut packet)
-            .unwrap();
+        SynAckPacket {
+            transfer,
+            nat_type,
+            restricted_mode: FIPS_MODE,
+        }
+        .serialize_into_buf(&mut packet)
+        .unwrap();
 
         static_aux_hr
             .protect_message_packet(Some(security_level), HDP_HEADER_BYTE_LEN, &mut packet)
@@ -1404,6 +1527,32 @@ pub(crate) mod pre_connect {
 
         packet
     }
+
+    /// Redirects the client to the node holding its account. Unencrypted, since this node does
+    /// not hold the ratchets of the client
+    pub fn craft_redirect(prev_header: &HdpHeader, node_addr: SocketAddr) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::DO_PRE_CONNECT,
+            cmd_aux: packet_flags::cmd::aux::do_preconnect::REDIRECT,
+            algorithm: 0,
+            security_level: 0,
+            context_info: U128::new(0),
+            group: U64::new(0),
+            wave_id: U32::new(0),
+            session_cid: prev_header.session_cid,
+            drill_version: prev_header.drill_version,
+            timestamp: prev_header.timestamp,
+            target_cid: U64::new(0),
+        };
+
+        let node_addr = node_addr.to_string();
+        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN + node_addr.len());
+        header.inscribe_into(&mut packet);
+        packet.put(node_addr.as_bytes());
+
+        packet
+    }
 }
 
 pub(crate) mod peer_cmd {
@@ -1583,8 +1732,15 @@ pub(crate) mod file {
     use crate::proto::remote::Ticket;
     use crate::proto::state_container::VirtualTargetType;
     use bytes::BytesMut;
+    use citadel_crypt::chunk_dedup::ChunkHash;
+    use citadel_crypt::compression::CompressionAlgorithm;
+    use citadel_crypt::delta_sync::FileSignature;
     use citadel_crypt::stacked_ratchet::StackedRatchet;
-    use citadel_user::backend::utils::VirtualObjectMetadata;
+    use citadel_crypt::transfer_manifest::TransferManifest;
+    use citadel_user::backend::utils::{
+        ObjectTransferControl, ObjectTransferOrientation, ReVFSDirectoryOperation,
+        SharedObjectOperation, VirtualDirEntry, VirtualObjectMetadata,
+    };
     use citadel_user::serialization::SyncIO;
     use serde::{Deserialize, Serialize};
     use std::path::PathBuf;
@@ -1595,6 +1751,8 @@ pub(crate) mod file {
         pub file_metadata: VirtualObjectMetadata,
         pub virtual_target: VirtualTargetType,
         pub local_encryption_level: Option<SecurityLevel>,
+        // offered by the sender. Only applied if echoed back in the [`FileHeaderAckPacket`]
+        pub compression: Option<CompressionAlgorithm>,
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -1607,6 +1765,7 @@ pub(crate) mod file {
         file_metadata: VirtualObjectMetadata,
         timestamp: i64,
         local_encryption_level: Option<SecurityLevel>,
+        compression: Option<CompressionAlgorithm>,
     ) -> BytesMut {
         let header = HdpHeader {
             protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
@@ -1630,6 +1789,7 @@ pub(crate) mod file {
             file_metadata,
             virtual_target,
             local_encryption_level,
+            compression,
         };
 
         payload.serialize_into_buf(&mut packet).unwrap();
@@ -1646,6 +1806,10 @@ pub(crate) mod file {
         pub success: bool,
         pub virtual_target: VirtualTargetType,
         pub object_id: u32,
+        // the compression accepted by the receiver, if any was offered
+        pub compression: Option<CompressionAlgorithm>,
+        // why the receiver declined the transfer, if it did
+        pub rejection_reason: Option<String>,
     }
 
     #[allow(clippy::too_many_arguments)]
@@ -1658,6 +1822,8 @@ pub(crate) mod file {
         security_level: SecurityLevel,
         virtual_target: VirtualTargetType,
         timestamp: i64,
+        compression: Option<CompressionAlgorithm>,
+        rejection_reason: Option<String>,
     ) -> BytesMut {
         let header = HdpHeader {
             protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
@@ -1681,6 +1847,8 @@ pub(crate) mod file {
             success,
             virtual_target,
             object_id,
+            compression,
+            rejection_reason,
         };
 
         payload.serialize_into_buf(&mut packet).unwrap();
@@ -1697,10 +1865,13 @@ pub(crate) mod file {
         pub virtual_path: PathBuf,
         pub delete_on_pull: bool,
         pub security_level: SecurityLevel,
+        // None when the sender pulls from its own RE-VFS
+        pub owner_cid: Option<u64>,
     }
 
     /// This packet will essentially cause the receiving endpoint to emulate
     /// a FILE_HEADER with auto-accept on
+    #[allow(clippy::too_many_arguments)]
     pub fn craft_revfs_pull(
         hyper_ratchet: &StackedRatchet,
         security_level: SecurityLevel,
@@ -1709,6 +1880,7 @@ pub(crate) mod file {
         target_cid: u64,
         virtual_path: PathBuf,
         delete_on_pull: bool,
+        owner_cid: Option<u64>,
     ) -> BytesMut {
         let header = HdpHeader {
             protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
@@ -1732,6 +1904,7 @@ pub(crate) mod file {
             virtual_path,
             delete_on_pull,
             security_level,
+            owner_cid,
         };
 
         payload.serialize_into_buf(&mut packet).unwrap();
@@ -1745,6 +1918,8 @@ pub(crate) mod file {
     #[derive(Serialize, Deserialize, Debug)]
     pub struct ReVFSDeletePacket {
         pub virtual_path: PathBuf,
+        // None when the sender deletes from its own RE-VFS
+        pub owner_cid: Option<u64>,
     }
 
     pub fn craft_revfs_delete(
@@ -1754,6 +1929,7 @@ pub(crate) mod file {
         timestamp: i64,
         target_cid: u64,
         virtual_path: PathBuf,
+        owner_cid: Option<u64>,
     ) -> BytesMut {
         let header = HdpHeader {
             protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
@@ -1773,7 +1949,10 @@ pub(crate) mod file {
         let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
         header.inscribe_into(&mut packet);
 
-        let payload = ReVFSDeletePacket { virtual_path };
+        let payload = ReVFSDeletePacket {
+            virtual_path,
+            owner_cid,
+        };
 
         payload.serialize_into_buf(&mut packet).unwrap();
         hyper_ratchet
@@ -1827,23 +2006,22 @@ pub(crate) mod file {
     }
 
     #[derive(Serialize, Deserialize, Debug)]
-    pub enum ReVFSPullAckPacket {
-        Success,
-        Error { error: String },
+    pub struct ReVFSDirPacket {
+        pub operation: ReVFSDirectoryOperation,
     }
 
-    pub fn craft_revfs_pull_ack(
+    pub fn craft_revfs_dir(
         hyper_ratchet: &StackedRatchet,
         security_level: SecurityLevel,
         ticket: Ticket,
         timestamp: i64,
         target_cid: u64,
-        payload: ReVFSPullAckPacket,
+        operation: ReVFSDirectoryOperation,
     ) -> BytesMut {
         let header = HdpHeader {
             protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
             cmd_primary: packet_flags::cmd::primary::FILE,
-            cmd_aux: packet_flags::cmd::aux::file::REVFS_PULL_ACK,
+            cmd_aux: packet_flags::cmd::aux::file::REVFS_DIR,
             algorithm: 0,
             security_level: security_level.value(),
             context_info: U128::new(ticket.0),
@@ -1858,6 +2036,8 @@ pub(crate) mod file {
         let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
         header.inscribe_into(&mut packet);
 
+        let payload = ReVFSDirPacket { operation };
+
         payload.serialize_into_buf(&mut packet).unwrap();
         hyper_ratchet
             .protect_message_packet(Some(security_level), HDP_HEADER_BYTE_LEN, &mut packet)
@@ -1865,48 +2045,665 @@ pub(crate) mod file {
 
         packet
     }
-}
 
-pub(crate) mod udp {
-    use crate::constants::HDP_HEADER_BYTE_LEN;
-    use crate::proto::packet::{packet_flags, HdpHeader};
-    use bytes::BytesMut;
-    use citadel_crypt::entropy_bank::SecurityLevel;
-    use citadel_crypt::stacked_ratchet::StackedRatchet;
-    use zerocopy::{U32, U64};
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct ReVFSDirAckPacket {
+        pub entries: Option<Vec<VirtualDirEntry>>,
+        pub error_msg: Option<String>,
+    }
 
-    pub(crate) fn craft_udp_packet(
+    pub fn craft_revfs_dir_ack(
         hyper_ratchet: &StackedRatchet,
-        cmd_aux: u8,
-        payload: BytesMut,
+        security_level: SecurityLevel,
+        ticket: Ticket,
+        timestamp: i64,
+        target_cid: u64,
+        payload: ReVFSDirAckPacket,
+    ) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::FILE,
+            cmd_aux: packet_flags::cmd::aux::file::REVFS_DIR_ACK,
+            algorithm: 0,
+            security_level: security_level.value(),
+            context_info: U128::new(ticket.0),
+            group: U64::new(0),
+            wave_id: U32::new(0),
+            session_cid: U64::new(hyper_ratchet.get_cid()),
+            drill_version: U32::new(hyper_ratchet.version()),
+            timestamp: I64::new(timestamp),
+            target_cid: U64::new(target_cid),
+        };
+
+        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
+        header.inscribe_into(&mut packet);
+
+        payload.serialize_into_buf(&mut packet).unwrap();
+        hyper_ratchet
+            .protect_message_packet(Some(security_level), HDP_HEADER_BYTE_LEN, &mut packet)
+            .unwrap();
+
+        packet
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct SharedObjectPacket {
+        pub operation: SharedObjectOperation,
+        /// For writes, the object id of the upload that is to be redirected
+        pub object_id: Option<u32>,
+    }
+
+    pub fn craft_shared_object(
+        hyper_ratchet: &StackedRatchet,
+        security_level: SecurityLevel,
+        ticket: Ticket,
+        timestamp: i64,
         target_cid: u64,
+        operation: SharedObjectOperation,
+        object_id: Option<u32>,
+    ) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::FILE,
+            cmd_aux: packet_flags::cmd::aux::file::SHARED_OBJECT,
+            algorithm: 0,
+            security_level: security_level.value(),
+            context_info: U128::new(ticket.0),
+            group: U64::new(0),
+            wave_id: U32::new(0),
+            session_cid: U64::new(hyper_ratchet.get_cid()),
+            drill_version: U32::new(hyper_ratchet.version()),
+            timestamp: I64::new(timestamp),
+            target_cid: U64::new(target_cid),
+        };
+
+        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
+        header.inscribe_into(&mut packet);
+
+        let payload = SharedObjectPacket {
+            operation,
+            object_id,
+        };
+
+        payload.serialize_into_buf(&mut packet).unwrap();
+        hyper_ratchet
+            .protect_message_packet(Some(security_level), HDP_HEADER_BYTE_LEN, &mut packet)
+            .unwrap();
+
+        packet
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct SharedObjectAckPacket {
+        pub error_msg: Option<String>,
+    }
+
+    pub fn craft_shared_object_ack(
+        hyper_ratchet: &StackedRatchet,
         security_level: SecurityLevel,
+        ticket: Ticket,
+        timestamp: i64,
+        target_cid: u64,
+        error_msg: Option<String>,
     ) -> BytesMut {
         let header = HdpHeader {
             protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
-            cmd_primary: packet_flags::cmd::primary::UDP,
-            cmd_aux,
+            cmd_primary: packet_flags::cmd::primary::FILE,
+            cmd_aux: packet_flags::cmd::aux::file::SHARED_OBJECT_ACK,
             algorithm: 0,
             security_level: security_level.value(),
-            context_info: Default::default(),
-            group: Default::default(),
-            wave_id: Default::default(),
+            context_info: U128::new(ticket.0),
+            group: U64::new(0),
+            wave_id: U32::new(0),
             session_cid: U64::new(hyper_ratchet.get_cid()),
             drill_version: U32::new(hyper_ratchet.version()),
-            timestamp: Default::default(),
+            timestamp: I64::new(timestamp),
             target_cid: U64::new(target_cid),
         };
 
-        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN + payload.len());
+        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
         header.inscribe_into(&mut packet);
-        packet.extend_from_slice(&payload[..]);
 
+        let payload = SharedObjectAckPacket { error_msg };
+
+        payload.serialize_into_buf(&mut packet).unwrap();
+        hyper_ratchet
+            .protect_message_packet(Some(security_level), HDP_HEADER_BYTE_LEN, &mut packet)
+            .unwrap();
+
+        packet
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct DeltaSignatureRequestPacket {
+        pub name: String,
+        pub block_size: usize,
+    }
+
+    pub fn craft_delta_signature_request(
+        hyper_ratchet: &StackedRatchet,
+        security_level: SecurityLevel,
+        ticket: Ticket,
+        timestamp: i64,
+        target_cid: u64,
+        name: String,
+        block_size: usize,
+    ) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::FILE,
+            cmd_aux: packet_flags::cmd::aux::file::DELTA_SIGNATURE_REQUEST,
+            algorithm: 0,
+            security_level: security_level.value(),
+            context_info: U128::new(ticket.0),
+            group: U64::new(0),
+            wave_id: U32::new(0),
+            session_cid: U64::new(hyper_ratchet.get_cid()),
+            drill_version: U32::new(hyper_ratchet.version()),
+            timestamp: I64::new(timestamp),
+            target_cid: U64::new(target_cid),
+        };
+
+        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
+        header.inscribe_into(&mut packet);
+
+        let payload = DeltaSignatureRequestPacket { name, block_size };
+
+        payload.serialize_into_buf(&mut packet).unwrap();
         hyper_ratchet
             .protect_message_packet(Some(security_level), HDP_HEADER_BYTE_LEN, &mut packet)
             .unwrap();
 
         packet
     }
+
+    /// None if the receiver does not hold a copy of the object, or does not support delta sync
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct DeltaSignaturePacket {
+        pub signature: Option<FileSignature>,
+    }
+
+    pub fn craft_delta_signature(
+        hyper_ratchet: &StackedRatchet,
+        security_level: SecurityLevel,
+        ticket: Ticket,
+        timestamp: i64,
+        target_cid: u64,
+        signature: Option<FileSignature>,
+    ) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::FILE,
+            cmd_aux: packet_flags::cmd::aux::file::DELTA_SIGNATURE,
+            algorithm: 0,
+            security_level: security_level.value(),
+            context_info: U128::new(ticket.0),
+            group: U64::new(0),
+            wave_id: U32::new(0),
+            session_cid: U64::new(hyper_ratchet.get_cid()),
+            drill_version: U32::new(hyper_ratchet.version()),
+            timestamp: I64::new(timestamp),
+            target_cid: U64::new(target_cid),
+        };
+
+        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
+        header.inscribe_into(&mut packet);
+
+        let payload = DeltaSignaturePacket { signature };
+
+        payload.serialize_into_buf(&mut packet).unwrap();
+        hyper_ratchet
+            .protect_message_packet(Some(security_level), HDP_HEADER_BYTE_LEN, &mut packet)
+            .unwrap();
+
+        packet
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct ChunkQueryPacket {
+        pub name: String,
+        pub chunk_size: usize,
+        pub hashes: Vec<ChunkHash>,
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn craft_chunk_query(
+        hyper_ratchet: &StackedRatchet,
+        security_level: SecurityLevel,
+        ticket: Ticket,
+        timestamp: i64,
+        target_cid: u64,
+        name: String,
+        chunk_size: usize,
+        hashes: Vec<ChunkHash>,
+    ) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::FILE,
+            cmd_aux: packet_flags::cmd::aux::file::CHUNK_QUERY,
+            algorithm: 0,
+            security_level: security_level.value(),
+            context_info: U128::new(ticket.0),
+            group: U64::new(0),
+            wave_id: U32::new(0),
+            session_cid: U64::new(hyper_ratchet.get_cid()),
+            drill_version: U32::new(hyper_ratchet.version()),
+            timestamp: I64::new(timestamp),
+            target_cid: U64::new(target_cid),
+        };
+
+        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
+        header.inscribe_into(&mut packet);
+
+        let payload = ChunkQueryPacket {
+            name,
+            chunk_size,
+            hashes,
+        };
+
+        payload.serialize_into_buf(&mut packet).unwrap();
+        hyper_ratchet
+            .protect_message_packet(Some(security_level), HDP_HEADER_BYTE_LEN, &mut packet)
+            .unwrap();
+
+        packet
+    }
+
+    /// For each queried hash, whether the receiver holds the chunk in its cache. None if the
+    /// receiver does not cache chunks
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct ChunkQueryAckPacket {
+        pub cached: Option<Vec<bool>>,
+    }
+
+    pub fn craft_chunk_query_ack(
+        hyper_ratchet: &StackedRatchet,
+        security_level: SecurityLevel,
+        ticket: Ticket,
+        timestamp: i64,
+        target_cid: u64,
+        cached: Option<Vec<bool>>,
+    ) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::FILE,
+            cmd_aux: packet_flags::cmd::aux::file::CHUNK_QUERY_ACK,
+            algorithm: 0,
+            security_level: security_level.value(),
+            context_info: U128::new(ticket.0),
+            group: U64::new(0),
+            wave_id: U32::new(0),
+            session_cid: U64::new(hyper_ratchet.get_cid()),
+            drill_version: U32::new(hyper_ratchet.version()),
+            timestamp: I64::new(timestamp),
+            target_cid: U64::new(target_cid),
+        };
+
+        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
+        header.inscribe_into(&mut packet);
+
+        let payload = ChunkQueryAckPacket { cached };
+
+        payload.serialize_into_buf(&mut packet).unwrap();
+        hyper_ratchet
+            .protect_message_packet(Some(security_level), HDP_HEADER_BYTE_LEN, &mut packet)
+            .unwrap();
+
+        packet
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub enum ReVFSPullAckPacket {
+        Success,
+        Error { error: String },
+    }
+
+    pub fn craft_revfs_pull_ack(
+        hyper_ratchet: &StackedRatchet,
+        security_level: SecurityLevel,
+        ticket: Ticket,
+        timestamp: i64,
+        target_cid: u64,
+        payload: ReVFSPullAckPacket,
+    ) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::FILE,
+            cmd_aux: packet_flags::cmd::aux::file::REVFS_PULL_ACK,
+            algorithm: 0,
+            security_level: security_level.value(),
+            context_info: U128::new(ticket.0),
+            group: U64::new(0),
+            wave_id: U32::new(0),
+            session_cid: U64::new(hyper_ratchet.get_cid()),
+            drill_version: U32::new(hyper_ratchet.version()),
+            timestamp: I64::new(timestamp),
+            target_cid: U64::new(target_cid),
+        };
+
+        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
+        header.inscribe_into(&mut packet);
+
+        payload.serialize_into_buf(&mut packet).unwrap();
+        hyper_ratchet
+            .protect_message_packet(Some(security_level), HDP_HEADER_BYTE_LEN, &mut packet)
+            .unwrap();
+
+        packet
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct TransferControlPacket {
+        pub object_id: u32,
+        pub signal: ObjectTransferControl,
+        /// The orientation of the node that issued the signal
+        pub issuer_orientation: ObjectTransferOrientation,
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn craft_transfer_control_packet(
+        hyper_ratchet: &StackedRatchet,
+        security_level: SecurityLevel,
+        ticket: Ticket,
+        timestamp: i64,
+        target_cid: u64,
+        object_id: u32,
+        signal: ObjectTransferControl,
+        issuer_orientation: ObjectTransferOrientation,
+    ) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::FILE,
+            cmd_aux: packet_flags::cmd::aux::file::TRANSFER_CONTROL,
+            algorithm: 0,
+            security_level: security_level.value(),
+            context_info: U128::new(ticket.0),
+            group: U64::new(0),
+            wave_id: U32::new(0),
+            session_cid: U64::new(hyper_ratchet.get_cid()),
+            drill_version: U32::new(hyper_ratchet.version()),
+            timestamp: I64::new(timestamp),
+            target_cid: U64::new(target_cid),
+        };
+
+        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
+        header.inscribe_into(&mut packet);
+
+        let payload = TransferControlPacket {
+            object_id,
+            signal,
+            issuer_orientation,
+        };
+
+        payload.serialize_into_buf(&mut packet).unwrap();
+        hyper_ratchet
+            .protect_message_packet(Some(security_level), HDP_HEADER_BYTE_LEN, &mut packet)
+            .unwrap();
+
+        packet
+    }
+
+    #[derive(Serialize, Deserialize, Debug)]
+    pub struct TransferManifestPacket {
+        pub object_id: u32,
+        pub manifest: TransferManifest,
+        // binds the manifest to the session, the sender and the object
+        pub tag: [u8; 32],
+    }
+
+    pub(crate) fn craft_transfer_manifest_packet(
+        hyper_ratchet: &StackedRatchet,
+        security_level: SecurityLevel,
+        ticket: Ticket,
+        timestamp: i64,
+        target_cid: u64,
+        object_id: u32,
+        manifest: TransferManifest,
+    ) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::FILE,
+            cmd_aux: packet_flags::cmd::aux::file::TRANSFER_MANIFEST,
+            algorithm: 0,
+            security_level: security_level.value(),
+            context_info: U128::new(ticket.0),
+            group: U64::new(0),
+            wave_id: U32::new(0),
+            session_cid: U64::new(hyper_ratchet.get_cid()),
+            drill_version: U32::new(hyper_ratchet.version()),
+            timestamp: I64::new(timestamp),
+            target_cid: U64::new(target_cid),
+        };
+
+        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
+        header.inscribe_into(&mut packet);
+
+        let tag = manifest
+            .tag(hyper_ratchet, hyper_ratchet.get_cid(), object_id)
+            .unwrap();
+        let payload = TransferManifestPacket {
+            object_id,
+            manifest,
+            tag,
+        };
+
+        payload.serialize_into_buf(&mut packet).unwrap();
+        hyper_ratchet
+            .protect_message_packet(Some(security_level), HDP_HEADER_BYTE_LEN, &mut packet)
+            .unwrap();
+
+        packet
+    }
+}
+
+pub(crate) mod udp {
+    use crate::constants::HDP_HEADER_BYTE_LEN;
+    use crate::proto::packet::{packet_flags, HdpHeader};
+    use bytes::BytesMut;
+    use citadel_crypt::entropy_bank::SecurityLevel;
+    use citadel_crypt::stacked_ratchet::StackedRatchet;
+    use citadel_pqcrypto::key_domain::KeyDomain;
+    use zerocopy::{U32, U64};
+
+    /// UDP packets are protected using the subkeys of [`KeyDomain::Unordered`], keeping them
+    /// cryptographically separate from the primary stream
+    pub(crate) fn craft_udp_packet(
+        hyper_ratchet: &StackedRatchet,
+        cmd_aux: u8,
+        payload: BytesMut,
+        target_cid: u64,
+        security_level: SecurityLevel,
+    ) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::UDP,
+            cmd_aux,
+            algorithm: 0,
+            security_level: security_level.value(),
+            context_info: Default::default(),
+            group: Default::default(),
+            wave_id: Default::default(),
+            session_cid: U64::new(hyper_ratchet.get_cid()),
+            drill_version: U32::new(hyper_ratchet.version()),
+            timestamp: Default::default(),
+            target_cid: U64::new(target_cid),
+        };
+
+        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN + payload.len());
+        header.inscribe_into(&mut packet);
+        packet.extend_from_slice(&payload[..]);
+
+        hyper_ratchet
+            .protect_message_packet_in_domain(
+                KeyDomain::Unordered,
+                Some(security_level),
+                HDP_HEADER_BYTE_LEN,
+                &mut packet,
+            )
+            .unwrap();
+
+        packet
+    }
+}
+
+pub(crate) mod capabilities {
+    use crate::constants::HDP_HEADER_BYTE_LEN;
+    use crate::proto::misc::protocol_capabilities::ProtocolCapabilities;
+    use crate::proto::packet::{packet_flags, HdpHeader};
+    use crate::proto::packet_crafter::peer_cmd::C2S_ENCRYPTION_ONLY;
+    use crate::proto::remote::Ticket;
+    use bytes::BytesMut;
+    use citadel_crypt::prelude::SecurityLevel;
+    use citadel_crypt::stacked_ratchet::StackedRatchet;
+    use citadel_user::serialization::SyncIO;
+    use serde::{Deserialize, Serialize};
+    use zerocopy::{I64, U128, U32, U64};
+
+    #[derive(Serialize, Deserialize)]
+    pub struct CapabilitiesPacket {
+        pub capabilities: ProtocolCapabilities,
+    }
+
+    /// `cmd_aux` is either ADVERTISE or ACK
+    pub(crate) fn craft_capabilities(
+        hyper_ratchet: &StackedRatchet,
+        cmd_aux: u8,
+        security_level: SecurityLevel,
+        ticket: Ticket,
+        timestamp: i64,
+        capabilities: ProtocolCapabilities,
+    ) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::CAPABILITIES,
+            cmd_aux,
+            algorithm: 0,
+            security_level: security_level.value(),
+            context_info: U128::new(ticket.0),
+            group: U64::new(0),
+            wave_id: U32::new(0),
+            session_cid: U64::new(hyper_ratchet.get_cid()),
+            drill_version: U32::new(hyper_ratchet.version()),
+            timestamp: I64::new(timestamp),
+            target_cid: U64::new(C2S_ENCRYPTION_ONLY),
+        };
+
+        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
+        header.inscribe_into(&mut packet);
+
+        CapabilitiesPacket { capabilities }
+            .serialize_into_buf(&mut packet)
+            .unwrap();
+        hyper_ratchet
+            .protect_message_packet(Some(security_level), HDP_HEADER_BYTE_LEN, &mut packet)
+            .unwrap();
+
+        packet
+    }
+}
+
+pub(crate) mod challenge {
+    use crate::constants::HDP_HEADER_BYTE_LEN;
+    use crate::proto::packet::{packet_flags, HdpHeader};
+    use bytes::BytesMut;
+    use citadel_crypt::handshake_challenge::Challenge;
+    use zerocopy::{I64, U128, U32, U64};
+
+    /// The cookie is stored inside the context info, and the difficulty inside the wave ID. The
+    /// packet is unauthenticated, since no keys exist yet
+    pub(crate) fn craft_issue(challenge: Challenge, session_cid: u64, timestamp: i64) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::CHALLENGE,
+            cmd_aux: packet_flags::cmd::aux::challenge::ISSUE,
+            algorithm: 0,
+            security_level: 0,
+            context_info: U128::new(challenge.cookie),
+            group: U64::new(0),
+            wave_id: U32::new(challenge.difficulty as u32),
+            session_cid: U64::new(session_cid),
+            drill_version: U32::new(0),
+            timestamp: I64::new(timestamp),
+            target_cid: U64::new(0),
+        };
+
+        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN);
+        header.inscribe_into(&mut packet);
+        packet
+    }
+
+    /// The cookie is stored inside the context info, and the solution inside the group. The
+    /// payload is the unaltered packet that prompted the challenge
+    pub(crate) fn craft_response(
+        cookie: u128,
+        nonce: u64,
+        session_cid: u64,
+        timestamp: i64,
+        challenged_packet: &[u8],
+    ) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::CHALLENGE,
+            cmd_aux: packet_flags::cmd::aux::challenge::RESPONSE,
+            algorithm: 0,
+            security_level: 0,
+            context_info: U128::new(cookie),
+            group: U64::new(nonce),
+            wave_id: U32::new(0),
+            session_cid: U64::new(session_cid),
+            drill_version: U32::new(0),
+            timestamp: I64::new(timestamp),
+            target_cid: U64::new(0),
+        };
+
+        let mut packet = BytesMut::with_capacity(HDP_HEADER_BYTE_LEN + challenged_packet.len());
+        header.inscribe_into(&mut packet);
+        packet.extend_from_slice(challenged_packet);
+        packet
+    }
+}
+
+pub(crate) mod ping {
+    use crate::constants::HDP_HEADER_BYTE_LEN;
+    use crate::proto::packet::{packet_flags, HdpHeader};
+    use crate::proto::remote::Ticket;
+    use bytes::BytesMut;
+    use citadel_crypt::prelude::SecurityLevel;
+    use citadel_crypt::stacked_ratchet::StackedRatchet;
+    use zerocopy::{I64, U128, U32, U64};
+
+    /// `cmd_aux` is either REQUEST or RESPONSE. The packet carries no payload; the time the
+    /// responder spent processing the request is stored inside the group, and is zero for requests
+    pub(crate) fn craft_ping(
+        hyper_ratchet: &StackedRatchet,
+        cmd_aux: u8,
+        security_level: SecurityLevel,
+        ticket: Ticket,
+        timestamp: i64,
+        target_cid: u64,
+        processing_time_ns: u64,
+    ) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary: packet_flags::cmd::primary::PING,
+            cmd_aux,
+            algorithm: 0,
+            security_level: security_level.value(),
+            context_info: U128::new(ticket.0),
+            group: U64::new(processing_time_ns),
+            wave_id: U32::new(0),
+            session_cid: U64::new(hyper_ratchet.get_cid()),
+            drill_version: U32::new(hyper_ratchet.version()),
+            timestamp: I64::new(timestamp),
+            target_cid: U64::new(target_cid),
+        };
+
+        let mut packet = header.as_packet();
+        hyper_ratchet
+            .protect_message_packet(Some(security_level), HDP_HEADER_BYTE_LEN, &mut packet)
+            .unwrap();
+        packet
+    }
 }
 
 pub(crate) mod hole_punch {
@@ -0,0 +1,73 @@
+use super::includes::*;
+use crate::error::NetworkError;
+use crate::prelude::{ProtocolRenegotiated, Ticket};
+use crate::proto::packet_processor::primary_group_packet::get_proper_hyper_ratchet;
+use std::sync::atomic::Ordering;
+
+/// Renegotiates the protocol capabilities of the session. The receiver of an ADVERTISE adopts the
+/// common capabilities and replies with its own, allowing the sender to do the same
+#[cfg_attr(feature = "localhost-testing", tracing::instrument(target = "citadel", skip_all, ret, err, fields(is_server = session.is_server, src = packet.parse().unwrap().0.session_cid.get(), target = packet.parse().unwrap().0.target_cid.get())))]
+pub fn process_capabilities(
+    session: &HdpSession,
+    packet: HdpPacket,
+    header_drill_vers: u32,
+) -> Result<PrimaryProcessorResult, NetworkError> {
+    if session.state.load(Ordering::Relaxed) != SessionState::Connected {
+        log::warn!(target: "citadel", "Capabilities received, but session not connected. Dropping packet");
+        return Ok(PrimaryProcessorResult::Void);
+    }
+
+    let (header, payload, _, _) = packet.decompose();
+    let mut state_container = inner_mut_state!(session.state_container);
+    let hyper_ratchet = return_if_none!(
+        get_proper_hyper_ratchet(header_drill_vers, &state_container, None),
+        "Unable to get proper HR"
+    );
+    let (header, payload, hyper_ratchet) = return_if_none!(
+        validation::aead::validate(hyper_ratchet, &header, payload),
+        "Unable to validate capabilities packet"
+    );
+    let adjacent = return_if_none!(
+        validation::capabilities::validate_capabilities(&payload),
+        "Unable to deserialize capabilities packet"
+    )
+    .capabilities;
+
+    let security_level = header.security_level.into();
+    let ticket: Ticket = header.context_info.get().into();
+    let capabilities = state_container
+        .capabilities
+        .on_adjacent_capabilities(&adjacent);
+    log::trace!(target: "citadel", "Renegotiated protocol capabilities: {capabilities:?}");
+
+    let reply = match header.cmd_aux {
+        packet_flags::cmd::aux::capabilities::ADVERTISE => {
+            Some(packet_crafter::capabilities::craft_capabilities(
+                &hyper_ratchet,
+                packet_flags::cmd::aux::capabilities::ACK,
+                security_level,
+                ticket,
+                session.time_tracker.get_global_time_ns(),
+                state_container.capabilities.local,
+            ))
+        }
+
+        packet_flags::cmd::aux::capabilities::ACK => None,
+
+        _ => {
+            log::error!(target: "citadel", "Invalid capabilities command received");
+            return Ok(PrimaryProcessorResult::Void);
+        }
+    };
+
+    std::mem::drop(state_container);
+    session.send_to_kernel(NodeResult::ProtocolRenegotiated(ProtocolRenegotiated {
+        ticket,
+        implicated_cid: header.session_cid.get(),
+        capabilities,
+    }))?;
+
+    Ok(reply
+        .map(PrimaryProcessorResult::ReplyToSender)
+        .unwrap_or(PrimaryProcessorResult::Void))
+}
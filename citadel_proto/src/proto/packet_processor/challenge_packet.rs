@@ -0,0 +1,320 @@
+use super::includes::*;
+use crate::constants::HDP_HEADER_BYTE_LEN;
+use crate::error::NetworkError;
+use bytes::BytesMut;
+use citadel_crypt::handshake_challenge::{Challenge, ChallengeIssuer, MAX_DIFFICULTY};
+
+pub enum ChallengeGate {
+    /// The packet may be processed as usual
+    Proceed(BytesMut),
+    /// The packet was consumed by the challenge
+    Consumed(PrimaryProcessorResult),
+}
+
+/// Returns true for the packets that make the receiver perform a post-quantum key exchange on
+/// behalf of a sender it has yet to authenticate
+fn is_challengeable(header: &HdpHeader) -> bool {
+    matches!(
+        (header.cmd_primary, header.cmd_aux),
+        (
+            packet_flags::cmd::primary::DO_REGISTER,
+            packet_flags::cmd::aux::do_register::STAGE0
+        ) | (
+            packet_flags::cmd::primary::DO_PRE_CONNECT,
+            packet_flags::cmd::aux::do_preconnect::SYN
+        )
+    )
+}
+
+/// If the node demands handshake challenges, a register STAGE0 or pre-connect SYN is only processed
+/// once it returns wrapped inside a RESPONSE carrying a solution to the challenge the node issued.
+/// Until then, the node neither stores anything for the packet nor performs its key exchange
+pub async fn gate_handshake(
+    session: &HdpSession,
+    packet: BytesMut,
+    remote_peer: SocketAddr,
+) -> Result<ChallengeGate, NetworkError> {
+    let header = match LayoutVerified::<_, HdpHeader>::new_from_prefix(&packet[..]) {
+        Some((header, _)) => header.clone(),
+        // truncated packets are discarded once parsed
+        None => return Ok(ChallengeGate::Proceed(packet)),
+    };
+
+    if header.cmd_primary == packet_flags::cmd::primary::CHALLENGE {
+        return match header.cmd_aux {
+            packet_flags::cmd::aux::challenge::ISSUE if !session.is_server => {
+                solve_challenge(session, &header)
+                    .await
+                    .map(ChallengeGate::Consumed)
+            }
+
+            packet_flags::cmd::aux::challenge::RESPONSE if session.is_server => {
+                Ok(verify_response(session, &header, packet, remote_peer))
+            }
+
+            _ => {
+                log::warn!(target: "citadel", "Invalid challenge command received");
+                Ok(ChallengeGate::Consumed(PrimaryProcessorResult::Void))
+            }
+        };
+    }
+
+    if session.is_server && is_challengeable(&header) {
+        if let Some(issuer) = session.session_manager.handshake_challenge() {
+            let challenge = issuer.issue(remote_peer.ip());
+            log::trace!(target: "citadel", "Challenging the handshake of {remote_peer} with difficulty {}", challenge.difficulty);
+            let issue = packet_crafter::challenge::craft_issue(
+                challenge,
+                header.session_cid.get(),
+                session.time_tracker.get_global_time_ns(),
+            );
+            return Ok(ChallengeGate::Consumed(
+                PrimaryProcessorResult::ReplyToSender(issue),
+            ));
+        }
+    }
+
+    if !session.is_server
+        && matches!(
+            header.cmd_primary,
+            packet_flags::cmd::primary::DO_REGISTER | packet_flags::cmd::primary::DO_PRE_CONNECT
+        )
+    {
+        // the server handled the handshake without challenging it
+        inner_mut_state!(session.state_container).challengeable_handshake = None;
+    }
+
+    Ok(ChallengeGate::Proceed(packet))
+}
+
+async fn solve_challenge(
+    session: &HdpSession,
+    header: &HdpHeader,
+) -> Result<PrimaryProcessorResult, NetworkError> {
+    let difficulty = header.wave_id.get();
+    if difficulty > MAX_DIFFICULTY as u32 {
+        log::warn!(target: "citadel", "Refusing to solve a challenge of difficulty {difficulty}");
+        return Ok(PrimaryProcessorResult::Void);
+    }
+
+    // each handshake packet is only resent once, such that a server cannot keep the client busy
+    let challenged = inner_mut_state!(session.state_container)
+        .challengeable_handshake
+        .take();
+    let challenged = return_if_none!(
+        challenged,
+        "Received a challenge, yet no handshake is pending"
+    );
+
+    let challenge = Challenge {
+        cookie: header.context_info.get(),
+        difficulty: difficulty as u8,
+    };
+    let nonce = citadel_io::spawn_blocking(move || challenge.solve())
+        .await
+        .map_err(|err| NetworkError::Generic(err.message))?;
+    log::trace!(target: "citadel", "Solved handshake challenge of difficulty {difficulty}");
+
+    Ok(PrimaryProcessorResult::ReplyToSender(
+        packet_crafter::challenge::craft_response(
+            challenge.cookie,
+            nonce,
+            header.session_cid.get(),
+            session.time_tracker.get_global_time_ns(),
+            &challenged,
+        ),
+    ))
+}
+
+fn verify_response(
+    session: &HdpSession,
+    header: &HdpHeader,
+    packet: BytesMut,
+    remote_peer: SocketAddr,
+) -> ChallengeGate {
+    match session.session_manager.handshake_challenge() {
+        Some(issuer) => redeem_response(&issuer, header, packet, remote_peer),
+        None => {
+            log::warn!(target: "citadel", "Received a challenge response, yet no challenges are issued");
+            ChallengeGate::Consumed(PrimaryProcessorResult::Void)
+        }
+    }
+}
+
+/// Unwraps the handshake packet carried by a response whose solution is valid, and has yet to be
+/// redeemed
+fn redeem_response(
+    issuer: &ChallengeIssuer,
+    header: &HdpHeader,
+    mut packet: BytesMut,
+    remote_peer: SocketAddr,
+) -> ChallengeGate {
+    if !issuer.verify(
+        remote_peer.ip(),
+        header.context_info.get(),
+        header.group.get(),
+    ) {
+        log::warn!(target: "citadel", "Discarding an invalid, expired or replayed challenge response from {remote_peer}");
+        return ChallengeGate::Consumed(PrimaryProcessorResult::Void);
+    }
+
+    let challenged = packet.split_off(HDP_HEADER_BYTE_LEN);
+    match LayoutVerified::<_, HdpHeader>::new_from_prefix(&challenged[..]) {
+        Some((inner, _)) if is_challengeable(&inner) => ChallengeGate::Proceed(challenged),
+        _ => {
+            log::warn!(target: "citadel", "A challenge response may only carry a handshake packet");
+            ChallengeGate::Consumed(PrimaryProcessorResult::Void)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::packet::{packet_flags, HdpHeader};
+    use crate::proto::packet_crafter;
+    use crate::proto::packet_processor::challenge_packet::{redeem_response, ChallengeGate};
+    use bytes::BytesMut;
+    use citadel_crypt::handshake_challenge::ChallengeIssuer;
+    use std::net::SocketAddr;
+    use std::time::Duration;
+    use zerocopy::{LayoutVerified, I64, U128, U32, U64};
+
+    fn handshake_packet(cmd_primary: u8, cmd_aux: u8) -> BytesMut {
+        let header = HdpHeader {
+            protocol_version: (*crate::constants::PROTOCOL_VERSION).into(),
+            cmd_primary,
+            cmd_aux,
+            algorithm: 0,
+            security_level: 0,
+            context_info: U128::new(0),
+            group: U64::new(0),
+            wave_id: U32::new(0),
+            session_cid: U64::new(0),
+            drill_version: U32::new(0),
+            timestamp: I64::new(0),
+            target_cid: U64::new(0),
+        };
+
+        let mut packet = BytesMut::new();
+        header.inscribe_into(&mut packet);
+        packet
+    }
+
+    fn syn() -> BytesMut {
+        handshake_packet(
+            packet_flags::cmd::primary::DO_PRE_CONNECT,
+            packet_flags::cmd::aux::do_preconnect::SYN,
+        )
+    }
+
+    fn redeem(issuer: &ChallengeIssuer, response: BytesMut, remote_peer: SocketAddr) -> bool {
+        let (header, _) = LayoutVerified::<_, HdpHeader>::new_from_prefix(&response[..]).unwrap();
+        let header = header.clone();
+        match redeem_response(issuer, &header, response, remote_peer) {
+            ChallengeGate::Proceed(challenged) => {
+                assert_eq!(&challenged[..], &syn()[..]);
+                true
+            }
+            ChallengeGate::Consumed(_) => false,
+        }
+    }
+
+    #[test]
+    fn test_valid_response_proceeds() {
+        let issuer = ChallengeIssuer::new(8, Duration::from_secs(30));
+        let client: SocketAddr = "10.0.0.1:25000".parse().unwrap();
+        let challenge = issuer.issue(client.ip());
+        let response = packet_crafter::challenge::craft_response(
+            challenge.cookie,
+            challenge.solve(),
+            0,
+            0,
+            &syn(),
+        );
+        assert!(redeem(&issuer, response, client));
+    }
+
+    #[test]
+    fn test_invalid_response_rejected() {
+        let issuer = ChallengeIssuer::new(8, Duration::from_secs(30));
+        let client: SocketAddr = "10.0.0.1:25000".parse().unwrap();
+        let spoofed: SocketAddr = "10.0.0.2:25000".parse().unwrap();
+        let challenge = issuer.issue(client.ip());
+        let nonce = challenge.solve();
+
+        // issued to another address
+        let response =
+            packet_crafter::challenge::craft_response(challenge.cookie, nonce, 0, 0, &syn());
+        assert!(!redeem(&issuer, response, spoofed));
+
+        // unsolved
+        let unsolved = (0..)
+            .find(|nonce| {
+                !citadel_crypt::handshake_challenge::is_solution(challenge.cookie, *nonce, 8)
+            })
+            .unwrap();
+        let response =
+            packet_crafter::challenge::craft_response(challenge.cookie, unsolved, 0, 0, &syn());
+        assert!(!redeem(&issuer, response, client));
+
+        // forged cookie
+        let response =
+            packet_crafter::challenge::craft_response(challenge.cookie ^ 1, nonce, 0, 0, &syn());
+        assert!(!redeem(&issuer, response, client));
+
+        // carrying a packet that is not a handshake
+        let connected = handshake_packet(
+            packet_flags::cmd::primary::DO_CONNECT,
+            packet_flags::cmd::aux::do_connect::STAGE0,
+        );
+        let response =
+            packet_crafter::challenge::craft_response(challenge.cookie, nonce, 0, 0, &connected);
+        let (header, _) = LayoutVerified::<_, HdpHeader>::new_from_prefix(&response[..]).unwrap();
+        let header = header.clone();
+        assert!(matches!(
+            redeem_response(&issuer, &header, response, client),
+            ChallengeGate::Consumed(_)
+        ));
+    }
+
+    #[test]
+    fn test_expired_response_rejected() {
+        let issuer = ChallengeIssuer::new(8, Duration::from_secs(1));
+        let client: SocketAddr = "10.0.0.1:25000".parse().unwrap();
+        let challenge = issuer.issue(client.ip());
+        let nonce = challenge.solve();
+
+        // the cookie outlives the epoch after the one it was issued in by no more than a second
+        std::thread::sleep(Duration::from_millis(2100));
+        let response =
+            packet_crafter::challenge::craft_response(challenge.cookie, nonce, 0, 0, &syn());
+        assert!(!redeem(&issuer, response, client));
+    }
+
+    #[test]
+    fn test_replayed_response_rejected() {
+        let issuer = ChallengeIssuer::new(8, Duration::from_secs(30));
+        let client: SocketAddr = "10.0.0.1:25000".parse().unwrap();
+        let challenge = issuer.issue(client.ip());
+        let response = packet_crafter::challenge::craft_response(
+            challenge.cookie,
+            challenge.solve(),
+            0,
+            0,
+            &syn(),
+        );
+
+        assert!(redeem(&issuer, response.clone(), client));
+        assert!(!redeem(&issuer, response, client));
+
+        // a fresh solution to the same cookie admits another handshake
+        let response = packet_crafter::challenge::craft_response(
+            challenge.cookie,
+            challenge.solve(),
+            0,
+            0,
+            &syn(),
+        );
+        assert!(redeem(&issuer, response, client));
+    }
+}
@@ -1,9 +1,12 @@
 use super::includes::*;
 use crate::error::NetworkError;
+use crate::proto::misc::handshake_failures::HandshakeFailureReason;
 use crate::proto::node::ConnectMode;
-use crate::proto::node_result::{ConnectFail, ConnectSuccess, MailboxDelivery};
+use crate::proto::node_result::{ConnectFail, ConnectSuccess, HandshakeFailed, MailboxDelivery};
 use crate::proto::packet_processor::primary_group_packet::get_proper_hyper_ratchet;
+use crate::proto::peer::peer_layer::PeerListDelta;
 use crate::proto::state_container::VirtualConnectionType;
+use crate::proto::state_subcontainers::stage_machine::ConnectStage;
 use citadel_user::external_services::ServicesObject;
 use std::sync::atomic::Ordering;
 
@@ -19,8 +22,7 @@ pub async fn process_connect(
     let (hr, cnac) = {
         let state_container = inner_state!(session.state_container);
         if !session.is_provisional()
-            && state_container.connect_state.last_stage
-                != packet_flags::cmd::aux::do_connect::SUCCESS
+            && state_container.connect_state.stage.current() != ConnectStage::Success
         {
             log::error!(target: "citadel", "Connect packet received, but the system is not in a provisional state. Dropping");
             return Ok(PrimaryProcessorResult::Void);
@@ -57,8 +59,16 @@ pub async fn process_connect(
             packet_flags::cmd::aux::do_connect::STAGE0 => {
                 log::trace!(target: "citadel", "STAGE 2 CONNECT PACKET");
                 let task = {
-                    match validation::do_connect::validate_stage0_packet(&cnac, &payload).await {
-                        Ok(_) => {
+                    match validation::do_connect::validate_stage0_packet(
+                        &session.account_manager,
+                        &cnac,
+                        &payload,
+                        security_level,
+                    )
+                    .await
+                    {
+                        Ok(account_permissions) => {
+                            session.account_permissions.set(account_permissions);
                             let mut state_container = inner_mut_state!(session.state_container);
 
                             let cid = hyper_ratchet.get_cid();
@@ -68,10 +78,13 @@ pub async fn process_connect(
                             let kernel_ticket = session.kernel_ticket.get();
 
                             //let pqc = state_container.connect_stage.generated_pqc.take();
-                            state_container.connect_state.last_stage =
-                                packet_flags::cmd::aux::do_connect::SUCCESS;
+                            return_if_illegal!(state_container
+                                .connect_state
+                                .stage
+                                .advance(ConnectStage::Success));
                             state_container.connect_state.fail_time = None;
                             state_container.connect_state.on_connect_packet_received();
+                            let timings = state_container.connect_state.timer.finish();
                             let udp_channel_rx = state_container
                                 .pre_connect_state
                                 .udp_channel_oneshot_tx
@@ -109,14 +122,19 @@ pub async fn process_connect(
                                     .unwrap_or_default();
 
                                 #[cfg(feature = "google-services")]
-                                let post_login_object = account_manager
+                                let mut post_login_object = account_manager
                                     .services_handler()
                                     .on_post_login_serverside(cid)
                                     .await?;
                                 #[cfg(not(feature = "google-services"))]
-                                let post_login_object =
+                                let mut post_login_object =
                                     citadel_user::external_services::ServicesObject::default();
 
+                                post_login_object.legal_hold = session
+                                    .legal_hold
+                                    .as_ref()
+                                    .and_then(|legal_hold| legal_hold.notice(cid));
+
                                 let success_packet =
                                     packet_crafter::do_connect::craft_final_status_packet(
                                         &hyper_ratchet,
@@ -144,7 +162,8 @@ pub async fn process_connect(
                                     services: post_login_object,
                                     welcome_message: format!("Client {cid} successfully established a connection to the local HyperNode"),
                                     channel,
-                                    udp_rx_opt: udp_channel_rx
+                                    udp_rx_opt: udp_channel_rx,
+                                    timings,
                                 });
                                 // safe unwrap. Store the signal
                                 inner_mut_state!(session.state_container)
@@ -152,12 +171,23 @@ pub async fn process_connect(
                                     .as_mut()
                                     .unwrap()
                                     .channel_signal = Some(channel_signal);
+                                session
+                                    .session_manager
+                                    .notify_peer_list_subscribers(PeerListDelta::Online { cid });
                                 Ok(PrimaryProcessorResult::ReplyToSender(success_packet))
                             }
                         }
 
                         Err(err) => {
                             log::error!(target: "citadel", "Error validating stage2 packet. Reason: {}", err.to_string());
+                            session
+                                .session_manager
+                                .report_handshake_failure(HandshakeFailed {
+                                    peer_addr: session.remote_peer,
+                                    cid_opt: Some(hyper_ratchet.get_cid()),
+                                    reason: HandshakeFailureReason::Authentication,
+                                    message: err.to_string(),
+                                });
                             let fail_time = time_tracker.get_global_time_ns();
 
                             //session.state = SessionState::NeedsConnect;
@@ -191,7 +221,7 @@ pub async fn process_connect(
                         .unwrap_or_else(|_| "Invalid UTF-8 message".to_string());
                     log::trace!(target: "citadel", "The server refused to login the user. Reason: {}", &message);
                     let cid = hyper_ratchet.get_cid();
-                    state_container.connect_state.on_fail();
+                    return_if_illegal!(state_container.connect_state.on_fail());
                     std::mem::drop(state_container);
 
                     //session.session_manager.clear_provisional_tracker(session.kernel_ticket);
@@ -206,6 +236,7 @@ pub async fn process_connect(
                         ticket: kernel_ticket,
                         cid_opt: Some(cid),
                         error_message: message,
+                        redirect: None,
                     }))?;
                     Ok(PrimaryProcessorResult::EndSession(
                         "Failed connecting. Try again",
@@ -222,9 +253,7 @@ pub async fn process_connect(
 
                 let task = {
                     let mut state_container = inner_mut_state!(session.state_container);
-                    let last_stage = state_container.connect_state.last_stage;
-
-                    if last_stage == packet_flags::cmd::aux::do_connect::STAGE1 {
+                    if state_container.connect_state.stage.current() == ConnectStage::Stage1 {
                         if let Some(payload) =
                             validation::do_connect::validate_final_status_packet(&payload)
                         {
@@ -234,8 +263,9 @@ pub async fn process_connect(
                             let kernel_ticket = session.kernel_ticket.get();
                             let cid = hyper_ratchet.get_cid();
 
-                            state_container.connect_state.on_success();
+                            return_if_illegal!(state_container.connect_state.on_success());
                             state_container.connect_state.on_connect_packet_received();
+                            let timings = state_container.connect_state.timer.finish();
 
                             let use_ka = state_container.keep_alive_timeout_ns != 0;
                             let connect_mode = return_if_none!(
@@ -303,6 +333,7 @@ pub async fn process_connect(
                                 welcome_message: message,
                                 channel,
                                 udp_rx_opt: udp_channel_rx,
+                                timings,
                             }))?;
                             //finally, if there are any mailbox items, send them to the kernel for processing
                             if let Some(mailbox_delivery) = payload.mailbox {
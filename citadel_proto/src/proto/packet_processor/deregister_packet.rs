@@ -1,7 +1,9 @@
 use super::includes::*;
 use crate::error::NetworkError;
+use crate::proto::misc::account_hook::{AccountEvent, AccountEventKind};
 use crate::proto::node_result::DeRegistration;
 use crate::proto::packet_processor::primary_group_packet::get_proper_hyper_ratchet;
+use crate::proto::peer::peer_layer::PeerListDelta;
 use citadel_crypt::stacked_ratchet::StackedRatchet;
 use std::sync::atomic::Ordering;
 
@@ -96,18 +98,37 @@ async fn deregister_client_from_self(
     security_level: SecurityLevel,
 ) -> Result<PrimaryProcessorResult, NetworkError> {
     let session = session_ref;
-    let (acc_mgr, ticket) = {
+    let (acc_mgr, ticket, account) = {
         let state_container = inner_state!(session.state_container);
         let ticket = state_container.deregister_state.current_ticket;
+        // the metadata must be read before the account is purged
+        let account = state_container
+            .cnac
+            .as_ref()
+            .map(|cnac| cnac.get_metadata());
 
         let acc_manager = session.account_manager.clone();
         std::mem::drop(state_container);
-        (acc_manager, ticket)
+        (acc_manager, ticket, account)
     };
 
     let (ret, success) = match acc_mgr.delete_client_by_cid(implicated_cid).await {
         Ok(_) => {
             log::trace!(target: "citadel", "Successfully purged account {} locally!", implicated_cid);
+            session
+                .session_manager
+                .notify_peer_list_subscribers(PeerListDelta::Deregistered {
+                    cid: implicated_cid,
+                });
+            if let Some(account) = account {
+                session.session_manager.notify_account_hook(
+                    AccountEventKind::Deregistered,
+                    AccountEvent {
+                        account,
+                        remote_addr: session.remote_peer,
+                    },
+                );
+            }
             let stage_success_packet = packet_crafter::do_deregister::craft_final(
                 hyper_ratchet,
                 true,
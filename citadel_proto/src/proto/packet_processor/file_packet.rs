@@ -1,13 +1,23 @@
 use super::includes::*;
 use crate::error::NetworkError;
-use crate::prelude::{ReVFSResult, Ticket};
-use crate::proto::packet_crafter::file::ReVFSPullAckPacket;
+use crate::prelude::{InternalServerError, PeerSignal, ReVFSResult, SharedObjectResult, Ticket};
+use crate::proto::packet_crafter::file::{ReVFSDirAckPacket, ReVFSPullAckPacket};
 use crate::proto::packet_processor::header_to_response_vconn_type;
 use crate::proto::packet_processor::primary_group_packet::{
     get_proper_hyper_ratchet, get_resp_target_cid_from_header,
 };
+use crate::proto::state_container::{
+    FileKey, PendingDeduplicatedTransfer, PendingDeltaTransfer, PendingSharedObjectWrite,
+};
 use crate::proto::{get_preferred_primary_stream, send_with_error_logging};
+use citadel_crypt::chunk_dedup::encode_deduplicated_source;
+use citadel_crypt::delta_sync::encode_delta_source;
 use citadel_crypt::misc::TransferType;
+use citadel_user::backend::utils::{
+    ReVFSDirectoryOperation, SharedObjectNotification, SharedObjectOperation,
+    SharedObjectPermission,
+};
+use citadel_user::backend::PersistenceHandler;
 use std::sync::atomic::Ordering;
 
 #[cfg_attr(feature = "localhost-testing", tracing::instrument(target = "citadel", skip_all, ret, err, fields(is_server = session.is_server, src = packet.parse().unwrap().0.session_cid.get(), target = packet.parse().unwrap().0.target_cid.get())))]
@@ -71,6 +81,29 @@ pub fn process_file_packet(
                                 }
                             };
 
+                            if session.is_server
+                                && !session.account_permissions.get().can_file_transfer
+                            {
+                                log::warn!(target: "citadel", "Rejecting file transfer from {}: denied by the account permissions", header.session_cid.get());
+                                let file_header_ack =
+                                    packet_crafter::file::craft_file_header_ack_packet(
+                                        &hyper_ratchet,
+                                        false,
+                                        vfm.object_id,
+                                        target_cid,
+                                        ticket,
+                                        security_level,
+                                        v_target_flipped,
+                                        ts,
+                                        None,
+                                        Some(
+                                            "File transfers are not permitted for this account"
+                                                .to_string(),
+                                        ),
+                                    );
+                                return Ok(PrimaryProcessorResult::ReplyToSender(file_header_ack));
+                            }
+
                             let preferred_primary_stream = return_if_none!(
                                 get_preferred_primary_stream(&header, session, &state_container)
                             );
@@ -86,6 +119,7 @@ pub fn process_file_packet(
                                 v_target_flipped,
                                 preferred_primary_stream,
                                 local_encryption_level,
+                                payload.compression,
                             ) {
                                 log::warn!(target: "citadel", "Failed to run on_file_header_received");
                             }
@@ -114,11 +148,15 @@ pub fn process_file_packet(
                             // conclude by passing this data into the state container
                             if state_container
                                 .on_file_header_ack_received(
+                                    &session.state_container,
                                     success,
                                     original_implicated_cid,
                                     header.context_info.get().into(),
                                     object_id,
                                     v_target,
+                                    security_level,
+                                    payload.compression,
+                                    payload.rejection_reason,
                                 )
                                 .is_none()
                             {
@@ -143,6 +181,9 @@ pub fn process_file_packet(
                     // The only extra information we need to give the adjacent endpoint
                     // is the metadata pertaining to the encryption strength used on
                     // the data.
+                    // If A pulls from the RE-VFS of another owner, the owner must have granted A
+                    // access to the path. The object remains encrypted under the owner's local
+                    // key, hence, is sent as stored via a standard transfer A must accept
                     match validation::file::validate_revfs_pull(&header, &payload) {
                         Some(packet) => {
                             let session = session.clone();
@@ -150,42 +191,48 @@ pub fn process_file_packet(
                                 get_preferred_primary_stream(&header, &session, &state_container)
                             );
                             let virtual_target = header_to_response_vconn_type(&header);
-                            let revfs_cid = header.session_cid.get();
+                            let requester_cid = header.session_cid.get();
                             let resp_target_cid = get_resp_target_cid_from_header(&header);
                             let delete_on_pull = packet.delete_on_pull;
 
                             // get the real_path and security level used from the backend
                             let task = async move {
-                                let response_payload = match session
-                                    .account_manager
-                                    .get_persistence_handler()
-                                    .revfs_get_file_info(revfs_cid, packet.virtual_path)
-                                    .await
-                                {
-                                    Ok((source, local_encryption_level)) => {
-                                        let transfer_type = TransferType::FileTransfer; // use a basic file transfer since we don't need to data to be locally encrypted when sending it back
-                                        match session.process_outbound_file(
+                                let pers = session.account_manager.get_persistence_handler();
+                                let result: Result<(), AccountError> = async {
+                                    let revfs_cid = pers
+                                        .revfs_resolve_owner(
+                                            requester_cid,
+                                            packet.owner_cid,
+                                            packet.virtual_path.clone(),
+                                        )
+                                        .await?;
+                                    let (source, local_encryption_level) = pers
+                                        .revfs_get_file_info(revfs_cid, packet.virtual_path)
+                                        .await?;
+                                    let local_encryption_level = (revfs_cid == requester_cid)
+                                        .then_some(local_encryption_level);
+                                    let transfer_type = TransferType::FileTransfer; // use a basic file transfer since we don't need to data to be locally encrypted when sending it back
+                                    session
+                                        .process_outbound_file(
                                             ticket,
                                             None,
                                             source,
                                             virtual_target,
                                             packet.security_level,
                                             transfer_type,
-                                            Some(local_encryption_level),
+                                            local_encryption_level,
                                             move |source| {
                                                 if delete_on_pull {
                                                     spawn!(tokio::fs::remove_file(source));
                                                 }
                                             },
-                                        ) {
-                                            Ok(_) => ReVFSPullAckPacket::Success,
-
-                                            Err(err) => ReVFSPullAckPacket::Error {
-                                                error: err.into_string(),
-                                            },
-                                        }
-                                    }
+                                        )
+                                        .map_err(|err| AccountError::msg(err.into_string()))
+                                }
+                                .await;
 
+                                let response_payload = match result {
+                                    Ok(()) => ReVFSPullAckPacket::Success,
                                     Err(err) => ReVFSPullAckPacket::Error {
                                         error: err.into_string(),
                                     },
@@ -221,8 +268,9 @@ pub fn process_file_packet(
                     match validation::file::validate_revfs_delete(&header, &payload) {
                         Some(payload) => {
                             let virtual_path = payload.virtual_path;
-                            // we use the cid of the sender, because, they are requesting to alter data here
-                            let re_vfs_cid = header.session_cid.get();
+                            // we use the cid of the sender, because, they are requesting to alter data here,
+                            // unless the sender names another owner that granted them access
+                            let requester_cid = header.session_cid.get();
                             let resp_target_cid = get_resp_target_cid_from_header(&header);
                             let pers = session.account_manager.get_persistence_handler().clone();
 
@@ -231,11 +279,20 @@ pub fn process_file_packet(
                             );
 
                             let task = async move {
-                                let err_opt = pers
-                                    .revfs_delete(re_vfs_cid, virtual_path)
+                                let result = match pers
+                                    .revfs_resolve_owner(
+                                        requester_cid,
+                                        payload.owner_cid,
+                                        virtual_path.clone(),
+                                    )
                                     .await
-                                    .err()
-                                    .map(|e| e.into_string());
+                                {
+                                    Ok(re_vfs_cid) => {
+                                        pers.revfs_delete(re_vfs_cid, virtual_path).await
+                                    }
+                                    Err(err) => Err(err),
+                                };
+                                let err_opt = result.err().map(|e| e.into_string());
                                 let response_packet = packet_crafter::file::craft_revfs_ack(
                                     &hyper_ratchet,
                                     security_level,
@@ -266,6 +323,7 @@ pub fn process_file_packet(
                             let response = NodeResult::ReVFS(ReVFSResult {
                                 error_message: payload.error_msg,
                                 data: None,
+                                entries: None,
                                 ticket,
                             });
 
@@ -281,6 +339,309 @@ pub fn process_file_packet(
                     }
                 }
 
+                packet_flags::cmd::aux::file::REVFS_DIR => {
+                    log::trace!(target: "citadel", "RECV REVFS DIR");
+                    match validation::file::validate_revfs_dir(&header, &payload) {
+                        Some(payload) => {
+                            // the sender's cid is used, since they are operating on their own RE-VFS
+                            let re_vfs_cid = header.session_cid.get();
+                            let resp_target_cid = get_resp_target_cid_from_header(&header);
+                            let pers = session.account_manager.get_persistence_handler().clone();
+
+                            let preferred_primary_stream = return_if_none!(
+                                get_preferred_primary_stream(&header, session, &state_container)
+                            );
+
+                            let task = async move {
+                                let result = match payload.operation {
+                                    ReVFSDirectoryOperation::CreateDirectory { virtual_dir } => {
+                                        pers.revfs_create_dir(re_vfs_cid, virtual_dir)
+                                            .await
+                                            .map(|_| None)
+                                    }
+
+                                    ReVFSDirectoryOperation::List {
+                                        virtual_dir,
+                                        owner_cid,
+                                    } => match pers
+                                        .revfs_resolve_owner(
+                                            re_vfs_cid,
+                                            owner_cid,
+                                            virtual_dir.clone(),
+                                        )
+                                        .await
+                                    {
+                                        Ok(owner_cid) => {
+                                            pers.revfs_list(owner_cid, virtual_dir).await.map(Some)
+                                        }
+                                        Err(err) => Err(err),
+                                    },
+
+                                    ReVFSDirectoryOperation::GrantAccess {
+                                        virtual_dir,
+                                        peer_cid,
+                                    } => pers
+                                        .revfs_grant_access(re_vfs_cid, virtual_dir, peer_cid)
+                                        .await
+                                        .map(|_| None),
+
+                                    ReVFSDirectoryOperation::RevokeAccess {
+                                        virtual_dir,
+                                        peer_cid,
+                                    } => pers
+                                        .revfs_revoke_access(re_vfs_cid, virtual_dir, peer_cid)
+                                        .await
+                                        .map(|_| None),
+                                };
+
+                                let response_payload = match result {
+                                    Ok(entries) => ReVFSDirAckPacket {
+                                        entries,
+                                        error_msg: None,
+                                    },
+                                    Err(err) => ReVFSDirAckPacket {
+                                        entries: None,
+                                        error_msg: Some(err.into_string()),
+                                    },
+                                };
+
+                                let response_packet = packet_crafter::file::craft_revfs_dir_ack(
+                                    &hyper_ratchet,
+                                    security_level,
+                                    ticket,
+                                    ts,
+                                    resp_target_cid,
+                                    response_payload,
+                                );
+                                send_with_error_logging(&preferred_primary_stream, response_packet);
+                            };
+
+                            spawn!(task);
+
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+
+                        None => {
+                            log::error!(target: "citadel", "Unable to validate REVFS DIR packet");
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+                    }
+                }
+
+                packet_flags::cmd::aux::file::REVFS_DIR_ACK => {
+                    log::trace!(target: "citadel", "RECV REVFS DIR ACK");
+                    match validation::file::validate_revfs_dir_ack(&header, &payload) {
+                        Some(payload) => {
+                            let response = NodeResult::ReVFS(ReVFSResult {
+                                error_message: payload.error_msg,
+                                data: None,
+                                entries: payload.entries,
+                                ticket,
+                            });
+
+                            session.send_to_kernel(response)?;
+
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+
+                        None => {
+                            log::error!(target: "citadel", "Unable to validate REVFS DIR ACK packet");
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+                    }
+                }
+
+                packet_flags::cmd::aux::file::SHARED_OBJECT => {
+                    log::trace!(target: "citadel", "RECV SHARED OBJECT");
+                    match validation::file::validate_shared_object(&header, &payload) {
+                        Some(payload) => {
+                            let session = session.clone();
+                            let requester_cid = header.session_cid.get();
+                            let resp_target_cid = get_resp_target_cid_from_header(&header);
+                            let virtual_target = header_to_response_vconn_type(&header);
+                            let preferred_primary_stream = return_if_none!(
+                                get_preferred_primary_stream(&header, &session, &state_container)
+                            );
+
+                            let task = async move {
+                                let pers = session.account_manager.get_persistence_handler();
+                                let object_id = payload.object_id;
+                                let result = match payload.operation {
+                                    SharedObjectOperation::Grant {
+                                        name,
+                                        grantee_cid,
+                                        permission,
+                                    } => pers
+                                        .share_object(requester_cid, &name, grantee_cid, permission)
+                                        .await
+                                        .map(|_| {
+                                            Some((
+                                                grantee_cid,
+                                                SharedObjectNotification {
+                                                    owner_cid: requester_cid,
+                                                    name,
+                                                    permission: Some(permission),
+                                                },
+                                            ))
+                                        }),
+
+                                    SharedObjectOperation::Revoke { name, grantee_cid } => pers
+                                        .unshare_object(requester_cid, &name, grantee_cid)
+                                        .await
+                                        .map(|_| {
+                                            Some((
+                                                grantee_cid,
+                                                SharedObjectNotification {
+                                                    owner_cid: requester_cid,
+                                                    name,
+                                                    permission: None,
+                                                },
+                                            ))
+                                        }),
+
+                                    SharedObjectOperation::Read { owner_cid, name } => {
+                                        match ensure_shared_object_permission(
+                                            pers,
+                                            owner_cid,
+                                            &name,
+                                            requester_cid,
+                                            SharedObjectPermission::Read,
+                                        )
+                                        .await
+                                        {
+                                            Ok(()) => match pers
+                                                .get_stored_object(owner_cid, &name)
+                                                .await
+                                            {
+                                                // the transfer itself acts as the acknowledgement
+                                                Ok(source) => match session.process_outbound_file(
+                                                    ticket,
+                                                    None,
+                                                    source,
+                                                    virtual_target,
+                                                    security_level,
+                                                    TransferType::FileTransfer,
+                                                    None,
+                                                    |_| {},
+                                                ) {
+                                                    Ok(()) => return,
+                                                    Err(err) => {
+                                                        Err(AccountError::msg(err.into_string()))
+                                                    }
+                                                },
+                                                Err(err) => Err(err),
+                                            },
+                                            Err(err) => Err(err),
+                                        }
+                                    }
+
+                                    SharedObjectOperation::Write { owner_cid, name } => {
+                                        match object_id {
+                                            Some(object_id) => ensure_shared_object_permission(
+                                                pers,
+                                                owner_cid,
+                                                &name,
+                                                requester_cid,
+                                                SharedObjectPermission::ReadWrite,
+                                            )
+                                            .await
+                                            .map(|_| {
+                                                let _ = inner_mut_state!(session.state_container)
+                                                    .pending_shared_object_writes
+                                                    .insert(
+                                                        (requester_cid, object_id),
+                                                        PendingSharedObjectWrite { owner_cid, name },
+                                                    );
+                                                None
+                                            }),
+                                            None => Err(AccountError::msg(
+                                                "Shared object writes must declare the id of the upload",
+                                            )),
+                                        }
+                                    }
+
+                                    SharedObjectOperation::Delete { owner_cid, name } => {
+                                        match ensure_shared_object_permission(
+                                            pers,
+                                            owner_cid,
+                                            &name,
+                                            requester_cid,
+                                            SharedObjectPermission::ReadWrite,
+                                        )
+                                        .await
+                                        {
+                                            Ok(()) => pers
+                                                .delete_stored_object(owner_cid, &name)
+                                                .await
+                                                .map(|_| None),
+                                            Err(err) => Err(err),
+                                        }
+                                    }
+                                };
+
+                                let error_msg = match result {
+                                    Ok(notification) => {
+                                        if let Some((grantee_cid, notification)) = notification {
+                                            // the grantee is only notified if online
+                                            let _ = session.session_manager.send_signal_to_peer(
+                                                grantee_cid,
+                                                ticket,
+                                                PeerSignal::SharedObject(notification),
+                                                ts,
+                                                security_level,
+                                            );
+                                        }
+
+                                        None
+                                    }
+
+                                    Err(err) => Some(err.into_string()),
+                                };
+
+                                let response_packet = packet_crafter::file::craft_shared_object_ack(
+                                    &hyper_ratchet,
+                                    security_level,
+                                    ticket,
+                                    ts,
+                                    resp_target_cid,
+                                    error_msg,
+                                );
+                                send_with_error_logging(&preferred_primary_stream, response_packet);
+                            };
+
+                            spawn!(task);
+
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+
+                        None => {
+                            log::error!(target: "citadel", "Unable to validate SHARED OBJECT packet");
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+                    }
+                }
+
+                packet_flags::cmd::aux::file::SHARED_OBJECT_ACK => {
+                    log::trace!(target: "citadel", "RECV SHARED OBJECT ACK");
+                    match validation::file::validate_shared_object_ack(&header, &payload) {
+                        Some(payload) => {
+                            let response = NodeResult::SharedObject(SharedObjectResult {
+                                error_message: payload.error_msg,
+                                ticket,
+                            });
+
+                            session.send_to_kernel(response)?;
+
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+
+                        None => {
+                            log::error!(target: "citadel", "Unable to validate SHARED OBJECT ACK packet");
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+                    }
+                }
+
                 packet_flags::cmd::aux::file::REVFS_PULL_ACK => {
                     log::trace!(target: "citadel", "RECV REVFS PULL ACK");
                     match validation::file::validate_revfs_pull_ack(&header, &payload) {
@@ -295,6 +656,7 @@ pub fn process_file_packet(
                                 let error_signal = NodeResult::ReVFS(ReVFSResult {
                                     error_message: Some(error),
                                     data: None,
+                                    entries: None,
                                     ticket,
                                 });
 
@@ -311,6 +673,284 @@ pub fn process_file_packet(
                     }
                 }
 
+                packet_flags::cmd::aux::file::TRANSFER_CONTROL => {
+                    log::trace!(target: "citadel", "RECV TRANSFER CONTROL");
+                    match validation::file::validate_transfer_control(&header, &payload) {
+                        Some(payload) => {
+                            let key = FileKey::new(header.session_cid.get(), payload.object_id);
+                            if !state_container.on_transfer_control_received(
+                                key,
+                                payload.signal,
+                                payload.issuer_orientation,
+                            ) {
+                                log::warn!(target: "citadel", "Received {:?} for inactive transfer {:?}", payload.signal, key);
+                            }
+
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+
+                        None => {
+                            log::error!(target: "citadel", "Unable to validate TRANSFER CONTROL packet");
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+                    }
+                }
+
+                packet_flags::cmd::aux::file::DELTA_SIGNATURE_REQUEST => {
+                    log::trace!(target: "citadel", "RECV DELTA SIGNATURE REQUEST");
+                    match validation::file::validate_delta_signature_request(&header, &payload) {
+                        Some(payload) => {
+                            // objects received via file transfer are stored under the sender's cid
+                            let sender_cid = header.session_cid.get();
+                            let resp_target_cid = get_resp_target_cid_from_header(&header);
+                            let pers = session.account_manager.get_persistence_handler().clone();
+
+                            let preferred_primary_stream = return_if_none!(
+                                get_preferred_primary_stream(&header, session, &state_container)
+                            );
+
+                            let task = async move {
+                                // any failure results in a full transfer
+                                let signature = pers
+                                    .get_object_signature(
+                                        sender_cid,
+                                        &payload.name,
+                                        payload.block_size,
+                                    )
+                                    .await
+                                    .unwrap_or_else(|err| {
+                                        log::warn!(target: "citadel", "Unable to compute signature of {}: {:?}", payload.name, err);
+                                        None
+                                    });
+
+                                let response_packet = packet_crafter::file::craft_delta_signature(
+                                    &hyper_ratchet,
+                                    security_level,
+                                    ticket,
+                                    ts,
+                                    resp_target_cid,
+                                    signature,
+                                );
+                                send_with_error_logging(&preferred_primary_stream, response_packet);
+                            };
+
+                            spawn!(task);
+
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+
+                        None => {
+                            log::error!(target: "citadel", "Unable to validate DELTA SIGNATURE REQUEST packet");
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+                    }
+                }
+
+                packet_flags::cmd::aux::file::DELTA_SIGNATURE => {
+                    log::trace!(target: "citadel", "RECV DELTA SIGNATURE");
+                    match validation::file::validate_delta_signature(&header, &payload) {
+                        Some(payload) => {
+                            let PendingDeltaTransfer {
+                                source,
+                                max_group_size,
+                                virtual_target,
+                                security_level,
+                            } = return_if_none!(
+                                state_container.pending_delta_transfers.remove(&ticket),
+                                "Received a delta signature for an unknown transfer"
+                            );
+                            let session = session.clone();
+
+                            let task = async move {
+                                let prepared = match payload.signature {
+                                    Some(signature) => citadel_io::spawn_blocking(move || {
+                                        encode_delta_source(source, &signature)
+                                    })
+                                    .await
+                                    .map_err(|err| NetworkError::Generic(err.message))
+                                    .and_then(|res| {
+                                        res.map_err(|err| NetworkError::Generic(err.into_string()))
+                                    }),
+
+                                    None => {
+                                        log::trace!(target: "citadel", "Receiver has no basis; sending in full");
+                                        Ok((source, TransferType::FileTransfer))
+                                    }
+                                };
+
+                                let result = prepared.and_then(|(source, transfer_type)| {
+                                    session.process_outbound_file(
+                                        ticket,
+                                        max_group_size,
+                                        source,
+                                        virtual_target,
+                                        security_level,
+                                        transfer_type,
+                                        None,
+                                        |_| {},
+                                    )
+                                });
+
+                                if let Err(err) = result {
+                                    let _ = session.send_to_kernel(
+                                        NodeResult::InternalServerError(InternalServerError {
+                                            ticket_opt: Some(ticket),
+                                            message: err.into_string(),
+                                        }),
+                                    );
+                                }
+                            };
+
+                            spawn!(task);
+
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+
+                        None => {
+                            log::error!(target: "citadel", "Unable to validate DELTA SIGNATURE packet");
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+                    }
+                }
+
+                packet_flags::cmd::aux::file::CHUNK_QUERY => {
+                    log::trace!(target: "citadel", "RECV CHUNK QUERY");
+                    match validation::file::validate_chunk_query(&header, &payload) {
+                        Some(payload) => {
+                            // chunks are cached under the sender's cid
+                            let sender_cid = header.session_cid.get();
+                            let resp_target_cid = get_resp_target_cid_from_header(&header);
+                            let pers = session.account_manager.get_persistence_handler().clone();
+
+                            let preferred_primary_stream = return_if_none!(
+                                get_preferred_primary_stream(&header, session, &state_container)
+                            );
+
+                            let task = async move {
+                                // any failure results in a full transfer
+                                let cached = pers
+                                    .get_cached_chunks(sender_cid, &payload.hashes)
+                                    .await
+                                    .unwrap_or_else(|err| {
+                                        log::warn!(target: "citadel", "Unable to query the chunk cache for {}: {:?}", payload.name, err);
+                                        None
+                                    });
+
+                                let response_packet = packet_crafter::file::craft_chunk_query_ack(
+                                    &hyper_ratchet,
+                                    security_level,
+                                    ticket,
+                                    ts,
+                                    resp_target_cid,
+                                    cached,
+                                );
+                                send_with_error_logging(&preferred_primary_stream, response_packet);
+                            };
+
+                            spawn!(task);
+
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+
+                        None => {
+                            log::error!(target: "citadel", "Unable to validate CHUNK QUERY packet");
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+                    }
+                }
+
+                packet_flags::cmd::aux::file::CHUNK_QUERY_ACK => {
+                    log::trace!(target: "citadel", "RECV CHUNK QUERY ACK");
+                    match validation::file::validate_chunk_query_ack(&header, &payload) {
+                        Some(payload) => {
+                            let PendingDeduplicatedTransfer {
+                                object,
+                                max_group_size,
+                                virtual_target,
+                                security_level,
+                            } = return_if_none!(
+                                state_container
+                                    .pending_deduplicated_transfers
+                                    .remove(&ticket),
+                                "Received a chunk query ack for an unknown transfer"
+                            );
+                            let session = session.clone();
+
+                            let task = async move {
+                                // a missing or malformed response results in a full transfer
+                                let cached = payload
+                                    .cached
+                                    .filter(|cached| cached.len() == object.hashes.len())
+                                    .unwrap_or_else(|| vec![false; object.hashes.len()]);
+                                session
+                                    .session_manager
+                                    .deduplication_counters()
+                                    .record(&cached);
+
+                                // the object is only read once the transfer streams it
+                                let prepared = encode_deduplicated_source(object, &cached)
+                                    .map_err(|err| NetworkError::Generic(err.into_string()));
+
+                                let result = prepared.and_then(|(source, transfer_type)| {
+                                    session.process_outbound_file(
+                                        ticket,
+                                        max_group_size,
+                                        source,
+                                        virtual_target,
+                                        security_level,
+                                        transfer_type,
+                                        None,
+                                        |_| {},
+                                    )
+                                });
+
+                                if let Err(err) = result {
+                                    let _ = session.send_to_kernel(
+                                        NodeResult::InternalServerError(InternalServerError {
+                                            ticket_opt: Some(ticket),
+                                            message: err.into_string(),
+                                        }),
+                                    );
+                                }
+                            };
+
+                            spawn!(task);
+
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+
+                        None => {
+                            log::error!(target: "citadel", "Unable to validate CHUNK QUERY ACK packet");
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+                    }
+                }
+
+                packet_flags::cmd::aux::file::TRANSFER_MANIFEST => {
+                    log::trace!(target: "citadel", "RECV TRANSFER MANIFEST");
+                    match validation::file::validate_transfer_manifest(
+                        &hyper_ratchet,
+                        &header,
+                        &payload,
+                    ) {
+                        Some(payload) => {
+                            let key = FileKey::new(header.session_cid.get(), payload.object_id);
+                            if let Err(err) =
+                                state_container.on_transfer_manifest_received(key, payload.manifest)
+                            {
+                                log::warn!(target: "citadel", "Rejecting transfer manifest: {:?}", err);
+                            }
+
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+
+                        None => {
+                            log::error!(target: "citadel", "Unable to validate TRANSFER MANIFEST packet");
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+                    }
+                }
+
                 _ => {
                     log::error!(target: "citadel", "Invalid REVFS ACK command received");
                     Ok(PrimaryProcessorResult::Void)
@@ -324,3 +964,21 @@ pub fn process_file_packet(
         }
     }
 }
+
+async fn ensure_shared_object_permission(
+    pers: &PersistenceHandler,
+    owner_cid: u64,
+    name: &str,
+    requester_cid: u64,
+    required: SharedObjectPermission,
+) -> Result<(), AccountError> {
+    match pers
+        .get_shared_object_permission(owner_cid, name, requester_cid)
+        .await?
+    {
+        Some(permission) if permission.allows(required) => Ok(()),
+        _ => Err(AccountError::msg(format!(
+            "Insufficient permissions for object {name} owned by {owner_cid}"
+        ))),
+    }
+}
@@ -35,6 +35,10 @@ pub mod includes {
     pub use super::PrimaryProcessorResult;
 }
 
+///
+pub mod capabilities_packet;
+///
+pub mod challenge_packet;
 ///
 pub mod connect_packet;
 ///
@@ -48,6 +52,8 @@ pub mod keep_alive_packet;
 ///
 pub mod peer;
 ///
+pub mod ping_packet;
+///
 pub mod preconnect_packet;
 ///
 pub mod primary_group_packet;
@@ -19,8 +19,8 @@ pub enum GroupBroadcast {
     End(MessageGroupKey),
     EndResponse(MessageGroupKey, bool),
     Disconnected(MessageGroupKey),
-    // sender cid, key, message
-    Message(u64, MessageGroupKey, SecBuffer),
+    // sender cid, key, message, epoch. The epoch is stamped by the server when relaying
+    Message(u64, MessageGroupKey, SecBuffer, u64),
     // not actually a "response message", but rather, just like the other response types, just what the server sends to the requesting client
     MessageResponse(MessageGroupKey, bool),
     Add(MessageGroupKey, Vec<u64>),
@@ -38,6 +38,8 @@ pub enum GroupBroadcast {
     Invitation(MessageGroupKey),
     CreateResponse(Option<MessageGroupKey>),
     MemberStateChanged(MessageGroupKey, MemberState),
+    /// Sent to the remaining members once a member is removed, containing the new epoch
+    EpochRotated(MessageGroupKey, u64),
     GroupNonExists(MessageGroupKey),
     SignalResponse(Result<(), String>),
 }
@@ -74,17 +76,22 @@ pub async fn process_group_broadcast(
     log::trace!(target: "citadel", "[GROUP:{}] message: {:?}", session.is_server.if_true("server").if_false("client"), signal);
     match signal {
         GroupBroadcast::Create(initial_peers, options) => {
-            let key = session
-                .session_manager
-                .create_message_group_and_notify(
-                    timestamp,
-                    ticket,
-                    implicated_cid,
-                    initial_peers,
-                    security_level,
-                    options,
-                )
-                .await;
+            let key = if session.account_permissions.get().can_create_groups {
+                session
+                    .session_manager
+                    .create_message_group_and_notify(
+                        timestamp,
+                        ticket,
+                        implicated_cid,
+                        initial_peers,
+                        security_level,
+                        options,
+                    )
+                    .await
+            } else {
+                log::warn!(target: "citadel", "Rejecting group creation from {}: denied by the account permissions", implicated_cid);
+                None
+            };
             let signal = GroupBroadcast::CreateResponse(key);
             let return_packet = packet_crafter::peer_cmd::craft_group_message_packet(
                 sess_hyper_ratchet,
@@ -200,6 +207,13 @@ pub async fn process_group_broadcast(
             GroupBroadcast::MemberStateChanged(key, state),
         ),
 
+        GroupBroadcast::EpochRotated(key, epoch) => forward_signal(
+            session,
+            ticket,
+            Some(key),
+            GroupBroadcast::EpochRotated(key, epoch),
+        ),
+
         GroupBroadcast::End(key) => {
             return_if_none!(permission_gate(implicated_cid, key), "Permission denied");
             let success = session
@@ -244,22 +258,33 @@ pub async fn process_group_broadcast(
             res
         }),
 
-        GroupBroadcast::Message(username, key, message) => {
+        GroupBroadcast::Message(username, key, message, epoch) => {
             if session.is_server {
                 log::trace!(target: "citadel", "[Group/Server] Received message {:?}", message);
-                // The message will need to be broadcasted to every member in the group
-                let success = session
-                    .session_manager
-                    .broadcast_signal_to_group(
-                        implicated_cid,
-                        timestamp,
-                        ticket,
-                        key,
-                        GroupBroadcast::Message(username, key, message),
-                        security_level,
-                    )
+                // Only current members may broadcast. The message is stamped with the current
+                // epoch, then broadcasted to every member in the group
+                let success = match session
+                    .hypernode_peer_layer
+                    .get_message_group_epoch(key, implicated_cid)
                     .await
-                    .unwrap_or(false);
+                {
+                    Some(epoch) => session
+                        .session_manager
+                        .broadcast_signal_to_group(
+                            implicated_cid,
+                            timestamp,
+                            ticket,
+                            key,
+                            GroupBroadcast::Message(username, key, message, epoch),
+                            security_level,
+                        )
+                        .await
+                        .unwrap_or(false),
+                    None => {
+                        log::warn!(target: "citadel", "Peer {} is not a member of group {}", implicated_cid, key);
+                        false
+                    }
+                };
                 let resp = GroupBroadcast::MessageResponse(key, success);
                 let packet = packet_crafter::peer_cmd::craft_group_message_packet(
                     sess_hyper_ratchet,
@@ -276,7 +301,7 @@ pub async fn process_group_broadcast(
                     session,
                     ticket,
                     Some(key),
-                    GroupBroadcast::Message(username, key, message),
+                    GroupBroadcast::Message(username, key, message, epoch),
                 )
             }
         }
@@ -538,8 +563,15 @@ fn create_group_channel(
 impl From<GroupBroadcast> for GroupBroadcastPayload {
     fn from(broadcast: GroupBroadcast) -> Self {
         match broadcast {
-            GroupBroadcast::Message(sender, _key, payload) => {
-                GroupBroadcastPayload::Message { payload, sender }
+            GroupBroadcast::Message(sender, _key, payload, epoch) => {
+                GroupBroadcastPayload::Message {
+                    payload,
+                    sender,
+                    epoch,
+                }
+            }
+            GroupBroadcast::EpochRotated(_key, epoch) => {
+                GroupBroadcastPayload::EpochRotated { epoch }
             }
             evt => GroupBroadcastPayload::Event { payload: evt },
         }
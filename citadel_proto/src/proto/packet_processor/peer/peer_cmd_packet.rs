@@ -13,26 +13,30 @@ use citadel_user::serialization::SyncIO;
 use netbeam::sync::RelativeNodeType;
 
 use crate::error::NetworkError;
+use crate::proto::misc::security_downgrade::check_for_downgrade;
 use crate::proto::node_result::{PeerChannelCreated, PeerEvent};
 use crate::proto::outbound_sender::OutboundPrimaryStreamSender;
+use crate::proto::packet_crafter::peer_cmd::C2S_ENCRYPTION_ONLY;
 use crate::proto::packet_processor::includes::*;
 use crate::proto::packet_processor::peer::group_broadcast;
 use crate::proto::packet_processor::preconnect_packet::{
-    calculate_sync_time, generate_hole_punch_crypt_container,
+    calculate_sync_time, generate_hole_punch_crypt_container, traverse_deferred_udp,
 };
 use crate::proto::packet_processor::primary_group_packet::{
     get_proper_hyper_ratchet, get_resp_target_cid,
 };
+use crate::proto::peer::channel::CrossConnect;
 use crate::proto::peer::hole_punch_compat_sink_stream::ReliableOrderedCompatStream;
 use crate::proto::peer::p2p_conn_handler::attempt_simultaneous_hole_punch;
 use crate::proto::peer::peer_crypt::{KeyExchangeProcess, PeerNatInfo};
 use crate::proto::peer::peer_layer::{
-    HyperNodePeerLayerInner, HypernodeConnectionType, PeerConnectionType, PeerResponse, PeerSignal,
-    UdpMode,
+    HyperNodePeerLayerInner, HypernodeConnectionType, PeerConnectionType, PeerListDelta,
+    PeerResponse, PeerSignal, SimultaneousConnect, UdpMode,
 };
 use crate::proto::remote::Ticket;
 use crate::proto::session_manager::HdpSessionManager;
 use crate::proto::state_subcontainers::peer_kem_state_container::PeerKemStateContainer;
+use crate::proto::state_subcontainers::stage_machine::PeerKemStage;
 use netbeam::sync::network_endpoint::NetworkEndpoint;
 
 #[allow(unused_results)]
@@ -158,6 +162,17 @@ pub async fn process_peer_cmd(
                             return Ok(PrimaryProcessorResult::Void);
                         }
 
+                        PeerSignal::EnableUdp(_) => {
+                            // the server is ready to traverse NATs
+                            if let Some(ready_tx) = inner_mut_state!(session.state_container)
+                                .deferred_udp_ready_tx
+                                .take()
+                            {
+                                let _ = ready_tx.send(());
+                            }
+                            return Ok(PrimaryProcessorResult::Void);
+                        }
+
                         PeerSignal::DisconnectUDP(vconn) => {
                             let target_cid = return_if_none!(get_resp_target_cid(vconn));
                             inner_mut_state!(session.state_container)
@@ -179,6 +194,9 @@ pub async fn process_peer_cmd(
                                 log::warn!(target: "citadel", "Unable to remove hyperlan peer {}", peer_cid);
                             }
 
+                            let _ = inner_mut_state!(session.state_container)
+                                .remove_virtual_connection(*peer_cid);
+
                             kernel_tx.unbounded_send(NodeResult::PeerEvent(PeerEvent {
                                 event: PeerSignal::DeregistrationSuccess(*peer_cid),
                                 ticket,
@@ -233,6 +251,19 @@ pub async fn process_peer_cmd(
                             return Ok(PrimaryProcessorResult::Void);
                         }
 
+                        PeerSignal::PostConnect(conn, _, None, _, _) => {
+                            let peer_cid = conn.get_original_implicated_cid();
+                            if inner_state!(session.state_container)
+                                .outgoing_peer_connect_attempts
+                                .contains_key(&peer_cid)
+                            {
+                                // the server collapses both requests into a single channel once this node's
+                                // request arrives, hence the request of the peer must not be answered separately
+                                log::trace!(target: "citadel", "Cross-connect with {} detected. Awaiting the collapsed channel", peer_cid);
+                                return Ok(PrimaryProcessorResult::Void);
+                            }
+                        }
+
                         PeerSignal::PostConnect(
                             conn,
                             _,
@@ -255,6 +286,26 @@ pub async fn process_peer_cmd(
                                         // unique to the session.
                                         //let mut state_container = inner_mut!(session.state_container);
                                         //let peer_cid = conn.get_original_implicated_cid();
+                                        let requested = inner_state!(session.state_container)
+                                            .outgoing_peer_connect_attempts
+                                            .get(original_implicated_cid)
+                                            .copied();
+                                        if let Some((local_ticket, preferred)) = requested {
+                                            if check_for_downgrade(
+                                                session,
+                                                local_ticket,
+                                                implicated_cid,
+                                                *original_implicated_cid,
+                                                &preferred,
+                                                endpoint_security_settings,
+                                            ) {
+                                                let _ = inner_mut_state!(session.state_container)
+                                                    .outgoing_peer_connect_attempts
+                                                    .remove(original_implicated_cid);
+                                                return Ok(PrimaryProcessorResult::Void);
+                                            }
+                                        }
+
                                         let mut peer_kem_state_container =
                                             PeerKemStateContainer::new(
                                                 *endpoint_security_settings,
@@ -337,6 +388,21 @@ pub async fn process_peer_cmd(
                                     //let mut state_container = inner_mut!(session.state_container);
                                     //let this_cid = conn.get_original_target_cid();
                                     let peer_cid = conn.get_original_implicated_cid();
+                                    let preferred = inner_state!(session.state_container)
+                                        .session_security_settings;
+                                    if let Some(preferred) = preferred {
+                                        if check_for_downgrade(
+                                            session,
+                                            ticket,
+                                            implicated_cid,
+                                            peer_cid,
+                                            &preferred,
+                                            session_security_settings,
+                                        ) {
+                                            return Ok(PrimaryProcessorResult::Void);
+                                        }
+                                    }
+
                                     let transfer_deser = return_if_none!(
                                         AliceToBobTransfer::deserialize_from(transfer)
                                     );
@@ -357,9 +423,28 @@ pub async fn process_peer_cmd(
                                     let bob_transfer =
                                         return_if_none!(transfer.serialize_to_vector().ok());
 
+                                    let mut state_container =
+                                        inner_mut_state!(session.state_container);
+                                    // if this node concurrently requested to connect to the peer, the server absorbed
+                                    // that request into the one of the peer
+                                    let cross_connect = state_container
+                                        .outgoing_peer_connect_attempts
+                                        .get(&peer_cid)
+                                        .map(|(local_ticket, _)| *local_ticket)
+                                        .filter(|local_ticket| *local_ticket != ticket)
+                                        .map(|absorbed_ticket| CrossConnect {
+                                            initiator_cid: peer_cid,
+                                            channel_ticket: ticket,
+                                            absorbed_ticket,
+                                        });
+
                                     let signal = PeerSignal::Kem(
                                         conn.reverse(),
-                                        KeyExchangeProcess::Stage1(bob_transfer, None),
+                                        KeyExchangeProcess::Stage1(
+                                            bob_transfer,
+                                            None,
+                                            cross_connect.map(|cross| cross.absorbed_ticket),
+                                        ),
                                     );
 
                                     let mut state_container_kem = PeerKemStateContainer::new(
@@ -367,9 +452,14 @@ pub async fn process_peer_cmd(
                                         *udp_enabled == UdpMode::Enabled,
                                     );
                                     state_container_kem.constructor = Some(bob_constructor);
-                                    inner_mut_state!(session.state_container)
+                                    state_container_kem.cross_connect = cross_connect;
+                                    return_if_illegal!(state_container_kem
+                                        .stage
+                                        .advance(PeerKemStage::Stage1));
+                                    state_container
                                         .peer_kem_states
                                         .insert(peer_cid, state_container_kem);
+                                    std::mem::drop(state_container);
 
                                     let stage1_kem = packet_crafter::peer_cmd::craft_peer_signal(
                                         &sess_hyper_ratchet,
@@ -382,7 +472,11 @@ pub async fn process_peer_cmd(
                                     Ok(PrimaryProcessorResult::ReplyToSender(stage1_kem))
                                 }
 
-                                KeyExchangeProcess::Stage1(transfer, Some(bob_nat_info)) => {
+                                KeyExchangeProcess::Stage1(
+                                    transfer,
+                                    Some(bob_nat_info),
+                                    absorbed_ticket,
+                                ) => {
                                     // Here, we finalize the creation of the pqc for alice, and then, generate the new toolset
                                     // The toolset gets encrypted to ensure the central server doesn't see the toolset. This is
                                     // to combat a "chinese communist hijack" scenario wherein a rogue government takes over our
@@ -403,6 +497,11 @@ pub async fn process_peer_cmd(
                                             inner_mut_state!(session.state_container);
                                         let peer_cid = conn.get_original_implicated_cid();
                                         let this_cid = conn.get_original_target_cid();
+                                        return_if_illegal!(return_if_none!(state_container
+                                            .peer_kem_states
+                                            .get_mut(&peer_cid))
+                                        .stage
+                                        .advance(PeerKemStage::Stage2));
                                         let mut kem_state = return_if_none!(state_container
                                             .peer_kem_states
                                             .remove(&peer_cid));
@@ -431,7 +530,18 @@ pub async fn process_peer_cmd(
                                         // toolset AND the single drill
                                         let toolset = Toolset::new(this_cid, hyper_ratchet);
                                         // now, register the loaded PQC + toolset into the virtual conn
-                                        let peer_crypto = PeerSessionCrypto::new(toolset, true);
+                                        let mut peer_crypto = PeerSessionCrypto::new(toolset, true);
+                                        peer_crypto.set_drift_tolerance(
+                                            session_security_settings.drift_tolerance,
+                                        );
+                                        if let Some(max) =
+                                            session_security_settings.max_toolset_history
+                                        {
+                                            peer_crypto.set_max_toolset_history(max);
+                                        }
+                                        peer_crypto.set_precompute_constructor(
+                                            session_security_settings.precompute_constructor,
+                                        );
                                         let vconn_type = VirtualConnectionType::LocalGroupPeer(
                                             this_cid, peer_cid,
                                         );
@@ -442,6 +552,12 @@ pub async fn process_peer_cmd(
                                         log::trace!(target: "citadel", "[STUN] Peer public addr: {:?} || needs TURN? {}", &bob_predicted_socket_addr, needs_turn);
                                         let udp_rx_opt = kem_state.udp_channel_sender.rx.take();
 
+                                        let cross_connect =
+                                            absorbed_ticket.map(|absorbed_ticket| CrossConnect {
+                                                initiator_cid: this_cid,
+                                                channel_ticket: ticket,
+                                                absorbed_ticket,
+                                            });
                                         let channel = state_container
                                             .insert_new_peer_virtual_connection_as_endpoint(
                                                 bob_predicted_socket_addr,
@@ -451,7 +567,8 @@ pub async fn process_peer_cmd(
                                                 vconn_type,
                                                 peer_crypto,
                                                 session,
-                                            );
+                                            )
+                                            .with_cross_connect(cross_connect);
                                         // load the channel now that the keys have been exchanged
 
                                         kem_state.local_is_initiator = true;
@@ -479,7 +596,8 @@ pub async fn process_peer_cmd(
                                             );
                                         let ticket_for_chan = state_container
                                             .outgoing_peer_connect_attempts
-                                            .remove(&peer_cid);
+                                            .remove(&peer_cid)
+                                            .map(|(ticket, _)| ticket);
                                         std::mem::drop(state_container);
                                         let stun_servers = session.stun_servers.clone();
                                         let encrypted_config_container =
@@ -488,6 +606,7 @@ pub async fn process_peer_cmd(
                                                 SecurityLevel::Standard,
                                                 peer_cid,
                                                 stun_servers,
+                                                session.hole_punch_settings.as_ref(),
                                             );
 
                                         // we need to use the session pqc since this signal needs to get processed by the center node
@@ -576,6 +695,7 @@ pub async fn process_peer_cmd(
                                         let kem = return_if_none!(state_container
                                             .peer_kem_states
                                             .get_mut(&peer_cid));
+                                        return_if_illegal!(kem.stage.advance(PeerKemStage::Stage2));
                                         let session_security_settings =
                                             kem.session_security_settings;
                                         // since the AES-GCM was a success, we can now entrust that the toolset is perfectly symmetric to the
@@ -583,6 +703,7 @@ pub async fn process_peer_cmd(
                                         let bob_constructor =
                                             return_if_none!(kem.constructor.take());
                                         let udp_rx_opt = kem.udp_channel_sender.rx.take();
+                                        let cross_connect = kem.cross_connect;
                                         let endpoint_hyper_ratchet = return_if_none!(
                                             bob_constructor.finish_with_custom_cid(this_cid)
                                         );
@@ -590,7 +711,19 @@ pub async fn process_peer_cmd(
                                             endpoint_hyper_ratchet.get_default_security_level();
                                         let toolset =
                                             Toolset::new(this_cid, endpoint_hyper_ratchet.clone());
-                                        let peer_crypto = PeerSessionCrypto::new(toolset, false);
+                                        let mut peer_crypto =
+                                            PeerSessionCrypto::new(toolset, false);
+                                        peer_crypto.set_drift_tolerance(
+                                            session_security_settings.drift_tolerance,
+                                        );
+                                        if let Some(max) =
+                                            session_security_settings.max_toolset_history
+                                        {
+                                            peer_crypto.set_max_toolset_history(max);
+                                        }
+                                        peer_crypto.set_precompute_constructor(
+                                            session_security_settings.precompute_constructor,
+                                        );
 
                                         // create an endpoint vconn
                                         let vconn_type = VirtualConnectionType::LocalGroupPeer(
@@ -610,7 +743,8 @@ pub async fn process_peer_cmd(
                                                 vconn_type,
                                                 peer_crypto,
                                                 session,
-                                            );
+                                            )
+                                            .with_cross_connect(cross_connect);
 
                                         log::trace!(target: "citadel", "Virtual connection forged on endpoint tuple {} -> {}", this_cid, peer_cid);
                                         // We can now send the channel to the kernel, where TURN traversal is immediantly available.
@@ -618,7 +752,8 @@ pub async fn process_peer_cmd(
                                         //state_container.kernel_tx.unbounded_send(HdpServerResult::PeerChannelCreated(ticket, channel, udp_rx_opt)).ok()?;
                                         let ticket_for_chan = state_container
                                             .outgoing_peer_connect_attempts
-                                            .remove(&peer_cid);
+                                            .remove(&peer_cid)
+                                            .map(|(ticket, _)| ticket);
                                         let hole_punch_compat_stream =
                                             ReliableOrderedCompatStream::new(
                                                 return_if_none!(session.to_primary_stream.clone()),
@@ -661,6 +796,7 @@ pub async fn process_peer_cmd(
                                                 SecurityLevel::Standard,
                                                 peer_cid,
                                                 stun_servers,
+                                                session.hole_punch_settings.as_ref(),
                                             );
                                         let diff = Duration::from_nanos(i64::abs(
                                             timestamp - *sync_time_ns,
@@ -695,6 +831,15 @@ pub async fn process_peer_cmd(
 
                                 KeyExchangeProcess::HolePunchFailed => {
                                     log::trace!(target: "citadel", "RECV HolePunchFailed");
+                                    let peer_cid = conn.get_original_implicated_cid();
+                                    if let Some(kem) = inner_mut_state!(session.state_container)
+                                        .peer_kem_states
+                                        .get_mut(&peer_cid)
+                                    {
+                                        return_if_illegal!(kem
+                                            .stage
+                                            .advance(PeerKemStage::Failure));
+                                    }
                                     // TODO/optional: for future consideration, but is currently not at all necessary
                                     Ok(PrimaryProcessorResult::Void)
                                 }
@@ -753,6 +898,11 @@ async fn process_signal_command_as_server(
     security_level: SecurityLevel,
 ) -> Result<PrimaryProcessorResult, NetworkError> {
     let session = sess_ref;
+    if let Err(err) = check_peer_permissions(session, &signal) {
+        log::warn!(target: "citadel", "Rejecting peer signal from {}: {}", header.session_cid.get(), err);
+        return reply_to_sender_err(err, &sess_hyper_ratchet, ticket, timestamp, security_level);
+    }
+
     match signal {
         PeerSignal::Kem(conn, mut kep) => {
             // before just routing the signals, we also need to add socket information into intercepted stage1 and stage2 signals
@@ -777,7 +927,7 @@ async fn process_signal_command_as_server(
             };
 
             match &mut kep {
-                KeyExchangeProcess::Stage1(_, val) | KeyExchangeProcess::Stage2(_, val) => {
+                KeyExchangeProcess::Stage1(_, val, _) | KeyExchangeProcess::Stage2(_, val) => {
                     *val = Some(peer_nat_info);
                 }
 
@@ -979,6 +1129,31 @@ async fn process_signal_command_as_server(
 
                     match dereg_result {
                         Ok(_) => {
+                            // the peers may no longer communicate, hence tear down any virtual connection between them
+                            let _ = inner_mut_state!(session.state_container)
+                                .remove_virtual_connection(target_cid);
+                            let _ = session_manager.disconnect_virtual_conn(
+                                implicated_cid,
+                                target_cid,
+                                |peer_hyper_ratchet| {
+                                    packet_crafter::peer_cmd::craft_peer_signal(
+                                        peer_hyper_ratchet,
+                                        PeerSignal::Disconnect(
+                                            PeerConnectionType::HyperLANPeerToHyperLANPeer(
+                                                implicated_cid,
+                                                target_cid,
+                                            ),
+                                            Some(PeerResponse::Disconnected(format!(
+                                                "Peer {implicated_cid} deregistered from {target_cid}"
+                                            ))),
+                                        ),
+                                        ticket,
+                                        timestamp,
+                                        security_level,
+                                    )
+                                },
+                            );
+
                             if register_event {
                                 log::trace!(target: "citadel", "Registering dereg event");
                                 peer_layer_lock
@@ -990,6 +1165,20 @@ async fn process_signal_command_as_server(
                                         |_| {},
                                     )
                                     .await;
+                                session_manager.notify_peer_list_subscriber(
+                                    implicated_cid,
+                                    PeerListDelta::MutualsChanged {
+                                        peer_cid: target_cid,
+                                        is_mutual: false,
+                                    },
+                                );
+                                session_manager.notify_peer_list_subscriber(
+                                    target_cid,
+                                    PeerListDelta::MutualsChanged {
+                                        peer_cid: implicated_cid,
+                                        is_mutual: false,
+                                    },
+                                );
                             }
                             let peer_alert_signal =
                                 PeerSignal::DeregistrationSuccess(implicated_cid);
@@ -1074,14 +1263,13 @@ async fn process_signal_command_as_server(
                         // the signal is going to be routed from HyperLAN client A to HyperLAN client B (initiation phase)
                         let to_primary_stream = return_if_none!(session.to_primary_stream.clone());
                         let sess_mgr = session.session_manager.clone();
-                        if let Some(ticket_new) =
-                            peer_layer.check_simultaneous_connect(implicated_cid, target_cid)
-                        {
-                            log::trace!(target: "citadel", "Simultaneous connect detected! Simulating implicated_cid={} sent an accept_connect to target={}", implicated_cid, target_cid);
-                            log::trace!(target: "citadel", "Simultaneous connect: first_ticket: {} | sender expected ticket: {}", ticket_new, ticket);
-                            // NOTE: Packet will rebound to sender, then, sender will locally send
-                            // packet to the peer who first attempted a connect request
-                            let _ =
+                        match peer_layer.check_simultaneous_connect(implicated_cid, target_cid) {
+                            Some(SimultaneousConnect::AcceptPeerPosting(ticket_new)) => {
+                                log::trace!(target: "citadel", "Simultaneous connect detected! Simulating implicated_cid={} sent an accept_connect to target={}", implicated_cid, target_cid);
+                                log::trace!(target: "citadel", "Simultaneous connect: first_ticket: {} | sender expected ticket: {}", ticket_new, ticket);
+                                // NOTE: Packet will rebound to sender, then, sender will locally send
+                                // packet to the peer who first attempted a connect request
+                                let _ =
                                 super::server::post_connect::handle_response_phase_post_connect(
                                     &mut peer_layer,
                                     peer_conn_type,
@@ -1097,28 +1285,78 @@ async fn process_signal_command_as_server(
                                     security_level,
                                 )
                                 .await?;
-                            Ok(PrimaryProcessorResult::Void)
-                        } else {
-                            route_signal_and_register_ticket_forwards(
-                                &mut peer_layer,
-                                PeerSignal::PostConnect(
-                                    peer_conn_type,
-                                    Some(ticket),
-                                    None,
-                                    endpoint_security_level,
-                                    udp_enabled,
-                                ),
-                                TIMEOUT,
-                                implicated_cid,
-                                target_cid,
-                                timestamp,
-                                ticket,
-                                &to_primary_stream,
-                                &sess_mgr,
-                                &sess_hyper_ratchet,
-                                security_level,
-                            )
-                            .await
+                                Ok(PrimaryProcessorResult::Void)
+                            }
+
+                            Some(SimultaneousConnect::WithdrawPeerPosting(peer_ticket)) => {
+                                log::trace!(target: "citadel", "Simultaneous connect detected! Withdrawing posting {} of target={}, and simulating it sent an accept_connect to implicated_cid={}", peer_ticket, target_cid, implicated_cid);
+                                let (peer_sess, peer_hyper_ratchet) = return_if_none!(
+                                    sess_mgr.get_session_with_c2s_ratchet(target_cid),
+                                    "Simultaneous connect peer no longer connected"
+                                );
+                                let _ = peer_layer
+                                    .remove_tracked_posting_inner(target_cid, peer_ticket);
+                                // the response phase consumes the posting of the implicated client, hence post it first
+                                peer_layer
+                                    .insert_tracked_posting(
+                                        implicated_cid,
+                                        TIMEOUT,
+                                        ticket,
+                                        PeerSignal::PostConnect(
+                                            peer_conn_type,
+                                            Some(ticket),
+                                            None,
+                                            endpoint_security_level,
+                                            udp_enabled,
+                                        ),
+                                        |_| {},
+                                    )
+                                    .await;
+                                // NOTE: from the perspective of the response phase, the peer is the implicated client
+                                let _ =
+                                    super::server::post_connect::handle_response_phase_post_connect(
+                                        &mut peer_layer,
+                                        PeerConnectionType::HyperLANPeerToHyperLANPeer(
+                                            target_cid,
+                                            implicated_cid,
+                                        ),
+                                        ticket,
+                                        PeerResponse::Accept(None),
+                                        endpoint_security_level,
+                                        udp_enabled,
+                                        target_cid,
+                                        implicated_cid,
+                                        timestamp,
+                                        &peer_sess,
+                                        &peer_hyper_ratchet,
+                                        security_level,
+                                    )
+                                    .await?;
+                                Ok(PrimaryProcessorResult::Void)
+                            }
+
+                            None => {
+                                route_signal_and_register_ticket_forwards(
+                                    &mut peer_layer,
+                                    PeerSignal::PostConnect(
+                                        peer_conn_type,
+                                        Some(ticket),
+                                        None,
+                                        endpoint_security_level,
+                                        udp_enabled,
+                                    ),
+                                    TIMEOUT,
+                                    implicated_cid,
+                                    target_cid,
+                                    timestamp,
+                                    ticket,
+                                    &to_primary_stream,
+                                    &sess_mgr,
+                                    &sess_hyper_ratchet,
+                                    security_level,
+                                )
+                                .await
+                            }
                         }
                     }
                 }
@@ -1200,7 +1438,30 @@ async fn process_signal_command_as_server(
                         Ok(PrimaryProcessorResult::Void)
                     } else {
                         //reply_to_sender_err(format!("{} is not connected to {}", implicated_cid, target_cid), &sess_hyper_ratchet, ticket, timestamp, security_level)
-                        // connection may already be dc'ed from another dc attempt. Just say nothing
+                        // connection may already be dc'ed from another dc attempt. Just say nothing, but ensure the
+                        // peer does not retain its half of the virtual connection
+                        std::mem::drop(state_container);
+                        let _ = session.session_manager.disconnect_virtual_conn(
+                            implicated_cid,
+                            target_cid,
+                            |peer_hyper_ratchet| {
+                                packet_crafter::peer_cmd::craft_peer_signal(
+                                    peer_hyper_ratchet,
+                                    PeerSignal::Disconnect(
+                                        PeerConnectionType::HyperLANPeerToHyperLANPeer(
+                                            implicated_cid,
+                                            target_cid,
+                                        ),
+                                        Some(resp.unwrap_or(PeerResponse::Disconnected(format!(
+                                            "Peer {implicated_cid} closed the virtual connection to {target_cid}"
+                                        )))),
+                                    ),
+                                    ticket,
+                                    timestamp,
+                                    security_level,
+                                )
+                            },
+                        );
                         Ok(PrimaryProcessorResult::Void)
                     }
                 }
@@ -1290,6 +1551,78 @@ async fn process_signal_command_as_server(
             }
         },
 
+        PeerSignal::SubscribePeerList(hypernode_conn_type) => match hypernode_conn_type {
+            HypernodeConnectionType::HyperLANPeerToHyperLANServer(implicated_cid) => {
+                session.session_manager.subscribe_to_peer_list(
+                    implicated_cid,
+                    ticket,
+                    security_level,
+                );
+                reply_to_sender(
+                    PeerSignal::SignalReceived(ticket),
+                    &sess_hyper_ratchet,
+                    ticket,
+                    timestamp,
+                    security_level,
+                )
+            }
+
+            HypernodeConnectionType::HyperLANPeerToHyperWANServer(_implicated_cid, _icid) => {
+                log::error!(target: "citadel", "HyperWAN functionality not implemented");
+                Ok(PrimaryProcessorResult::Void)
+            }
+        },
+
+        PeerSignal::EnableUdp(hypernode_conn_type) => {
+            let implicated_cid = header.session_cid.get();
+            let stream = {
+                let mut state_container = inner_mut_state!(session.state_container);
+                if state_container.udp_primary_outbound_tx.is_some() {
+                    return reply_to_sender_err(
+                        "UDP is already enabled for this session",
+                        &sess_hyper_ratchet,
+                        ticket,
+                        timestamp,
+                        security_level,
+                    );
+                }
+
+                ReliableOrderedCompatStream::new(
+                    return_if_none!(session.to_primary_stream.clone()),
+                    &mut state_container,
+                    C2S_ENCRYPTION_ONLY,
+                    sess_hyper_ratchet.clone(),
+                    security_level,
+                )
+            };
+
+            let session_ref = session.clone();
+            let hyper_ratchet = sess_hyper_ratchet.clone();
+            spawn!(async move {
+                if let Err(err) = traverse_deferred_udp(
+                    session_ref,
+                    RelativeNodeType::Receiver,
+                    stream,
+                    hyper_ratchet,
+                    implicated_cid,
+                    ticket,
+                )
+                .await
+                {
+                    log::warn!(target: "citadel", "Unable to enable UDP for {}: {:?}", implicated_cid, err);
+                }
+            });
+
+            // signal the client that the pipe is ready
+            reply_to_sender(
+                PeerSignal::EnableUdp(hypernode_conn_type),
+                &sess_hyper_ratchet,
+                ticket,
+                timestamp,
+                security_level,
+            )
+        }
+
         PeerSignal::BroadcastConnected(_hypernode_conn_type) => Ok(PrimaryProcessorResult::Void),
 
         PeerSignal::PostFileUploadRequest(_peer_conn_type, _file_metadata, _ticket) => {
@@ -1323,6 +1656,11 @@ async fn process_signal_command_as_server(
 
         PeerSignal::DeregistrationSuccess(..) => Ok(PrimaryProcessorResult::Void),
 
+        // only the server may send these
+        PeerSignal::SharedObject(..) | PeerSignal::PeerListChanged(..) => {
+            Ok(PrimaryProcessorResult::Void)
+        }
+
         PeerSignal::DisconnectUDP(v_conn) => {
             // close this UDP channel
             inner_mut_state!(session.state_container).remove_udp_channel(v_conn.get_target_cid());
@@ -1362,6 +1700,24 @@ fn reply_to_sender_err<E: ToString>(
     ))
 }
 
+/// Ensures the permissions of the account permit the peer registration or connection in `signal`
+fn check_peer_permissions(session: &HdpSession, signal: &PeerSignal) -> Result<(), &'static str> {
+    let permissions = session.account_permissions.get();
+    match signal {
+        PeerSignal::PostRegister(..) | PeerSignal::PostConnect(..) if !permissions.can_p2p => {
+            Err("Peer connections are not permitted for this account")
+        }
+
+        PeerSignal::PostConnect(_, _, _, session_security_settings, _)
+            if !permissions.permits_security_level(session_security_settings.security_level) =>
+        {
+            Err("The security level exceeds the maximum permitted for this account")
+        }
+
+        _ => Ok(()),
+    }
+}
+
 fn construct_error_signal<E: ToString>(
     err: E,
     hyper_ratchet: &StackedRatchet,
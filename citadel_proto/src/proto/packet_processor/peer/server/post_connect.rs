@@ -48,7 +48,7 @@ pub(crate) async fn handle_response_phase_post_connect(
                                       this_sess_state_container.insert_new_virtual_connection_as_server(target_cid, virtual_conn_relative_to_this, peer_udp_sender, peer_tcp_sender);
                                       peer_sess_state_container.insert_new_virtual_connection_as_server(implicated_cid, virtual_conn_relative_to_peer, this_udp_sender, this_tcp_sender);
                                       log::trace!(target: "citadel", "Virtual connection between {} <-> {} forged", implicated_cid, target_cid);
-                                      // TODO: Ensure that, upon disconnect, the corresponding entry gets dropped in the connection table of not the dropped peer
+                                      // Both entries are removed once either peer disconnects or deregisters, or once either session ends
                                   }
                               }
                           }, security_level).await
@@ -2,7 +2,7 @@ use crate::error::NetworkError;
 use crate::prelude::{PeerConnectionType, PeerResponse, PeerSignal};
 use crate::proto::packet_processor::peer::peer_cmd_packet::route_signal_response;
 use crate::proto::packet_processor::PrimaryProcessorResult;
-use crate::proto::peer::peer_layer::{HyperNodePeerLayerInner, Username};
+use crate::proto::peer::peer_layer::{HyperNodePeerLayerInner, PeerListDelta, Username};
 use crate::proto::remote::Ticket;
 use crate::proto::session::HdpSession;
 use citadel_crypt::entropy_bank::SecurityLevel;
@@ -29,10 +29,14 @@ pub async fn handle_response_phase_post_register(
                           |this_sess, _peer_sess, _original_tracked_posting| {
                               if !decline {
                                   let account_manager = this_sess.account_manager.clone();
+                                  let session_manager = this_sess.session_manager.clone();
                                   let task = async move {
                                       if let Err(err) = account_manager.register_hyperlan_p2p_as_server(implicated_cid, target_cid).await {
                                           // TODO: route error
                                           log::error!(target: "citadel", "Unable to register hyperlan p2p at server: {:?}", err);
+                                      } else {
+                                          session_manager.notify_peer_list_subscriber(implicated_cid, PeerListDelta::MutualsChanged { peer_cid: target_cid, is_mutual: true });
+                                          session_manager.notify_peer_list_subscriber(target_cid, PeerListDelta::MutualsChanged { peer_cid: implicated_cid, is_mutual: true });
                                       }
                                   };
 
@@ -0,0 +1,82 @@
+use super::includes::*;
+use crate::error::NetworkError;
+use crate::prelude::{PingResult, Ticket};
+use crate::proto::packet_processor::primary_group_packet::{
+    get_proper_hyper_ratchet, get_resp_target_cid_from_header,
+};
+use crate::proto::{get_preferred_primary_stream, send_with_error_logging};
+use std::sync::atomic::Ordering;
+
+/// Answers a REQUEST with the time spent processing it, or, completes an outbound ping once its
+/// RESPONSE arrives. Works for both client to server and peer to peer connections
+#[cfg_attr(feature = "localhost-testing", tracing::instrument(target = "citadel", skip_all, ret, err, fields(is_server = session.is_server, src = packet.parse().unwrap().0.session_cid.get(), target = packet.parse().unwrap().0.target_cid.get())))]
+pub fn process_ping(
+    session: &HdpSession,
+    packet: HdpPacket,
+    header_drill_vers: u32,
+    proxy_cid_info: Option<(u64, u64)>,
+) -> Result<PrimaryProcessorResult, NetworkError> {
+    let received = Instant::now();
+    if session.state.load(Ordering::Relaxed) != SessionState::Connected {
+        log::warn!(target: "citadel", "Ping received, but session not connected. Dropping packet");
+        return Ok(PrimaryProcessorResult::Void);
+    }
+
+    let (header, payload, _, _) = packet.decompose();
+    let mut state_container = inner_mut_state!(session.state_container);
+    let hyper_ratchet = return_if_none!(
+        get_proper_hyper_ratchet(header_drill_vers, &state_container, proxy_cid_info),
+        "Unable to get proper HR"
+    );
+    let (header, _payload, hyper_ratchet) = return_if_none!(
+        validation::aead::validate(hyper_ratchet, &header, payload),
+        "Unable to validate ping packet"
+    );
+
+    let ticket: Ticket = header.context_info.get().into();
+    let peer_cid = get_resp_target_cid_from_header(&header);
+
+    match header.cmd_aux {
+        packet_flags::cmd::aux::ping::REQUEST => {
+            let preferred_primary_stream = return_if_none!(
+                get_preferred_primary_stream(&header, session, &state_container),
+                "Unable to get preferred primary stream"
+            );
+            let reply = packet_crafter::ping::craft_ping(
+                &hyper_ratchet,
+                packet_flags::cmd::aux::ping::RESPONSE,
+                header.security_level.into(),
+                ticket,
+                session.time_tracker.get_global_time_ns(),
+                peer_cid,
+                received.elapsed().as_nanos() as u64,
+            );
+            send_with_error_logging(&preferred_primary_stream, reply);
+            Ok(PrimaryProcessorResult::Void)
+        }
+
+        packet_flags::cmd::aux::ping::RESPONSE => {
+            let sent = return_if_none!(
+                state_container.pending_pings.remove(&ticket),
+                "Received a ping response for an unknown ticket"
+            );
+            std::mem::drop(state_container);
+
+            let implicated_cid =
+                return_if_none!(session.implicated_cid.get(), "Implicated CID not loaded");
+            session.send_to_kernel(NodeResult::PingResult(PingResult {
+                ticket,
+                implicated_cid,
+                peer_cid,
+                rtt: received.duration_since(sent),
+                remote_processing_time: Duration::from_nanos(header.group.get()),
+            }))?;
+            Ok(PrimaryProcessorResult::Void)
+        }
+
+        _ => {
+            log::error!(target: "citadel", "Invalid ping command received");
+            Ok(PrimaryProcessorResult::Void)
+        }
+    }
+}
@@ -1,10 +1,11 @@
 use citadel_crypt::stacked_ratchet::StackedRatchet;
 use citadel_wire::udp_traversal::linear::encrypted_config_container::HolePunchConfigContainer;
-use citadel_wire::udp_traversal::targetted_udp_socket_addr::HolePunchedUdpSocket;
+use citadel_wire::udp_traversal::repunch::UdpRepuncher;
 use netbeam::sync::RelativeNodeType;
 
-use crate::constants::HOLE_PUNCH_SYNC_TIME_MULTIPLIER;
+use crate::constants::{DEFERRED_UDP_TIMEOUT, HOLE_PUNCH_SYNC_TIME_MULTIPLIER};
 use crate::error::NetworkError;
+use crate::proto::misc::connect_timings::ConnectTimingStage;
 use crate::proto::misc::udp_internal_interface::{
     QuicUdpSocketConnector, RawUdpSocketConnector, UdpSplittableTypes,
 };
@@ -15,9 +16,12 @@ use crate::proto::peer::peer_layer::UdpMode;
 use crate::proto::state_container::{StateContainerInner, VirtualTargetType};
 
 use super::includes::*;
-use crate::proto::node_result::ConnectFail;
+use crate::proto::misc::handshake_failures::HandshakeFailureReason;
+use crate::proto::node_result::{ConnectFail, HandshakeFailed, UdpChannelCreated};
 use crate::proto::packet_processor::primary_group_packet::get_proper_hyper_ratchet;
+use crate::proto::remote::Ticket;
 use crate::proto::state_subcontainers::preconnect_state_container::UdpChannelSender;
+use crate::proto::state_subcontainers::stage_machine::{ConnectStage, PreConnectStage};
 use citadel_wire::exports::Connection;
 use citadel_wire::udp_traversal::udp_hole_puncher::EndpointHolePunchExt;
 use netbeam::sync::network_endpoint::NetworkEndpoint;
@@ -48,7 +52,8 @@ pub async fn process_preconnect(
                 log::trace!(target: "citadel", "RECV STAGE SYN PRE_CONNECT PACKET");
                 // TODO: prevent logins if semvers out of sync. For now, don't
                 let adjacent_proto_version = header.protocol_version.get();
-                if proto_version_out_of_sync(adjacent_proto_version)? {
+                let versions_out_of_sync = proto_version_out_of_sync(adjacent_proto_version)?;
+                if versions_out_of_sync {
                     log::warn!(target: "citadel", "\nLocal protocol version: {} | Adjacent protocol version: {} | Versions out of sync; program may not function\n", *crate::constants::PROTOCOL_VERSION, adjacent_proto_version);
                     // TODO: protocol translations for inter-version compatibility
                 }
@@ -58,6 +63,16 @@ pub async fn process_preconnect(
                     .session_active(header.session_cid.get());
                 let account_manager = session.account_manager.clone();
                 let header_if_err_occurs = header.clone();
+                let report_failure = |reason, message: String| {
+                    session
+                        .session_manager
+                        .report_handshake_failure(HandshakeFailed {
+                            peer_addr: session.remote_peer,
+                            cid_opt: Some(header.session_cid.get()),
+                            reason,
+                            message,
+                        })
+                };
 
                 let error = |err: NetworkError| {
                     let packet = packet_crafter::pre_connect::craft_halt(
@@ -68,20 +83,48 @@ pub async fn process_preconnect(
                 };
 
                 if session_already_active {
+                    report_failure(
+                        HandshakeFailureReason::Quota,
+                        "The session is already connected".to_string(),
+                    );
                     return error(NetworkError::InvalidRequest("Session Already Connected"));
                 }
 
+                if let Some(node_addr) =
+                    session
+                        .session_manager
+                        .session_affinity()
+                        .and_then(|affinity| {
+                            affinity.redirect(header.context_info.get(), header.session_cid.get())
+                        })
+                {
+                    log::trace!(target: "citadel", "Redirecting client {} to {node_addr}", header.session_cid.get());
+                    let packet = packet_crafter::pre_connect::craft_redirect(&header, node_addr);
+                    return Ok(PrimaryProcessorResult::ReplyToSender(packet));
+                }
+
                 if let Some(cnac) = account_manager
                     .get_client_by_cid(header.session_cid.get())
                     .await?
                 {
+                    let validation = {
+                        let cnac = cnac.clone();
+                        let session_active = session
+                            .session_manager
+                            .session_active(header.session_cid.get());
+                        session
+                            .session_manager
+                            .crypto_offload()
+                            .run(move || {
+                                validation::pre_connect::validate_syn(&cnac, packet, session_active)
+                            })
+                            .await
+                            .and_then(|validation| validation)
+                    };
+
                     let mut state_container = inner_mut_state!(session.state_container);
 
-                    match validation::pre_connect::validate_syn(
-                        &cnac,
-                        packet,
-                        &session.session_manager,
-                    ) {
+                    match validation {
                         Ok((
                             static_aux_ratchet,
                             transfer,
@@ -95,6 +138,10 @@ pub async fn process_preconnect(
                             session.adjacent_nat_type.set_once(Some(nat_type));
                             state_container.pre_connect_state.generated_ratchet =
                                 Some(new_hyper_ratchet);
+                            state_container
+                                .connect_state
+                                .timer
+                                .lap(ConnectTimingStage::FirstRatchet);
                             // since the SYN's been validated, the CNACs toolset has been updated
                             let new_session_sec_lvl = transfer.security_level;
 
@@ -104,8 +151,10 @@ pub async fn process_preconnect(
 
                             state_container.pre_connect_state.on_packet_received();
 
-                            state_container.pre_connect_state.last_stage =
-                                packet_flags::cmd::aux::do_preconnect::SYN_ACK;
+                            return_if_illegal!(state_container
+                                .pre_connect_state
+                                .stage
+                                .advance(PreConnectStage::SynAck));
                             state_container.keep_alive_timeout_ns = kat;
 
                             // here, we also send the peer's external address to itself
@@ -131,12 +180,29 @@ pub async fn process_preconnect(
 
                         Err(err) => {
                             log::error!(target: "citadel", "Invalid SYN packet received: {:?}", &err);
+                            std::mem::drop(state_container);
+                            if versions_out_of_sync {
+                                report_failure(
+                                    HandshakeFailureReason::ProtocolVersion,
+                                    format!(
+                                        "Local protocol version {} is incompatible with {adjacent_proto_version}: {}",
+                                        *crate::constants::PROTOCOL_VERSION,
+                                        err
+                                    ),
+                                );
+                            } else {
+                                report_failure(
+                                    HandshakeFailureReason::Authentication,
+                                    err.to_string(),
+                                );
+                            }
                             error(err)
                         }
                     }
                 } else {
                     let bad_cid = header.session_cid.get();
                     let error = format!("CID {bad_cid} is not registered to this node");
+                    report_failure(HandshakeFailureReason::Authentication, error.clone());
                     let packet = packet_crafter::pre_connect::craft_halt(&header, error);
                     Ok(PrimaryProcessorResult::ReplyToSender(packet))
                 }
@@ -152,8 +218,7 @@ pub async fn process_preconnect(
 
                 let (stream, new_hyper_ratchet) = {
                     let mut state_container = inner_mut_state!(session.state_container);
-                    if state_container.pre_connect_state.last_stage
-                        == packet_flags::cmd::aux::do_preconnect::SYN_ACK
+                    if state_container.pre_connect_state.stage.current() == PreConnectStage::SynAck
                     {
                         // cnac should already be loaded locally
                         let alice_constructor = return_if_none!(
@@ -173,6 +238,10 @@ pub async fn process_preconnect(
                             session.adjacent_nat_type.set_once(Some(nat_type));
                             state_container.pre_connect_state.generated_ratchet =
                                 Some(new_hyper_ratchet.clone());
+                            state_container
+                                .connect_state
+                                .timer
+                                .lap(ConnectTimingStage::FirstRatchet);
 
                             let local_node_type = session.local_node_type;
                             let timestamp = session.time_tracker.get_global_time_ns();
@@ -187,8 +256,10 @@ pub async fn process_preconnect(
                                         local_node_type,
                                         security_level,
                                     );
-                                state_container.pre_connect_state.last_stage =
-                                    packet_flags::cmd::aux::do_preconnect::SUCCESS;
+                                return_if_illegal!(state_container
+                                    .pre_connect_state
+                                    .stage
+                                    .advance(PreConnectStage::Success));
                                 return Ok(PrimaryProcessorResult::ReplyToSender(
                                     stage0_preconnect_packet,
                                 ));
@@ -244,25 +315,32 @@ pub async fn process_preconnect(
                     }
                 };
 
-                let conn = &(NetworkEndpoint::register(RelativeNodeType::Initiator, stream)
+                let conn = NetworkEndpoint::register(RelativeNodeType::Initiator, stream)
                     .await
-                    .map_err(|err| NetworkError::Generic(err.to_string()))?);
+                    .map_err(|err| NetworkError::Generic(err.to_string()))?;
                 log::trace!(target: "citadel", "Initiator created");
                 let stun_servers = session.stun_servers.clone();
-                let res = conn
-                    .begin_udp_hole_punch(generate_hole_punch_crypt_container(
+                let res = punch_raw_udp_interface(
+                    conn,
+                    generate_hole_punch_crypt_container(
                         new_hyper_ratchet.clone(),
                         SecurityLevel::Standard,
                         C2S_ENCRYPTION_ONLY,
                         stun_servers,
-                    ))
-                    .await;
+                        session.hole_punch_settings.as_ref(),
+                    ),
+                )
+                .await;
+                inner_mut_state!(session.state_container)
+                    .connect_state
+                    .timer
+                    .lap(ConnectTimingStage::UdpTraversal);
 
                 match res {
                     Ok(ret) => {
                         log::trace!(target: "citadel", "Initiator finished NAT traversal ...");
                         send_success_as_initiator(
-                            Some(get_raw_udp_interface(ret)),
+                            Some(ret),
                             &new_hyper_ratchet,
                             session,
                             security_level,
@@ -301,8 +379,7 @@ pub async fn process_preconnect(
                         "HR version not found"
                     );
 
-                    if state_container.pre_connect_state.last_stage
-                        == packet_flags::cmd::aux::do_preconnect::SYN_ACK
+                    if state_container.pre_connect_state.stage.current() == PreConnectStage::SynAck
                     {
                         if validation::pre_connect::validate_stage0(&hyper_ratchet, packet)
                             .is_some()
@@ -348,23 +425,30 @@ pub async fn process_preconnect(
                     }
                 };
 
-                let conn = &(NetworkEndpoint::register(RelativeNodeType::Receiver, stream)
+                let conn = NetworkEndpoint::register(RelativeNodeType::Receiver, stream)
                     .await
-                    .map_err(|err| NetworkError::Generic(err.to_string()))?);
+                    .map_err(|err| NetworkError::Generic(err.to_string()))?;
                 log::trace!(target: "citadel", "Receiver created");
                 let stun_servers = session.stun_servers.clone();
-                let res = conn
-                    .begin_udp_hole_punch(generate_hole_punch_crypt_container(
+                let res = punch_raw_udp_interface(
+                    conn,
+                    generate_hole_punch_crypt_container(
                         hyper_ratchet.clone(),
                         SecurityLevel::Standard,
                         C2S_ENCRYPTION_ONLY,
                         stun_servers,
-                    ))
-                    .await;
+                        session.hole_punch_settings.as_ref(),
+                    ),
+                )
+                .await;
+                inner_mut_state!(session.state_container)
+                    .connect_state
+                    .timer
+                    .lap(ConnectTimingStage::UdpTraversal);
 
                 match res {
                     Ok(ret) => handle_success_as_receiver(
-                        Some(get_raw_udp_interface(ret)),
+                        Some(ret),
                         session,
                         implicated_cid,
                         &mut inner_mut_state!(session.state_container),
@@ -375,8 +459,10 @@ pub async fn process_preconnect(
                         // We await the initiator to choose a method
                         let mut state_container = inner_mut_state!(session.state_container);
                         state_container.udp_mode = UdpMode::Disabled;
-                        state_container.pre_connect_state.last_stage =
-                            packet_flags::cmd::aux::do_preconnect::SUCCESS;
+                        return_if_illegal!(state_container
+                            .pre_connect_state
+                            .stage
+                            .advance(PreConnectStage::Success));
                         Ok(PrimaryProcessorResult::Void)
                     }
                 }
@@ -427,6 +513,10 @@ pub async fn process_preconnect(
                         if let Some(quic_conn) = inner_mut!(session.primary_stream_quic_conn).take()
                         {
                             log::trace!(target: "citadel", "[Server/QUIC-UDP] Loading ...");
+                            return_if_illegal!(state_container
+                                .pre_connect_state
+                                .stage
+                                .advance(PreConnectStage::Success));
                             let _ = handle_success_as_receiver(
                                 Some(get_quic_udp_interface(quic_conn, session.local_bind_addr)),
                                 session,
@@ -448,6 +538,7 @@ pub async fn process_preconnect(
                             ticket,
                             cid_opt: Some(cnac.get_cid()),
                             error_message: "Preconnect stage failed".to_string(),
+                            redirect: None,
                         }))?;
                         Ok(PrimaryProcessorResult::EndSession(
                             "Failure packet received",
@@ -475,9 +566,7 @@ pub async fn process_preconnect(
                     "Could not get proper HR [preconnect1]"
                 );
 
-                if state_container.pre_connect_state.last_stage
-                    == packet_flags::cmd::aux::do_preconnect::SUCCESS
-                {
+                if state_container.pre_connect_state.stage.current() == PreConnectStage::Success {
                     let (header, payload, _, _) = packet.decompose();
                     if let Some((_, _, hyper_ratchet)) =
                         validation::aead::validate(hr, &header, payload)
@@ -504,6 +593,7 @@ pub async fn process_preconnect(
                     ticket,
                     cid_opt: Some(header.session_cid.get()),
                     error_message: message,
+                    redirect: None,
                 }))?;
                 //session.needs_close_message.set(false);
                 Ok(PrimaryProcessorResult::EndSession(
@@ -511,6 +601,27 @@ pub async fn process_preconnect(
                 ))
             }
 
+            packet_flags::cmd::aux::do_preconnect::REDIRECT => {
+                let node_addr = return_if_none!(
+                    std::str::from_utf8(&payload)
+                        .ok()
+                        .and_then(|addr| addr.parse::<SocketAddr>().ok()),
+                    "Invalid redirect address"
+                );
+                let ticket = session.kernel_ticket.get();
+                session.send_to_kernel(NodeResult::ConnectFail(ConnectFail {
+                    ticket,
+                    cid_opt: Some(header.session_cid.get()),
+                    error_message: format!(
+                        "This account is held by another node of the deployment at {node_addr}"
+                    ),
+                    redirect: Some(node_addr),
+                }))?;
+                Ok(PrimaryProcessorResult::EndSession(
+                    "Preconnect redirected to another node",
+                ))
+            }
+
             _ => {
                 log::error!(target: "citadel", "Invalid auxiliary command");
                 Ok(PrimaryProcessorResult::Void)
@@ -540,7 +651,10 @@ fn begin_connect_process(
         timestamp,
         security_level,
     );
-    state_container.connect_state.last_stage = packet_flags::cmd::aux::do_connect::STAGE1;
+    return_if_illegal!(state_container
+        .connect_state
+        .stage
+        .advance(ConnectStage::Stage1));
     // we now store the pqc temporarily in the state container
     //session.post_quantum = Some(new_pqc);
     std::mem::drop(state_container);
@@ -562,6 +676,11 @@ fn send_success_as_initiator(
     implicated_cid: u64,
     state_container: &mut StateContainerInner,
 ) -> Result<PrimaryProcessorResult, NetworkError> {
+    // advanced here, since the result of the receiver's handler is discarded
+    return_if_illegal!(state_container
+        .pre_connect_state
+        .stage
+        .advance(PreConnectStage::Success));
     let _ = handle_success_as_receiver(udp_splittable, session, implicated_cid, state_container)?;
 
     let success_packet = packet_crafter::pre_connect::craft_stage_final(
@@ -582,7 +701,10 @@ fn handle_success_as_receiver(
 ) -> Result<PrimaryProcessorResult, NetworkError> {
     let tcp_loaded_alerter_rx = state_container.setup_tcp_alert_if_udp_c2s();
 
-    state_container.pre_connect_state.last_stage = packet_flags::cmd::aux::do_preconnect::SUCCESS;
+    return_if_illegal!(state_container
+        .pre_connect_state
+        .stage
+        .advance(PreConnectStage::Success));
     state_container.pre_connect_state.on_packet_received();
 
     if state_container
@@ -613,15 +735,85 @@ fn handle_success_as_receiver(
     Ok(PrimaryProcessorResult::Void)
 }
 
+/// Traverses NATs on behalf of a session that connected with [`UdpMode::Disabled`], then loads the
+/// UDP subsystem. Once loaded, the UDP channel is passed to the kernel
+pub(crate) async fn traverse_deferred_udp(
+    session: HdpSession,
+    relative_node_type: RelativeNodeType,
+    stream: ReliableOrderedCompatStream,
+    hyper_ratchet: StackedRatchet,
+    implicated_cid: u64,
+    ticket: Ticket,
+) -> Result<(), NetworkError> {
+    // if the primary stream uses QUIC, both endpoints reuse the QUIC connection instead
+    let quic_conn = inner_mut!(session.primary_stream_quic_conn).take();
+    let udp_splittable = if let Some(quic_conn) = quic_conn {
+        log::trace!(target: "citadel", "Skipping deferred NAT traversal since QUIC is enabled for this session");
+        get_quic_udp_interface(quic_conn, session.local_bind_addr)
+    } else {
+        let conn = tokio::time::timeout(
+            DEFERRED_UDP_TIMEOUT,
+            NetworkEndpoint::register(relative_node_type, stream),
+        )
+        .await
+        .map_err(|_| NetworkError::msg("The adjacent node did not begin NAT traversal in time"))?
+        .map_err(|err| NetworkError::Generic(err.to_string()))?;
+        punch_raw_udp_interface(
+            conn,
+            generate_hole_punch_crypt_container(
+                hyper_ratchet,
+                SecurityLevel::Standard,
+                C2S_ENCRYPTION_ONLY,
+                session.stun_servers.clone(),
+                session.hole_punch_settings.as_ref(),
+            ),
+        )
+        .await
+        .map_err(|err| NetworkError::Generic(err.to_string()))?
+    };
+
+    let (udp_channel_tx, udp_channel_rx) = tokio::sync::oneshot::channel();
+    {
+        let mut state_container = inner_mut_state!(session.state_container);
+        state_container.udp_mode = UdpMode::Enabled;
+        state_container.pre_connect_state.udp_channel_oneshot_tx = UdpChannelSender {
+            tx: Some(udp_channel_tx),
+            rx: None,
+        };
+    }
+
+    let peer_addr = udp_splittable.peer_addr();
+    // TCP is already loaded, hence the UDP subsystem need not wait
+    HdpSession::udp_socket_loader(
+        session.clone(),
+        VirtualTargetType::LocalGroupServer(implicated_cid),
+        udp_splittable,
+        peer_addr,
+        ticket,
+        None,
+    );
+
+    let channel = udp_channel_rx
+        .await
+        .map_err(|_| NetworkError::InternalError("The UDP subsystem failed to load"))?;
+    session.send_to_kernel(NodeResult::UdpChannelCreated(UdpChannelCreated {
+        ticket,
+        implicated_cid,
+        channel,
+    }))?;
+    Ok(())
+}
+
 pub(crate) fn generate_hole_punch_crypt_container(
     hyper_ratchet: StackedRatchet,
     security_level: SecurityLevel,
     target_cid: u64,
     stun_servers: Option<Vec<String>>,
+    hole_punch_settings: Option<&HolePunchConfigContainer>,
 ) -> HolePunchConfigContainer {
     let hyper_ratchet_cloned = hyper_ratchet.clone();
 
-    HolePunchConfigContainer::new(
+    let container = HolePunchConfigContainer::new(
         move |plaintext| {
             packet_crafter::hole_punch::generate_packet(
                 &hyper_ratchet,
@@ -638,7 +830,12 @@ pub(crate) fn generate_hole_punch_crypt_container(
             )
         },
         stun_servers,
-    )
+    );
+
+    match hole_punch_settings {
+        Some(hole_punch_settings) => container.with_settings_of(hole_punch_settings),
+        None => container,
+    }
 }
 
 /// Returns the instant in time when the sync_time happens, and the inscribable i64 thereof
@@ -670,11 +867,20 @@ fn proto_version_out_of_sync(adjacent_proto_version: u32) -> Result<bool, Networ
     }
 }
 
-fn get_raw_udp_interface(socket: HolePunchedUdpSocket) -> UdpSplittableTypes {
+/// Punches a hole alongside the adjacent node over `conn`. The endpoint is kept by a
+/// [`UdpRepuncher`], such that the UDP subsystem may punch a fresh hole once the NAT binding dies
+async fn punch_raw_udp_interface(
+    conn: NetworkEndpoint,
+    encrypted_config: HolePunchConfigContainer,
+) -> Result<UdpSplittableTypes, anyhow::Error> {
+    // registered before punching, since both nodes get this far even if only one punches a hole
+    let repuncher = UdpRepuncher::register(conn.clone(), encrypted_config.clone()).await?;
+    let mut socket = conn.begin_udp_hole_punch(encrypted_config).await?;
+    // in multi-path mode, the secondary paths are spares to fail over to once the bindings die
+    let repuncher = repuncher.with_spare_paths(std::mem::take(&mut socket.secondary_paths));
     log::trace!(target: "citadel", "Will use Raw UDP for UDP transmission");
-    UdpSplittableTypes::Raw(RawUdpSocketConnector::new(
-        socket.socket,
-        socket.addr.send_address,
+    Ok(UdpSplittableTypes::Raw(
+        RawUdpSocketConnector::hole_punched(socket, repuncher),
     ))
 }
 
@@ -135,15 +135,34 @@ pub fn process_primary_packet(
                                         0
                                     };
 
+                                if target_cid == 0 && session.is_server {
+                                    if let Some(legal_hold) = session.legal_hold.as_ref() {
+                                        legal_hold.record(
+                                            timestamp,
+                                            header.session_cid.get(),
+                                            0,
+                                            plaintext.len(),
+                                            Some(plaintext.as_ref()),
+                                        );
+                                    }
+                                }
+
                                 if !state_container.forward_data_to_ordered_channel(
                                     target_cid,
                                     header.group.get(),
+                                    ticket,
                                     plaintext,
                                 ) {
                                     log::error!(target: "citadel", "Unable to forward data to channel (peer: {})", target_cid);
                                     return Ok(PrimaryProcessorResult::Void);
                                 }
 
+                                if let Err(err) =
+                                    state_container.announce_receive_window(target_cid)
+                                {
+                                    log::warn!(target: "citadel", "Unable to announce the receive window to {target_cid}: {err:?}");
+                                }
+
                                 let group_header_ack =
                                     packet_crafter::group::craft_group_header_ack(
                                         &hyper_ratchet,
@@ -272,6 +291,12 @@ pub fn process_primary_packet(
 
                                     let resp_target_cid = get_resp_target_cid_from_header(&header);
                                     let peer_cid = header.session_cid.get();
+                                    if fast_msg {
+                                        state_container.on_channel_close_acknowledged(
+                                            resp_target_cid,
+                                            header.context_info.get().into(),
+                                        );
+                                    }
                                     //let mut state_container = session.state_container.borrow_mut();
                                     let group_id = header.group.get();
 
@@ -302,6 +327,7 @@ pub fn process_primary_packet(
 
                                     // TODO: make the below function return a result, not bools
                                     if state_container.on_group_header_ack_received(
+                                        &session.state_container,
                                         secrecy_mode,
                                         peer_cid,
                                         target_cid,
@@ -338,11 +364,9 @@ pub fn process_primary_packet(
                                         //std::mem::drop(state_container);
 
                                         // if a transfer occurred, we will get polled once we get an TRUNCATE_ACK. No need to double poll
-                                        if secrecy_mode == SecrecyMode::Perfect {
-                                            log::trace!(target: "citadel", "Polling next in pgp");
-                                            let _ = state_container
-                                                .poll_next_enqueued(resp_target_cid)?;
-                                        }
+                                        log::trace!(target: "citadel", "Polling next in pgp");
+                                        let _ =
+                                            state_container.poll_next_enqueued(resp_target_cid)?;
 
                                         Ok(PrimaryProcessorResult::Void)
                                     } else if udp_mode == UdpMode::Disabled {
@@ -428,6 +452,23 @@ pub fn process_primary_packet(
                             }
                         }
 
+                        packet_flags::cmd::aux::group::WINDOW_UPDATE => {
+                            log::trace!(target: "citadel", "RECV WINDOW UPDATE");
+                            let update = return_if_none!(
+                                validation::group::validate_window_update(&payload),
+                                "Error validating WINDOW_UPDATE"
+                            );
+                            // window updates are addressed the same way as the messages they grant room for
+                            let target_cid = proxy_cid_info
+                                .map(|(original_implicated_cid, _)| original_implicated_cid)
+                                .unwrap_or(0);
+                            if !state_container.on_window_update_received(target_cid, update) {
+                                log::warn!(target: "citadel", "Received a window update for an inactive channel (peer: {target_cid})");
+                            }
+
+                            Ok(PrimaryProcessorResult::Void)
+                        }
+
                         _ => {
                             log::trace!(target: "citadel", "Primary port GROUP packet has an invalid auxiliary command. Dropping");
                             Ok(PrimaryProcessorResult::Void)
@@ -460,9 +501,10 @@ pub(super) fn get_proper_hyper_ratchet(
             .get(&original_implicated_cid)
         {
             //log::trace!(target: "citadel", "[Peer StackedRatchet] v{} from vconn w/ {}", header_drill_vers, original_implicated_cid);
-            vconn
-                .borrow_endpoint_hyper_ratchet(Some(header_drill_vers))
-                .cloned()
+            get_ratchet_within_drift(
+                &vconn.endpoint_container.as_ref()?.endpoint_crypto,
+                header_drill_vers,
+            )
         } else {
             log::warn!(target: "citadel", "Unable to find vconn for {}. Unable to process primary group packet", original_implicated_cid);
             None
@@ -472,12 +514,26 @@ pub(super) fn get_proper_hyper_ratchet(
         if state_container.state.load(Ordering::Relaxed) != SessionState::Connected {
             state_container.pre_connect_state.generated_ratchet.clone()
         } else {
-            state_container
-                .c2s_channel_container
-                .as_ref()?
-                .peer_session_crypto
-                .get_hyper_ratchet(Some(header_drill_vers))
-                .cloned()
+            get_ratchet_within_drift(
+                &state_container
+                    .c2s_channel_container
+                    .as_ref()?
+                    .peer_session_crypto,
+                header_drill_vers,
+            )
+        }
+    }
+}
+
+fn get_ratchet_within_drift(
+    crypt: &PeerSessionCrypto,
+    header_drill_vers: u32,
+) -> Option<StackedRatchet> {
+    match crypt.get_hyper_ratchet_within_drift(header_drill_vers) {
+        Ok(ratchet) => Some(ratchet.clone()),
+        Err(err) => {
+            log::warn!(target: "citadel", "Dropping packet: {}", err.into_string());
+            None
         }
     }
 }
@@ -554,10 +610,12 @@ impl ToolsetUpdate<'_> {
     }
 
     /// This should only be called after an update
-    pub(crate) fn post_stage1_alice_or_bob(&mut self) {
+    /// Returns the latest usable version once the new ratchet is usable
+    pub(crate) fn post_stage1_alice_or_bob(&mut self) -> u32 {
         match self {
             ToolsetUpdate::E2E { crypt, .. } => {
                 crypt.post_alice_stage1_or_post_stage1_bob();
+                crypt.latest_usable_version
             }
 
             ToolsetUpdate::Fcm {
@@ -565,6 +623,7 @@ impl ToolsetUpdate<'_> {
                 ..
             } => {
                 fcm_crypt_container.post_alice_stage1_or_post_stage1_bob();
+                fcm_crypt_container.latest_usable_version
             }
         }
     }
@@ -700,7 +759,15 @@ pub(crate) fn attempt_kem_as_alice_finish(
 
                 // Since alice has updated, and bob has the latest ratchet committed (but not yet able to use it), we can begin sending packets from the latest version to bob
                 // in order for bob to begin using the latest version, he needs to receive the TRUNCATE_STATUS packet
-                toolset_update_method.post_stage1_alice_or_bob();
+                let version = toolset_update_method.post_stage1_alice_or_bob();
+                let epoch_cid = if target_cid != C2S_ENCRYPTION_ONLY {
+                    peer_cid
+                } else {
+                    C2S_ENCRYPTION_ONLY
+                };
+                state_container
+                    .security_epochs
+                    .on_rekey_completed(epoch_cid, version);
 
                 match secrecy_mode {
                     SecrecyMode::Perfect | SecrecyMode::BestEffort => {
@@ -768,7 +835,14 @@ pub(crate) fn attempt_kem_as_bob(
             }
         };
 
-        update_toolset_as_bob(update, transfer, hr)
+        let status = update_toolset_as_bob(update, transfer, hr)?;
+        if status.has_some() {
+            state_container
+                .security_epochs
+                .on_rekey_started(resp_target_cid);
+        }
+
+        Some(status)
     } else {
         Some(KemTransferStatus::Empty)
     }
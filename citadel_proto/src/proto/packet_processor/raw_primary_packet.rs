@@ -1,5 +1,8 @@
 use bytes::BytesMut;
 
+use crate::proto::misc::abuse_detection::AbuseVerdict;
+use crate::proto::node_result::AbuseDetected;
+use crate::proto::packet_processor::challenge_packet::{gate_handshake, ChallengeGate};
 use crate::proto::packet_processor::peer::peer_cmd_packet;
 
 use super::includes::*;
@@ -27,9 +30,19 @@ pub async fn process_raw_packet(
     packet: BytesMut,
 ) -> Result<PrimaryProcessorResult, NetworkError> {
     //return_if_none!(header_obfuscator.on_packet_received(&mut packet));
+    let packet = match gate_handshake(session, packet, remote_peer).await? {
+        ChallengeGate::Proceed(packet) => packet,
+        ChallengeGate::Consumed(result) => return Ok(result),
+    };
     let packet = HdpPacket::new_recv(packet, remote_peer, local_primary_port);
-    log::trace!(target: "citadel", "RECV Raw packet: {:?}", &packet.parse().unwrap().0);
-    let (header, _payload) = return_if_none!(packet.parse(), "Unable to parse packet");
+    let (header, _payload) = match packet.try_parse() {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            log::warn!(target: "citadel", "Unable to parse packet: {err}");
+            return Ok(PrimaryProcessorResult::Void);
+        }
+    };
+    log::trace!(target: "citadel", "RECV Raw packet: {:?}", &*header);
 
     let target_cid = header.target_cid.get();
     let mut endpoint_cid_info = None;
@@ -38,6 +51,40 @@ pub async fn process_raw_packet(
     let cmd_aux = header.cmd_aux;
     let header_drill_vers = header.drill_version.get();
 
+    if let (Some(implicated_cid), Some(abuse_monitor)) =
+        (this_implicated_cid, session.abuse_monitor.as_ref())
+    {
+        let is_message = cmd_primary == packet_flags::cmd::primary::GROUP_PACKET
+            && cmd_aux == packet_flags::cmd::aux::group::GROUP_HEADER;
+        let (verdict, report) =
+            abuse_monitor.on_packet(implicated_cid, target_cid, packet.get_length(), is_message);
+
+        if let Some((sample, action)) = report {
+            log::warn!(target: "citadel", "Abuse detector acted against {implicated_cid}: {action:?} ({sample:?})");
+            if let Err(err) = session.send_to_kernel(NodeResult::AbuseDetected(AbuseDetected {
+                implicated_cid,
+                remote_addr: remote_peer,
+                sample,
+                action,
+            })) {
+                log::error!(target: "citadel", "Unable to report abuse to the kernel: {err:?}");
+            }
+        }
+
+        match verdict {
+            AbuseVerdict::Accept => {}
+            AbuseVerdict::Drop => {
+                log::trace!(target: "citadel", "Dropping message from {implicated_cid}: throttled by the abuse detector");
+                return Ok(PrimaryProcessorResult::Void);
+            }
+            AbuseVerdict::Disconnect => {
+                return Ok(PrimaryProcessorResult::EndSession(
+                    "Disconnected by the abuse detector",
+                ));
+            }
+        }
+    }
+
     match check_proxy(
         this_implicated_cid,
         header.cmd_primary,
@@ -115,6 +162,17 @@ pub async fn process_raw_packet(
                 endpoint_cid_info,
             ),
 
+            packet_flags::cmd::primary::CAPABILITIES => {
+                super::capabilities_packet::process_capabilities(session, packet, header_drill_vers)
+            }
+
+            packet_flags::cmd::primary::PING => super::ping_packet::process_ping(
+                session,
+                packet,
+                header_drill_vers,
+                endpoint_cid_info,
+            ),
+
             _ => {
                 warn!(target: "citadel", "The primary port received an invalid packet command. Dropping");
                 Ok(PrimaryProcessorResult::Void)
@@ -159,6 +217,35 @@ pub(crate) fn check_proxy(
                 log::trace!(target: "citadel", "Proxying {}:{} packet from {} to {}", cmd_primary, cmd_aux, this_implicated_cid, target_cid);
                 // Proxy will only occur if there exists a virtual connection, in which case, we get the TcpSender (since these are primary packets)
 
+                if let Some(relay_ledger) = session.relay_ledger.as_ref() {
+                    if !relay_ledger.admit(this_implicated_cid, packet.get_length()) {
+                        log::trace!(target: "citadel", "Dropping relayed packet from {} to {}: bandwidth limit exceeded", this_implicated_cid, target_cid);
+                        return None;
+                    }
+                }
+
+                if cmd_primary == packet_flags::cmd::primary::FILE
+                    && cmd_aux == packet_flags::cmd::aux::file::FILE_HEADER
+                    && !session.account_permissions.get().can_file_transfer
+                {
+                    log::warn!(target: "citadel", "Dropping file transfer from {} to {}: denied by the account permissions", this_implicated_cid, target_cid);
+                    return None;
+                }
+
+                if let Some(legal_hold) = session.legal_hold.as_ref() {
+                    if cmd_primary == packet_flags::cmd::primary::GROUP_PACKET
+                        && cmd_aux == packet_flags::cmd::aux::group::GROUP_HEADER
+                    {
+                        legal_hold.record(
+                            session.time_tracker.get_global_time_ns(),
+                            this_implicated_cid,
+                            target_cid,
+                            packet.get_length(),
+                            None,
+                        );
+                    }
+                }
+
                 let mut state_container = inner_mut_state!(session.state_container);
                 state_container.meta_expiry_state.on_event_confirmation();
 
@@ -1,9 +1,14 @@
 use super::includes::*;
 use crate::error::NetworkError;
+use crate::proto::misc::account_hook::{AccountEvent, AccountEventKind};
+use crate::proto::misc::connect_timings::ConnectTimingStage;
+use crate::proto::misc::session_affinity;
 use crate::proto::node_result::{RegisterFailure, RegisterOkay};
+use crate::proto::peer::peer_layer::PeerListDelta;
+use crate::proto::state_subcontainers::stage_machine::RegisterStage;
 use citadel_crypt::prelude::ConstructorOpts;
 use citadel_crypt::stacked_ratchet::constructor::{
-    BobToAliceTransfer, BobToAliceTransferType, StackedRatchetConstructor,
+    BobToAliceTransferType, StackedRatchetConstructor,
 };
 use std::sync::atomic::Ordering;
 
@@ -38,9 +43,7 @@ pub async fn process_register(
                 let task = {
                     let mut state_container = inner_mut_state!(session.state_container);
                     // This node is Bob (receives a stage 0 packet from Alice). The payload should have Alice's public key
-                    if state_container.register_state.last_stage
-                        == packet_flags::cmd::aux::do_register::STAGE0
-                    {
+                    if state_container.register_state.stage.current() == RegisterStage::Stage0 {
                         let algorithm = header.algorithm;
 
                         match validation::do_register::validate_stage0(&payload) {
@@ -60,22 +63,45 @@ pub async fn process_register(
                                     return Ok(PrimaryProcessorResult::ReplyToSender(err));
                                 }
 
+                                if !passwordless
+                                    && session.account_manager.get_misc_settings().relay.is_some()
+                                {
+                                    // relays hold no accounts
+                                    let err = packet_crafter::do_register::craft_failure(algorithm, timestamp, "The target node only relays traffic, and does not accept registrations", header.session_cid.get());
+                                    return Ok(PrimaryProcessorResult::ReplyToSender(err));
+                                }
+
                                 std::mem::drop(state_container);
 
                                 async move {
                                     let cid = header.session_cid.get();
-                                    let bob_constructor = StackedRatchetConstructor::new_bob(
-                                        cid,
-                                        0,
-                                        ConstructorOpts::new_vec_init(
-                                            Some(transfer.params),
-                                            (transfer.security_level.value() + 1) as usize,
-                                        ),
-                                        transfer,
-                                    )
-                                    .ok_or(NetworkError::InvalidRequest("Bad bob transfer"))?;
-                                    let transfer = return_if_none!(
-                                        bob_constructor.stage0_bob(),
+                                    let key_exchange = session
+                                        .session_manager
+                                        .crypto_offload()
+                                        .run(move || {
+                                            let bob_constructor =
+                                                StackedRatchetConstructor::new_bob(
+                                                    cid,
+                                                    0,
+                                                    ConstructorOpts::new_vec_init(
+                                                        Some(transfer.params),
+                                                        (transfer.security_level.value() + 1)
+                                                            as usize,
+                                                    ),
+                                                    transfer,
+                                                )
+                                                .ok_or(NetworkError::InvalidRequest(
+                                                    "Bad bob transfer",
+                                                ))?;
+                                            Ok::<_, NetworkError>(
+                                                bob_constructor.stage0_bob().and_then(|transfer| {
+                                                    Some((transfer, bob_constructor.finish()?))
+                                                }),
+                                            )
+                                        })
+                                        .await??;
+                                    let (transfer, new_hyper_ratchet) = return_if_none!(
+                                        key_exchange,
                                         "Unable to advance past stage0-bob"
                                     );
 
@@ -83,18 +109,18 @@ pub async fn process_register(
                                         algorithm,
                                         timestamp,
                                         transfer,
+                                        session.account_manager.argon_policy(),
                                         header.session_cid.get(),
                                     );
 
                                     let mut state_container =
                                         inner_mut_state!(session.state_container);
                                     state_container.register_state.created_hyper_ratchet =
-                                        Some(return_if_none!(
-                                            bob_constructor.finish(),
-                                            "Unable to finish bob constructor"
-                                        ));
-                                    state_container.register_state.last_stage =
-                                        packet_flags::cmd::aux::do_register::STAGE1;
+                                        Some(new_hyper_ratchet);
+                                    return_if_illegal!(state_container
+                                        .register_state
+                                        .stage
+                                        .advance(RegisterStage::Stage1));
                                     state_container.register_state.on_register_packet_received();
 
                                     Ok(PrimaryProcessorResult::ReplyToSender(stage1_packet))
@@ -103,7 +129,7 @@ pub async fn process_register(
 
                             _ => {
                                 log::error!(target: "citadel", "Unable to validate STAGE0_REGISTER packet");
-                                state_container.register_state.on_fail();
+                                return_if_illegal!(state_container.register_state.on_fail());
                                 state_container.register_state.on_register_packet_received();
                                 std::mem::drop(state_container);
 
@@ -126,10 +152,45 @@ pub async fn process_register(
             packet_flags::cmd::aux::do_register::STAGE1 => {
                 log::trace!(target: "citadel", "STAGE 1 REGISTER PACKET");
                 // Node is Alice. This packet will contain Bob's ciphertext; Alice will now be able to create the shared private key
-                let mut state_container = inner_mut_state!(session.state_container);
-                if state_container.register_state.last_stage
-                    == packet_flags::cmd::aux::do_register::STAGE0
+                let (stage1, proposed_credentials) = {
+                    let state_container = inner_state!(session.state_container);
+                    if state_container.register_state.stage.current() != RegisterStage::Stage0 {
+                        warn!(target: "citadel", "Inconsistency between the session's stage and the packet's state. Dropping");
+                        return Ok(PrimaryProcessorResult::Void);
+                    }
+
+                    let stage1 = return_if_none!(
+                        validation::do_register::validate_stage1(&payload[..]),
+                        "Unable to deserialize STAGE1_REGISTER packet"
+                    );
+                    let proposed_credentials = return_if_none!(
+                        state_container.connect_state.proposed_credentials.clone(),
+                        "Unable to load proposed credentials"
+                    );
+                    (stage1, proposed_credentials)
+                };
+
+                // the password is hashed once more if the server requires more costly parameters
+                let proposed_credentials = match proposed_credentials
+                    .adopt_argon_policy(&stage1.argon_policy)
+                    .await
                 {
+                    Ok(proposed_credentials) => proposed_credentials,
+                    Err(err) => {
+                        log::error!(target: "citadel", "Unable to hash the password under the server's policy: {err:?}");
+                        session.send_to_kernel(NodeResult::RegisterFailure(RegisterFailure {
+                            ticket: session.kernel_ticket.get(),
+                            error_message: err.into_string(),
+                        }))?;
+                        session.shutdown();
+                        return Ok(PrimaryProcessorResult::EndSession(
+                            "Registration subroutine ended (Status: FAIL)",
+                        ));
+                    }
+                };
+
+                let mut state_container = inner_mut_state!(session.state_container);
+                if state_container.register_state.stage.current() == RegisterStage::Stage0 {
                     let algorithm = header.algorithm;
 
                     // pqc is stored in the register state container for now
@@ -137,10 +198,7 @@ pub async fn process_register(
                     if let Some(mut alice_constructor) =
                         state_container.register_state.constructor.take()
                     {
-                        let transfer = return_if_none!(
-                            BobToAliceTransfer::deserialize_from(&payload[..]),
-                            "Unable to deserialize BobToAliceTransfer"
-                        );
+                        let transfer = stage1.transfer;
                         let security_level = transfer.security_level;
                         alice_constructor
                             .stage1_alice(BobToAliceTransferType::Default(transfer))
@@ -151,24 +209,24 @@ pub async fn process_register(
                         );
                         let timestamp = session.time_tracker.get_global_time_ns();
 
-                        let proposed_credentials = return_if_none!(
-                            state_container.connect_state.proposed_credentials.as_ref(),
-                            "Unable to load proposed credentials"
-                        );
-
                         let stage2_packet = packet_crafter::do_register::craft_stage2(
                             &new_hyper_ratchet,
                             algorithm,
                             timestamp,
-                            proposed_credentials,
+                            &proposed_credentials,
                             security_level,
                         );
+                        // the account is stored alongside the settings the password was hashed under
+                        state_container.connect_state.proposed_credentials =
+                            Some(proposed_credentials);
                         //let mut state_container = inner_mut!(session.state_container);
 
                         state_container.register_state.created_hyper_ratchet =
                             Some(new_hyper_ratchet);
-                        state_container.register_state.last_stage =
-                            packet_flags::cmd::aux::do_register::STAGE2;
+                        return_if_illegal!(state_container
+                            .register_state
+                            .stage
+                            .advance(RegisterStage::Stage2));
                         state_container.register_state.on_register_packet_received();
 
                         Ok(PrimaryProcessorResult::ReplyToSender(stage2_packet))
@@ -188,9 +246,7 @@ pub async fn process_register(
 
                 let task = {
                     let state_container = inner_state!(session.state_container);
-                    if state_container.register_state.last_stage
-                        == packet_flags::cmd::aux::do_register::STAGE1
-                    {
+                    if state_container.register_state.stage.current() == RegisterStage::Stage1 {
                         let algorithm = header.algorithm;
                         let hyper_ratchet = return_if_none!(
                             state_container.register_state.created_hyper_ratchet.clone(),
@@ -209,6 +265,21 @@ pub async fn process_register(
                             let account_manager = session.account_manager.clone();
                             std::mem::drop(state_container);
 
+                            if let Err(err) = creds.check_argon_policy(
+                                stage2_packet.argon_parameters.as_ref(),
+                                &account_manager.argon_policy(),
+                            ) {
+                                let err = err.into_string();
+                                log::warn!(target: "citadel", "Refusing registration: {err}");
+                                let packet = packet_crafter::do_register::craft_failure(
+                                    algorithm,
+                                    timestamp,
+                                    err,
+                                    header.session_cid.get(),
+                                );
+                                return Ok(PrimaryProcessorResult::ReplyToSender(packet));
+                            }
+
                             // we must now create the CNAC
                             async move {
                                 match account_manager
@@ -221,14 +292,33 @@ pub async fn process_register(
                                 {
                                     Ok(peer_cnac) => {
                                         log::trace!(target: "citadel", "Server successfully created a CNAC during the DO_REGISTER process! CID: {}", peer_cnac.get_cid());
+                                        session.session_manager.notify_peer_list_subscribers(
+                                            PeerListDelta::Registered {
+                                                cid: peer_cnac.get_cid(),
+                                                username: peer_cnac.get_username(),
+                                            },
+                                        );
+                                        session.session_manager.notify_account_hook(
+                                            AccountEventKind::Registered,
+                                            AccountEvent {
+                                                account: peer_cnac.get_metadata(),
+                                                remote_addr,
+                                            },
+                                        );
                                         let success_message =
                                             session.create_register_success_message();
+                                        let affinity_token = session
+                                            .session_manager
+                                            .session_affinity()
+                                            .map(|affinity| affinity.issue(peer_cnac.get_cid()))
+                                            .unwrap_or_default();
                                         let packet = packet_crafter::do_register::craft_success(
                                             &hyper_ratchet,
                                             algorithm,
                                             timestamp,
                                             success_message,
                                             security_level,
+                                            affinity_token,
                                         );
                                         Ok(PrimaryProcessorResult::ReplyToSender(packet))
                                     }
@@ -267,9 +357,7 @@ pub async fn process_register(
 
                 let task = {
                     let state_container = inner_state!(session.state_container);
-                    if state_container.register_state.last_stage
-                        == packet_flags::cmd::aux::do_register::STAGE2
-                    {
+                    if state_container.register_state.stage.current() == RegisterStage::Stage2 {
                         let hyper_ratchet = return_if_none!(
                             state_container.register_state.created_hyper_ratchet.clone(),
                             "Unable to load created hyper ratchet"
@@ -294,9 +382,12 @@ pub async fn process_register(
                                 state_container.register_state.passwordless,
                                 "Passwordless unset (reg)"
                             );
+                            // like passwordless registrations, OIDC registrations are implied by a connect request
+                            let connect_after_register = passwordless || credentials.is_oidc();
 
                             std::mem::drop(state_container);
 
+                            let affinity_token = header.context_info.get();
                             let reg_ticket = session.kernel_ticket.clone();
                             let account_manager = session.account_manager.clone();
                             let kernel_tx = session.kernel_tx.clone();
@@ -311,8 +402,25 @@ pub async fn process_register(
                                     .await
                                 {
                                     Ok(new_cnac) => {
-                                        if passwordless {
-                                            HdpSession::begin_connect(&session, &new_cnac)?;
+                                        if affinity_token != 0 {
+                                            session_affinity::store_token(
+                                                account_manager.get_persistence_handler(),
+                                                new_cnac.get_cid(),
+                                                affinity_token,
+                                            )
+                                            .await?;
+                                        }
+
+                                        if connect_after_register {
+                                            inner_mut_state!(session.state_container)
+                                                .connect_state
+                                                .timer
+                                                .lap(ConnectTimingStage::Register);
+                                            HdpSession::begin_connect(
+                                                &session,
+                                                &new_cnac,
+                                                affinity_token,
+                                            )?;
                                             inner_mut_state!(session.state_container).cnac =
                                                 Some(new_cnac);
                                             // begin_connect will handle the connection process from here on out
@@ -366,8 +474,9 @@ pub async fn process_register(
                 // A failure can be sent at any stage greater than the zeroth
                 if inner_state!(session.state_container)
                     .register_state
-                    .last_stage
-                    > packet_flags::cmd::aux::do_register::STAGE0
+                    .stage
+                    .current()
+                    != RegisterStage::Stage0
                 {
                     if let Some(error_message) =
                         validation::do_register::validate_failure(&header, &payload[..])
@@ -1,7 +1,6 @@
 use super::includes::*;
 use crate::error::NetworkError;
 use crate::prelude::ReKeyReturnType;
-use crate::proto::node::SecrecyMode;
 use crate::proto::packet_crafter::peer_cmd::C2S_ENCRYPTION_ONLY;
 use crate::proto::packet_processor::header_to_response_vconn_type;
 use crate::proto::packet_processor::primary_group_packet::{
@@ -162,7 +161,7 @@ pub fn process_rekey(
             );
             let resp_target_cid = get_resp_target_cid_from_header(&header);
 
-            let (mut method, secrecy_mode) = if resp_target_cid != C2S_ENCRYPTION_ONLY {
+            let mut method = if resp_target_cid != C2S_ENCRYPTION_ONLY {
                 let endpoint_container = return_if_none!(return_if_none!(state_container
                     .active_virtual_connections
                     .get_mut(&resp_target_cid))
@@ -170,23 +169,15 @@ pub fn process_rekey(
                 .as_mut());
                 let crypt = &mut endpoint_container.endpoint_crypto;
                 let local_cid = header.target_cid.get();
-                (
-                    ToolsetUpdate::E2E { crypt, local_cid },
-                    endpoint_container.default_security_settings.secrecy_mode,
-                )
+                ToolsetUpdate::E2E { crypt, local_cid }
             } else {
-                let secrecy_mode = state_container
-                    .session_security_settings
-                    .as_ref()
-                    .map(|r| r.secrecy_mode)
-                    .unwrap();
                 let crypt = &mut state_container
                     .c2s_channel_container
                     .as_mut()
                     .unwrap()
                     .peer_session_crypto;
                 let local_cid = header.session_cid.get();
-                (ToolsetUpdate::E2E { crypt, local_cid }, secrecy_mode)
+                ToolsetUpdate::E2E { crypt, local_cid }
             };
 
             // We optionally deregister at this endpoint to prevent any further packets with this version from being sent
@@ -202,9 +193,12 @@ pub fn process_rekey(
             }
 
             // We update the internal latest version usable
-            method.post_stage1_alice_or_bob();
+            let version = method.post_stage1_alice_or_bob();
 
             let lock_set_by_alice = return_if_none!(method.unlock(false)).1;
+            state_container
+                .security_epochs
+                .on_rekey_completed(resp_target_cid, version);
 
             // if lock set by bob, do poll
             let do_poll = lock_set_by_alice.map(|r| !r).unwrap_or(false);
@@ -224,7 +218,8 @@ pub fn process_rekey(
 
             //std::mem::drop(state_container);
 
-            if secrecy_mode == SecrecyMode::Perfect && do_poll {
+            // Perfect secrecy messages may be enqueued regardless of the channel's default mode
+            if do_poll {
                 let _ = state_container.poll_next_enqueued(resp_target_cid)?;
             }
 
@@ -241,7 +236,7 @@ pub fn process_rekey(
 
             let resp_target_cid = get_resp_target_cid_from_header(&header);
 
-            let (mut method, secrecy_mode) = if resp_target_cid != C2S_ENCRYPTION_ONLY {
+            let mut method = if resp_target_cid != C2S_ENCRYPTION_ONLY {
                 let endpoint_container = return_if_none!(return_if_none!(state_container
                     .active_virtual_connections
                     .get_mut(&resp_target_cid))
@@ -249,32 +244,22 @@ pub fn process_rekey(
                 .as_mut());
                 let crypt = &mut endpoint_container.endpoint_crypto;
                 let local_cid = header.target_cid.get();
-                (
-                    ToolsetUpdate::E2E { crypt, local_cid },
-                    endpoint_container.default_security_settings.secrecy_mode,
-                )
+                ToolsetUpdate::E2E { crypt, local_cid }
             } else {
-                let secrecy_mode = state_container
-                    .session_security_settings
-                    .as_ref()
-                    .map(|r| r.secrecy_mode)
-                    .unwrap();
                 let crypt = &mut state_container
                     .c2s_channel_container
                     .as_mut()
                     .unwrap()
                     .peer_session_crypto;
                 let local_cid = header.session_cid.get();
-                (ToolsetUpdate::E2E { crypt, local_cid }, secrecy_mode)
+                ToolsetUpdate::E2E { crypt, local_cid }
             };
 
             let _ = return_if_none!(method.unlock(true)); // unconditional unlock
 
             // now, we can poll any packets
             //std::mem::drop(state_container);
-            if secrecy_mode == SecrecyMode::Perfect {
-                let _ = state_container.poll_next_enqueued(resp_target_cid)?;
-            }
+            let _ = state_container.poll_next_enqueued(resp_target_cid)?;
 
             state_container.ratchet_update_state.on_complete(
                 header_to_response_vconn_type(&header),
@@ -1,7 +1,10 @@
 use super::includes::*;
 use crate::error::NetworkError;
 use crate::proto::endpoint_crypto_accessor::EndpointCryptoAccessor;
+use crate::proto::misc::dual_cell::DualCell;
 use crate::proto::packet_processor::primary_group_packet::get_resp_target_cid_from_header;
+use crate::proto::packet_processor::raw_primary_packet::{check_proxy, ReceivePortType};
+use citadel_pqcrypto::key_domain::KeyDomain;
 
 /// This will handle an inbound group packet
 #[cfg_attr(feature = "localhost-testing", tracing::instrument(target = "citadel", skip_all, ret, err, fields(is_server = _session.is_server, src = packet.parse().unwrap().0.session_cid.get(), target = packet.parse().unwrap().0.target_cid.get())))]
@@ -18,7 +21,12 @@ pub fn process_udp_packet(
         move |hr, state_container| -> PrimaryProcessorResult {
             let header = header.as_ref();
             if let Some((header, payload)) =
-                super::super::validation::aead::validate_custom(hr, &header, payload)
+                super::super::validation::aead::validate_custom_in_domain(
+                    KeyDomain::Unordered,
+                    hr,
+                    &header,
+                    payload,
+                )
             {
                 let peer_cid = get_resp_target_cid_from_header(&header);
                 let payload = SecBuffer::from(payload.as_ref());
@@ -45,3 +53,158 @@ pub fn process_udp_packet(
         }
     }
 }
+
+/// Migrates the address UDP packets are sent to onto the source of an authenticated keep-alive.
+/// Once the adjacent node rebinds a failed socket, its first packet is a keep-alive from the new
+/// address. The anti-replay window of the unordered key domain prevents a captured keep-alive from
+/// being replayed from another address.
+///
+/// Returns whether the keep-alive arrived from the prior address, or None if it failed to
+/// authenticate
+pub fn process_udp_keep_alive(
+    packet: HdpPacket,
+    hr_version: u32,
+    accessor: &EndpointCryptoAccessor,
+    peer_addr: &DualCell<SocketAddr>,
+) -> Option<bool> {
+    let (header, payload, remote_peer, _) = packet.decompose();
+    if remote_peer == peer_addr.get() {
+        return Some(true);
+    }
+
+    let authenticated = accessor.borrow_hr(Some(hr_version), move |hr, _| {
+        let header = header.as_ref();
+        super::super::validation::aead::validate_custom_in_domain(
+            KeyDomain::Unordered,
+            hr,
+            &header,
+            payload,
+        )
+        .is_some()
+    });
+
+    if let Ok(true) = authenticated {
+        log::info!(target: "citadel", "UDP peer migrated from {} to {}", peer_addr.get(), remote_peer);
+        peer_addr.set(remote_peer);
+        Some(false)
+    } else {
+        log::warn!(target: "citadel", "Discarding unauthenticated UDP keep-alive from {}", remote_peer);
+        None
+    }
+}
+
+/// Returns whether the adjacent node received the answered keep-alive probe from the prior address
+pub fn process_udp_keep_alive_ack(
+    packet: HdpPacket,
+    hr_version: u32,
+    accessor: &EndpointCryptoAccessor,
+) -> Option<bool> {
+    let (header, payload, _, _) = packet.decompose();
+    let preserved = accessor.borrow_hr(Some(hr_version), move |hr, _| {
+        let header = header.as_ref();
+        super::super::validation::aead::validate_custom_in_domain(
+            KeyDomain::Unordered,
+            hr,
+            &header,
+            payload,
+        )
+        .and_then(|(_, payload)| payload.first().map(|preserved| *preserved != 0))
+    });
+
+    match preserved {
+        Ok(Some(preserved)) => Some(preserved),
+        _ => {
+            log::warn!(target: "citadel", "Unable to validate UDP keep-alive acknowledgement");
+            None
+        }
+    }
+}
+
+/// This will handle a group payload packet of a file transfer that was striped across the UDP stream.
+/// Once the outer UDP layer is removed, the inner packet is processed as if it arrived through the
+/// primary stream
+pub fn process_udp_stripe_packet(
+    session: &HdpSession,
+    packet: HdpPacket,
+    hr_version: u32,
+    accessor: &EndpointCryptoAccessor,
+) -> Result<PrimaryProcessorResult, NetworkError> {
+    let (header, payload, remote_peer, local_port) = packet.decompose();
+
+    let inner = accessor.borrow_hr(Some(hr_version), move |hr, _| {
+        let header = header.as_ref();
+        super::super::validation::aead::validate_custom_in_domain(
+            KeyDomain::Unordered,
+            hr,
+            &header,
+            payload,
+        )
+        .map(|(_, payload)| payload)
+    });
+
+    let inner = match inner {
+        Ok(Some(inner)) => inner,
+        Ok(None) => {
+            log::warn!(target: "citadel", "Unable to validate UDP stripe packet");
+            return Ok(PrimaryProcessorResult::Void);
+        }
+        Err(err) => {
+            log::warn!(target: "citadel", "Unable to borrow HR: {:?}", err);
+            return Ok(PrimaryProcessorResult::Void);
+        }
+    };
+
+    let packet = HdpPacket::new_recv(inner, remote_peer, local_port);
+    let (header, _) = return_if_none!(packet.parse(), "Unable to parse UDP stripe packet");
+    if header.cmd_primary != packet_flags::cmd::primary::GROUP_PACKET
+        || header.cmd_aux != packet_flags::cmd::aux::group::GROUP_PAYLOAD
+    {
+        log::warn!(target: "citadel", "Only group payloads may be striped across the UDP stream");
+        return Ok(PrimaryProcessorResult::Void);
+    }
+
+    let cmd_aux = header.cmd_aux;
+    let mut endpoint_cid_info = None;
+    let packet = match check_proxy(
+        session.implicated_cid.get(),
+        header.cmd_primary,
+        header.cmd_aux,
+        header.session_cid.get(),
+        header.target_cid.get(),
+        session,
+        &mut endpoint_cid_info,
+        ReceivePortType::UnorderedUnreliable,
+        packet,
+    ) {
+        Some(packet) => packet,
+        None => return Ok(PrimaryProcessorResult::Void),
+    };
+
+    match super::primary_group_packet::process_primary_packet(
+        session,
+        cmd_aux,
+        packet,
+        endpoint_cid_info,
+    )? {
+        // wave acks travel back through the reliable stream that carries the rest of the group
+        PrimaryProcessorResult::ReplyToSender(ack) => {
+            let to_primary_stream = if let Some((peer_cid, _)) = endpoint_cid_info {
+                inner_state!(session.state_container)
+                    .get_preferred_stream(peer_cid)
+                    .clone()
+            } else {
+                session
+                    .to_primary_stream
+                    .clone()
+                    .ok_or(NetworkError::InternalError("Primary stream not loaded"))?
+            };
+
+            to_primary_stream
+                .unbounded_send(ack)
+                .map_err(|err| NetworkError::Generic(err.to_string()))?;
+            Ok(PrimaryProcessorResult::Void)
+        }
+
+        res => Ok(res),
+    }
+}
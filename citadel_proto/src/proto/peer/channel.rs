@@ -1,9 +1,14 @@
 use crate::error::NetworkError;
+use crate::proto::misc::flow_control::{FlowControl, ReceiveWindow, SendWindow};
+use crate::proto::misc::security_info::SecurityInfo;
+use crate::proto::misc::stats_history::{ChannelStatsSnapshot, StatsHistory};
+use crate::proto::node::SecrecyMode;
 use crate::proto::node_request::{NodeRequest, PeerCommand};
 use crate::proto::outbound_sender::{OutboundUdpSender, Sender, UnboundedReceiver};
 use crate::proto::packet_crafter::SecureProtocolPacket;
 use crate::proto::packet_processor::raw_primary_packet::ReceivePortType;
 use crate::proto::peer::peer_layer::{PeerConnectionType, PeerSignal};
+use crate::proto::peer::security_epoch::{RekeyEvent, SecurityEpoch};
 use crate::proto::remote::{NodeRemote, Ticket};
 use crate::proto::session::SessionRequest;
 use crate::proto::state_container::VirtualConnectionType;
@@ -12,16 +17,149 @@ use citadel_crypt::prelude::SecBuffer;
 use citadel_user::re_exports::__private::Formatter;
 use futures::task::{Context, Poll};
 use futures::Stream;
-use std::fmt::Debug;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::fmt::{Debug, Display};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::macros::support::Pin;
+use tokio::sync::mpsc::error::{SendTimeoutError, TrySendError};
+use tokio::time::Instant;
+
+// Set in the ID of each named channel, distinguishing named channels from the default channel,
+// whose ID is the ticket of the request that created the connection
+const NAMED_CHANNEL_FLAG: u128 = 1 << 127;
+
+/// Returns the ID of the named channel `name`. Both endpoints derive the same ID from the name
+pub(crate) fn named_channel_id(name: &str) -> Ticket {
+    // FNV-1a, since the ID must be stable across builds and platforms
+    let hash = name.bytes().fold(0xcbf29ce484222325u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    Ticket(NAMED_CHANNEL_FLAG | hash as u128)
+}
+
+pub(crate) fn is_named_channel(channel_id: Ticket) -> bool {
+    channel_id.0 & NAMED_CHANNEL_FLAG != 0
+}
+
+// Set in the ticket of the message that closes a channel. The message has no payload
+const CHANNEL_CLOSE_FLAG: u128 = 1 << 126;
+
+/// Returns the ticket of the message that closes the channel `channel_id`
+pub(crate) fn close_channel_id(channel_id: Ticket) -> Ticket {
+    Ticket(channel_id.0 | CHANNEL_CLOSE_FLAG)
+}
+
+pub(crate) fn is_channel_close(ticket: Ticket) -> bool {
+    ticket.0 & CHANNEL_CLOSE_FLAG != 0
+}
+
+/// Returns the ID of the channel closed by the message with the given ticket
+pub(crate) fn closed_channel_id(ticket: Ticket) -> Ticket {
+    Ticket(ticket.0 & !CHANNEL_CLOSE_FLAG)
+}
+
+/// Returned by the non-blocking and time-limited send functions of channel senders, allowing
+/// applications to shed load instead of waiting under backpressure. The message is not sent
+/// unless `Ok` is returned
+#[derive(Debug)]
+pub enum ChannelSendError {
+    /// The channel has no room for the message without waiting
+    Full,
+    /// No room became available for the message before the timeout elapsed
+    Timeout,
+    /// The message expired before the session accepted it, and was dropped
+    Expired,
+    /// The channel is closed, or the message could not be sent
+    Network(NetworkError),
+}
+
+impl Display for ChannelSendError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelSendError::Full => write!(f, "The channel is full"),
+            ChannelSendError::Timeout => write!(f, "Timed out waiting for room in the channel"),
+            ChannelSendError::Expired => write!(f, "The message expired before it was sent"),
+            ChannelSendError::Network(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for ChannelSendError {}
+
+impl From<NetworkError> for ChannelSendError {
+    fn from(err: NetworkError) -> Self {
+        ChannelSendError::Network(err)
+    }
+}
+
+impl From<ChannelSendError> for NetworkError {
+    fn from(err: ChannelSendError) -> Self {
+        match err {
+            ChannelSendError::Network(err) => err,
+            err => NetworkError::Generic(err.to_string()),
+        }
+    }
+}
+
+impl<T> From<TrySendError<T>> for ChannelSendError {
+    fn from(err: TrySendError<T>) -> Self {
+        match err {
+            TrySendError::Full(_) => ChannelSendError::Full,
+            TrySendError::Closed(_) => {
+                ChannelSendError::Network(NetworkError::msg("The session is closed"))
+            }
+        }
+    }
+}
+
+impl<T> From<SendTimeoutError<T>> for ChannelSendError {
+    fn from(err: SendTimeoutError<T>) -> Self {
+        match err {
+            SendTimeoutError::Timeout(_) => ChannelSendError::Timeout,
+            SendTimeoutError::Closed(_) => {
+                ChannelSendError::Network(NetworkError::msg("The session is closed"))
+            }
+        }
+    }
+}
+
+/// A snapshot of the backpressure on the send half of a channel, allowing applications to adapt,
+/// e.g., by dropping frames, before sends begin to wait
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct ChannelMetrics {
+    /// The number of requests accepted by the channel that the session has yet to process
+    pub queue_depth: usize,
+    /// The number of bytes sent that the receiver's application has yet to read
+    pub bytes_in_flight: u64,
+    /// How long the most recent successful send waited before the session accepted the message.
+    /// `None` until a message is sent
+    pub last_send_latency: Option<Duration>,
+}
+
+// The value of the last send latency before any message is sent
+const NO_SEND_LATENCY: u64 = u64::MAX;
 
 // 1 peer channel per virtual connection. This enables high-level communication between the [HdpServer] and the API-layer.
+// Named channels are multiplexed over the same virtual connection
 #[derive(Debug)]
 pub struct PeerChannel {
     send_half: PeerChannelSendHalf,
     recv_half: PeerChannelRecvHalf,
+    cross_connect: Option<CrossConnect>,
+}
+
+/// Created when two peers attempt to connect to one another at the same time. The server collapses
+/// both requests into a single channel: the request of the lower CID stands, and the request of the
+/// higher CID is absorbed, such that both peers agree on the outcome irrespective of timing
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct CrossConnect {
+    /// The CID whose request stood, and which thus initiated the key exchange
+    pub initiator_cid: u64,
+    /// The ticket of the request that stood, and the ID of the channel
+    pub channel_ticket: Ticket,
+    /// The ticket of the absorbed request
+    pub absorbed_ticket: Ticket,
 }
 
 impl PeerChannel {
@@ -35,17 +173,27 @@ impl PeerChannel {
         is_alive: Arc<AtomicBool>,
         receiver: UnboundedReceiver<SecBuffer>,
         to_outbound_stream: Sender<SessionRequest>,
+        security_epoch: SecurityEpoch,
+        security_info: SecurityInfo,
+        flow_control: &FlowControl,
     ) -> Self {
         let implicated_cid = vconn_type.get_implicated_cid();
         let recv_type = ReceivePortType::OrderedReliable;
 
         let send_half = PeerChannelSendHalf {
-            to_outbound_stream,
+            to_outbound_stream: to_outbound_stream.clone(),
             target_cid,
             vconn_type,
             implicated_cid,
             channel_id,
             security_level,
+            security_epoch,
+            security_info: Arc::new(security_info),
+            send_window: flow_control.send.clone(),
+            name: None,
+            is_closed: Arc::new(AtomicBool::new(false)),
+            last_send_latency_ns: Arc::new(AtomicU64::new(NO_SEND_LATENCY)),
+            stats_history: Arc::new(StatsHistory::default()),
         };
 
         let recv_half = PeerChannelRecvHalf {
@@ -56,14 +204,41 @@ impl PeerChannel {
             channel_id,
             is_alive,
             recv_type,
+            receive_window: Some((flow_control.receive.clone(), to_outbound_stream)),
+            is_named: false,
         };
 
         PeerChannel {
             send_half,
             recv_half,
+            cross_connect: None,
         }
     }
 
+    pub(crate) fn with_cross_connect(mut self, cross_connect: Option<CrossConnect>) -> Self {
+        self.cross_connect = cross_connect;
+        self
+    }
+
+    /// Marks this channel as the named channel `name`. Unlike the default channel, dropping a
+    /// named channel does not disconnect the virtual connection
+    pub(crate) fn with_name(mut self, name: String) -> Self {
+        self.recv_half.is_named = true;
+        self.send_half.name = Some(name);
+        self
+    }
+
+    /// Returns the name of the channel, or `None` for the default channel of the connection
+    pub fn name(&self) -> Option<&str> {
+        self.send_half.name()
+    }
+
+    /// Returns `Some` if this channel resulted from both peers attempting to connect to one another
+    /// at the same time
+    pub fn cross_connect(&self) -> Option<CrossConnect> {
+        self.cross_connect
+    }
+
     /// Gets the CID of the endpoint
     pub fn get_peer_cid(&self) -> u64 {
         self.send_half.target_cid
@@ -79,6 +254,42 @@ impl PeerChannel {
         self.send_half.vconn_type.try_as_peer_connection()
     }
 
+    /// Returns the ratchet version currently used to encrypt outbound messages
+    pub fn current_security_epoch(&self) -> u32 {
+        self.send_half.current_security_epoch()
+    }
+
+    /// Subscribes to the re-key events of this channel
+    pub fn rekey_events(&self) -> tokio::sync::broadcast::Receiver<RekeyEvent> {
+        self.send_half.rekey_events()
+    }
+
+    /// Returns the effective security of the connection. See [`SecurityInfo`]
+    pub fn security_info(&self) -> SecurityInfo {
+        self.send_half.security_info()
+    }
+
+    /// Returns a snapshot of the backpressure on the channel. See [`ChannelMetrics`]
+    pub fn metrics(&self) -> ChannelMetrics {
+        self.send_half.metrics()
+    }
+
+    /// Returns the metrics of the channel over the last five minutes. See [`ChannelStatsSnapshot`]
+    pub fn stats_history(&self) -> Vec<ChannelStatsSnapshot> {
+        self.send_half.stats_history()
+    }
+
+    /// Returns a handle to the send half without consuming the channel
+    pub fn sender(&self) -> PeerChannelSendHalf {
+        self.send_half.clone()
+    }
+
+    /// Closes the channel once the adjacent endpoint received every message sent prior. See
+    /// [`PeerChannelSendHalf::close`]
+    pub async fn close(self) -> Result<(), NetworkError> {
+        self.send_half.close().await
+    }
+
     /// In order to use the [PeerChannel] properly, split must be called in order to receive
     /// an asynchronous interface. The SendHalf implements Sink, whereas the RecvHalf implements
     /// Stream
@@ -96,6 +307,14 @@ pub struct PeerChannelSendHalf {
     vconn_type: VirtualConnectionType,
     channel_id: Ticket,
     security_level: SecurityLevel,
+    security_epoch: SecurityEpoch,
+    security_info: Arc<SecurityInfo>,
+    send_window: Arc<SendWindow>,
+    name: Option<String>,
+    // shared by each clone of the send half, such that none may send once the channel closes
+    is_closed: Arc<AtomicBool>,
+    last_send_latency_ns: Arc<AtomicU64>,
+    stats_history: Arc<StatsHistory>,
 }
 
 impl Debug for PeerChannelSendHalf {
@@ -109,19 +328,202 @@ impl PeerChannelSendHalf {
         self.security_level = security_level;
     }
 
-    /// Sends a message through the channel
+    /// Sends a message through the channel. Once the receiver's application falls behind by
+    /// more than the window the receiver advertised, waits until the receiver catches up
     pub async fn send_message(&self, message: SecureProtocolPacket) -> Result<(), NetworkError> {
+        self.send(message, None).await
+    }
+
+    /// Sends a message through the channel using `secrecy_mode` instead of the channel's default.
+    ///
+    /// A [`SecrecyMode::Perfect`] message waits for any in-progress re-key to finish, whereas a
+    /// [`SecrecyMode::BestEffort`] message is sent immediately, even if Perfect messages are still
+    /// waiting. As such, messages of different secrecy modes are not ordered relative to each other
+    pub async fn send_message_with_secrecy_mode(
+        &self,
+        message: SecureProtocolPacket,
+        secrecy_mode: SecrecyMode,
+    ) -> Result<(), NetworkError> {
+        self.send(message, Some(secrecy_mode)).await
+    }
+
+    /// Sends a message through the channel without waiting. If the receiver's application has
+    /// fallen behind, or the session is backlogged, returns [`ChannelSendError::Full`]
+    pub fn try_send(&self, message: SecureProtocolPacket) -> Result<(), ChannelSendError> {
+        self.ensure_open()?;
+        let len = message.message_len() as u64;
+        if !self.send_window.try_reserve(len) {
+            return Err(ChannelSendError::Full);
+        }
+
+        self.to_outbound_stream
+            .try_send(self.request(message, None, None))
+            .map_err(|err| {
+                self.send_window.unreserve(len);
+                err.into()
+            })?;
+        self.on_sent(Duration::ZERO);
+        Ok(())
+    }
+
+    /// Sends a message through the channel, waiting at most `timeout` for room. If no room
+    /// became available in time, returns [`ChannelSendError::Timeout`]
+    pub async fn send_timeout(
+        &self,
+        message: SecureProtocolPacket,
+        timeout: Duration,
+    ) -> Result<(), ChannelSendError> {
+        self.ensure_open()?;
+        let start = tokio::time::Instant::now();
+        let deadline = start + timeout;
+        let len = message.message_len() as u64;
+        tokio::time::timeout_at(deadline, self.send_window.reserve(len))
+            .await
+            .map_err(|_| ChannelSendError::Timeout)?;
+
+        let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+        self.to_outbound_stream
+            .send_timeout(self.request(message, None, None), remaining)
+            .await
+            .map_err(|err| {
+                self.send_window.unreserve(len);
+                ChannelSendError::from(err)
+            })?;
+        self.on_sent(start.elapsed());
+        Ok(())
+    }
+
+    /// Sends a message that is only worth delivering until `ttl` elapses, e.g., a real-time
+    /// update superseded by the next one. A message still waiting for room in the channel, or
+    /// still queued inside the session behind a re-key, once `ttl` elapses is dropped instead of
+    /// being delivered late. Returns [`ChannelSendError::Expired`] if the message expired before
+    /// the session accepted it; a message expiring afterwards is dropped silently
+    pub async fn send_with_expiry(
+        &self,
+        message: SecureProtocolPacket,
+        ttl: Duration,
+    ) -> Result<(), ChannelSendError> {
+        self.ensure_open()?;
+        let start = tokio::time::Instant::now();
+        let expires_at = start + ttl;
+        let len = message.message_len() as u64;
+        tokio::time::timeout_at(expires_at, self.send_window.reserve(len))
+            .await
+            .map_err(|_| ChannelSendError::Expired)?;
+
+        let remaining = expires_at.saturating_duration_since(tokio::time::Instant::now());
+        self.to_outbound_stream
+            .send_timeout(self.request(message, None, Some(expires_at)), remaining)
+            .await
+            .map_err(|err| {
+                self.send_window.unreserve(len);
+                match err {
+                    SendTimeoutError::Timeout(_) => ChannelSendError::Expired,
+                    err => ChannelSendError::from(err),
+                }
+            })?;
+        self.on_sent(start.elapsed());
+        Ok(())
+    }
+
+    async fn send(
+        &self,
+        message: SecureProtocolPacket,
+        secrecy_mode: Option<SecrecyMode>,
+    ) -> Result<(), NetworkError> {
+        self.ensure_open()?;
+        let start = tokio::time::Instant::now();
+        self.send_window.reserve(message.message_len() as u64).await;
+        self.to_outbound_stream
+            .send(self.request(message, secrecy_mode, None))
+            .await
+            .map_err(|err| NetworkError::Generic(err.to_string()))?;
+        self.on_sent(start.elapsed());
+        Ok(())
+    }
+
+    fn on_sent(&self, latency: Duration) {
+        let latency_ns = (latency.as_nanos() as u64).min(NO_SEND_LATENCY - 1);
+        self.last_send_latency_ns
+            .store(latency_ns, Ordering::Relaxed);
+        self.stats_history.record(self.metrics());
+    }
+
+    /// Returns a snapshot of the backpressure on the channel
+    pub fn metrics(&self) -> ChannelMetrics {
+        let last_send_latency = match self.last_send_latency_ns.load(Ordering::Relaxed) {
+            NO_SEND_LATENCY => None,
+            latency_ns => Some(Duration::from_nanos(latency_ns)),
+        };
+
+        ChannelMetrics {
+            queue_depth: self.to_outbound_stream.max_capacity()
+                - self.to_outbound_stream.capacity(),
+            bytes_in_flight: self.bytes_in_flight(),
+            last_send_latency,
+        }
+    }
+
+    /// Returns the metrics of the channel over the last five minutes, oldest first, with at most
+    /// one snapshot per second. Seconds during which nothing was sent have no snapshot
+    pub fn stats_history(&self) -> Vec<ChannelStatsSnapshot> {
+        self.stats_history.snapshots()
+    }
+
+    fn ensure_open(&self) -> Result<(), NetworkError> {
+        if self.is_closed.load(Ordering::SeqCst) {
+            Err(NetworkError::msg("The channel is closed"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn request(
+        &self,
+        message: SecureProtocolPacket,
+        secrecy_mode: Option<SecrecyMode>,
+        expires_at: Option<Instant>,
+    ) -> SessionRequest {
         let (ticket, packet, target, security_level) = self.get_args(message);
-        let request = SessionRequest::SendMessage {
+        SessionRequest::SendMessage {
             ticket,
             packet,
             target,
             security_level,
+            secrecy_mode,
+            expires_at,
+        }
+    }
+
+    /// Closes the channel gracefully. Messages sent prior are flushed, after which the adjacent
+    /// endpoint is told to close the channel. Once the adjacent endpoint's stream yields every
+    /// message sent prior, it ends. Returns once the adjacent endpoint acknowledges the close.
+    /// Afterwards, no clone of this send half may send through the channel
+    pub async fn close(&self) -> Result<(), NetworkError> {
+        if self.is_closed.swap(true, Ordering::SeqCst) {
+            return Err(NetworkError::msg("The channel is already closed"));
+        }
+
+        let (on_closed, closed) = tokio::sync::oneshot::channel();
+        let request = SessionRequest::CloseChannel {
+            ticket: close_channel_id(self.channel_id),
+            target: self.vconn_type,
+            security_level: self.security_level,
+            on_closed,
         };
         self.to_outbound_stream
             .send(request)
             .await
-            .map_err(|err| NetworkError::Generic(err.to_string()))
+            .map_err(|err| NetworkError::Generic(err.to_string()))?;
+        closed.await.map_err(|_| {
+            NetworkError::msg("The connection ended before the channel close was acknowledged")
+        })
+    }
+
+    /// Returns the number of bytes sent that the receiver's application has yet to read. Always
+    /// zero if the receiver does not advertise a window
+    pub fn bytes_in_flight(&self) -> u64 {
+        self.send_window.in_flight()
     }
 
     /// used to identify this channel in the network
@@ -129,6 +531,61 @@ impl PeerChannelSendHalf {
         self.channel_id
     }
 
+    /// Returns the name of the channel, or `None` for the default channel of the connection
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Creates the named channel `name`, multiplexed over the virtual connection of this channel
+    pub(crate) fn new_named_channel(
+        &self,
+        name: String,
+        server_remote: NodeRemote,
+        is_alive: Arc<AtomicBool>,
+        receiver: UnboundedReceiver<SecBuffer>,
+        flow_control: &FlowControl,
+    ) -> PeerChannel {
+        PeerChannel::new(
+            server_remote,
+            self.target_cid,
+            self.vconn_type,
+            named_channel_id(&name),
+            self.security_level,
+            is_alive,
+            receiver,
+            self.to_outbound_stream.clone(),
+            self.security_epoch.clone(),
+            (*self.security_info).clone(),
+            flow_control,
+        )
+        .with_name(name)
+    }
+
+    /// The security level used to encrypt outbound messages
+    pub fn security_level(&self) -> SecurityLevel {
+        self.security_level
+    }
+
+    /// Returns the effective security of the connection. See [`SecurityInfo`]
+    pub fn security_info(&self) -> SecurityInfo {
+        let mut security_info = (*self.security_info).clone();
+        security_info.security_level = self.security_level;
+        security_info.ratchet_version = self.current_security_epoch();
+        security_info
+    }
+
+    /// Returns the ratchet version currently used to encrypt outbound messages. Applications may
+    /// bind state to this epoch, since it only changes once a re-key completes
+    pub fn current_security_epoch(&self) -> u32 {
+        self.security_epoch.current()
+    }
+
+    /// Subscribes to the re-key events of this channel. Only events occurring after the
+    /// subscription are received
+    pub fn rekey_events(&self) -> tokio::sync::broadcast::Receiver<RekeyEvent> {
+        self.security_epoch.subscribe()
+    }
+
     #[inline]
     fn get_args(
         &self,
@@ -163,6 +620,9 @@ pub struct PeerChannelRecvHalf {
     is_alive: Arc<AtomicBool>,
     server_remote: NodeRemote,
     recv_type: ReceivePortType,
+    /// `None` for unordered channels, which are not flow controlled
+    receive_window: Option<(Arc<ReceiveWindow>, Sender<SessionRequest>)>,
+    is_named: bool,
 }
 
 impl Debug for PeerChannelRecvHalf {
@@ -181,7 +641,10 @@ impl Stream for PeerChannelRecvHalf {
             Poll::Ready(None)
         } else {
             match futures::ready!(Pin::new(&mut self.receiver).poll_recv(cx)) {
-                Some(data) => Poll::Ready(Some(data)),
+                Some(data) => {
+                    self.on_consumed(data.len());
+                    Poll::Ready(Some(data))
+                }
                 _ => {
                     log::trace!(target: "citadel", "[PeerChannelRecvHalf] ending");
                     Poll::Ready(None)
@@ -191,8 +654,29 @@ impl Stream for PeerChannelRecvHalf {
     }
 }
 
+impl PeerChannelRecvHalf {
+    /// Grants the sender more room each time the application reads a portion of the window
+    fn on_consumed(&self, len: usize) {
+        if let Some((receive_window, to_outbound_stream)) = self.receive_window.as_ref() {
+            if let Some(update) = receive_window.on_consumed(len as u64) {
+                let request = SessionRequest::AdvertiseWindow {
+                    target: self.vconn_type,
+                    update,
+                };
+                if to_outbound_stream.try_send(request).is_err() {
+                    receive_window.on_advertisement_failed(update);
+                }
+            }
+        }
+    }
+}
+
 impl Drop for PeerChannelRecvHalf {
     fn drop(&mut self) {
+        if self.is_named {
+            return;
+        }
+
         if let VirtualConnectionType::LocalGroupPeer(local_cid, peer_cid) = self.vconn_type {
             log::trace!(target: "citadel", "[PeerChannelRecvHalf] Dropping {:?} type. Will maybe set is_alive to false if this is a tcp p2p connection", self.recv_type);
 
@@ -247,6 +731,8 @@ impl UdpChannel {
                 is_alive,
                 server_remote,
                 recv_type: ReceivePortType::UnorderedUnreliable,
+                receive_window: None,
+                is_named: false,
             },
         }
     }
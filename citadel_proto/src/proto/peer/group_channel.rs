@@ -2,6 +2,7 @@ use crate::error::NetworkError;
 use crate::prelude::{MessageGroupKey, SecBuffer};
 use crate::proto::outbound_sender::{Sender, UnboundedReceiver};
 use crate::proto::packet_processor::peer::group_broadcast::GroupBroadcast;
+use crate::proto::peer::channel::ChannelSendError;
 use crate::proto::remote::{NodeRemote, Ticket};
 use crate::proto::session::SessionRequest;
 use citadel_user::re_exports::__private::Formatter;
@@ -10,6 +11,7 @@ use std::fmt::Debug;
 use std::ops::Deref;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Duration;
 use tokio_stream::StreamExt;
 
 #[derive(Debug)]
@@ -71,8 +73,20 @@ impl GroupChannel {
 
 #[derive(Debug)]
 pub enum GroupBroadcastPayload {
-    Message { payload: SecBuffer, sender: u64 },
-    Event { payload: GroupBroadcast },
+    /// A message relayed by the server, stamped with the epoch of the group at the time of relay
+    Message {
+        payload: SecBuffer,
+        sender: u64,
+        epoch: u64,
+    },
+    /// The epoch of the group rotated because a member was removed. Members removed prior to
+    /// the rotation do not receive messages of the new epoch
+    EpochRotated {
+        epoch: u64,
+    },
+    Event {
+        payload: GroupBroadcast,
+    },
 }
 
 pub struct GroupChannelSendHalf {
@@ -101,10 +115,39 @@ impl GroupChannelSendHalf {
             self.implicated_cid,
             self.key,
             message,
+            0,
         ))
         .await
     }
 
+    /// Broadcasts a message to the group without waiting. If the session is backlogged, returns
+    /// [`ChannelSendError::Full`]
+    pub fn try_send_message(&self, message: SecBuffer) -> Result<(), ChannelSendError> {
+        Ok(self.tx.try_send(SessionRequest::Group {
+            ticket: self.ticket,
+            broadcast: GroupBroadcast::Message(self.implicated_cid, self.key, message, 0),
+        })?)
+    }
+
+    /// Broadcasts a message to the group, waiting at most `timeout` for the session to accept it.
+    /// If the session remained backlogged, returns [`ChannelSendError::Timeout`]
+    pub async fn send_message_timeout(
+        &self,
+        message: SecBuffer,
+        timeout: Duration,
+    ) -> Result<(), ChannelSendError> {
+        Ok(self
+            .tx
+            .send_timeout(
+                SessionRequest::Group {
+                    ticket: self.ticket,
+                    broadcast: GroupBroadcast::Message(self.implicated_cid, self.key, message, 0),
+                },
+                timeout,
+            )
+            .await?)
+    }
+
     /// Kicks a peer from the group. User must be owner
     pub async fn kick(&self, peer: u64) -> Result<(), NetworkError> {
         self.kick_all(vec![peer]).await
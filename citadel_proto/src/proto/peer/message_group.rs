@@ -21,11 +21,15 @@ use std::fmt::Formatter;
 /// [MessageGroup]s should be seen as short-lived messaging frames. They stay alive as long as the axis of consent
 /// keeps the group alive or disconnects from the HyperLAN Server. When P_0 leaves, users will still have local messages
 /// of the chat, but won't receive anymore chats from the group
+#[derive(Serialize, Deserialize, Clone)]
 pub struct MessageGroup {
     // peer cid, entry (entry will contain metadata in the future)
     pub(crate) concurrent_peers: HashMap<u64, MessageGroupPeer>,
     pub(crate) pending_peers: HashMap<u64, MessageGroupPeer>,
     pub(crate) options: MessageGroupOptions,
+    // incremented each time a member is removed, such that messages sent afterwards are
+    // distinguishable from those the departed members could have received
+    pub(crate) epoch: u64,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -54,6 +58,7 @@ pub enum GroupType {
 }
 
 /// TODO: Attributed data (e.g., permissions)
+#[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct MessageGroupPeer {
     #[allow(dead_code)]
     pub peer_cid: u64,
@@ -11,4 +11,10 @@ pub mod message_group;
 
 pub mod p2p_conn_handler;
 
+pub mod security_epoch;
+
+pub mod snapshot;
+
+pub mod posting_store;
+
 pub(crate) mod hole_punch_compat_sink_stream;
@@ -11,7 +11,7 @@ use crate::proto::misc::udp_internal_interface::{QuicUdpSocketConnector, UdpSpli
 use crate::proto::node::HdpServer;
 use crate::proto::node_result::NodeResult;
 use crate::proto::outbound_sender::OutboundPrimaryStreamSender;
-use crate::proto::outbound_sender::{unbounded, OutboundPrimaryStreamReceiver, UnboundedSender};
+use crate::proto::outbound_sender::{unbounded, KernelEventSender, OutboundPrimaryStreamReceiver};
 use crate::proto::packet_processor::includes::{Duration, Instant, SocketAddr};
 use crate::proto::peer::peer_crypt::PeerNatInfo;
 use crate::proto::peer::peer_layer::PeerConnectionType;
@@ -81,6 +81,8 @@ async fn setup_listener_non_initiator(
         ServerUnderlyingProtocol::new_quic_self_signed(),
         None,
         None,
+        None,
+        None,
         local_bind_addr,
     )?;
     p2p_conn_handler(
@@ -154,7 +156,7 @@ fn handle_p2p_stream(
     mut p2p_stream: GenericNetworkStream,
     implicated_cid: DualCell<Option<u64>>,
     session: HdpSession,
-    kernel_tx: UnboundedSender<NodeResult>,
+    kernel_tx: KernelEventSender,
     from_listener: bool,
     v_conn: VirtualConnectionType,
     hole_punched_addr: TargettedSocketAddr,
@@ -239,7 +241,7 @@ pub struct P2PInboundHandle {
     pub local_bind_port: u16,
     // this has to be the CID of the local session, not the peer's CID
     pub implicated_cid: DualCell<Option<u64>>,
-    pub kernel_tx: UnboundedSender<NodeResult>,
+    pub kernel_tx: KernelEventSender,
     pub to_primary_stream: OutboundPrimaryStreamSender,
 }
 
@@ -248,7 +250,7 @@ impl P2PInboundHandle {
         remote_peer: SocketAddr,
         local_bind_port: u16,
         implicated_cid: DualCell<Option<u64>>,
-        kernel_tx: UnboundedSender<NodeResult>,
+        kernel_tx: KernelEventSender,
         to_primary_stream: OutboundPrimaryStreamSender,
     ) -> Self {
         Self {
@@ -277,7 +279,7 @@ pub(crate) async fn attempt_simultaneous_hole_punch(
     ref session: HdpSession,
     peer_nat_info: PeerNatInfo,
     implicated_cid: DualCell<Option<u64>>,
-    ref kernel_tx: UnboundedSender<NodeResult>,
+    ref kernel_tx: KernelEventSender,
     channel_signal: NodeResult,
     sync_time: Instant,
     ref app: NetworkEndpoint,
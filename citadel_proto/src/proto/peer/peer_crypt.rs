@@ -1,6 +1,7 @@
 use crate::proto::misc::session_security_settings::SessionSecuritySettings;
 use crate::proto::node::TlsDomain;
 use crate::proto::peer::peer_layer::UdpMode;
+use crate::proto::remote::Ticket;
 use citadel_wire::nat_identification::NatType;
 use serde::{Deserialize, Serialize};
 use std::net::SocketAddr;
@@ -9,8 +10,9 @@ use std::net::SocketAddr;
 pub enum KeyExchangeProcess {
     // alice sends public key
     Stage0(Vec<u8>, SessionSecuritySettings, UdpMode),
-    // Bob sends ciphertext, addr
-    Stage1(Vec<u8>, Option<PeerNatInfo>),
+    // Bob sends ciphertext, addr, and the ticket of its own connect request if the request was
+    // absorbed by a cross-connect
+    Stage1(Vec<u8>, Option<PeerNatInfo>, Option<Ticket>),
     // Alice sends a sync time over. Server takes care of external addr
     Stage2(i64, Option<PeerNatInfo>),
     // The hole-punch failed
@@ -37,7 +39,9 @@ impl PeerNatInfo {
         // TODO: This assumes same IP, Port as visible from server. For EDM's w/delta,
         // we need to *ensure* the dualstack udp hole puncher can already handle deltas
         // properly
-        let needs_turn = !self.peer_nat.stun_compatible(local_nat_type);
+        // peers sharing a LAN connect through their internal addrs, even if behind the same NAT
+        let needs_turn = !self.peer_nat.stun_compatible(local_nat_type)
+            && !self.peer_nat.shares_lan_with(local_nat_type);
         (needs_turn, predicted_addr)
     }
 }
@@ -1,14 +1,18 @@
+use crate::constants::SESSION_MAP_SHARDS;
 use crate::error::NetworkError;
 use crate::macros::SyncContextRequirements;
 use crate::proto::misc::session_security_settings::SessionSecuritySettings;
+use crate::proto::misc::sharded_map::ShardedMap;
 use crate::proto::packet_processor::peer::group_broadcast::GroupBroadcast;
 use crate::proto::peer::message_group::{
     GroupType, MessageGroup, MessageGroupKey, MessageGroupOptions, MessageGroupPeer,
 };
 use crate::proto::peer::peer_crypt::KeyExchangeProcess;
+use crate::proto::peer::posting_store;
+use crate::proto::peer::snapshot::{ClientSnapshot, PendingConsent};
 use crate::proto::remote::Ticket;
 use crate::proto::state_container::VirtualConnectionType;
-use citadel_user::backend::utils::VirtualObjectMetadata;
+use citadel_user::backend::utils::{SharedObjectNotification, VirtualObjectMetadata};
 use citadel_user::backend::PersistenceHandler;
 use citadel_user::serialization::SyncIO;
 use futures::task::AtomicWaker;
@@ -33,7 +37,12 @@ impl<T: FnOnce(PeerSignal) + SyncContextRequirements> PeerLayerTimeoutFunction f
 pub struct HyperNodePeerLayerInner {
     // When a signal is routed to the target destination, the server needs to keep track of the state while awaiting
     pub(crate) persistence_handler: PersistenceHandler,
-    pub(crate) message_groups: HashMap<u64, HashMap<u128, MessageGroup>>,
+    // sharded, such that group operations only need a read lock on the peer layer. The observed
+    // postings remain behind the write lock, since detecting simultaneous requests requires a
+    // consistent view of the postings of both clients
+    pub(crate) message_groups: ShardedMap<HashMap<u128, MessageGroup>>,
+    // consents restored from a snapshot, reinstated once the requesting client reconnects
+    restored_consents: HashMap<u64, Vec<PendingConsent>>,
     waker: Arc<AtomicWaker>,
     inner: Arc<citadel_io::RwLock<SharedInner>>,
 }
@@ -58,6 +67,7 @@ pub struct HyperNodePeerLayer {
 pub struct HyperNodePeerLayerExecutor {
     inner: Arc<citadel_io::RwLock<SharedInner>>,
     waker: Arc<AtomicWaker>,
+    persistence_handler: PersistenceHandler,
 }
 
 /// We don't use an "on_success" here because it would be structurally redundant. On success, the target node should
@@ -89,7 +99,8 @@ impl HyperNodePeerLayer {
             waker: waker.clone(),
             inner: Arc::new(citadel_io::RwLock::new(Default::default())),
             persistence_handler,
-            message_groups: HashMap::new(),
+            message_groups: ShardedMap::new(SESSION_MAP_SHARDS),
+            restored_consents: HashMap::new(),
         };
         let inner = std::sync::Arc::new(tokio::sync::RwLock::new(inner));
 
@@ -97,9 +108,11 @@ impl HyperNodePeerLayer {
     }
 
     pub async fn create_executor(&self) -> HyperNodePeerLayerExecutor {
+        let this = self.inner.read().await;
         HyperNodePeerLayerExecutor {
             waker: self.waker.clone(),
-            inner: self.inner.read().await.inner.clone(),
+            inner: this.inner.clone(),
+            persistence_handler: this.persistence_handler.clone(),
         }
     }
 
@@ -109,10 +122,14 @@ impl HyperNodePeerLayer {
         let pers = {
             let mut this_orig = self.inner.write().await;
 
-            this_orig.message_groups.entry(cid).or_insert_with(|| {
-                log::trace!(target: "citadel", "Adding message group hashmap for {}", cid);
-                HashMap::new()
-            });
+            this_orig
+                .message_groups
+                .write_shard(&cid)
+                .entry(cid)
+                .or_insert_with(|| {
+                    log::trace!(target: "citadel", "Adding message group hashmap for {}", cid);
+                    HashMap::new()
+                });
 
             let mut this = this_orig.inner.write();
 
@@ -165,8 +182,9 @@ impl HyperNodePeerLayer {
         initial_peers: &Vec<u64>,
         options: MessageGroupOptions,
     ) -> Option<MessageGroupKey> {
-        let mut this = self.inner.write().await;
-        let map = this.message_groups.get_mut(&implicated_cid)?;
+        let this = self.inner.read().await;
+        let mut shard = this.message_groups.write_shard(&implicated_cid);
+        let map = shard.get_mut(&implicated_cid)?;
         let mgid = options.id;
         if map.len() <= u8::MAX as usize {
             if let std::collections::hash_map::Entry::Vacant(e) = map.entry(mgid) {
@@ -174,6 +192,7 @@ impl HyperNodePeerLayer {
                     concurrent_peers: HashMap::new(),
                     pending_peers: HashMap::with_capacity(initial_peers.len()),
                     options,
+                    epoch: 0,
                 };
                 // insert peers into the pending_peers map to allow/process AcceptMembership signals
                 for peer_cid in initial_peers {
@@ -207,15 +226,16 @@ impl HyperNodePeerLayer {
 
     /// removes a [MessageGroup]
     pub async fn remove_message_group(&self, key: MessageGroupKey) -> Option<MessageGroup> {
-        let mut this = self.inner.write().await;
-        let map = this.message_groups.get_mut(&key.cid)?;
-        map.remove(&key.mgid)
+        let this = self.inner.read().await;
+        let mut shard = this.message_groups.write_shard(&key.cid);
+        shard.get_mut(&key.cid)?.remove(&key.mgid)
     }
 
     #[allow(unused_results)]
     pub async fn add_pending_peers_to_group(&self, key: MessageGroupKey, peers: Vec<u64>) {
-        let mut this = self.inner.write().await;
-        if let Some(map) = this.message_groups.get_mut(&key.cid) {
+        let this = self.inner.read().await;
+        let mut shard = this.message_groups.write_shard(&key.cid);
+        if let Some(map) = shard.get_mut(&key.cid) {
             if let Some(entry) = map.get_mut(&key.mgid) {
                 for peer_cid in peers {
                     let insert = MessageGroupPeer { peer_cid };
@@ -230,8 +250,9 @@ impl HyperNodePeerLayer {
     #[allow(unused_results)]
     // Upgrades a peer from pending to concurrent (enabled reception of broadcasts)
     pub async fn upgrade_peer_in_group(&self, key: MessageGroupKey, peer_cid: u64) -> bool {
-        let mut this = self.inner.write().await;
-        if let Some(map) = this.message_groups.get_mut(&key.cid) {
+        let this = self.inner.read().await;
+        let mut shard = this.message_groups.write_shard(&key.cid);
+        if let Some(map) = shard.get_mut(&key.cid) {
             if let Some(entry) = map.get_mut(&key.mgid) {
                 if let Some(peer) = entry.pending_peers.remove(&peer_cid) {
                     entry.concurrent_peers.insert(peer_cid, peer);
@@ -246,7 +267,8 @@ impl HyperNodePeerLayer {
     /// Determines if the [MessageGroupKey] maps to a [MessageGroup]
     pub async fn message_group_exists(&self, key: MessageGroupKey) -> bool {
         let this = self.inner.read().await;
-        if let Some(map) = this.message_groups.get(&key.cid) {
+        let shard = this.message_groups.read_shard(&key.cid);
+        if let Some(map) = shard.get(&key.cid) {
             map.contains_key(&key.mgid)
         } else {
             false
@@ -256,8 +278,8 @@ impl HyperNodePeerLayer {
     /// Returns the set of peers in a [MessageGroup]
     pub async fn get_peers_in_message_group(&self, key: MessageGroupKey) -> Option<Vec<u64>> {
         let this = self.inner.read().await;
-        let map = this.message_groups.get(&key.cid)?;
-        let message_group = map.get(&key.mgid)?;
+        let shard = this.message_groups.read_shard(&key.cid);
+        let message_group = shard.get(&key.cid)?.get(&key.mgid)?;
         let peers = message_group
             .concurrent_peers
             .keys()
@@ -270,19 +292,41 @@ impl HyperNodePeerLayer {
         }
     }
 
-    /// Removes the provided peers from the group. Returns a set of peers that were removed successfully, as well as the remaining peers
+    /// Returns the epoch of a [MessageGroup] if `peer_cid` is a member of the group
+    pub async fn get_message_group_epoch(
+        &self,
+        key: MessageGroupKey,
+        peer_cid: u64,
+    ) -> Option<u64> {
+        let this = self.inner.read().await;
+        let shard = this.message_groups.read_shard(&key.cid);
+        let message_group = shard.get(&key.cid)?.get(&key.mgid)?;
+        message_group
+            .concurrent_peers
+            .contains_key(&peer_cid)
+            .then_some(message_group.epoch)
+    }
+
+    /// Removes the provided peers from the group. Returns a set of peers that were removed successfully, the remaining peers,
+    /// and the epoch of the group, which rotates whenever any peer is removed
     pub async fn remove_peers_from_message_group(
         &self,
         key: MessageGroupKey,
         mut peers: Vec<u64>,
-    ) -> Result<(Vec<u64>, Vec<u64>), ()> {
-        let mut this = self.inner.write().await;
-        let map = this.message_groups.get_mut(&key.cid).ok_or(())?;
-        let message_group = map.get_mut(&key.mgid).ok_or(())?;
+    ) -> Result<(Vec<u64>, Vec<u64>, u64), ()> {
+        let this = self.inner.read().await;
+        let mut shard = this.message_groups.write_shard(&key.cid);
+        let message_group = shard
+            .get_mut(&key.cid)
+            .and_then(|map| map.get_mut(&key.mgid))
+            .ok_or(())?;
         //let mut peers_removed = Vec::new();
         // Keep all the peers that were not removed. I.e., if the remove operation returns None
         // then that peer wasn't removed and hence should stay in the vec
         peers.retain(|peer| message_group.concurrent_peers.remove(peer).is_some());
+        if !peers.is_empty() {
+            message_group.epoch += 1;
+        }
 
         let peers_remaining = message_group
             .concurrent_peers
@@ -291,15 +335,18 @@ impl HyperNodePeerLayer {
             .collect::<Vec<u64>>();
         let peers_successfully_removed = peers;
 
-        Ok((peers_successfully_removed, peers_remaining))
+        Ok((
+            peers_successfully_removed,
+            peers_remaining,
+            message_group.epoch,
+        ))
     }
 
     pub async fn list_message_groups_for(&self, cid: u64) -> Option<Vec<MessageGroupKey>> {
+        let this = self.inner.read().await;
+        let shard = this.message_groups.read_shard(&cid);
         Some(
-            self.inner
-                .read()
-                .await
-                .message_groups
+            shard
                 .get(&cid)?
                 .keys()
                 .copied()
@@ -311,8 +358,9 @@ impl HyperNodePeerLayer {
     /// returns true if auto-accepted, false if requires the owner to accept
     /// returns None if the key does not match an active group
     pub async fn request_join(&self, peer_cid: u64, key: MessageGroupKey) -> Option<bool> {
-        let mut write = self.inner.write().await;
-        let group = write.message_groups.get_mut(&key.cid)?.get_mut(&key.mgid)?;
+        let this = self.inner.read().await;
+        let mut shard = this.message_groups.write_shard(&key.cid);
+        let group = shard.get_mut(&key.cid)?.get_mut(&key.mgid)?;
         if group.options.group_type == GroupType::Public {
             let _ = group
                 .concurrent_peers
@@ -323,6 +371,83 @@ impl HyperNodePeerLayer {
         }
     }
 
+    /// Captures the message groups, and the register and connect requests awaiting consent, of every client
+    pub async fn snapshot(&self) -> HashMap<u64, ClientSnapshot> {
+        let this = self.inner.read().await;
+        let mut snapshots = HashMap::new();
+        this.message_groups.for_each(|cid, groups| {
+            if !groups.is_empty() {
+                let _ = snapshots.insert(
+                    *cid,
+                    ClientSnapshot {
+                        message_groups: groups.clone(),
+                        pending_consents: Vec::new(),
+                    },
+                );
+            }
+        });
+
+        let shared = this.inner.read();
+        let now = tokio::time::Instant::now();
+        for (cid, postings) in &shared.observed_postings {
+            for (ticket, posting) in postings {
+                if matches!(
+                    posting.signal,
+                    PeerSignal::PostRegister(..) | PeerSignal::PostConnect(..)
+                ) {
+                    let remaining = shared
+                        .delay_queue
+                        .deadline(&posting.key)
+                        .saturating_duration_since(now);
+                    snapshots
+                        .entry(*cid)
+                        .or_default()
+                        .pending_consents
+                        .push(PendingConsent {
+                            ticket: *ticket,
+                            signal: posting.signal.clone(),
+                            remaining,
+                        });
+                }
+            }
+        }
+
+        snapshots
+    }
+
+    /// Restores the message groups of each client, and holds onto the pending consents until
+    /// [`Self::take_restored_consents`] is called once the requesting client reconnects
+    #[allow(unused_results)]
+    pub async fn restore(&self, snapshots: HashMap<u64, ClientSnapshot>) {
+        let mut this = self.inner.write().await;
+        for (cid, snapshot) in snapshots {
+            if !snapshot.message_groups.is_empty() {
+                this.message_groups
+                    .write_shard(&cid)
+                    .entry(cid)
+                    .or_default()
+                    .extend(snapshot.message_groups);
+            }
+
+            if !snapshot.pending_consents.is_empty() {
+                this.restored_consents
+                    .entry(cid)
+                    .or_default()
+                    .extend(snapshot.pending_consents);
+            }
+        }
+    }
+
+    /// Removes the restored consents requested by `cid`
+    pub async fn take_restored_consents(&self, cid: u64) -> Vec<PendingConsent> {
+        self.inner
+            .write()
+            .await
+            .restored_consents
+            .remove(&cid)
+            .unwrap_or_default()
+    }
+
     /// returns true if added successfully, or false if not (mailbox may be overloaded)
     /// `add_queue_if_non_existing`: Creates an event queue if non-existing (useful if target not connected yet)
     /// `target_cid`: Should be the destination
@@ -355,6 +480,13 @@ impl HyperNodePeerLayerExecutor {
             if let Some(active_postings) = this.observed_postings.get_mut(&implicated_cid) {
                 if let Some(posting) = active_postings.remove(&ticket) {
                     log::warn!(target: "citadel", "Running on_timeout for active posting {} for CID {}", ticket, implicated_cid);
+                    if posting_store::is_persisted(&posting.signal) {
+                        forget_persisted_posting(
+                            self.persistence_handler.clone(),
+                            implicated_cid,
+                            ticket,
+                        );
+                    }
                     (posting.on_timeout)(posting.signal)
                 } else {
                     log::warn!(target: "citadel", "Attempted to remove active posting {} for CID {}, but failed", implicated_cid, ticket);
@@ -389,15 +521,15 @@ impl HyperNodePeerLayerInner {
     }
 
     /// Determines if `peer_cid` is already attempting to connect to `implicated_cid`
-    /// Returns the target's ticket and signal for their corresponding request
+    /// Returns how the two requests are to be resolved, irrespective of which arrived first
     pub fn check_simultaneous_connect(
         &mut self,
         implicated_cid: u64,
         peer_cid: u64,
-    ) -> Option<Ticket> {
-        log::trace!(target: "citadel", "Checking simultaneous register between {} and {}", implicated_cid, peer_cid);
+    ) -> Option<SimultaneousConnect> {
+        log::trace!(target: "citadel", "Checking simultaneous connect between {} and {}", implicated_cid, peer_cid);
 
-        self.check_simultaneous_event(peer_cid, |posting| if let PeerSignal::PostConnect(conn, _, _, _, _) = &posting.signal {
+        let peer_ticket = self.check_simultaneous_event(peer_cid, |posting| if let PeerSignal::PostConnect(conn, _, None, _, _) = &posting.signal {
             log::trace!(target: "citadel", "Checking if posting from conn={:?} ~ {:?}", conn, implicated_cid);
             if let PeerConnectionType::HyperLANPeerToHyperLANPeer(_, b) = conn {
                 *b == implicated_cid
@@ -406,7 +538,13 @@ impl HyperNodePeerLayerInner {
             }
         } else {
             false
-        })
+        })?;
+
+        Some(SimultaneousConnect::resolve(
+            implicated_cid,
+            peer_cid,
+            peer_ticket,
+        ))
     }
 
     pub fn check_simulataneous_deregister(
@@ -441,6 +579,8 @@ impl HyperNodePeerLayerInner {
     /// `on_timeout`: This function will be called if a timeout occurs. The provided session belongs to `implicated_cid`
     /// NOTE: the ticket MUST be unique per session, otherwise unexpired items may disappear unnecessarily! If the ticket ID's are provided
     /// by the HyperLAN client's side, this should work out
+    /// Requests awaiting consent are additionally persisted, allowing them to be reinstated via
+    /// [`posting_store::take_postings`] if the server goes down before the target responds
     #[allow(unused_results)]
     pub async fn insert_tracked_posting(
         &self,
@@ -455,11 +595,26 @@ impl HyperNodePeerLayerInner {
         log::trace!(target: "citadel", "Creating TrackedPosting {} (Ticket: {})", implicated_cid, ticket);
 
         if let Some(map) = this.observed_postings.get_mut(&implicated_cid) {
+            let persisted = posting_store::is_persisted(&signal).then(|| signal.clone());
             let tracked_posting = TrackedPosting::new(signal, delay_key, on_timeout);
             map.insert(ticket, tracked_posting);
 
             std::mem::drop(this);
             self.waker.wake();
+
+            if let Some(signal) = persisted {
+                if let Err(err) = posting_store::store_posting(
+                    &self.persistence_handler,
+                    implicated_cid,
+                    ticket,
+                    signal,
+                    timeout,
+                )
+                .await
+                {
+                    log::warn!(target: "citadel", "Unable to persist posting {ticket} of {implicated_cid}: {err:?}");
+                }
+            }
         } else {
             log::error!(target: "citadel", "Unable to find implicated_cid in observed_posting. Bad init state?");
         }
@@ -478,6 +633,13 @@ impl HyperNodePeerLayerInner {
                 let _ = this.delay_queue.remove(&active_posting.key);
                 std::mem::drop(this);
                 self.waker.wake();
+                if posting_store::is_persisted(&active_posting.signal) {
+                    forget_persisted_posting(
+                        self.persistence_handler.clone(),
+                        implicated_cid,
+                        ticket,
+                    );
+                }
                 Some(active_posting.signal)
             } else {
                 log::warn!(target: "citadel", "Tracked posting for {} (ticket: {}) does not exist since key for ticket does not exist", implicated_cid, ticket);
@@ -490,6 +652,38 @@ impl HyperNodePeerLayerInner {
     }
 }
 
+/// Removes a persisted posting in the background, since postings are removed synchronously
+fn forget_persisted_posting(pers: PersistenceHandler, implicated_cid: u64, ticket: Ticket) {
+    spawn!(async move {
+        if let Err(err) = posting_store::remove_posting(&pers, implicated_cid, ticket).await {
+            log::warn!(target: "citadel", "Unable to remove persisted posting {ticket} of {implicated_cid}: {err:?}");
+        }
+    });
+}
+
+/// The resolution of two clients posting a connect request to one another before either responded.
+/// The request of the client with the lower CID always stands, such that both clients agree on
+/// which of them initiates the key exchange no matter which request reached the server first
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SimultaneousConnect {
+    /// The posting of the peer stands, and the request of the implicated client is treated as
+    /// its acceptance
+    AcceptPeerPosting(Ticket),
+    /// The posting of the peer is withdrawn, and the peer is treated as having accepted the
+    /// request of the implicated client
+    WithdrawPeerPosting(Ticket),
+}
+
+impl SimultaneousConnect {
+    fn resolve(implicated_cid: u64, peer_cid: u64, peer_ticket: Ticket) -> Self {
+        if peer_cid < implicated_cid {
+            Self::AcceptPeerPosting(peer_ticket)
+        } else {
+            Self::WithdrawPeerPosting(peer_ticket)
+        }
+    }
+}
+
 impl Stream for HyperNodePeerLayerExecutor {
     type Item = ();
 
@@ -560,6 +754,42 @@ pub enum PeerSignal {
     SignalReceived(Ticket),
     // for key-exchange
     Kem(PeerConnectionType, KeyExchangeProcess),
+    // sent by the server to a grantee when an object is shared with, or unshared from, it
+    SharedObject(SharedObjectNotification),
+    // subscribes the client to changes in the peer list until its session ends
+    SubscribePeerList(HypernodeConnectionType),
+    // sent by the server to each subscriber when the peer list changes
+    PeerListChanged(PeerListDelta),
+    // enables UDP for a session that connected with UdpMode::Disabled. The server echoes the signal once ready to traverse NATs
+    EnableUdp(HypernodeConnectionType),
+}
+
+/// A change to the peer list of a client, as observed by the server
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, PartialEq)]
+pub enum PeerListDelta {
+    /// A new client registered to the server
+    Registered { cid: u64, username: String },
+    /// A client deregistered from the server
+    Deregistered { cid: u64 },
+    /// A client connected to the server
+    Online { cid: u64 },
+    /// A client disconnected from the server
+    Offline { cid: u64 },
+    /// The peer became, or stopped being, mutually registered with the subscriber
+    MutualsChanged { peer_cid: u64, is_mutual: bool },
+}
+
+impl PeerListDelta {
+    /// The CID of the client that the delta concerns
+    pub fn cid(&self) -> u64 {
+        match self {
+            Self::Registered { cid, .. }
+            | Self::Deregistered { cid }
+            | Self::Online { cid }
+            | Self::Offline { cid } => *cid,
+            Self::MutualsChanged { peer_cid, .. } => *peer_cid,
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Copy, Clone, Eq, PartialEq)]
@@ -709,3 +939,155 @@ impl From<Vec<PeerSignal>> for MailboxTransfer {
         MailboxTransfer::Signals(signals)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::misc::session_security_settings::SessionSecuritySettings;
+    use crate::proto::peer::message_group::{GroupType, MessageGroupOptions};
+    use crate::proto::peer::peer_layer::{
+        HyperNodePeerLayer, PeerConnectionType, PeerSignal, SimultaneousConnect, UdpMode,
+    };
+    use crate::proto::peer::snapshot::ClientSnapshot;
+    use crate::proto::remote::Ticket;
+    use citadel_user::account_manager::AccountManager;
+    use citadel_user::backend::BackendType;
+    use citadel_user::serialization::SyncIO;
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    async fn peer_layer() -> HyperNodePeerLayer {
+        let account_manager = AccountManager::new(BackendType::InMemory, None, None, None)
+            .await
+            .unwrap();
+        HyperNodePeerLayer::new(account_manager.get_persistence_handler().clone())
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_restores_message_groups() {
+        let (owner, peer) = (10, 20);
+        let options = MessageGroupOptions {
+            group_type: GroupType::Public,
+            id: 1,
+        };
+
+        let before = peer_layer().await;
+        let _ = before.register_peer(owner).await.unwrap();
+        let key = before
+            .create_new_message_group(owner, &vec![peer], options)
+            .await
+            .unwrap();
+
+        // the snapshot is stored serialized, hence round-trip it before restoring
+        let snapshots = before
+            .snapshot()
+            .await
+            .into_iter()
+            .map(|(cid, snapshot)| {
+                let serialized = snapshot.serialize_to_vector().unwrap();
+                (
+                    cid,
+                    ClientSnapshot::deserialize_from_vector(&serialized).unwrap(),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        assert_eq!(snapshots.len(), 1);
+
+        let after = peer_layer().await;
+        after.restore(snapshots).await;
+        // reconnecting must not discard the restored groups
+        let _ = after.register_peer(owner).await.unwrap();
+        assert!(after.message_group_exists(key).await);
+        assert_eq!(
+            after.get_peers_in_message_group(key).await,
+            Some(vec![owner])
+        );
+        assert!(after.upgrade_peer_in_group(key, peer).await);
+        assert!(after.take_restored_consents(owner).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_group_epoch_rotates_on_member_removal() {
+        let (owner, peer) = (10, 20);
+        let layer = peer_layer().await;
+        let _ = layer.register_peer(owner).await.unwrap();
+        let key = layer
+            .create_new_message_group(owner, &vec![peer], MessageGroupOptions::default())
+            .await
+            .unwrap();
+        assert!(layer.upgrade_peer_in_group(key, peer).await);
+        assert_eq!(layer.get_message_group_epoch(key, peer).await, Some(0));
+
+        // removing a non-member leaves the epoch intact
+        let (removed, _, epoch) = layer
+            .remove_peers_from_message_group(key, vec![30])
+            .await
+            .unwrap();
+        assert!(removed.is_empty());
+        assert_eq!(epoch, 0);
+
+        let (removed, remaining, epoch) = layer
+            .remove_peers_from_message_group(key, vec![peer])
+            .await
+            .unwrap();
+        assert_eq!(removed, vec![peer]);
+        assert_eq!(remaining, vec![owner]);
+        assert_eq!(epoch, 1);
+        assert_eq!(layer.get_message_group_epoch(key, owner).await, Some(1));
+        // departed members are no longer entitled to the epoch
+        assert_eq!(layer.get_message_group_epoch(key, peer).await, None);
+    }
+
+    #[tokio::test]
+    async fn test_simultaneous_connect_resolves_by_cid() {
+        let (low, high) = (10, 20);
+        let post_connect = |implicated_cid, target_cid| {
+            PeerSignal::PostConnect(
+                PeerConnectionType::HyperLANPeerToHyperLANPeer(implicated_cid, target_cid),
+                None,
+                None,
+                SessionSecuritySettings::default(),
+                UdpMode::Disabled,
+            )
+        };
+
+        let layer = peer_layer().await;
+        for cid in [low, high] {
+            let _ = layer.register_peer(cid).await.unwrap();
+        }
+
+        let mut inner = layer.inner.write().await;
+        assert!(inner.check_simultaneous_connect(low, high).is_none());
+
+        // the request of the lower CID stands, even if it reaches the server last
+        inner
+            .insert_tracked_posting(
+                high,
+                Duration::from_secs(60),
+                Ticket(2),
+                post_connect(high, low),
+                |_| {},
+            )
+            .await;
+        assert_eq!(
+            inner.check_simultaneous_connect(low, high),
+            Some(SimultaneousConnect::WithdrawPeerPosting(Ticket(2)))
+        );
+        assert!(inner
+            .remove_tracked_posting_inner(high, Ticket(2))
+            .is_some());
+
+        inner
+            .insert_tracked_posting(
+                low,
+                Duration::from_secs(60),
+                Ticket(1),
+                post_connect(low, high),
+                |_| {},
+            )
+            .await;
+        assert_eq!(
+            inner.check_simultaneous_connect(high, low),
+            Some(SimultaneousConnect::AcceptPeerPosting(Ticket(1)))
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! Write-through persistence of the register and connect requests awaiting the consent of their target
+//!
+//! A [snapshot](crate::proto::peer::snapshot) only captures the pending consents during a planned
+//! shutdown. Storing each posting inside the byte map of the requesting client once it is tracked,
+//! and removing it once it resolves or times out, allows the consents to survive a crash as well.
+//! Each posting records the wall-clock time it expires, such that postings that expired while the
+//! server was down are discarded instead of reinstated
+use crate::error::NetworkError;
+use crate::proto::peer::peer_layer::PeerSignal;
+use crate::proto::peer::snapshot::PendingConsent;
+use crate::proto::remote::Ticket;
+use citadel_user::backend::PersistenceHandler;
+use citadel_user::serialization::SyncIO;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const POSTINGS: &str = "peer_layer_postings";
+
+#[derive(Serialize, Deserialize)]
+struct PersistedPosting {
+    ticket: Ticket,
+    signal: PeerSignal,
+    /// The time since the unix epoch at which the target no longer may respond
+    expires_at: Duration,
+}
+
+impl PersistedPosting {
+    /// Returns `None` if the posting expired before `now`
+    fn into_pending_consent(self, now: Duration) -> Option<PendingConsent> {
+        let remaining = self.expires_at.checked_sub(now)?;
+        if remaining.is_zero() {
+            return None;
+        }
+
+        Some(PendingConsent {
+            ticket: self.ticket,
+            signal: self.signal,
+            remaining,
+        })
+    }
+}
+
+fn unix_time() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+/// Only requests awaiting consent are persisted. Other postings, such as deregistrations, are
+/// meaningless once the sessions that created them end
+pub(crate) fn is_persisted(signal: &PeerSignal) -> bool {
+    matches!(
+        signal,
+        PeerSignal::PostRegister(..) | PeerSignal::PostConnect(..)
+    )
+}
+
+/// Stores the posting of `implicated_cid`, overwriting any posting with the same ticket
+pub async fn store_posting(
+    pers: &PersistenceHandler,
+    implicated_cid: u64,
+    ticket: Ticket,
+    signal: PeerSignal,
+    timeout: Duration,
+) -> Result<(), NetworkError> {
+    let posting = PersistedPosting {
+        ticket,
+        signal,
+        expires_at: unix_time() + timeout,
+    };
+    let serialized = posting
+        .serialize_to_vector()
+        .map_err(|err| NetworkError::Generic(err.into_string()))?;
+    let _ = pers
+        .store_byte_map_value(implicated_cid, 0, POSTINGS, &ticket.to_string(), serialized)
+        .await?;
+    Ok(())
+}
+
+/// Removes the posting of `implicated_cid` once it resolves or times out
+pub async fn remove_posting(
+    pers: &PersistenceHandler,
+    implicated_cid: u64,
+    ticket: Ticket,
+) -> Result<(), NetworkError> {
+    let _ = pers
+        .remove_byte_map_value(implicated_cid, 0, POSTINGS, &ticket.to_string())
+        .await?;
+    Ok(())
+}
+
+/// Removes every posting of `implicated_cid`, returning those that have yet to expire
+pub async fn take_postings(
+    pers: &PersistenceHandler,
+    implicated_cid: u64,
+) -> Result<Vec<PendingConsent>, NetworkError> {
+    let values = pers
+        .remove_byte_map_values_by_key(implicated_cid, 0, POSTINGS)
+        .await?;
+    let now = unix_time();
+
+    Ok(values
+        .into_values()
+        .filter_map(
+            |serialized| match PersistedPosting::deserialize_from_vector(&serialized) {
+                Ok(posting) => posting.into_pending_consent(now),
+                Err(err) => {
+                    log::warn!(target: "citadel", "Discarding the invalid persisted posting of {implicated_cid}: {err:?}");
+                    None
+                }
+            },
+        )
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::peer::peer_layer::PeerSignal;
+    use crate::proto::peer::posting_store::PersistedPosting;
+    use crate::proto::remote::Ticket;
+    use std::time::Duration;
+
+    #[test]
+    fn test_expired_postings_are_discarded() {
+        let posting = |expires_at| PersistedPosting {
+            ticket: Ticket(1),
+            signal: PeerSignal::DeregistrationSuccess(10),
+            expires_at,
+        };
+        let now = Duration::from_secs(1000);
+
+        let consent = posting(now + Duration::from_secs(30))
+            .into_pending_consent(now)
+            .unwrap();
+        assert_eq!(consent.remaining, Duration::from_secs(30));
+        assert_eq!(consent.ticket, Ticket(1));
+        assert!(posting(now).into_pending_consent(now).is_none());
+        assert!(posting(now - Duration::from_secs(1))
+            .into_pending_consent(now)
+            .is_none());
+    }
+}
@@ -0,0 +1,119 @@
+//! Exposes the ratchet version used by a channel, alongside the re-key events that advance it,
+//! such that applications may log key rotations and bind application state to a given epoch
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+const REKEY_EVENT_CAPACITY: usize = 32;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RekeyEvent {
+    /// A re-key began, either locally or by the peer. Messages keep using the current epoch
+    /// until the re-key completes
+    RekeyStarted,
+    /// A re-key completed, and all subsequent messages use the ratchet `version`. If both nodes
+    /// began a re-key concurrently, only one completes
+    RekeyCompleted { version: u32 },
+}
+
+/// The security epoch of a channel, i.e., the version of the ratchet used to encrypt the
+/// messages it sends
+#[derive(Clone)]
+pub struct SecurityEpoch {
+    inner: Arc<SecurityEpochInner>,
+}
+
+struct SecurityEpochInner {
+    version: AtomicU32,
+    events: broadcast::Sender<RekeyEvent>,
+}
+
+impl SecurityEpoch {
+    pub(crate) fn new(version: u32) -> Self {
+        let (events, _) = broadcast::channel(REKEY_EVENT_CAPACITY);
+        Self {
+            inner: Arc::new(SecurityEpochInner {
+                version: AtomicU32::new(version),
+                events,
+            }),
+        }
+    }
+
+    /// Returns the ratchet version currently used to encrypt outbound messages
+    pub fn current(&self) -> u32 {
+        self.inner.version.load(Ordering::Relaxed)
+    }
+
+    /// Subscribes to the re-key events of the channel. Only events occurring after the
+    /// subscription are received
+    pub fn subscribe(&self) -> broadcast::Receiver<RekeyEvent> {
+        self.inner.events.subscribe()
+    }
+
+    fn on_rekey_started(&self) {
+        // sending only fails if there are no subscribers
+        let _ = self.inner.events.send(RekeyEvent::RekeyStarted);
+    }
+
+    fn on_rekey_completed(&self, version: u32) {
+        self.inner.version.store(version, Ordering::Relaxed);
+        let _ = self
+            .inner
+            .events
+            .send(RekeyEvent::RekeyCompleted { version });
+    }
+}
+
+/// The security epochs of each channel in a session, keyed by the peer CID (or
+/// `C2S_ENCRYPTION_ONLY` for the client-to-server channel)
+#[derive(Default)]
+pub(crate) struct SecurityEpochs {
+    epochs: HashMap<u64, SecurityEpoch>,
+}
+
+impl SecurityEpochs {
+    pub(crate) fn insert(&mut self, cid: u64, version: u32) -> SecurityEpoch {
+        let epoch = SecurityEpoch::new(version);
+        let _ = self.epochs.insert(cid, epoch.clone());
+        epoch
+    }
+
+    pub(crate) fn on_rekey_started(&self, cid: u64) {
+        if let Some(epoch) = self.epochs.get(&cid) {
+            epoch.on_rekey_started()
+        }
+    }
+
+    pub(crate) fn on_rekey_completed(&self, cid: u64, version: u32) {
+        if let Some(epoch) = self.epochs.get(&cid) {
+            log::trace!(target: "citadel", "Security epoch for {} advanced to {}", cid, version);
+            epoch.on_rekey_completed(version)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::peer::security_epoch::{RekeyEvent, SecurityEpochs};
+
+    #[test]
+    fn test_rekey_events() {
+        let mut epochs = SecurityEpochs::default();
+        let epoch = epochs.insert(0, 0);
+        let mut events = epoch.subscribe();
+
+        epochs.on_rekey_started(0);
+        epochs.on_rekey_completed(0, 1);
+        // untracked channels are ignored
+        epochs.on_rekey_completed(1, 5);
+
+        assert_eq!(epoch.current(), 1);
+        assert_eq!(events.try_recv().unwrap(), RekeyEvent::RekeyStarted);
+        assert_eq!(
+            events.try_recv().unwrap(),
+            RekeyEvent::RekeyCompleted { version: 1 }
+        );
+        assert!(events.try_recv().is_err());
+    }
+}
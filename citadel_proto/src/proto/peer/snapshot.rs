@@ -0,0 +1,96 @@
+//! Snapshots of the peer layer state of a server, allowing a planned restart to resume it
+//!
+//! Sessions cannot survive a restart, since their ratchets and streams are bound to the running
+//! process. The state that outlives individual sessions can: the message groups owned by each
+//! client, and the register and connect requests still awaiting the consent of their target.
+//! Mailboxes are already stored by the backend, and thus need no snapshot. The snapshot of each
+//! client is stored inside the byte map of its account, hence a persistent backend is required
+//! for the snapshot to survive the restart
+//!
+//! In-flight file transfers are not included, since a transfer cannot resume once either
+//! endpoint's session ends; the sender has to retransmit after reconnecting
+use crate::error::NetworkError;
+use crate::proto::peer::message_group::MessageGroup;
+use crate::proto::peer::peer_layer::PeerSignal;
+use crate::proto::remote::Ticket;
+use citadel_user::backend::PersistenceHandler;
+use citadel_user::serialization::SyncIO;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::Duration;
+
+const SNAPSHOT: &str = "peer_layer_snapshot";
+const SNAPSHOT_SUB_KEY: &str = "state";
+
+/// The peer layer state of a single client
+#[derive(Serialize, Deserialize, Default)]
+pub struct ClientSnapshot {
+    pub(crate) message_groups: HashMap<u128, MessageGroup>,
+    pub(crate) pending_consents: Vec<PendingConsent>,
+}
+
+/// A register or connect request that awaited the consent of its target when the snapshot was taken
+#[derive(Serialize, Deserialize)]
+pub struct PendingConsent {
+    pub(crate) ticket: Ticket,
+    pub(crate) signal: PeerSignal,
+    /// The time the target had left to respond
+    pub(crate) remaining: Duration,
+}
+
+impl ClientSnapshot {
+    pub fn is_empty(&self) -> bool {
+        self.message_groups.is_empty() && self.pending_consents.is_empty()
+    }
+}
+
+/// Stores the snapshot of each client inside its byte map. Returns the number of clients stored
+pub async fn store_snapshots(
+    pers: &PersistenceHandler,
+    snapshots: HashMap<u64, ClientSnapshot>,
+) -> Result<usize, NetworkError> {
+    let mut stored = 0;
+    for (cid, snapshot) in snapshots {
+        if snapshot.is_empty() {
+            continue;
+        }
+
+        let serialized = snapshot
+            .serialize_to_vector()
+            .map_err(|err| NetworkError::Generic(err.into_string()))?;
+        let _ = pers
+            .store_byte_map_value(cid, 0, SNAPSHOT, SNAPSHOT_SUB_KEY, serialized)
+            .await?;
+        stored += 1;
+    }
+
+    Ok(stored)
+}
+
+/// Removes the snapshots of every registered client, such that each snapshot is only restored once
+pub async fn take_snapshots(
+    pers: &PersistenceHandler,
+) -> Result<HashMap<u64, ClientSnapshot>, NetworkError> {
+    let cids = pers
+        .get_registered_impersonal_cids(None)
+        .await?
+        .unwrap_or_default();
+    let mut snapshots = HashMap::new();
+
+    for cid in cids {
+        let values = pers.remove_byte_map_values_by_key(cid, 0, SNAPSHOT).await?;
+        if let Some(serialized) = values.get(SNAPSHOT_SUB_KEY) {
+            match ClientSnapshot::deserialize_from_vector(serialized) {
+                Ok(snapshot) => {
+                    let _ = snapshots.insert(cid, snapshot);
+                }
+
+                Err(err) => {
+                    log::warn!(target: "citadel", "Discarding the invalid peer layer snapshot of {cid}: {err:?}");
+                }
+            }
+        }
+    }
+
+    Ok(snapshots)
+}
@@ -1,6 +1,7 @@
 use crate::error::NetworkError;
 use crate::kernel::kernel_communicator::{KernelAsyncCallbackHandler, KernelStreamSubscription};
 use crate::prelude::{NodeRequest, NodeResult};
+use crate::proto::misc::scheduler::{ScheduledTask, Scheduler};
 use crate::proto::node::HdpServerRemoteInner;
 use crate::proto::outbound_sender::BoundedSender;
 use citadel_user::account_manager::AccountManager;
@@ -100,6 +101,7 @@ impl NodeRemote {
                 callback_handler,
                 account_manager,
                 node_type,
+                scheduler: Scheduler::default(),
             }),
         }
     }
@@ -225,6 +227,31 @@ impl NodeRemote {
     pub fn account_manager(&self) -> &AccountManager {
         &self.inner.account_manager
     }
+
+    /// Runs the task according to its schedule until cancelled or the node shuts down. A task
+    /// scheduled under the name of an existing task replaces it
+    pub async fn schedule(&self, task: ScheduledTask) -> Result<(), NetworkError> {
+        self.inner.scheduler.schedule(self.clone(), task).await
+    }
+
+    /// Stops the task scheduled under `name`. Returns false if no such task is running
+    pub fn cancel_scheduled(&self, name: &str) -> bool {
+        self.inner.scheduler.cancel(name)
+    }
+
+    /// Returns the names of the scheduled tasks that have yet to finish
+    pub fn scheduled_tasks(&self) -> Vec<String> {
+        self.inner.scheduler.scheduled()
+    }
+
+    pub(crate) fn scheduler(&self) -> &Scheduler {
+        &self.inner.scheduler
+    }
+
+    /// Returns the number of tickets awaiting a callback
+    pub(crate) fn tracked_tickets(&self) -> usize {
+        self.inner.callback_handler.tracked_tickets()
+    }
 }
 
 impl Unpin for NodeRemote {}
@@ -1,10 +1,9 @@
-use std::net::IpAddr;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 //use async_std::prelude::*;
 use bytes::{Bytes, BytesMut};
-use futures::{SinkExt, StreamExt, TryFutureExt, TryStreamExt};
+use futures::{Sink, SinkExt, StreamExt, TryFutureExt, TryStreamExt};
 use tokio::time::Instant;
 use tokio_util::codec::LengthDelimitedCodec;
 
@@ -15,27 +14,47 @@ use citadel_user::account_manager::AccountManager;
 use citadel_user::auth::proposed_credentials::ProposedCredentials;
 use citadel_user::client_account::ClientNetworkAccount;
 use citadel_user::network_account::ConnectProtocol;
+use citadel_user::permissions::AccountPermissions;
+use citadel_user::server_misc_settings::SessionWatchdogSettings;
 use citadel_wire::hypernode_type::NodeType;
+use citadel_wire::udp_traversal::linear::encrypted_config_container::HolePunchConfigContainer;
 use citadel_wire::udp_traversal::targetted_udp_socket_addr::TargettedSocketAddr;
+use netbeam::sync::RelativeNodeType;
 use netbeam::time_tracker::TimeTracker;
 
 use crate::constants::{
     DRILL_UPDATE_FREQUENCY_LOW_BASE, FIREWALL_KEEP_ALIVE_UDP, GROUP_EXPIRE_TIME_MS,
     HDP_HEADER_BYTE_LEN, INITIAL_RECONNECT_LOCKOUT_TIME_NS, KEEP_ALIVE_INTERVAL_MS,
-    KEEP_ALIVE_TIMEOUT_NS, LOGIN_EXPIRATION_TIME,
+    KEEP_ALIVE_TIMEOUT_NS, MAX_UDP_REBINDS, UDP_REBIND_BACKOFF,
 };
 use crate::error::NetworkError;
 use crate::proto::packet::{packet_flags, HdpPacket};
 use crate::proto::packet_crafter::peer_cmd::C2S_ENCRYPTION_ONLY;
 use crate::proto::packet_crafter::{self, GroupTransmitter, RatchetPacketCrafterContainer};
-use citadel_user::backend::utils::VirtualObjectMetadata;
+use citadel_user::backend::utils::{
+    ReVFSDirectoryOperation, SharedObjectOperation, TransferProgressTracker, VirtualObjectMetadata,
+};
 //use futures_codec::Framed;
 use crate::proto::misc;
+use crate::proto::misc::abuse_detection::AbuseMonitor;
 use crate::proto::misc::clean_shutdown::{CleanShutdownSink, CleanShutdownStream};
+use crate::proto::misc::connect_timings::ConnectTimer;
 use crate::proto::misc::dual_rwlock::DualRwLock;
+use crate::proto::misc::flow_control::WindowUpdate;
+use crate::proto::misc::legal_hold::LegalHold;
+use crate::proto::misc::multipath::{MultipathCongestion, MIN_MULTIPATH_OBJECT_LEN};
 use crate::proto::misc::net::GenericNetworkStream;
+use crate::proto::misc::packet_filter::{self, PacketFilter, PacketVerdict};
+use crate::proto::misc::processing_budget::ProcessingBudget;
+use crate::proto::misc::protocol_capabilities::ProtocolCapabilities;
+use crate::proto::misc::provisional_reaper::{self, ProvisionalReaper, ProvisionalStage};
+use crate::proto::misc::relay::RelayLedger;
+use crate::proto::misc::resource_counters::{SessionLifecycle, SessionLifecycleGuard};
+use crate::proto::misc::security_info::TransportSecurity;
+use crate::proto::misc::session_affinity;
 use crate::proto::misc::session_security_settings::SessionSecuritySettings;
-use crate::proto::node::ConnectMode;
+use crate::proto::misc::watchdog::EventLoopProgress;
+use crate::proto::node::{ConnectMode, SecrecyMode};
 use crate::proto::packet_processor::includes::{Duration, SocketAddr};
 use crate::proto::packet_processor::{self, PrimaryProcessorResult};
 use crate::proto::session_manager::HdpSessionManager;
@@ -46,29 +65,38 @@ use crate::prelude::{GroupBroadcast, SecureProtocolPacket};
 use crate::proto::endpoint_crypto_accessor::EndpointCryptoAccessor;
 use crate::proto::misc::dual_cell::DualCell;
 use crate::proto::misc::dual_late_init::DualLateInit;
-use crate::proto::misc::udp_internal_interface::{UdpSplittableTypes, UdpStream};
+use crate::proto::misc::udp_internal_interface::{
+    is_transient_udp_error, RawUdpSocketConnector, UdpSplittableTypes, UdpStream,
+};
+use crate::proto::misc::udp_keep_alive::KeepAliveTuner;
 use crate::proto::outbound_sender::{
-    channel, unbounded, SendError, UnboundedReceiver, UnboundedSender,
+    channel, unbounded, KernelEventSender, SendError, UnboundedReceiver, UnboundedSender,
 };
 use crate::proto::outbound_sender::{
-    OutboundPrimaryStreamReceiver, OutboundPrimaryStreamSender, OutboundUdpSender,
+    OutboundPrimaryStreamReceiver, OutboundPrimaryStreamSender, OutboundUdpSender, KEEP_ALIVE,
 };
+use crate::proto::packet_processor::preconnect_packet::traverse_deferred_udp;
 use crate::proto::packet_processor::raw_primary_packet::{check_proxy, ReceivePortType};
+use crate::proto::peer::hole_punch_compat_sink_stream::ReliableOrderedCompatStream;
 use crate::proto::peer::p2p_conn_handler::P2PInboundHandle;
 use crate::proto::peer::peer_layer::{HyperNodePeerLayer, PeerSignal, UdpMode};
 use crate::proto::session_queue_handler::{
     QueueWorkerResult, QueueWorkerTicket, SessionQueueWorker, SessionQueueWorkerHandle,
-    DRILL_REKEY_WORKER, FIREWALL_KEEP_ALIVE, KEEP_ALIVE_CHECKER, PROVISIONAL_CHECKER,
-    RESERVED_CID_IDX,
+    DRILL_REKEY_WORKER, KEEP_ALIVE_CHECKER, PROVISIONAL_CHECKER, RESERVED_CID_IDX,
 };
 use crate::proto::state_container::{
-    FileKey, GroupKey, OutboundFileTransfer, OutboundTransmitterContainer, StateContainer,
-    StateContainerInner, VirtualConnectionType, VirtualTargetType,
+    FileKey, GroupKey, OutboundFileTransfer, OutboundTransmitterContainer,
+    PendingDeduplicatedTransfer, PendingDeltaTransfer, StateContainer, StateContainerInner,
+    VirtualConnectionType, VirtualTargetType,
 };
 use crate::proto::state_subcontainers::preconnect_state_container::UdpChannelSender;
 use crate::proto::state_subcontainers::rekey_container::calculate_update_frequency;
+use crate::proto::state_subcontainers::stage_machine::PreConnectStage;
 use crate::proto::transfer_stats::TransferStats;
 use atomic::Atomic;
+use citadel_crypt::chunk_dedup::{ChunkedObject, DEFAULT_CACHE_CHUNK_SIZE};
+use citadel_crypt::compression::CompressionAlgorithm;
+use citadel_crypt::delta_sync::DEFAULT_DELTA_BLOCK_SIZE;
 use citadel_crypt::misc::TransferType;
 use citadel_crypt::prelude::ConstructorOpts;
 use citadel_crypt::streaming_crypt_scrambler::{scramble_encrypt_source, ObjectSource};
@@ -80,7 +108,7 @@ use std::ops::Deref;
 use std::path::PathBuf;
 use std::pin::Pin;
 //use futures_codec::Framed;
-use crate::proto::node_result::{Disconnect, InternalServerError, NodeResult};
+use crate::proto::node_result::{Disconnect, InternalServerError, NamedChannelOpened, NodeResult};
 use crate::proto::remote::{NodeRemote, Ticket};
 
 //use crate::define_struct;
@@ -173,19 +201,21 @@ pub struct HdpSessionInner {
     pub(super) kernel_ticket: DualCell<Ticket>,
     pub(super) remote_peer: SocketAddr,
     // Sends results directly to the kernel
-    pub(super) kernel_tx: UnboundedSender<NodeResult>,
+    pub(super) kernel_tx: KernelEventSender,
     pub(super) to_primary_stream: DualLateInit<Option<OutboundPrimaryStreamSender>>,
     // Setting this will determine what algorithm is used during the DO_CONNECT stage
     pub(super) session_manager: HdpSessionManager,
     pub(super) state: Arc<Atomic<SessionState>>,
     pub(super) state_container: StateContainer,
     pub(super) account_manager: AccountManager,
+    // the permissions of the client, as determined by the node once the client connects
+    pub(super) account_permissions: DualCell<AccountPermissions>,
     pub(super) time_tracker: TimeTracker,
     pub(super) local_node_type: NodeType,
     pub(super) remote_node_type: Option<NodeType>,
     pub(super) local_bind_addr: SocketAddr,
     pub(super) do_static_hr_refresh_atexit: DualCell<bool>,
-    pub(super) dc_signal_sender: DualRwLock<Option<UnboundedSender<NodeResult>>>,
+    pub(super) dc_signal_sender: DualRwLock<Option<KernelEventSender>>,
     pub(super) is_server: bool,
     pub(super) stopper_tx: DualRwLock<tokio::sync::broadcast::Sender<()>>,
     pub(super) queue_handle: DualLateInit<SessionQueueWorkerHandle>,
@@ -197,7 +227,16 @@ pub struct HdpSessionInner {
     pub(super) client_config: Arc<rustls::ClientConfig>,
     pub(super) hypernode_peer_layer: HyperNodePeerLayer,
     pub(super) stun_servers: Option<Vec<String>>,
+    pub(super) hole_punch_settings: Option<HolePunchConfigContainer>,
+    pub(super) event_loop_progress: EventLoopProgress,
+    pub(super) provisional_reaper: Arc<ProvisionalReaper>,
+    pub(super) processing_budget: Arc<ProcessingBudget>,
+    pub(super) packet_filter: Option<Arc<dyn PacketFilter>>,
+    pub(super) relay_ledger: Option<Arc<RelayLedger>>,
+    pub(super) legal_hold: Option<Arc<LegalHold>>,
+    pub(super) abuse_monitor: Option<Arc<AbuseMonitor>>,
     on_drop: UnboundedSender<()>,
+    lifecycle_guard: SessionLifecycleGuard,
 }
 
 /// allows each session worker to check the state of the session
@@ -233,7 +272,7 @@ pub(crate) struct SessionInitParams {
     pub hdp_remote: NodeRemote,
     pub local_bind_addr: SocketAddr,
     pub local_node_type: NodeType,
-    pub kernel_tx: UnboundedSender<NodeResult>,
+    pub kernel_tx: KernelEventSender,
     pub session_manager: HdpSessionManager,
     pub account_manager: AccountManager,
     pub time_tracker: TimeTracker,
@@ -244,6 +283,23 @@ pub(crate) struct SessionInitParams {
     // this is set only when a local client is attempting to start an outbound session
     pub client_only_settings: Option<ClientOnlySessionInitSettings>,
     pub stun_servers: Option<Vec<String>>,
+    pub hole_punch_settings: Option<HolePunchConfigContainer>,
+    pub provisional_reaper: Arc<ProvisionalReaper>,
+    pub session_lifecycle: Arc<SessionLifecycle>,
+    pub processing_budget: Arc<ProcessingBudget>,
+    pub packet_filter: Option<Arc<dyn PacketFilter>>,
+    pub relay_ledger: Option<Arc<RelayLedger>>,
+    pub legal_hold: Option<Arc<LegalHold>>,
+    pub abuse_monitor: Option<Arc<AbuseMonitor>>,
+}
+
+/// Why the listener or sender of a UDP path stopped without failing
+enum UdpPathExit {
+    /// The UDP channel closed
+    Closed,
+    /// The NAT binding of a hole-punched path died, either as found by this node, or as reported
+    /// by the adjacent node
+    BindingDead { requested_by_peer: bool },
 }
 
 pub(crate) struct ClientOnlySessionInitSettings {
@@ -255,6 +311,7 @@ pub(crate) struct ClientOnlySessionInitSettings {
     pub keep_alive_timeout_ns: i64,
     pub security_settings: SessionSecuritySettings,
     pub connect_mode: Option<ConnectMode>,
+    pub connect_timer: ConnectTimer,
 }
 
 impl HdpSession {
@@ -290,6 +347,26 @@ impl HdpSession {
                                     None,
                                 )
                             }
+
+                            AuthenticationRequest::Oidc { .. } => {
+                                match client_init_settings.cnac.clone() {
+                                    Some(cnac) => {
+                                        let cid = cnac.get_cid();
+                                        (
+                                            Some(cnac),
+                                            Arc::new(Atomic::new(SessionState::NeedsConnect)),
+                                            Some(cid),
+                                        )
+                                    }
+
+                                    // register will redirect to preconnect afterwards
+                                    None => (
+                                        None,
+                                        Arc::new(Atomic::new(SessionState::NeedsRegister)),
+                                        None,
+                                    ),
+                                }
+                            }
                         }
                     }
 
@@ -311,7 +388,8 @@ impl HdpSession {
         let hypernode_peer_layer = session_init_params.hypernode_peer_layer;
         let connect_mode = client_only_settings.as_ref().and_then(|r| r.connect_mode);
         let local_nat_type = session_init_params.local_nat_type;
-        let kernel_tx = session_init_params.kernel_tx;
+        // the events of each session are numbered independently
+        let kernel_tx = session_init_params.kernel_tx.for_session();
         let peer_only_connect_protocol = client_only_settings
             .as_ref()
             .map(|r| r.peer_only_connect_proto.clone())
@@ -324,6 +402,7 @@ impl HdpSession {
         let kernel_ticket = session_init_params.init_ticket;
         let remote_peer = session_init_params.remote_peer;
         let session_manager = session_init_params.session_manager;
+        let multipath_counters = session_manager.multipath_counters();
         let hdp_remote = session_init_params.hdp_remote;
         let session_security_settings = client_only_settings.as_ref().map(|r| r.security_settings);
         let udp_mode = client_only_settings
@@ -337,6 +416,14 @@ impl HdpSession {
             .map(|r| r.keep_alive_timeout_ns)
             .unwrap_or(KEEP_ALIVE_TIMEOUT_NS);
         let stun_servers = session_init_params.stun_servers;
+        let hole_punch_settings = session_init_params.hole_punch_settings;
+        let provisional_reaper = session_init_params.provisional_reaper;
+        let processing_budget = session_init_params.processing_budget;
+        let packet_filter = session_init_params.packet_filter;
+        let relay_ledger = session_init_params.relay_ledger;
+        let legal_hold = session_init_params.legal_hold;
+        let abuse_monitor = session_init_params.abuse_monitor;
+        let lifecycle_guard = session_init_params.session_lifecycle.track();
 
         let mut inner = HdpSessionInner {
             hypernode_peer_layer,
@@ -368,22 +455,32 @@ impl HdpSession {
                 is_server,
                 TransferStats::new(timestamp, 0),
                 udp_mode,
+                multipath_counters,
             ),
             to_primary_stream: DualLateInit::default(),
             state,
             account_manager,
+            account_permissions: DualCell::new(AccountPermissions::default()),
             is_server,
             stopper_tx: stopper_tx.clone().into(),
             queue_handle: DualLateInit::default(),
             client_config,
             stun_servers,
+            hole_punch_settings,
+            event_loop_progress: EventLoopProgress::default(),
+            provisional_reaper,
+            processing_budget,
+            packet_filter,
+            relay_ledger,
+            legal_hold,
+            abuse_monitor,
+            lifecycle_guard,
         };
 
-        if let Some(proposed_credentials) = session_init_params
-            .client_only_settings
-            .map(|r| r.proposed_credentials)
-        {
-            inner.store_proposed_credentials(proposed_credentials);
+        if let Some(client_only_settings) = session_init_params.client_only_settings {
+            inner.store_proposed_credentials(client_only_settings.proposed_credentials);
+            inner_mut_state!(inner.state_container).connect_state.timer =
+                client_only_settings.connect_timer;
         }
 
         Ok((stopper_tx, Self::from(inner)))
@@ -411,6 +508,8 @@ impl HdpSession {
         let this_close = self.clone();
 
         let (session_future, handle_zero_state, implicated_cid) = {
+            inner_mut_state!(this.state_container).transport_security =
+                TransportSecurity::of(&primary_stream);
             let quic_conn_opt = primary_stream.take_quic_connection();
             let (writer, reader) = misc::net::safe_split_stream(primary_stream);
 
@@ -468,6 +567,10 @@ impl HdpSession {
             // separate task, we solve the issue of re-entrancing of mutex
             spawn!(queue_worker_future);
 
+            if let Some(settings) = this.account_manager.get_misc_settings().session_watchdog {
+                spawn!(Self::execute_watchdog(this.as_weak(), settings));
+            }
+
             (session_future, handle_zero_state, implicated_cid)
         };
 
@@ -591,6 +694,7 @@ impl HdpSession {
                         passwordless,
                         proposed_cid,
                     );
+                state_container.challengeable_handshake = Some(stage0_register_packet.clone());
                 to_outbound
                     .unbounded_send(stage0_register_packet)
                     .map_err(|_| NetworkError::InternalError("Writer stream corrupted"))?;
@@ -600,7 +704,10 @@ impl HdpSession {
             }
 
             SessionState::NeedsConnect => {
-                Self::begin_connect(&session, cnac.as_ref().unwrap())?;
+                let cnac = cnac.as_ref().unwrap();
+                let affinity_token =
+                    session_affinity::load_token(&persistence_handler, cnac.get_cid()).await?;
+                Self::begin_connect(&session, cnac, affinity_token)?;
             }
 
             // This implies this node received a new incoming connection. It is up to the other node, Alice, to send a stage 0 packet
@@ -623,9 +730,11 @@ impl HdpSession {
         Ok(())
     }
 
+    /// `affinity_token` is zero unless the node the client registered to is load-balanced
     pub(crate) fn begin_connect(
         session: &HdpSession,
         cnac: &ClientNetworkAccount,
+        affinity_token: u128,
     ) -> Result<(), NetworkError> {
         log::trace!(target: "citadel", "Beginning pre-connect subroutine!");
         let session_ref = session;
@@ -681,12 +790,16 @@ impl HdpSession {
             session_security_settings,
             peer_only_connect_mode,
             connect_mode,
+            affinity_token,
         );
 
-        state_container.pre_connect_state.last_stage =
-            packet_flags::cmd::aux::do_preconnect::SYN_ACK;
+        state_container
+            .pre_connect_state
+            .stage
+            .advance(PreConnectStage::SynAck)?;
         state_container.pre_connect_state.constructor = Some(alice_constructor);
         state_container.connect_state.connect_mode = Some(connect_mode);
+        state_container.challengeable_handshake = Some(syn.clone());
 
         session.send_to_primary_stream(None, syn)?;
 
@@ -706,7 +819,7 @@ impl HdpSession {
         let this_weak = this.as_weak();
         std::mem::drop(this);
         let task = async move {
-            let (listener, udp_sender_future, stopper_rx) = {
+            let (subsystem, stopper_rx) = {
                 let this = HdpSession::upgrade_weak(&this_weak)
                     .ok_or(NetworkError::InternalError("HdpSession no longer exists"))?;
 
@@ -715,17 +828,17 @@ impl HdpSession {
                 // we supply the natted ip since it is where we expect to receive packets
                 // whether local is server or not, we should expect to receive packets from natted
                 let hole_punched_socket = addr.receive_address;
-                let hole_punched_addr_ip = hole_punched_socket.ip();
 
                 let local_bind_addr = udp_conn.local_addr().unwrap();
                 let needs_manual_ka = udp_conn.needs_manual_ka();
+                let can_repunch = udp_conn.can_repunch();
 
                 let (outbound_sender_tx, outbound_sender_rx) = unbounded();
+                let keep_alive_tx = outbound_sender_tx.clone();
                 let udp_sender = OutboundUdpSender::new(
                     outbound_sender_tx,
                     local_bind_addr,
                     hole_punched_socket,
-                    needs_manual_ka,
                 );
                 let (stopper_tx, stopper_rx) = tokio::sync::oneshot::channel::<()>();
 
@@ -741,6 +854,18 @@ impl HdpSession {
                 let sess = HdpSession::upgrade_weak(&this_weak)
                     .ok_or(NetworkError::InternalError("HdpSession no longer exists"))?;
 
+                // QUIC handles its own keep-alives
+                let keep_alive = needs_manual_ka.then(|| {
+                    let mut config = inner_state!(sess.state_container)
+                        .session_security_settings
+                        .map(|settings| settings.udp_keep_alive)
+                        .unwrap_or_default();
+                    if !can_repunch {
+                        config.repunch_after = None;
+                    }
+                    citadel_io::Mutex::new(KeepAliveTuner::new(config))
+                });
+
                 let accessor = match v_target {
                     VirtualConnectionType::LocalGroupServer(_) => {
                         let mut state_container = inner_mut_state!(sess.state_container);
@@ -817,24 +942,17 @@ impl HdpSession {
                     }
                 };
 
-                // unlike TCP, we will not use [LengthDelimitedCodec] because there is no guarantee that packets
-                // will arrive in order
-                let (writer, reader) = udp_conn.split();
+                log::trace!(target: "citadel", "Server established UDP Port {}", local_bind_addr);
 
-                let listener = Self::listen_udp_port(
+                let subsystem = Self::udp_subsystem(
                     sess,
-                    hole_punched_addr_ip,
-                    local_bind_addr.port(),
-                    reader,
-                    accessor.clone(),
+                    udp_conn,
+                    outbound_sender_rx,
+                    keep_alive_tx,
+                    keep_alive,
+                    accessor,
                 );
-
-                log::trace!(target: "citadel", "Server established UDP Port {}", local_bind_addr);
-
-                //futures.push();
-                let udp_sender_future =
-                    Self::udp_outbound_sender(outbound_sender_rx, addr, writer, accessor);
-                (listener, udp_sender_future, stopper_rx)
+                (subsystem, stopper_rx)
             };
 
             log::trace!(target: "citadel", "[Q-UDP] Initiated UDP subsystem...");
@@ -846,9 +964,8 @@ impl HdpSession {
             };
 
             tokio::select! {
-                res0 = listener => res0,
-                res1 = udp_sender_future => res1,
-                res2 = stopper => res2
+                res0 = subsystem => res0,
+                res1 = stopper => res1
             }
         };
 
@@ -932,7 +1049,7 @@ impl HdpSession {
         fn evaluate_result(
             result: Result<PrimaryProcessorResult, NetworkError>,
             primary_stream: &OutboundPrimaryStreamSender,
-            kernel_tx: &UnboundedSender<NodeResult>,
+            kernel_tx: &KernelEventSender,
             session: &HdpSession,
         ) -> std::io::Result<()> {
             match result {
@@ -989,31 +1106,53 @@ impl HdpSession {
             NetworkError::Generic(err.to_string())
         }
 
+        // filtered packets are dropped here, such that they never occupy a processing slot
+        let packet_filter = this_main.packet_filter.as_deref();
         let reader = async_stream::stream! {
             while let Some(packet) = reader.next().await {
-                yield packet
+                match packet {
+                    Ok(packet) => match packet_filter::filter_packet(packet_filter, &packet, *remote_peer) {
+                        PacketVerdict::Drop => {
+                            log::trace!(target: "citadel", "Packet filter dropped a packet of {} bytes", packet.len());
+                        }
+
+                        verdict => yield Ok((packet, verdict)),
+                    },
+
+                    Err(err) => yield Err(err),
+                }
             }
         };
 
         reader
-            .try_for_each_concurrent(None, |packet| async move {
-                let result = packet_processor::raw_primary_packet::process_raw_packet(
-                    implicated_cid.get(),
-                    this_main,
-                    *remote_peer,
-                    *local_primary_port,
-                    packet,
-                )
-                .await;
-                evaluate_result(result, primary_stream, kernel_tx, this_main)
-            })
+            .try_for_each_concurrent(
+                this_main.processing_budget.per_session(),
+                |(packet, verdict)| async move {
+                    let _deprioritized = if verdict == PacketVerdict::Deprioritize {
+                        this_main.processing_budget.acquire_deprioritized().await
+                    } else {
+                        None
+                    };
+                    let _permit = this_main.processing_budget.acquire().await;
+                    let _progress = this_main.event_loop_progress.begin();
+                    let result = packet_processor::raw_primary_packet::process_raw_packet(
+                        implicated_cid.get(),
+                        this_main,
+                        *remote_peer,
+                        *local_primary_port,
+                        packet,
+                    )
+                    .await;
+                    evaluate_result(result, primary_stream, kernel_tx, this_main)
+                },
+            )
             .map_err(|err| handle_session_terminating_error(err, is_server, p2p))
             .await
     }
 
     pub(crate) fn send_to_primary_stream_closure(
         to_primary_stream: &OutboundPrimaryStreamSender,
-        kernel_tx: &UnboundedSender<NodeResult>,
+        kernel_tx: &KernelEventSender,
         msg: BytesMut,
         ticket: Option<Ticket>,
     ) -> Result<(), NetworkError> {
@@ -1052,23 +1191,39 @@ impl HdpSession {
 
             let kernel_ticket = borrow.kernel_ticket.get();
             let is_server = borrow.is_server;
+            let provisional_timeouts = borrow
+                .account_manager
+                .get_misc_settings()
+                .provisional_timeouts;
+            let provisional_reaper = borrow.provisional_reaper.clone();
+            let remote_peer = borrow.remote_peer;
             std::mem::drop(borrow);
 
             // now, begin loading the subroutines
             //let mut loop_idx = 0;
+            let session_start = Instant::now();
             queue_worker.insert_reserved_fn(
-                Some(QueueWorkerTicket::Oneshot(
+                Some(QueueWorkerTicket::Periodic(
                     PROVISIONAL_CHECKER,
                     RESERVED_CID_IDX,
                 )),
-                LOGIN_EXPIRATION_TIME,
-                |state_container| {
-                    if state_container.state.load(Ordering::SeqCst) != SessionState::Connected {
-                        QueueWorkerResult::EndSession
-                    } else {
-                        // remove it from being called again
-                        QueueWorkerResult::Complete
+                provisional_reaper::check_interval(&provisional_timeouts),
+                move |state_container| {
+                    if let Some(stage) = state_container
+                        .stalled_provisional_stage(session_start, &provisional_timeouts)
+                    {
+                        log::warn!(target: "citadel", "Reaping session with {remote_peer:?} stalled in the {stage:?} stage | stages: {:?}", state_container.stage_dump());
+                        provisional_reaper.record(stage);
+                        return QueueWorkerResult::EndSession;
+                    }
+
+                    for _ in 0..state_container
+                        .reap_stalled_peer_key_exchanges(provisional_timeouts.peer_key_exchange)
+                    {
+                        provisional_reaper.record(ProvisionalStage::PeerKeyExchange);
                     }
+
+                    QueueWorkerResult::Incomplete
                 },
             );
 
@@ -1130,31 +1285,6 @@ impl HdpSession {
                 }
             });
 
-            // TODO: Rework UDP keep-alive subsystem to take QUIC into consideration.
-            // QUIC already handles KA, but raw udp does not
-            queue_worker.insert_reserved_fn(
-                Some(QueueWorkerTicket::Periodic(FIREWALL_KEEP_ALIVE, 0)),
-                FIREWALL_KEEP_ALIVE_UDP,
-                move |state_container| {
-                    if state_container.state.load(Ordering::SeqCst) == SessionState::Connected {
-                        if state_container.udp_mode == UdpMode::Disabled {
-                            //log::trace!(target: "citadel", "TCP only mode detected. Removing FIREWALL_KEEP_ALIVE subroutine");
-                            return QueueWorkerResult::Complete;
-                        }
-
-                        if let Some(tx) = state_container.udp_primary_outbound_tx.as_ref() {
-                            if !tx.needs_manual_ka {
-                                return QueueWorkerResult::Complete;
-                            }
-
-                            let _ = tx.send_keep_alive();
-                        }
-                    }
-
-                    QueueWorkerResult::Incomplete
-                },
-            );
-
             queue_worker
         };
 
@@ -1162,12 +1292,99 @@ impl HdpSession {
         queue_worker.await
     }
 
+    /// Periodically checks for inbound packet handlers that stopped making progress, logging a
+    /// diagnostic of the session once one exceeds the stall timeout. Ends once the session drops
+    #[cfg(not(feature = "multi-threaded"))]
+    async fn execute_watchdog(
+        this: std::rc::Weak<HdpSessionInner>,
+        settings: SessionWatchdogSettings,
+    ) {
+        Self::watchdog_loop(move || HdpSession::upgrade_weak(&this), settings).await
+    }
+
+    #[cfg(feature = "multi-threaded")]
+    async fn execute_watchdog(
+        this: std::sync::Weak<HdpSessionInner>,
+        settings: SessionWatchdogSettings,
+    ) {
+        Self::watchdog_loop(move || HdpSession::upgrade_weak(&this), settings).await
+    }
+
+    async fn watchdog_loop(
+        upgrade: impl Fn() -> Option<HdpSession>,
+        settings: SessionWatchdogSettings,
+    ) {
+        let period = std::cmp::max(settings.stall_timeout / 4, Duration::from_millis(100));
+        let mut last_reported = None;
+
+        loop {
+            tokio::time::sleep(period).await;
+            let session = match upgrade() {
+                Some(session) => session,
+                None => return,
+            };
+
+            let stage = session.state.load(Ordering::Relaxed);
+            if stage == SessionState::Disconnected {
+                return;
+            }
+
+            let (handler_id, stalled_for, in_flight) =
+                match session.event_loop_progress.oldest_in_flight() {
+                    Some(oldest) => oldest,
+                    None => continue,
+                };
+
+            // each stalled handler is only reported once
+            if stalled_for < settings.stall_timeout || last_reported == Some(handler_id) {
+                continue;
+            }
+
+            last_reported = Some(handler_id);
+            let (pending_tickets, stages) = session
+                .state_container
+                .try_inspect(|state_container| {
+                    (
+                        format!("{:?}", state_container.pending_tickets()),
+                        format!("{:?}", state_container.stage_dump()),
+                    )
+                })
+                .unwrap_or_else(|| {
+                    let locked = "unavailable (state container locked)".to_string();
+                    (locked.clone(), locked)
+                });
+            log::error!(target: "citadel", "[Watchdog] Session is hung | cid: {:?} | session ticket: {} | is_server: {} | remote: {} | stage: {:?} | stalled for: {:?} | packet handlers in flight: {} | pending tickets: {} | stages: {}",
+                session.implicated_cid.get(),
+                session.kernel_ticket.get(),
+                session.is_server,
+                session.remote_peer,
+                stage,
+                stalled_for,
+                in_flight,
+                pending_tickets,
+                stages,
+            );
+
+            if settings.terminate_hung_sessions {
+                log::warn!(target: "citadel", "[Watchdog] Terminating hung session");
+                session.send_session_dc_signal(
+                    None,
+                    false,
+                    format!("Session terminated after making no progress for {stalled_for:?}"),
+                );
+                session.shutdown();
+                return;
+            }
+        }
+    }
+
     pub fn revfs_pull(
         &self,
         ticket: Ticket,
         v_conn: VirtualConnectionType,
         virtual_path: PathBuf,
         delete_on_pull: bool,
+        owner_cid: Option<u64>,
         security_level: SecurityLevel,
     ) -> Result<(), NetworkError> {
         self.ensure_connected(&ticket)?;
@@ -1192,6 +1409,7 @@ impl HdpSession {
                     C2S_ENCRYPTION_ONLY,
                     virtual_path,
                     delete_on_pull,
+                    owner_cid,
                 );
                 self.send_to_primary_stream(Some(ticket), packet)
             }
@@ -1210,6 +1428,7 @@ impl HdpSession {
                     target_cid,
                     virtual_path,
                     delete_on_pull,
+                    owner_cid,
                 );
                 let primary_stream = endpoint_container
                     .get_direct_p2p_primary_stream()
@@ -1230,6 +1449,7 @@ impl HdpSession {
         ticket: Ticket,
         v_conn: VirtualConnectionType,
         virtual_path: PathBuf,
+        owner_cid: Option<u64>,
         security_level: SecurityLevel,
     ) -> Result<(), NetworkError> {
         self.ensure_connected(&ticket)?;
@@ -1253,6 +1473,7 @@ impl HdpSession {
                     ts,
                     C2S_ENCRYPTION_ONLY,
                     virtual_path,
+                    owner_cid,
                 );
                 self.send_to_primary_stream(Some(ticket), packet)
             }
@@ -1270,6 +1491,7 @@ impl HdpSession {
                     ts,
                     target_cid,
                     virtual_path,
+                    owner_cid,
                 );
                 let primary_stream = endpoint_container
                     .get_direct_p2p_primary_stream()
@@ -1285,6 +1507,326 @@ impl HdpSession {
         }
     }
 
+    pub fn revfs_directory(
+        &self,
+        ticket: Ticket,
+        v_conn: VirtualConnectionType,
+        operation: ReVFSDirectoryOperation,
+        security_level: SecurityLevel,
+    ) -> Result<(), NetworkError> {
+        self.ensure_connected(&ticket)?;
+
+        let mut state_container = inner_mut_state!(self.state_container);
+        let ts = self.time_tracker.get_global_time_ns();
+
+        match v_conn {
+            VirtualConnectionType::LocalGroupServer(_implicated_cid) => {
+                let crypt_container = &mut state_container
+                    .c2s_channel_container
+                    .as_mut()
+                    .unwrap()
+                    .peer_session_crypto;
+
+                let latest_hr = crypt_container.get_hyper_ratchet(None).unwrap();
+                let packet = packet_crafter::file::craft_revfs_dir(
+                    latest_hr,
+                    security_level,
+                    ticket,
+                    ts,
+                    C2S_ENCRYPTION_ONLY,
+                    operation,
+                );
+                self.send_to_primary_stream(Some(ticket), packet)
+            }
+            VirtualConnectionType::LocalGroupPeer(_, target_cid) => {
+                let endpoint_container =
+                    state_container.get_peer_endpoint_container_mut(target_cid)?;
+                let latest_hr = endpoint_container
+                    .endpoint_crypto
+                    .get_hyper_ratchet(None)
+                    .unwrap();
+                let packet = packet_crafter::file::craft_revfs_dir(
+                    latest_hr,
+                    security_level,
+                    ticket,
+                    ts,
+                    target_cid,
+                    operation,
+                );
+                let primary_stream = endpoint_container
+                    .get_direct_p2p_primary_stream()
+                    .unwrap_or_else(|| self.to_primary_stream.as_ref().unwrap());
+                primary_stream
+                    .unbounded_send(packet)
+                    .map_err(|err| NetworkError::Generic(err.to_string()))
+            }
+
+            ty => Err(NetworkError::msg(format!(
+                "REVFS is not yet enabled for virtual connections of type {ty:?}"
+            ))),
+        }
+    }
+
+    /// Asks the receiver for the signature of the copy of the object it already holds. The transfer
+    /// begins once the signature arrives, transmitting only the blocks that changed, or, the entire
+    /// object if the receiver does not hold a copy
+    pub fn process_outbound_file_delta(
+        &self,
+        ticket: Ticket,
+        max_group_size: Option<usize>,
+        source: Box<dyn ObjectSource>,
+        virtual_target: VirtualTargetType,
+        security_level: SecurityLevel,
+    ) -> Result<(), NetworkError> {
+        self.ensure_connected(&ticket)?;
+
+        let name = source
+            .get_source_name()
+            .map_err(|err| NetworkError::msg(err.into_string()))?;
+        let mut state_container = inner_mut_state!(self.state_container);
+        state_container.send_delta_signature_request(
+            virtual_target,
+            ticket,
+            security_level,
+            name,
+            DEFAULT_DELTA_BLOCK_SIZE,
+        )?;
+
+        let _ = state_container.pending_delta_transfers.insert(
+            ticket,
+            PendingDeltaTransfer {
+                source,
+                max_group_size,
+                virtual_target,
+                security_level,
+            },
+        );
+
+        Ok(())
+    }
+
+    /// Reads the object and sends the hashes of its chunks to the receiver, which replies with
+    /// the chunks it holds in its chunk cache. The transfer then begins, transmitting only the
+    /// chunks the receiver does not hold, or, the entire object if the receiver has no cache
+    pub fn process_outbound_file_deduplicated(
+        &self,
+        ticket: Ticket,
+        max_group_size: Option<usize>,
+        source: Box<dyn ObjectSource>,
+        virtual_target: VirtualTargetType,
+        security_level: SecurityLevel,
+    ) -> Result<(), NetworkError> {
+        self.ensure_connected(&ticket)?;
+
+        let this = self.clone();
+        let task = async move {
+            // reading and hashing the object may take a while for large objects
+            let result = citadel_io::spawn_blocking(move || {
+                ChunkedObject::read(source, DEFAULT_CACHE_CHUNK_SIZE)
+            })
+            .await
+            .map_err(|err| NetworkError::Generic(err.message))
+            .and_then(|res| res.map_err(|err| NetworkError::Generic(err.into_string())))
+            .and_then(|object| {
+                let mut state_container = inner_mut_state!(this.state_container);
+                state_container.send_chunk_query(
+                    virtual_target,
+                    ticket,
+                    security_level,
+                    &object,
+                )?;
+
+                let _ = state_container.pending_deduplicated_transfers.insert(
+                    ticket,
+                    PendingDeduplicatedTransfer {
+                        object,
+                        max_group_size,
+                        virtual_target,
+                        security_level,
+                    },
+                );
+
+                Ok(())
+            });
+
+            if let Err(err) = result {
+                let _ = this.send_to_kernel(NodeResult::InternalServerError(InternalServerError {
+                    ticket_opt: Some(ticket),
+                    message: err.into_string(),
+                }));
+            }
+        };
+
+        spawn!(task);
+        Ok(())
+    }
+
+    /// Shared objects are stored on, and access-controlled by, the server
+    pub fn shared_object(
+        &self,
+        ticket: Ticket,
+        operation: SharedObjectOperation,
+        security_level: SecurityLevel,
+    ) -> Result<(), NetworkError> {
+        self.ensure_connected(&ticket)?;
+
+        let mut state_container = inner_mut_state!(self.state_container);
+        let ts = self.time_tracker.get_global_time_ns();
+        // the server only redirects the upload bearing this id, which is reserved for the next
+        // upload of the named object
+        let object_id = match &operation {
+            SharedObjectOperation::Write { name, .. } => {
+                let object_id = state_container
+                    .c2s_channel_container
+                    .as_mut()
+                    .ok_or(NetworkError::InternalError("C2S channel not loaded"))?
+                    .peer_session_crypto
+                    .get_and_increment_object_id();
+                let _ = state_container
+                    .reserved_shared_object_uploads
+                    .insert(name.clone(), object_id);
+                Some(object_id)
+            }
+            _ => None,
+        };
+
+        let latest_hr = state_container
+            .get_c2s_crypto()
+            .and_then(|crypt_container| crypt_container.get_hyper_ratchet(None))
+            .ok_or(NetworkError::InternalError("C2S channel not loaded"))?;
+
+        let packet = packet_crafter::file::craft_shared_object(
+            latest_hr,
+            security_level,
+            ticket,
+            ts,
+            C2S_ENCRYPTION_ONLY,
+            operation,
+            object_id,
+        );
+        self.send_to_primary_stream(Some(ticket), packet)
+    }
+
+    /// Adopts `capabilities` as those of this endpoint and advertises them to the adjacent node.
+    /// Until the adjacent node replies, the previously negotiated capabilities remain in use
+    pub fn renegotiate_protocol(
+        &self,
+        ticket: Ticket,
+        capabilities: ProtocolCapabilities,
+    ) -> Result<(), NetworkError> {
+        self.ensure_connected(&ticket)?;
+
+        let mut state_container = inner_mut_state!(self.state_container);
+        let ts = self.time_tracker.get_global_time_ns();
+        let latest_hr = state_container
+            .get_c2s_crypto()
+            .and_then(|crypt_container| crypt_container.get_hyper_ratchet(None))
+            .cloned()
+            .ok_or(NetworkError::InternalError("C2S channel not loaded"))?;
+        state_container.capabilities.local = capabilities;
+        std::mem::drop(state_container);
+
+        let packet = packet_crafter::capabilities::craft_capabilities(
+            &latest_hr,
+            packet_flags::cmd::aux::capabilities::ADVERTISE,
+            SecurityLevel::Standard,
+            ticket,
+            ts,
+            capabilities,
+        );
+        self.send_to_primary_stream(Some(ticket), packet)
+    }
+
+    /// Sends an authenticated probe to the server or a peer. The round-trip time is returned to
+    /// the kernel once the probe is answered
+    pub fn ping(&self, ticket: Ticket, v_conn: VirtualConnectionType) -> Result<(), NetworkError> {
+        self.ensure_connected(&ticket)?;
+
+        let mut state_container = inner_mut_state!(self.state_container);
+        let ts = self.time_tracker.get_global_time_ns();
+
+        match v_conn {
+            VirtualConnectionType::LocalGroupServer(_implicated_cid) => {
+                let latest_hr = state_container
+                    .get_c2s_crypto()
+                    .and_then(|crypt_container| crypt_container.get_hyper_ratchet(None))
+                    .ok_or(NetworkError::InternalError("C2S channel not loaded"))?;
+                let packet = packet_crafter::ping::craft_ping(
+                    latest_hr,
+                    packet_flags::cmd::aux::ping::REQUEST,
+                    SecurityLevel::Standard,
+                    ticket,
+                    ts,
+                    C2S_ENCRYPTION_ONLY,
+                    0,
+                );
+                let _ = state_container.pending_pings.insert(ticket, Instant::now());
+                std::mem::drop(state_container);
+                self.send_to_primary_stream(Some(ticket), packet)
+            }
+
+            VirtualConnectionType::LocalGroupPeer(_, target_cid) => {
+                let endpoint_container =
+                    state_container.get_peer_endpoint_container_mut(target_cid)?;
+                let latest_hr = endpoint_container
+                    .endpoint_crypto
+                    .get_hyper_ratchet(None)
+                    .unwrap();
+                let packet = packet_crafter::ping::craft_ping(
+                    latest_hr,
+                    packet_flags::cmd::aux::ping::REQUEST,
+                    SecurityLevel::Standard,
+                    ticket,
+                    ts,
+                    target_cid,
+                    0,
+                );
+                let primary_stream = endpoint_container
+                    .get_direct_p2p_primary_stream()
+                    .unwrap_or_else(|| self.to_primary_stream.as_ref().unwrap())
+                    .clone();
+                let _ = state_container.pending_pings.insert(ticket, Instant::now());
+                primary_stream
+                    .unbounded_send(packet)
+                    .map_err(|err| NetworkError::Generic(err.to_string()))
+            }
+
+            ty => Err(NetworkError::msg(format!(
+                "Pings are not yet enabled for virtual connections of type {ty:?}"
+            ))),
+        }
+    }
+
+    /// Opens the named channel `name` over the connection to the server or a peer, returning
+    /// the channel to the kernel
+    pub fn open_named_channel(
+        &self,
+        ticket: Ticket,
+        v_conn: VirtualConnectionType,
+        name: String,
+    ) -> Result<(), NetworkError> {
+        self.ensure_connected(&ticket)?;
+
+        let target_cid = match v_conn {
+            VirtualConnectionType::LocalGroupServer(_implicated_cid) => C2S_ENCRYPTION_ONLY,
+            VirtualConnectionType::LocalGroupPeer(_, target_cid) => target_cid,
+            ty => {
+                return Err(NetworkError::msg(format!(
+                    "Named channels are not yet enabled for virtual connections of type {ty:?}"
+                )))
+            }
+        };
+
+        let channel =
+            inner_mut_state!(self.state_container).open_named_channel(target_cid, name)?;
+        self.send_to_kernel(NodeResult::NamedChannelOpened(NamedChannelOpened {
+            ticket,
+            implicated_cid: v_conn.get_implicated_cid(),
+            channel,
+        }))
+        .map_err(|err| NetworkError::Generic(err.to_string()))
+    }
+
     fn ensure_connected(&self, ticket: &Ticket) -> Result<(), NetworkError> {
         if self.state.load(Ordering::Relaxed) != SessionState::Connected {
             Err(NetworkError::Generic(format!("Attempted to send a request (ticket: {ticket}) outbound, but the session is not connected")))
@@ -1323,6 +1865,27 @@ impl HdpSession {
         let (group_sender, group_sender_rx) = channel(5);
         let mut group_sender_rx = tokio_stream::wrappers::ReceiverStream::new(group_sender_rx);
         let (stop_tx, stop_rx) = tokio::sync::oneshot::channel();
+        // manifests are only computed for plaintext transfers, since RE-VFS pushes locally encrypt
+        // each group prior to transmission
+        let (manifest_tx, manifest_rx) = tokio::sync::oneshot::channel();
+        let manifest_tx = matches!(
+            transfer_type,
+            TransferType::FileTransfer
+                | TransferType::DeltaSync { .. }
+                | TransferType::Deduplicated { .. }
+        )
+        .then_some(manifest_tx);
+        // compression is offered for plaintext transfers only, since locally encrypted groups
+        // would not shrink. The scrambler waits for the receiver's answer before rendering groups
+        let compression = (matches!(
+            transfer_type,
+            TransferType::FileTransfer
+                | TransferType::DeltaSync { .. }
+                | TransferType::Deduplicated { .. }
+        ) && local_encryption_level.is_none())
+        .then_some(CompressionAlgorithm::Zstd);
+        let (compression_tx, compression_rx) =
+            compression.map(|_| tokio::sync::oneshot::channel()).unzip();
         // the above are the same for all vtarget types. Now, we need to get the proper drill and pqc
 
         let mut state_container = inner_mut_state!(this.state_container);
@@ -1330,168 +1893,197 @@ impl HdpSession {
         log::trace!(target: "citadel", "Transmit file name: {}", &file_name);
         // the key cid must be differentiated from the target cid because the target_cid needs to be zero if
         // there is no proxying. the key cid cannot be zero; if client -> server, key uses implicated cid
-        let (to_primary_stream, file_header, object_id, target_cid, key_cid, groups_needed) =
-            match virtual_target {
-                VirtualTargetType::LocalGroupServer(implicated_cid) => {
-                    // if we are sending this just to the HyperLAN server (in the case of file uploads),
-                    // then, we use this session's pqc, the cnac's latest drill, and 0 for target_cid
-                    let crypt_container = &mut state_container
-                        .c2s_channel_container
-                        .as_mut()
-                        .unwrap()
-                        .peer_session_crypto;
-                    let object_id = crypt_container.get_and_increment_object_id();
-                    let group_id_start = crypt_container.get_and_increment_group_id();
-                    let latest_hr = crypt_container.get_hyper_ratchet(None).cloned().unwrap();
-                    let static_aux_ratchet = crypt_container
-                        .toolset
-                        .get_static_auxiliary_ratchet()
-                        .clone();
-
-                    let to_primary_stream = this.to_primary_stream.clone().unwrap();
-                    let target_cid = 0;
-                    let (file_size, groups_needed, _max_bytes_per_group) = scramble_encrypt_source(
-                        source,
-                        max_group_size,
-                        object_id,
-                        group_sender,
-                        stop_rx,
-                        security_level,
-                        latest_hr.clone(),
-                        static_aux_ratchet,
-                        HDP_HEADER_BYTE_LEN,
-                        target_cid,
-                        group_id_start,
-                        transfer_type.clone(),
-                        packet_crafter::group::craft_wave_payload_packet_into,
-                    )
-                    .map_err(|err| NetworkError::Generic(err.to_string()))?;
+        let (
+            to_primary_stream,
+            file_header,
+            object_id,
+            target_cid,
+            key_cid,
+            groups_needed,
+            file_size,
+        ) = match virtual_target {
+            VirtualTargetType::LocalGroupServer(implicated_cid) => {
+                // if we are sending this just to the HyperLAN server (in the case of file uploads),
+                // then, we use this session's pqc, the cnac's latest drill, and 0 for target_cid
+                // a write to a shared object reserved the id the server expects for this upload
+                let reserved_object_id = if matches!(transfer_type, TransferType::FileTransfer) {
+                    state_container
+                        .reserved_shared_object_uploads
+                        .remove(&file_name)
+                } else {
+                    None
+                };
+                let crypt_container = &mut state_container
+                    .c2s_channel_container
+                    .as_mut()
+                    .unwrap()
+                    .peer_session_crypto;
+                let object_id = reserved_object_id
+                    .unwrap_or_else(|| crypt_container.get_and_increment_object_id());
+                let group_id_start = crypt_container.get_and_increment_group_id();
+                let (version, latest_hr) = crypt_container
+                    .pin_hyper_ratchet()
+                    .map(|(version, ratchet)| (version, ratchet.clone()))
+                    .ok_or(NetworkError::InternalError("Ratchet not loaded"))?;
+                log::trace!(target: "citadel", "Pinned c2s ratchet v{version} for object {object_id}");
+                let static_aux_ratchet = crypt_container
+                    .toolset
+                    .get_static_auxiliary_ratchet()
+                    .clone();
+
+                let to_primary_stream = this.to_primary_stream.clone().unwrap();
+                let target_cid = 0;
+                let (file_size, groups_needed, _max_bytes_per_group) = scramble_encrypt_source(
+                    source,
+                    max_group_size,
+                    object_id,
+                    group_sender,
+                    stop_rx,
+                    security_level,
+                    latest_hr.clone(),
+                    static_aux_ratchet,
+                    HDP_HEADER_BYTE_LEN,
+                    target_cid,
+                    group_id_start,
+                    transfer_type.clone(),
+                    packet_crafter::group::craft_wave_payload_packet_into,
+                    manifest_tx,
+                    compression_rx,
+                )
+                .map_err(|err| NetworkError::Generic(err.to_string()))?;
 
-                    let file_metadata = VirtualObjectMetadata {
-                        object_id,
-                        name: file_name,
-                        // TODO: update metadata using the local file handle metadata
-                        date_created: "".to_string(),
-                        author: "N/A".to_string(),
-                        plaintext_length: file_size,
-                        group_count: groups_needed,
-                        cid: implicated_cid,
-                        transfer_type,
-                    };
+                let file_metadata = VirtualObjectMetadata {
+                    object_id,
+                    name: file_name,
+                    // TODO: update metadata using the local file handle metadata
+                    date_created: "".to_string(),
+                    author: "N/A".to_string(),
+                    plaintext_length: file_size,
+                    group_count: groups_needed,
+                    cid: implicated_cid,
+                    transfer_type,
+                };
 
-                    // if 1 group, we don't need to reserve any more group IDs. If 2, then we reserve just one. 3, then 2
-                    let amt_to_reserve = groups_needed - 1;
-                    crypt_container.rolling_group_id += amt_to_reserve as u64;
-                    let file_header = packet_crafter::file::craft_file_header_packet(
-                        &latest_hr,
-                        group_id_start,
-                        ticket,
-                        security_level,
-                        virtual_target,
-                        file_metadata,
-                        timestamp,
-                        local_encryption_level,
-                    );
-                    (
-                        to_primary_stream,
-                        file_header,
-                        object_id,
-                        target_cid,
-                        implicated_cid,
-                        groups_needed,
-                    )
-                }
+                // if 1 group, we don't need to reserve any more group IDs. If 2, then we reserve just one. 3, then 2
+                let amt_to_reserve = groups_needed - 1;
+                crypt_container.rolling_group_id += amt_to_reserve as u64;
+                let file_header = packet_crafter::file::craft_file_header_packet(
+                    &latest_hr,
+                    group_id_start,
+                    ticket,
+                    security_level,
+                    virtual_target,
+                    file_metadata,
+                    timestamp,
+                    local_encryption_level,
+                    compression,
+                );
+                (
+                    to_primary_stream,
+                    file_header,
+                    object_id,
+                    target_cid,
+                    implicated_cid,
+                    groups_needed,
+                    file_size,
+                )
+            }
 
-                VirtualConnectionType::LocalGroupPeer(implicated_cid, target_cid) => {
-                    log::trace!(target: "citadel", "Sending HyperLAN peer ({}) <-> HyperLAN Peer ({})", implicated_cid, target_cid);
-                    // here, we don't use the base session's PQC. Instead, we use the vconn's pqc and
-                    let endpoint_container =
-                        state_container.get_peer_endpoint_container_mut(target_cid)?;
-
-                    let object_id = endpoint_container
-                        .endpoint_crypto
-                        .get_and_increment_object_id();
-                    // reserve group ids
-                    let start_group_id = endpoint_container
-                        .endpoint_crypto
-                        .get_and_increment_group_id();
-
-                    let latest_usable_ratchet = endpoint_container
-                        .endpoint_crypto
-                        .get_hyper_ratchet(None)
-                        .unwrap();
-
-                    let static_aux_ratchet = endpoint_container
-                        .endpoint_crypto
-                        .toolset
-                        .get_static_auxiliary_ratchet()
-                        .clone();
-
-                    let preferred_primary_stream = endpoint_container
-                        .get_direct_p2p_primary_stream()
-                        .cloned()
-                        .unwrap_or_else(|| this.to_primary_stream.clone().unwrap());
+            VirtualConnectionType::LocalGroupPeer(implicated_cid, target_cid) => {
+                log::trace!(target: "citadel", "Sending HyperLAN peer ({}) <-> HyperLAN Peer ({})", implicated_cid, target_cid);
+                // here, we don't use the base session's PQC. Instead, we use the vconn's pqc and
+                let endpoint_container =
+                    state_container.get_peer_endpoint_container_mut(target_cid)?;
 
-                    let (file_size, groups_needed, _max_bytes_per_group) = scramble_encrypt_source(
-                        source,
-                        max_group_size,
-                        object_id,
-                        group_sender,
-                        stop_rx,
-                        security_level,
-                        latest_usable_ratchet.clone(),
-                        static_aux_ratchet,
-                        HDP_HEADER_BYTE_LEN,
-                        target_cid,
-                        start_group_id,
-                        transfer_type.clone(),
-                        packet_crafter::group::craft_wave_payload_packet_into,
-                    )
-                    .map_err(|err| NetworkError::Generic(err.to_string()))?;
+                let object_id = endpoint_container
+                    .endpoint_crypto
+                    .get_and_increment_object_id();
+                // reserve group ids
+                let start_group_id = endpoint_container
+                    .endpoint_crypto
+                    .get_and_increment_group_id();
 
-                    let file_metadata = VirtualObjectMetadata {
-                        object_id,
-                        name: file_name,
-                        date_created: "".to_string(),
-                        author: "".to_string(),
-                        plaintext_length: file_size,
-                        group_count: groups_needed,
-                        cid: implicated_cid,
-                        transfer_type,
-                    };
+                let (version, latest_usable_ratchet) = endpoint_container
+                    .endpoint_crypto
+                    .pin_hyper_ratchet()
+                    .ok_or(NetworkError::InternalError("Ratchet not loaded"))?;
+                log::trace!(target: "citadel", "Pinned p2p ratchet v{version} for object {object_id}");
 
-                    let file_header = packet_crafter::file::craft_file_header_packet(
-                        latest_usable_ratchet,
-                        start_group_id,
-                        ticket,
-                        security_level,
-                        virtual_target,
-                        file_metadata,
-                        timestamp,
-                        local_encryption_level,
-                    );
+                let static_aux_ratchet = endpoint_container
+                    .endpoint_crypto
+                    .toolset
+                    .get_static_auxiliary_ratchet()
+                    .clone();
 
-                    // if 1 group, we don't need to reserve any more group IDs. If 2, then we reserve just one. 3, then 2
-                    let amt_to_reserve = groups_needed - 1;
-                    endpoint_container.endpoint_crypto.rolling_group_id += amt_to_reserve as u64;
+                let preferred_primary_stream = endpoint_container
+                    .get_direct_p2p_primary_stream()
+                    .cloned()
+                    .unwrap_or_else(|| this.to_primary_stream.clone().unwrap());
+
+                let (file_size, groups_needed, _max_bytes_per_group) = scramble_encrypt_source(
+                    source,
+                    max_group_size,
+                    object_id,
+                    group_sender,
+                    stop_rx,
+                    security_level,
+                    latest_usable_ratchet.clone(),
+                    static_aux_ratchet,
+                    HDP_HEADER_BYTE_LEN,
+                    target_cid,
+                    start_group_id,
+                    transfer_type.clone(),
+                    packet_crafter::group::craft_wave_payload_packet_into,
+                    manifest_tx,
+                    compression_rx,
+                )
+                .map_err(|err| NetworkError::Generic(err.to_string()))?;
 
-                    (
-                        preferred_primary_stream,
-                        file_header,
-                        object_id,
-                        target_cid,
-                        target_cid,
-                        groups_needed,
-                    )
-                }
+                let file_metadata = VirtualObjectMetadata {
+                    object_id,
+                    name: file_name,
+                    date_created: "".to_string(),
+                    author: "".to_string(),
+                    plaintext_length: file_size,
+                    group_count: groups_needed,
+                    cid: implicated_cid,
+                    transfer_type,
+                };
 
-                _ => {
-                    log::error!(target: "citadel", "HyperWAN functionality not yet implemented");
-                    return Err(NetworkError::InternalError(
-                        "HyperWAN functionality not yet implemented",
-                    ));
-                }
-            };
+                let file_header = packet_crafter::file::craft_file_header_packet(
+                    latest_usable_ratchet,
+                    start_group_id,
+                    ticket,
+                    security_level,
+                    virtual_target,
+                    file_metadata,
+                    timestamp,
+                    local_encryption_level,
+                    compression,
+                );
+
+                // if 1 group, we don't need to reserve any more group IDs. If 2, then we reserve just one. 3, then 2
+                let amt_to_reserve = groups_needed - 1;
+                endpoint_container.endpoint_crypto.rolling_group_id += amt_to_reserve as u64;
+
+                (
+                    preferred_primary_stream,
+                    file_header,
+                    object_id,
+                    target_cid,
+                    target_cid,
+                    groups_needed,
+                    file_size,
+                )
+            }
+
+            _ => {
+                log::error!(target: "citadel", "HyperWAN functionality not yet implemented");
+                return Err(NetworkError::InternalError(
+                    "HyperWAN functionality not yet implemented",
+                ));
+            }
+        };
 
         // now that the async cryptscrambler tasks have been spawned on the threadpool, we need to also
         // spawn tasks that read the [GroupSenders] from there. We also need to store an [OutboundFileMetadataTransmitter]
@@ -1510,12 +2102,18 @@ impl HdpSession {
         let mut next_gs_alerter_rx =
             tokio_stream::wrappers::UnboundedReceiverStream::new(next_gs_alerter_rx);
         let (start, start_rx) = tokio::sync::oneshot::channel();
+        let (pause_tx, mut pause_rx) = tokio::sync::watch::channel(false);
         let outbound_file_transfer_container = OutboundFileTransfer {
             stop_tx: Some(stop_tx),
             object_id,
             ticket,
             next_gs_alerter: next_gs_alerter.clone(),
             start: Some(start),
+            pause_tx,
+            compression_tx,
+            // large objects are striped across the UDP stream, if one exists by the time groups are sent
+            multipath: (file_size >= MIN_MULTIPATH_OBJECT_LEN).then(MultipathCongestion::default),
+            progress: TransferProgressTracker::new(groups_needed, file_size as u64),
         };
         let file_key = FileKey::new(key_cid, object_id);
         let _ = state_container
@@ -1544,6 +2142,22 @@ impl HdpSession {
 
             log::trace!(target: "citadel", "Outbound file transfer async subroutine signalled to begin!");
 
+            // the manifest is computed before the first group is scrambled, so it is always sent
+            // ahead of the first group
+            if let Ok(Some(manifest)) = manifest_rx.await {
+                let state_container = inner_state!(this.state_container);
+                if let Err(err) = state_container.send_transfer_manifest(
+                    virtual_target,
+                    ticket,
+                    security_level,
+                    object_id,
+                    manifest,
+                ) {
+                    log::error!(target: "citadel", "Unable to send transfer manifest: {:?}", err);
+                    return;
+                }
+            }
+
             // TODO: planning/overhaul of file transmission process
             // By now, the file container has been created remotely and locally
             // We have been signalled to begin polling the group sender
@@ -1558,6 +2172,20 @@ impl HdpSession {
             while let Some(sender) = group_sender_rx.next().await {
                 match sender {
                     Ok(sender) => {
+                        // do not send the next group while paused. If the pause signal sender
+                        // drops, the transfer was cancelled
+                        loop {
+                            let is_paused = *pause_rx.borrow();
+                            if !is_paused {
+                                break;
+                            }
+
+                            if pause_rx.changed().await.is_err() {
+                                log::trace!(target: "citadel", "Outbound transfer {file_key:?} cancelled while paused");
+                                return;
+                            }
+                        }
+
                         let (group_id, key) = {
                             // construct the OutboundTransmitters
                             let sess = this;
@@ -1567,6 +2195,10 @@ impl HdpSession {
                             }
 
                             let mut state_container = inner_mut_state!(sess.state_container);
+                            if !state_container.outbound_files.contains_key(&file_key) {
+                                log::trace!(target: "citadel", "Outbound transfer {file_key:?} cancelled");
+                                return;
+                            }
 
                             let proper_latest_hyper_ratchet = match virtual_target {
                                 VirtualConnectionType::LocalGroupServer(_) => state_container
@@ -1756,12 +2388,25 @@ impl HdpSession {
                             packet,
                             target,
                             security_level,
+                            secrecy_mode,
+                            expires_at,
                         } => {
+                            if expires_at.map(|t| t <= Instant::now()).unwrap_or(false) {
+                                state_container.drop_expired_message(
+                                    target.get_target_cid(),
+                                    ticket,
+                                    &packet,
+                                );
+                                continue;
+                            }
+
                             if let Err(err) = state_container.process_outbound_message(
                                 ticket,
                                 packet,
                                 target,
                                 security_level,
+                                secrecy_mode,
+                                expires_at,
                                 false,
                             ) {
                                 to_kernel_tx
@@ -1775,6 +2420,44 @@ impl HdpSession {
                             }
                         }
 
+                        SessionRequest::CloseChannel {
+                            ticket,
+                            target,
+                            security_level,
+                            on_closed,
+                        } => {
+                            let _ = state_container
+                                .pending_channel_closes
+                                .insert((target.get_target_cid(), ticket), on_closed);
+                            if let Err(err) = state_container.process_outbound_message(
+                                ticket,
+                                SecureProtocolPacket::from(&[] as &[u8]),
+                                target,
+                                security_level,
+                                None,
+                                None,
+                                false,
+                            ) {
+                                let _ = state_container
+                                    .pending_channel_closes
+                                    .remove(&(target.get_target_cid(), ticket));
+                                to_kernel_tx
+                                    .unbounded_send(NodeResult::InternalServerError(
+                                        InternalServerError {
+                                            ticket_opt: Some(ticket),
+                                            message: err.into_string(),
+                                        },
+                                    ))
+                                    .map_err(|err| NetworkError::Generic(err.to_string()))?
+                            }
+                        }
+
+                        SessionRequest::AdvertiseWindow { target, update } => {
+                            if let Err(err) = state_container.send_window_update(target, update) {
+                                log::warn!(target: "citadel", "Unable to advertise the receive window to {target:?}: {err:?}");
+                            }
+                        }
+
                         SessionRequest::Group { ticket, broadcast } => {
                             if let Err(err) = state_container
                                 .process_outbound_broadcast_command(ticket, &broadcast)
@@ -1832,6 +2515,31 @@ impl HdpSession {
                     PeerSignal::DisconnectUDP(v_conn)
                 }
 
+                PeerSignal::Disconnect(conn, None) => {
+                    // the server does not acknowledge the disconnect, hence the local virtual connection is
+                    // removed here once the packets enqueued for the peer are sent
+                    let peer_cid = conn.get_original_target_cid();
+                    let state_container_ref = this.state_container.clone();
+                    spawn!(async move {
+                        loop {
+                            let drained = inner_state!(state_container_ref)
+                                .enqueued_packets
+                                .get(&peer_cid)
+                                .map(|queue| queue.is_empty())
+                                .unwrap_or(true);
+                            if drained {
+                                break;
+                            }
+
+                            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                        }
+
+                        let _ = inner_mut_state!(state_container_ref)
+                            .remove_virtual_connection(peer_cid);
+                    });
+                    PeerSignal::Disconnect(conn, None)
+                }
+
                 PeerSignal::PostConnect(a, b, None, d, e) => {
                     if state_container
                         .outgoing_peer_connect_attempts
@@ -1843,10 +2551,62 @@ impl HdpSession {
                     // in case the ticket gets mapped during simultaneous_connect, store locally
                     let _ = state_container
                         .outgoing_peer_connect_attempts
-                        .insert(a.get_original_target_cid(), ticket);
+                        .insert(a.get_original_target_cid(), (ticket, d));
                     PeerSignal::PostConnect(a, b, None, d, e)
                 }
 
+                PeerSignal::EnableUdp(conn) => {
+                    if state_container.udp_primary_outbound_tx.is_some() {
+                        return Err(NetworkError::msg("UDP is already enabled for this session"));
+                    }
+
+                    let implicated_cid = conn.get_implicated_cid();
+                    let hyper_ratchet = state_container
+                        .get_c2s_crypto()
+                        .and_then(|crypt_container| crypt_container.get_hyper_ratchet(None))
+                        .cloned()
+                        .ok_or(NetworkError::InternalError("C2S channel not loaded"))?;
+                    // the pipe is created before the server may begin traversing NATs
+                    let stream = ReliableOrderedCompatStream::new(
+                        to_primary_stream.clone(),
+                        &mut state_container,
+                        C2S_ENCRYPTION_ONLY,
+                        hyper_ratchet.clone(),
+                        security_level,
+                    );
+                    let (ready_tx, ready_rx) = tokio::sync::oneshot::channel();
+                    state_container.deferred_udp_ready_tx = Some(ready_tx);
+
+                    let this_weak = this.as_weak();
+                    spawn!(async move {
+                        // if the server declines, the sender is dropped alongside the session
+                        if ready_rx.await.is_ok() {
+                            if let Some(session) = HdpSession::upgrade_weak(&this_weak) {
+                                if let Err(err) = traverse_deferred_udp(
+                                    session.clone(),
+                                    RelativeNodeType::Initiator,
+                                    stream,
+                                    hyper_ratchet,
+                                    implicated_cid,
+                                    ticket,
+                                )
+                                .await
+                                {
+                                    log::warn!(target: "citadel", "Unable to enable UDP: {:?}", err);
+                                    let _ = session.send_to_kernel(
+                                        NodeResult::InternalServerError(InternalServerError {
+                                            ticket_opt: Some(ticket),
+                                            message: err.into_string(),
+                                        }),
+                                    );
+                                }
+                            }
+                        }
+                    });
+
+                    PeerSignal::EnableUdp(conn)
+                }
+
                 n => n,
             };
 
@@ -1877,24 +2637,152 @@ impl HdpSession {
         }
     }
 
+    /// Runs the UDP subsystem until the UDP channel closes. Should a raw socket fail irrecoverably,
+    /// a new socket is bound in its place, resuming the same channel. Packets are encrypted by the
+    /// same ratchet under the unordered key domain regardless of the socket, hence the nonce and
+    /// anti-replay state carry over. The first packet sent through the new socket is a keep-alive,
+    /// allowing the adjacent node to migrate onto the new address once it authenticates.
+    ///
+    /// For raw UDP, `keep_alive` tracks the interval after which an idle path sends a keep-alive.
+    /// Once the keep-alives of a hole-punched path go unanswered, or the adjacent node reports the
+    /// same, the listener and sender pause while a fresh hole is punched over the primary stream.
+    /// Packets queued meanwhile are sent through the new socket, after a keep-alive
+    async fn udp_subsystem(
+        this: HdpSession,
+        mut udp_conn: UdpSplittableTypes,
+        outbound_sender_rx: UnboundedReceiver<(u8, BytesMut)>,
+        outbound_sender_tx: UnboundedSender<(u8, BytesMut)>,
+        keep_alive: Option<citadel_io::Mutex<KeepAliveTuner>>,
+        accessor: EndpointCryptoAccessor,
+    ) -> Result<(), NetworkError> {
+        let peer_addr = udp_conn.shared_peer_addr();
+        let repuncher = udp_conn.take_repuncher();
+        let mut outbound = tokio_stream::wrappers::UnboundedReceiverStream::new(outbound_sender_rx);
+        let mut rebinds = 0;
+        let mut probe = false;
+
+        loop {
+            let local_bind_addr = udp_conn.local_addr()?;
+            let bound_at = Instant::now();
+            // unlike TCP, we will not use [LengthDelimitedCodec] because there is no guarantee that packets
+            // will arrive in order
+            let (writer, reader) = udp_conn.split();
+
+            let listener = Self::listen_udp_port(
+                this.clone(),
+                local_bind_addr.port(),
+                reader,
+                &accessor,
+                peer_addr.as_ref(),
+                &outbound_sender_tx,
+                keep_alive.as_ref(),
+            );
+            let sender = Self::udp_outbound_sender(
+                &mut outbound,
+                writer,
+                &accessor,
+                probe,
+                keep_alive.as_ref(),
+            );
+
+            let repunch_requested = async {
+                if let Some(repuncher) = repuncher.as_ref() {
+                    match repuncher.recv_request().await {
+                        Ok(()) => return,
+                        Err(err) => {
+                            log::warn!(target: "citadel", "Unable to receive UDP re-punch requests: {err:?}")
+                        }
+                    }
+                }
+                futures::future::pending::<()>().await
+            };
+
+            let res = tokio::select! {
+                res0 = listener => res0.map(|_| UdpPathExit::Closed),
+                res1 = sender => res1,
+                _ = repunch_requested => Ok(UdpPathExit::BindingDead { requested_by_peer: true })
+            };
+
+            let (err, shared_peer_addr) = match (res, repuncher.as_ref(), peer_addr.as_ref()) {
+                (
+                    Ok(UdpPathExit::BindingDead { requested_by_peer }),
+                    Some(repuncher),
+                    Some(peer_addr),
+                ) => {
+                    // the listener and sender of the dead socket stopped above, thus nothing reads it
+                    log::warn!(target: "citadel", "NAT binding of the UDP path on {local_bind_addr} died (reported by peer: {requested_by_peer}). Re-punching");
+                    let socket = repuncher
+                        .repunch(requested_by_peer)
+                        .await
+                        .map_err(|err| NetworkError::Generic(err.to_string()))?;
+                    udp_conn = UdpSplittableTypes::Raw(RawUdpSocketConnector::repunched(
+                        socket,
+                        peer_addr.clone(),
+                    ));
+                    if let Some(keep_alive) = keep_alive.as_ref() {
+                        keep_alive.lock().on_repunch();
+                    }
+                    probe = true;
+                    continue;
+                }
+
+                (Err(NetworkError::SocketError(err)), _, Some(peer_addr)) => {
+                    (err, peer_addr.clone())
+                }
+
+                (res, ..) => return res.map(|_| ()),
+            };
+
+            // a socket that outlived a keep-alive interval was healthy, hence its failure is new
+            if bound_at.elapsed() >= FIREWALL_KEEP_ALIVE_UDP {
+                rebinds = 0;
+            }
+
+            if rebinds == MAX_UDP_REBINDS {
+                log::error!(target: "citadel", "UDP socket on {local_bind_addr} failed: {err}. Giving up after {rebinds} rebinds");
+                return Err(NetworkError::SocketError(err));
+            }
+
+            rebinds += 1;
+            log::warn!(target: "citadel", "UDP socket on {local_bind_addr} failed: {err}. Rebinding ({rebinds}/{MAX_UDP_REBINDS})");
+            tokio::time::sleep(UDP_REBIND_BACKOFF * rebinds).await;
+            udp_conn = UdpSplittableTypes::Raw(
+                RawUdpSocketConnector::rebind(local_bind_addr, shared_peer_addr).await?,
+            );
+            probe = true;
+        }
+    }
+
     async fn listen_udp_port<S: UdpStream>(
         this: HdpSession,
-        _hole_punched_addr_ip: IpAddr,
         local_port: u16,
         mut stream: S,
-        ref peer_session_accessor: EndpointCryptoAccessor,
+        peer_session_accessor: &EndpointCryptoAccessor,
+        peer_addr: Option<&DualCell<SocketAddr>>,
+        outbound_sender_tx: &UnboundedSender<(u8, BytesMut)>,
+        keep_alive: Option<&citadel_io::Mutex<KeepAliveTuner>>,
     ) -> Result<(), NetworkError> {
         while let Some(res) = stream.next().await {
             match res {
                 Ok((packet, remote_peer)) => {
                     log::trace!(target: "citadel", "packet received on waveport {} has {} bytes (src: {:?})", local_port, packet.len(), &remote_peer);
                     let packet = HdpPacket::new_recv(packet, remote_peer, local_port);
-                    this.process_inbound_packet_udp(packet, peer_session_accessor)?;
+                    this.process_inbound_packet_udp(
+                        packet,
+                        peer_session_accessor,
+                        peer_addr,
+                        outbound_sender_tx,
+                        keep_alive,
+                    )?;
+                }
+
+                Err(err) if is_transient_udp_error(&err) => {
+                    log::trace!(target: "citadel", "Transient UDP stream error: {:?}", err);
                 }
 
                 Err(err) => {
                     log::warn!(target: "citadel", "UDP Stream error: {:#?}", err);
-                    break;
+                    return Err(NetworkError::SocketError(err.to_string()));
                 }
             }
         }
@@ -1904,17 +2792,47 @@ impl HdpSession {
         Ok(())
     }
 
-    async fn udp_outbound_sender<S: SinkExt<Bytes> + Unpin>(
-        receiver: UnboundedReceiver<(u8, BytesMut)>,
-        hole_punched_addr: TargettedSocketAddr,
+    async fn udp_outbound_sender<S: Sink<Bytes, Error = NetworkError> + Unpin>(
+        receiver: &mut tokio_stream::wrappers::UnboundedReceiverStream<(u8, BytesMut)>,
         mut sink: S,
-        peer_session_accessor: EndpointCryptoAccessor,
-    ) -> Result<(), NetworkError> {
-        let mut receiver = tokio_stream::wrappers::UnboundedReceiverStream::new(receiver);
+        peer_session_accessor: &EndpointCryptoAccessor,
+        probe: bool,
+        keep_alive: Option<&citadel_io::Mutex<KeepAliveTuner>>,
+    ) -> Result<UdpPathExit, NetworkError> {
         let target_cid = peer_session_accessor.get_target_cid();
+        let probe = probe.then(|| {
+            (
+                packet_flags::cmd::aux::udp::KEEP_ALIVE,
+                BytesMut::from(&KEEP_ALIVE[..]),
+            )
+        });
+        let mut outbound = futures::stream::iter(probe).chain(receiver);
+        let mut last_sent = Instant::now();
+
+        loop {
+            let next = if let Some(keep_alive) = keep_alive {
+                let idle_deadline = last_sent + keep_alive.lock().interval();
+                tokio::select! {
+                    next = outbound.next() => next,
+                    _ = tokio::time::sleep_until(idle_deadline) => {
+                        let mut keep_alive = keep_alive.lock();
+                        let cmd_aux = keep_alive.on_idle();
+                        if keep_alive.binding_dead() {
+                            return Ok(UdpPathExit::BindingDead { requested_by_peer: false });
+                        }
+                        Some((cmd_aux, BytesMut::from(&KEEP_ALIVE[..])))
+                    }
+                }
+            } else {
+                outbound.next().await
+            };
+
+            let (cmd_aux, packet) = if let Some(next) = next {
+                next
+            } else {
+                break;
+            };
 
-        while let Some((cmd_aux, packet)) = receiver.next().await {
-            let send_addr = hole_punched_addr.send_address;
             let packet = peer_session_accessor.borrow_hr(None, |hr, _| {
                 packet_crafter::udp::craft_udp_packet(
                     hr,
@@ -1924,34 +2842,72 @@ impl HdpSession {
                     SecurityLevel::Standard,
                 )
             })?;
-            log::trace!(target: "citadel", "About to send packet w/len {} | Dest: {:?}", packet.len(), &send_addr);
-            sink.send(packet.freeze()).await.map_err(|_| {
-                NetworkError::InternalError("UDP sink unable to receive outbound requests")
-            })?;
+            log::trace!(target: "citadel", "About to send packet w/len {}", packet.len());
+            sink.send(packet.freeze())
+                .await
+                .map_err(|err| NetworkError::SocketError(err.into_string()))?;
+            last_sent = Instant::now();
         }
 
         log::trace!(target: "citadel", "Outbound wave sender ending");
 
-        Ok(())
+        Ok(UdpPathExit::Closed)
     }
 
     pub fn process_inbound_packet_udp(
         &self,
         packet: HdpPacket,
         accessor: &EndpointCryptoAccessor,
+        peer_addr: Option<&DualCell<SocketAddr>>,
+        outbound_sender_tx: &UnboundedSender<(u8, BytesMut)>,
+        keep_alive: Option<&citadel_io::Mutex<KeepAliveTuner>>,
     ) -> Result<(), NetworkError> {
         if packet.get_length() < HDP_HEADER_BYTE_LEN {
             return Ok(());
         }
 
         if let Some((header, _)) = packet.parse() {
-            // we only process streaming packets
-            if header.cmd_aux != packet_flags::cmd::aux::udp::STREAM {
-                // discard any keep alives
+            // we only process streaming packets, and group payloads striped across the UDP stream
+            let is_stripe = header.cmd_aux == packet_flags::cmd::aux::udp::GROUP_STRIPE;
+            let hr_version = header.drill_version.get();
+            if header.cmd_aux == packet_flags::cmd::aux::udp::KEEP_ALIVE
+                || header.cmd_aux == packet_flags::cmd::aux::udp::KEEP_ALIVE_PROBE
+            {
+                // keep alives are otherwise discarded, yet may announce a rebound socket
+                let is_probe = header.cmd_aux == packet_flags::cmd::aux::udp::KEEP_ALIVE_PROBE;
+                if let Some(peer_addr) = peer_addr {
+                    let preserved = packet_processor::udp_packet::process_udp_keep_alive(
+                        packet, hr_version, accessor, peer_addr,
+                    );
+
+                    if let (true, Some(preserved)) = (is_probe, preserved) {
+                        // tell the prober whether its NAT mapping survived the idle interval
+                        let _ = outbound_sender_tx.unbounded_send((
+                            packet_flags::cmd::aux::udp::KEEP_ALIVE_ACK,
+                            BytesMut::from(&[preserved as u8][..]),
+                        ));
+                    }
+                }
+                return Ok(());
+            }
+
+            if header.cmd_aux == packet_flags::cmd::aux::udp::KEEP_ALIVE_ACK {
+                if let Some(keep_alive) = keep_alive {
+                    if let Some(preserved) =
+                        packet_processor::udp_packet::process_udp_keep_alive_ack(
+                            packet, hr_version, accessor,
+                        )
+                    {
+                        keep_alive.lock().on_ack(preserved);
+                    }
+                }
+                return Ok(());
+            }
+
+            if header.cmd_aux != packet_flags::cmd::aux::udp::STREAM && !is_stripe {
                 return Ok(());
             }
 
-            let hr_version = header.drill_version.get();
             let mut endpoint_cid_info = None;
             match check_proxy(
                 self.implicated_cid.get(),
@@ -1965,9 +2921,17 @@ impl HdpSession {
                 packet,
             ) {
                 Some(packet) => {
-                    match packet_processor::udp_packet::process_udp_packet(
-                        self, packet, hr_version, accessor,
-                    ) {
+                    let res = if is_stripe {
+                        packet_processor::udp_packet::process_udp_stripe_packet(
+                            self, packet, hr_version, accessor,
+                        )
+                    } else {
+                        packet_processor::udp_packet::process_udp_packet(
+                            self, packet, hr_version, accessor,
+                        )
+                    };
+
+                    match res {
                         Ok(PrimaryProcessorResult::Void) => Ok(()),
 
                         Ok(PrimaryProcessorResult::EndSession(err)) => {
@@ -2199,9 +3163,25 @@ pub enum SessionRequest {
         packet: SecureProtocolPacket,
         target: VirtualTargetType,
         security_level: SecurityLevel,
+        /// Overrides the default secrecy mode of the channel for this message only
+        secrecy_mode: Option<SecrecyMode>,
+        /// Once passed, the message is dropped instead of sent
+        expires_at: Option<Instant>,
     },
     Group {
         ticket: Ticket,
         broadcast: GroupBroadcast,
     },
+    /// Grants the sender of the ordered channel to `target` more room
+    AdvertiseWindow {
+        target: VirtualTargetType,
+        update: WindowUpdate,
+    },
+    /// Sends the message that closes a channel behind all messages sent prior
+    CloseChannel {
+        ticket: Ticket,
+        target: VirtualTargetType,
+        security_level: SecurityLevel,
+        on_closed: tokio::sync::oneshot::Sender<()>,
+    },
 }
@@ -10,23 +10,48 @@ use citadel_crypt::prelude::SecurityLevel;
 use citadel_crypt::stacked_ratchet::StackedRatchet;
 use citadel_user::account_manager::AccountManager;
 use citadel_user::auth::proposed_credentials::ProposedCredentials;
-use citadel_user::prelude::ConnectProtocol;
+use citadel_user::backend::utils::{ReVFSDirectoryOperation, SharedObjectOperation};
+use citadel_user::prelude::{ConnectProtocol, UserIdentifier};
 use citadel_wire::hypernode_type::NodeType;
 use citadel_wire::nat_identification::NatType;
+use citadel_wire::server_key_pinning::ServerKeyPinning;
+use citadel_wire::udp_traversal::linear::encrypted_config_container::HolePunchConfigContainer;
 use netbeam::time_tracker::TimeTracker;
 
 use crate::auth::AuthenticationRequest;
-use crate::constants::{DO_CONNECT_EXPIRE_TIME_MS, KEEP_ALIVE_TIMEOUT_NS, UDP_MODE};
+use crate::constants::{
+    DO_CONNECT_EXPIRE_TIME_MS, KEEP_ALIVE_TIMEOUT_NS, SESSION_MAP_SHARDS, UDP_MODE,
+};
 use crate::error::NetworkError;
 use crate::kernel::RuntimeFuture;
 use crate::macros::SyncContextRequirements;
 use crate::proto::endpoint_crypto_accessor::EndpointCryptoAccessor;
+use crate::proto::misc::abuse_detection::{AbuseDetector, AbuseMonitor};
+use crate::proto::misc::account_hook::{self, AccountEvent, AccountEventKind, AccountHook};
+use crate::proto::misc::connect_timings::ConnectTimer;
+use crate::proto::misc::crypto_offload::{CryptoOffload, CryptoOffloadMetrics};
+use crate::proto::misc::deduplication::{DeduplicationCounters, DeduplicationMetrics};
+use crate::proto::misc::handshake_failures::{
+    HandshakeFailureCounts, HandshakeFailureReason, HandshakeFailureTracker,
+};
+use crate::proto::misc::legal_hold::LegalHold;
+use crate::proto::misc::multipath::{MultipathCounters, MultipathMetrics};
 use crate::proto::misc::net::GenericNetworkStream;
+use crate::proto::misc::packet_filter::PacketFilter;
+use crate::proto::misc::processing_budget::ProcessingBudget;
+use crate::proto::misc::protocol_capabilities::ProtocolCapabilities;
+use crate::proto::misc::provisional_reaper::{ProvisionalReaper, ReapedSessionCounts};
+use crate::proto::misc::relay::{RelayLedger, RelayUsage};
+use crate::proto::misc::resource_counters::{ResourceCounters, SessionLifecycle};
+use crate::proto::misc::session_affinity::SessionAffinity;
 use crate::proto::misc::session_security_settings::SessionSecuritySettings;
+use crate::proto::misc::sharded_map::ShardedMap;
 use crate::proto::misc::underlying_proto::ServerUnderlyingProtocol;
 use crate::proto::node::{ConnectMode, HdpServer};
-use crate::proto::node_result::NodeResult;
-use crate::proto::outbound_sender::{unbounded, UnboundedReceiver, UnboundedSender};
+use crate::proto::node_result::{HandshakeFailed, NodeResult};
+use crate::proto::outbound_sender::{
+    unbounded, KernelEventSender, UnboundedReceiver, UnboundedSender,
+};
 use crate::proto::packet_crafter::peer_cmd::C2S_ENCRYPTION_ONLY;
 use crate::proto::packet_processor::includes::{Duration, Instant};
 use crate::proto::packet_processor::peer::group_broadcast::{
@@ -35,14 +60,17 @@ use crate::proto::packet_processor::peer::group_broadcast::{
 use crate::proto::packet_processor::PrimaryProcessorResult;
 use crate::proto::peer::message_group::{MessageGroupKey, MessageGroupOptions};
 use crate::proto::peer::peer_layer::{
-    HyperNodePeerLayer, HyperNodePeerLayerInner, MailboxTransfer, PeerConnectionType, PeerResponse,
-    PeerSignal, UdpMode,
+    HyperNodePeerLayer, HyperNodePeerLayerInner, MailboxTransfer, PeerConnectionType,
+    PeerListDelta, PeerResponse, PeerSignal, UdpMode,
 };
+use crate::proto::peer::posting_store;
+use crate::proto::peer::snapshot;
 use crate::proto::remote::{NodeRemote, Ticket};
 use crate::proto::session::{
     ClientOnlySessionInitSettings, HdpSession, HdpSessionInitMode, SessionInitParams,
 };
 use crate::proto::state_container::{VirtualConnectionType, VirtualTargetType};
+use citadel_crypt::handshake_challenge::ChallengeIssuer;
 use citadel_crypt::misc::TransferType;
 use citadel_crypt::streaming_crypt_scrambler::ObjectSource;
 use citadel_wire::exports::tokio_rustls::rustls;
@@ -55,7 +83,8 @@ define_outer_struct_wrapper!(HdpSessionManager, HdpSessionManagerInner);
 /// Used for handling stateful connections between two peer
 pub struct HdpSessionManagerInner {
     local_node_type: NodeType,
-    sessions: HashMap<u64, (Sender<()>, HdpSession)>,
+    // sharded, such that routing lookups and session insertions only contend within a shard
+    sessions: ShardedMap<(Sender<()>, HdpSession)>,
     account_manager: AccountManager,
     pub(crate) hypernode_peer_layer: HyperNodePeerLayer,
     server_remote: Option<NodeRemote>,
@@ -64,26 +93,80 @@ pub struct HdpSessionManagerInner {
     /// in the state of NeedsRegister. Once they leave that state, they are eventually polled
     /// by the [HdpSessionManager] and thereafter placed inside an appropriate session
     provisional_connections: HashMap<SocketAddr, (Instant, Sender<()>, HdpSession)>,
-    kernel_tx: UnboundedSender<NodeResult>,
+    provisional_reaper: Arc<ProvisionalReaper>,
+    handshake_failures: HandshakeFailureTracker,
+    session_lifecycle: Arc<SessionLifecycle>,
+    processing_budget: Arc<ProcessingBudget>,
+    crypto_offload: Arc<CryptoOffload>,
+    multipath_counters: Arc<MultipathCounters>,
+    deduplication_counters: Arc<DeduplicationCounters>,
+    relay_ledger: Option<Arc<RelayLedger>>,
+    legal_hold: Option<Arc<LegalHold>>,
+    packet_filter: Option<Arc<dyn PacketFilter>>,
+    account_hook: Option<Arc<dyn AccountHook>>,
+    abuse_monitor: Option<Arc<AbuseMonitor>>,
+    server_key_pinning: Option<ServerKeyPinning>,
+    cert_bootstrap_code: Option<String>,
+    handshake_challenge: Option<Arc<ChallengeIssuer>>,
+    session_affinity: Option<Arc<SessionAffinity>>,
+    kernel_tx: KernelEventSender,
+    // clients subscribed to peer list deltas, mapped to the ticket and security level of their subscription
+    peer_list_subscribers: HashMap<u64, (Ticket, SecurityLevel)>,
     time_tracker: TimeTracker,
     clean_shutdown_tracker_tx: UnboundedSender<()>,
     clean_shutdown_tracker: Option<UnboundedReceiver<()>>,
     client_config: Arc<rustls::ClientConfig>,
     stun_servers: Option<Vec<String>>,
+    hole_punch_settings: Option<HolePunchConfigContainer>,
 }
 
 impl HdpSessionManager {
     /// Creates a new [SessionManager] which handles individual connections
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         local_node_type: NodeType,
-        kernel_tx: UnboundedSender<NodeResult>,
+        kernel_tx: KernelEventSender,
         account_manager: AccountManager,
         time_tracker: TimeTracker,
         client_config: Arc<rustls::ClientConfig>,
         stun_servers: Option<Vec<String>>,
+        hole_punch_settings: Option<HolePunchConfigContainer>,
+        packet_filter: Option<Arc<dyn PacketFilter>>,
+        account_hook: Option<Arc<dyn AccountHook>>,
+        abuse_detector: Option<Arc<dyn AbuseDetector>>,
+        server_key_pinning: Option<ServerKeyPinning>,
+        cert_bootstrap_code: Option<String>,
     ) -> Self {
         let incoming_cxn_count = 0;
         let (clean_shutdown_tracker_tx, clean_shutdown_tracker_rx) = unbounded();
+        let processing_budget = Arc::new(ProcessingBudget::new(
+            &account_manager.get_misc_settings().packet_processing,
+        ));
+        let crypto_offload = Arc::new(CryptoOffload::new(
+            account_manager.get_misc_settings().crypto_offload.as_ref(),
+        ));
+        let relay_ledger = account_manager
+            .get_misc_settings()
+            .relay
+            .map(|settings| Arc::new(RelayLedger::new(settings)));
+        let legal_hold = account_manager
+            .get_misc_settings()
+            .legal_hold
+            .as_ref()
+            .map(|settings| Arc::new(LegalHold::new(settings)));
+        let handshake_challenge = account_manager
+            .get_misc_settings()
+            .handshake_challenge
+            .map(|settings| Arc::new(ChallengeIssuer::new(settings.difficulty, settings.epoch)));
+        let session_affinity = account_manager
+            .get_misc_settings()
+            .session_affinity
+            .as_ref()
+            .map(|settings| Arc::new(SessionAffinity::new(settings)));
+        // only servers sample the traffic of their clients
+        let abuse_monitor = abuse_detector
+            .filter(|_| local_node_type.is_server())
+            .map(|detector| Arc::new(AbuseMonitor::new(detector)));
         let inner = HdpSessionManagerInner {
             clean_shutdown_tracker_tx,
             clean_shutdown_tracker: Some(clean_shutdown_tracker_rx),
@@ -92,14 +175,32 @@ impl HdpSessionManager {
             ),
             server_remote: None,
             local_node_type,
-            sessions: HashMap::new(),
+            sessions: ShardedMap::new(SESSION_MAP_SHARDS),
             incoming_cxn_count,
             account_manager,
             provisional_connections: HashMap::new(),
+            provisional_reaper: Arc::new(ProvisionalReaper::default()),
+            handshake_failures: HandshakeFailureTracker::default(),
+            session_lifecycle: Arc::new(SessionLifecycle::default()),
+            processing_budget,
+            crypto_offload,
+            multipath_counters: Arc::new(MultipathCounters::default()),
+            deduplication_counters: Arc::new(DeduplicationCounters::default()),
+            relay_ledger,
+            legal_hold,
+            packet_filter,
+            account_hook,
+            abuse_monitor,
+            server_key_pinning,
+            cert_bootstrap_code,
+            handshake_challenge,
+            session_affinity,
             kernel_tx,
+            peer_list_subscribers: HashMap::new(),
             time_tracker,
             client_config,
             stun_servers,
+            hole_punch_settings,
         };
 
         Self::from(inner)
@@ -119,6 +220,65 @@ impl HdpSessionManager {
         this.sessions.contains_key(&cid)
     }
 
+    /// Returns the issuer of handshake challenges, if this node demands them
+    pub(crate) fn handshake_challenge(&self) -> Option<Arc<ChallengeIssuer>> {
+        inner!(self).handshake_challenge.clone()
+    }
+
+    /// Notifies the account hook, if any, of a registration or deregistration
+    pub(crate) fn notify_account_hook(&self, kind: AccountEventKind, event: AccountEvent) {
+        if let Some(hook) = inner!(self).account_hook.clone() {
+            account_hook::dispatch(hook, kind, event);
+        }
+    }
+
+    /// Returns the affinity between clients and the nodes of the deployment, if this node is load-balanced
+    pub(crate) fn session_affinity(&self) -> Option<Arc<SessionAffinity>> {
+        inner!(self).session_affinity.clone()
+    }
+
+    /// Returns the budget of the key exchanges computed on behalf of unauthenticated handshakes
+    pub(crate) fn crypto_offload(&self) -> Arc<CryptoOffload> {
+        inner!(self).crypto_offload.clone()
+    }
+
+    /// Returns a snapshot of the key exchanges computed since the node started
+    pub fn get_crypto_offload_metrics(&self) -> CryptoOffloadMetrics {
+        inner!(self).crypto_offload.metrics()
+    }
+
+    /// Returns the counters of the multipath transfers sent by every session of the node
+    pub(crate) fn multipath_counters(&self) -> Arc<MultipathCounters> {
+        inner!(self).multipath_counters.clone()
+    }
+
+    /// Returns how the packets of the multipath transfers completed since the node started were
+    /// split across paths
+    pub fn get_multipath_metrics(&self) -> MultipathMetrics {
+        inner!(self).multipath_counters.metrics()
+    }
+
+    /// Returns the counters of the deduplicated transfers sent by every session of the node
+    pub(crate) fn deduplication_counters(&self) -> Arc<DeduplicationCounters> {
+        inner!(self).deduplication_counters.clone()
+    }
+
+    /// Returns how many chunks of the deduplicated transfers sent since the node started were
+    /// already cached by their receivers
+    pub fn get_deduplication_metrics(&self) -> DeduplicationMetrics {
+        inner!(self).deduplication_counters.metrics()
+    }
+
+    /// Returns the traffic relayed on behalf of each session, keyed by the CID of the session. Empty
+    /// unless this node serves as a relay
+    pub fn get_relay_usage(&self) -> HashMap<u64, RelayUsage> {
+        inner!(self)
+            .relay_ledger
+            .as_ref()
+            .map(|ledger| ledger.usage())
+            .unwrap_or_default()
+    }
+
     /// Called by the higher-level [HdpServer] async writer loop
     /// `nid_local` is only needed in case a provisional id is needed.
     ///
@@ -155,6 +315,7 @@ impl HdpSessionManager {
                 proposed_credentials,
                 peer_layer,
                 stun_servers,
+                connect_timer,
             ) = {
                 let (
                     remote,
@@ -203,6 +364,28 @@ impl HdpSessionManager {
 
                                     (peer_addr, Some(cnac), proposed_credentials)
                                 }
+
+                                AuthenticationRequest::Oidc {
+                                    id_token,
+                                    server_addr,
+                                } => {
+                                    let acc_mgr = {
+                                        let inner = inner!(self);
+                                        inner.account_manager.clone()
+                                    };
+
+                                    let proposed_credentials = ProposedCredentials::new_oidc(
+                                        id_token.clone(),
+                                    )
+                                    .map_err(|err| NetworkError::Generic(err.into_string()))?;
+                                    // if the account does not yet exist, it gets registered first
+                                    let cnac =
+                                        UserIdentifier::from(proposed_credentials.username())
+                                            .search(&acc_mgr)
+                                            .await?;
+
+                                    (*server_addr, cnac, proposed_credentials)
+                                }
                             },
                         }
                     };
@@ -247,10 +430,23 @@ impl HdpSessionManager {
                     ConnectProtocol::Quic(listener_underlying_proto.maybe_get_identity());
 
                 // create conn to peer
-                let primary_stream =
-                    HdpServer::create_session_transport_init(peer_addr, default_client_config)
-                        .await
-                        .map_err(|err| NetworkError::SocketError(err.to_string()))?;
+                let (server_key_pinning, cert_bootstrap_code) = {
+                    let this = inner!(self);
+                    (
+                        this.server_key_pinning.clone(),
+                        this.cert_bootstrap_code.clone(),
+                    )
+                };
+                let mut connect_timer = ConnectTimer::default();
+                let primary_stream = HdpServer::create_session_transport_init(
+                    peer_addr,
+                    default_client_config,
+                    server_key_pinning.as_ref(),
+                    cert_bootstrap_code.as_deref(),
+                    &mut connect_timer,
+                )
+                .await
+                .map_err(|err| NetworkError::SocketError(err.to_string()))?;
                 let local_bind_addr = primary_stream
                     .local_addr()
                     .map_err(|err| NetworkError::Generic(err.to_string()))?;
@@ -268,6 +464,7 @@ impl HdpSessionManager {
                     proposed_credentials,
                     peer_layer,
                     stun_servers,
+                    connect_timer,
                 )
             };
 
@@ -281,8 +478,31 @@ impl HdpSessionManager {
                 keep_alive_timeout_ns: keep_alive_timeout_ns.unwrap_or(KEEP_ALIVE_TIMEOUT_NS),
                 security_settings,
                 peer_only_connect_proto: peer_only_connect_mode,
+                connect_timer,
             };
 
+            let (
+                provisional_reaper,
+                session_lifecycle,
+                processing_budget,
+                hole_punch_settings,
+                packet_filter,
+                relay_ledger,
+                legal_hold,
+                abuse_monitor,
+            ) = {
+                let this = inner!(self);
+                (
+                    this.provisional_reaper.clone(),
+                    this.session_lifecycle.clone(),
+                    this.processing_budget.clone(),
+                    this.hole_punch_settings.clone(),
+                    this.packet_filter.clone(),
+                    this.relay_ledger.clone(),
+                    this.legal_hold.clone(),
+                    this.abuse_monitor.clone(),
+                )
+            };
             let session_init_params = SessionInitParams {
                 local_nat_type,
                 remote_peer: peer_addr,
@@ -299,6 +519,14 @@ impl HdpSessionManager {
                 hypernode_peer_layer: peer_layer,
                 client_only_settings: Some(client_only_settings),
                 stun_servers,
+                hole_punch_settings,
+                provisional_reaper,
+                session_lifecycle,
+                processing_budget,
+                packet_filter,
+                relay_ledger,
+                legal_hold,
+                abuse_monitor,
             };
 
             let (stopper, new_session) = HdpSession::new(session_init_params)?;
@@ -387,6 +615,14 @@ impl HdpSessionManager {
             // if this is the case, ignore safe-shutdown of the session since no possible vconns
             // exist
             if let Some(implicated_cid) = sess.implicated_cid.get() {
+                if let Some(relay_ledger) = sess.relay_ledger.as_ref() {
+                    relay_ledger.remove(implicated_cid);
+                }
+
+                if let Some(abuse_monitor) = sess.abuse_monitor.as_ref() {
+                    abuse_monitor.remove(implicated_cid);
+                }
+
                 let task = async move { peer_layer.on_session_shutdown(implicated_cid).await };
 
                 spawn!(task);
@@ -414,8 +650,7 @@ impl HdpSessionManager {
                                     //log::error!(target: "citadel", "Unable to send shutdown signal to {}: {:?}", peer_cid, err);
                                 }
 
-                                if let Some(peer_sess) = sess_mgr.sessions.get(&peer_cid) {
-                                    let peer_sess = &peer_sess.1;
+                                if let Some((_, peer_sess)) = sess_mgr.sessions.get(&peer_cid) {
                                     let mut peer_state_container = inner_mut_state!(peer_sess.state_container);
                                     if peer_state_container.active_virtual_connections.remove(&implicated_cid).is_none() {
                                         log::warn!(target: "citadel", "While dropping session {}, attempted to remove vConn to {}, but peer did not have the vConn listed. Report to developers", implicated_cid, peer_cid);
@@ -470,10 +705,18 @@ impl HdpSessionManager {
             if init_time.elapsed() > DO_CONNECT_EXPIRE_TIME_MS {
                 this.provisional_connections.remove(&peer_addr);
             } else {
-                return Err(NetworkError::Generic(format!(
+                let message = format!(
                     "Peer from {} is already a provisional connection. Denying attempt",
                     &peer_addr
-                )));
+                );
+                std::mem::drop(this);
+                self.report_handshake_failure(HandshakeFailed {
+                    peer_addr,
+                    cid_opt: None,
+                    reason: HandshakeFailureReason::Quota,
+                    message: message.clone(),
+                });
+                return Err(NetworkError::Generic(message));
             }
         }
 
@@ -499,6 +742,14 @@ impl HdpSessionManager {
             hypernode_peer_layer: peer_layer,
             client_only_settings: None,
             stun_servers,
+            hole_punch_settings: this.hole_punch_settings.clone(),
+            provisional_reaper: this.provisional_reaper.clone(),
+            session_lifecycle: this.session_lifecycle.clone(),
+            processing_budget: this.processing_budget.clone(),
+            packet_filter: this.packet_filter.clone(),
+            relay_ledger: this.relay_ledger.clone(),
+            legal_hold: this.legal_hold.clone(),
+            abuse_monitor: this.abuse_monitor.clone(),
         };
 
         let (stopper, new_session) = HdpSession::new(session_init_params)?;
@@ -565,6 +816,57 @@ impl HdpSessionManager {
         }
     }
 
+    pub fn process_outbound_file_delta(
+        &self,
+        ticket: Ticket,
+        max_group_size: Option<usize>,
+        source: Box<dyn ObjectSource>,
+        implicated_cid: u64,
+        virtual_target: VirtualTargetType,
+        security_level: SecurityLevel,
+    ) -> Result<(), NetworkError> {
+        let this = inner!(self);
+        if let Some(existing_session) = this.sessions.get(&implicated_cid) {
+            existing_session.1.process_outbound_file_delta(
+                ticket,
+                max_group_size,
+                source,
+                virtual_target,
+                security_level,
+            )
+        } else {
+            Err(NetworkError::Generic(format!(
+                "Hypernode session for {implicated_cid} does not exist! Not going to send data ..."
+            )))
+        }
+    }
+
+    pub fn process_outbound_file_deduplicated(
+        &self,
+        ticket: Ticket,
+        max_group_size: Option<usize>,
+        source: Box<dyn ObjectSource>,
+        implicated_cid: u64,
+        virtual_target: VirtualTargetType,
+        security_level: SecurityLevel,
+    ) -> Result<(), NetworkError> {
+        let this = inner!(self);
+        if let Some(existing_session) = this.sessions.get(&implicated_cid) {
+            existing_session.1.process_outbound_file_deduplicated(
+                ticket,
+                max_group_size,
+                source,
+                virtual_target,
+                security_level,
+            )
+        } else {
+            Err(NetworkError::Generic(format!(
+                "Hypernode session for {implicated_cid} does not exist! Not going to send data ..."
+            )))
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     pub fn revfs_pull(
         &self,
         ticket: Ticket,
@@ -572,11 +874,19 @@ impl HdpSessionManager {
         v_conn: VirtualConnectionType,
         virtual_path: PathBuf,
         delete_on_pull: bool,
+        owner_cid: Option<u64>,
         security_level: SecurityLevel,
     ) -> Result<(), NetworkError> {
         let lock = inner!(self);
         if let Some((_, sess)) = lock.sessions.get(&implicated_cid) {
-            sess.revfs_pull(ticket, v_conn, virtual_path, delete_on_pull, security_level)
+            sess.revfs_pull(
+                ticket,
+                v_conn,
+                virtual_path,
+                delete_on_pull,
+                owner_cid,
+                security_level,
+            )
         } else {
             Err(NetworkError::Generic(format!(
                 "Hypernode session for {implicated_cid} does not exist! Not going to process request ..."
@@ -590,11 +900,96 @@ impl HdpSessionManager {
         implicated_cid: u64,
         v_conn: VirtualConnectionType,
         virtual_path: PathBuf,
+        owner_cid: Option<u64>,
         security_level: SecurityLevel,
     ) -> Result<(), NetworkError> {
         let lock = inner!(self);
         if let Some((_, sess)) = lock.sessions.get(&implicated_cid) {
-            sess.revfs_delete(ticket, v_conn, virtual_path, security_level)
+            sess.revfs_delete(ticket, v_conn, virtual_path, owner_cid, security_level)
+        } else {
+            Err(NetworkError::Generic(format!(
+                "Hypernode session for {implicated_cid} does not exist! Not going to process request ..."
+            )))
+        }
+    }
+
+    pub fn revfs_directory(
+        &self,
+        ticket: Ticket,
+        implicated_cid: u64,
+        v_conn: VirtualConnectionType,
+        operation: ReVFSDirectoryOperation,
+        security_level: SecurityLevel,
+    ) -> Result<(), NetworkError> {
+        let lock = inner!(self);
+        if let Some((_, sess)) = lock.sessions.get(&implicated_cid) {
+            sess.revfs_directory(ticket, v_conn, operation, security_level)
+        } else {
+            Err(NetworkError::Generic(format!(
+                "Hypernode session for {implicated_cid} does not exist! Not going to process request ..."
+            )))
+        }
+    }
+
+    pub fn shared_object(
+        &self,
+        ticket: Ticket,
+        implicated_cid: u64,
+        operation: SharedObjectOperation,
+        security_level: SecurityLevel,
+    ) -> Result<(), NetworkError> {
+        let lock = inner!(self);
+        if let Some((_, sess)) = lock.sessions.get(&implicated_cid) {
+            sess.shared_object(ticket, operation, security_level)
+        } else {
+            Err(NetworkError::Generic(format!(
+                "Hypernode session for {implicated_cid} does not exist! Not going to process request ..."
+            )))
+        }
+    }
+
+    pub fn renegotiate_protocol(
+        &self,
+        ticket: Ticket,
+        implicated_cid: u64,
+        capabilities: ProtocolCapabilities,
+    ) -> Result<(), NetworkError> {
+        let lock = inner!(self);
+        if let Some((_, sess)) = lock.sessions.get(&implicated_cid) {
+            sess.renegotiate_protocol(ticket, capabilities)
+        } else {
+            Err(NetworkError::Generic(format!(
+                "Hypernode session for {implicated_cid} does not exist! Not going to process request ..."
+            )))
+        }
+    }
+
+    pub fn ping(
+        &self,
+        ticket: Ticket,
+        implicated_cid: u64,
+        v_conn: VirtualTargetType,
+    ) -> Result<(), NetworkError> {
+        let lock = inner!(self);
+        if let Some((_, sess)) = lock.sessions.get(&implicated_cid) {
+            sess.ping(ticket, v_conn)
+        } else {
+            Err(NetworkError::Generic(format!(
+                "Hypernode session for {implicated_cid} does not exist! Not going to process request ..."
+            )))
+        }
+    }
+
+    pub fn open_named_channel(
+        &self,
+        ticket: Ticket,
+        implicated_cid: u64,
+        v_conn: VirtualTargetType,
+        name: String,
+    ) -> Result<(), NetworkError> {
+        let lock = inner!(self);
+        if let Some((_, sess)) = lock.sessions.get(&implicated_cid) {
+            sess.open_named_channel(ticket, v_conn, name)
         } else {
             Err(NetworkError::Generic(format!(
                 "Hypernode session for {implicated_cid} does not exist! Not going to process request ..."
@@ -660,8 +1055,8 @@ impl HdpSessionManager {
     ) -> Result<(), NetworkError> {
         let sess = {
             let this = inner!(self);
-            if let Some(sess) = this.sessions.get(&implicated_cid) {
-                sess.1.clone()
+            if let Some((_, sess)) = this.sessions.get(&implicated_cid) {
+                sess
             } else {
                 return Err(NetworkError::msg(format!("Session for {implicated_cid} not found in session manager. Failed to dispatch peer command {peer_command:?}")));
             }
@@ -673,8 +1068,99 @@ impl HdpSessionManager {
 
     /// Returns a list of active sessions
     pub fn get_active_sessions(&self) -> Vec<u64> {
+        inner!(self).sessions.keys()
+    }
+
+    /// Returns the number of sessions reaped in each pre-connect stage since the node started
+    pub fn get_reaped_session_counts(&self) -> ReapedSessionCounts {
+        inner!(self).provisional_reaper.counts()
+    }
+
+    /// Returns the number of inbound handshakes that failed for each reason since the node started
+    pub fn get_handshake_failure_counts(&self) -> HandshakeFailureCounts {
+        inner!(self).handshake_failures.counts()
+    }
+
+    /// Counts the failed inbound handshake, and reports it to the kernel
+    pub(crate) fn report_handshake_failure(&self, failure: HandshakeFailed) {
+        log::warn!(target: "citadel", "Inbound handshake from {} failed ({:?}): {}", failure.peer_addr, failure.reason, failure.message);
         let this = inner!(self);
-        this.sessions.keys().copied().collect()
+        this.handshake_failures.record(failure.reason);
+        if let Err(err) = this
+            .kernel_tx
+            .unbounded_send(NodeResult::HandshakeFailed(failure))
+        {
+            log::error!(target: "citadel", "Unable to report the failed handshake to the kernel: {err:?}");
+        }
+    }
+
+    /// Returns a snapshot of the resources held by the sessions of this node. The state containers
+    /// are sampled after releasing the lock on the session manager
+    pub fn get_resource_counters(&self) -> ResourceCounters {
+        let (sessions, active_sessions, provisional_sessions, tracked_tickets, lifecycle) = {
+            let this = inner!(self);
+            let sessions = this
+                .sessions
+                .values()
+                .into_iter()
+                .map(|(_, session)| session)
+                .chain(
+                    this.provisional_connections
+                        .values()
+                        .map(|(_, _, session)| session.clone()),
+                )
+                .collect::<Vec<_>>();
+            (
+                sessions,
+                this.sessions.len() as u64,
+                this.provisional_connections.len() as u64,
+                this.server_remote
+                    .as_ref()
+                    .map(|remote| remote.tracked_tickets())
+                    .unwrap_or(0) as u64,
+                this.session_lifecycle.clone(),
+            )
+        };
+
+        let (state_container_entries, toolset_versions_retained) = sessions
+            .iter()
+            .map(|session| {
+                let state_container = inner_state!(session.state_container);
+                (
+                    state_container.tracked_entries() as u64,
+                    state_container.toolset_versions_retained() as u64,
+                )
+            })
+            .fold((0, 0), |(entries, versions), (e, v)| {
+                (entries + e, versions + v)
+            });
+        let (sessions_created, sessions_destroyed) = lifecycle.counts();
+
+        ResourceCounters {
+            sessions_created,
+            sessions_destroyed,
+            active_sessions,
+            provisional_sessions,
+            tracked_tickets,
+            state_container_entries,
+            toolset_versions_retained,
+        }
+    }
+
+    /// Returns the virtual connections held by each connected session. The state containers are
+    /// sampled after releasing the lock on the session manager
+    pub fn get_virtual_connections(&self) -> HashMap<u64, Vec<VirtualConnectionType>> {
+        let mut sessions = Vec::new();
+        inner!(self)
+            .sessions
+            .for_each(|cid, (_, session)| sessions.push((*cid, session.clone())));
+        sessions
+            .into_iter()
+            .map(|(cid, session)| {
+                let connections = inner_state!(session.state_container).virtual_connections();
+                (cid, connections)
+            })
+            .collect()
     }
 
     /// This upgrades a provisional connection to a full connection. Returns true if the upgrade
@@ -726,8 +1212,64 @@ impl HdpSessionManager {
 
     /// Clears a session from the internal map
     pub fn clear_session(&self, cid: u64) {
-        let mut this = inner_mut!(self);
-        this.clear_session(cid);
+        let removed = {
+            let mut this = inner_mut!(self);
+            let _ = this.peer_list_subscribers.remove(&cid);
+            this.clear_session(cid)
+        };
+
+        if removed {
+            self.notify_peer_list_subscribers(PeerListDelta::Offline { cid });
+        }
+    }
+
+    /// Subscribes `implicated_cid` to [`PeerListDelta`]s until its session ends
+    pub fn subscribe_to_peer_list(
+        &self,
+        implicated_cid: u64,
+        ticket: Ticket,
+        security_level: SecurityLevel,
+    ) {
+        let _ = inner_mut!(self)
+            .peer_list_subscribers
+            .insert(implicated_cid, (ticket, security_level));
+    }
+
+    /// Sends `delta` to every subscriber besides the client that the delta concerns
+    pub fn notify_peer_list_subscribers(&self, delta: PeerListDelta) {
+        let subscribers = inner!(self)
+            .peer_list_subscribers
+            .keys()
+            .copied()
+            .filter(|cid| *cid != delta.cid())
+            .collect::<Vec<_>>();
+
+        for cid in subscribers {
+            self.notify_peer_list_subscriber(cid, delta.clone());
+        }
+    }
+
+    /// Sends `delta` to `subscriber_cid`, if subscribed
+    pub fn notify_peer_list_subscriber(&self, subscriber_cid: u64, delta: PeerListDelta) {
+        let (subscription, timestamp) = {
+            let this = inner!(self);
+            (
+                this.peer_list_subscribers.get(&subscriber_cid).copied(),
+                this.time_tracker.get_global_time_ns(),
+            )
+        };
+
+        if let Some((ticket, security_level)) = subscription {
+            if !self.send_signal_to_peer(
+                subscriber_cid,
+                ticket,
+                PeerSignal::PeerListChanged(delta),
+                timestamp,
+                security_level,
+            ) {
+                log::warn!(target: "citadel", "Unable to send peer list delta to {subscriber_cid}");
+            }
+        }
     }
 
     /// When the registration process completes, and before sending the kernel a message, this should be called on BOTH ends
@@ -836,8 +1378,10 @@ impl HdpSessionManager {
         let mut to_broadcast_left = vec![];
         let dc_signal = GroupBroadcast::Disconnected(key);
 
+        let mut to_broadcast_epoch = vec![];
+        let mut epoch_signal = None;
         let left_signal = match peer_layer.remove_peers_from_message_group(key, peers).await {
-            Ok((peers_removed, peers_remaining)) => {
+            Ok((peers_removed, peers_remaining, epoch)) => {
                 log::trace!(target: "citadel", "Peers removed: {:?}", &peers_removed);
                 // We only notify the members when kicking, not leaving
                 if mode == GroupMemberAlterMode::Kick {
@@ -849,6 +1393,13 @@ impl HdpSessionManager {
                     }
                 }
 
+                // the epoch only rotates when a member departs. Every remaining member,
+                // including the requester, must learn of the new epoch
+                if !peers_removed.is_empty() {
+                    to_broadcast_epoch = peers_remaining.clone();
+                    epoch_signal = Some(GroupBroadcast::EpochRotated(key, epoch));
+                }
+
                 for peer in peers_remaining {
                     if peer != implicated_cid {
                         to_broadcast_left.push(peer);
@@ -897,6 +1448,24 @@ impl HdpSessionManager {
             .await
             .map_err(NetworkError::Generic)?;
 
+        if let Some(epoch_signal) = epoch_signal {
+            let len = to_broadcast_epoch.len();
+            let peers_and_statuses_epoch = to_broadcast_epoch
+                .into_iter()
+                .zip(std::iter::repeat(true).take(len));
+            let _ = self
+                .send_group_broadcast_signal_to(
+                    timestamp,
+                    ticket,
+                    peers_and_statuses_epoch,
+                    true,
+                    epoch_signal,
+                    security_level,
+                )
+                .await
+                .map_err(NetworkError::Generic)?;
+        }
+
         Ok(true)
     }
 
@@ -976,9 +1545,96 @@ impl HdpSessionManager {
         &self,
         implicated_cid: u64,
     ) -> Result<Option<MailboxTransfer>, NetworkError> {
-        let peer_layer = { inner!(self).hypernode_peer_layer.clone() };
+        let (peer_layer, pers) = {
+            let this = inner!(self);
+            (
+                this.hypernode_peer_layer.clone(),
+                this.account_manager.get_persistence_handler().clone(),
+            )
+        };
 
-        peer_layer.register_peer(implicated_cid).await
+        let mailbox = peer_layer.register_peer(implicated_cid).await?;
+        // a consent may be both persisted and inside a snapshot. Keyed by ticket, such that each is only reinstated once
+        let restored_consents = peer_layer
+            .take_restored_consents(implicated_cid)
+            .await
+            .into_iter()
+            .chain(posting_store::take_postings(&pers, implicated_cid).await?)
+            .map(|consent| (consent.ticket, consent))
+            .collect::<HashMap<_, _>>();
+        if !restored_consents.is_empty() {
+            log::trace!(target: "citadel", "Reinstating {} restored consent requests of {implicated_cid}", restored_consents.len());
+            let peer_layer = peer_layer.inner.read().await;
+            for consent in restored_consents.into_values() {
+                let session_manager = self.clone();
+                peer_layer
+                    .insert_tracked_posting(
+                        implicated_cid,
+                        consent.remaining,
+                        consent.ticket,
+                        consent.signal,
+                        move |stale_signal| {
+                            let timestamp =
+                                { inner!(session_manager).time_tracker.get_global_time_ns() };
+                            let _ = session_manager.send_signal_to_peer(
+                                implicated_cid,
+                                consent.ticket,
+                                stale_signal,
+                                timestamp,
+                                SecurityLevel::Standard,
+                            );
+                        },
+                    )
+                    .await;
+            }
+        }
+
+        Ok(mailbox)
+    }
+
+    /// Saves the peer layer state of every client to the backend, allowing it to be restored after
+    /// the server restarts. Returns the number of clients whose state was saved
+    pub async fn save_peer_layer_snapshot(&self) -> Result<usize, NetworkError> {
+        let (peer_layer, pers) = {
+            let this = inner!(self);
+            (
+                this.hypernode_peer_layer.clone(),
+                this.account_manager.get_persistence_handler().clone(),
+            )
+        };
+
+        let snapshots = peer_layer.snapshot().await;
+        snapshot::store_snapshots(&pers, snapshots).await
+    }
+
+    /// Restores the peer layer state saved before the previous shutdown, removing it from the backend.
+    /// Returns the number of clients whose state was restored
+    pub async fn restore_peer_layer_snapshot(&self) -> Result<usize, NetworkError> {
+        let (peer_layer, pers) = {
+            let this = inner!(self);
+            (
+                this.hypernode_peer_layer.clone(),
+                this.account_manager.get_persistence_handler().clone(),
+            )
+        };
+
+        let snapshots = snapshot::take_snapshots(&pers).await?;
+        let restored = snapshots.len();
+        peer_layer.restore(snapshots).await;
+        Ok(restored)
+    }
+
+    /// Returns the session of `cid` alongside its latest C2S ratchet, if `cid` is connected
+    pub(crate) fn get_session_with_c2s_ratchet(
+        &self,
+        cid: u64,
+    ) -> Option<(HdpSession, StackedRatchet)> {
+        let (_, sess) = inner!(self).sessions.get(&cid)?;
+        let hyper_ratchet = inner_state!(sess.state_container)
+            .get_c2s_crypto()?
+            .get_hyper_ratchet(None)
+            .cloned()?;
+        Some((sess, hyper_ratchet))
     }
 
     /// Removes a virtual connection `implicated_cid` from `peer_cid`
@@ -1065,7 +1721,7 @@ impl HdpSessionManager {
         {
             let (sess, pers) = {
                 let this = inner!(self);
-                let sess = this.sessions.get(&target_cid).map(|r| r.1.clone());
+                let sess = this.sessions.get(&target_cid).map(|r| r.1);
                 (sess, this.account_manager.get_persistence_handler().clone())
             };
 
@@ -1108,9 +1764,9 @@ impl HdpSessionManager {
             let mut inner = inner_mut!(self);
             if let Some(recv) = inner.clean_shutdown_tracker.take() {
                 let len = inner.sessions.len();
-                for sender in inner.sessions.values().map(|r| &r.0) {
+                inner.sessions.for_each(|_, (sender, _)| {
                     let _ = sender.send(());
-                }
+                });
                 (recv, len)
             } else {
                 return Err(NetworkError::InternalError(
@@ -1247,10 +1903,13 @@ impl HdpSessionManager {
 }
 
 impl HdpSessionManagerInner {
-    /// Clears a session from the SessionManager
-    pub fn clear_session(&mut self, cid: u64) {
+    /// Clears a session from the SessionManager. Returns true if the session existed
+    pub fn clear_session(&self, cid: u64) -> bool {
         if self.sessions.remove(&cid).is_none() {
             log::warn!(target: "citadel", "Tried removing a session (non-provisional), but did not find it ...");
+            false
+        } else {
+            true
         }
     }
 
@@ -21,7 +21,6 @@ pub const RESERVED_CID_IDX: u64 = 0;
 pub const PROVISIONAL_CHECKER: usize = 0;
 pub const DRILL_REKEY_WORKER: usize = 1;
 pub const KEEP_ALIVE_CHECKER: usize = 2;
-pub const FIREWALL_KEEP_ALIVE: usize = 3;
 
 pub trait QueueFunction:
     Fn(&mut dyn ExpectedInnerTargetMut<StateContainerInner>) -> QueueWorkerResult + Send + 'static
@@ -9,7 +9,9 @@ use crate::proto::packet_processor::primary_group_packet::{
 use citadel_crypt::stacked_ratchet::constructor::{ConstructorType, StackedRatchetConstructor};
 use serde::{Deserialize, Serialize};
 
-use crate::proto::outbound_sender::{unbounded, UnboundedSender};
+use crate::proto::outbound_sender::{
+    channel, unbounded, KernelEventSender, Receiver, Sender, TrySendError, UnboundedSender,
+};
 use zerocopy::LayoutVerified;
 
 use citadel_crypt::scramble::crypt_splitter::{
@@ -19,31 +21,40 @@ use citadel_user::client_account::ClientNetworkAccount;
 use netbeam::time_tracker::TimeTracker;
 
 use crate::constants::{
-    GROUP_EXPIRE_TIME_MS, GROUP_TIMEOUT_MS, INDIVIDUAL_WAVE_TIMEOUT_MS, KEEP_ALIVE_INTERVAL_MS,
-    MAX_OUTGOING_UNPROCESSED_REQUESTS,
+    FLOW_CONTROL_WINDOW, GROUP_EXPIRE_TIME_MS, GROUP_TIMEOUT_MS, INDIVIDUAL_WAVE_TIMEOUT_MS,
+    KEEP_ALIVE_INTERVAL_MS, MAX_OUTGOING_UNPROCESSED_REQUESTS,
 };
 use crate::error::NetworkError;
 use crate::functional::IfEqConditional;
 use crate::prelude::{InternalServerError, MessageGroupKey, ReKeyResult, ReKeyReturnType};
 use crate::proto::misc::dual_late_init::DualLateInit;
+use crate::proto::misc::flow_control::{FlowControl, WindowUpdate};
+use crate::proto::misc::multipath::{
+    MultipathCongestion, MultipathCounters, StripedGroup, MULTIPATH_LOSS_TIMEOUT,
+};
 use crate::proto::misc::ordered_channel::OrderedChannel;
+use crate::proto::misc::protocol_capabilities::CapabilityState;
+use crate::proto::misc::provisional_reaper::ProvisionalStage;
+use crate::proto::misc::security_info::{SecurityInfo, TransportSecurity};
 use crate::proto::misc::session_security_settings::SessionSecuritySettings;
 use crate::proto::node::SecrecyMode;
 use crate::proto::node_result::{NodeResult, ObjectTransferHandle};
 use crate::proto::outbound_sender::{OutboundPrimaryStreamSender, OutboundUdpSender};
-use crate::proto::packet::packet_flags;
 use crate::proto::packet::HdpHeader;
 use crate::proto::packet_crafter::peer_cmd::C2S_ENCRYPTION_ONLY;
 use crate::proto::packet_crafter::{
     GroupTransmitter, RatchetPacketCrafterContainer, SecureProtocolPacket,
 };
-use crate::proto::packet_processor::includes::{HdpSession, Instant, SocketAddr};
+use crate::proto::packet_processor::includes::{Duration, HdpSession, Instant, SocketAddr};
 use crate::proto::packet_processor::peer::group_broadcast::GroupBroadcast;
 use crate::proto::packet_processor::PrimaryProcessorResult;
-use crate::proto::peer::channel::{PeerChannel, UdpChannel};
+use crate::proto::peer::channel::{
+    is_channel_close, named_channel_id, PeerChannel, PeerChannelSendHalf, UdpChannel,
+};
 use crate::proto::peer::group_channel::{GroupBroadcastPayload, GroupChannel};
 use crate::proto::peer::p2p_conn_handler::DirectP2PRemote;
 use crate::proto::peer::peer_layer::{PeerConnectionType, UdpMode};
+use crate::proto::peer::security_epoch::SecurityEpochs;
 use crate::proto::remote::{NodeRemote, Ticket};
 use crate::proto::session::SessionState;
 use crate::proto::session_queue_handler::{QueueWorkerResult, SessionQueueWorkerHandle};
@@ -54,17 +65,24 @@ use crate::proto::state_subcontainers::peer_kem_state_container::PeerKemStateCon
 use crate::proto::state_subcontainers::preconnect_state_container::PreConnectState;
 use crate::proto::state_subcontainers::register_state_container::RegisterState;
 use crate::proto::state_subcontainers::rekey_container::RatchetUpdateState;
+use crate::proto::state_subcontainers::stage_machine::StageDump;
 use crate::proto::transfer_stats::TransferStats;
 use crate::proto::{packet_crafter, send_with_error_logging};
 use atomic::Atomic;
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
+use citadel_crypt::chunk_dedup::ChunkedObject;
+use citadel_crypt::compression::{CompressionAlgorithm, GroupDecompressor};
 use citadel_crypt::endpoint_crypto_container::{KemTransferStatus, PeerSessionCrypto};
 use citadel_crypt::entropy_bank::SecurityLevel;
+use citadel_crypt::misc::{CryptError, TransferType};
 use citadel_crypt::prelude::SecBuffer;
 use citadel_crypt::stacked_ratchet::{Ratchet, StackedRatchet};
+use citadel_crypt::streaming_crypt_scrambler::ObjectSource;
+use citadel_crypt::transfer_manifest::{ManifestVerifier, TransferManifest};
 use citadel_user::backend::utils::*;
 use citadel_user::backend::PersistenceHandler;
 use citadel_user::serialization::SyncIO;
+use citadel_user::server_misc_settings::ProvisionalTimeouts;
 use either::Either;
 use std::sync::atomic::{AtomicBool, Ordering};
 
@@ -76,6 +94,30 @@ impl Debug for StateContainer {
 
 define_outer_struct_wrapper!(StateContainer, StateContainerInner);
 
+impl StateContainer {
+    /// Inspects the state container, or returns None if it is currently locked (e.g., by a hung
+    /// packet handler)
+    pub(crate) fn try_inspect<T>(
+        &self,
+        inspect: impl FnOnce(&StateContainerInner) -> T,
+    ) -> Option<T> {
+        #[cfg(not(feature = "multi-threaded"))]
+        {
+            self.inner
+                .try_borrow()
+                .ok()
+                .map(|state_container| inspect(&state_container))
+        }
+
+        #[cfg(feature = "multi-threaded")]
+        {
+            self.inner
+                .try_read()
+                .map(|state_container| inspect(&state_container))
+        }
+    }
+}
+
 /// For keeping track of the stages
 pub struct StateContainerInner {
     pub(super) pre_connect_state: PreConnectState,
@@ -95,9 +137,15 @@ pub struct StateContainerInner {
             SecureProtocolPacket,
             VirtualTargetType,
             SecurityLevel,
+            Option<Instant>,
         )>,
     >,
     pub(super) updates_in_progress: HashMap<u64, Arc<AtomicBool>>,
+    pub(crate) security_epochs: SecurityEpochs,
+    pub(crate) capabilities: CapabilityState,
+    /// The register STAGE0 or pre-connect SYN last sent by this client, resent alongside the
+    /// solution should the server challenge it
+    pub(crate) challengeable_handshake: Option<BytesMut>,
     pub(super) inbound_files: HashMap<FileKey, InboundFileTransfer>,
     pub(super) outbound_files: HashMap<FileKey, OutboundFileTransfer>,
     pub(super) file_transfer_handles: HashMap<FileKey, UnboundedSender<ObjectTransferStatus>>,
@@ -105,10 +153,16 @@ pub struct StateContainerInner {
     pub(super) outbound_transmitters: HashMap<GroupKey, OutboundTransmitterContainer>,
     pub(super) peer_kem_states: HashMap<u64, PeerKemStateContainer>,
     // u64 is peer id, ticket is the local original ticket (ticket may
-    // transform if a simultaneous connect)
-    pub(super) outgoing_peer_connect_attempts: HashMap<u64, Ticket>,
+    // transform if a simultaneous connect), alongside the locally requested security settings
+    pub(super) outgoing_peer_connect_attempts: HashMap<u64, (Ticket, SessionSecuritySettings)>,
+    // (uploader cid, object id) -> the shared object the upload is stored as
+    pub(super) pending_shared_object_writes: HashMap<(u64, u32), PendingSharedObjectWrite>,
+    // object name -> the object id reserved for the next upload of the named shared object
+    pub(super) reserved_shared_object_uploads: HashMap<String, u32>,
+    pub(super) pending_delta_transfers: HashMap<Ticket, PendingDeltaTransfer>,
+    pub(super) pending_deduplicated_transfers: HashMap<Ticket, PendingDeduplicatedTransfer>,
     pub(super) udp_primary_outbound_tx: Option<OutboundUdpSender>,
-    pub(super) kernel_tx: UnboundedSender<NodeResult>,
+    pub(super) kernel_tx: KernelEventSender,
     pub(super) active_virtual_connections: HashMap<u64, VirtualConnection>,
     pub(super) c2s_channel_container: Option<C2SChannelContainer>,
     pub(crate) keep_alive_timeout_ns: i64,
@@ -116,13 +170,23 @@ pub struct StateContainerInner {
     // whenever a c2s or p2p channel is loaded, this is fired to signal any UDP loaders that it is safe to store the UDP conn in the corresponding v_conn
     pub(super) tcp_loaded_status: Option<tokio::sync::oneshot::Sender<()>>,
     pub(super) hole_puncher_pipes: HashMap<u64, tokio::sync::mpsc::UnboundedSender<Bytes>>,
+    // fired once the server is ready to traverse NATs for a UDP channel enabled after connecting
+    pub(super) deferred_udp_ready_tx: Option<tokio::sync::oneshot::Sender<()>>,
+    // the instant each outbound ping was sent, keyed by the ticket of the request
+    pub(super) pending_pings: HashMap<Ticket, Instant>,
+    // fired once the adjacent endpoint receives the message closing a channel, keyed by the
+    // target CID and the ticket of the message
+    pub(super) pending_channel_closes: HashMap<(u64, Ticket), tokio::sync::oneshot::Sender<()>>,
     pub(super) cnac: Option<ClientNetworkAccount>,
     pub(super) time_tracker: TimeTracker,
     pub(super) session_security_settings: Option<SessionSecuritySettings>,
     pub(super) queue_handle: DualLateInit<SessionQueueWorkerHandle>,
     pub(super) group_channels: HashMap<MessageGroupKey, UnboundedSender<GroupBroadcastPayload>>,
     pub(super) transfer_stats: TransferStats,
+    pub(super) multipath_counters: Arc<MultipathCounters>,
     pub(super) udp_mode: UdpMode,
+    // set once the primary stream is established
+    pub(super) transport_security: TransportSecurity,
     is_server: bool,
 }
 
@@ -149,12 +213,141 @@ pub(crate) struct InboundFileTransfer {
     pub groups_rendered: usize,
     pub last_group_window_len: usize,
     pub last_group_finish_time: Instant,
+    pub progress: TransferProgressTracker,
     pub ticket: Ticket,
     pub virtual_target: VirtualTargetType,
     pub metadata: VirtualObjectMetadata,
-    pub stream_to_hd: UnboundedSender<Vec<u8>>,
-    pub reception_complete_tx: tokio::sync::oneshot::Sender<HdpHeader>,
     pub local_encryption_level: Option<SecurityLevel>,
+    // completed groups are post-processed off the session task. Dropping this ends the processor
+    pub chunk_queue: InboundChunkQueue,
+}
+
+/// The number of completed groups of an inbound object allowed to await post-processing. Beyond
+/// this, wave ACKs are held back, such that the sender stops transmitting until the processor
+/// catches up
+const INBOUND_CHUNK_BACKLOG: usize = 2;
+
+/// Hands the jobs of an inbound object to its processor, in order. Once the processor's queue is
+/// full, further jobs are backlogged, and the wave ACKs of the object are held back until the
+/// backlog is handed over
+#[derive(Clone)]
+pub(crate) struct InboundChunkQueue {
+    processor: Sender<InboundChunkJob>,
+    backlog: Arc<citadel_io::Mutex<InboundChunkBacklog>>,
+    reply_stream: OutboundPrimaryStreamSender,
+}
+
+#[derive(Default)]
+struct InboundChunkBacklog {
+    jobs: VecDeque<InboundChunkJob>,
+    held_wave_acks: Vec<BytesMut>,
+}
+
+impl InboundChunkQueue {
+    fn new(reply_stream: OutboundPrimaryStreamSender) -> (Self, Receiver<InboundChunkJob>) {
+        let (processor, jobs) = channel(INBOUND_CHUNK_BACKLOG);
+        let this = Self {
+            processor,
+            backlog: Default::default(),
+            reply_stream,
+        };
+
+        (this, jobs)
+    }
+
+    fn push(&self, job: InboundChunkJob) -> Result<(), NetworkError> {
+        let mut backlog = self.backlog.lock();
+        // jobs may not overtake those already backlogged
+        if !backlog.jobs.is_empty() {
+            backlog.jobs.push_back(job);
+            return Ok(());
+        }
+
+        match self.processor.try_send(job) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(job)) => {
+                log::trace!(target: "citadel", "Inbound chunk processor fell behind; holding back wave ACKs");
+                backlog.jobs.push_back(job);
+                self.spawn_backlog_drainer();
+                Ok(())
+            }
+            Err(TrySendError::Closed(_)) => Err(NetworkError::InternalError(
+                "The inbound chunk processor has stopped",
+            )),
+        }
+    }
+
+    /// Returns the wave ACK if it may be sent now. Otherwise, it is sent once the backlog is
+    /// handed over to the processor
+    fn hold_wave_ack(&self, wave_ack: BytesMut) -> Option<BytesMut> {
+        let mut backlog = self.backlog.lock();
+        if backlog.jobs.is_empty() {
+            Some(wave_ack)
+        } else {
+            backlog.held_wave_acks.push(wave_ack);
+            None
+        }
+    }
+
+    fn spawn_backlog_drainer(&self) {
+        let this = self.clone();
+        let task = async move {
+            loop {
+                let permit = match this.processor.clone().reserve_owned().await {
+                    Ok(permit) => permit,
+                    // the processor ended; the transfer is over
+                    Err(_) => return,
+                };
+
+                let mut backlog = this.backlog.lock();
+                if let Some(job) = backlog.jobs.pop_front() {
+                    let _ = permit.send(job);
+                }
+
+                if backlog.jobs.is_empty() {
+                    for wave_ack in backlog.held_wave_acks.drain(..) {
+                        send_with_error_logging(&this.reply_stream, wave_ack);
+                    }
+
+                    return;
+                }
+            }
+        };
+
+        spawn!(task);
+    }
+}
+
+/// Work handed to the processor of an inbound object, in the order the groups complete
+pub(crate) enum InboundChunkJob {
+    /// Loaded once the sender transmits the object's manifest
+    Manifest(ManifestVerifier),
+    Chunk(Vec<u8>),
+    /// Sent once the last group arrives over the network
+    Finish(HdpHeader),
+}
+
+/// An upload the server stores into the storage of the owner of a shared object, once the grant
+/// is confirmed to still be in place
+pub(crate) struct PendingSharedObjectWrite {
+    pub owner_cid: u64,
+    pub name: String,
+}
+
+/// An outbound transfer awaiting the receiver's signature of its copy of the object
+pub(crate) struct PendingDeltaTransfer {
+    pub source: Box<dyn ObjectSource>,
+    pub max_group_size: Option<usize>,
+    pub virtual_target: VirtualTargetType,
+    pub security_level: SecurityLevel,
+}
+
+/// An outbound transfer awaiting the receiver's report of which chunks it already holds
+pub(crate) struct PendingDeduplicatedTransfer {
+    pub object: ChunkedObject,
+    pub max_group_size: Option<usize>,
+    pub virtual_target: VirtualTargetType,
+    pub security_level: SecurityLevel,
 }
 
 #[allow(dead_code)]
@@ -167,6 +360,13 @@ pub(crate) struct OutboundFileTransfer {
     pub start: Option<tokio::sync::oneshot::Sender<bool>>,
     // This sends a shutdown signal to the async cryptscambler
     pub stop_tx: Option<tokio::sync::oneshot::Sender<()>>,
+    // true while paused. Dropping this sender signals cancellation to the group-sending subroutine
+    pub pause_tx: tokio::sync::watch::Sender<bool>,
+    // present if compression was offered. Relays the receiver's answer to the async cryptscrambler
+    pub compression_tx: Option<tokio::sync::oneshot::Sender<Option<CompressionAlgorithm>>>,
+    // present if the object is large enough to be striped across the primary and UDP streams
+    pub multipath: Option<MultipathCongestion>,
+    pub progress: TransferProgressTracker,
 }
 
 impl GroupKey {
@@ -215,6 +415,9 @@ pub struct EndpointChannelContainer<R: Ratchet = StackedRatchet> {
     pub(crate) direct_p2p_remote: Option<DirectP2PRemote>,
     pub(crate) endpoint_crypto: PeerSessionCrypto<R>,
     to_default_channel: OrderedChannel,
+    // the send half of the default channel, from which named channels are derived
+    default_sender: PeerChannelSendHalf,
+    flow_control: FlowControl,
     // for UDP
     pub(crate) to_unordered_channel: Option<UnorderedChannelContainer>,
     #[allow(dead_code)]
@@ -223,6 +426,9 @@ pub struct EndpointChannelContainer<R: Ratchet = StackedRatchet> {
 
 pub struct C2SChannelContainer<R: Ratchet = StackedRatchet> {
     to_channel: OrderedChannel,
+    // the send half of the default channel, from which named channels are derived
+    default_sender: PeerChannelSendHalf,
+    flow_control: FlowControl,
     // for UDP
     pub(crate) to_unordered_channel: Option<UnorderedChannelContainer>,
     is_active: Arc<AtomicBool>,
@@ -393,12 +599,13 @@ pub(crate) struct OutboundTransmitterContainer {
     object_notifier: Option<UnboundedSender<()>>,
     waves_in_current_window: usize,
     group_plaintext_length: usize,
-    transmission_start_time: Instant,
     parent_object_total_groups: usize,
     relative_group_id: u32,
     #[allow(dead_code)]
     ticket: Ticket,
     pub has_begun: bool,
+    // the packets sent through the UDP stream that are not yet acknowledged
+    striped: Option<StripedGroup>,
 }
 
 impl OutboundTransmitterContainer {
@@ -414,7 +621,6 @@ impl OutboundTransmitterContainer {
             .hyper_ratchet_container
             .base_constructor
             .take();
-        let transmission_start_time = Instant::now();
         let has_begun = false;
 
         Self {
@@ -423,11 +629,11 @@ impl OutboundTransmitterContainer {
             relative_group_id,
             ticket,
             parent_object_total_groups,
-            transmission_start_time,
             group_plaintext_length,
             object_notifier,
             burst_transmitter,
             waves_in_current_window: 0,
+            striped: None,
         }
     }
 }
@@ -477,10 +683,137 @@ impl GroupReceiverContainer {
 }
 
 impl StateContainerInner {
+    pub(crate) fn pending_tickets(&self) -> Vec<Ticket> {
+        let mut tickets = self
+            .inbound_files
+            .values()
+            .map(|transfer| transfer.ticket)
+            .chain(self.outbound_files.values().map(|transfer| transfer.ticket))
+            .chain(
+                self.outgoing_peer_connect_attempts
+                    .values()
+                    .map(|(ticket, _)| *ticket),
+            )
+            .chain(self.pending_delta_transfers.keys().copied())
+            .chain(self.pending_deduplicated_transfers.keys().copied())
+            .chain(
+                self.enqueued_packets
+                    .values()
+                    .flat_map(|queue| queue.iter().map(|(ticket, ..)| *ticket)),
+            )
+            .collect::<Vec<_>>();
+        tickets.sort();
+        tickets.dedup();
+        tickets
+    }
+
+    /// Returns the total number of entries across the maps of this container, used to detect
+    /// entries that are never removed
+    pub(crate) fn tracked_entries(&self) -> usize {
+        self.enqueued_packets
+            .values()
+            .map(VecDeque::len)
+            .sum::<usize>()
+            + self.updates_in_progress.len()
+            + self.inbound_files.len()
+            + self.outbound_files.len()
+            + self.file_transfer_handles.len()
+            + self.inbound_groups.len()
+            + self.outbound_transmitters.len()
+            + self.peer_kem_states.len()
+            + self.outgoing_peer_connect_attempts.len()
+            + self.pending_shared_object_writes.len()
+            + self.reserved_shared_object_uploads.len()
+            + self.pending_delta_transfers.len()
+            + self.pending_deduplicated_transfers.len()
+            + self.active_virtual_connections.len()
+            + self.hole_puncher_pipes.len()
+            + self.group_channels.len()
+    }
+
+    /// Returns the number of ratchet versions retained by the c2s container and every virtual connection
+    pub(crate) fn toolset_versions_retained(&self) -> usize {
+        let c2s = self
+            .c2s_channel_container
+            .as_ref()
+            .map(|c2s| c2s.peer_session_crypto.toolset.len())
+            .unwrap_or(0);
+
+        c2s + self
+            .active_virtual_connections
+            .values()
+            .filter_map(|vconn| vconn.endpoint_container.as_ref())
+            .map(|endpoint| endpoint.endpoint_crypto.toolset.len())
+            .sum::<usize>()
+    }
+
+    pub(crate) fn stage_dump(&self) -> StageDump {
+        StageDump {
+            session: self.state.load(Ordering::Relaxed),
+            pre_connect: self.pre_connect_state.stage,
+            register: self.register_state.stage,
+            connect: self.connect_state.stage,
+        }
+    }
+
+    /// Returns the pre-connect stage the session stalled in, if any. Each stage is timed from the
+    /// latest packet that advanced it or, if none arrived yet, from `session_start`
+    pub(crate) fn stalled_provisional_stage(
+        &self,
+        session_start: Instant,
+        timeouts: &ProvisionalTimeouts,
+    ) -> Option<ProvisionalStage> {
+        let (stage, timeout) = match self.state.load(Ordering::Relaxed) {
+            SessionState::NeedsRegister => (ProvisionalStage::Register, timeouts.register),
+            SessionState::SocketJustOpened
+            | SessionState::NeedsConnect
+            | SessionState::ConnectionProcess => (ProvisionalStage::Connect, timeouts.connect),
+            SessionState::Connected | SessionState::Disconnected => return None,
+        };
+
+        let stage_start = [
+            self.register_state.last_packet_time,
+            self.connect_state.last_packet_time,
+        ]
+        .into_iter()
+        .flatten()
+        .fold(session_start, std::cmp::max);
+
+        (stage_start.elapsed() > timeout).then_some(stage)
+    }
+
+    /// Drops the peer key exchanges that did not complete within `timeout`, notifying the kernel
+    /// of those initiated locally. Returns the number of exchanges dropped
+    pub(crate) fn reap_stalled_peer_key_exchanges(&mut self, timeout: Duration) -> usize {
+        let stalled = self
+            .peer_kem_states
+            .iter()
+            .filter(|(_, kem_state)| {
+                kem_state.constructor.is_some() && kem_state.created.elapsed() > timeout
+            })
+            .map(|(peer_cid, _)| *peer_cid)
+            .collect::<Vec<_>>();
+
+        for peer_cid in &stalled {
+            log::warn!(target: "citadel", "Key exchange with peer {peer_cid} timed out; dropping");
+            let _ = self.peer_kem_states.remove(peer_cid);
+            if let Some((ticket, _)) = self.outgoing_peer_connect_attempts.remove(peer_cid) {
+                let _ = self
+                    .kernel_tx
+                    .unbounded_send(NodeResult::InternalServerError(InternalServerError {
+                        ticket_opt: Some(ticket),
+                        message: format!("The key exchange with peer {peer_cid} timed out"),
+                    }));
+            }
+        }
+
+        stalled.len()
+    }
+
     /// Creates a new container
     #[allow(clippy::too_many_arguments)]
     pub fn create(
-        kernel_tx: UnboundedSender<NodeResult>,
+        kernel_tx: KernelEventSender,
         hdp_server_remote: NodeRemote,
         keep_alive_timeout_ns: i64,
         state: Arc<Atomic<SessionState>>,
@@ -490,20 +823,33 @@ impl StateContainerInner {
         is_server: bool,
         transfer_stats: TransferStats,
         udp_mode: UdpMode,
+        multipath_counters: Arc<MultipathCounters>,
     ) -> StateContainer {
         let inner = Self {
             outgoing_peer_connect_attempts: Default::default(),
+            pending_shared_object_writes: Default::default(),
+            reserved_shared_object_uploads: Default::default(),
+            pending_delta_transfers: Default::default(),
+            pending_deduplicated_transfers: Default::default(),
             file_transfer_handles: HashMap::new(),
             group_channels: Default::default(),
             udp_mode,
+            transport_security: TransportSecurity::Tcp,
             transfer_stats,
+            multipath_counters,
             queue_handle: Default::default(),
             is_server,
             session_security_settings,
             time_tracker,
             cnac,
             updates_in_progress: HashMap::new(),
+            security_epochs: SecurityEpochs::default(),
+            capabilities: CapabilityState::default(),
+            challengeable_handshake: None,
             hole_puncher_pipes: HashMap::new(),
+            deferred_udp_ready_tx: None,
+            pending_pings: HashMap::new(),
+            pending_channel_closes: HashMap::new(),
             tcp_loaded_status: None,
             enqueued_packets: HashMap::new(),
             state,
@@ -518,8 +864,8 @@ impl StateContainerInner {
             active_virtual_connections: Default::default(),
             network_stats: Default::default(),
             kernel_tx,
-            register_state: packet_flags::cmd::aux::do_register::STAGE0.into(),
-            connect_state: packet_flags::cmd::aux::do_connect::STAGE0.into(),
+            register_state: RegisterState::default(),
+            connect_state: ConnectState::default(),
             inbound_groups: HashMap::new(),
             outbound_transmitters: HashMap::new(),
             peer_kem_states: HashMap::new(),
@@ -553,26 +899,41 @@ impl StateContainerInner {
             .unwrap()
     }
 
+    /// Returns the UDP stream to the given peer, or, to the server if the peer cid is zero
+    fn get_udp_stream(&self, peer_cid: u64) -> Option<&OutboundUdpSender> {
+        if peer_cid == C2S_ENCRYPTION_ONLY {
+            self.udp_primary_outbound_tx.as_ref()
+        } else {
+            self.active_virtual_connections
+                .get(&peer_cid)?
+                .sender
+                .as_ref()?
+                .0
+                .as_ref()
+        }
+    }
+
     /// This assumes the data has reached its destination endpoint, and must be forwarded to the channel
     /// (thus bypassing the unordered kernel)
     pub fn forward_data_to_ordered_channel(
         &mut self,
         target_cid: u64,
         group_id: u64,
+        channel_id: Ticket,
         data: SecBuffer,
     ) -> bool {
         if target_cid == 0 {
             if let Some(c2s_container) = self.c2s_channel_container.as_mut() {
                 return c2s_container
                     .to_channel
-                    .on_packet_received(group_id, data)
+                    .on_packet_received(group_id, channel_id, data)
                     .is_ok();
             }
         } else if let Some(vconn) = self.active_virtual_connections.get_mut(&target_cid) {
             if let Some(channel) = vconn.endpoint_container.as_mut() {
                 return channel
                     .to_default_channel
-                    .on_packet_received(group_id, data)
+                    .on_packet_received(group_id, channel_id, data)
                     .is_ok();
             }
         }
@@ -580,6 +941,119 @@ impl StateContainerInner {
         false
     }
 
+    /// Completes the close of a channel once the adjacent endpoint acknowledges the message that
+    /// closes it
+    pub fn on_channel_close_acknowledged(&mut self, target_cid: u64, ticket: Ticket) {
+        if is_channel_close(ticket) {
+            if let Some(on_closed) = self.pending_channel_closes.remove(&(target_cid, ticket)) {
+                let _ = on_closed.send(());
+            }
+        }
+    }
+
+    /// Opens the named channel `name` over the virtual connection to `target_cid`, or, to the
+    /// server if `target_cid` is zero. Each name may only be open once at a time
+    pub fn open_named_channel(
+        &mut self,
+        target_cid: u64,
+        name: String,
+    ) -> Result<PeerChannel, NetworkError> {
+        let channel_id = named_channel_id(&name);
+        let server_remote = self.hdp_server_remote.clone();
+        let (ordered_channel, default_sender, is_alive, flow_control) = if target_cid == 0 {
+            let c2s_container = self
+                .c2s_channel_container
+                .as_mut()
+                .ok_or(NetworkError::InternalError("C2S channel not loaded"))?;
+            (
+                &mut c2s_container.to_channel,
+                &c2s_container.default_sender,
+                c2s_container.is_active.clone(),
+                &c2s_container.flow_control,
+            )
+        } else {
+            let vconn = self
+                .active_virtual_connections
+                .get_mut(&target_cid)
+                .ok_or_else(|| {
+                    NetworkError::msg(format!(
+                        "Unable to find virtual connection to peer {target_cid}"
+                    ))
+                })?;
+            let endpoint_container = vconn.endpoint_container.as_mut().ok_or_else(|| {
+                NetworkError::msg(format!(
+                    "Unable to access endpoint container to peer {target_cid}"
+                ))
+            })?;
+            (
+                &mut endpoint_container.to_default_channel,
+                &endpoint_container.default_sender,
+                vconn.is_active.clone(),
+                &endpoint_container.flow_control,
+            )
+        };
+
+        let receiver = ordered_channel
+            .open_named_channel(channel_id)
+            .ok_or_else(|| NetworkError::msg(format!("The channel {name} is already open")))?;
+        Ok(default_sender.new_named_channel(name, server_remote, is_alive, receiver, flow_control))
+    }
+
+    fn get_flow_control(&self, target_cid: u64) -> Option<&FlowControl> {
+        if target_cid == 0 {
+            Some(&self.c2s_channel_container.as_ref()?.flow_control)
+        } else {
+            Some(
+                &self
+                    .active_virtual_connections
+                    .get(&target_cid)?
+                    .endpoint_container
+                    .as_ref()?
+                    .flow_control,
+            )
+        }
+    }
+
+    /// Advertises the receive window of the ordered channel to `target_cid` once its first
+    /// message arrives
+    pub fn announce_receive_window(&self, target_cid: u64) -> Result<(), NetworkError> {
+        if let Some(flow_control) = self.get_flow_control(target_cid) {
+            if let Some(update) = flow_control.receive.announce() {
+                return self.send_window_update(flow_control.v_target, update);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn send_window_update(
+        &self,
+        v_target: VirtualTargetType,
+        update: WindowUpdate,
+    ) -> Result<(), NetworkError> {
+        self.send_file_packet(v_target, |hyper_ratchet, target_cid, timestamp| {
+            packet_crafter::group::craft_window_update(
+                hyper_ratchet,
+                target_cid,
+                timestamp,
+                update,
+                SecurityLevel::Standard,
+            )
+        })
+    }
+
+    /// Grants the local sender of the ordered channel to `target_cid` the room advertised by its receiver
+    pub fn on_window_update_received(&self, target_cid: u64, update: WindowUpdate) -> bool {
+        match self.get_flow_control(target_cid) {
+            Some(flow_control) => {
+                flow_control.send.on_window_update(update);
+                true
+            }
+
+            None => false,
+        }
+    }
+
     /// This assumes the data has reached its destination endpoint, and must be forwarded to the channel
     /// (thus bypassing the unordered kernel)
     pub fn forward_data_to_unordered_channel(&self, target_cid: u64, data: SecBuffer) -> bool {
@@ -683,6 +1157,23 @@ impl StateContainerInner {
         }
     }
 
+    /// Returns the type of every active virtual connection
+    pub(crate) fn virtual_connections(&self) -> Vec<VirtualConnectionType> {
+        self.active_virtual_connections
+            .values()
+            .map(|vconn| vconn.connection_type)
+            .collect()
+    }
+
+    /// Removes the virtual connection to `peer_cid`, marking it inactive such that any remaining
+    /// handle stops sending. Returns true if the virtual connection existed
+    pub(crate) fn remove_virtual_connection(&mut self, peer_cid: u64) -> bool {
+        self.active_virtual_connections
+            .remove(&peer_cid)
+            .map(|vconn| vconn.is_active.store(false, Ordering::SeqCst))
+            .is_some()
+    }
+
     /// The inner P2P handles will get dropped, causing the connections to end
     pub fn end_connections(&mut self) {
         self.active_virtual_connections.clear();
@@ -735,6 +1226,13 @@ impl StateContainerInner {
 
         self.updates_in_progress
             .insert(target_cid, endpoint_crypto.update_in_progress.clone());
+        let security_epoch = self
+            .security_epochs
+            .insert(target_cid, endpoint_crypto.latest_usable_version);
+
+        let flow_control = FlowControl::new(connection_type, FLOW_CONTROL_WINDOW);
+        let security_info =
+            SecurityInfo::new(&default_security_settings, self.transport_security.clone());
 
         //let (tx, rx) = futures::channel::mpsc::channel(MAX_OUTGOING_UNPROCESSED_REQUESTS);
         let peer_channel = PeerChannel::new(
@@ -746,6 +1244,9 @@ impl StateContainerInner {
             is_active.clone(),
             channel_rx,
             tx,
+            security_epoch,
+            security_info,
+            &flow_control,
         );
         let to_channel = OrderedChannel::new(channel_tx);
         HdpSession::spawn_message_sender_function(sess.clone(), rx);
@@ -755,6 +1256,8 @@ impl StateContainerInner {
             direct_p2p_remote: None,
             endpoint_crypto,
             to_default_channel: to_channel,
+            default_sender: peer_channel.sender(),
+            flow_control,
             to_unordered_channel: None,
             peer_socket_addr,
         });
@@ -786,6 +1289,27 @@ impl StateContainerInner {
         let (channel_tx, channel_rx) = unbounded();
         let (tx, rx) = crate::proto::outbound_sender::channel(MAX_OUTGOING_UNPROCESSED_REQUESTS);
         let is_active = Arc::new(AtomicBool::new(true));
+        let mut peer_session_crypto = cnac.read().crypt_container.new_session();
+        if let Some(settings) = self.session_security_settings.as_ref() {
+            peer_session_crypto.set_drift_tolerance(settings.drift_tolerance);
+            if let Some(max) = settings.max_toolset_history {
+                peer_session_crypto.set_max_toolset_history(max);
+            }
+            peer_session_crypto.set_precompute_constructor(settings.precompute_constructor);
+        }
+
+        let security_epoch = self.security_epochs.insert(
+            C2S_ENCRYPTION_ONLY,
+            peer_session_crypto.latest_usable_version,
+        );
+        let flow_control = FlowControl::new(
+            VirtualConnectionType::LocalGroupServer(implicated_cid),
+            FLOW_CONTROL_WINDOW,
+        );
+        let security_info = SecurityInfo::new(
+            &self.session_security_settings.unwrap_or_default(),
+            self.transport_security.clone(),
+        );
         let peer_channel = PeerChannel::new(
             self.hdp_server_remote.clone(),
             implicated_cid,
@@ -795,16 +1319,21 @@ impl StateContainerInner {
             is_active.clone(),
             channel_rx,
             tx,
+            security_epoch,
+            security_info,
+            &flow_control,
         );
         HdpSession::spawn_message_sender_function(session.clone(), rx);
 
         let c2s = C2SChannelContainer {
             to_channel: OrderedChannel::new(channel_tx),
+            default_sender: peer_channel.sender(),
+            flow_control,
             to_unordered_channel: None,
             is_active,
             to_primary_stream: session.to_primary_stream.clone().unwrap(),
             channel_signal: None,
-            peer_session_crypto: cnac.read().crypt_container.new_session(),
+            peer_session_crypto,
         };
 
         let updates_in_progress = c2s.peer_session_crypto.update_in_progress.clone();
@@ -1010,7 +1539,7 @@ impl StateContainerInner {
         &mut self,
         header: &LayoutVerified<&[u8], HdpHeader>,
         virtual_target: VirtualTargetType,
-        metadata_orig: VirtualObjectMetadata,
+        mut metadata_orig: VirtualObjectMetadata,
         pers: &PersistenceHandler<R, Fcm>,
         state_container: StateContainer,
         hyper_ratchet: StackedRatchet,
@@ -1018,14 +1547,33 @@ impl StateContainerInner {
         v_target_flipped: VirtualTargetType,
         preferred_primary_stream: OutboundPrimaryStreamSender,
         local_encryption_level: Option<SecurityLevel>,
+        compression: Option<CompressionAlgorithm>,
     ) -> bool {
         let key = FileKey::new(header.session_cid.get(), metadata_orig.object_id);
         let ticket = header.context_info.get().into();
         let is_revfs_pull = local_encryption_level.is_some();
+        let uploader_cid = header.session_cid.get();
+
+        let mut shared_write = None;
+        if matches!(metadata_orig.transfer_type, TransferType::FileTransfer) {
+            if let Some(write) = self
+                .pending_shared_object_writes
+                .remove(&(uploader_cid, metadata_orig.object_id))
+            {
+                if write.name == metadata_orig.name {
+                    log::trace!(target: "citadel", "Redirecting upload of {} to owner {}", write.name, write.owner_cid);
+                    metadata_orig.cid = write.owner_cid;
+                    shared_write = Some(write);
+                } else {
+                    log::warn!(target: "citadel", "Upload {} does not match the shared object {} it was declared for; not redirecting", metadata_orig.name, write.name);
+                }
+            }
+        }
 
         if let std::collections::hash_map::Entry::Vacant(e) = self.inbound_files.entry(key) {
             let (stream_to_hd, stream_to_hd_rx) = unbounded::<Vec<u8>>();
-            let (start_recv_tx, start_recv_rx) = tokio::sync::oneshot::channel::<bool>();
+            let (start_recv_tx, start_recv_rx) =
+                tokio::sync::oneshot::channel::<InboundTransferDecision>();
 
             let security_level_rebound: SecurityLevel = header.security_level.into();
             let timestamp = self.time_tracker.get_global_time_ns();
@@ -1034,8 +1582,14 @@ impl StateContainerInner {
             let metadata = metadata_orig.clone();
             let tt = self.time_tracker;
             let (reception_complete_tx, success_receiving_rx) = tokio::sync::oneshot::channel();
+            let (chunk_queue, chunk_processor_rx) =
+                InboundChunkQueue::new(preferred_primary_stream.clone());
             let entry = InboundFileTransfer {
                 last_group_finish_time: Instant::now(),
+                progress: TransferProgressTracker::new(
+                    metadata_orig.group_count,
+                    metadata_orig.plaintext_length as u64,
+                ),
                 last_group_window_len: 0,
                 object_id,
                 total_groups: metadata_orig.group_count,
@@ -1043,19 +1597,53 @@ impl StateContainerInner {
                 groups_rendered: 0,
                 virtual_target,
                 metadata: metadata.clone(),
-                reception_complete_tx,
-                stream_to_hd,
                 local_encryption_level,
+                chunk_queue,
             };
 
             e.insert(entry);
-            let (handle, tx_status) = ObjectTransferHandler::new(
+            let (handle, tx_status, control_rx) = ObjectTransferHandler::new(
                 header.session_cid.get(),
                 header.target_cid.get(),
                 ObjectTransferOrientation::Receiver,
                 Some(start_recv_tx),
+                (!is_revfs_pull).then(|| {
+                    InboundTransferProposal::new(header.session_cid.get(), &metadata_orig)
+                }),
                 is_revfs_pull,
             );
+            Self::spawn_transfer_control_listener(
+                &state_container,
+                control_rx,
+                key,
+                v_target_flipped,
+                ObjectTransferOrientation::Receiver,
+                ticket,
+                security_level_rebound,
+            );
+            // which static hr do we need? Since we are receiving this object, always our local account's
+            let local_decryption = local_encryption_level.map(|level| {
+                let static_aux_hr = self
+                    .cnac
+                    .as_ref()
+                    .unwrap()
+                    .get_static_auxiliary_hyper_ratchet();
+                (static_aux_hr, level)
+            });
+            Self::spawn_inbound_chunk_processor(
+                &state_container,
+                chunk_processor_rx,
+                stream_to_hd,
+                reception_complete_tx,
+                tx_status.clone(),
+                key,
+                v_target_flipped,
+                ticket,
+                security_level_rebound,
+                metadata_orig.group_count,
+                compression,
+                local_decryption,
+            );
             self.file_transfer_handles.insert(
                 key,
                 crate::proto::outbound_sender::UnboundedSender(tx_status.clone()),
@@ -1072,13 +1660,68 @@ impl StateContainerInner {
                 let res = if is_revfs_pull {
                     // auto-accept for revfs pull requests
                     log::trace!(target: "citadel", "Auto-accepting for REVFS pull request");
-                    Ok(true)
+                    Ok(InboundTransferDecision::Accept)
                 } else {
                     log::trace!(target: "citadel", "Will not auto-accept");
                     start_recv_rx.await
                 };
 
-                let accepted = res.as_ref().map(|r| *r).unwrap_or(false);
+                let decision = res.unwrap_or_else(|err| {
+                    log::error!(target: "citadel", "Start_recv_rx failed: {:?}", err);
+                    InboundTransferDecision::Reject("The receiver dropped the transfer".into())
+                });
+
+                // the grant may have been revoked since the write was requested
+                let decision = match shared_write {
+                    Some(write) if !matches!(decision, InboundTransferDecision::Reject(_)) => {
+                        match pers
+                            .get_shared_object_permission(
+                                write.owner_cid,
+                                &write.name,
+                                uploader_cid,
+                            )
+                            .await
+                        {
+                            Ok(Some(permission))
+                                if permission.allows(SharedObjectPermission::ReadWrite) =>
+                            {
+                                decision
+                            }
+                            res => {
+                                log::warn!(target: "citadel", "Refusing write of {uploader_cid} to {} owned by {}: {res:?}", write.name, write.owner_cid);
+                                InboundTransferDecision::Reject(format!(
+                                    "Insufficient permissions for object {} owned by {}",
+                                    write.name, write.owner_cid
+                                ))
+                            }
+                        }
+                    }
+                    _ => decision,
+                };
+
+                let decision = match decision {
+                    InboundTransferDecision::AcceptInto(ref destination)
+                    | InboundTransferDecision::ScanThenAccept(ref destination, _) => {
+                        match RedirectedObjectMetadata::check_destination(destination) {
+                            Ok(()) => decision,
+                            Err(err) => {
+                                log::error!(target: "citadel", "Refusing inbound transfer: {err:?}");
+                                InboundTransferDecision::Reject(
+                                    "The receiver chose an invalid destination".to_string(),
+                                )
+                            }
+                        }
+                    }
+                    decision => decision,
+                };
+
+                let (accepted, rejection_reason) = match &decision {
+                    InboundTransferDecision::Reject(reason) => (false, Some(reason.clone())),
+                    // the handler never relays a deferral
+                    InboundTransferDecision::Defer => (false, None),
+                    _ => (true, None),
+                };
+
                 // first, send a rebound signal immediately to the sender
                 // to ensure the sender knows if the user accepted or not
                 let file_header_ack = packet_crafter::file::craft_file_header_ack_packet(
@@ -1090,6 +1733,8 @@ impl StateContainerInner {
                     security_level_rebound,
                     v_target_flipped,
                     timestamp,
+                    compression.filter(|_| accepted),
+                    rejection_reason,
                 );
 
                 if let Err(err) = preferred_primary_stream.unbounded_send(file_header_ack) {
@@ -1097,76 +1742,113 @@ impl StateContainerInner {
                     return;
                 }
 
-                match res {
-                    Ok(accepted) => {
-                        if accepted {
-                            // local user accepts the file transfer. Alert the adjacent end
-                            // and get ready to begin streaming
-                            match pers
-                                .stream_object_to_backend(
-                                    stream_to_hd_rx,
-                                    Arc::new(metadata.clone()),
-                                    tx_status.clone(),
-                                )
-                                .await
-                            {
-                                Ok(()) => {
-                                    log::info!(target: "citadel", "Successfully synced file to backend | {is_revfs_pull}");
-                                    let status = match success_receiving_rx.await {
-                                        Ok(header) => {
-                                            // write the header
-                                            let wave_ack = packet_crafter::group::craft_wave_ack(
-                                                &hyper_ratchet,
-                                                header.context_info.get() as u32,
-                                                get_resp_target_cid_from_header(&header),
-                                                header.group.get(),
-                                                header.wave_id.get(),
-                                                tt.get_global_time_ns(),
-                                                None,
-                                                header.security_level.into(),
-                                            );
-
-                                            send_with_error_logging(
-                                                &preferred_primary_stream,
-                                                wave_ack,
-                                            );
-
-                                            ObjectTransferStatus::ReceptionComplete
+                if !accepted {
+                    // user did not accept. cleanup local
+                    let mut state_container = inner_mut_state!(state_container);
+                    let _ = state_container.inbound_files.remove(&key);
+                    let _ = state_container.file_transfer_handles.remove(&key);
+                    return;
+                }
+
+                let (sink_metadata, scanner): (Arc<dyn StreamableTargetInformation>, _) =
+                    match decision {
+                        InboundTransferDecision::AcceptInto(destination) => (
+                            Arc::new(RedirectedObjectMetadata {
+                                metadata,
+                                destination,
+                            }),
+                            None,
+                        ),
+                        InboundTransferDecision::ScanThenAccept(destination, scanner) => (
+                            Arc::new(RedirectedObjectMetadata {
+                                metadata,
+                                destination,
+                            }),
+                            Some(scanner),
+                        ),
+                        _ => (Arc::new(metadata), None),
+                    };
+
+                // local user accepts the file transfer. Alert the adjacent end
+                // and get ready to begin streaming
+                match pers
+                    .stream_object_to_backend(
+                        stream_to_hd_rx,
+                        sink_metadata.clone(),
+                        tx_status.clone(),
+                    )
+                    .await
+                {
+                    Ok(()) => {
+                        log::info!(target: "citadel", "Successfully synced file to backend | {is_revfs_pull}");
+                        let status = match success_receiving_rx.await {
+                            Ok(header) => {
+                                if let (Some(scanner), Some(path)) =
+                                    (scanner, sink_metadata.get_destination())
+                                {
+                                    if let Err(reason) = scanner.scan(path).await {
+                                        log::warn!(target: "citadel", "Scanner refused inbound object {path:?}: {reason}");
+                                        if let Err(err) = tokio::fs::remove_file(path).await {
+                                            log::error!(target: "citadel", "Unable to delete refused object {path:?}: {err:?}");
                                         }
 
-                                        Err(_) => ObjectTransferStatus::Fail(
-                                            "An unknown error occurred while receiving file"
-                                                .to_string(),
-                                        ),
-                                    };
+                                        // the sender is told to stop instead of receiving the final ACK
+                                        if let Err(err) = inner_state!(state_container)
+                                            .send_transfer_control(
+                                                v_target_flipped,
+                                                ticket,
+                                                security_level_rebound,
+                                                object_id,
+                                                ObjectTransferControl::Cancel,
+                                                ObjectTransferOrientation::Receiver,
+                                            )
+                                        {
+                                            log::error!(target: "citadel", "Unable to cancel {key:?} after refusing the object: {err:?}");
+                                        }
 
-                                    let _ = tx_status.send(status);
+                                        let _ = tx_status.send(ObjectTransferStatus::Fail(
+                                            format!("The object was refused: {reason}"),
+                                        ));
+                                        return;
+                                    }
                                 }
-                                Err(err) => {
-                                    log::error!(target: "citadel", "Unable to sync file to backend: {:?}", err);
+
+                                // write the header
+                                let wave_ack = packet_crafter::group::craft_wave_ack(
+                                    &hyper_ratchet,
+                                    header.context_info.get() as u32,
+                                    get_resp_target_cid_from_header(&header),
+                                    header.group.get(),
+                                    header.wave_id.get(),
+                                    tt.get_global_time_ns(),
+                                    None,
+                                    header.security_level.into(),
+                                );
+
+                                send_with_error_logging(&preferred_primary_stream, wave_ack);
+
+                                ObjectTransferStatus::ReceptionComplete
+                            }
+
+                            Err(_) => {
+                                if !inner_state!(state_container)
+                                    .file_transfer_handles
+                                    .contains_key(&key)
+                                {
+                                    log::trace!(target: "citadel", "Inbound transfer {key:?} was cancelled");
+                                    return;
                                 }
+
+                                ObjectTransferStatus::Fail(
+                                    "An unknown error occurred while receiving file".to_string(),
+                                )
                             }
-                        } else {
-                            // user did not accept. cleanup local
-                            let mut state_container = inner_mut_state!(state_container);
-                            let _ = state_container.inbound_files.remove(&key);
-                            let _ = state_container.file_transfer_handles.remove(&key);
-                        }
-                    }
+                        };
 
+                        let _ = tx_status.send(status);
+                    }
                     Err(err) => {
-                        log::error!(target: "citadel", "Start_recv_rx failed: {:?}", err);
-                        let err_packet = packet_crafter::file::craft_file_header_ack_packet(
-                            &hyper_ratchet,
-                            false,
-                            object_id,
-                            target_cid,
-                            ticket,
-                            security_level_rebound,
-                            virtual_target,
-                            timestamp,
-                        );
-                        let _ = preferred_primary_stream.unbounded_send(err_packet);
+                        log::error!(target: "citadel", "Unable to sync file to backend: {:?}", err);
                     }
                 }
             };
@@ -1179,23 +1861,34 @@ impl StateContainerInner {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn on_file_header_ack_received(
         &mut self,
+        state_container: &StateContainer,
         success: bool,
         implicated_cid: u64,
         ticket: Ticket,
         object_id: u32,
         v_target: VirtualTargetType,
+        security_level: SecurityLevel,
+        compression: Option<CompressionAlgorithm>,
+        rejection_reason: Option<String>,
     ) -> Option<()> {
-        let (key, receiver_cid) = match v_target {
-            VirtualConnectionType::LocalGroupPeer(implicated_cid, target_cid) => {
+        let (key, receiver_cid, local_v_target) = match v_target {
+            VirtualConnectionType::LocalGroupPeer(peer_cid, target_cid) => {
                 // since the order hasn't flipped yet, get the implicated cid
-                (FileKey::new(implicated_cid, object_id), target_cid)
+                (
+                    FileKey::new(peer_cid, object_id),
+                    target_cid,
+                    VirtualConnectionType::LocalGroupPeer(implicated_cid, peer_cid),
+                )
             }
 
-            VirtualConnectionType::LocalGroupServer(implicated_cid) => {
-                (FileKey::new(implicated_cid, object_id), 0)
-            }
+            VirtualConnectionType::LocalGroupServer(implicated_cid) => (
+                FileKey::new(implicated_cid, object_id),
+                0,
+                VirtualConnectionType::LocalGroupServer(implicated_cid),
+            ),
 
             _ => {
                 log::error!(target: "citadel", "HyperWAN functionality not yet enabled");
@@ -1206,16 +1899,30 @@ impl StateContainerInner {
         if success {
             // remove the inbound file transfer, send the signals to end async loops, and tell the kernel
             if let Some(file_transfer) = self.outbound_files.get_mut(&key) {
+                // the compression is only applied if it was offered in the first place
+                if let Some(compression_tx) = file_transfer.compression_tx.take() {
+                    let _ = compression_tx.send(compression);
+                }
                 // start the async task pulling from the async cryptscrambler
                 file_transfer.start.take()?.send(true).ok()?;
-                let (handle, tx) = ObjectTransferHandler::new(
+                let (handle, tx, control_rx) = ObjectTransferHandler::new(
                     implicated_cid,
                     receiver_cid,
                     ObjectTransferOrientation::Sender,
                     None,
+                    None,
                     true, //this value does not matter here since start_recv_tx is false. TODO: refactor
                 );
                 tx.send(ObjectTransferStatus::TransferBeginning).ok()?;
+                Self::spawn_transfer_control_listener(
+                    state_container,
+                    control_rx,
+                    key,
+                    local_v_target,
+                    ObjectTransferOrientation::Sender,
+                    ticket,
+                    security_level,
+                );
                 let _ = self
                     .file_transfer_handles
                     .insert(key, crate::proto::outbound_sender::UnboundedSender(tx));
@@ -1236,11 +1943,16 @@ impl StateContainerInner {
                 file_transfer.stop_tx?.send(()).ok()?;
                 // stop the async task pulling from the async cryptscrambler
                 file_transfer.start?.send(false).ok()?;
+                let message = match rejection_reason {
+                    Some(reason) => format!(
+                        "The adjacent node did not accept the file transfer request: {reason}"
+                    ),
+                    None => "The adjacent node did not accept the file transfer request".into(),
+                };
                 let _ = self
                     .kernel_tx
                     .unbounded_send(NodeResult::InternalServerError(InternalServerError {
-                        message: "The adjacent node did not accept the file transfer request"
-                            .into(),
+                        message,
                         ticket_opt: Some(ticket),
                     }));
             } else {
@@ -1251,27 +1963,444 @@ impl StateContainerInner {
         Some(())
     }
 
-    /// This tells us that we should burst-send the packets now. Returns false if the UDP sockets disconnected
-    /// `to_primary_stream`: If None, will use the Burst Transmitter
-    /// `proposed_window`: In TCP only mode, this won't matter since reliability is handled by the TCP layer. As such, in TCP only mode
-    /// the tcp sender dispatches ALL packets
-    /// NOTE! object ID is in wave_id for header ACKS
-    /// NOTE: If object id != 0, then this header ack belongs to a file transfer and must thus be transmitted via TCP
-    #[allow(unused_results)]
+    /// Relays the [`ObjectTransferControl`] signals issued via the local [`ObjectTransferHandler`]
+    /// to the adjacent node, then applies them locally. The task ends once the handler drops
     #[allow(clippy::too_many_arguments)]
-    pub fn on_group_header_ack_received(
-        &mut self,
-        base_session_secrecy_mode: SecrecyMode,
-        peer_cid: u64,
-        target_cid: u64,
-        group_id: u64,
-        next_window: Option<RangeInclusive<u32>>,
-        transfer: KemTransferStatus,
-        fast_msg: bool,
-    ) -> bool {
-        let key = GroupKey::new(peer_cid, group_id);
+    fn spawn_transfer_control_listener(
+        state_container: &StateContainer,
+        mut control_rx: tokio::sync::mpsc::UnboundedReceiver<ObjectTransferControl>,
+        key: FileKey,
+        v_target: VirtualTargetType,
+        orientation: ObjectTransferOrientation,
+        ticket: Ticket,
+        security_level: SecurityLevel,
+    ) {
+        let state_container = state_container.as_weak();
+        let task = async move {
+            while let Some(signal) = control_rx.recv().await {
+                let state_container =
+                    if let Some(state_container) = StateContainer::upgrade_weak(&state_container) {
+                        state_container
+                    } else {
+                        return;
+                    };
 
-        let constructor = if let Some(outbound_container) = self.outbound_transmitters.get_mut(&key)
+                let mut state_container = inner_mut_state!(state_container);
+                if !state_container.file_transfer_handles.contains_key(&key) {
+                    log::warn!(target: "citadel", "Transfer {key:?} is no longer active; ignoring {signal:?}");
+                    continue;
+                }
+
+                if let Err(err) = state_container.send_transfer_control(
+                    v_target,
+                    ticket,
+                    security_level,
+                    key.object_id,
+                    signal,
+                    orientation,
+                ) {
+                    log::error!(target: "citadel", "Unable to relay {signal:?} for {key:?}: {err:?}");
+                }
+
+                state_container.apply_transfer_control(key, orientation, signal);
+            }
+        };
+
+        spawn!(task);
+    }
+
+    /// Decompresses, verifies and locally decrypts the completed groups of an inbound object on the
+    /// blocking pool before forwarding them to the backend. Groups are processed in order, and
+    /// verification is allowed to trail the network so that hashing large objects does not stall
+    /// the session. The object is only declared complete once the final content hash matches
+    #[allow(clippy::too_many_arguments)]
+    fn spawn_inbound_chunk_processor(
+        state_container: &StateContainer,
+        mut jobs: Receiver<InboundChunkJob>,
+        stream_to_hd: UnboundedSender<Vec<u8>>,
+        reception_complete_tx: tokio::sync::oneshot::Sender<HdpHeader>,
+        tx_status: tokio::sync::mpsc::UnboundedSender<ObjectTransferStatus>,
+        key: FileKey,
+        v_target: VirtualTargetType,
+        ticket: Ticket,
+        security_level: SecurityLevel,
+        total_groups: usize,
+        compression: Option<CompressionAlgorithm>,
+        local_decryption: Option<(StackedRatchet, SecurityLevel)>,
+    ) {
+        let state_container = state_container.as_weak();
+        let task = async move {
+            let res: Result<Option<HdpHeader>, CryptError> = async {
+                let mut verifier: Option<ManifestVerifier> = None;
+                let mut chunks_processed = 0;
+                // the groups of an object form a single compression stream
+                let mut decompressor = compression.map(|_| GroupDecompressor::new());
+                while let Some(job) = jobs.recv().await {
+                    match job {
+                        InboundChunkJob::Manifest(manifest_verifier) => {
+                            verifier = Some(manifest_verifier);
+                        }
+
+                        InboundChunkJob::Chunk(chunk) => {
+                            let local_decryption = local_decryption.clone();
+                            let mut job_verifier = verifier.take();
+                            let mut job_decompressor = decompressor.take();
+                            let (chunk, job_verifier, job_decompressor) =
+                                citadel_io::spawn_blocking(move || {
+                                    let chunk = match job_decompressor.as_mut() {
+                                        Some(decompressor) => decompressor.decompress(&chunk)?,
+                                        None => chunk,
+                                    };
+
+                                    if let Some(verifier) = job_verifier.as_mut() {
+                                        verifier.verify_next(&chunk)?;
+                                    }
+
+                                    let chunk = match local_decryption {
+                                        Some((static_aux_hr, level)) => {
+                                            static_aux_hr.local_decrypt(chunk, level)?
+                                        }
+                                        None => chunk,
+                                    };
+
+                                    Ok::<_, CryptError>((chunk, job_verifier, job_decompressor))
+                                })
+                                .await
+                                .map_err(|err| CryptError::Decrypt(err.message))??;
+
+                            // without a manifest, the chunks were only decompressed and decrypted
+                            chunks_processed += 1;
+                            let _ = tx_status.send(ObjectTransferStatus::VerificationTick(
+                                chunks_processed,
+                                total_groups,
+                            ));
+
+                            verifier = job_verifier;
+                            decompressor = job_decompressor;
+                            stream_to_hd
+                                .unbounded_send(chunk)
+                                .map_err(|err| CryptError::Decrypt(err.to_string()))?;
+                        }
+
+                        InboundChunkJob::Finish(header) => {
+                            if let Some(verifier) = verifier.take() {
+                                citadel_io::spawn_blocking(move || verifier.finish())
+                                    .await
+                                    .map_err(|err| CryptError::Decrypt(err.message))??;
+                            }
+
+                            return Ok(Some(header));
+                        }
+                    }
+                }
+
+                // the inbound object was dropped before completion (e.g., cancelled or expired)
+                Ok(None)
+            }
+            .await;
+
+            match res {
+                Ok(Some(header)) => {
+                    // the inbound object is only forgotten once fully verified
+                    if let Some(state_container) = StateContainer::upgrade_weak(&state_container) {
+                        let mut state_container = inner_mut_state!(state_container);
+                        let _ = state_container.inbound_files.remove(&key);
+                        let _ = state_container.file_transfer_handles.remove(&key);
+                    }
+
+                    // dropping stream_to_hd after this allows the backend to finish syncing
+                    let _ = reception_complete_tx.send(header);
+                }
+
+                Ok(None) => {
+                    log::trace!(target: "citadel", "Inbound object {key:?} ended before completion");
+                }
+
+                Err(err) => {
+                    let state_container = if let Some(state_container) =
+                        StateContainer::upgrade_weak(&state_container)
+                    {
+                        state_container
+                    } else {
+                        return;
+                    };
+
+                    let mut state_container = inner_mut_state!(state_container);
+                    state_container.on_chunk_verification_failed(
+                        key,
+                        v_target,
+                        ticket,
+                        security_level,
+                        err.into_string(),
+                        &tx_status,
+                    );
+                }
+            }
+        };
+
+        spawn!(task);
+    }
+
+    fn send_transfer_control(
+        &self,
+        v_target: VirtualTargetType,
+        ticket: Ticket,
+        security_level: SecurityLevel,
+        object_id: u32,
+        signal: ObjectTransferControl,
+        orientation: ObjectTransferOrientation,
+    ) -> Result<(), NetworkError> {
+        self.send_file_packet(v_target, |hyper_ratchet, target_cid, timestamp| {
+            packet_crafter::file::craft_transfer_control_packet(
+                hyper_ratchet,
+                security_level,
+                ticket,
+                timestamp,
+                target_cid,
+                object_id,
+                signal,
+                orientation,
+            )
+        })
+    }
+
+    pub(crate) fn send_transfer_manifest(
+        &self,
+        v_target: VirtualTargetType,
+        ticket: Ticket,
+        security_level: SecurityLevel,
+        object_id: u32,
+        manifest: TransferManifest,
+    ) -> Result<(), NetworkError> {
+        self.send_file_packet(v_target, |hyper_ratchet, target_cid, timestamp| {
+            packet_crafter::file::craft_transfer_manifest_packet(
+                hyper_ratchet,
+                security_level,
+                ticket,
+                timestamp,
+                target_cid,
+                object_id,
+                manifest,
+            )
+        })
+    }
+
+    pub(crate) fn send_delta_signature_request(
+        &self,
+        v_target: VirtualTargetType,
+        ticket: Ticket,
+        security_level: SecurityLevel,
+        name: String,
+        block_size: usize,
+    ) -> Result<(), NetworkError> {
+        self.send_file_packet(v_target, |hyper_ratchet, target_cid, timestamp| {
+            packet_crafter::file::craft_delta_signature_request(
+                hyper_ratchet,
+                security_level,
+                ticket,
+                timestamp,
+                target_cid,
+                name,
+                block_size,
+            )
+        })
+    }
+
+    pub(crate) fn send_chunk_query(
+        &self,
+        v_target: VirtualTargetType,
+        ticket: Ticket,
+        security_level: SecurityLevel,
+        object: &ChunkedObject,
+    ) -> Result<(), NetworkError> {
+        self.send_file_packet(v_target, |hyper_ratchet, target_cid, timestamp| {
+            packet_crafter::file::craft_chunk_query(
+                hyper_ratchet,
+                security_level,
+                ticket,
+                timestamp,
+                target_cid,
+                object.name.clone(),
+                object.chunk_size,
+                object.hashes.clone(),
+            )
+        })
+    }
+
+    /// Crafts a packet using the latest ratchet for `v_target` (from the local node's perspective)
+    /// and sends it through the preferred primary stream
+    fn send_file_packet(
+        &self,
+        v_target: VirtualTargetType,
+        craft: impl FnOnce(&StackedRatchet, u64, i64) -> BytesMut,
+    ) -> Result<(), NetworkError> {
+        let (crypto, target_cid, primary_stream) = match v_target {
+            VirtualConnectionType::LocalGroupServer(_) => (
+                self.get_c2s_crypto(),
+                C2S_ENCRYPTION_ONLY,
+                self.get_primary_stream(),
+            ),
+
+            VirtualConnectionType::LocalGroupPeer(_, peer_cid) => (
+                self.get_peer_session_crypto(peer_cid),
+                peer_cid,
+                Some(self.get_preferred_stream(peer_cid)),
+            ),
+
+            _ => {
+                return Err(NetworkError::InternalError(
+                    "HyperWAN functionality not yet enabled",
+                ))
+            }
+        };
+
+        let hyper_ratchet = crypto
+            .and_then(|crypto| crypto.get_hyper_ratchet(None))
+            .ok_or(NetworkError::InternalError(
+                "Unable to obtain latest ratchet",
+            ))?;
+        let primary_stream =
+            primary_stream.ok_or(NetworkError::InternalError("Primary stream not loaded"))?;
+
+        let packet = craft(
+            hyper_ratchet,
+            target_cid,
+            self.time_tracker.get_global_time_ns(),
+        );
+
+        primary_stream
+            .unbounded_send(packet)
+            .map_err(|err| NetworkError::Generic(err.to_string()))
+    }
+
+    /// Called when the sender declares the manifest of an inbound object. Each group is verified
+    /// against the manifest by the object's chunk processor
+    pub fn on_transfer_manifest_received(
+        &mut self,
+        key: FileKey,
+        manifest: TransferManifest,
+    ) -> Result<(), NetworkError> {
+        let file_transfer = self.inbound_files.get_mut(&key).ok_or_else(|| {
+            NetworkError::msg(format!("inbound_files does not contain key for {key:?}"))
+        })?;
+
+        if manifest.chunk_count() != file_transfer.total_groups
+            || manifest.total_size != file_transfer.metadata.plaintext_length as u64
+        {
+            return Err(NetworkError::msg(format!(
+                "Manifest for {key:?} does not match the declared object metadata"
+            )));
+        }
+
+        file_transfer
+            .chunk_queue
+            .push(InboundChunkJob::Manifest(ManifestVerifier::new(manifest)))
+    }
+
+    /// Called when the adjacent node pauses, resumes or cancels a transfer
+    pub fn on_transfer_control_received(
+        &mut self,
+        key: FileKey,
+        signal: ObjectTransferControl,
+        issuer_orientation: ObjectTransferOrientation,
+    ) -> bool {
+        let local_orientation = match issuer_orientation {
+            ObjectTransferOrientation::Sender => ObjectTransferOrientation::Receiver,
+            ObjectTransferOrientation::Receiver => ObjectTransferOrientation::Sender,
+        };
+
+        self.apply_transfer_control(key, local_orientation, signal)
+    }
+
+    /// Returns false if the transfer does not exist locally
+    fn apply_transfer_control(
+        &mut self,
+        key: FileKey,
+        local_orientation: ObjectTransferOrientation,
+        signal: ObjectTransferControl,
+    ) -> bool {
+        if !self.file_transfer_handles.contains_key(&key) {
+            return false;
+        }
+
+        match (local_orientation, signal) {
+            (
+                ObjectTransferOrientation::Sender,
+                ObjectTransferControl::Pause | ObjectTransferControl::Resume,
+            ) => {
+                if let Some(file_transfer) = self.outbound_files.get(&key) {
+                    let _ = file_transfer
+                        .pause_tx
+                        .send(signal == ObjectTransferControl::Pause);
+                }
+            }
+
+            (ObjectTransferOrientation::Sender, ObjectTransferControl::Cancel) => {
+                if let Some(mut file_transfer) = self.outbound_files.remove(&key) {
+                    if let Some(stop_tx) = file_transfer.stop_tx.take() {
+                        let _ = stop_tx.send(());
+                    }
+                    // wake the group-sending subroutine in case it is awaiting the in-flight group
+                    let _ = file_transfer.next_gs_alerter.unbounded_send(());
+                }
+
+                self.outbound_transmitters.retain(|group_key, container| {
+                    group_key.target_cid != key.target_cid
+                        || container.burst_transmitter.object_id != key.object_id
+                });
+            }
+
+            (ObjectTransferOrientation::Receiver, ObjectTransferControl::Cancel) => {
+                let _ = self.inbound_files.remove(&key);
+                self.inbound_groups.retain(|group_key, container| {
+                    group_key.target_cid != key.target_cid || container.object_id != key.object_id
+                });
+            }
+
+            // pausing is enforced by the sender
+            (ObjectTransferOrientation::Receiver, _) => {}
+        }
+
+        let status = match signal {
+            ObjectTransferControl::Pause => ObjectTransferStatus::Paused,
+            ObjectTransferControl::Resume => ObjectTransferStatus::Resumed,
+            ObjectTransferControl::Cancel => ObjectTransferStatus::Cancelled,
+        };
+
+        if let Some(tx) = self.file_transfer_handles.get(&key) {
+            let _ = tx.unbounded_send(status);
+        }
+
+        if signal == ObjectTransferControl::Cancel {
+            let _ = self.file_transfer_handles.remove(&key);
+        }
+
+        true
+    }
+
+    /// This tells us that we should burst-send the packets now. Returns false if the UDP sockets disconnected
+    /// `to_primary_stream`: If None, will use the Burst Transmitter
+    /// `proposed_window`: In TCP only mode, this won't matter since reliability is handled by the TCP layer. As such, in TCP only mode
+    /// the tcp sender dispatches ALL packets
+    /// NOTE! object ID is in wave_id for header ACKS
+    /// NOTE: If object id != 0, then this header ack belongs to a file transfer and must thus be transmitted via TCP,
+    /// unless the object is large enough to be striped across both the TCP and UDP streams
+    #[allow(unused_results)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn on_group_header_ack_received(
+        &mut self,
+        state_container: &StateContainer,
+        base_session_secrecy_mode: SecrecyMode,
+        peer_cid: u64,
+        target_cid: u64,
+        group_id: u64,
+        next_window: Option<RangeInclusive<u32>>,
+        transfer: KemTransferStatus,
+        fast_msg: bool,
+    ) -> bool {
+        let key = GroupKey::new(peer_cid, group_id);
+
+        let constructor = if let Some(outbound_container) = self.outbound_transmitters.get_mut(&key)
         {
             outbound_container
                 .ratchet_constructor
@@ -1301,12 +2430,96 @@ impl StateContainerInner {
             return true;
         }
 
+        let udp_cid = if target_cid != C2S_ENCRYPTION_ONLY {
+            peer_cid
+        } else {
+            C2S_ENCRYPTION_ONLY
+        };
+        let to_udp_stream = self.get_udp_stream(udp_cid).cloned();
+
         let outbound_container = self.outbound_transmitters.get_mut(&key).unwrap();
         outbound_container.waves_in_current_window = next_window.unwrap_or(0..=0).count();
-        // file-transfer, or TCP only mode since next_window is none. Use TCP
-        outbound_container
-            .burst_transmitter
-            .transmit_tcp_file_transfer()
+        let file_key = FileKey::new(peer_cid, outbound_container.burst_transmitter.object_id);
+        let congestion = self
+            .outbound_files
+            .get_mut(&file_key)
+            .and_then(|transfer| transfer.multipath.as_mut())
+            .filter(|congestion| congestion.udp_share() > 0f32);
+
+        if let (Some(to_udp_stream), Some(congestion)) = (to_udp_stream, congestion) {
+            let striped = outbound_container
+                .burst_transmitter
+                .transmit_multipath_file_transfer(&to_udp_stream, congestion);
+            if !striped.is_empty() {
+                outbound_container.striped = Some(striped);
+                Self::spawn_multipath_loss_detector(state_container, key, file_key);
+            }
+
+            true
+        } else {
+            // file-transfer, or TCP only mode since next_window is none. Use TCP
+            outbound_container
+                .burst_transmitter
+                .transmit_tcp_file_transfer()
+        }
+    }
+
+    /// Periodically checks if the UDP packets of a striped group stopped being acknowledged, in
+    /// which case they are retransmitted through the primary stream
+    fn spawn_multipath_loss_detector(
+        state_container: &StateContainer,
+        key: GroupKey,
+        file_key: FileKey,
+    ) {
+        let state_container = state_container.as_weak();
+        let task = async move {
+            loop {
+                tokio::time::sleep(MULTIPATH_LOSS_TIMEOUT / 2).await;
+                let state_container =
+                    if let Some(state_container) = StateContainer::upgrade_weak(&state_container) {
+                        state_container
+                    } else {
+                        return;
+                    };
+
+                let mut state_container = inner_mut_state!(state_container);
+                let state_container = &mut *state_container;
+                let container =
+                    if let Some(container) = state_container.outbound_transmitters.get_mut(&key) {
+                        container
+                    } else {
+                        // the group finished
+                        return;
+                    };
+
+                let striped = if let Some(striped) = container.striped.as_mut() {
+                    striped
+                } else {
+                    return;
+                };
+
+                if let Some(lost) = striped.take_stalled() {
+                    log::warn!(target: "citadel", "[MULTIPATH] {} UDP packets of {:?} stalled", lost.len(), key);
+                    if let Some(congestion) = state_container
+                        .outbound_files
+                        .get_mut(&file_key)
+                        .and_then(|transfer| transfer.multipath.as_mut())
+                    {
+                        congestion.on_udp_loss(lost.len());
+                    }
+
+                    container
+                        .burst_transmitter
+                        .retransmit_through_primary_stream(lost);
+                }
+
+                if striped.is_empty() {
+                    return;
+                }
+            }
+        };
+
+        spawn!(task);
     }
 
     pub fn on_group_payload_received(
@@ -1368,50 +2581,37 @@ impl StateContainerInner {
         ) {
             GroupReceiverStatus::GROUP_COMPLETE(_last_wid) => {
                 log::trace!(target: "citadel", "GROUP {} COMPLETE. Total groups: {}", group_id, file_container.total_groups);
-                let mut chunk = self
+                let chunk = self
                     .inbound_groups
                     .remove(&group_key)
                     .unwrap()
                     .receiver
                     .finalize();
+                let progress = file_container
+                    .progress
+                    .on_chunk_complete(group_id as usize, chunk.len() as u64);
 
-                if let Some(local_encryption_level) = file_container.local_encryption_level {
-                    // which static hr do we need? Since we are receiving this chunk, always our local account's
-                    let static_aux_hr = self
-                        .cnac
-                        .as_ref()
-                        .unwrap()
-                        .get_static_auxiliary_hyper_ratchet();
-                    chunk = static_aux_hr
-                        .local_decrypt(chunk, local_encryption_level)
-                        .map_err(|err| NetworkError::msg(err.into_string()))?;
-                }
-
+                // decompression, verification and local decryption happen off the session task
                 file_container
-                    .stream_to_hd
-                    .unbounded_send(chunk)
-                    .map_err(|err| NetworkError::Generic(err.to_string()))?;
+                    .chunk_queue
+                    .push(InboundChunkJob::Chunk(chunk))?;
 
                 send_wave_ack = true;
 
                 if group_id as usize == file_container.total_groups - 1 {
                     complete = true;
-                    let file_container = self.inbound_files.remove(&file_key).unwrap();
-                    // status of reception complete now located where the streaming to HD completes
-                    // we need only take the sender and send a signal to prove that we finished correctly here
-                    // TODO: it seems to be sending the file before the backend streamer even gets a chance to finish
+                    file_transfer_handle
+                        .unbounded_send(ObjectTransferStatus::NetworkComplete)
+                        .map_err(|err| NetworkError::Generic(err.to_string()))?;
+                    // status of reception complete now located where the streaming to HD completes.
+                    // The processor signals completion, then removes the inbound object, once the
+                    // remaining chunks are verified
                     file_container
-                        .reception_complete_tx
-                        .send(header.clone())
-                        .map_err(|_| NetworkError::msg("reception_complete_tx err"))?;
+                        .chunk_queue
+                        .push(InboundChunkJob::Finish(header.clone()))?;
                 } else {
                     file_container.last_group_finish_time = Instant::now();
-                    // TODO: Compute Mb/s
-                    let status = ObjectTransferStatus::ReceptionTick(
-                        group_id as usize,
-                        file_container.total_groups,
-                        0 as f32,
-                    );
+                    let status = ObjectTransferStatus::ReceptionTick(progress);
                     // sending the wave ack will complete the group on the initiator side
                     file_transfer_handle
                         .unbounded_send(status)
@@ -1422,6 +2622,11 @@ impl StateContainerInner {
             // common case
             GroupReceiverStatus::INSERT_SUCCESS => {}
 
+            // packets striped across the UDP stream may be retransmitted through the primary stream
+            GroupReceiverStatus::ALREADY_RECEIVED => {
+                log::trace!(target: "citadel", "Duplicate payload packet for {:?}", group_key);
+            }
+
             GroupReceiverStatus::WAVE_COMPLETE(..) => {
                 // send wave ACK to update progress on adjacent node
                 send_wave_ack = true;
@@ -1434,8 +2639,6 @@ impl StateContainerInner {
 
         if complete {
             log::trace!(target: "citadel", "Finished receiving file {:?}", file_key);
-            let _ = self.inbound_files.remove(&file_key);
-            let _ = self.file_transfer_handles.remove(&file_key);
         }
 
         if send_wave_ack {
@@ -1451,13 +2654,51 @@ impl StateContainerInner {
                     None,
                     header.security_level.into(),
                 );
-                return Ok(PrimaryProcessorResult::ReplyToSender(wave_ack));
+                // held back while the processor of the object is behind
+                let wave_ack = match self.inbound_files.get(&file_key) {
+                    Some(file_container) => file_container.chunk_queue.hold_wave_ack(wave_ack),
+                    None => Some(wave_ack),
+                };
+
+                if let Some(wave_ack) = wave_ack {
+                    return Ok(PrimaryProcessorResult::ReplyToSender(wave_ack));
+                }
             }
         }
 
         Ok(PrimaryProcessorResult::Void)
     }
 
+    /// Drops the inbound object, reports the failure locally and tells the sender to stop transmitting
+    fn on_chunk_verification_failed(
+        &mut self,
+        file_key: FileKey,
+        v_target: VirtualTargetType,
+        ticket: Ticket,
+        security_level: SecurityLevel,
+        err: String,
+        tx_status: &tokio::sync::mpsc::UnboundedSender<ObjectTransferStatus>,
+    ) {
+        log::error!(target: "citadel", "Integrity check failed for {:?}: {}", file_key, err);
+        let _ = self.inbound_files.remove(&file_key);
+        self.inbound_groups.retain(|group_key, container| {
+            group_key.target_cid != file_key.target_cid || container.object_id != file_key.object_id
+        });
+        let _ = self.file_transfer_handles.remove(&file_key);
+        let _ = tx_status.send(ObjectTransferStatus::Fail(err));
+
+        if let Err(err) = self.send_transfer_control(
+            v_target,
+            ticket,
+            security_level,
+            file_key.object_id,
+            ObjectTransferControl::Cancel,
+            ObjectTransferOrientation::Receiver,
+        ) {
+            log::error!(target: "citadel", "Unable to cancel {file_key:?} after failed verification: {err:?}");
+        }
+    }
+
     /// This function is called on Alice's side after Bob sends her a WAVE_ACK.
     /// The purpose of this function, for both tcp_only and reliable-udp, is to free memory.
     /// If using reliable-udp, then then this function has an additional purpose: to keep track
@@ -1480,17 +2721,35 @@ impl StateContainerInner {
         if let Some(transmitter_container) = self.outbound_transmitters.get_mut(&key) {
             // we set has_begun here instead of the transmit_tcp, simply because we want the first wave to ACK
             transmitter_container.has_begun = true;
+            if let Some(striped) = transmitter_container.striped.as_mut() {
+                let (delivered_via_udp, lost) = striped.on_wave_ack(wave_id);
+                let file_key = FileKey::new(target_cid, object_id as u32);
+                if let Some(congestion) = self
+                    .outbound_files
+                    .get_mut(&file_key)
+                    .and_then(|transfer| transfer.multipath.as_mut())
+                {
+                    if delivered_via_udp {
+                        congestion.on_udp_delivered();
+                    }
+
+                    if !lost.is_empty() {
+                        congestion.on_udp_loss(lost.len());
+                    }
+                }
+
+                if !lost.is_empty() {
+                    transmitter_container
+                        .burst_transmitter
+                        .retransmit_through_primary_stream(lost);
+                }
+            }
+
             let transmitter = &mut transmitter_container.burst_transmitter.group_transmitter;
             let relative_group_id = transmitter_container.relative_group_id;
             if transmitter.on_wave_tail_ack_received(wave_id) {
                 // Group is finished. Delete it
-                let elapsed_sec = transmitter_container
-                    .transmission_start_time
-                    .elapsed()
-                    .as_secs_f32();
-                let rate_mb_per_s = (transmitter_container.group_plaintext_length as f32
-                    / 1_000_000f32)
-                    / elapsed_sec;
+                let group_plaintext_length = transmitter_container.group_plaintext_length;
                 log::trace!(target: "citadel", "Transmitter received final wave ack. Alerting local node to continue transmission of next group");
                 // if there is n=1 waves, then the below must be ran. The other use of object notifier in this function only applies for multiple waves
                 if let Some(next_group_notifier) = transmitter_container.object_notifier.take() {
@@ -1500,19 +2759,36 @@ impl StateContainerInner {
                 }
 
                 let file_key = FileKey::new(target_cid, object_id as u32);
+                let progress = self.outbound_files.get_mut(&file_key).map(|transfer| {
+                    transfer.progress.on_chunk_complete(
+                        relative_group_id as usize,
+                        group_plaintext_length as u64,
+                    )
+                });
 
-                if let Some(tx) = self.file_transfer_handles.get(&file_key) {
-                    let status = if relative_group_id as usize
-                        != transmitter_container.parent_object_total_groups - 1
-                    {
-                        ObjectTransferStatus::TransferTick(
-                            relative_group_id as usize,
-                            transmitter_container.parent_object_total_groups,
-                            rate_mb_per_s,
-                        )
-                    } else {
-                        ObjectTransferStatus::TransferComplete
-                    };
+                let status = if relative_group_id as usize
+                    != transmitter_container.parent_object_total_groups - 1
+                {
+                    progress.map(ObjectTransferStatus::TransferTick)
+                } else {
+                    Some(ObjectTransferStatus::TransferComplete)
+                };
+
+                if let (Some(tx), Some(status)) =
+                    (self.file_transfer_handles.get(&file_key), status)
+                {
+                    // recorded before the sender learns of the completion
+                    if matches!(status, ObjectTransferStatus::TransferComplete) {
+                        if let Some(congestion) = self
+                            .outbound_files
+                            .get(&file_key)
+                            .and_then(|transfer| transfer.multipath.as_ref())
+                        {
+                            let metrics = congestion.metrics();
+                            log::trace!(target: "citadel", "[MULTIPATH] {} packets of {file_key:?} were sent via the primary stream and {} via UDP, of which {} were retransmitted", metrics.packets_via_primary, metrics.packets_via_udp, metrics.packets_retransmitted);
+                            self.multipath_counters.record(congestion);
+                        }
+                    }
 
                     if let Err(err) = tx.unbounded_send(status.clone()) {
                         // if the server is using an accept-only policy with no further responses, this branch
@@ -1527,7 +2803,7 @@ impl StateContainerInner {
                         log::trace!(target: "citadel", "FileTransfer is complete!");
                         let _ = self.file_transfer_handles.remove(&file_key);
                     }
-                } else {
+                } else if !self.file_transfer_handles.contains_key(&file_key) {
                     log::error!(target: "citadel", "Unable to find ObjectTransferHandle for {:?}", file_key);
                 }
 
@@ -1584,54 +2860,79 @@ impl StateContainerInner {
     }
 
     /// Returns true if a packet was sent, false otherwise. This should only be called when a packet is received
+    ///
+    /// Only messages sent with [`SecrecyMode::Perfect`] are ever enqueued, so this polls regardless
+    /// of the channel's default secrecy mode
     pub(crate) fn poll_next_enqueued(&mut self, target_cid: u64) -> Result<bool, NetworkError> {
         log::trace!(target: "citadel", "Polling next for {}", target_cid);
-        let secrecy_mode = self
-            .get_secrecy_mode(target_cid)
-            .ok_or(NetworkError::InternalError("Secrecy mode not loaded"))?;
-        match secrecy_mode {
-            SecrecyMode::BestEffort => {}
-
-            SecrecyMode::Perfect => {
-                // fetch_nand(false
-                let update_in_progress = self
-                    .updates_in_progress
-                    .get(&target_cid)
-                    .map(|r| r.fetch_nand(false, Ordering::SeqCst))
-                    .ok_or(NetworkError::InternalError(
-                        "Update state not loaded in hashmap!",
-                    ))?;
-
-                // We have to make sure when this is called, it also sets update_in_progress to true to place a lock. We will also need to reinforce this via a force_mode inside the get_next_constructor fn in the crypt container
-                // it's possible in high-stress loads, a new inbound packet triggers update_in_progress to true right after checking below. The fetch_nand w/false helps us achieve this
-                if update_in_progress {
-                    log::trace!(target: "citadel", "Cannot send packet at this time since update_in_progress"); // in this case, update will happen upon reception of TRUNCATE packet
-                    return Ok(false);
-                }
+        if !self.has_enqueued(target_cid) {
+            log::trace!(target: "citadel", "NO packets enqueued for target {}", target_cid);
+            return Ok(false);
+        }
 
-                let queue = self.enqueued_packets.entry(target_cid).or_default();
-                log::trace!(target: "citadel", "Queue has: {} items", queue.len());
-                // since we have a mutable lock on the session, no other attempts will happen. We can safely pop the front of the queue and rest assured that it won't be denied a send this time
-                if let Some((ticket, packet, virtual_target, security_level)) = queue.pop_front() {
-                    //std::mem::drop(enqueued);
-                    return self
-                        .process_outbound_message(
-                            ticket,
-                            packet,
-                            virtual_target,
-                            security_level,
-                            true,
-                        )
-                        .map(|_| true);
-                } else {
-                    log::trace!(target: "citadel", "NO packets enqueued for target {}", target_cid);
-                }
+        // fetch_nand(false
+        let update_in_progress = self
+            .updates_in_progress
+            .get(&target_cid)
+            .map(|r| r.fetch_nand(false, Ordering::SeqCst))
+            .ok_or(NetworkError::InternalError(
+                "Update state not loaded in hashmap!",
+            ))?;
+
+        // We have to make sure when this is called, it also sets update_in_progress to true to place a lock. We will also need to reinforce this via a force_mode inside the get_next_constructor fn in the crypt container
+        // it's possible in high-stress loads, a new inbound packet triggers update_in_progress to true right after checking below. The fetch_nand w/false helps us achieve this
+        if update_in_progress {
+            log::trace!(target: "citadel", "Cannot send packet at this time since update_in_progress"); // in this case, update will happen upon reception of TRUNCATE packet
+            return Ok(false);
+        }
+
+        // since we have a mutable lock on the session, no other attempts will happen. We can safely pop the front of the queue and rest assured that it won't be denied a send this time
+        while let Some((ticket, packet, virtual_target, security_level, expires_at)) = self
+            .enqueued_packets
+            .get_mut(&target_cid)
+            .and_then(|queue| queue.pop_front())
+        {
+            // messages that expired while waiting behind the re-key are dropped instead of sent late
+            if expires_at.map(|t| t <= Instant::now()).unwrap_or(false) {
+                self.drop_expired_message(target_cid, ticket, &packet);
+                continue;
             }
+
+            return self
+                .process_outbound_message(
+                    ticket,
+                    packet,
+                    virtual_target,
+                    security_level,
+                    Some(SecrecyMode::Perfect),
+                    expires_at,
+                    true,
+                )
+                .map(|_| true);
+        }
+
+        // every enqueued message expired. Release the lock placed above
+        if let Some(update_in_progress) = self.updates_in_progress.get(&target_cid) {
+            update_in_progress.store(false, Ordering::SeqCst);
         }
 
         Ok(false)
     }
 
+    /// Drops a message of the ordered channel to `target_cid` that expired before it was sent,
+    /// returning the room it reserved to the sender
+    pub(crate) fn drop_expired_message(
+        &self,
+        target_cid: u64,
+        ticket: Ticket,
+        packet: &SecureProtocolPacket,
+    ) {
+        log::trace!(target: "citadel", "Dropping expired message (ticket: {ticket}) to {target_cid}");
+        if let Some(flow_control) = self.get_flow_control(target_cid) {
+            flow_control.send.unreserve(packet.message_len() as u64);
+        }
+    }
+
     fn enqueue_packet(
         &mut self,
         target_cid: u64,
@@ -1639,11 +2940,12 @@ impl StateContainerInner {
         packet: SecureProtocolPacket,
         target: VirtualTargetType,
         security_level: SecurityLevel,
+        expires_at: Option<Instant>,
     ) {
         self.enqueued_packets
             .entry(target_cid)
             .or_default()
-            .push_back((ticket, packet, target, security_level))
+            .push_back((ticket, packet, target, security_level, expires_at))
     }
 
     fn has_enqueued(&self, target_cid: u64) -> bool {
@@ -1660,6 +2962,8 @@ impl StateContainerInner {
         packet: SecureProtocolPacket,
         virtual_target: VirtualTargetType,
         security_level: SecurityLevel,
+        secrecy_mode: Option<SecrecyMode>,
+        expires_at: Option<Instant>,
         called_from_poll: bool,
     ) -> Result<(), NetworkError> {
         let this = self;
@@ -1669,10 +2973,16 @@ impl StateContainerInner {
                 "Attempted to send data (ticket: {ticket}) outbound, but the session is not connected"
             )))
         } else {
+            // A per-message secrecy mode overrides the channel's default. Only Perfect messages
+            // wait behind re-keys; BestEffort messages are sent even while Perfect ones are enqueued
+            let secrecy_mode = match secrecy_mode {
+                Some(secrecy_mode) => secrecy_mode,
+                None => this
+                    .get_secrecy_mode(virtual_target.get_target_cid())
+                    .ok_or(NetworkError::InternalError("Secrecy mode not loaded"))?,
+            };
+
             // first, make sure that there aren't already packets in the queue (unless we were called from the poll, in which case, we are getting the latest version)
-            let secrecy_mode = this
-                .get_secrecy_mode(virtual_target.get_target_cid())
-                .ok_or(NetworkError::InternalError("Secrecy mode not loaded"))?;
 
             let time_tracker = this.time_tracker;
 
@@ -1695,6 +3005,7 @@ impl StateContainerInner {
                         packet,
                         virtual_target,
                         security_level,
+                        expires_at,
                     );
                     return Ok(());
                 }
@@ -1713,10 +3024,16 @@ impl StateContainerInner {
                         .as_mut()
                         .unwrap()
                         .peer_session_crypto;
-                    let latest_hyper_ratchet =
-                        crypt_container.get_hyper_ratchet(None).cloned().unwrap();
+                    let (version, latest_hyper_ratchet) = crypt_container
+                        .pin_hyper_ratchet()
+                        .map(|(version, ratchet)| (version, ratchet.clone()))
+                        .ok_or(NetworkError::InternalError("Ratchet not loaded"))?;
+                    log::trace!(target: "citadel", "Pinned c2s ratchet v{version} for outbound message");
                     latest_hyper_ratchet.verify_level(Some(security_level)).map_err(|_err| NetworkError::Generic(format!("Invalid security level. The maximum security level for this session is {:?}", latest_hyper_ratchet.get_default_security_level())))?;
                     let constructor = crypt_container.get_next_constructor(called_from_poll);
+                    if constructor.is_some() {
+                        this.security_epochs.on_rekey_started(C2S_ENCRYPTION_ONLY);
+                    }
 
                     let result = match secrecy_mode {
                         SecrecyMode::BestEffort => {
@@ -1801,15 +3118,19 @@ impl StateContainerInner {
                                 default_primary_stream
                             });
                             //let to_primary_stream_preferred = this.to_primary_stream.clone().unwrap();
-                            let latest_usable_ratchet = endpoint_container
+                            let (version, latest_usable_ratchet) = endpoint_container
                                 .endpoint_crypto
-                                .get_hyper_ratchet(None)
-                                .unwrap()
-                                .clone();
+                                .pin_hyper_ratchet()
+                                .map(|(version, ratchet)| (version, ratchet.clone()))
+                                .ok_or(NetworkError::InternalError("Ratchet not loaded"))?;
+                            log::trace!(target: "citadel", "Pinned p2p ratchet v{version} for outbound message to {target_cid}");
                             latest_usable_ratchet.verify_level(Some(security_level)).map_err(|_err| NetworkError::Generic(format!("Invalid security level. The maximum security level for this session is {:?}", latest_usable_ratchet.get_default_security_level())))?;
                             let constructor = endpoint_container
                                 .endpoint_crypto
                                 .get_next_constructor(called_from_poll);
+                            if constructor.is_some() {
+                                this.security_epochs.on_rekey_started(target_cid);
+                            }
 
                             match secrecy_mode {
                                 SecrecyMode::BestEffort => {
@@ -1961,7 +3282,7 @@ impl StateContainerInner {
         ticket: Option<Ticket>,
     ) -> Result<(), NetworkError> {
         fn return_already_in_progress(
-            kernel_tx: &UnboundedSender<NodeResult>,
+            kernel_tx: &KernelEventSender,
             ticket: Ticket,
         ) -> Result<(), NetworkError> {
             kernel_tx
@@ -2000,7 +3321,10 @@ impl StateContainerInner {
 
                 match crypt_container.get_next_constructor(false) {
                     Some(alice_constructor) => {
-                        let ratchet = crypt_container.get_hyper_ratchet(None).unwrap();
+                        // the re-key is based on the pinned version
+                        let (_, ratchet) = crypt_container
+                            .pin_hyper_ratchet()
+                            .ok_or(NetworkError::InternalError("Ratchet not loaded"))?;
                         let stage0_packet = packet_crafter::do_drill_update::craft_stage0(
                             ratchet,
                             alice_constructor
@@ -2011,6 +3335,7 @@ impl StateContainerInner {
                             security_level,
                         );
                         self.ratchet_update_state.alice_hyper_ratchet = Some(alice_constructor);
+                        self.security_epochs.on_rekey_started(C2S_ENCRYPTION_ONLY);
                         if let Some(ticket) = ticket {
                             // this request requires tracking
                             let _ = self
@@ -2051,9 +3376,10 @@ impl StateContainerInner {
                     .ok_or(MISSING)?;
                 let crypt = &mut endpoint_container.endpoint_crypto;
                 let alice_constructor = crypt.get_next_constructor(false);
+                // the re-key is based on the pinned version
                 let latest_hyper_ratchet = crypt
-                    .get_hyper_ratchet(None)
-                    .cloned()
+                    .pin_hyper_ratchet()
+                    .map(|(_, ratchet)| ratchet.clone())
                     .ok_or(NetworkError::InternalError("Ratchet not loaded"))?;
 
                 match alice_constructor {
@@ -2085,6 +3411,8 @@ impl StateContainerInner {
                             log::error!(target: "citadel", "Overwrote pre-existing peer kem. Report to developers");
                         }
 
+                        self.security_epochs.on_rekey_started(peer_cid);
+
                         if let Some(ticket) = ticket {
                             // this request requires tracking
                             let _ = self
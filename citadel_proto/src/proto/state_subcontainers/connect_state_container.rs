@@ -1,31 +1,36 @@
 use tokio::time::Instant;
 
+use crate::proto::misc::connect_timings::ConnectTimer;
 use crate::proto::node::ConnectMode;
-use crate::proto::packet::packet_flags;
+use crate::proto::state_subcontainers::stage_machine::{
+    ConnectStage, IllegalStageTransition, StageMachine,
+};
 use citadel_user::auth::proposed_credentials::ProposedCredentials;
 
-/// These values should correlate directly to the packet_flags::cmd::aux::do_connect::*
 #[derive(Default)]
 pub struct ConnectState {
-    pub(crate) last_stage: u8,
+    pub(crate) stage: StageMachine<ConnectStage>,
     pub(crate) proposed_credentials: Option<ProposedCredentials>,
     pub(crate) last_packet_time: Option<Instant>,
     pub(crate) fail_time: Option<i64>,
     pub(crate) connect_mode: Option<ConnectMode>,
+    pub(crate) timer: ConnectTimer,
 }
 
 impl ConnectState {
     /// Whenever the connection stage fails, this should be called. Do not forget to set the session's global state too
-    pub fn on_fail(&mut self) {
-        self.last_stage = packet_flags::cmd::aux::do_connect::FAILURE;
+    pub fn on_fail(&mut self) -> Result<(), IllegalStageTransition> {
+        self.stage.advance(ConnectStage::Failure)?;
         self.on_connect_packet_received();
+        Ok(())
     }
 
     /// Once the connection succeeds, call this closure. Do not forget to set the session's global state too
-    pub fn on_success(&mut self) {
-        self.last_stage = packet_flags::cmd::aux::do_connect::SUCCESS;
+    pub fn on_success(&mut self) -> Result<(), IllegalStageTransition> {
+        self.stage.advance(ConnectStage::Success)?;
         self.fail_time = None;
         self.on_connect_packet_received();
+        Ok(())
     }
 
     /// At the end of every stage, this should be called
@@ -33,12 +38,3 @@ impl ConnectState {
         self.last_packet_time = Some(Instant::now());
     }
 }
-
-impl From<u8> for ConnectState {
-    fn from(stage: u8) -> Self {
-        Self {
-            last_stage: stage,
-            ..Default::default()
-        }
-    }
-}
@@ -12,3 +12,5 @@ pub mod preconnect_state_container;
 pub mod register_state_container;
 ///
 pub mod rekey_container;
+///
+pub mod stage_machine;
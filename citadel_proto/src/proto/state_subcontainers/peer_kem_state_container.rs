@@ -1,17 +1,24 @@
 use crate::proto::misc::session_security_settings::SessionSecuritySettings;
+use crate::proto::peer::channel::CrossConnect;
 use crate::proto::state_subcontainers::preconnect_state_container::UdpChannelSender;
+use crate::proto::state_subcontainers::stage_machine::{PeerKemStage, StageMachine};
 use citadel_crypt::stacked_ratchet::constructor::StackedRatchetConstructor;
+use tokio::time::Instant;
 
 pub struct PeerKemStateContainer {
+    pub(crate) stage: StageMachine<PeerKemStage>,
     pub(crate) constructor: Option<StackedRatchetConstructor>,
     pub(crate) local_is_initiator: bool,
     pub(crate) session_security_settings: SessionSecuritySettings,
     pub(crate) udp_channel_sender: UdpChannelSender,
+    pub(crate) created: Instant,
+    pub(crate) cross_connect: Option<CrossConnect>,
 }
 
 impl PeerKemStateContainer {
     pub fn new(session_security_settings: SessionSecuritySettings, udp_enabled: bool) -> Self {
         Self {
+            stage: StageMachine::default(),
             constructor: None,
             local_is_initiator: false,
             session_security_settings,
@@ -20,6 +27,8 @@ impl PeerKemStateContainer {
             } else {
                 UdpChannelSender::empty()
             },
+            created: Instant::now(),
+            cross_connect: None,
         }
     }
 }
@@ -1,6 +1,7 @@
 use crate::proto::packet_processor::includes::Instant;
 use crate::proto::peer::channel::UdpChannel;
 use crate::proto::remote::Ticket;
+use crate::proto::state_subcontainers::stage_machine::{PreConnectStage, StageMachine};
 use citadel_crypt::stacked_ratchet::constructor::StackedRatchetConstructor;
 use citadel_crypt::stacked_ratchet::StackedRatchet;
 use citadel_wire::hypernode_type::NodeType;
@@ -8,7 +9,7 @@ use tokio::sync::oneshot::{channel, Receiver, Sender};
 
 /// For keeping track of the pre-connect state
 pub struct PreConnectState {
-    pub(crate) last_stage: u8,
+    pub(crate) stage: StageMachine<PreConnectStage>,
     #[allow(dead_code)]
     pub(crate) adjacent_node_type: Option<NodeType>,
     // This drill should be turned .into() the next toolset once the other side updated
@@ -33,7 +34,7 @@ impl Default for PreConnectState {
             udp_channel_oneshot_tx: UdpChannelSender::empty(),
             constructor: None,
             last_packet_time: None,
-            last_stage: 0,
+            stage: StageMachine::default(),
             adjacent_node_type: None,
             success: false,
             ticket: None,
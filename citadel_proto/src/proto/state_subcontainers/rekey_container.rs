@@ -7,7 +7,7 @@ use crate::constants::{
 };
 use crate::error::NetworkError;
 use crate::prelude::{NodeResult, ReKeyResult, ReKeyReturnType, Ticket, VirtualTargetType};
-use crate::proto::outbound_sender::UnboundedSender;
+use crate::proto::outbound_sender::KernelEventSender;
 use crate::proto::transfer_stats::TransferStats;
 use citadel_crypt::stacked_ratchet::constructor::StackedRatchetConstructor;
 use std::collections::HashMap;
@@ -25,7 +25,7 @@ impl RatchetUpdateState {
     pub(crate) fn on_complete(
         &mut self,
         v_conn_type: VirtualTargetType,
-        to_kernel_tx: &UnboundedSender<NodeResult>,
+        to_kernel_tx: &KernelEventSender,
         status: ReKeyReturnType,
     ) -> Result<(), NetworkError> {
         if let Some(ticket) = self.current_local_requests.remove(&v_conn_type) {
@@ -0,0 +1,277 @@
+use crate::error::NetworkError;
+use crate::proto::packet_processor::includes::Instant;
+use crate::proto::session::SessionState;
+use std::fmt::{Debug, Display, Formatter};
+
+/// A stage of a multi-packet process. Each process declares which transitions between its
+/// stages are legal, such that a packet arriving for the wrong stage is rejected with an
+/// [`IllegalStageTransition`] instead of silently corrupting the state of the session
+pub trait Stage: Copy + Debug + Eq {
+    /// The name of the process, used in diagnostics
+    const PROCESS: &'static str;
+    /// The stage each process begins in
+    const INITIAL: Self;
+    /// Returns true if the process may move from `self` into `next`. Remaining in the same stage
+    /// is always legal
+    fn can_advance_to(self, next: Self) -> bool;
+}
+
+/// The pre-connect stages, as seen by the local node
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PreConnectStage {
+    /// No SYN has been sent or received
+    Syn,
+    /// A SYN has been sent or received, and the SYN_ACK is outstanding
+    SynAck,
+    /// Both nodes agreed on the session parameters
+    Success,
+}
+
+impl Stage for PreConnectStage {
+    const PROCESS: &'static str = "pre-connect";
+    const INITIAL: Self = Self::Syn;
+
+    fn can_advance_to(self, next: Self) -> bool {
+        matches!(
+            (self, next),
+            (Self::Syn, Self::SynAck) | (Self::SynAck, Self::Success)
+        )
+    }
+}
+
+/// The register stages, as seen by the local node. Alice moves from `Stage0` directly into
+/// `Stage2` once she receives Bob's `Stage1` packet
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RegisterStage {
+    Stage0,
+    Stage1,
+    Stage2,
+    Failure,
+}
+
+impl Stage for RegisterStage {
+    const PROCESS: &'static str = "register";
+    const INITIAL: Self = Self::Stage0;
+
+    fn can_advance_to(self, next: Self) -> bool {
+        matches!(
+            (self, next),
+            (Self::Stage0, Self::Stage1 | Self::Stage2) | (_, Self::Failure)
+        )
+    }
+}
+
+/// The connect stages, as seen by the local node. Bob moves from `Stage0` directly into
+/// `Success` once he validates Alice's credentials
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ConnectStage {
+    Stage0,
+    Stage1,
+    Success,
+    Failure,
+}
+
+impl Stage for ConnectStage {
+    const PROCESS: &'static str = "connect";
+    const INITIAL: Self = Self::Stage0;
+
+    fn can_advance_to(self, next: Self) -> bool {
+        matches!(
+            (self, next),
+            (Self::Stage0, Self::Stage1 | Self::Success)
+                | (Self::Stage1, Self::Success)
+                | (Self::Stage0 | Self::Stage1, Self::Failure)
+        )
+    }
+}
+
+/// The peer KEM stages, as seen by the local node. The initiator moves from `Stage0` directly into
+/// `Stage2` once it receives the `Stage1` packet of the receiver
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum PeerKemStage {
+    Stage0,
+    Stage1,
+    Stage2,
+    Failure,
+}
+
+impl Stage for PeerKemStage {
+    const PROCESS: &'static str = "peer KEM";
+    const INITIAL: Self = Self::Stage0;
+
+    fn can_advance_to(self, next: Self) -> bool {
+        matches!(
+            (self, next),
+            (Self::Stage0, Self::Stage1 | Self::Stage2)
+                | (Self::Stage1, Self::Stage2)
+                | (_, Self::Failure)
+        )
+    }
+}
+
+/// Tracks the current stage of a process alongside the time it was entered
+#[derive(Copy, Clone)]
+pub struct StageMachine<S: Stage> {
+    current: S,
+    entered: Instant,
+}
+
+impl<S: Stage> StageMachine<S> {
+    pub fn current(&self) -> S {
+        self.current
+    }
+
+    /// Moves the process into `next`, failing if the transition is illegal
+    pub fn advance(&mut self, next: S) -> Result<(), IllegalStageTransition> {
+        if next == self.current {
+            return Ok(());
+        }
+
+        if !self.current.can_advance_to(next) {
+            return Err(IllegalStageTransition {
+                process: S::PROCESS,
+                from: format!("{:?}", self.current),
+                to: format!("{next:?}"),
+            });
+        }
+
+        log::trace!(target: "citadel", "[{}] {:?} -> {:?}", S::PROCESS, self.current, next);
+        self.current = next;
+        self.entered = Instant::now();
+        Ok(())
+    }
+}
+
+impl<S: Stage> Default for StageMachine<S> {
+    fn default() -> Self {
+        Self {
+            current: S::INITIAL,
+            entered: Instant::now(),
+        }
+    }
+}
+
+impl<S: Stage> Debug for StageMachine<S> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?} (for {:?})", self.current, self.entered.elapsed())
+    }
+}
+
+#[derive(Debug)]
+pub struct IllegalStageTransition {
+    pub process: &'static str,
+    pub from: String,
+    pub to: String,
+}
+
+impl Display for IllegalStageTransition {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Illegal {} stage transition from {} to {}",
+            self.process, self.from, self.to
+        )
+    }
+}
+
+impl From<IllegalStageTransition> for NetworkError {
+    fn from(err: IllegalStageTransition) -> Self {
+        NetworkError::Generic(err.to_string())
+    }
+}
+
+/// A snapshot of every stage tracked by a session, used for diagnostics
+#[derive(Debug)]
+pub struct StageDump {
+    pub session: SessionState,
+    pub pre_connect: StageMachine<PreConnectStage>,
+    pub register: StageMachine<RegisterStage>,
+    pub connect: StageMachine<ConnectStage>,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::proto::state_subcontainers::stage_machine::{
+        ConnectStage, PeerKemStage, PreConnectStage, RegisterStage, Stage, StageMachine,
+    };
+
+    /// Returns every stage reachable from the initial stage via legal transitions
+    fn reachable<S: Stage>(all: &[S]) -> Vec<S> {
+        let mut reached = vec![S::INITIAL];
+        let mut idx = 0;
+        while idx < reached.len() {
+            let from = reached[idx];
+            for next in all {
+                if from.can_advance_to(*next) && !reached.contains(next) {
+                    reached.push(*next);
+                }
+            }
+            idx += 1;
+        }
+
+        reached
+    }
+
+    fn check_transitions<S: Stage>(all: &[S], terminal: &[S]) {
+        // every stage can be reached, ensuring no stage is dead
+        assert_eq!(reachable(all).len(), all.len());
+
+        for from in all {
+            for next in all {
+                let mut machine = StageMachine::<S>::default();
+                machine.current = *from;
+                let legal = from == next || from.can_advance_to(*next);
+                assert_eq!(
+                    machine.advance(*next).is_ok(),
+                    legal,
+                    "{from:?} -> {next:?}"
+                );
+                // an illegal transition must leave the machine untouched
+                assert_eq!(machine.current(), if legal { *next } else { *from });
+            }
+
+            // terminal stages cannot be left
+            if terminal.contains(from) {
+                assert!(all
+                    .iter()
+                    .filter(|next| *next != from)
+                    .all(|next| !from.can_advance_to(*next)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_pre_connect_transitions() {
+        use PreConnectStage::*;
+        check_transitions(&[Syn, SynAck, Success], &[Success]);
+        assert!(!Syn.can_advance_to(Success));
+    }
+
+    #[test]
+    fn test_register_transitions() {
+        use RegisterStage::*;
+        check_transitions(&[Stage0, Stage1, Stage2, Failure], &[Failure]);
+        assert!(!Stage1.can_advance_to(Stage2));
+        assert!(!Stage2.can_advance_to(Stage0));
+        assert!([Stage0, Stage1, Stage2]
+            .iter()
+            .all(|stage| stage.can_advance_to(Failure)));
+    }
+
+    #[test]
+    fn test_connect_transitions() {
+        use ConnectStage::*;
+        check_transitions(&[Stage0, Stage1, Success, Failure], &[Success, Failure]);
+        assert!(!Stage1.can_advance_to(Stage0));
+    }
+
+    #[test]
+    fn test_peer_kem_transitions() {
+        use PeerKemStage::*;
+        check_transitions(&[Stage0, Stage1, Stage2, Failure], &[Failure]);
+        assert!(!Stage2.can_advance_to(Stage1));
+        assert!([Stage0, Stage1, Stage2]
+            .iter()
+            .all(|stage| stage.can_advance_to(Failure)));
+    }
+}
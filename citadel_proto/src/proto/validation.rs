@@ -1,5 +1,8 @@
 pub(crate) mod do_connect {
+    use citadel_crypt::entropy_bank::SecurityLevel;
+    use citadel_user::account_manager::AccountManager;
     use citadel_user::client_account::ClientNetworkAccount;
+    use citadel_user::permissions::AccountPermissions;
 
     use crate::error::NetworkError;
     use crate::proto::packet_crafter::do_connect::{
@@ -7,19 +10,35 @@ pub(crate) mod do_connect {
     };
     use citadel_user::serialization::SyncIO;
 
-    /// Here, Bob receives a payload of the encrypted username + password. We must verify the login data is valid
+    /// Here, Bob receives a payload of the encrypted username + password. We must verify the login data is valid.
+    /// Returns the permissions of the session, which must permit `security_level`
     pub(crate) async fn validate_stage0_packet(
+        account_manager: &AccountManager,
         cnac: &ClientNetworkAccount,
         payload: &[u8],
-    ) -> Result<(), NetworkError> {
+        security_level: SecurityLevel,
+    ) -> Result<AccountPermissions, NetworkError> {
         // Now, validate the username and password. The payload is already decrypted
         let payload = DoConnectStage0Packet::deserialize_from_vector(payload)
             .map_err(|err| NetworkError::Generic(err.into_string()))?;
-        cnac.validate_credentials(payload.proposed_credentials)
+        let policy = account_manager
+            .validate_credentials(cnac, payload.proposed_credentials)
             .await
             .map_err(|err| NetworkError::Generic(err.into_string()))?;
         log::trace!(target: "citadel", "Success validating credentials!");
-        Ok(())
+        let permissions = account_manager
+            .get_account_permissions(cnac.get_cid())
+            .await
+            .map_err(|err| NetworkError::Generic(err.into_string()))?
+            .restrict(policy);
+
+        if !permissions.permits_security_level(security_level) {
+            return Err(NetworkError::msg(format!(
+                "Security level {security_level:?} exceeds the maximum permitted for this account"
+            )));
+        }
+
+        Ok(permissions)
     }
 
     pub(crate) fn validate_final_status_packet(
@@ -36,6 +55,7 @@ pub(crate) mod group {
 
     use citadel_crypt::scramble::crypt_splitter::GroupReceiverConfig;
 
+    use crate::proto::misc::flow_control::WindowUpdate;
     use crate::proto::packet_crafter::SecureProtocolPacket;
     use crate::proto::state_container::VirtualTargetType;
     use citadel_crypt::endpoint_crypto_container::KemTransferStatus;
@@ -69,10 +89,8 @@ pub(crate) mod group {
         let mut group_header = GroupHeader::deserialize_from_vector(payload).ok()?;
         match &mut group_header {
             GroupHeader::Standard(group_receiver_config, _) => {
-                if group_receiver_config.plaintext_length
-                    > citadel_user::prelude::MAX_BYTES_PER_GROUP
-                {
-                    log::error!(target: "citadel", "The provided GroupReceiverConfiguration contains an oversized allocation request. Dropping ...");
+                if let Err(err) = group_receiver_config.validate() {
+                    log::error!(target: "citadel", "Dropping group header: {}", err.into_string());
                     return None;
                 }
             }
@@ -117,6 +135,10 @@ pub(crate) mod group {
     pub(crate) fn validate_wave_ack(payload: &[u8]) -> Option<WaveAck> {
         WaveAck::deserialize_from_vector(payload).ok()
     }
+
+    pub(crate) fn validate_window_update(payload: &[u8]) -> Option<WindowUpdate> {
+        WindowUpdate::deserialize_from_vector(payload).ok()
+    }
 }
 
 pub(crate) mod do_register {
@@ -124,7 +146,9 @@ pub(crate) mod do_register {
     use zerocopy::LayoutVerified;
 
     use crate::proto::packet::HdpHeader;
-    use crate::proto::packet_crafter::do_register::{DoRegisterStage0, DoRegisterStage2Packet};
+    use crate::proto::packet_crafter::do_register::{
+        DoRegisterStage0, DoRegisterStage1, DoRegisterStage2Packet,
+    };
     use bytes::BytesMut;
     use citadel_crypt::stacked_ratchet::constructor::AliceToBobTransfer;
     use citadel_crypt::stacked_ratchet::StackedRatchet;
@@ -137,6 +161,10 @@ pub(crate) mod do_register {
             .map(|r| (r.transfer, r.passwordless))
     }
 
+    pub(crate) fn validate_stage1(payload: &[u8]) -> Option<DoRegisterStage1> {
+        DoRegisterStage1::deserialize_from_vector(payload).ok()
+    }
+
     /// Returns the decrypted username, password, and full name
     pub(crate) fn validate_stage2(
         hyper_ratchet: &StackedRatchet,
@@ -212,11 +240,11 @@ pub(crate) mod pre_connect {
     use crate::proto::packet_crafter::pre_connect::{PreConnectStage0, SynPacket};
     use crate::proto::packet_processor::includes::packet_crafter::pre_connect::SynAckPacket;
     use crate::proto::peer::peer_layer::UdpMode;
-    use crate::proto::session_manager::HdpSessionManager;
     use citadel_crypt::stacked_ratchet::constructor::{
         BobToAliceTransfer, BobToAliceTransferType, StackedRatchetConstructor,
     };
     use citadel_crypt::stacked_ratchet::{Ratchet, StackedRatchet};
+    use citadel_pqcrypto::algorithm_dictionary::FIPS_MODE;
     use citadel_user::prelude::ConnectProtocol;
     use citadel_user::serialization::SyncIO;
     use citadel_wire::nat_identification::NatType;
@@ -232,10 +260,13 @@ pub(crate) mod pre_connect {
         StackedRatchet,
     );
 
+    /// `session_active` must be true if the client already has a connected session, in which case
+    /// the SYN is only valid if it forces a login. Runs on the blocking pool, hence must not
+    /// touch the session manager
     pub(crate) fn validate_syn(
         cnac: &ClientNetworkAccount,
         packet: HdpPacket,
-        session_manager: &HdpSessionManager,
+        session_active: bool,
     ) -> Result<SynValidationResult, NetworkError> {
         // TODO: NOTE: This can interrupt any active session's. This should be moved up after checking the connect mode
         let static_auxiliary_ratchet = cnac.refresh_static_hyper_ratchet();
@@ -249,12 +280,26 @@ pub(crate) mod pre_connect {
         let transfer = SynPacket::deserialize_from_vector(&payload)
             .map_err(|err| NetworkError::Generic(err.into_string()))?;
 
+        // a node in restricted mode only accepts peers that are also in restricted mode
+        if FIPS_MODE {
+            if !transfer.restricted_mode {
+                return Err(NetworkError::InvalidRequest(
+                    "This node only accepts peers in restricted (FIPS) mode",
+                ));
+            }
+
+            citadel_pqcrypto::validate_crypto_params(
+                &transfer.session_security_settings.crypto_params,
+            )
+            .map_err(|err| NetworkError::Generic(err.to_string()))?;
+        }
+
         // TODO: Consider adding connect_mode to the HdpSession to sync between both nodes. For now, there's no need
         match transfer.connect_mode {
             ConnectMode::Fetch { force_login: false }
             | ConnectMode::Standard { force_login: false } => {
                 // before going further, make sure the user isn't already logged-in. We wouldn't want to replace the toolset that is already being used
-                if session_manager.session_active(header.session_cid.get()) {
+                if session_active {
                     return Err(NetworkError::InternalError("User is already logged in"));
                 }
             }
@@ -323,6 +368,11 @@ pub(crate) mod pre_connect {
             super::aead::validate_custom(&static_auxiliary_ratchet, &header, payload)?;
         let packet = SynAckPacket::deserialize_from_vector(&payload).ok()?;
 
+        if FIPS_MODE && !packet.restricted_mode {
+            log::error!(target: "citadel", "Server is not in restricted (FIPS) mode. Refusing to connect");
+            return None;
+        }
+
         let lvl = packet.transfer.security_level;
         log::trace!(target: "citadel", "Session security level based-on returned transfer: {:?}", lvl);
         if let Err(err) =
@@ -352,10 +402,13 @@ pub(crate) mod pre_connect {
 pub(crate) mod file {
     use crate::proto::packet::HdpHeader;
     use crate::proto::packet_crafter::file::{
+        ChunkQueryAckPacket, ChunkQueryPacket, DeltaSignaturePacket, DeltaSignatureRequestPacket,
         FileHeaderAckPacket, FileHeaderPacket, ReVFSAckPacket, ReVFSDeletePacket,
-        ReVFSPullAckPacket, ReVFSPullPacket,
+        ReVFSDirAckPacket, ReVFSDirPacket, ReVFSPullAckPacket, ReVFSPullPacket,
+        SharedObjectAckPacket, SharedObjectPacket, TransferControlPacket, TransferManifestPacket,
     };
     use crate::proto::packet_processor::includes::LayoutVerified;
+    use citadel_crypt::stacked_ratchet::StackedRatchet;
     use citadel_user::serialization::SyncIO;
 
     pub fn validate_file_header(
@@ -400,6 +453,97 @@ pub(crate) mod file {
     ) -> Option<ReVFSPullAckPacket> {
         ReVFSPullAckPacket::deserialize_from_vector(payload).ok()
     }
+
+    pub fn validate_revfs_dir(
+        _header: &LayoutVerified<&[u8], HdpHeader>,
+        payload: &[u8],
+    ) -> Option<ReVFSDirPacket> {
+        ReVFSDirPacket::deserialize_from_vector(payload).ok()
+    }
+
+    pub fn validate_revfs_dir_ack(
+        _header: &LayoutVerified<&[u8], HdpHeader>,
+        payload: &[u8],
+    ) -> Option<ReVFSDirAckPacket> {
+        ReVFSDirAckPacket::deserialize_from_vector(payload).ok()
+    }
+
+    pub fn validate_shared_object(
+        _header: &LayoutVerified<&[u8], HdpHeader>,
+        payload: &[u8],
+    ) -> Option<SharedObjectPacket> {
+        SharedObjectPacket::deserialize_from_vector(payload).ok()
+    }
+
+    pub fn validate_shared_object_ack(
+        _header: &LayoutVerified<&[u8], HdpHeader>,
+        payload: &[u8],
+    ) -> Option<SharedObjectAckPacket> {
+        SharedObjectAckPacket::deserialize_from_vector(payload).ok()
+    }
+
+    pub fn validate_delta_signature_request(
+        _header: &LayoutVerified<&[u8], HdpHeader>,
+        payload: &[u8],
+    ) -> Option<DeltaSignatureRequestPacket> {
+        DeltaSignatureRequestPacket::deserialize_from_vector(payload).ok()
+    }
+
+    pub fn validate_delta_signature(
+        _header: &LayoutVerified<&[u8], HdpHeader>,
+        payload: &[u8],
+    ) -> Option<DeltaSignaturePacket> {
+        DeltaSignaturePacket::deserialize_from_vector(payload).ok()
+    }
+
+    pub fn validate_chunk_query(
+        _header: &LayoutVerified<&[u8], HdpHeader>,
+        payload: &[u8],
+    ) -> Option<ChunkQueryPacket> {
+        ChunkQueryPacket::deserialize_from_vector(payload).ok()
+    }
+
+    pub fn validate_chunk_query_ack(
+        _header: &LayoutVerified<&[u8], HdpHeader>,
+        payload: &[u8],
+    ) -> Option<ChunkQueryAckPacket> {
+        ChunkQueryAckPacket::deserialize_from_vector(payload).ok()
+    }
+
+    pub fn validate_transfer_control(
+        _header: &LayoutVerified<&[u8], HdpHeader>,
+        payload: &[u8],
+    ) -> Option<TransferControlPacket> {
+        TransferControlPacket::deserialize_from_vector(payload).ok()
+    }
+
+    /// Rejects manifests whose tag was not computed for this session, sender and object
+    pub fn validate_transfer_manifest(
+        hyper_ratchet: &StackedRatchet,
+        header: &LayoutVerified<&[u8], HdpHeader>,
+        payload: &[u8],
+    ) -> Option<TransferManifestPacket> {
+        let packet = TransferManifestPacket::deserialize_from_vector(payload).ok()?;
+        packet
+            .manifest
+            .verify_tag(
+                hyper_ratchet,
+                header.session_cid.get(),
+                packet.object_id,
+                &packet.tag,
+            )
+            .ok()?;
+        Some(packet)
+    }
+}
+
+pub(crate) mod capabilities {
+    use crate::proto::packet_crafter::capabilities::CapabilitiesPacket;
+    use citadel_user::serialization::SyncIO;
+
+    pub(crate) fn validate_capabilities(payload: &[u8]) -> Option<CapabilitiesPacket> {
+        CapabilitiesPacket::deserialize_from_vector(payload).ok()
+    }
 }
 
 pub(crate) mod aead {
@@ -408,6 +552,7 @@ pub(crate) mod aead {
 
     use crate::proto::packet::HdpHeader;
     use citadel_crypt::stacked_ratchet::StackedRatchet;
+    use citadel_pqcrypto::key_domain::KeyDomain;
 
     pub(crate) type AeadValidationResult<'a> =
         (LayoutVerified<&'a [u8], HdpHeader>, Bytes, StackedRatchet);
@@ -432,13 +577,24 @@ pub(crate) mod aead {
 
     /// First-pass validation. Ensures header integrity through AAD-services in AES-GCM
     pub(crate) fn validate_custom<'a, 'b: 'a, H: AsRef<[u8]> + 'b>(
+        hyper_ratchet: &StackedRatchet,
+        header: &'b H,
+        payload: BytesMut,
+    ) -> Option<(LayoutVerified<&'a [u8], HdpHeader>, BytesMut)> {
+        validate_custom_in_domain(KeyDomain::Primary, hyper_ratchet, header, payload)
+    }
+
+    /// Same as [`validate_custom`], using the subkeys of `domain`
+    pub(crate) fn validate_custom_in_domain<'a, 'b: 'a, H: AsRef<[u8]> + 'b>(
+        domain: KeyDomain,
         hyper_ratchet: &StackedRatchet,
         header: &'b H,
         mut payload: BytesMut,
     ) -> Option<(LayoutVerified<&'a [u8], HdpHeader>, BytesMut)> {
         let header_bytes = header.as_ref();
         let header = LayoutVerified::new(header_bytes)? as LayoutVerified<&[u8], HdpHeader>;
-        if let Err(err) = hyper_ratchet.validate_message_packet_in_place_split(
+        if let Err(err) = hyper_ratchet.validate_message_packet_in_place_split_in_domain(
+            domain,
             Some(header.security_level.into()),
             header_bytes,
             &mut payload,
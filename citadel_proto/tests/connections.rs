@@ -74,7 +74,8 @@ pub mod tests {
         for proto in protocols {
             log::trace!(target: "citadel", "Testing proto {:?} @ {:?}", &proto, addr);
 
-            let res = HdpServer::server_create_primary_listen_socket(proto.clone(), addr);
+            let res =
+                HdpServer::server_create_primary_listen_socket(proto.clone(), None, None, addr);
 
             if let Err(err) = res.as_ref() {
                 log::error!(target: "citadel", "Error creating primary socket: {:?}", err);
@@ -128,7 +129,8 @@ pub mod tests {
             log::trace!(target: "citadel", "Testing proto {:?}", &proto);
             let cnt = &AtomicUsize::new(0);
 
-            let res = HdpServer::server_create_primary_listen_socket(proto.clone(), addr);
+            let res =
+                HdpServer::server_create_primary_listen_socket(proto.clone(), None, None, addr);
 
             if let Err(err) = res.as_ref() {
                 log::error!(target: "citadel", "Error creating primary socket: {:?}", err);
@@ -0,0 +1,262 @@
+use crate::prefabs::client::single_connection::SingleClientServerConnectionKernel;
+use crate::prefabs::{get_socket_addr, ClientServerRemote};
+use crate::prelude::*;
+use futures::StreamExt;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use uuid::Uuid;
+
+/// A synchronous client connected to a central server. The client owns a runtime that executes
+/// the protocol on a background thread, such that each function blocks the calling thread until
+/// the request completes. Dropping the client disconnects from the server
+pub struct BlockingClient {
+    handle: Handle,
+    sender: PeerChannelSendHalf,
+    receiver: PeerChannelRecvHalf,
+    remote: Option<ClientServerRemote>,
+    cid: u64,
+    node: Option<JoinHandle<Result<(), NetworkError>>>,
+}
+
+enum BlockingConnectionType {
+    Register {
+        full_name: String,
+        username: String,
+        password: SecBuffer,
+        server_addr: SocketAddr,
+    },
+    Passwordless {
+        uuid: Uuid,
+        server_addr: SocketAddr,
+    },
+}
+
+type ConnectionEstablished = (ConnectionSuccess, ClientServerRemote, Handle);
+
+impl BlockingClient {
+    /// Registers with the central server if the account does not yet exist locally, then connects
+    pub fn connect<T: Into<String>, R: Into<String>, P: Into<SecBuffer>, V: ToSocketAddrs>(
+        full_name: T,
+        username: R,
+        password: P,
+        server_addr: V,
+    ) -> Result<Self, NetworkError> {
+        Self::connect_with(BlockingConnectionType::Register {
+            full_name: full_name.into(),
+            username: username.into(),
+            password: password.into(),
+            server_addr: get_socket_addr(server_addr)?,
+        })
+    }
+
+    /// Connects to the central server without credentials
+    pub fn connect_passwordless<V: ToSocketAddrs>(
+        uuid: Uuid,
+        server_addr: V,
+    ) -> Result<Self, NetworkError> {
+        Self::connect_with(BlockingConnectionType::Passwordless {
+            uuid,
+            server_addr: get_socket_addr(server_addr)?,
+        })
+    }
+
+    fn connect_with(connection_type: BlockingConnectionType) -> Result<Self, NetworkError> {
+        let (connected_tx, connected_rx) = tokio::sync::oneshot::channel::<ConnectionEstablished>();
+
+        let node = std::thread::Builder::new()
+            .name("citadel-blocking-client".to_string())
+            .spawn(move || {
+                let runtime = tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                    .map_err(|err| NetworkError::Generic(err.to_string()))?;
+
+                let on_channel_received =
+                    move |conn: ConnectionSuccess, remote: ClientServerRemote| async move {
+                        // if the caller is gone, the node is shut down once the remote drops
+                        let _ = connected_tx.send((conn, remote, Handle::current()));
+                        Ok::<(), NetworkError>(())
+                    };
+
+                runtime.block_on(async move {
+                    match connection_type {
+                        BlockingConnectionType::Register {
+                            full_name,
+                            username,
+                            password,
+                            server_addr,
+                        } => {
+                            run_node(SingleClientServerConnectionKernel::new_register_defaults(
+                                full_name,
+                                username,
+                                password,
+                                server_addr,
+                                on_channel_received,
+                            )?)
+                            .await
+                        }
+
+                        BlockingConnectionType::Passwordless { uuid, server_addr } => {
+                            run_node(
+                                SingleClientServerConnectionKernel::new_passwordless_defaults(
+                                    uuid,
+                                    server_addr,
+                                    on_channel_received,
+                                )?,
+                            )
+                            .await
+                        }
+                    }
+                })
+            })
+            .map_err(|err| NetworkError::Generic(err.to_string()))?;
+
+        match connected_rx.blocking_recv() {
+            Ok((conn, remote, handle)) => {
+                let (sender, receiver) = conn.channel.split();
+                Ok(Self {
+                    handle,
+                    sender,
+                    receiver,
+                    remote: Some(remote),
+                    cid: conn.cid,
+                    node: Some(node),
+                })
+            }
+
+            Err(_) => Err(match node.join() {
+                Ok(Err(err)) => err,
+                Ok(Ok(_)) => NetworkError::msg("The node stopped before connecting"),
+                Err(_) => NetworkError::msg("The node panicked before connecting"),
+            }),
+        }
+    }
+
+    /// The CID of the local user
+    pub fn cid(&self) -> u64 {
+        self.cid
+    }
+
+    /// Sends a message to the server, blocking while the server falls behind
+    pub fn send<T: Into<SecureProtocolPacket>>(&self, message: T) -> Result<(), NetworkError> {
+        self.handle
+            .block_on(self.sender.send_message(message.into()))
+    }
+
+    /// Blocks until a message arrives. Returns `None` once the connection closes
+    pub fn recv(&mut self) -> Option<SecBuffer> {
+        self.handle.block_on(self.receiver.next())
+    }
+
+    /// Blocks until a message arrives or `timeout` elapses. Returns `Ok(None)` if the timeout
+    /// elapsed first
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<Option<SecBuffer>, NetworkError> {
+        match self
+            .handle
+            .block_on(tokio::time::timeout(timeout, self.receiver.next()))
+        {
+            Ok(Some(message)) => Ok(Some(message)),
+            Ok(None) => Err(NetworkError::msg("The connection is closed")),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Sends a file to the server, blocking until the server has received the whole file
+    pub fn send_file<T: ObjectSource>(&mut self, source: T) -> Result<(), NetworkError> {
+        let remote = self
+            .remote
+            .as_mut()
+            .ok_or_else(|| NetworkError::msg("The client is shut down"))?;
+        self.handle.block_on(remote.send_file(source))
+    }
+
+    /// Disconnects from the server and stops the background runtime
+    pub fn shutdown(mut self) -> Result<(), NetworkError> {
+        self.close()
+    }
+
+    fn close(&mut self) -> Result<(), NetworkError> {
+        if let Some(remote) = self.remote.take() {
+            self.handle.block_on(remote.shutdown_kernel())?;
+        }
+
+        match self.node.take().map(JoinHandle::join) {
+            Some(Ok(result)) => result,
+            Some(Err(_)) => Err(NetworkError::msg("The node panicked")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for BlockingClient {
+    fn drop(&mut self) {
+        if let Err(err) = self.close() {
+            log::warn!(target: "citadel", "Unable to cleanly shut down the blocking client: {err:?}");
+        }
+    }
+}
+
+async fn run_node<K: NetKernel>(kernel: K) -> Result<(), NetworkError> {
+    let _ = NodeBuilder::default()
+        .build(kernel)
+        .map_err(|err| NetworkError::Generic(err.to_string()))?
+        .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blocking::BlockingClient;
+    use crate::prelude::*;
+    use crate::test_common::server_info_reactive;
+    use futures::StreamExt;
+    use rstest::rstest;
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_blocking_client_echo() {
+        citadel_logging::setup_log();
+
+        let (server, server_addr) = server_info_reactive(
+            |conn, _remote| async move {
+                let (tx, mut rx) = conn.channel.split();
+                while let Some(message) = rx.next().await {
+                    tx.send_message(message.as_ref().into()).await?;
+                }
+
+                Ok(())
+            },
+            |_| {},
+        );
+
+        let client = tokio::task::spawn_blocking(move || {
+            let mut client = BlockingClient::connect_passwordless(Uuid::new_v4(), server_addr)?;
+            assert_ne!(client.cid(), 0);
+            assert!(client
+                .recv_timeout(Duration::from_millis(100))
+                .unwrap()
+                .is_none());
+
+            for idx in 0..10u8 {
+                client.send([idx; 64])?;
+                let echo = client.recv_timeout(Duration::from_secs(10))?.unwrap();
+                assert_eq!(echo.as_ref(), &[idx; 64]);
+            }
+
+            client.shutdown()
+        });
+
+        tokio::select! {
+            res0 = server => {
+                res0.unwrap();
+                panic!("The server stopped before the client finished");
+            },
+            res1 = client => res1.unwrap().unwrap()
+        }
+    }
+}
@@ -5,6 +5,7 @@ use citadel_proto::re_imports::RustlsClientConfig;
 use futures::Future;
 use std::fmt::{Debug, Formatter};
 use std::marker::PhantomData;
+use std::net::SocketAddr;
 use std::path::Path;
 use std::pin::Pin;
 use std::sync::Arc;
@@ -23,6 +24,17 @@ pub struct NodeBuilder {
     client_tls_config: Option<RustlsClientConfig>,
     kernel_executor_settings: Option<KernelExecutorSettings>,
     stun_servers: Option<Vec<String>>,
+    hole_punch_settings: Option<HolePunchConfigContainer>,
+    entropy_source: Option<(Box<dyn EntropySource>, HealthTestConfig)>,
+    memory_lock_policy: Option<MemoryLockPolicy>,
+    packet_filter: Option<Arc<dyn PacketFilter>>,
+    account_hook: Option<Arc<dyn AccountHook>>,
+    abuse_detector: Option<Arc<dyn AbuseDetector>>,
+    server_key_pinning: Option<ServerKeyPinning>,
+    cert_bootstrap_code: Option<String>,
+    key_rotation: Option<KeyRotationAnnouncement>,
+    embedded_stun_server: Option<SocketAddr>,
+    credential_validator: Option<(Arc<dyn CredentialValidator>, GroupPolicies)>,
 }
 
 /// An awaitable future whose return value propagates any internal protocol or kernel-level errors
@@ -55,6 +67,15 @@ impl NodeBuilder {
         kernel: K,
     ) -> anyhow::Result<NodeFuture<'b, K>> {
         self.check()?;
+        if let Some((source, config)) = self.entropy_source.take() {
+            citadel_proto::re_imports::set_entropy_source(source, config)
+                .map_err(|err| anyhow::Error::msg(err.to_string()))?;
+        }
+
+        if let Some(policy) = self.memory_lock_policy.take() {
+            citadel_proto::re_imports::set_memory_lock_policy(policy);
+        }
+
         let hypernode_type = self.hypernode_type.take().unwrap_or_default();
         let backend_type = self.backend_type.take().unwrap_or_else(|| {
             if cfg!(feature = "filesystem") {
@@ -75,6 +96,20 @@ impl NodeBuilder {
         let client_config = self.client_tls_config.take().map(Arc::new);
         let kernel_executor_settings = self.kernel_executor_settings.take().unwrap_or_default();
         let stun_servers = self.stun_servers.take();
+        let hole_punch_settings = self.hole_punch_settings.take();
+        let packet_filter = self.packet_filter.take();
+        let account_hook = self.account_hook.take();
+        let abuse_detector = self.abuse_detector.take();
+        let cert_bootstrap_code = self.cert_bootstrap_code.take();
+        let key_rotation = self.key_rotation.take();
+        // the bootstrapped certificate is verified via pinning
+        let server_key_pinning = self.server_key_pinning.take().or_else(|| {
+            cert_bootstrap_code
+                .as_ref()
+                .map(|_| ServerKeyPinning::in_memory(ServerKeyChangePolicy::Refuse))
+        });
+        let embedded_stun_server = self.embedded_stun_server.take();
+        let credential_validator = self.credential_validator.take();
 
         let underlying_proto = if let Some(proto) = self.underlying_protocol.take() {
             proto
@@ -98,6 +133,12 @@ impl NodeBuilder {
                     server_misc_settings,
                 )
                 .await?;
+                let account_manager = match credential_validator {
+                    Some((validator, policies)) => {
+                        account_manager.with_credential_validator(validator, policies)
+                    }
+                    None => account_manager,
+                };
 
                 let args = KernelExecutorArguments {
                     rt,
@@ -108,6 +149,14 @@ impl NodeBuilder {
                     client_config,
                     kernel_executor_settings,
                     stun_servers,
+                    hole_punch_settings,
+                    packet_filter,
+                    account_hook,
+                    abuse_detector,
+                    server_key_pinning,
+                    cert_bootstrap_code,
+                    key_rotation,
+                    embedded_stun_server,
                 };
 
                 log::trace!(target: "citadel", "[NodeBuilder] Creating KernelExecutor ...");
@@ -149,6 +198,72 @@ impl NodeBuilder {
         self
     }
 
+    /// Inspects the header of each inbound packet before its payload is decrypted, allowing floods
+    /// of expensive-to-decrypt packets to be dropped or deprioritized
+    /// ```
+    /// use citadel_sdk::prelude::*;
+    ///
+    /// NodeBuilder::default().with_packet_filter(|packet: &PacketMetadata| {
+    ///     if packet.command == PacketCommand::PeerCommand && packet.len > 64 * 1024 {
+    ///         PacketVerdict::Drop
+    ///     } else {
+    ///         PacketVerdict::Accept
+    ///     }
+    /// });
+    /// ```
+    pub fn with_packet_filter(&mut self, packet_filter: impl PacketFilter) -> &mut Self {
+        self.packet_filter = Some(Arc::new(packet_filter));
+        self
+    }
+
+    /// Notifies `account_hook` whenever an account is registered to, or deregistered from, this
+    /// server, allowing external systems to be provisioned without polling the backend
+    /// ```
+    /// use citadel_sdk::prelude::*;
+    ///
+    /// struct Provisioner;
+    ///
+    /// #[async_trait]
+    /// impl AccountHook for Provisioner {
+    ///     async fn on_register(&self, event: AccountEvent) -> Result<(), NetworkError> {
+    ///         println!("Provisioning {}", event.account.username);
+    ///         Ok(())
+    ///     }
+    /// }
+    ///
+    /// NodeBuilder::default().with_account_hook(Provisioner);
+    /// ```
+    pub fn with_account_hook(&mut self, account_hook: impl AccountHook) -> &mut Self {
+        self.account_hook = Some(Arc::new(account_hook));
+        self
+    }
+
+    /// Samples the messages, bytes and peers each client sends per window, handing each sample to
+    /// `abuse_detector`. The returned [`AbuseAction`] is enforced on the session of the client, and
+    /// reported to the kernel via [`NodeResult::AbuseDetected`] unless it is [`AbuseAction::Allow`].
+    /// Applies only to servers
+    /// ```
+    /// use citadel_sdk::prelude::*;
+    /// use std::time::Duration;
+    ///
+    /// NodeBuilder::default().with_abuse_detector(|sample: &SessionRateSample| {
+    ///     if sample.fanout > 100 {
+    ///         AbuseAction::Disconnect
+    ///     } else if sample.messages_per_second > 500.0 {
+    ///         AbuseAction::Throttle {
+    ///             max_messages_per_second: 50,
+    ///             duration: Duration::from_secs(60),
+    ///         }
+    ///     } else {
+    ///         AbuseAction::Allow
+    ///     }
+    /// });
+    /// ```
+    pub fn with_abuse_detector(&mut self, abuse_detector: impl AbuseDetector) -> &mut Self {
+        self.abuse_detector = Some(Arc::new(abuse_detector));
+        self
+    }
+
     /// Attaches custom Argon settings for password hashing at the server
     pub fn with_server_argon_settings(
         &mut self,
@@ -224,6 +339,59 @@ impl NodeBuilder {
         self
     }
 
+    /// Pins the certificate each self-signed server presents on the first connection, instead of
+    /// skipping its verification. Once the certificate of a server changes, the connection is
+    /// refused or proceeds with a warning as specified by the [`ServerKeyChangePolicy`]. Servers
+    /// presenting a certificate with a domain are verified against the client TLS config as usual
+    /// ```
+    /// use citadel_sdk::prelude::*;
+    ///
+    /// let store = FilePinnedCertStore::open("/tmp/citadel_pinned_certs").unwrap();
+    /// NodeBuilder::default().with_server_key_pinning(ServerKeyPinning::new(
+    ///     store,
+    ///     ServerKeyChangePolicy::Refuse,
+    /// ));
+    /// ```
+    pub fn with_server_key_pinning(&mut self, pinning: ServerKeyPinning) -> &mut Self {
+        self.server_key_pinning = Some(pinning);
+        self
+    }
+
+    /// Authenticates the self-signed TLS certificate of a server via a short pre-shared code,
+    /// such that no CA is needed. As a server, clients holding the same code may authenticate
+    /// its certificate on their first connection. As a client, the certificate of each
+    /// self-signed server not yet pinned is authenticated via the code, then pinned. Unless
+    /// [`Self::with_server_key_pinning`] is specified, certificates are pinned in-memory, and
+    /// changed certificates are refused. Only applies to the TLS underlying protocol
+    /// ```
+    /// use citadel_sdk::prelude::*;
+    ///
+    /// NodeBuilder::default().with_cert_bootstrap_code("428-913");
+    /// ```
+    pub fn with_cert_bootstrap_code<T: Into<String>>(&mut self, code: T) -> &mut Self {
+        self.cert_bootstrap_code = Some(code.into());
+        self
+    }
+
+    /// Publishes the upcoming certificate of this self-signed server to each client ahead of the
+    /// handshake. Clients pinning the current certificate via [`Self::with_server_key_pinning`]
+    /// accept the upcoming certificate once the server presents it, instead of refusing the
+    /// changed certificate. Keep publishing the announcement for a while after the cutover, such
+    /// that clients which did not connect in between rotate their pin as well
+    /// ```
+    /// use citadel_sdk::prelude::*;
+    ///
+    /// let current = ServerIdentity::generate().unwrap();
+    /// let upcoming = ServerIdentity::generate().unwrap();
+    /// NodeBuilder::default()
+    ///     .with_underlying_protocol(ServerUnderlyingProtocol::new_tls_from_identity(&current).unwrap())
+    ///     .with_server_key_rotation(current.announce_rotation(&upcoming).unwrap());
+    /// ```
+    pub fn with_server_key_rotation(&mut self, announcement: KeyRotationAnnouncement) -> &mut Self {
+        self.key_rotation = Some(announcement);
+        self
+    }
+
     /// Loads a custom list of certs into the acceptable certificate list. Connections that present server certificates
     /// that are outside of this list during the handshake process are refused
     pub fn with_custom_certs<T: AsRef<[u8]>>(
@@ -251,6 +419,92 @@ impl NodeBuilder {
         self
     }
 
+    /// Applies `settings` to each UDP hole punch of this node, such as port mapping, multi-path mode
+    /// or custom traversal methods. The packet crypto of `settings` is ignored, since each session
+    /// keys its own hole punches, as are its STUN servers, which are set via
+    /// [`Self::with_stun_servers`]. Settings the nodes must agree upon, such as multi-path mode,
+    /// must be applied to both
+    /// ```
+    /// use citadel_sdk::prelude::*;
+    ///
+    /// NodeBuilder::default().with_hole_punch_settings(
+    ///     HolePunchConfigContainer::default()
+    ///         .with_port_mapping(true)
+    ///         .with_multipath(true),
+    /// );
+    /// ```
+    pub fn with_hole_punch_settings(&mut self, settings: HolePunchConfigContainer) -> &mut Self {
+        self.hole_punch_settings = Some(settings);
+        self
+    }
+
+    /// Validates the passwords of clients registering with [`ProposedCredentials::new_delegated`]
+    /// against an external directory, such as LDAP or Active Directory, instead of storing their
+    /// hashes. The groups of each user are mapped onto the policy of their sessions via `policies`
+    /// ```
+    /// use citadel_sdk::prelude::*;
+    ///
+    /// struct Directory;
+    ///
+    /// #[async_trait]
+    /// impl CredentialValidator for Directory {
+    ///     async fn validate(&self, username: &str, password: &SecBuffer) -> Result<ValidatedUser, AccountError> {
+    ///         if username == "alice" && password.as_ref() == b"password" {
+    ///             Ok(ValidatedUser { full_name: None, groups: vec!["engineering".to_string()] })
+    ///         } else {
+    ///             Err(AccountError::InvalidPassword)
+    ///         }
+    ///     }
+    /// }
+    ///
+    /// let policies = GroupPolicies::new(AccountPolicy { allow_file_transfer: false })
+    ///     .with_group("engineering", AccountPolicy::default());
+    /// NodeBuilder::default().with_credential_validator(Directory, policies);
+    /// ```
+    pub fn with_credential_validator(
+        &mut self,
+        credential_validator: impl CredentialValidator,
+        policies: GroupPolicies,
+    ) -> &mut Self {
+        self.credential_validator = Some((Arc::new(credential_validator), policies));
+        self
+    }
+
+    /// Serves STUN binding requests on `bind_addr`, such that a single-server deployment needs no
+    /// third-party STUN servers. Clients of the server should list its STUN addr thrice via
+    /// [`Self::with_stun_servers`]. Unless STUN servers are specified for this node, the node
+    /// uses its own responder. Only valid for servers
+    pub fn with_embedded_stun_server<T: Into<SocketAddr>>(&mut self, bind_addr: T) -> &mut Self {
+        self.embedded_stun_server = Some(bind_addr.into());
+        self
+    }
+
+    /// Specifies an external entropy source (e.g., an HSM) for the entropy bank. The source is
+    /// continuously health tested against `config`; if it fails its start-up tests, the node
+    /// fails to build, and if it fails later on, all further key generation fails.
+    /// If left unspecified, the thread-local CSPRNG is used
+    ///
+    /// Note: the entropy source is process-wide, and thus is shared by all nodes in the process
+    pub fn with_entropy_source<S: EntropySource>(
+        &mut self,
+        source: S,
+        config: HealthTestConfig,
+    ) -> &mut Self {
+        self.entropy_source = Some((Box::new(source), config));
+        self
+    }
+
+    /// Locks the pages holding the key material of ratchets at, or above, the given security
+    /// level into memory, preventing them from being swapped to disk. If locking fails (e.g.,
+    /// because the process exceeds its limit of locked memory), the ratchet remains usable.
+    /// By default, ratchets are not locked
+    ///
+    /// Note: the policy is process-wide, and thus is shared by all nodes in the process
+    pub fn with_memory_lock_policy(&mut self, policy: MemoryLockPolicy) -> &mut Self {
+        self.memory_lock_policy = Some(policy);
+        self
+    }
+
     fn check(&self) -> anyhow::Result<()> {
         #[cfg(feature = "google-services")]
         if let Some(svc) = self.services.as_ref() {
@@ -261,6 +515,12 @@ impl NodeBuilder {
             }
         }
 
+        if self.embedded_stun_server.is_some()
+            && !matches!(self.hypernode_type, Some(NodeType::Server(..)))
+        {
+            return Err(anyhow::Error::msg("Only servers may serve STUN requests"));
+        }
+
         if let Some(stun_servers) = self.stun_servers.as_ref() {
             if stun_servers.len() != 3 {
                 return Err(anyhow::Error::msg(
@@ -277,10 +537,12 @@ impl NodeBuilder {
 mod tests {
     use crate::builder::node_builder::NodeBuilder;
     use crate::prefabs::server::empty::EmptyKernel;
-    use crate::prelude::{BackendType, NodeType};
-    use citadel_proto::prelude::{KernelExecutorSettings, ServerUnderlyingProtocol};
+    use crate::prelude::{BackendType, NodeType, ProtocolRemoteExt};
+    use citadel_proto::prelude::*;
     use rstest::rstest;
     use std::str::FromStr;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
 
     #[test]
     #[cfg(feature = "google-services")]
@@ -309,6 +571,14 @@ mod tests {
             .is_err());
     }
 
+    #[test]
+    fn bad_config3() {
+        assert!(NodeBuilder::default()
+            .with_embedded_stun_server(std::net::SocketAddr::from_str("127.0.0.1:3478").unwrap())
+            .build(EmptyKernel::default())
+            .is_err());
+    }
+
     #[rstest]
     #[tokio::test]
     #[timeout(std::time::Duration::from_secs(60))]
@@ -345,4 +615,343 @@ mod tests {
         #[allow(clippy::let_underscore_must_use)]
         let _ = builder.build(EmptyKernel::default()).unwrap();
     }
+
+    /// Panics the first time it receives a list of sessions, then shuts down once notified
+    #[derive(Default)]
+    struct PanickingKernel {
+        remote: Option<NodeRemote>,
+        state: Arc<PanickingKernelState>,
+    }
+
+    /// Shared with the test, since the kernel is not returned if the node shuts down with an error
+    #[derive(Default)]
+    struct PanickingKernelState {
+        starts: AtomicUsize,
+        panicked: AtomicBool,
+        notified: AtomicBool,
+    }
+
+    #[async_trait]
+    impl NetKernel for PanickingKernel {
+        fn load_remote(&mut self, node_remote: NodeRemote) -> Result<(), NetworkError> {
+            self.remote = Some(node_remote);
+            Ok(())
+        }
+
+        async fn on_start(&self) -> Result<(), NetworkError> {
+            let _ = self.state.starts.fetch_add(1, Ordering::Relaxed);
+            let _ = self
+                .remote
+                .clone()
+                .unwrap()
+                .send(NodeRequest::GetActiveSessions)
+                .await?;
+            Ok(())
+        }
+
+        async fn on_node_event_received(&self, message: NodeResult) -> Result<(), NetworkError> {
+            match message {
+                NodeResult::SessionList(_)
+                    if !self.state.panicked.swap(true, Ordering::Relaxed) =>
+                {
+                    panic!("Intentional panic")
+                }
+
+                NodeResult::KernelPanicked(panicked) => {
+                    assert_eq!(panicked.message, "Intentional panic");
+                    assert_eq!(panicked.event, "SessionList");
+                    self.state.notified.store(true, Ordering::Relaxed);
+                    self.remote.clone().unwrap().shutdown().await
+                }
+
+                _ => Ok(()),
+            }
+        }
+
+        async fn on_stop(&mut self) -> Result<(), NetworkError> {
+            Ok(())
+        }
+    }
+
+    #[rstest]
+    #[case(KernelPanicPolicy::DropEvent, 1, true)]
+    #[case(KernelPanicPolicy::RestartKernel { max_restarts: 1 }, 2, true)]
+    #[case(KernelPanicPolicy::Shutdown, 1, false)]
+    #[tokio::test]
+    #[timeout(std::time::Duration::from_secs(60))]
+    async fn test_kernel_panic_policy(
+        #[case] panic_policy: KernelPanicPolicy,
+        #[case] expected_starts: usize,
+        #[case] expect_notified: bool,
+    ) {
+        citadel_logging::setup_log();
+        let mut builder = NodeBuilder::default();
+        let _ = builder.with_kernel_executor_settings(
+            KernelExecutorSettings::default().with_panic_policy(panic_policy),
+        );
+
+        let kernel = PanickingKernel::default();
+        let state = kernel.state.clone();
+        let result = builder.build(kernel).unwrap().await;
+        // upon shutdown, the node may end with the error of the kernel or with the clean stop of
+        // the server, depending on which finishes first
+        if expect_notified {
+            assert!(result.is_ok());
+        }
+
+        assert!(state.panicked.load(Ordering::Relaxed));
+        assert_eq!(state.notified.load(Ordering::Relaxed), expect_notified);
+        assert_eq!(state.starts.load(Ordering::Relaxed), expected_starts);
+    }
+
+    /// Opens a connection to itself that never advances past the first stage, then reports how
+    /// many sessions were reaped
+    struct ReapingKernel {
+        remote: Option<NodeRemote>,
+        bind_addr: std::net::SocketAddr,
+        counts: citadel_io::Mutex<Option<ReapedSessionCounts>>,
+    }
+
+    #[async_trait]
+    impl NetKernel for ReapingKernel {
+        fn load_remote(&mut self, node_remote: NodeRemote) -> Result<(), NetworkError> {
+            self.remote = Some(node_remote);
+            Ok(())
+        }
+
+        async fn on_start(&self) -> Result<(), NetworkError> {
+            let _half_open = tokio::net::TcpStream::connect(self.bind_addr)
+                .await
+                .map_err(|err| NetworkError::Generic(err.to_string()))?;
+            tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+            let _ = self
+                .remote
+                .clone()
+                .unwrap()
+                .send(NodeRequest::GetReapedSessions)
+                .await?;
+            Ok(())
+        }
+
+        async fn on_node_event_received(&self, message: NodeResult) -> Result<(), NetworkError> {
+            if let NodeResult::ReapedSessions(reaped) = message {
+                *self.counts.lock() = Some(reaped.counts);
+                self.remote.clone().unwrap().shutdown().await?;
+            }
+
+            Ok(())
+        }
+
+        async fn on_stop(&mut self) -> Result<(), NetworkError> {
+            Ok(())
+        }
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[timeout(std::time::Duration::from_secs(60))]
+    #[cfg(feature = "localhost-testing")]
+    async fn test_provisional_session_reaped() {
+        citadel_logging::setup_log();
+        let port = crate::test_common::get_unused_tcp_port();
+        let bind_addr = std::net::SocketAddr::from_str(&format!("127.0.0.1:{port}")).unwrap();
+        let kernel = ReapingKernel {
+            remote: None,
+            bind_addr,
+            counts: Default::default(),
+        };
+
+        let kernel = crate::test_common::server_test_node(bind_addr, kernel, |builder| {
+            let _ = builder
+                .with_underlying_protocol(ServerUnderlyingProtocol::Tcp)
+                .with_server_misc_settings(ServerMiscSettings {
+                    provisional_timeouts: ProvisionalTimeouts {
+                        connect: std::time::Duration::from_secs(1),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                });
+        })
+        .await
+        .unwrap();
+
+        let counts = kernel.counts.lock().unwrap();
+        assert_eq!(counts.connect, 1);
+        assert_eq!(counts.register, 0);
+    }
+
+    /// A directory that never answers, hanging the handler of the registration packet
+    struct HangingDirectory;
+
+    #[async_trait]
+    impl CredentialValidator for HangingDirectory {
+        async fn validate(
+            &self,
+            _username: &str,
+            _password: &SecBuffer,
+        ) -> Result<ValidatedUser, AccountError> {
+            futures::future::pending().await
+        }
+    }
+
+    /// Registers to a server whose directory hangs, then shuts down once the registration ends
+    struct HungRegistrationKernel {
+        remote: Option<NodeRemote>,
+        server_addr: std::net::SocketAddr,
+        ended: AtomicBool,
+    }
+
+    #[async_trait]
+    impl NetKernel for HungRegistrationKernel {
+        fn load_remote(&mut self, node_remote: NodeRemote) -> Result<(), NetworkError> {
+            self.remote = Some(node_remote);
+            Ok(())
+        }
+
+        async fn on_start(&self) -> Result<(), NetworkError> {
+            let mut remote = self.remote.clone().unwrap();
+            let res = remote
+                .register_delegated(
+                    self.server_addr,
+                    "Alice",
+                    "alice",
+                    "password",
+                    SessionSecuritySettings::default(),
+                )
+                .await;
+            assert!(res.is_err());
+            self.ended.store(true, Ordering::Relaxed);
+            remote.shutdown().await
+        }
+
+        async fn on_node_event_received(&self, _message: NodeResult) -> Result<(), NetworkError> {
+            Ok(())
+        }
+
+        async fn on_stop(&mut self) -> Result<(), NetworkError> {
+            Ok(())
+        }
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[timeout(std::time::Duration::from_secs(60))]
+    #[cfg(feature = "localhost-testing")]
+    async fn test_watchdog_terminates_hung_session() {
+        citadel_logging::setup_log();
+        let port = crate::test_common::get_unused_tcp_port();
+        let server_addr = std::net::SocketAddr::from_str(&format!("127.0.0.1:{port}")).unwrap();
+        let server = crate::test_common::server_test_node(server_addr, EmptyKernel, |builder| {
+            let _ = builder
+                .with_credential_validator(
+                    HangingDirectory,
+                    GroupPolicies::new(AccountPolicy::default()),
+                )
+                .with_server_misc_settings(ServerMiscSettings {
+                    session_watchdog: Some(SessionWatchdogSettings {
+                        stall_timeout: std::time::Duration::from_secs(1),
+                        terminate_hung_sessions: true,
+                    }),
+                    // ensures only the watchdog may end the registration within the test's timeout
+                    provisional_timeouts: ProvisionalTimeouts {
+                        register: std::time::Duration::from_secs(600),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                });
+        });
+
+        let client = NodeBuilder::default()
+            .build(HungRegistrationKernel {
+                remote: None,
+                server_addr,
+                ended: AtomicBool::new(false),
+            })
+            .unwrap();
+
+        tokio::select! {
+            res0 = server => panic!("The server ended unexpectedly: {:?}", res0.map(|_| ())),
+            res1 = client => assert!(res1.unwrap().ended.load(Ordering::Relaxed)),
+        }
+    }
+
+    /// Sends plaintext where the server expects a TLS handshake, then reports the failure
+    struct HandshakeFailureKernel {
+        remote: Option<NodeRemote>,
+        bind_addr: std::net::SocketAddr,
+        failure: citadel_io::Mutex<Option<(HandshakeFailureReason, HandshakeFailureCounts)>>,
+    }
+
+    #[async_trait]
+    impl NetKernel for HandshakeFailureKernel {
+        fn load_remote(&mut self, node_remote: NodeRemote) -> Result<(), NetworkError> {
+            self.remote = Some(node_remote);
+            Ok(())
+        }
+
+        async fn on_start(&self) -> Result<(), NetworkError> {
+            use tokio::io::AsyncWriteExt;
+            let mut stream = tokio::net::TcpStream::connect(self.bind_addr)
+                .await
+                .map_err(|err| NetworkError::Generic(err.to_string()))?;
+            stream
+                .write_all(b"GET / HTTP/1.1\r\n\r\n")
+                .await
+                .map_err(|err| NetworkError::Generic(err.to_string()))?;
+            tokio::time::sleep(std::time::Duration::from_secs(3)).await;
+            Ok(())
+        }
+
+        async fn on_node_event_received(&self, message: NodeResult) -> Result<(), NetworkError> {
+            match message {
+                NodeResult::HandshakeFailed(failed) => {
+                    *self.failure.lock() = Some((failed.reason, Default::default()));
+                    let _ = self
+                        .remote
+                        .clone()
+                        .unwrap()
+                        .send(NodeRequest::GetHandshakeFailures)
+                        .await?;
+                }
+
+                NodeResult::HandshakeFailures(failures) => {
+                    if let Some((_, counts)) = self.failure.lock().as_mut() {
+                        *counts = failures.counts;
+                    }
+                    self.remote.clone().unwrap().shutdown().await?;
+                }
+
+                _ => {}
+            }
+
+            Ok(())
+        }
+
+        async fn on_stop(&mut self) -> Result<(), NetworkError> {
+            Ok(())
+        }
+    }
+
+    #[rstest]
+    #[tokio::test]
+    #[timeout(std::time::Duration::from_secs(60))]
+    #[cfg(feature = "localhost-testing")]
+    async fn test_tls_handshake_failure_reported() {
+        citadel_logging::setup_log();
+        let port = crate::test_common::get_unused_tcp_port();
+        let bind_addr = std::net::SocketAddr::from_str(&format!("127.0.0.1:{port}")).unwrap();
+        let kernel = HandshakeFailureKernel {
+            remote: None,
+            bind_addr,
+            failure: Default::default(),
+        };
+
+        let kernel = crate::test_common::server_test_node(bind_addr, kernel, |_| {})
+            .await
+            .unwrap();
+
+        let (reason, counts) = kernel.failure.lock().unwrap();
+        assert_eq!(reason, HandshakeFailureReason::Tls);
+        assert_eq!(counts.tls, 1);
+    }
 }
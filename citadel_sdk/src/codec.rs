@@ -0,0 +1,454 @@
+//! Codecs turning a [`PeerChannel`] into a typed [`Sink`] and [`Stream`]
+//!
+//! Channels carry raw bytes, leaving each application to serialize every message it sends and
+//! deserialize every message it receives. Through [`TypedChannelExt::typed`], a channel is split
+//! into a [`TypedSink`] and [`TypedStream`] that do so using the given [`Codec`]. Codecs for JSON
+//! and bincode are built in, alongside a codec for protobuf messages when the `protobuf` feature
+//! is enabled. Both endpoints must use the same codec
+//!
+//! During rolling upgrades, endpoints running different versions of an application may disagree
+//! on the messages of a channel, which would otherwise surface as deserialization failures
+//! midway through a conversation. [`TypedChannelExt::typed_negotiated`] exchanges a
+//! [`SchemaManifest`] describing the [`ChannelSchema`] and codec of each endpoint once the channel
+//! opens, failing with a [`SchemaError`] before any message is exchanged if they are incompatible
+//!
+//! ```no_run
+//! use citadel_sdk::codec::{JsonCodec, TypedChannelExt};
+//! use citadel_sdk::prelude::*;
+//! use futures::{SinkExt, StreamExt};
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Chat {
+//!     text: String,
+//! }
+//!
+//! # async fn run(channel: PeerChannel) -> Result<(), NetworkError> {
+//! let (mut sink, mut stream) = channel.typed::<Chat, JsonCodec>();
+//! sink.send(Chat { text: "hello".into() }).await?;
+//! while let Some(chat) = stream.next().await {
+//!     println!("{}", chat?.text);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::prelude::*;
+use futures::{Sink, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Converts messages of type `T` to and from the bytes sent through a channel
+pub trait Codec<T>: Send + Sync + 'static {
+    fn encode(item: &T) -> Result<Vec<u8>, NetworkError>;
+    fn decode(bytes: &[u8]) -> Result<T, NetworkError>;
+
+    /// Identifies the codec within a [`SchemaManifest`]
+    fn name() -> &'static str
+    where
+        Self: Sized,
+    {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// Encodes messages as JSON, allowing interoperability with applications outside of Rust
+pub struct JsonCodec;
+
+impl<T: Serialize + DeserializeOwned> Codec<T> for JsonCodec {
+    fn encode(item: &T) -> Result<Vec<u8>, NetworkError> {
+        serde_json::to_vec(item).map_err(|err| NetworkError::Generic(err.to_string()))
+    }
+
+    fn decode(bytes: &[u8]) -> Result<T, NetworkError> {
+        serde_json::from_slice(bytes).map_err(|err| NetworkError::Generic(err.to_string()))
+    }
+
+    fn name() -> &'static str {
+        "json"
+    }
+}
+
+/// Encodes messages using bincode, the compact format used by the protocol itself
+pub struct BincodeCodec;
+
+impl<T: Serialize + DeserializeOwned> Codec<T> for BincodeCodec {
+    fn encode(item: &T) -> Result<Vec<u8>, NetworkError> {
+        item.serialize_to_vector()
+            .map_err(|err| NetworkError::Generic(err.into_string()))
+    }
+
+    fn decode(bytes: &[u8]) -> Result<T, NetworkError> {
+        T::deserialize_from_vector(bytes).map_err(|err| NetworkError::Generic(err.into_string()))
+    }
+
+    fn name() -> &'static str {
+        "bincode"
+    }
+}
+
+/// Encodes protobuf messages generated by prost
+#[cfg(feature = "protobuf")]
+pub struct ProtobufCodec;
+
+#[cfg(feature = "protobuf")]
+impl<T: prost::Message + Default> Codec<T> for ProtobufCodec {
+    fn encode(item: &T) -> Result<Vec<u8>, NetworkError> {
+        Ok(item.encode_to_vec())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<T, NetworkError> {
+        T::decode(bytes).map_err(|err| NetworkError::Generic(err.to_string()))
+    }
+
+    fn name() -> &'static str {
+        "protobuf"
+    }
+}
+
+type SendFuture = Pin<Box<dyn Future<Output = Result<(), NetworkError>> + Send>>;
+
+/// Sends messages of type `T` through a channel, encoded using `C`. Each message is sent once the
+/// prior message was accepted by the channel
+pub struct TypedSink<T, C> {
+    sender: PeerChannelSendHalf,
+    in_flight: Option<SendFuture>,
+    _pd: PhantomData<fn(T, C)>,
+}
+
+impl<T, C: Codec<T>> TypedSink<T, C> {
+    pub fn new(sender: PeerChannelSendHalf) -> Self {
+        Self {
+            sender,
+            in_flight: None,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Returns the underlying send half
+    pub fn get_ref(&self) -> &PeerChannelSendHalf {
+        &self.sender
+    }
+}
+
+impl<T, C: Codec<T>> Sink<T> for TypedSink<T, C> {
+    type Error = NetworkError;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+
+    fn start_send(mut self: Pin<&mut Self>, item: T) -> Result<(), Self::Error> {
+        let encoded = C::encode(&item)?;
+        let sender = self.sender.clone();
+        self.in_flight = Some(Box::pin(async move {
+            sender.send_message(encoded.into()).await
+        }));
+        Ok(())
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if let Some(in_flight) = self.in_flight.as_mut() {
+            let res = futures::ready!(in_flight.as_mut().poll(cx));
+            self.in_flight = None;
+            Poll::Ready(res)
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.poll_flush(cx)
+    }
+}
+
+/// Receives messages of type `T` from a channel, decoded using `C`. A message that fails to
+/// decode yields an error without ending the stream
+pub struct TypedStream<T, C> {
+    receiver: PeerChannelRecvHalf,
+    _pd: PhantomData<fn() -> (T, C)>,
+}
+
+impl<T, C: Codec<T>> TypedStream<T, C> {
+    pub fn new(receiver: PeerChannelRecvHalf) -> Self {
+        Self {
+            receiver,
+            _pd: PhantomData,
+        }
+    }
+
+    /// Returns the underlying receive half
+    pub fn into_inner(self) -> PeerChannelRecvHalf {
+        self.receiver
+    }
+}
+
+impl<T, C: Codec<T>> Stream for TypedStream<T, C> {
+    type Item = Result<T, NetworkError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver)
+            .poll_next(cx)
+            .map(|message| message.map(|message| C::decode(message.as_ref())))
+    }
+}
+
+/// Encodes `item` using `C` and sends it through the channel
+pub async fn send_encoded<T, C: Codec<T>>(
+    sender: &PeerChannelSendHalf,
+    item: &T,
+) -> Result<(), NetworkError> {
+    sender.send_message(C::encode(item)?.into()).await
+}
+
+/// Receives the next message from the channel, decoded using `C`. Returns `None` once the channel
+/// closes
+pub async fn recv_decoded<T, C: Codec<T>>(
+    receiver: &mut PeerChannelRecvHalf,
+) -> Option<Result<T, NetworkError>> {
+    let message = receiver.next().await?;
+    Some(C::decode(message.as_ref()))
+}
+
+/// Describes the schema of the messages exchanged through a negotiated typed channel. The version
+/// is incremented on each change of the schema, while the minimum compatible version is raised
+/// only once older versions can no longer exchange messages with this version
+pub trait ChannelSchema {
+    /// Identifies the message type. Both endpoints must agree on the tag
+    fn schema_tag() -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    fn schema_version() -> u32 {
+        0
+    }
+
+    /// The oldest version of the schema this version can exchange messages with
+    fn min_compatible_version() -> u32 {
+        Self::schema_version()
+    }
+}
+
+/// The schema an endpoint sends and receives through a negotiated typed channel
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct SchemaManifest {
+    pub tag: String,
+    pub version: u32,
+    pub min_compatible_version: u32,
+    pub codec: String,
+}
+
+impl SchemaManifest {
+    pub fn of<T: ChannelSchema, C: Codec<T>>() -> Self {
+        Self {
+            tag: T::schema_tag().to_string(),
+            version: T::schema_version(),
+            min_compatible_version: T::min_compatible_version(),
+            codec: C::name().to_string(),
+        }
+    }
+
+    /// Returns true if the endpoints may exchange messages, i.e., if both use the same type and
+    /// codec, and the version of each endpoint is accepted by the other
+    pub fn is_compatible_with(&self, remote: &SchemaManifest) -> bool {
+        self.tag == remote.tag
+            && self.codec == remote.codec
+            && remote.version >= self.min_compatible_version
+            && self.version >= remote.min_compatible_version
+    }
+}
+
+/// Prefixes the manifest sent once a negotiated typed channel opens
+const MANIFEST_MAGIC: &[u8] = b"\xCEschema1";
+/// The maximum time to wait for the manifest of the adjacent endpoint
+const MANIFEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+/// The reason a typed channel could not be negotiated
+#[derive(Debug)]
+pub enum SchemaError {
+    /// The endpoints use incompatible schemas or codecs
+    Mismatch {
+        local: SchemaManifest,
+        remote: SchemaManifest,
+    },
+    /// The adjacent endpoint did not send a manifest, implying it does not negotiate its typed
+    /// channels. `None` if no message arrived before the timeout
+    MissingManifest(Option<SecBuffer>),
+    Network(NetworkError),
+}
+
+impl std::fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaError::Mismatch { local, remote } => write!(
+                f,
+                "Incompatible channel schemas: local {} v{} ({}), remote {} v{} ({})",
+                local.tag, local.version, local.codec, remote.tag, remote.version, remote.codec
+            ),
+            SchemaError::MissingManifest(_) => {
+                write!(f, "The adjacent endpoint did not send a schema manifest")
+            }
+            SchemaError::Network(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+impl std::error::Error for SchemaError {}
+
+impl From<NetworkError> for SchemaError {
+    fn from(err: NetworkError) -> Self {
+        SchemaError::Network(err)
+    }
+}
+
+impl From<SchemaError> for NetworkError {
+    fn from(err: SchemaError) -> Self {
+        match err {
+            SchemaError::Network(err) => err,
+            err => NetworkError::Generic(err.to_string()),
+        }
+    }
+}
+
+/// Splits a channel into a typed sink and stream
+#[async_trait]
+pub trait TypedChannelExt {
+    fn typed<T, C: Codec<T>>(self) -> (TypedSink<T, C>, TypedStream<T, C>);
+
+    /// Exchanges a [`SchemaManifest`] with the adjacent endpoint before splitting the channel,
+    /// failing with [`SchemaError::Mismatch`] if the schemas are incompatible. Both endpoints
+    /// must negotiate the channel
+    async fn typed_negotiated<T: ChannelSchema, C: Codec<T>>(
+        self,
+    ) -> Result<(TypedSink<T, C>, TypedStream<T, C>), SchemaError>;
+}
+
+#[async_trait]
+impl TypedChannelExt for PeerChannel {
+    fn typed<T, C: Codec<T>>(self) -> (TypedSink<T, C>, TypedStream<T, C>) {
+        let (sender, receiver) = self.split();
+        (TypedSink::new(sender), TypedStream::new(receiver))
+    }
+
+    async fn typed_negotiated<T: ChannelSchema, C: Codec<T>>(
+        self,
+    ) -> Result<(TypedSink<T, C>, TypedStream<T, C>), SchemaError> {
+        let local = SchemaManifest::of::<T, C>();
+        let (sender, mut receiver) = self.split();
+
+        let mut manifest = MANIFEST_MAGIC.to_vec();
+        manifest.extend(BincodeCodec::encode(&local)?);
+        sender.send_message(manifest.into()).await?;
+
+        let message = tokio::time::timeout(MANIFEST_TIMEOUT, receiver.next())
+            .await
+            .map_err(|_| SchemaError::MissingManifest(None))?
+            .ok_or(NetworkError::InternalError("The channel closed"))?;
+        let remote = match message.as_ref().strip_prefix(MANIFEST_MAGIC) {
+            Some(encoded) => BincodeCodec::decode(encoded)?,
+            None => return Err(SchemaError::MissingManifest(Some(message))),
+        };
+
+        if !local.is_compatible_with(&remote) {
+            return Err(SchemaError::Mismatch { local, remote });
+        }
+
+        Ok((TypedSink::new(sender), TypedStream::new(receiver)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::{BincodeCodec, ChannelSchema, Codec, JsonCodec, SchemaManifest};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, Eq, PartialEq)]
+    struct Chat {
+        from: String,
+        seq: u64,
+    }
+
+    #[test]
+    fn test_codecs_round_trip() {
+        let chat = Chat {
+            from: "alice".to_string(),
+            seq: 7,
+        };
+
+        let json = JsonCodec::encode(&chat).unwrap();
+        assert_eq!(json, br#"{"from":"alice","seq":7}"#.to_vec());
+        assert_eq!(<JsonCodec as Codec<Chat>>::decode(&json).unwrap(), chat);
+
+        let bincode = BincodeCodec::encode(&chat).unwrap();
+        assert_eq!(
+            <BincodeCodec as Codec<Chat>>::decode(&bincode).unwrap(),
+            chat
+        );
+
+        assert!(<JsonCodec as Codec<Chat>>::decode(&bincode).is_err());
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct ChatV1;
+    impl ChannelSchema for ChatV1 {
+        fn schema_tag() -> &'static str {
+            "chat"
+        }
+
+        fn schema_version() -> u32 {
+            1
+        }
+    }
+
+    // adds an optional field, remaining compatible with v1
+    #[derive(Serialize, Deserialize)]
+    struct ChatV2;
+    impl ChannelSchema for ChatV2 {
+        fn schema_tag() -> &'static str {
+            "chat"
+        }
+
+        fn schema_version() -> u32 {
+            2
+        }
+
+        fn min_compatible_version() -> u32 {
+            1
+        }
+    }
+
+    // drops v1 support
+    #[derive(Serialize, Deserialize)]
+    struct ChatV3;
+    impl ChannelSchema for ChatV3 {
+        fn schema_tag() -> &'static str {
+            "chat"
+        }
+
+        fn schema_version() -> u32 {
+            3
+        }
+
+        fn min_compatible_version() -> u32 {
+            2
+        }
+    }
+
+    #[test]
+    fn test_schema_compatibility() {
+        let v1 = SchemaManifest::of::<ChatV1, JsonCodec>();
+        let v2 = SchemaManifest::of::<ChatV2, JsonCodec>();
+        let v3 = SchemaManifest::of::<ChatV3, JsonCodec>();
+        assert_eq!(v1.codec, "json");
+
+        assert!(v1.is_compatible_with(&v2) && v2.is_compatible_with(&v1));
+        assert!(v2.is_compatible_with(&v3) && v3.is_compatible_with(&v2));
+        assert!(!v1.is_compatible_with(&v3) && !v3.is_compatible_with(&v1));
+
+        let v2_bincode = SchemaManifest::of::<ChatV2, BincodeCodec>();
+        assert!(!v2.is_compatible_with(&v2_bincode));
+    }
+}
@@ -0,0 +1,181 @@
+//! A structured envelope for channel messages
+//!
+//! Channels carry raw bytes. Applications that exchange more than one kind of message commonly
+//! need to label each message with its content type, encoding, or the request it answers. Rather
+//! than each application inventing its own framing, a [`MessageEnvelope`] carries this metadata
+//! alongside the body in a format understood by every application built on the SDK
+//!
+//! Envelopes are optional: [`MessageEnvelope::from_message`] accepts raw messages too, returning
+//! an envelope holding only the body, such that a receiver may handle senders that use envelopes
+//! and senders that do not alike
+//!
+//! Envelopes are encoded using [`EnvelopeCodec`], such that a channel may also be split into a
+//! sink and stream of envelopes through [`TypedChannelExt::typed`](crate::codec::TypedChannelExt::typed)
+//!
+//! ```no_run
+//! use citadel_sdk::envelope::{recv_envelope, send_envelope, MessageEnvelope};
+//! use citadel_sdk::prelude::*;
+//! # async fn run(tx: PeerChannelSendHalf, mut rx: PeerChannelRecvHalf) -> Result<(), NetworkError> {
+//! let request = MessageEnvelope::new(br#"{"query":"status"}"#.to_vec())
+//!     .with_content_type("application/json")
+//!     .with_correlation_id("request-1");
+//! send_envelope(&tx, &request).await?;
+//!
+//! if let Some(response) = recv_envelope(&mut rx).await {
+//!     let response = response?;
+//!     assert_eq!(response.correlation_id.as_deref(), Some("request-1"));
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::codec::{recv_decoded, send_encoded, BincodeCodec, Codec};
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Prefixes every encoded envelope, distinguishing envelopes from raw messages
+const ENVELOPE_MAGIC: &[u8] = b"\xCEenv1";
+
+/// Encodes a [`MessageEnvelope`] as a magic prefix followed by the bincode-encoded envelope.
+/// Decoding a message without the prefix yields an envelope holding only the body
+pub struct EnvelopeCodec;
+
+impl Codec<MessageEnvelope> for EnvelopeCodec {
+    fn encode(envelope: &MessageEnvelope) -> Result<Vec<u8>, NetworkError> {
+        let mut message = ENVELOPE_MAGIC.to_vec();
+        message.extend(BincodeCodec::encode(envelope)?);
+        Ok(message)
+    }
+
+    fn decode(message: &[u8]) -> Result<MessageEnvelope, NetworkError> {
+        match message.strip_prefix(ENVELOPE_MAGIC) {
+            Some(encoded) => BincodeCodec::decode(encoded),
+            None => Ok(MessageEnvelope::new(message)),
+        }
+    }
+
+    fn name() -> &'static str {
+        "envelope"
+    }
+}
+
+/// A channel message alongside metadata describing it
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct MessageEnvelope {
+    /// The media type of the body, e.g., `application/json`
+    pub content_type: Option<String>,
+    /// The encoding applied to the body, e.g., `gzip`
+    pub encoding: Option<String>,
+    /// Associates a response with the request it answers
+    pub correlation_id: Option<String>,
+    /// Application-defined headers
+    pub headers: BTreeMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl MessageEnvelope {
+    /// Creates an envelope without metadata
+    pub fn new<T: Into<Vec<u8>>>(body: T) -> Self {
+        Self {
+            body: body.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_content_type<T: Into<String>>(mut self, content_type: T) -> Self {
+        self.content_type = Some(content_type.into());
+        self
+    }
+
+    pub fn with_encoding<T: Into<String>>(mut self, encoding: T) -> Self {
+        self.encoding = Some(encoding.into());
+        self
+    }
+
+    pub fn with_correlation_id<T: Into<String>>(mut self, correlation_id: T) -> Self {
+        self.correlation_id = Some(correlation_id.into());
+        self
+    }
+
+    pub fn with_header<K: Into<String>, V: Into<String>>(mut self, key: K, value: V) -> Self {
+        let _ = self.headers.insert(key.into(), value.into());
+        self
+    }
+
+    /// Returns the value of the header `key`
+    pub fn header(&self, key: &str) -> Option<&str> {
+        self.headers.get(key).map(|value| value.as_str())
+    }
+
+    /// Creates an envelope answering this envelope, carrying over its correlation ID
+    pub fn reply<T: Into<Vec<u8>>>(&self, body: T) -> Self {
+        Self {
+            correlation_id: self.correlation_id.clone(),
+            ..Self::new(body)
+        }
+    }
+
+    /// Encodes the envelope into a channel message
+    pub fn encode(&self) -> Result<SecBuffer, NetworkError> {
+        EnvelopeCodec::encode(self).map(Into::into)
+    }
+
+    /// Decodes a channel message. Messages sent without an envelope are returned as an envelope
+    /// holding only the body
+    pub fn from_message(message: &[u8]) -> Result<Self, NetworkError> {
+        EnvelopeCodec::decode(message)
+    }
+
+    /// Returns true if the message was sent inside an envelope
+    pub fn is_envelope(message: &[u8]) -> bool {
+        message.starts_with(ENVELOPE_MAGIC)
+    }
+}
+
+/// Sends an envelope through the channel
+pub async fn send_envelope(
+    sender: &PeerChannelSendHalf,
+    envelope: &MessageEnvelope,
+) -> Result<(), NetworkError> {
+    send_encoded::<_, EnvelopeCodec>(sender, envelope).await
+}
+
+/// Receives the next message from the channel as an envelope. Returns `None` once the channel
+/// closes
+pub async fn recv_envelope(
+    receiver: &mut PeerChannelRecvHalf,
+) -> Option<Result<MessageEnvelope, NetworkError>> {
+    recv_decoded::<_, EnvelopeCodec>(receiver).await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::codec::Codec;
+    use crate::envelope::{EnvelopeCodec, MessageEnvelope};
+
+    #[test]
+    fn test_envelope_round_trip() {
+        let envelope = MessageEnvelope::new(b"{}".to_vec())
+            .with_content_type("application/json")
+            .with_encoding("identity")
+            .with_correlation_id("42")
+            .with_header("x-tenant", "acme");
+        let message = envelope.encode().unwrap();
+        assert!(MessageEnvelope::is_envelope(message.as_ref()));
+
+        let decoded = MessageEnvelope::from_message(message.as_ref()).unwrap();
+        assert_eq!(decoded, envelope);
+        assert_eq!(decoded.header("x-tenant"), Some("acme"));
+        // the codec used by typed channels of envelopes agrees with the envelope encoding
+        assert_eq!(EnvelopeCodec::encode(&envelope).unwrap(), message.as_ref());
+
+        let reply = decoded.reply(b"ok".to_vec());
+        assert_eq!(reply.correlation_id.as_deref(), Some("42"));
+        assert!(reply.content_type.is_none());
+
+        // raw messages are accepted as bodies
+        assert!(!MessageEnvelope::is_envelope(b"raw"));
+        let raw = MessageEnvelope::from_message(b"raw").unwrap();
+        assert_eq!(raw, MessageEnvelope::new(b"raw".to_vec()));
+    }
+}
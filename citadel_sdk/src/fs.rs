@@ -1,7 +1,17 @@
-use crate::prelude::{ObjectSource, ProtocolRemoteTargetExt, SecurityLevel, TargetLockedRemote};
+use crate::prelude::{
+    CryptError, FixedSizedSource, ObjectSource, ObjectTransferHandler, ObjectTransferStatus,
+    ProtocolRemoteTargetExt, SecurityLevel, TargetLockedRemote, VirtualDirEntry,
+};
 
 use citadel_proto::prelude::NetworkError;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use std::io::Read;
 use std::path::PathBuf;
+use tokio::io::AsyncReadExt;
+
+/// The maximum size of the chunks yielded by [`read_stream`]
+const READ_STREAM_CHUNK_SIZE: usize = 64 * 1024;
 
 /// Writes a file or BytesSource to the Remote Encrypted Virtual Filesystem
 pub async fn write<T: ObjectSource, R: Into<PathBuf> + Send>(
@@ -24,6 +34,28 @@ pub async fn write_with_security_level<T: ObjectSource, R: Into<PathBuf> + Send>
         .await
 }
 
+/// Writes exactly `length` bytes read from `reader` to the Remote Encrypted Virtual Filesystem.
+/// The bytes are read as they are sent, hence need not be held in memory nor written to disk
+/// beforehand. Like files, the reader is read from the runtime, so it should not block for long
+pub async fn write_stream<T: Read + Send + 'static, R: Into<PathBuf> + Send>(
+    remote: &mut impl TargetLockedRemote,
+    reader: T,
+    length: u64,
+    virtual_path: R,
+) -> Result<(), NetworkError> {
+    let virtual_path = virtual_path.into();
+    let name = virtual_path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .ok_or_else(|| NetworkError::msg(format!("{virtual_path:?} does not name a file")))?;
+    write(
+        remote,
+        ReaderSource::new(name, reader, length),
+        virtual_path,
+    )
+    .await
+}
+
 /// Reads a file from the Remote Encrypted Virtual Filesystem
 pub async fn read<R: Into<PathBuf> + Send>(
     remote: &mut impl TargetLockedRemote,
@@ -43,6 +75,55 @@ pub async fn read_with_security_level<R: Into<PathBuf> + Send>(
         .await
 }
 
+/// Reads a file from the Remote Encrypted Virtual Filesystem, yielding its contents as they arrive
+/// instead of once the transfer completes
+pub async fn read_stream<R: Into<PathBuf> + Send>(
+    remote: &mut impl TargetLockedRemote,
+    virtual_path: R,
+) -> Result<BoxStream<'static, Result<Vec<u8>, NetworkError>>, NetworkError> {
+    let handle = remote
+        .remote_encrypted_virtual_filesystem_pull_handle(
+            virtual_path.into(),
+            None,
+            Default::default(),
+            false,
+        )
+        .await?;
+
+    let state = PulledObjectReader {
+        handle,
+        file: None,
+        complete: false,
+    };
+
+    let stream = futures::stream::unfold(Some(state), |state| async move {
+        let mut state = state?;
+        match state.next_chunk().await {
+            Ok(Some(chunk)) => Some((Ok(chunk), Some(state))),
+            Ok(None) => None,
+            Err(err) => Some((Err(err), None)),
+        }
+    });
+
+    Ok(stream.boxed())
+}
+
+/// Reads a file another user shared from their Remote Encrypted Virtual Filesystem. The file is
+/// received as stored, encrypted under the owner's local key
+pub async fn read_shared<R: Into<PathBuf> + Send>(
+    remote: &mut impl TargetLockedRemote,
+    owner_cid: u64,
+    virtual_path: R,
+) -> Result<PathBuf, NetworkError> {
+    remote
+        .remote_encrypted_virtual_filesystem_pull_shared(
+            owner_cid,
+            virtual_path,
+            Default::default(),
+        )
+        .await
+}
+
 /// Takes a file from the Remote Encrypted Virtual Filesystem
 pub async fn take<R: Into<PathBuf> + Send>(
     remote: &mut impl TargetLockedRemote,
@@ -74,6 +155,179 @@ pub async fn delete<R: Into<PathBuf> + Send>(
         .await
 }
 
+/// Deletes a file another user shared from their Remote Encrypted Virtual Filesystem
+pub async fn delete_shared<R: Into<PathBuf> + Send>(
+    remote: &mut impl TargetLockedRemote,
+    owner_cid: u64,
+    virtual_path: R,
+) -> Result<(), NetworkError> {
+    remote
+        .remote_encrypted_virtual_filesystem_delete_shared(owner_cid, virtual_path)
+        .await
+}
+
+/// Creates a directory, and any missing parents, inside the Remote Encrypted Virtual Filesystem
+pub async fn create_dir<R: Into<PathBuf> + Send>(
+    remote: &mut impl TargetLockedRemote,
+    virtual_dir: R,
+) -> Result<(), NetworkError> {
+    remote
+        .remote_encrypted_virtual_filesystem_create_dir(virtual_dir)
+        .await
+}
+
+/// Lists the entries of a directory inside the Remote Encrypted Virtual Filesystem
+pub async fn list<R: Into<PathBuf> + Send>(
+    remote: &mut impl TargetLockedRemote,
+    virtual_dir: R,
+) -> Result<Vec<VirtualDirEntry>, NetworkError> {
+    remote
+        .remote_encrypted_virtual_filesystem_list(virtual_dir)
+        .await
+}
+
+/// Lists the entries of a directory another user shared from their Remote Encrypted Virtual Filesystem
+pub async fn list_shared<R: Into<PathBuf> + Send>(
+    remote: &mut impl TargetLockedRemote,
+    owner_cid: u64,
+    virtual_dir: R,
+) -> Result<Vec<VirtualDirEntry>, NetworkError> {
+    remote
+        .remote_encrypted_virtual_filesystem_list_shared(owner_cid, virtual_dir)
+        .await
+}
+
+/// Grants a peer access to a path inside the Remote Encrypted Virtual Filesystem
+pub async fn grant_access<R: Into<PathBuf> + Send>(
+    remote: &mut impl TargetLockedRemote,
+    virtual_dir: R,
+    peer_cid: u64,
+) -> Result<(), NetworkError> {
+    remote
+        .remote_encrypted_virtual_filesystem_grant_access(virtual_dir, peer_cid)
+        .await
+}
+
+/// Revokes a peer's access to a path inside the Remote Encrypted Virtual Filesystem
+pub async fn revoke_access<R: Into<PathBuf> + Send>(
+    remote: &mut impl TargetLockedRemote,
+    virtual_dir: R,
+    peer_cid: u64,
+) -> Result<(), NetworkError> {
+    remote
+        .remote_encrypted_virtual_filesystem_revoke_access(virtual_dir, peer_cid)
+        .await
+}
+
+/// An [`ObjectSource`] reading exactly `length` bytes from a reader, allowing objects to be sent
+/// without first being written to disk
+pub struct ReaderSource {
+    name: String,
+    // locked, since sources are shared across threads, unlike their streams
+    stream: citadel_io::Mutex<Option<Box<dyn FixedSizedSource>>>,
+}
+
+impl ReaderSource {
+    pub fn new<T: Read + Send + 'static>(name: String, reader: T, length: u64) -> Self {
+        let stream = BoundedReader {
+            inner: reader.take(length),
+            length,
+        };
+
+        Self {
+            name,
+            stream: citadel_io::Mutex::new(Some(Box::new(stream))),
+        }
+    }
+}
+
+impl ObjectSource for ReaderSource {
+    fn try_get_stream(&mut self) -> Result<Box<dyn FixedSizedSource>, CryptError> {
+        self.stream
+            .lock()
+            .take()
+            .ok_or_else(|| CryptError::Encrypt("Source has already been exhausted".into()))
+    }
+
+    fn get_source_name(&self) -> Result<String, CryptError> {
+        Ok(self.name.clone())
+    }
+}
+
+struct BoundedReader<T> {
+    inner: std::io::Take<T>,
+    length: u64,
+}
+
+impl<T: Read> Read for BoundedReader<T> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.inner.read(buf)
+    }
+}
+
+impl<T: Read + Send + 'static> FixedSizedSource for BoundedReader<T> {
+    fn length(&self) -> std::io::Result<u64> {
+        Ok(self.length)
+    }
+}
+
+/// Reads a pulled file while it is being written to disk
+struct PulledObjectReader {
+    handle: ObjectTransferHandler,
+    file: Option<tokio::fs::File>,
+    complete: bool,
+}
+
+impl PulledObjectReader {
+    async fn next_chunk(&mut self) -> Result<Option<Vec<u8>>, NetworkError> {
+        loop {
+            if let Some(file) = self.file.as_mut() {
+                let mut chunk = vec![0u8; READ_STREAM_CHUNK_SIZE];
+                let read = file
+                    .read(&mut chunk)
+                    .await
+                    .map_err(|err| NetworkError::Generic(err.to_string()))?;
+                if read != 0 {
+                    chunk.truncate(read);
+                    return Ok(Some(chunk));
+                }
+            }
+
+            if self.complete {
+                return match self.file {
+                    Some(_) => Ok(None),
+                    None => Err(NetworkError::InternalError("Local path never loaded")),
+                };
+            }
+
+            // the file is caught up with; wait for more of it to be received
+            match self.handle.next().await {
+                Some(ObjectTransferStatus::ReceptionBeginning(path, _)) => {
+                    let file = tokio::fs::File::open(path)
+                        .await
+                        .map_err(|err| NetworkError::Generic(err.to_string()))?;
+                    self.file = Some(file);
+                }
+
+                Some(
+                    ObjectTransferStatus::TransferComplete
+                    | ObjectTransferStatus::ReceptionComplete,
+                ) => self.complete = true,
+
+                Some(ObjectTransferStatus::Cancelled) => {
+                    return Err(NetworkError::msg("File transfer was cancelled"))
+                }
+
+                Some(ObjectTransferStatus::Fail(err)) => return Err(NetworkError::Generic(err)),
+
+                Some(_) => {}
+
+                None => return Err(NetworkError::InternalError("File transfer stream died")),
+            }
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::builder::node_builder::{NodeBuilder, NodeFuture};
@@ -81,6 +335,7 @@ mod tests {
     use crate::prefabs::server::accept_file_transfer_kernel::AcceptFileTransferKernel;
 
     use crate::prelude::*;
+    use citadel_proto::auth::AuthenticationRequest;
     use rstest::rstest;
     use std::net::SocketAddr;
     use std::path::PathBuf;
@@ -318,4 +573,196 @@ mod tests {
 
         assert!(client_success.load(Ordering::Relaxed));
     }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test]
+    async fn test_c2s_revfs_directory_listing() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let (server, server_addr) = server_info();
+        let uuid = Uuid::new_v4();
+
+        let source_dir = PathBuf::from("../resources/TheBridge.pdf");
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            uuid,
+            server_addr,
+            |_channel, mut remote| async move {
+                assert!(crate::fs::list(&mut remote, "/").await?.is_empty());
+                crate::fs::write(
+                    &mut remote,
+                    source_dir.clone(),
+                    "/home/john.doe/TheBridge.pdf",
+                )
+                .await?;
+                crate::fs::create_dir(&mut remote, "/home/john.doe/docs").await?;
+
+                let entries = crate::fs::list(&mut remote, "/home/john.doe").await?;
+                assert_eq!(entries.len(), 2);
+                assert_eq!(entries[0].name, "TheBridge.pdf");
+                assert!(!entries[0].is_dir);
+                let metadata = entries[0].metadata.as_ref().unwrap();
+                assert_eq!(
+                    metadata.plaintext_length as u64,
+                    tokio::fs::metadata(&source_dir).await.unwrap().len()
+                );
+                assert_eq!(entries[1].name, "docs");
+                assert!(entries[1].is_dir);
+                assert!(crate::fs::list(&mut remote, "/home/john.doe/docs")
+                    .await?
+                    .is_empty());
+
+                // paths may not escape the RE-VFS
+                assert!(crate::fs::list(&mut remote, "/home/../..").await.is_err());
+                // access may only be granted to registered peers
+                assert!(crate::fs::grant_access(&mut remote, "/home/john.doe", 1234)
+                    .await
+                    .is_err());
+
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        let result = tokio::select! {
+            res0 = client => res0.map(|_| ()),
+            res1 = server => res1.map(|_| ())
+        };
+
+        result.unwrap();
+
+        assert!(client_success.load(Ordering::Relaxed));
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test]
+    async fn test_c2s_revfs_streaming() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let (server, server_addr) = server_info();
+        let uuid = Uuid::new_v4();
+
+        let source_dir = PathBuf::from("../resources/TheBridge.pdf");
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            uuid,
+            server_addr,
+            |_channel, mut remote| async move {
+                use futures::StreamExt;
+                let virtual_path = PathBuf::from("/home/john.doe/streamed.pdf");
+                let original_bytes = tokio::fs::read(&source_dir).await.unwrap();
+                let reader = std::fs::File::open(&source_dir).unwrap();
+                crate::fs::write_stream(
+                    &mut remote,
+                    reader,
+                    original_bytes.len() as u64,
+                    &virtual_path,
+                )
+                .await?;
+
+                let mut stream = crate::fs::read_stream(&mut remote, &virtual_path).await?;
+                let mut streamed_bytes = Vec::new();
+                while let Some(chunk) = stream.next().await {
+                    streamed_bytes.extend_from_slice(&chunk?);
+                }
+
+                assert_eq!(original_bytes, streamed_bytes);
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        let result = tokio::select! {
+            res0 = client => res0.map(|_| ()),
+            res1 = server => res1.map(|_| ())
+        };
+
+        result.unwrap();
+
+        assert!(client_success.load(Ordering::Relaxed));
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test]
+    async fn test_c2s_revfs_access_denied() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let (server, server_addr) = server_info();
+        let uuid = Uuid::new_v4();
+
+        let source_dir = PathBuf::from("../resources/TheBridge.pdf");
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            uuid,
+            server_addr,
+            |_channel, mut remote| async move {
+                let owner_cid = remote.user().get_implicated_cid();
+                let virtual_path = PathBuf::from("/home/john.doe/TheBridge.pdf");
+                crate::fs::write(&mut remote, source_dir.clone(), &virtual_path).await?;
+
+                // another account, which was not granted access, connects from the same node
+                let _ = remote
+                    .register_with_defaults(server_addr, "RE-VFS", "revfs_user", "password")
+                    .await?;
+                let other = remote
+                    .connect_with_defaults(AuthenticationRequest::credentialed(
+                        "revfs_user",
+                        "password",
+                    ))
+                    .await?;
+
+                let mut other_remote = SymmetricIdentifierHandleRef {
+                    user: VirtualTargetType::LocalGroupServer(other.cid),
+                    remote: remote.remote(),
+                    target_username: None,
+                };
+
+                assert!(
+                    crate::fs::read_shared(&mut other_remote, owner_cid, &virtual_path)
+                        .await
+                        .is_err()
+                );
+                assert!(
+                    crate::fs::delete_shared(&mut other_remote, owner_cid, &virtual_path)
+                        .await
+                        .is_err()
+                );
+                assert!(
+                    crate::fs::list_shared(&mut other_remote, owner_cid, "/home/john.doe")
+                        .await
+                        .is_err()
+                );
+
+                // the denied delete left the owner's file intact
+                let save_dir = crate::fs::read(&mut remote, &virtual_path).await?;
+                let original_bytes = tokio::fs::read(&source_dir).await.unwrap();
+                let revfs_pulled_bytes = tokio::fs::read(&save_dir).await.unwrap();
+                assert_eq!(original_bytes, revfs_pulled_bytes);
+
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        let result = tokio::select! {
+            res0 = client => res0.map(|_| ()),
+            res1 = server => res1.map(|_| ())
+        };
+
+        result.unwrap();
+
+        assert!(client_success.load(Ordering::Relaxed));
+    }
 }
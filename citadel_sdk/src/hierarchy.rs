@@ -0,0 +1,238 @@
+//! Hierarchical deployments, wherein child servers (e.g., edge relays) trust a parent identity server
+//!
+//! Clients register once with the parent. Before connecting to a child, a client obtains a
+//! [`UserAttestation`] from the parent, signed by the parent's [`ServerIdentity`], and presents it
+//! in place of a password via [`UserAttestation::into_credentials`]. Each child validates the
+//! attestation through a [`ParentAttestationValidator`] configured with the certificate of the
+//! parent, thus children never store the passwords of the users of the parent.
+//!
+//! Children are registered with the parent through a [`ChildEndorsement`]: the parent signs the
+//! certificate of the child, and clients that trust the parent pin the endorsed certificate via
+//! [`ChildEndorsement::trust`] ahead of the first connection to the child
+//!
+//! ```no_run
+//! use citadel_sdk::hierarchy::ParentAttestationValidator;
+//! use citadel_sdk::prelude::*;
+//! # fn run(parent_cert_der: Vec<u8>) {
+//! // on each child
+//! let policies = GroupPolicies::new(AccountPolicy::default());
+//! let _ = NodeBuilder::default().with_credential_validator(
+//!     ParentAttestationValidator::new(parent_cert_der),
+//!     policies,
+//! );
+//! # }
+//! ```
+use citadel_proto::prelude::{
+    async_trait, verify_signature, AccountError, CredentialValidator, ProposedCredentials,
+    SecBuffer, ServerIdentity, ServerKeyPinning, ValidatedUser,
+};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const ATTESTATION_DOMAIN: &[u8] = b"citadel-parent-user-attestation";
+const ENDORSEMENT_DOMAIN: &[u8] = b"citadel-parent-child-endorsement";
+
+/// The claims of the parent about one of its users
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct AttestedUser {
+    /// The username of the user at the parent
+    pub username: String,
+    /// The full name of the user at the parent
+    pub full_name: String,
+    /// The groups of the user, which children map onto their [`GroupPolicies`]
+    ///
+    /// [`GroupPolicies`]: citadel_proto::prelude::GroupPolicies
+    pub groups: Vec<String>,
+    /// The time, in seconds since the unix epoch, after which the attestation is expired
+    pub expires_at: u64,
+}
+
+/// A user vouched for by the parent, signed by the key of the parent
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct UserAttestation {
+    pub user: AttestedUser,
+    signature: Vec<u8>,
+}
+
+impl UserAttestation {
+    /// Issues an attestation for `user`, valid for `valid_for`. Called by the parent once the
+    /// user authenticated with it
+    pub fn issue<T: Into<String>, R: Into<String>>(
+        parent: &ServerIdentity,
+        username: T,
+        full_name: R,
+        groups: Vec<String>,
+        valid_for: Duration,
+    ) -> Result<Self, anyhow::Error> {
+        let user = AttestedUser {
+            username: username.into(),
+            full_name: full_name.into(),
+            groups,
+            expires_at: (unix_time() + valid_for).as_secs(),
+        };
+        let signature = parent.sign(ATTESTATION_DOMAIN, &serde_json::to_vec(&user)?)?;
+        Ok(Self { user, signature })
+    }
+
+    /// Verifies that the key of `parent_cert_der` signed this attestation, and that it has not
+    /// expired
+    pub fn verify(&self, parent_cert_der: &[u8]) -> Result<(), AccountError> {
+        let message =
+            serde_json::to_vec(&self.user).map_err(|err| AccountError::Generic(err.to_string()))?;
+        if !verify_signature(
+            parent_cert_der,
+            ATTESTATION_DOMAIN,
+            &message,
+            &self.signature,
+        )
+        .map_err(|err| AccountError::Generic(err.to_string()))?
+        {
+            return Err(AccountError::Generic(
+                "The attestation was not signed by the parent server".to_string(),
+            ));
+        }
+
+        if unix_time().as_secs() > self.user.expires_at {
+            return Err(AccountError::Generic(
+                "The attestation has expired".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Returns the credentials presenting this attestation to a child. The same credentials are
+    /// used for registering and connecting
+    pub fn into_credentials(self) -> Result<ProposedCredentials, anyhow::Error> {
+        let encoded = serde_json::to_vec(&self)?;
+        Ok(ProposedCredentials::new_delegated(
+            self.user.full_name,
+            self.user.username,
+            SecBuffer::from(encoded),
+        ))
+    }
+}
+
+/// Validates the attestations of clients on a child, against the certificate of the parent
+pub struct ParentAttestationValidator {
+    parent_cert_der: Vec<u8>,
+}
+
+impl ParentAttestationValidator {
+    pub fn new(parent_cert_der: Vec<u8>) -> Self {
+        Self { parent_cert_der }
+    }
+}
+
+#[async_trait]
+impl CredentialValidator for ParentAttestationValidator {
+    async fn validate(
+        &self,
+        username: &str,
+        password: &SecBuffer,
+    ) -> Result<ValidatedUser, AccountError> {
+        let attestation: UserAttestation = serde_json::from_slice(password.as_ref())
+            .map_err(|_| AccountError::Generic("Malformed attestation".to_string()))?;
+        attestation.verify(&self.parent_cert_der)?;
+
+        if attestation.user.username != username {
+            return Err(AccountError::InvalidUsername);
+        }
+
+        Ok(ValidatedUser {
+            full_name: Some(attestation.user.full_name),
+            groups: attestation.user.groups,
+        })
+    }
+}
+
+/// The certificate of a child server, signed by the key of the parent
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct ChildEndorsement {
+    pub child_cert_der: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl ChildEndorsement {
+    /// Registers `child` with the parent, returning the endorsement the parent publishes to its
+    /// clients
+    pub fn issue(parent: &ServerIdentity, child: &ServerIdentity) -> Result<Self, anyhow::Error> {
+        Ok(Self {
+            child_cert_der: child.cert_der.clone(),
+            signature: parent.sign(ENDORSEMENT_DOMAIN, &child.cert_der)?,
+        })
+    }
+
+    /// Verifies that the key of `parent_cert_der` endorsed the child
+    pub fn verify(&self, parent_cert_der: &[u8]) -> Result<(), anyhow::Error> {
+        if verify_signature(
+            parent_cert_der,
+            ENDORSEMENT_DOMAIN,
+            &self.child_cert_der,
+            &self.signature,
+        )? {
+            Ok(())
+        } else {
+            Err(anyhow::Error::msg(
+                "The child server was not endorsed by the parent server",
+            ))
+        }
+    }
+
+    /// Verifies the endorsement, then pins the certificate of the child listening at `child`
+    pub fn trust(
+        &self,
+        parent_cert_der: &[u8],
+        pinning: &ServerKeyPinning,
+        child: SocketAddr,
+    ) -> Result<(), anyhow::Error> {
+        self.verify(parent_cert_der)?;
+        pinning.pin(child, self.child_cert_der.clone())
+    }
+}
+
+fn unix_time() -> Duration {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hierarchy::{ChildEndorsement, ParentAttestationValidator, UserAttestation};
+    use citadel_proto::prelude::{CredentialValidator, ServerIdentity};
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_parent_attestation() {
+        let parent = ServerIdentity::generate().unwrap();
+        let other = ServerIdentity::generate().unwrap();
+        let validator = ParentAttestationValidator::new(parent.cert_der.clone());
+
+        let attestation = UserAttestation::issue(
+            &parent,
+            "alice",
+            "Alice",
+            vec!["staff".to_string()],
+            Duration::from_secs(60),
+        )
+        .unwrap();
+        let password = serde_json::to_vec(&attestation).unwrap().into();
+        let user = validator.validate("alice", &password).await.unwrap();
+        assert_eq!(user.groups, vec!["staff".to_string()]);
+        assert!(validator.validate("bob", &password).await.is_err());
+
+        // attestations issued by another server are rejected
+        let forged =
+            UserAttestation::issue(&other, "alice", "Alice", vec![], Duration::from_secs(60))
+                .unwrap();
+        let password = serde_json::to_vec(&forged).unwrap().into();
+        assert!(validator.validate("alice", &password).await.is_err());
+
+        let child = ServerIdentity::generate().unwrap();
+        let endorsement = ChildEndorsement::issue(&parent, &child).unwrap();
+        endorsement.verify(&parent.cert_der).unwrap();
+        assert!(endorsement.verify(&other.cert_der).is_err());
+    }
+}
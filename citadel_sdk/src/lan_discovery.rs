@@ -0,0 +1,191 @@
+//! Finds Citadel nodes on the local network through mDNS/DNS-SD
+//!
+//! A [`LanDiscovery`] browses the local network for nodes, yielding a [`LanPeerEvent`] each time a
+//! node appears, changes its advertisement, or leaves. Optionally, it advertises the local node as
+//! well, allowing two nodes on the same network to find each other without a central server. Once
+//! found, a node is connected to through the address it was discovered at, e.g., by a client
+//! connecting to a node that advertised the port its server listens on
+//!
+//! ```no_run
+//! use citadel_sdk::lan_discovery::{LanAdvertisement, LanDiscovery, LanPeerEvent};
+//! use futures::StreamExt;
+//! # async fn run() -> Result<(), citadel_sdk::prelude::NetworkError> {
+//! let mut discovery = LanDiscovery::advertise(LanAdvertisement {
+//!     instance: "alice-laptop".to_string(),
+//!     port: 25021,
+//!     properties: Default::default(),
+//! })?;
+//!
+//! while let Some(event) = discovery.next().await {
+//!     if let LanPeerEvent::Discovered(node) = event {
+//!         println!("Found {} at {}", node.instance, node.addr);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use crate::prelude::NetworkError;
+use citadel_io::UdpSocket;
+use citadel_proto::re_imports::lan_discovery::{
+    bind_mdns_socket, encode_query, encode_response, mdns_group, parse_message, RECORD_TTL,
+};
+pub use citadel_proto::re_imports::lan_discovery::{DiscoveredNode, LanAdvertisement};
+use futures::Stream;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+
+/// How often the network is queried for nodes. Nodes that neither answer a query nor announce
+/// themselves within the TTL of their records are considered departed
+const QUERY_INTERVAL: Duration = Duration::from_secs(10);
+const MAX_MDNS_PACKET_LEN: usize = 9000;
+
+/// A change to the set of nodes on the local network
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum LanPeerEvent {
+    /// A node was discovered, or changed its advertisement
+    Discovered(DiscoveredNode),
+    /// The node with the given instance name left the network
+    Departed(String),
+}
+
+/// A stream of [`LanPeerEvent`]s. Dropping the stream stops browsing, and, if advertising,
+/// announces the departure of the local node
+pub struct LanDiscovery {
+    events: UnboundedReceiver<LanPeerEvent>,
+    _stop: oneshot::Sender<()>,
+}
+
+impl LanDiscovery {
+    /// Browses the local network for nodes without advertising the local node
+    pub fn browse() -> Result<Self, NetworkError> {
+        Self::start(None)
+    }
+
+    /// Advertises the local node while browsing the local network for other nodes
+    pub fn advertise(advertisement: LanAdvertisement) -> Result<Self, NetworkError> {
+        Self::start(Some(advertisement))
+    }
+
+    fn start(advertisement: Option<LanAdvertisement>) -> Result<Self, NetworkError> {
+        let socket = bind_mdns_socket().map_err(|err| NetworkError::Generic(err.to_string()))?;
+        let (tx, events) = tokio::sync::mpsc::unbounded_channel();
+        let (stop_tx, stop_rx) = oneshot::channel();
+        std::mem::drop(citadel_io::spawn(run_discovery(
+            socket,
+            advertisement,
+            tx,
+            stop_rx,
+        )));
+
+        Ok(Self {
+            events,
+            _stop: stop_tx,
+        })
+    }
+}
+
+impl Stream for LanDiscovery {
+    type Item = LanPeerEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.events.poll_recv(cx)
+    }
+}
+
+async fn run_discovery(
+    socket: UdpSocket,
+    advertisement: Option<LanAdvertisement>,
+    tx: UnboundedSender<LanPeerEvent>,
+    mut stop: oneshot::Receiver<()>,
+) {
+    let group = mdns_group();
+    let own_instance = advertisement
+        .as_ref()
+        .map(|advertisement| advertisement.instance.clone());
+    let mut known: HashMap<String, (DiscoveredNode, Instant)> = HashMap::new();
+    let mut query_interval = tokio::time::interval(QUERY_INTERVAL);
+    let mut buf = vec![0u8; MAX_MDNS_PACKET_LEN];
+
+    if let Some(advertisement) = advertisement.as_ref() {
+        send(&socket, &encode_response(advertisement, RECORD_TTL), group).await;
+    }
+
+    loop {
+        tokio::select! {
+            _ = &mut stop => break,
+
+            _ = query_interval.tick() => {
+                send(&socket, &encode_query(), group).await;
+
+                let ttl = Duration::from_secs(RECORD_TTL as u64);
+                let expired = known
+                    .iter()
+                    .filter(|(_, (_, last_seen))| last_seen.elapsed() > ttl)
+                    .map(|(instance, _)| instance.clone())
+                    .collect::<Vec<_>>();
+                for instance in expired {
+                    let _ = known.remove(&instance);
+                    let _ = tx.send(LanPeerEvent::Departed(instance));
+                }
+            }
+
+            res = socket.recv_from(&mut buf) => {
+                let (len, source) = match res {
+                    Ok(res) => res,
+                    Err(err) => {
+                        log::warn!(target: "citadel", "Unable to receive mDNS packet: {err:?}");
+                        continue;
+                    }
+                };
+
+                let message = if let Some(message) = parse_message(&buf[..len], source) {
+                    message
+                } else {
+                    log::trace!(target: "citadel", "Discarding malformed mDNS packet from {source}");
+                    continue;
+                };
+
+                if message.queries_service {
+                    if let Some(advertisement) = advertisement.as_ref() {
+                        send(&socket, &encode_response(advertisement, RECORD_TTL), group).await;
+                    }
+                }
+
+                for node in message.discovered {
+                    if Some(&node.instance) == own_instance.as_ref() {
+                        continue;
+                    }
+
+                    let changed = known
+                        .get(&node.instance)
+                        .map(|(prior, _)| prior != &node)
+                        .unwrap_or(true);
+                    let _ = known.insert(node.instance.clone(), (node.clone(), Instant::now()));
+                    if changed {
+                        let _ = tx.send(LanPeerEvent::Discovered(node));
+                    }
+                }
+
+                for instance in message.departed {
+                    if known.remove(&instance).is_some() {
+                        let _ = tx.send(LanPeerEvent::Departed(instance));
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(advertisement) = advertisement.as_ref() {
+        send(&socket, &encode_response(advertisement, 0), group).await;
+    }
+}
+
+async fn send(socket: &UdpSocket, packet: &[u8], group: std::net::SocketAddr) {
+    if let Err(err) = socket.send_to(packet, group).await {
+        log::warn!(target: "citadel", "Unable to send mDNS packet: {err:?}");
+    }
+}
@@ -240,19 +240,45 @@ pub mod prelude {
 
 /// Store data to the backend using this library
 pub mod backend_kv_store;
+/// A synchronous client for applications that cannot adopt async
+#[cfg(not(target_family = "wasm"))]
+pub mod blocking;
 mod builder;
+/// Typed sinks and streams over channels, encoded as JSON, bincode, or protobuf
+pub mod codec;
+/// A structured envelope for channel messages, carrying a content type and headers alongside the body
+pub mod envelope;
 /// Convenience functions for interacting with the remote encrypted virtual filesystem (RE-VFS)
 pub mod fs;
+/// Lets child servers accept clients registered at a parent identity server
+#[cfg(not(target_family = "wasm"))]
+pub mod hierarchy;
+/// Discovers nodes on the local network through mDNS, allowing direct connections without a central server
+#[cfg(all(feature = "lan-discovery", not(target_family = "wasm")))]
+pub mod lan_discovery;
+/// Bridges MQTT topics and group channels, allowing MQTT devices to interoperate with Citadel consumers
+#[cfg(all(feature = "mqtt-bridge", not(target_family = "wasm")))]
+pub mod mqtt_bridge;
+/// Queues outgoing messages while disconnected, flushing them once reconnected
+pub mod outbox;
 /// A list of prefabricated kernels designed for common use cases. If a greater degree of control is required for an application, a custom implementation of [NetKernel](crate::prelude::NetKernel) is desirable
 pub mod prefabs;
+/// Chooses, and switches mid-session between, the relays minimizing the latency between two peers
+#[cfg(not(target_family = "wasm"))]
+pub mod relay_selection;
 /// Extension implementations endowed upon the [NodeRemote](crate::prelude::NodeRemote)
 pub mod remote_ext;
 /// For easy construction of replies to common message types
 pub mod responses;
 #[doc(hidden)]
 pub mod test_common;
+/// Typed message handlers that are dispatched by the type of each inbound message
+pub mod typed;
 
 #[macro_use]
 pub(crate) mod macros;
 /// Convenience for SDK users
 pub use citadel_proto::prelude::async_trait;
+
+// allows the code generated by citadel_sdk_macros to refer to this crate from within
+extern crate self as citadel_sdk;
@@ -0,0 +1,309 @@
+//! Bridges MQTT topics and Citadel group channels
+//!
+//! IoT fleets commonly speak MQTT, while Citadel consumers exchange messages through group
+//! channels. An [`MqttBridge`] connects to an MQTT broker as a regular client and relays messages
+//! between the broker and a [`GroupChannel`] the bridge is a member of:
+//!
+//! - Messages published to a topic matching one of the inbound filters are broadcast to the
+//!   group inside a [`MessageEnvelope`], with the topic in the [`MQTT_TOPIC_HEADER`] header
+//! - Messages broadcast to the group by a member are published to the topic named by the
+//!   [`MQTT_TOPIC_HEADER`] of their envelope, or, to the default outbound topic. Each member may
+//!   only publish to the topics its entry in the publisher map permits, and members absent from
+//!   the map may not publish at all
+//!
+//! Messages are relayed at QoS 0. To prevent messages from looping between the broker and the
+//! group, topics matching an inbound filter can never be published to
+use crate::envelope::MessageEnvelope;
+use crate::prelude::*;
+use citadel_proto::re_imports::RustlsClientConfig;
+use futures::StreamExt;
+use rumqttc::{
+    AsyncClient, Event, EventLoop, MqttOptions, Outgoing, Packet, QoS, SubscribeFilter,
+    SubscribeReasonCode, TlsConfiguration, Transport,
+};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// The envelope header holding the MQTT topic of a bridged message
+pub const MQTT_TOPIC_HEADER: &str = "mqtt-topic";
+
+/// The number of requests queued for the broker before further messages of the group are dropped
+const REQUEST_CAPACITY: usize = 64;
+
+/// Configures an [`MqttBridge`]
+#[derive(Clone)]
+pub struct MqttBridgeConfig {
+    /// The hostname of the MQTT broker, which its certificate is verified against when using TLS
+    pub host: String,
+    pub port: u16,
+    /// Connects to the broker over TLS when present, e.g., with a config from
+    /// [`cert_vec_to_secure_client_config`](citadel_proto::re_imports::cert_vec_to_secure_client_config).
+    /// Without it, messages and credentials cross the network in plaintext
+    pub tls: Option<RustlsClientConfig>,
+    /// The client identifier the bridge connects to the broker with
+    pub client_id: String,
+    /// The credentials the bridge authenticates to the broker with, if required
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// The interval after which the bridge pings an otherwise idle broker. Intervals below 5
+    /// seconds are rounded up
+    pub keep_alive: Duration,
+    /// The largest packet exchanged with the broker. Larger messages end the bridge
+    pub max_packet_size: usize,
+    /// The topic filters, which may contain the `+` and `#` wildcards, whose messages are
+    /// broadcast to the group
+    pub inbound_filters: Vec<String>,
+    /// The topic messages of the group are published to when their envelope names no topic
+    pub default_outbound_topic: Option<String>,
+    /// Maps the CID of each member allowed to publish to MQTT to the topic filters it may publish to
+    pub publishers: HashMap<u64, Vec<String>>,
+}
+
+/// Relays messages between an MQTT broker and a group channel
+pub struct MqttBridge {
+    config: MqttBridgeConfig,
+}
+
+impl MqttBridge {
+    /// Fails if the default outbound topic matches an inbound filter, since every message
+    /// published to it would loop back into the group
+    pub fn new(config: MqttBridgeConfig) -> Result<Self, NetworkError> {
+        if let Some(topic) = config.default_outbound_topic.as_ref() {
+            if config
+                .inbound_filters
+                .iter()
+                .any(|filter| topic_matches(filter, topic))
+            {
+                return Err(NetworkError::msg(format!(
+                    "The default outbound topic {topic} matches an inbound filter"
+                )));
+            }
+        }
+
+        Ok(Self { config })
+    }
+
+    /// Connects to the broker, then relays messages until either the broker or the group closes
+    pub async fn run(self, group: GroupChannel) -> Result<(), NetworkError> {
+        let local_cid = group.cid();
+        let (group_tx, mut group_rx) = group.split();
+        let (client, mut event_loop) = AsyncClient::new(self.mqtt_options(), REQUEST_CAPACITY);
+
+        if !self.config.inbound_filters.is_empty() {
+            client
+                .subscribe_many(
+                    self.config
+                        .inbound_filters
+                        .iter()
+                        .map(|filter| SubscribeFilter::new(filter.clone(), QoS::AtMostOnce)),
+                )
+                .await
+                .map_err(broker_error)?;
+        }
+
+        // messages of the group are only relayed once the broker accepted the connection
+        loop {
+            if let Event::Incoming(Packet::ConnAck(_)) =
+                event_loop.poll().await.map_err(broker_error)?
+            {
+                break;
+            }
+        }
+
+        loop {
+            tokio::select! {
+                event = event_loop.poll() => {
+                    match event.map_err(broker_error)? {
+                        Event::Incoming(Packet::Publish(publish)) => {
+                            let envelope = MessageEnvelope::new(publish.payload.to_vec())
+                                .with_header(MQTT_TOPIC_HEADER, publish.topic);
+                            group_tx.send_message(envelope.encode()?).await?;
+                        }
+
+                        Event::Incoming(Packet::SubAck(ack)) => {
+                            for (filter, code) in self.config.inbound_filters.iter().zip(ack.return_codes) {
+                                if matches!(code, SubscribeReasonCode::Failure) {
+                                    log::warn!(target: "citadel", "The MQTT broker refused the subscription to {filter}");
+                                }
+                            }
+                        }
+
+                        _ => {}
+                    }
+                }
+
+                event = group_rx.next() => {
+                    match event {
+                        Some(GroupBroadcastPayload::Message { payload, sender, .. }) if sender != local_cid => {
+                            if let Some((topic, body)) = self.outbound(sender, payload.as_ref()) {
+                                // awaiting a full request queue would stall the event loop polled alongside this branch
+                                if let Err(err) = client.try_publish(topic, QoS::AtMostOnce, false, body) {
+                                    log::warn!(target: "citadel", "Dropping message from {sender} to the MQTT broker: {err}");
+                                }
+                            }
+                        }
+
+                        Some(_) => {}
+
+                        None => {
+                            log::trace!(target: "citadel", "The group closed; disconnecting from the MQTT broker");
+                            return disconnect(&client, &mut event_loop).await;
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn mqtt_options(&self) -> MqttOptions {
+        let mut options = MqttOptions::new(
+            self.config.client_id.clone(),
+            self.config.host.clone(),
+            self.config.port,
+        );
+        let _ = options
+            .set_keep_alive(self.config.keep_alive.max(Duration::from_secs(5)))
+            .set_max_packet_size(self.config.max_packet_size, self.config.max_packet_size);
+
+        if let Some(username) = self.config.username.clone() {
+            let _ =
+                options.set_credentials(username, self.config.password.clone().unwrap_or_default());
+        }
+
+        if let Some(tls) = self.config.tls.clone() {
+            let _ = options.set_transport(Transport::Tls(TlsConfiguration::Rustls(Arc::new(tls))));
+        }
+
+        options
+    }
+
+    /// Returns the topic and body a group message is published with, or `None` if the sender may
+    /// not publish to the topic
+    fn outbound(&self, sender: u64, message: &[u8]) -> Option<(String, Vec<u8>)> {
+        let envelope = match MessageEnvelope::from_message(message) {
+            Ok(envelope) => envelope,
+            Err(err) => {
+                log::warn!(target: "citadel", "Dropping malformed envelope from {sender}: {err:?}");
+                return None;
+            }
+        };
+
+        let topic = envelope
+            .header(MQTT_TOPIC_HEADER)
+            .map(|topic| topic.to_string())
+            .or_else(|| self.config.default_outbound_topic.clone())?;
+
+        let permitted = self
+            .config
+            .publishers
+            .get(&sender)
+            .map(|filters| filters.iter().any(|filter| topic_matches(filter, &topic)))
+            .unwrap_or(false);
+        let loops = self
+            .config
+            .inbound_filters
+            .iter()
+            .any(|filter| topic_matches(filter, &topic));
+
+        if !permitted || loops || topic.contains(['+', '#']) {
+            log::warn!(target: "citadel", "{sender} may not publish to MQTT topic {topic}");
+            return None;
+        }
+
+        Some((topic, envelope.body))
+    }
+}
+
+/// Returns true if `topic` matches `filter`, where `+` matches a single level and a trailing `#`
+/// matches any number of levels
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    let mut filter_levels = filter.split('/');
+    let mut topic_levels = topic.split('/');
+
+    loop {
+        match (filter_levels.next(), topic_levels.next()) {
+            (Some("#"), _) => return true,
+            (Some("+"), Some(_)) => {}
+            (Some(filter_level), Some(topic_level)) if filter_level == topic_level => {}
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+/// Sends the broker a DISCONNECT, returning once it has been written
+async fn disconnect(client: &AsyncClient, event_loop: &mut EventLoop) -> Result<(), NetworkError> {
+    client.try_disconnect().map_err(broker_error)?;
+    loop {
+        if let Event::Outgoing(Outgoing::Disconnect) =
+            event_loop.poll().await.map_err(broker_error)?
+        {
+            return Ok(());
+        }
+    }
+}
+
+fn broker_error(err: impl std::fmt::Display) -> NetworkError {
+    NetworkError::msg(format!("MQTT broker connection failed: {err}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::envelope::MessageEnvelope;
+    use crate::mqtt_bridge::{topic_matches, MqttBridge, MqttBridgeConfig, MQTT_TOPIC_HEADER};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_topic_matching() {
+        assert!(topic_matches("sensors/+/temp", "sensors/a/temp"));
+        assert!(!topic_matches("sensors/+/temp", "sensors/a/b/temp"));
+        assert!(topic_matches("sensors/#", "sensors/a/b/temp"));
+        assert!(topic_matches("#", "anything"));
+        assert!(!topic_matches("sensors/a", "sensors/a/temp"));
+        assert!(!topic_matches("sensors/a/temp", "sensors/a"));
+    }
+
+    #[test]
+    fn test_outbound_permissions() {
+        let bridge = MqttBridge::new(MqttBridgeConfig {
+            host: "127.0.0.1".to_string(),
+            port: 1883,
+            tls: None,
+            client_id: "bridge".to_string(),
+            username: None,
+            password: None,
+            keep_alive: Duration::from_secs(30),
+            max_packet_size: 64 * 1024,
+            inbound_filters: vec!["sensors/#".to_string()],
+            default_outbound_topic: Some("commands/all".to_string()),
+            publishers: HashMap::from([(10, vec!["commands/#".to_string()])]),
+        })
+        .unwrap();
+
+        let raw = b"reboot".to_vec();
+        assert_eq!(
+            bridge.outbound(10, &raw),
+            Some(("commands/all".to_string(), raw.clone()))
+        );
+        // members absent from the publisher map may not publish
+        assert_eq!(bridge.outbound(11, &raw), None);
+
+        let targeted = MessageEnvelope::new(raw.clone())
+            .with_header(MQTT_TOPIC_HEADER, "commands/device-7")
+            .encode()
+            .unwrap();
+        assert_eq!(
+            bridge.outbound(10, targeted.as_ref()),
+            Some(("commands/device-7".to_string(), raw.clone()))
+        );
+
+        // publishing into an inbound filter would loop back into the group
+        let looping = MessageEnvelope::new(raw)
+            .with_header(MQTT_TOPIC_HEADER, "sensors/a/temp")
+            .encode()
+            .unwrap();
+        assert_eq!(bridge.outbound(10, looping.as_ref()), None);
+    }
+}
@@ -0,0 +1,392 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashSet, VecDeque};
+use std::time::{Duration, SystemTime};
+
+const OUTBOX_MAP_KEY: &str = "_INTERNAL_OUTBOX";
+
+/// Identifies a message sent through an [`Outbox`], allowing the receiver to discard the
+/// duplicates that arise when a message is flushed again after an interrupted flush
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct IdempotencyToken(pub u128);
+
+/// The wire format of each message sent through an [`Outbox`]. Receivers unwrap it via
+/// [`IdempotentReceiver`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct OutboxEnvelope {
+    pub token: IdempotencyToken,
+    pub payload: Vec<u8>,
+}
+
+// The form of each message stored in the backend
+#[derive(Serialize, Deserialize)]
+struct QueuedMessage {
+    envelope: Vec<u8>,
+    expires_at: Option<SystemTime>,
+}
+
+impl QueuedMessage {
+    fn is_expired(&self) -> bool {
+        self.expires_at
+            .map(|expires_at| expires_at <= SystemTime::now())
+            .unwrap_or(false)
+    }
+}
+
+struct OutboxState {
+    sender: Option<PeerChannelSendHalf>,
+    next_seq: u64,
+    queued: usize,
+}
+
+/// Queues outgoing messages while disconnected, persisting them in the local backend, and flushes
+/// them in order once a channel is attached. Applications create one outbox per connection and
+/// attach the channel of each new connection to it, e.g., after reconnecting. A message is
+/// removed from the backend once the channel accepts it; if the node stops mid-flush, the message
+/// is flushed again on the next attach, hence receivers should use an [`IdempotentReceiver`]
+pub struct Outbox {
+    remote: NodeRemote,
+    implicated_cid: u64,
+    peer_cid: u64,
+    capacity: usize,
+    state: tokio::sync::Mutex<OutboxState>,
+}
+
+impl Outbox {
+    /// Loads the outbox of the connection between `implicated_cid` and `peer_cid` (zero for the
+    /// central server), including any messages queued before the node last stopped. At most
+    /// `capacity` messages are queued
+    pub async fn new(
+        remote: NodeRemote,
+        implicated_cid: u64,
+        peer_cid: u64,
+        capacity: usize,
+    ) -> Result<Self, NetworkError> {
+        let this = Self {
+            remote,
+            implicated_cid,
+            peer_cid,
+            capacity,
+            state: tokio::sync::Mutex::new(OutboxState {
+                sender: None,
+                next_seq: 0,
+                queued: 0,
+            }),
+        };
+
+        let queued = this.load_queued().await?;
+        {
+            let mut state = this.state.lock().await;
+            state.next_seq = queued.last().map(|(seq, _)| seq + 1).unwrap_or(0);
+            state.queued = queued.len();
+        }
+
+        Ok(this)
+    }
+
+    /// Sends the message through the attached channel. If no channel is attached, or the
+    /// channel fails, the message is queued until the next attach. Returns an error if the
+    /// outbox is full
+    pub async fn send<T: Into<SecBuffer>>(
+        &self,
+        message: T,
+    ) -> Result<IdempotencyToken, NetworkError> {
+        self.send_inner(message.into(), None).await
+    }
+
+    /// Like [`Outbox::send`], except that the message is dropped instead of sent once `ttl`
+    /// elapses, both while queued and while waiting for room in the attached channel. Suited for
+    /// real-time updates that are superseded by the next
+    pub async fn send_with_expiry<T: Into<SecBuffer>>(
+        &self,
+        message: T,
+        ttl: Duration,
+    ) -> Result<IdempotencyToken, NetworkError> {
+        self.send_inner(message.into(), Some(ttl)).await
+    }
+
+    async fn send_inner(
+        &self,
+        message: SecBuffer,
+        ttl: Option<Duration>,
+    ) -> Result<IdempotencyToken, NetworkError> {
+        let expires_at = ttl.map(|ttl| SystemTime::now() + ttl);
+        let token = IdempotencyToken(uuid::Uuid::new_v4().as_u128());
+        let envelope = OutboxEnvelope {
+            token,
+            payload: message.into_buffer().to_vec(),
+        }
+        .serialize_to_vector()
+        .map_err(|err| NetworkError::Generic(err.into_string()))?;
+
+        let mut state = self.state.lock().await;
+        // messages queued earlier must be flushed first to preserve order
+        if state.queued == 0 {
+            if let Some(sender) = state.sender.as_ref() {
+                let result = match ttl {
+                    Some(ttl) => sender.send_with_expiry(envelope.clone().into(), ttl).await,
+                    None => sender
+                        .send_message(envelope.clone().into())
+                        .await
+                        .map_err(ChannelSendError::from),
+                };
+
+                match result {
+                    Ok(()) => return Ok(token),
+                    Err(ChannelSendError::Expired) => {
+                        log::trace!(target: "citadel", "Outbox message expired before it was sent");
+                        return Ok(token);
+                    }
+                    Err(err) => {
+                        log::warn!(target: "citadel", "Outbox channel failed, queueing instead: {err:?}");
+                        state.sender = None;
+                    }
+                }
+            }
+        }
+
+        if state.queued >= self.capacity {
+            return Err(NetworkError::InvalidRequest("The outbox is full"));
+        }
+
+        let queued = QueuedMessage {
+            envelope,
+            expires_at,
+        }
+        .serialize_to_vector()
+        .map_err(|err| NetworkError::Generic(err.into_string()))?;
+        let seq = state.next_seq;
+        let _ = self
+            .remote
+            .account_manager()
+            .get_persistence_handler()
+            .store_byte_map_value(
+                self.implicated_cid,
+                self.peer_cid,
+                OUTBOX_MAP_KEY,
+                &sub_key(seq),
+                queued,
+            )
+            .await
+            .map_err(|err| NetworkError::msg(err.into_string()))?;
+        state.next_seq += 1;
+        state.queued += 1;
+        Ok(token)
+    }
+
+    /// Flushes the queued messages in order through `sender`, then sends all subsequent messages
+    /// through it. Expired messages are dropped instead. Returns the number of messages flushed.
+    /// If the flush fails, the remaining messages stay queued and no channel is attached
+    pub async fn attach(&self, sender: PeerChannelSendHalf) -> Result<usize, NetworkError> {
+        let mut state = self.state.lock().await;
+        state.sender = None;
+        let mut flushed = 0;
+
+        for (seq, queued) in self.load_queued().await? {
+            let sent = match queued.expires_at {
+                _ if queued.is_expired() => false,
+                Some(expires_at) => {
+                    let ttl = expires_at
+                        .duration_since(SystemTime::now())
+                        .unwrap_or_default();
+                    match sender.send_with_expiry(queued.envelope.into(), ttl).await {
+                        Ok(()) => true,
+                        Err(ChannelSendError::Expired) => false,
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+                None => {
+                    sender.send_message(queued.envelope.into()).await?;
+                    true
+                }
+            };
+
+            let _ = self
+                .remote
+                .account_manager()
+                .get_persistence_handler()
+                .remove_byte_map_value(
+                    self.implicated_cid,
+                    self.peer_cid,
+                    OUTBOX_MAP_KEY,
+                    &sub_key(seq),
+                )
+                .await
+                .map_err(|err| NetworkError::msg(err.into_string()))?;
+            state.queued -= 1;
+            if sent {
+                flushed += 1;
+            }
+        }
+
+        state.sender = Some(sender);
+        Ok(flushed)
+    }
+
+    /// Stops sending through the attached channel, queueing all subsequent messages
+    pub async fn detach(&self) {
+        self.state.lock().await.sender = None;
+    }
+
+    /// Returns the number of messages awaiting a flush
+    pub async fn queued(&self) -> usize {
+        self.state.lock().await.queued
+    }
+
+    async fn load_queued(&self) -> Result<Vec<(u64, QueuedMessage)>, NetworkError> {
+        let values = self
+            .remote
+            .account_manager()
+            .get_persistence_handler()
+            .get_byte_map_values_by_key(self.implicated_cid, self.peer_cid, OUTBOX_MAP_KEY)
+            .await
+            .map_err(|err| NetworkError::msg(err.into_string()))?;
+        let mut queued = values
+            .into_iter()
+            .filter_map(|(key, queued)| {
+                let queued = QueuedMessage::deserialize_from_vector(&queued).ok()?;
+                Some((key.parse::<u64>().ok()?, queued))
+            })
+            .collect::<Vec<_>>();
+        queued.sort_by_key(|(seq, _)| *seq);
+        Ok(queued)
+    }
+}
+
+fn sub_key(seq: u64) -> String {
+    format!("{seq:020}")
+}
+
+/// Unwraps the messages sent through an [`Outbox`], discarding those already received. The most
+/// recent `capacity` tokens are remembered
+pub struct IdempotentReceiver {
+    seen: HashSet<IdempotencyToken>,
+    order: VecDeque<IdempotencyToken>,
+    capacity: usize,
+}
+
+impl IdempotentReceiver {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            seen: HashSet::with_capacity(capacity),
+            order: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Returns the payload of the message, or `None` if the message is a duplicate
+    pub fn receive(&mut self, message: &[u8]) -> Result<Option<Vec<u8>>, NetworkError> {
+        let OutboxEnvelope { token, payload } = OutboxEnvelope::deserialize_from_vector(message)
+            .map_err(|err| NetworkError::Generic(err.into_string()))?;
+        if !self.seen.insert(token) {
+            return Ok(None);
+        }
+
+        self.order.push_back(token);
+        if self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                let _ = self.seen.remove(&oldest);
+            }
+        }
+
+        Ok(Some(payload))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::outbox::{IdempotentReceiver, Outbox, OutboxEnvelope};
+    use crate::prefabs::client::single_connection::SingleClientServerConnectionKernel;
+    use crate::prelude::*;
+    use futures::StreamExt;
+    use rstest::rstest;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_duplicates_are_discarded() {
+        let envelope = OutboxEnvelope {
+            token: super::IdempotencyToken(1),
+            payload: vec![1, 2, 3],
+        }
+        .serialize_to_vector()
+        .unwrap();
+
+        let mut receiver = IdempotentReceiver::new(1);
+        assert_eq!(receiver.receive(&envelope).unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(receiver.receive(&envelope).unwrap(), None);
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_c2s_outbox_flushes_on_attach() {
+        citadel_logging::setup_log();
+        const QUEUED: usize = 3;
+        let server_success = &AtomicBool::new(false);
+        let (server, server_addr) = crate::test_common::server_info_reactive(
+            |conn, _remote| async move {
+                let mut receiver = IdempotentReceiver::new(16);
+                let (_tx, mut rx) = conn.channel.split();
+                for idx in 0..QUEUED as u8 {
+                    let message = rx.next().await.unwrap();
+                    let payload = receiver.receive(message.as_ref())?.unwrap();
+                    assert_eq!(payload, vec![idx; 8]);
+                }
+
+                server_success.store(true, Ordering::Relaxed);
+                Ok(())
+            },
+            |_| {},
+        );
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            Uuid::new_v4(),
+            server_addr,
+            |connection, remote| async move {
+                let outbox = Outbox::new(
+                    remote.remote().clone(),
+                    remote.user().get_implicated_cid(),
+                    remote.user().get_target_cid(),
+                    QUEUED,
+                )
+                .await?;
+
+                // no channel is attached, hence the messages are queued. The stale update
+                // expires before the channel is attached, so it is never delivered
+                let _ = outbox
+                    .send_with_expiry([u8::MAX; 8], std::time::Duration::ZERO)
+                    .await?;
+                for idx in 0..(QUEUED - 1) as u8 {
+                    let _ = outbox.send([idx; 8]).await?;
+                }
+                assert_eq!(outbox.queued().await, QUEUED);
+                assert!(outbox.send([u8::MAX; 8]).await.is_err());
+
+                assert_eq!(
+                    outbox.attach(connection.channel.sender()).await?,
+                    QUEUED - 1
+                );
+                assert_eq!(outbox.queued().await, 0);
+                let _ = outbox.send([(QUEUED - 1) as u8; 8]).await?;
+                assert_eq!(outbox.queued().await, 0);
+
+                while !server_success.load(Ordering::Relaxed) {
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                }
+
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        tokio::select! {
+            res0 = server => { res0.unwrap(); },
+            res1 = client => { res1.unwrap(); }
+        }
+
+        assert!(server_success.load(Ordering::Relaxed));
+    }
+}
@@ -401,6 +401,12 @@ mod tests {
                         log::trace!(target: "citadel", "User {} received {:?}", username, conn);
                         let conn = conn?;
                         crate::test_common::udp_mode_assertions(udp_mode, conn.udp_rx_opt).await;
+                        // every peer connects to every other peer, hence requests may cross
+                        if let Some(cross_connect) = conn.channel.cross_connect() {
+                            let local_cid = conn.channel.get_implicated_cid();
+                            let peer_cid = conn.channel.get_peer_cid();
+                            assert_eq!(cross_connect.initiator_cid, local_cid.min(peer_cid));
+                        }
                         success += 1;
                         let _ = p2p_remotes.insert(conn.channel.get_peer_cid(), conn.remote);
                         if success == peer_count - 1 {
@@ -545,11 +551,13 @@ mod tests {
     }
 
     #[rstest]
-    #[case(2)]
+    #[case(2, false)]
+    #[case(2, true)]
     #[timeout(std::time::Duration::from_secs(90))]
     #[tokio::test(flavor = "multi_thread")]
     async fn test_peer_to_peer_file_transfer(
         #[case] peer_count: usize,
+        #[case] pause_and_resume: bool,
     ) -> Result<(), Box<dyn std::error::Error>> {
         assert!(peer_count > 1);
         citadel_logging::setup_log();
@@ -613,10 +621,35 @@ mod tests {
 
                             use futures::StreamExt;
                             let mut path = None;
+                            let mut pause_requested = !pause_and_resume;
+                            let mut resumed = !pause_and_resume;
                             while let Some(status) = handle.next().await {
                                 match status {
+                                    // the receiver pauses the transfer partway, after which the
+                                    // sender must hold off until the receiver resumes
+                                    ObjectTransferStatus::ReceptionTick(..) if !pause_requested => {
+                                        pause_requested = true;
+                                        handle.pause().unwrap();
+                                    }
+
+                                    ObjectTransferStatus::Paused => {
+                                        tokio::time::sleep(std::time::Duration::from_millis(500))
+                                            .await;
+                                        handle.resume().unwrap();
+                                    }
+
+                                    ObjectTransferStatus::Resumed => {
+                                        resumed = true;
+                                    }
+
+                                    ObjectTransferStatus::Cancelled
+                                    | ObjectTransferStatus::Fail(_) => {
+                                        panic!("The transfer should have completed: {status:?}")
+                                    }
+
                                     ObjectTransferStatus::ReceptionComplete => {
                                         log::trace!(target: "citadel", "Peer has finished receiving the file!");
+                                        assert!(resumed, "The transfer completed without resuming");
                                         let cmp =
                                             include_bytes!("../../../../resources/TheBridge.pdf");
                                         let streamed_data =
@@ -748,4 +781,105 @@ mod tests {
         assert_eq!(client_success.load(Ordering::Relaxed), peer_count);
         Ok(())
     }
+
+    #[rstest]
+    #[case(2)]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_peer_to_peer_disconnect_leaves_no_virtual_connections(
+        #[case] peer_count: usize,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        assert!(peer_count > 1);
+        citadel_logging::setup_log();
+        TestBarrier::setup(peer_count);
+
+        let client_success = &AtomicUsize::new(0);
+        let (server, server_addr) = server_info();
+
+        let client_kernels = FuturesUnordered::new();
+        let total_peers = (0..peer_count)
+            .map(|_| Uuid::new_v4())
+            .collect::<Vec<Uuid>>();
+
+        for idx in 0..peer_count {
+            let uuid = total_peers.get(idx).cloned().unwrap();
+            let peers = total_peers
+                .clone()
+                .into_iter()
+                .filter(|r| r != &uuid)
+                .map(UserIdentifier::from)
+                .collect::<Vec<UserIdentifier>>();
+
+            let client_kernel = PeerConnectionKernel::new_passwordless_defaults(
+                uuid,
+                server_addr,
+                peers,
+                move |mut results, remote| async move {
+                    let mut success = 0;
+                    let implicated_cid = remote.conn_type.get_implicated_cid();
+
+                    while let Some(conn) = results.recv().await {
+                        log::trace!(target: "citadel", "User {} received {:?}", uuid, conn);
+                        // dropping the channel disconnects from the peer
+                        std::mem::drop(conn?);
+                        success += 1;
+                        if success == peer_count - 1 {
+                            break;
+                        }
+                    }
+
+                    wait_for_peers().await;
+                    let mut node_remote = remote.inner.clone();
+                    loop {
+                        match node_remote
+                            .send_callback(NodeRequest::GetVirtualConnections)
+                            .await?
+                        {
+                            NodeResult::VirtualConnections(VirtualConnections {
+                                connections,
+                                ..
+                            }) => {
+                                let remaining = connections
+                                    .get(&implicated_cid)
+                                    .map(Vec::len)
+                                    .unwrap_or(0);
+                                if remaining == 0 {
+                                    break;
+                                }
+
+                                log::trace!(target: "citadel", "{} virtual connections of {} remain", remaining, implicated_cid);
+                            }
+
+                            other => {
+                                return Err(NetworkError::Generic(format!(
+                                    "Unexpected response: {other:?}"
+                                )))
+                            }
+                        }
+
+                        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+                    }
+
+                    let _ = client_success.fetch_add(1, Ordering::Relaxed);
+                    wait_for_peers().await;
+                    remote.shutdown_kernel().await
+                },
+            ).unwrap();
+
+            let client = NodeBuilder::default().build(client_kernel).unwrap();
+            client_kernels.push(async move { client.await.map(|_| ()) });
+        }
+
+        let clients = Box::pin(async move { client_kernels.try_collect::<()>().await.map(|_| ()) });
+
+        if let Err(err) = futures::future::try_select(server, clients).await {
+            return match err {
+                futures::future::Either::Left(res) => Err(res.0.into_string().into()),
+                futures::future::Either::Right(res) => Err(res.0.into_string().into()),
+            };
+        }
+
+        assert_eq!(client_success.load(Ordering::Relaxed), peer_count);
+        Ok(())
+    }
 }
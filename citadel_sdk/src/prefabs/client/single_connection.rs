@@ -251,12 +251,14 @@ where
             None
         };
 
+        let sender = connect_success.channel.sender();
         (handler)(
             connect_success,
             ClientServerRemote {
                 inner: remote,
                 unprocessed_signals_rx: Arc::new(Mutex::new(unprocessed_signal_filter)),
                 conn_type,
+                sender,
             },
         )
         .await
@@ -403,6 +405,23 @@ mod tests {
             Default::default(),
             |channel, remote| async move {
                 log::trace!(target: "citadel", "***CLIENT TEST SUCCESS***");
+                let timings = channel.timings;
+                assert!(timings.transport.is_some());
+                assert!(timings.register.is_some());
+                assert!(timings.first_ratchet.is_some());
+                assert!(timings.connect.is_some());
+                let stages = [
+                    timings.transport,
+                    timings.tls,
+                    timings.register,
+                    timings.first_ratchet,
+                    timings.udp_traversal,
+                    timings.connect,
+                ];
+                assert_eq!(
+                    stages.into_iter().flatten().sum::<std::time::Duration>(),
+                    timings.total
+                );
                 wait_for_peers().await;
                 crate::test_common::udp_mode_assertions(udp_mode, channel.udp_channel_rx).await;
                 client_success.store(true, Ordering::Relaxed);
@@ -426,6 +445,126 @@ mod tests {
         assert!(server_success.load(Ordering::Relaxed));
     }
 
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_single_connection_with_handshake_challenge() {
+        citadel_logging::setup_log();
+        TestBarrier::setup(2);
+
+        let udp_mode = UdpMode::Disabled;
+
+        let client_success = &AtomicBool::new(false);
+        let server_success = &AtomicBool::new(false);
+        let (server, server_addr) = server_info_reactive(
+            |conn, remote| async move {
+                default_server_harness(udp_mode, conn, remote, server_success).await
+            },
+            |builder| {
+                let _ = builder.with_server_misc_settings(ServerMiscSettings {
+                    handshake_challenge: Some(HandshakeChallengeSettings {
+                        difficulty: 8,
+                        ..Default::default()
+                    }),
+                    ..Default::default()
+                });
+            },
+        );
+
+        let uuid = Uuid::new_v4();
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless(
+            uuid,
+            server_addr,
+            udp_mode,
+            Default::default(),
+            |_channel, remote| async move {
+                log::trace!(target: "citadel", "***CLIENT TEST SUCCESS***");
+                wait_for_peers().await;
+                client_success.store(true, Ordering::Relaxed);
+                wait_for_peers().await;
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        let joined = futures::future::try_join(server, client);
+
+        let _ = joined.await.unwrap();
+
+        assert!(client_success.load(Ordering::Relaxed));
+        assert!(server_success.load(Ordering::Relaxed));
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_single_connection_with_hole_punch_settings() {
+        citadel_logging::setup_log();
+        TestBarrier::setup(2);
+
+        let udp_mode = UdpMode::Enabled;
+        let (server_events_tx, mut server_events) = tokio::sync::mpsc::unbounded_channel();
+        let (client_events_tx, mut client_events) = tokio::sync::mpsc::unbounded_channel();
+        let settings = |events_tx| {
+            HolePunchConfigContainer::default()
+                .with_multipath(true)
+                .with_progress_observer(events_tx)
+        };
+
+        let client_success = &AtomicBool::new(false);
+        let server_success = &AtomicBool::new(false);
+        let (server, server_addr) = server_info_reactive(
+            |conn, remote| async move {
+                default_server_harness(udp_mode, conn, remote, server_success).await
+            },
+            |builder| {
+                let _ = builder.with_hole_punch_settings(settings(server_events_tx));
+            },
+        );
+
+        let uuid = Uuid::new_v4();
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless(
+            uuid,
+            server_addr,
+            udp_mode,
+            Default::default(),
+            |channel, remote| async move {
+                log::trace!(target: "citadel", "***CLIENT TEST SUCCESS***");
+                wait_for_peers().await;
+                crate::test_common::udp_mode_assertions(udp_mode, channel.udp_channel_rx).await;
+                client_success.store(true, Ordering::Relaxed);
+                wait_for_peers().await;
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default()
+            .with_hole_punch_settings(settings(client_events_tx))
+            .build(client_kernel)
+            .unwrap();
+
+        let joined = futures::future::try_join(server, client);
+
+        let _ = joined.await.unwrap();
+
+        assert!(client_success.load(Ordering::Relaxed));
+        assert!(server_success.load(Ordering::Relaxed));
+
+        // the observers set on each node saw the hole punch of the session
+        for events in [&mut server_events, &mut client_events] {
+            let mut winner_chosen = false;
+            while let Ok(event) = events.try_recv() {
+                winner_chosen |= matches!(event, HolePunchEvent::WinnerChosen { .. });
+            }
+            assert!(winner_chosen);
+        }
+    }
+
     #[rstest]
     #[case(UdpMode::Disabled)]
     #[timeout(std::time::Duration::from_secs(90))]
@@ -582,6 +721,8 @@ mod tests {
                     assert_eq!(remote.rekey().await?, Some(x));
                 }
 
+                assert_eq!(remote.rekey_now().await?, 10);
+
                 client_success.store(true, Ordering::Relaxed);
                 wait_for_peers().await;
                 remote.shutdown_kernel().await
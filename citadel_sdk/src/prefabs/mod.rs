@@ -20,6 +20,7 @@ pub struct ClientServerRemote {
     #[allow(dead_code)]
     unprocessed_signals_rx: Arc<Mutex<Option<tokio::sync::mpsc::UnboundedReceiver<NodeResult>>>>,
     conn_type: VirtualTargetType,
+    pub(crate) sender: PeerChannelSendHalf,
 }
 
 impl_remote!(ClientServerRemote);
@@ -63,7 +64,7 @@ impl ClientServerRemote {
     }
 }
 
-pub(self) fn get_socket_addr<T: ToSocketAddrs>(addr: T) -> Result<SocketAddr, NetworkError> {
+pub(crate) fn get_socket_addr<T: ToSocketAddrs>(addr: T) -> Result<SocketAddr, NetworkError> {
     addr.to_socket_addrs()
         .map_err(|err| NetworkError::SocketError(err.to_string()))?
         .next()
@@ -53,11 +53,13 @@ where
                 welcome_message: _,
                 channel,
                 udp_rx_opt: udp_channel_rx,
+                timings,
             }) => {
                 let client_server_remote = ClientServerRemote {
                     inner: self.node_remote.clone().unwrap(),
                     unprocessed_signals_rx: Default::default(),
                     conn_type,
+                    sender: channel.sender(),
                 };
                 (self.on_channel_received)(
                     ConnectionSuccess {
@@ -65,6 +67,7 @@ where
                         udp_channel_rx,
                         services,
                         cid,
+                        timings,
                     },
                     client_server_remote,
                 )
@@ -0,0 +1,332 @@
+//! A server kernel exposing connected clients to web backends over HTTP
+//!
+//! Backends that cannot embed the SDK integrate with a deployment through the gateway instead:
+//!
+//! - `GET /clients` returns the CIDs of the connected clients as a JSON array
+//! - `POST /clients/{cid}/messages` sends the request body to the client with the given CID
+//! - `GET /events` streams each message received from a client as a server-sent event
+//!
+//! Additionally, each message received from a client is POSTed to the webhook, if configured.
+//! Inbound messages are delivered as a JSON [`GatewayMessage`], holding the CID of the sender
+//! alongside the base64-encoded message. Since the gateway terminates the encryption of each
+//! client, it should only be exposed to trusted networks, and protected by an auth token
+use crate::prelude::*;
+use citadel_io::Mutex;
+use futures::StreamExt;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Client, Method, Request, Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::sync::broadcast;
+
+/// The number of inbound messages buffered for each event stream that falls behind
+const EVENT_BACKLOG: usize = 1024;
+
+/// Configures the [`HttpGatewayKernel`]
+#[derive(Clone, Debug)]
+pub struct HttpGatewaySettings {
+    /// The address the gateway listens on
+    pub bind_addr: SocketAddr,
+    /// If set, each request must carry the header `Authorization: Bearer <token>`
+    pub auth_token: Option<String>,
+    /// If set, each message received from a client is POSTed to this URL. Only plain HTTP is supported
+    pub webhook_url: Option<String>,
+}
+
+/// A message received from a client, as delivered to webhooks and event streams
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct GatewayMessage {
+    /// The CID of the client that sent the message
+    pub cid: u64,
+    /// The base64-encoded message
+    pub message: String,
+}
+
+struct GatewayState {
+    settings: HttpGatewaySettings,
+    clients: Mutex<HashMap<u64, PeerChannelSendHalf>>,
+    events: broadcast::Sender<GatewayMessage>,
+}
+
+/// A kernel that relays messages between connected clients and web backends over HTTP
+pub struct HttpGatewayKernel {
+    state: Arc<GatewayState>,
+}
+
+impl HttpGatewayKernel {
+    pub fn new(settings: HttpGatewaySettings) -> Self {
+        let (events, _) = broadcast::channel(EVENT_BACKLOG);
+        Self {
+            state: Arc::new(GatewayState {
+                settings,
+                clients: Mutex::new(HashMap::new()),
+                events,
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl NetKernel for HttpGatewayKernel {
+    fn load_remote(&mut self, _node_remote: NodeRemote) -> Result<(), NetworkError> {
+        Ok(())
+    }
+
+    async fn on_start(&self) -> Result<(), NetworkError> {
+        let state = self.state.clone();
+        let make_service = make_service_fn(move |_| {
+            let state = state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle_request(state.clone(), request)
+                }))
+            }
+        });
+
+        log::info!(target: "citadel", "HTTP gateway listening on {}", self.state.settings.bind_addr);
+        hyper::Server::try_bind(&self.state.settings.bind_addr)
+            .map_err(|err| NetworkError::Generic(err.to_string()))?
+            .serve(make_service)
+            .await
+            .map_err(|err| NetworkError::Generic(err.to_string()))
+    }
+
+    async fn on_node_event_received(&self, message: NodeResult) -> Result<(), NetworkError> {
+        if let NodeResult::ConnectSuccess(ConnectSuccess {
+            implicated_cid: cid,
+            channel,
+            ..
+        }) = message
+        {
+            let (sender, mut receiver) = channel.split();
+            let _ = self.state.clients.lock().insert(cid, sender);
+            let state = self.state.clone();
+            std::mem::drop(citadel_io::spawn(async move {
+                while let Some(message) = receiver.next().await {
+                    state.on_inbound_message(GatewayMessage {
+                        cid,
+                        message: base64::encode(message.as_ref()),
+                    });
+                }
+
+                log::trace!(target: "citadel", "Client {cid} left the HTTP gateway");
+                let _ = state.clients.lock().remove(&cid);
+            }));
+        }
+
+        Ok(())
+    }
+
+    async fn on_stop(&mut self) -> Result<(), NetworkError> {
+        Ok(())
+    }
+}
+
+impl GatewayState {
+    fn is_authorized(&self, request: &Request<Body>) -> bool {
+        match self.settings.auth_token.as_ref() {
+            Some(token) => request
+                .headers()
+                .get(hyper::header::AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer "))
+                .map(|provided| provided == token)
+                .unwrap_or(false),
+            None => true,
+        }
+    }
+
+    fn on_inbound_message(&self, message: GatewayMessage) {
+        let _ = self.events.send(message.clone());
+
+        if let Some(url) = self.settings.webhook_url.clone() {
+            std::mem::drop(citadel_io::spawn(async move {
+                let cid = message.cid;
+                let body = match serde_json::to_vec(&message) {
+                    Ok(body) => body,
+                    Err(err) => {
+                        log::error!(target: "citadel", "Unable to encode webhook body: {err:?}");
+                        return;
+                    }
+                };
+
+                let request = Request::post(url)
+                    .header(hyper::header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body));
+                let response = match request {
+                    Ok(request) => Client::new().request(request).await,
+                    Err(err) => {
+                        log::error!(target: "citadel", "Invalid webhook request: {err:?}");
+                        return;
+                    }
+                };
+
+                match response {
+                    Ok(response) if response.status().is_success() => {}
+                    Ok(response) => {
+                        log::warn!(target: "citadel", "Webhook rejected the message of {cid}: {}", response.status())
+                    }
+                    Err(err) => {
+                        log::warn!(target: "citadel", "Unable to deliver the message of {cid} to the webhook: {err:?}")
+                    }
+                }
+            }));
+        }
+    }
+}
+
+async fn handle_request(
+    state: Arc<GatewayState>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    if !state.is_authorized(&request) {
+        return Ok(status(StatusCode::UNAUTHORIZED));
+    }
+
+    let path = request.uri().path().trim_matches('/').to_string();
+    let segments = path.split('/').collect::<Vec<_>>();
+    let method = request.method().clone();
+
+    let response = match (&method, segments.as_slice()) {
+        (&Method::GET, ["clients"]) => {
+            let mut cids = state.clients.lock().keys().copied().collect::<Vec<_>>();
+            cids.sort_unstable();
+            json(&cids)
+        }
+
+        (&Method::POST, ["clients", cid, "messages"]) => {
+            let sender = cid
+                .parse::<u64>()
+                .ok()
+                .and_then(|cid| state.clients.lock().get(&cid).cloned());
+            match sender {
+                Some(sender) => match hyper::body::to_bytes(request.into_body()).await {
+                    Ok(body) => match sender.send_message(body.as_ref().into()).await {
+                        Ok(()) => status(StatusCode::ACCEPTED),
+                        Err(err) => {
+                            log::warn!(target: "citadel", "Unable to relay message to {cid}: {err:?}");
+                            status(StatusCode::BAD_GATEWAY)
+                        }
+                    },
+                    Err(_) => status(StatusCode::BAD_REQUEST),
+                },
+                None => status(StatusCode::NOT_FOUND),
+            }
+        }
+
+        (&Method::GET, ["events"]) => {
+            let mut events = state.events.subscribe();
+            let (mut tx, body) = Body::channel();
+            std::mem::drop(citadel_io::spawn(async move {
+                loop {
+                    let event = match events.recv().await {
+                        Ok(event) => event,
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            log::warn!(target: "citadel", "Event stream fell behind, skipping {skipped} messages");
+                            continue;
+                        }
+                        Err(broadcast::error::RecvError::Closed) => return,
+                    };
+
+                    let data = match serde_json::to_string(&event) {
+                        Ok(data) => data,
+                        Err(_) => continue,
+                    };
+
+                    // ends once the backend disconnects
+                    if tx
+                        .send_data(format!("data: {data}\n\n").into())
+                        .await
+                        .is_err()
+                    {
+                        return;
+                    }
+                }
+            }));
+
+            Response::builder()
+                .header(hyper::header::CONTENT_TYPE, "text/event-stream")
+                .header(hyper::header::CACHE_CONTROL, "no-cache")
+                .body(body)
+                .unwrap_or_else(|_| status(StatusCode::INTERNAL_SERVER_ERROR))
+        }
+
+        _ => status(StatusCode::NOT_FOUND),
+    };
+
+    Ok(response)
+}
+
+fn status(code: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = code;
+    response
+}
+
+fn json<T: Serialize>(value: &T) -> Response<Body> {
+    match serde_json::to_vec(value) {
+        Ok(body) => Response::builder()
+            .header(hyper::header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .unwrap_or_else(|_| status(StatusCode::INTERNAL_SERVER_ERROR)),
+        Err(_) => status(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prefabs::server::http_gateway::{
+        handle_request, HttpGatewayKernel, HttpGatewaySettings,
+    };
+    use hyper::{Body, Request, StatusCode};
+
+    #[tokio::test]
+    async fn test_gateway_routes_and_auth() {
+        let kernel = HttpGatewayKernel::new(HttpGatewaySettings {
+            bind_addr: "127.0.0.1:0".parse().unwrap(),
+            auth_token: Some("secret".to_string()),
+            webhook_url: None,
+        });
+
+        let request = |method: &str, uri: &str, token: Option<&str>| {
+            let mut builder = Request::builder().method(method).uri(uri);
+            if let Some(token) = token {
+                builder = builder.header("Authorization", format!("Bearer {token}"));
+            }
+            builder.body(Body::empty()).unwrap()
+        };
+
+        let unauthorized = handle_request(kernel.state.clone(), request("GET", "/clients", None))
+            .await
+            .unwrap();
+        assert_eq!(unauthorized.status(), StatusCode::UNAUTHORIZED);
+
+        let wrong_token = request("GET", "/clients", Some("guess"));
+        let response = handle_request(kernel.state.clone(), wrong_token)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = handle_request(
+            kernel.state.clone(),
+            request("GET", "/clients", Some("secret")),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(body.as_ref(), b"[]");
+
+        // clients that are not connected cannot be messaged
+        let send = request("POST", "/clients/1234/messages", Some("secret"));
+        let response = handle_request(kernel.state.clone(), send).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let unknown = request("GET", "/unknown", Some("secret"));
+        let response = handle_request(kernel.state.clone(), unknown).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
@@ -6,3 +6,6 @@ pub mod accept_file_transfer_kernel;
 pub mod client_connect_listener;
 /// A non-reactive kernel that does no additional processing on top of the protocol
 pub mod empty;
+/// A kernel exposing connected clients to web backends through a REST API, server-sent events and webhooks
+#[cfg(all(feature = "http-gateway", not(target_family = "wasm")))]
+pub mod http_gateway;
@@ -0,0 +1,506 @@
+//! Chooses among multiple relays for peers that cannot connect directly
+//!
+//! When a deployment runs several relay nodes, e.g., one per region, the relay nearest to one
+//! peer may be far from the other. Each peer measures its round-trip time to every relay through
+//! [`measure_relays`], then sends the resulting [`RelayLatencies`] to the other peer over their
+//! existing connection. Given both measurements, the [`RelaySelector`] picks the relay minimizing
+//! the latency of the slower leg. Since network conditions change during a session, measurements
+//! may be repeated at any time: the selector only switches relays once another relay improves the
+//! slower leg by at least the switch threshold, preventing two comparable relays from flapping
+//!
+//! A [`RelaySession`] drives the selection for a session. Each time the peer's measurements
+//! arrive, it measures the local latencies anew and, if warranted, migrates the session through
+//! its [`RelayConnector`]: the connection through the new relay is established before the
+//! connection through the old relay is torn down, such that the session is never without a relay.
+//! Connectors typically attach the new channel to an [`Outbox`](crate::outbox::Outbox), which
+//! flushes the messages queued during the switch. The measurements, current relay and switches of
+//! the session are exposed through [`RelaySession::diagnostics`]
+//!
+//! ```no_run
+//! use citadel_sdk::outbox::Outbox;
+//! use citadel_sdk::prelude::*;
+//! use citadel_sdk::relay_selection::{RelayConnector, RelayLatencies, RelaySelector, RelaySession};
+//! use std::net::SocketAddr;
+//! use std::sync::Arc;
+//!
+//! struct Connector {
+//!     outbox: Arc<Outbox>,
+//! }
+//!
+//! #[async_trait]
+//! impl RelayConnector for Connector {
+//!     type Connection = PeerChannelRecvHalf;
+//!
+//!     async fn connect(&self, relay: SocketAddr) -> Result<Self::Connection, NetworkError> {
+//!         # let channel: PeerChannel = todo!();
+//!         // ... connect to the peer through `relay` ...
+//!         let (sender, receiver) = channel.split();
+//!         let _ = self.outbox.attach(sender).await?;
+//!         Ok(receiver)
+//!     }
+//!
+//!     async fn disconnect(&self, relay: SocketAddr, _: Self::Connection) -> Result<(), NetworkError> {
+//!         // ... disconnect from `relay` ...
+//!         # Ok(())
+//!     }
+//! }
+//!
+//! # async fn run(connector: Connector, peer: RelayLatencies) -> Result<(), NetworkError> {
+//! let relays = vec!["203.0.113.1:25021".parse().unwrap(), "198.51.100.7:25021".parse().unwrap()];
+//! let mut session = RelaySession::new(relays, connector, RelaySelector::default());
+//! let local = session.measure().await;
+//! // ... exchange `local` with the peer, receiving `peer` ...
+//! if let Some(selection) = session.update(peer).await? {
+//!     println!("Relaying through {} (slower leg: {:?})", selection.relay, selection.max_leg());
+//! }
+//! println!("{:?}", session.diagnostics());
+//! # Ok(())
+//! # }
+//! ```
+use crate::prelude::*;
+use citadel_io::TcpStream;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::io::AsyncWriteExt;
+
+/// The number of round trips measured per relay, of which the fastest is kept
+const SAMPLES: usize = 3;
+/// Relays that take longer to answer are considered unreachable
+const PROBE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The round-trip time from a single node to each reachable relay
+#[derive(Serialize, Deserialize, Clone, Debug, Default, Eq, PartialEq)]
+pub struct RelayLatencies {
+    pub rtts: HashMap<SocketAddr, Duration>,
+}
+
+/// The relay chosen for a pair of peers, alongside the latency of each leg
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct RelaySelection {
+    /// The address of the relay
+    pub relay: SocketAddr,
+    /// The round-trip time from the local node to the relay
+    pub local_rtt: Duration,
+    /// The round-trip time from the peer to the relay
+    pub peer_rtt: Duration,
+}
+
+impl RelaySelection {
+    /// The round-trip time of the slower leg, which bounds the latency of the relayed connection
+    pub fn max_leg(&self) -> Duration {
+        self.local_rtt.max(self.peer_rtt)
+    }
+}
+
+/// Measures the round-trip time to each relay by timing the TCP handshake, concurrently across
+/// relays. Each probe connection is shut down as soon as it is established, such that the relay
+/// observes the end of the stream instead of holding a half-open session until it times out.
+/// Relays that cannot be reached are omitted
+pub async fn measure_relays(relays: &[SocketAddr]) -> RelayLatencies {
+    let rtts = futures::future::join_all(relays.iter().map(|relay| async move {
+        let mut best: Option<Duration> = None;
+        for _ in 0..SAMPLES {
+            let start = Instant::now();
+            match tokio::time::timeout(PROBE_TIMEOUT, TcpStream::connect(relay)).await {
+                Ok(Ok(mut stream)) => {
+                    let rtt = start.elapsed();
+                    best = Some(best.map(|best| best.min(rtt)).unwrap_or(rtt));
+                    // the FIN ends the stream on the relay's end
+                    let _ = stream.shutdown().await;
+                }
+                _ => {
+                    log::warn!(target: "citadel", "Relay {relay} did not answer the latency probe");
+                    break;
+                }
+            }
+        }
+
+        best.map(|rtt| (*relay, rtt))
+    }))
+    .await;
+
+    RelayLatencies {
+        rtts: rtts.into_iter().flatten().collect(),
+    }
+}
+
+/// Returns the relay reachable by both peers that minimizes the latency of the slower leg. Ties
+/// are broken by the faster leg
+pub fn select_relay(local: &RelayLatencies, peer: &RelayLatencies) -> Option<RelaySelection> {
+    local
+        .rtts
+        .iter()
+        .filter_map(|(relay, local_rtt)| {
+            peer.rtts.get(relay).map(|peer_rtt| RelaySelection {
+                relay: *relay,
+                local_rtt: *local_rtt,
+                peer_rtt: *peer_rtt,
+            })
+        })
+        .min_by_key(|selection| {
+            (
+                selection.max_leg(),
+                selection.local_rtt.min(selection.peer_rtt),
+                selection.relay,
+            )
+        })
+}
+
+/// Tracks the relay of a session across repeated measurements
+#[derive(Clone, Debug)]
+pub struct RelaySelector {
+    current: Option<RelaySelection>,
+    switch_threshold: Duration,
+}
+
+impl Default for RelaySelector {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(20))
+    }
+}
+
+impl RelaySelector {
+    /// Creates a selector that only switches relays once the slower leg improves by at least
+    /// `switch_threshold`
+    pub fn new(switch_threshold: Duration) -> Self {
+        Self {
+            current: None,
+            switch_threshold,
+        }
+    }
+
+    /// The relay currently in use, alongside the latencies it was last measured at
+    pub fn current(&self) -> Option<&RelaySelection> {
+        self.current.as_ref()
+    }
+
+    /// Updates the selection given fresh measurements from both peers. Returns the new selection
+    /// if the session should switch relays, or `None` if the current relay remains the best
+    /// choice. If the current relay is no longer reachable by either peer, the best remaining
+    /// relay is chosen regardless of the threshold
+    pub fn update(
+        &mut self,
+        local: &RelayLatencies,
+        peer: &RelayLatencies,
+    ) -> Option<RelaySelection> {
+        let best = select_relay(local, peer);
+        let current = self.current.and_then(|current| {
+            let local_rtt = local.rtts.get(&current.relay)?;
+            let peer_rtt = peer.rtts.get(&current.relay)?;
+            Some(RelaySelection {
+                relay: current.relay,
+                local_rtt: *local_rtt,
+                peer_rtt: *peer_rtt,
+            })
+        });
+
+        match (current, best) {
+            (Some(current), Some(best))
+                if best.relay == current.relay
+                    || best.max_leg() + self.switch_threshold > current.max_leg() =>
+            {
+                // keep the current relay, refreshing its latencies
+                self.current = Some(current);
+                None
+            }
+
+            (_, best) => {
+                if best.is_none() {
+                    log::warn!(target: "citadel", "No relay is reachable by both peers");
+                }
+
+                self.current = best;
+                best
+            }
+        }
+    }
+}
+
+/// Establishes and tears down the connections of a session through a given relay
+#[async_trait]
+pub trait RelayConnector: Send + Sync + 'static {
+    /// Whatever the session must hold on to while relayed, e.g., the receive half of its channel
+    type Connection: Send;
+
+    /// Connects the session through `relay`
+    async fn connect(&self, relay: SocketAddr) -> Result<Self::Connection, NetworkError>;
+
+    /// Disconnects the session from `relay` once it moved to another relay
+    async fn disconnect(
+        &self,
+        relay: SocketAddr,
+        connection: Self::Connection,
+    ) -> Result<(), NetworkError>;
+}
+
+/// The relay selection of a session, as last observed by its [`RelaySession`]
+#[derive(Clone, Debug, Default)]
+pub struct RelayDiagnostics {
+    /// The latest measurements of the local node
+    pub local: RelayLatencies,
+    /// The latest measurements received from the peer
+    pub peer: RelayLatencies,
+    /// The relay the session is connected through
+    pub current: Option<RelaySelection>,
+    /// The number of times the session switched relays, including the initial connection
+    pub switches: u64,
+    /// The reason the last attempt to switch relays failed, if it did
+    pub last_error: Option<String>,
+}
+
+/// Keeps a session connected through the relay minimizing the latency of the slower leg,
+/// switching relays mid-session as measurements change
+pub struct RelaySession<C: RelayConnector> {
+    relays: Vec<SocketAddr>,
+    connector: C,
+    selector: RelaySelector,
+    connection: Option<(SocketAddr, C::Connection)>,
+    diagnostics: RelayDiagnostics,
+}
+
+impl<C: RelayConnector> RelaySession<C> {
+    pub fn new(relays: Vec<SocketAddr>, connector: C, selector: RelaySelector) -> Self {
+        Self {
+            relays,
+            connector,
+            selector,
+            connection: None,
+            diagnostics: RelayDiagnostics::default(),
+        }
+    }
+
+    /// Measures the latency from the local node to each relay. The result should be sent to the
+    /// peer, which passes it to its own [`RelaySession::update`]
+    pub async fn measure(&mut self) -> RelayLatencies {
+        let local = measure_relays(&self.relays).await;
+        self.diagnostics.local = local.clone();
+        local
+    }
+
+    /// Given the latest measurements of the peer, switches the session to another relay if it
+    /// improves the slower leg by at least the switch threshold. Returns the new selection if the
+    /// session switched. If connecting through the new relay fails, the session remains on its
+    /// current relay and the error is returned
+    pub async fn update(
+        &mut self,
+        peer: RelayLatencies,
+    ) -> Result<Option<RelaySelection>, NetworkError> {
+        self.diagnostics.peer = peer;
+        let previous = self.selector.clone();
+        let selection = self
+            .selector
+            .update(&self.diagnostics.local, &self.diagnostics.peer);
+        self.diagnostics.current = self.selector.current().copied();
+
+        let selection = match selection {
+            Some(selection) => selection,
+            None => return Ok(None),
+        };
+
+        // the session may still be connected through the relay, e.g., if it was briefly
+        // unreachable by either peer
+        if matches!(&self.connection, Some((relay, _)) if *relay == selection.relay) {
+            return Ok(None);
+        }
+
+        match self.connector.connect(selection.relay).await {
+            Ok(connection) => {
+                let previous = self.connection.replace((selection.relay, connection));
+                if let Some((relay, connection)) = previous {
+                    if let Err(err) = self.connector.disconnect(relay, connection).await {
+                        log::warn!(target: "citadel", "Unable to disconnect from relay {relay}: {err}");
+                    }
+                }
+
+                log::info!(target: "citadel", "Switched to relay {} (slower leg: {:?})", selection.relay, selection.max_leg());
+                self.diagnostics.switches += 1;
+                self.diagnostics.last_error = None;
+                Ok(Some(selection))
+            }
+
+            Err(err) => {
+                log::warn!(target: "citadel", "Unable to switch to relay {}: {err}", selection.relay);
+                self.selector = previous;
+                self.diagnostics.current = self.selector.current().copied();
+                self.diagnostics.last_error = Some(err.to_string());
+                Err(err)
+            }
+        }
+    }
+
+    /// The connection through the current relay
+    pub fn connection(&self) -> Option<&C::Connection> {
+        self.connection.as_ref().map(|(_, connection)| connection)
+    }
+
+    pub fn diagnostics(&self) -> &RelayDiagnostics {
+        &self.diagnostics
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prelude::*;
+    use crate::relay_selection::{
+        measure_relays, select_relay, RelayConnector, RelayLatencies, RelaySelector, RelaySession,
+    };
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    fn latencies(rtts: &[(SocketAddr, u64)]) -> RelayLatencies {
+        RelayLatencies {
+            rtts: rtts
+                .iter()
+                .map(|(relay, ms)| (*relay, Duration::from_millis(*ms)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_selection_minimizes_slower_leg() {
+        let us: SocketAddr = "10.0.0.1:25021".parse().unwrap();
+        let eu: SocketAddr = "10.0.0.2:25021".parse().unwrap();
+        let asia: SocketAddr = "10.0.0.3:25021".parse().unwrap();
+
+        // the nearest relay of the local node is far from the peer
+        let local = latencies(&[(us, 10), (eu, 80), (asia, 150)]);
+        let peer = latencies(&[(us, 120), (eu, 40)]);
+        let selection = select_relay(&local, &peer).unwrap();
+        assert_eq!(selection.relay, eu);
+        assert_eq!(selection.max_leg(), Duration::from_millis(80));
+
+        let mut selector = RelaySelector::new(Duration::from_millis(20));
+        assert_eq!(selector.update(&local, &peer), Some(selection));
+
+        // a marginal improvement does not switch relays
+        let peer = latencies(&[(us, 70), (eu, 40)]);
+        assert_eq!(selector.update(&local, &peer), None);
+        assert_eq!(selector.current().unwrap().relay, eu);
+
+        // a significant improvement does
+        let peer = latencies(&[(us, 30), (eu, 40)]);
+        assert_eq!(selector.update(&local, &peer).unwrap().relay, us);
+
+        // losing the current relay switches regardless of the threshold
+        let local = latencies(&[(eu, 80)]);
+        assert_eq!(selector.update(&local, &peer).unwrap().relay, eu);
+
+        // no shared relay clears the selection
+        let peer = latencies(&[(asia, 10)]);
+        assert_eq!(selector.update(&local, &peer), None);
+        assert!(selector.current().is_none());
+    }
+
+    #[derive(Default)]
+    struct RecordingConnector {
+        events: Arc<Mutex<Vec<String>>>,
+        unreachable: Option<SocketAddr>,
+    }
+
+    #[async_trait]
+    impl RelayConnector for RecordingConnector {
+        type Connection = SocketAddr;
+
+        async fn connect(&self, relay: SocketAddr) -> Result<Self::Connection, NetworkError> {
+            if Some(relay) == self.unreachable {
+                return Err(NetworkError::msg("Relay unreachable"));
+            }
+
+            self.events.lock().unwrap().push(format!("connect {relay}"));
+            Ok(relay)
+        }
+
+        async fn disconnect(
+            &self,
+            relay: SocketAddr,
+            connection: Self::Connection,
+        ) -> Result<(), NetworkError> {
+            assert_eq!(relay, connection);
+            self.events
+                .lock()
+                .unwrap()
+                .push(format!("disconnect {relay}"));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_session_switches_relays_mid_session() {
+        let us: SocketAddr = "10.0.0.1:25021".parse().unwrap();
+        let eu: SocketAddr = "10.0.0.2:25021".parse().unwrap();
+        let asia: SocketAddr = "10.0.0.3:25021".parse().unwrap();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let connector = RecordingConnector {
+            events: events.clone(),
+            unreachable: Some(asia),
+        };
+
+        let mut session =
+            RelaySession::new(vec![us, eu, asia], connector, RelaySelector::default());
+        session.diagnostics.local = latencies(&[(us, 10), (eu, 80), (asia, 5)]);
+
+        let selection = session
+            .update(latencies(&[(us, 120), (eu, 40)]))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(selection.relay, eu);
+        assert_eq!(session.connection(), Some(&eu));
+
+        // the new relay is connected before the old relay is disconnected
+        let selection = session
+            .update(latencies(&[(us, 30), (eu, 40)]))
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(selection.relay, us);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                format!("connect {eu}"),
+                format!("connect {us}"),
+                format!("disconnect {eu}")
+            ]
+        );
+
+        // a failed switch keeps the session on its current relay
+        assert!(session
+            .update(latencies(&[(us, 300), (asia, 5)]))
+            .await
+            .is_err());
+        assert_eq!(session.connection(), Some(&us));
+
+        let diagnostics = session.diagnostics();
+        assert_eq!(diagnostics.current.unwrap().relay, us);
+        assert_eq!(diagnostics.switches, 2);
+        assert!(diagnostics.last_error.is_some());
+        assert_eq!(diagnostics.peer, latencies(&[(us, 300), (asia, 5)]));
+    }
+
+    #[tokio::test]
+    async fn test_probes_close_their_connections() {
+        use tokio::io::AsyncReadExt;
+        let listener = citadel_io::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let relay = listener.local_addr().unwrap();
+        let accepted = citadel_io::spawn(async move {
+            let mut closed = 0;
+            while closed < 3 {
+                let (mut stream, _) = listener.accept().await.unwrap();
+                // each probe must end its stream, rather than leave it half-open
+                let mut buf = [0u8; 16];
+                assert_eq!(stream.read(&mut buf).await.unwrap(), 0);
+                closed += 1;
+            }
+        });
+
+        let measured = measure_relays(&[relay]).await;
+        assert!(measured.rtts.contains_key(&relay));
+        tokio::time::timeout(Duration::from_secs(5), accepted)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
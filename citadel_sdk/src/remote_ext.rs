@@ -2,7 +2,7 @@ use crate::prefabs::ClientServerRemote;
 use crate::prelude::results::{PeerConnectSuccess, PeerRegisterStatus};
 use crate::prelude::*;
 use crate::remote_ext::remote_specialization::PeerRemote;
-use crate::remote_ext::results::HyperlanPeer;
+use crate::remote_ext::results::{HyperlanPeer, PeerListSubscription};
 use crate::remote_ext::user_ids::{SymmetricIdentifierHandleRef, TargetLockedRemote};
 
 use citadel_proto::auth::AuthenticationRequest;
@@ -10,6 +10,13 @@ use futures::StreamExt;
 use std::path::PathBuf;
 use std::time::Duration;
 
+/// How long [`ProtocolRemoteTargetExt::rekey_now`] waits before retrying when a re-key is already
+/// in progress
+const REKEY_RETRY_INTERVAL: Duration = Duration::from_millis(50);
+/// How long [`ProtocolRemoteTargetExt::rekey_now`] retries before giving up on re-keys that keep
+/// being preempted by others
+const REKEY_RETRY_TIMEOUT: Duration = Duration::from_secs(10);
+
 pub(crate) mod user_ids {
     use crate::prelude::*;
     use std::ops::{Deref, DerefMut};
@@ -123,6 +130,8 @@ pub struct ConnectionSuccess {
     /// Contains the Google auth minted at the central server (if the central server enabled it), as well as any other services enabled by the central server
     pub services: ServicesObject,
     pub cid: u64,
+    /// The time spent in each stage of establishing the connection
+    pub timings: ConnectTimings,
 }
 
 /// Contains the elements entailed by a successful registration
@@ -166,6 +175,40 @@ pub trait ProtocolRemoteExt: Remote {
         }
     }
 
+    /// Registers with credentials the server validates against its directory, such as LDAP or
+    /// Active Directory, instead of storing the hash of the password. Afterwards, connecting
+    /// with the same username and password validates them against the directory anew
+    async fn register_delegated<
+        T: std::net::ToSocketAddrs + Send,
+        R: Into<String> + Send,
+        V: Into<String> + Send,
+        K: Into<SecBuffer> + Send,
+    >(
+        &mut self,
+        addr: T,
+        full_name: R,
+        username: V,
+        password: K,
+        default_security_settings: SessionSecuritySettings,
+    ) -> Result<RegisterSuccess, NetworkError> {
+        let creds = ProposedCredentials::new_delegated(full_name, username, password.into());
+        let register_request = NodeRequest::RegisterToHypernode(RegisterToHypernode {
+            remote_addr: addr
+                .to_socket_addrs()?
+                .next()
+                .ok_or(NetworkError::InternalError("Invalid socket addr"))?,
+            proposed_credentials: creds,
+            static_security_settings: default_security_settings,
+        });
+
+        match map_errors(self.send_callback(register_request).await?)? {
+            NodeResult::RegisterOkay(RegisterOkay { .. }) => Ok(RegisterSuccess {}),
+            res => Err(NetworkError::msg(format!(
+                "An unexpected response occurred: {res:?}"
+            ))),
+        }
+    }
+
     /// Registers using the default settings. The default uses No Google FCM keys and the default session security settings
     /// Returns a ticket which is used to uniquely identify the request in the protocol
     async fn register_with_defaults<
@@ -221,16 +264,19 @@ pub trait ProtocolRemoteExt: Remote {
                 welcome_message: _,
                 channel,
                 udp_rx_opt: udp_channel_rx,
+                timings,
             }) => Ok(ConnectionSuccess {
                 channel,
                 udp_channel_rx,
                 services,
                 cid,
+                timings,
             }),
             NodeResult::ConnectFail(ConnectFail {
                 ticket: _,
                 cid_opt: _,
                 error_message: err,
+                redirect: _,
             }) => Err(NetworkError::Generic(err)),
             res => Err(NetworkError::msg(format!(
                 "[connect] An unexpected response occurred: {res:?}"
@@ -387,6 +433,39 @@ pub trait ProtocolRemoteExt: Remote {
         Err(NetworkError::InternalError("Internal kernel stream died"))
     }
 
+    /// Subscribes the local user to changes in the peer list of the server, removing the need to
+    /// poll [`Self::get_hyperlan_peers`] and [`Self::get_hyperlan_mutual_peers`]. The server sends
+    /// deltas until the session ends. Deltas received after the subscription is dropped are passed
+    /// to the kernel
+    async fn subscribe_peer_list<T: Into<UserIdentifier> + Send>(
+        &mut self,
+        local_user: T,
+    ) -> Result<PeerListSubscription, NetworkError> {
+        let local_cid = self.get_implicated_cid(local_user).await?;
+        let command = NodeRequest::PeerCommand(PeerCommand {
+            implicated_cid: local_cid,
+            command: PeerSignal::SubscribePeerList(
+                HypernodeConnectionType::HyperLANPeerToHyperLANServer(local_cid),
+            ),
+        });
+
+        let mut stream = self.send_callback_subscription(command).await?;
+
+        // wait for the server to acknowledge the subscription, such that no deltas are missed
+        // once this function returns
+        while let Some(status) = stream.next().await {
+            if let NodeResult::PeerEvent(PeerEvent {
+                event: PeerSignal::SignalReceived(_),
+                ticket: _,
+            }) = map_errors(status)?
+            {
+                return Ok(PeerListSubscription { inner: stream });
+            }
+        }
+
+        Err(NetworkError::InternalError("Internal kernel stream died"))
+    }
+
     #[doc(hidden)]
     fn remote_ref_mut(&mut self) -> &mut NodeRemote;
 
@@ -403,6 +482,71 @@ pub trait ProtocolRemoteExt: Remote {
     }
 }
 
+fn prepare_virtual_directory<R: Into<PathBuf>>(virtual_dir: R) -> Result<PathBuf, NetworkError> {
+    let virtual_dir = prepare_virtual_path(virtual_dir.into());
+    validate_virtual_directory(&virtual_dir)
+        .map_err(|err| NetworkError::Generic(err.into_string()))?;
+    Ok(virtual_dir)
+}
+
+/// Drives the sender's side of an object transfer to completion
+async fn await_outbound_transfer(result: NodeResult) -> Result<(), NetworkError> {
+    match map_errors(result)? {
+        NodeResult::ObjectTransferHandle(ObjectTransferHandle {
+            ticket: _ticket,
+            mut handle,
+        }) => {
+            while let Some(res) = handle.next().await {
+                log::trace!(target: "citadel", "Client received RES {:?}", res);
+                match res {
+                    ObjectTransferStatus::TransferComplete => {
+                        return Ok(());
+                    }
+
+                    ObjectTransferStatus::Cancelled => {
+                        return Err(NetworkError::msg("File transfer was cancelled"));
+                    }
+
+                    _ => {}
+                }
+            }
+        }
+
+        res => {
+            log::error!(target: "citadel", "Invalid NodeResult for FileTransfer request received: {:?}", res)
+        }
+    }
+
+    Err(NetworkError::InternalError("File transfer stream died"))
+}
+
+/// Waits for a file pulled from the RE-VFS to be received, returning the local path it was saved to
+pub(crate) async fn await_pulled_object(
+    mut handle: ObjectTransferHandler,
+) -> Result<PathBuf, NetworkError> {
+    let mut local_path = None;
+    while let Some(res) = handle.next().await {
+        log::trace!(target: "citadel", "Client received RES {:?}", res);
+        match res {
+            ObjectTransferStatus::ReceptionBeginning(path, _) => local_path = Some(path),
+
+            ObjectTransferStatus::TransferComplete | ObjectTransferStatus::ReceptionComplete => {
+                break;
+            }
+
+            ObjectTransferStatus::Cancelled => {
+                return Err(NetworkError::msg("File transfer was cancelled"));
+            }
+
+            ObjectTransferStatus::Fail(err) => return Err(NetworkError::Generic(err)),
+
+            _ => {}
+        }
+    }
+
+    local_path.ok_or(NetworkError::InternalError("Local path never loaded"))
+}
+
 pub(crate) fn map_errors(result: NodeResult) -> Result<NodeResult, NetworkError> {
     match result {
         NodeResult::InternalServerError(InternalServerError {
@@ -457,25 +601,50 @@ pub trait ProtocolRemoteTargetExt: TargetLockedRemote {
                 transfer_type,
             }))
             .await?;
-        match map_errors(result)? {
-            NodeResult::ObjectTransferHandle(ObjectTransferHandle {
-                ticket: _ticket,
-                mut handle,
-            }) => {
-                while let Some(res) = handle.next().await {
-                    log::trace!(target: "citadel", "Client received RES {:?}", res);
-                    if let ObjectTransferStatus::TransferComplete = res {
-                        return Ok(());
-                    }
-                }
-            }
+        await_outbound_transfer(result).await
+    }
 
-            res => {
-                log::error!(target: "citadel", "Invalid NodeResult for FileTransfer request received: {:?}", res)
-            }
-        }
+    /// Sends a file that the target may already hold a previous version of. Only the blocks that
+    /// changed since the previous version are transmitted. If the target does not hold a previous
+    /// version under the same name, the whole file is sent
+    async fn send_file_delta<T: ObjectSource>(&mut self, source: T) -> Result<(), NetworkError> {
+        let implicated_cid = self.user().get_implicated_cid();
+        let user = *self.user();
+        let remote = self.remote();
+
+        let result = remote
+            .send_callback(NodeRequest::SendObjectDelta(SendObjectDelta {
+                source: Box::new(source),
+                chunk_size: None,
+                implicated_cid,
+                v_conn_type: user,
+            }))
+            .await?;
+        await_outbound_transfer(result).await
+    }
+
+    /// Sends a file whose content the target may have already received, e.g., a build artifact.
+    /// Only the hashes of the chunks held in the target's chunk cache are transmitted. If the target
+    /// does not cache chunks, the whole file is sent
+    async fn send_file_deduplicated<T: ObjectSource>(
+        &mut self,
+        source: T,
+    ) -> Result<(), NetworkError> {
+        let implicated_cid = self.user().get_implicated_cid();
+        let user = *self.user();
+        let remote = self.remote();
 
-        Err(NetworkError::InternalError("File transfer stream died"))
+        let result = remote
+            .send_callback(NodeRequest::SendObjectDeduplicated(
+                SendObjectDeduplicated {
+                    source: Box::new(source),
+                    chunk_size: None,
+                    implicated_cid,
+                    v_conn_type: user,
+                },
+            ))
+            .await?;
+        await_outbound_transfer(result).await
     }
 
     /// Sends a file to the provided target using the default chunking size
@@ -533,10 +702,53 @@ pub trait ProtocolRemoteTargetExt: TargetLockedRemote {
         transfer_security_level: SecurityLevel,
         delete_on_pull: bool,
     ) -> Result<PathBuf, NetworkError> {
+        let handle = self
+            .remote_encrypted_virtual_filesystem_pull_handle(
+                virtual_directory.into(),
+                None,
+                transfer_security_level,
+                delete_on_pull,
+            )
+            .await?;
+        await_pulled_object(handle).await
+    }
+
+    /// Pulls a virtual file from the RE-VFS of `owner_cid`. The owner must have granted this node
+    /// access to the file via [`Self::remote_encrypted_virtual_filesystem_grant_access`]. Since
+    /// only the owner holds the local key the file was encrypted with, the file is received as
+    /// stored on the remote node
+    async fn remote_encrypted_virtual_filesystem_pull_shared<R: Into<PathBuf> + Send>(
+        &mut self,
+        owner_cid: u64,
+        virtual_directory: R,
+        transfer_security_level: SecurityLevel,
+    ) -> Result<PathBuf, NetworkError> {
+        let handle = self
+            .remote_encrypted_virtual_filesystem_pull_handle(
+                virtual_directory.into(),
+                Some(owner_cid),
+                transfer_security_level,
+                false,
+            )
+            .await?;
+        await_pulled_object(handle).await
+    }
+
+    /// Begins pulling a virtual file from the RE-VFS of `owner_cid`, or, the local node's if None.
+    /// Returns the handle of the inbound transfer, whose [`ObjectTransferStatus::ReceptionBeginning`]
+    /// status contains the path the file is written to as it arrives
+    async fn remote_encrypted_virtual_filesystem_pull_handle(
+        &mut self,
+        virtual_path: PathBuf,
+        owner_cid: Option<u64>,
+        transfer_security_level: SecurityLevel,
+        delete_on_pull: bool,
+    ) -> Result<ObjectTransferHandler, NetworkError> {
         let request = NodeRequest::PullObject(PullObject {
             v_conn: *self.user(),
-            virtual_dir: virtual_directory.into(),
+            virtual_dir: virtual_path,
             delete_on_pull,
+            owner_cid,
             transfer_security_level,
         });
 
@@ -545,22 +757,20 @@ pub trait ProtocolRemoteTargetExt: TargetLockedRemote {
                 ticket: _ticket,
                 mut handle,
             }) => {
-                let mut local_path = None;
-                while let Some(res) = handle.next().await {
-                    log::trace!(target: "citadel", "Client received RES {:?}", res);
-                    match res {
-                        ObjectTransferStatus::ReceptionBeginning(path, _) => {
-                            local_path = Some(path)
-                        }
-                        ObjectTransferStatus::TransferComplete => {
-                            break;
-                        }
-
-                        _ => {}
-                    }
+                // files pulled from another owner arrive as standard transfers
+                if !handle.is_revfs_pull {
+                    handle
+                        .accept()
+                        .map_err(|err| NetworkError::msg(err.into_string()))?;
                 }
 
-                Ok(local_path.ok_or(NetworkError::InternalError("Local path never loaded"))?)
+                Ok(handle)
+            }
+
+            NodeResult::ReVFS(result) => {
+                Err(NetworkError::Generic(result.error_message.unwrap_or_else(
+                    || "Object was not transferred".to_string(),
+                )))
             }
 
             res => {
@@ -578,10 +788,35 @@ pub trait ProtocolRemoteTargetExt: TargetLockedRemote {
     async fn remote_encrypted_virtual_filesystem_delete<R: Into<PathBuf> + Send>(
         &mut self,
         virtual_directory: R,
+    ) -> Result<(), NetworkError> {
+        self.remote_encrypted_virtual_filesystem_delete_from(None, virtual_directory.into())
+            .await
+    }
+
+    /// Deletes a file from the RE-VFS of `owner_cid`. The owner must have granted this node
+    /// access to the file via [`Self::remote_encrypted_virtual_filesystem_grant_access`]
+    async fn remote_encrypted_virtual_filesystem_delete_shared<R: Into<PathBuf> + Send>(
+        &mut self,
+        owner_cid: u64,
+        virtual_directory: R,
+    ) -> Result<(), NetworkError> {
+        self.remote_encrypted_virtual_filesystem_delete_from(
+            Some(owner_cid),
+            virtual_directory.into(),
+        )
+        .await
+    }
+
+    /// Deletes a file from the RE-VFS of `owner_cid`, or, the local node's if None
+    async fn remote_encrypted_virtual_filesystem_delete_from(
+        &mut self,
+        owner_cid: Option<u64>,
+        virtual_path: PathBuf,
     ) -> Result<(), NetworkError> {
         let request = NodeRequest::DeleteObject(DeleteObject {
             v_conn: *self.user(),
-            virtual_dir: virtual_directory.into(),
+            virtual_dir: virtual_path,
+            owner_cid,
             security_level: Default::default(),
         });
 
@@ -597,67 +832,329 @@ pub trait ProtocolRemoteTargetExt: TargetLockedRemote {
         }
     }
 
-    /// Connects to the peer with custom settings
-    async fn connect_to_peer_custom(
+    /// Executes a directory operation on the RE-VFS. Returns the listed entries, if any
+    async fn remote_encrypted_virtual_filesystem_directory_operation(
         &mut self,
-        session_security_settings: SessionSecuritySettings,
-        udp_mode: UdpMode,
-    ) -> Result<PeerConnectSuccess, NetworkError> {
-        let implicated_cid = self.user().get_implicated_cid();
-        let peer_target = self.try_as_peer_connection().await?;
+        operation: ReVFSDirectoryOperation,
+    ) -> Result<Option<Vec<VirtualDirEntry>>, NetworkError> {
+        let request = NodeRequest::ReVFSDirectory(ReVFSDirectory {
+            v_conn: *self.user(),
+            operation,
+            security_level: Default::default(),
+        });
 
-        let mut stream = self
-            .remote()
-            .send_callback_subscription(NodeRequest::PeerCommand(PeerCommand {
-                implicated_cid,
-                command: PeerSignal::PostConnect(
-                    peer_target,
-                    None,
-                    None,
-                    session_security_settings,
-                    udp_mode,
-                ),
-            }))
-            .await?;
+        let response = map_errors(self.remote().send_callback(request).await?)?;
+        if let NodeResult::ReVFS(result) = response {
+            if let Some(error) = result.error_message {
+                Err(NetworkError::Generic(error))
+            } else {
+                Ok(result.entries)
+            }
+        } else {
+            Err(NetworkError::InternalError("Invalid NodeRequest response"))
+        }
+    }
 
-        while let Some(status) = stream.next().await {
-            match map_errors(status)? {
-                NodeResult::PeerChannelCreated(PeerChannelCreated {
-                    ticket: _,
-                    channel,
-                    udp_rx_opt,
-                }) => {
-                    let username = self.target_username().cloned();
-                    let remote = PeerRemote {
-                        inner: self.remote().clone(),
-                        peer: peer_target.as_virtual_connection(),
-                        username,
-                    };
+    /// Creates a directory, and any missing parents, inside the RE-VFS
+    async fn remote_encrypted_virtual_filesystem_create_dir<R: Into<PathBuf> + Send>(
+        &mut self,
+        virtual_dir: R,
+    ) -> Result<(), NetworkError> {
+        let virtual_dir = prepare_virtual_directory(virtual_dir)?;
+        self.remote_encrypted_virtual_filesystem_directory_operation(
+            ReVFSDirectoryOperation::CreateDirectory { virtual_dir },
+        )
+        .await
+        .map(|_| ())
+    }
 
-                    return Ok(PeerConnectSuccess {
-                        remote,
-                        channel,
-                        udp_rx_opt,
-                        incoming_object_transfer_handles: None,
-                    });
-                }
+    /// Lists the files and directories directly inside `virtual_dir` of the RE-VFS
+    async fn remote_encrypted_virtual_filesystem_list<R: Into<PathBuf> + Send>(
+        &mut self,
+        virtual_dir: R,
+    ) -> Result<Vec<VirtualDirEntry>, NetworkError> {
+        let virtual_dir = prepare_virtual_directory(virtual_dir)?;
+        self.remote_encrypted_virtual_filesystem_directory_operation(
+            ReVFSDirectoryOperation::List {
+                virtual_dir,
+                owner_cid: None,
+            },
+        )
+        .await
+        .map(Option::unwrap_or_default)
+    }
 
-                NodeResult::PeerEvent(PeerEvent {
-                    event: PeerSignal::PostConnect(_, _, Some(PeerResponse::Decline), ..),
-                    ..
-                }) => return Err(NetworkError::msg("Peer declined to connect")),
+    /// Lists a directory inside the RE-VFS of `owner_cid`. The owner must have granted this
+    /// node access to the directory via [`Self::remote_encrypted_virtual_filesystem_grant_access`]
+    async fn remote_encrypted_virtual_filesystem_list_shared<R: Into<PathBuf> + Send>(
+        &mut self,
+        owner_cid: u64,
+        virtual_dir: R,
+    ) -> Result<Vec<VirtualDirEntry>, NetworkError> {
+        let virtual_dir = prepare_virtual_directory(virtual_dir)?;
+        self.remote_encrypted_virtual_filesystem_directory_operation(
+            ReVFSDirectoryOperation::List {
+                virtual_dir,
+                owner_cid: Some(owner_cid),
+            },
+        )
+        .await
+        .map(Option::unwrap_or_default)
+    }
 
-                _ => {}
-            }
-        }
+    /// Grants `peer_cid` access to `virtual_dir`, and everything below it, inside the RE-VFS.
+    /// The peer must be registered to this node
+    async fn remote_encrypted_virtual_filesystem_grant_access<R: Into<PathBuf> + Send>(
+        &mut self,
+        virtual_dir: R,
+        peer_cid: u64,
+    ) -> Result<(), NetworkError> {
+        let virtual_dir = prepare_virtual_directory(virtual_dir)?;
+        self.remote_encrypted_virtual_filesystem_directory_operation(
+            ReVFSDirectoryOperation::GrantAccess {
+                virtual_dir,
+                peer_cid,
+            },
+        )
+        .await
+        .map(|_| ())
+    }
 
-        Err(NetworkError::InternalError("Internal kernel stream died"))
+    /// Revokes access previously granted to `peer_cid`
+    async fn remote_encrypted_virtual_filesystem_revoke_access<R: Into<PathBuf> + Send>(
+        &mut self,
+        virtual_dir: R,
+        peer_cid: u64,
+    ) -> Result<(), NetworkError> {
+        let virtual_dir = prepare_virtual_directory(virtual_dir)?;
+        self.remote_encrypted_virtual_filesystem_directory_operation(
+            ReVFSDirectoryOperation::RevokeAccess {
+                virtual_dir,
+                peer_cid,
+            },
+        )
+        .await
+        .map(|_| ())
     }
 
-    /// Connects to the target peer with default settings
-    async fn connect_to_peer(&mut self) -> Result<PeerConnectSuccess, NetworkError> {
-        self.connect_to_peer_custom(Default::default(), Default::default())
-            .await
+    /// Executes an operation on an object stored on the server. Access is enforced by the server
+    async fn shared_object_operation(
+        &mut self,
+        operation: SharedObjectOperation,
+    ) -> Result<(), NetworkError> {
+        let request = NodeRequest::SharedObject(SharedObject {
+            implicated_cid: self.user().get_implicated_cid(),
+            operation,
+            security_level: Default::default(),
+        });
+
+        let response = map_errors(self.remote().send_callback(request).await?)?;
+        if let NodeResult::SharedObject(result) = response {
+            if let Some(error) = result.error_message {
+                Err(NetworkError::Generic(error))
+            } else {
+                Ok(())
+            }
+        } else {
+            Err(NetworkError::InternalError("Invalid NodeRequest response"))
+        }
+    }
+
+    /// Shares an object previously sent to the server via [`Self::send_file`] with `grantee_cid`.
+    /// The grantee must be registered to this node, and is notified if online
+    async fn share_object<T: Into<String> + Send>(
+        &mut self,
+        name: T,
+        grantee_cid: u64,
+        permission: SharedObjectPermission,
+    ) -> Result<(), NetworkError> {
+        self.shared_object_operation(SharedObjectOperation::Grant {
+            name: name.into(),
+            grantee_cid,
+            permission,
+        })
+        .await
+    }
+
+    /// Revokes the access `grantee_cid` has to the object
+    async fn unshare_object<T: Into<String> + Send>(
+        &mut self,
+        name: T,
+        grantee_cid: u64,
+    ) -> Result<(), NetworkError> {
+        self.shared_object_operation(SharedObjectOperation::Revoke {
+            name: name.into(),
+            grantee_cid,
+        })
+        .await
+    }
+
+    /// Pulls an object owned by `owner_cid` from the server, returning the local path it was saved to
+    async fn pull_shared_object<T: Into<String> + Send>(
+        &mut self,
+        owner_cid: u64,
+        name: T,
+    ) -> Result<PathBuf, NetworkError> {
+        let request = NodeRequest::SharedObject(SharedObject {
+            implicated_cid: self.user().get_implicated_cid(),
+            operation: SharedObjectOperation::Read {
+                owner_cid,
+                name: name.into(),
+            },
+            security_level: Default::default(),
+        });
+
+        match map_errors(self.remote().send_callback(request).await?)? {
+            NodeResult::ObjectTransferHandle(ObjectTransferHandle {
+                ticket: _ticket,
+                mut handle,
+            }) => {
+                handle
+                    .accept()
+                    .map_err(|err| NetworkError::msg(err.into_string()))?;
+                let mut local_path = None;
+                while let Some(res) = handle.next().await {
+                    match res {
+                        ObjectTransferStatus::ReceptionBeginning(path, _) => {
+                            local_path = Some(path)
+                        }
+
+                        ObjectTransferStatus::ReceptionComplete => {
+                            break;
+                        }
+
+                        ObjectTransferStatus::Cancelled => {
+                            return Err(NetworkError::msg("File transfer was cancelled"));
+                        }
+
+                        _ => {}
+                    }
+                }
+
+                Ok(local_path.ok_or(NetworkError::InternalError("Local path never loaded"))?)
+            }
+
+            NodeResult::SharedObject(result) => {
+                Err(NetworkError::Generic(result.error_message.unwrap_or_else(
+                    || "Object was not transferred".to_string(),
+                )))
+            }
+
+            res => {
+                log::error!(target: "citadel", "Invalid NodeResult for SharedObject request received: {:?}", res);
+                Err(NetworkError::InternalError(
+                    "Received invalid response from protocol",
+                ))
+            }
+        }
+    }
+
+    /// Overwrites the object owned by `owner_cid` with `source`. The name of the source must
+    /// match the name of the shared object, and this node must have been granted [`SharedObjectPermission::ReadWrite`]
+    async fn write_shared_object<T: ObjectSource>(
+        &mut self,
+        owner_cid: u64,
+        source: T,
+    ) -> Result<(), NetworkError> {
+        if !matches!(self.user(), VirtualTargetType::LocalGroupServer(..)) {
+            return Err(NetworkError::InvalidRequest(
+                "Shared objects may only be written through the server",
+            ));
+        }
+
+        let name = source
+            .get_source_name()
+            .map_err(|err| NetworkError::Generic(err.into_string()))?;
+        self.shared_object_operation(SharedObjectOperation::Write { owner_cid, name })
+            .await?;
+        self.send_file(source).await
+    }
+
+    /// Deletes the object owned by `owner_cid` from the server
+    async fn delete_shared_object<T: Into<String> + Send>(
+        &mut self,
+        owner_cid: u64,
+        name: T,
+    ) -> Result<(), NetworkError> {
+        self.shared_object_operation(SharedObjectOperation::Delete {
+            owner_cid,
+            name: name.into(),
+        })
+        .await
+    }
+
+    /// Connects to the peer with custom settings
+    async fn connect_to_peer_custom(
+        &mut self,
+        session_security_settings: SessionSecuritySettings,
+        udp_mode: UdpMode,
+    ) -> Result<PeerConnectSuccess, NetworkError> {
+        let implicated_cid = self.user().get_implicated_cid();
+        let peer_target = self.try_as_peer_connection().await?;
+
+        let mut stream = self
+            .remote()
+            .send_callback_subscription(NodeRequest::PeerCommand(PeerCommand {
+                implicated_cid,
+                command: PeerSignal::PostConnect(
+                    peer_target,
+                    None,
+                    None,
+                    session_security_settings,
+                    udp_mode,
+                ),
+            }))
+            .await?;
+
+        while let Some(status) = stream.next().await {
+            match map_errors(status)? {
+                NodeResult::PeerChannelCreated(PeerChannelCreated {
+                    ticket: _,
+                    channel,
+                    udp_rx_opt,
+                }) => {
+                    let username = self.target_username().cloned();
+                    let remote = PeerRemote {
+                        inner: self.remote().clone(),
+                        peer: peer_target.as_virtual_connection(),
+                        username,
+                        sender: channel.sender(),
+                    };
+
+                    return Ok(PeerConnectSuccess {
+                        remote,
+                        channel,
+                        udp_rx_opt,
+                        incoming_object_transfer_handles: None,
+                    });
+                }
+
+                NodeResult::PeerEvent(PeerEvent {
+                    event: PeerSignal::PostConnect(_, _, Some(PeerResponse::Decline), ..),
+                    ..
+                }) => return Err(NetworkError::msg("Peer declined to connect")),
+
+                NodeResult::SecurityDowngrade(SecurityDowngrade {
+                    refused: true,
+                    reasons,
+                    ..
+                }) => {
+                    return Err(NetworkError::Generic(format!(
+                        "Refused to connect, since the peer weakened the security settings: {reasons:?}"
+                    )))
+                }
+
+                _ => {}
+            }
+        }
+
+        Err(NetworkError::InternalError("Internal kernel stream died"))
+    }
+
+    /// Connects to the target peer with default settings
+    async fn connect_to_peer(&mut self) -> Result<PeerConnectSuccess, NetworkError> {
+        self.connect_to_peer_custom(Default::default(), Default::default())
+            .await
     }
 
     /// Posts a registration request to a peer
@@ -861,6 +1358,128 @@ pub trait ProtocolRemoteTargetExt: TargetLockedRemote {
         ))
     }
 
+    /// Forces an immediate re-key, returning the new key matrix version once it is committed
+    /// by both endpoints. Unlike [`Self::rekey`], if a re-key is already executing, this waits
+    /// for it to finish and then begins a new one, ensuring that the returned version was
+    /// created after this function was called. Fails with [`NetworkError::Timeout`] if no re-key
+    /// could be started within 10 seconds
+    async fn rekey_now(&mut self) -> Result<u32, NetworkError> {
+        let deadline = tokio::time::Instant::now() + REKEY_RETRY_TIMEOUT;
+        loop {
+            if let Some(version) = self.rekey().await? {
+                return Ok(version);
+            }
+
+            if tokio::time::Instant::now() + REKEY_RETRY_INTERVAL > deadline {
+                return Err(NetworkError::Timeout(self.user().get_implicated_cid()));
+            }
+
+            log::trace!(target: "citadel", "Re-key already in progress; will retry");
+            tokio::time::sleep(REKEY_RETRY_INTERVAL).await;
+        }
+    }
+
+    /// Advertises `capabilities` to the server, returning the capabilities common to both nodes
+    /// once the server replies. Used after an upgrade to adopt new protocol features without
+    /// reconnecting
+    async fn renegotiate_protocol(
+        &mut self,
+        capabilities: ProtocolCapabilities,
+    ) -> Result<ProtocolCapabilities, NetworkError> {
+        let request = NodeRequest::RenegotiateProtocol(RenegotiateProtocol {
+            implicated_cid: self.user().get_implicated_cid(),
+            capabilities,
+        });
+        let mut subscription = self.remote().send_callback_subscription(request).await?;
+
+        while let Some(evt) = subscription.next().await {
+            match map_errors(evt)? {
+                NodeResult::ProtocolRenegotiated(result) => return Ok(result.capabilities),
+                _ => continue,
+            }
+        }
+
+        Err(NetworkError::InternalError(
+            "Protocol renegotiation ended unexpectedly",
+        ))
+    }
+
+    /// Sends an authenticated probe to the target, returning the round-trip time alongside the
+    /// time the target spent processing the probe. Works for both client to server and peer to
+    /// peer connections, and may be used for health checks or latency displays
+    async fn ping(&mut self) -> Result<PingResult, NetworkError> {
+        let request = NodeRequest::Ping(Ping {
+            implicated_cid: self.user().get_implicated_cid(),
+            v_conn_type: *self.user(),
+        });
+        let mut subscription = self.remote().send_callback_subscription(request).await?;
+
+        while let Some(evt) = subscription.next().await {
+            match map_errors(evt)? {
+                NodeResult::PingResult(result) => return Ok(result),
+                _ => continue,
+            }
+        }
+
+        Err(NetworkError::InternalError("Ping ended unexpectedly"))
+    }
+
+    /// Opens a channel named `name` to the target, multiplexed over the existing connection.
+    /// Each named channel is ordered independently of the others, and receives only the messages
+    /// sent through the channel of the same name by the target. Messages sent before the target
+    /// opens the channel are delivered once it does
+    async fn open_channel<T: Into<String> + Send>(
+        &mut self,
+        name: T,
+    ) -> Result<PeerChannel, NetworkError> {
+        let request = NodeRequest::OpenNamedChannel(OpenNamedChannel {
+            implicated_cid: self.user().get_implicated_cid(),
+            v_conn_type: *self.user(),
+            name: name.into(),
+        });
+        let mut subscription = self.remote().send_callback_subscription(request).await?;
+
+        while let Some(evt) = subscription.next().await {
+            match map_errors(evt)? {
+                NodeResult::NamedChannelOpened(result) => return Ok(result.channel),
+                _ => continue,
+            }
+        }
+
+        Err(NetworkError::InternalError(
+            "Opening the named channel ended unexpectedly",
+        ))
+    }
+
+    /// Enables UDP for a client to server connection established with [`UdpMode::Disabled`],
+    /// traversing NATs only once called. Returns the UDP channel once loaded
+    async fn enable_udp(&mut self) -> Result<UdpChannel, NetworkError> {
+        let implicated_cid = match self.user() {
+            VirtualTargetType::LocalGroupServer(implicated_cid) => *implicated_cid,
+            _ => {
+                return Err(NetworkError::msg(
+                    "UDP can only be enabled on demand for client to server connections",
+                ))
+            }
+        };
+
+        let request = NodeRequest::PeerCommand(PeerCommand {
+            implicated_cid,
+            command: PeerSignal::EnableUdp(HypernodeConnectionType::HyperLANPeerToHyperLANServer(
+                implicated_cid,
+            )),
+        });
+        let mut subscription = self.remote().send_callback_subscription(request).await?;
+
+        while let Some(evt) = subscription.next().await {
+            if let NodeResult::UdpChannelCreated(result) = map_errors(evt)? {
+                return Ok(result.channel);
+            }
+        }
+
+        Err(NetworkError::InternalError("Internal kernel stream died"))
+    }
+
     #[doc(hidden)]
     async fn try_as_peer_connection(&mut self) -> Result<PeerConnectionType, NetworkError> {
         let verified_return = |user: &VirtualTargetType| {
@@ -895,10 +1514,112 @@ pub trait ProtocolRemoteTargetExt: TargetLockedRemote {
 
 impl<T: TargetLockedRemote> ProtocolRemoteTargetExt for T {}
 
+/// The state of the ordered channel of a connection
+#[derive(Debug, Clone, Copy)]
+pub struct ConnectionStats {
+    pub channel_id: Ticket,
+    /// The number of bytes sent that the receiving application has yet to read
+    pub bytes_in_flight: u64,
+    /// The number of messages accepted by the channel that the session has yet to process
+    pub queue_depth: usize,
+    /// How long the most recent send waited before the session accepted the message
+    pub last_send_latency: Option<Duration>,
+}
+
+#[async_trait]
+/// Implemented by both [`ClientServerRemote`] and [`PeerRemote`], such that code may be written
+/// irrespective of whether the other endpoint is the central server or a peer. Files are sent
+/// through [`ProtocolRemoteTargetExt::send_file`]
+pub trait ConnectionHandle: ProtocolRemoteTargetExt + Sync {
+    /// The send half of the ordered channel of the connection
+    fn channel_sender(&self) -> &PeerChannelSendHalf;
+
+    /// The CID of the local user
+    fn local_cid(&self) -> u64 {
+        self.user().get_implicated_cid()
+    }
+
+    /// The CID of the other endpoint, or zero if the other endpoint is the central server
+    fn target_cid(&self) -> u64 {
+        self.user().get_target_cid()
+    }
+
+    fn is_peer_connection(&self) -> bool {
+        self.user().try_as_peer_connection().is_some()
+    }
+
+    /// Sends a message through the ordered channel of the connection
+    async fn send_message<T: Into<SecureProtocolPacket> + Send>(
+        &self,
+        message: T,
+    ) -> Result<(), NetworkError> {
+        self.channel_sender().send_message(message.into()).await
+    }
+
+    fn stats(&self) -> ConnectionStats {
+        let metrics = self.channel_sender().metrics();
+        ConnectionStats {
+            channel_id: self.channel_sender().channel_id(),
+            bytes_in_flight: metrics.bytes_in_flight,
+            queue_depth: metrics.queue_depth,
+            last_send_latency: metrics.last_send_latency,
+        }
+    }
+
+    /// The stats of the ordered channel over the last five minutes, at a resolution of one second
+    fn stats_history(&self) -> Vec<ChannelStatsSnapshot> {
+        self.channel_sender().stats_history()
+    }
+
+    /// The negotiated algorithms, security level, secrecy mode, transport and ratchet version of
+    /// the connection
+    fn security_info(&self) -> SecurityInfo {
+        self.channel_sender().security_info()
+    }
+
+    /// Disconnects from the other endpoint. Disconnecting from the central server also ends
+    /// every connection with peers
+    async fn disconnect(&mut self) -> Result<(), NetworkError> {
+        let implicated_cid = self.local_cid();
+        let request = if let Some(peer_conn) = self.user().try_as_peer_connection() {
+            NodeRequest::PeerCommand(PeerCommand {
+                implicated_cid,
+                command: PeerSignal::Disconnect(peer_conn, None),
+            })
+        } else {
+            NodeRequest::DisconnectFromHypernode(DisconnectFromHypernode {
+                implicated_cid,
+                v_conn_type: *self.user(),
+            })
+        };
+
+        self.remote().send(request).await.map(|_| ())
+    }
+}
+
+impl ConnectionHandle for ClientServerRemote {
+    fn channel_sender(&self) -> &PeerChannelSendHalf {
+        &self.sender
+    }
+}
+
+impl ConnectionHandle for PeerRemote {
+    fn channel_sender(&self) -> &PeerChannelSendHalf {
+        &self.sender
+    }
+}
+
 pub mod results {
-    use crate::prelude::{ObjectTransferHandler, PeerChannel, UdpChannel};
+    use crate::prelude::{
+        NodeResult, ObjectTransferHandler, PeerChannel, PeerEvent, PeerListDelta, PeerSignal,
+        UdpChannel,
+    };
     use crate::remote_ext::remote_specialization::PeerRemote;
+    use citadel_proto::kernel::kernel_communicator::KernelStreamSubscription;
     use citadel_proto::prelude::NetworkError;
+    use futures::task::{Context, Poll};
+    use futures::{Stream, StreamExt};
+    use std::pin::Pin;
     use tokio::sync::mpsc::UnboundedReceiver;
     use tokio::sync::oneshot::Receiver;
 
@@ -936,6 +1657,29 @@ pub mod results {
         pub cid: u64,
         pub is_online: bool,
     }
+
+    /// A stream of changes to the peer list of the server, created by
+    /// [`ProtocolRemoteExt::subscribe_peer_list`](crate::prelude::ProtocolRemoteExt::subscribe_peer_list)
+    pub struct PeerListSubscription {
+        pub(crate) inner: KernelStreamSubscription,
+    }
+
+    impl Stream for PeerListSubscription {
+        type Item = PeerListDelta;
+
+        fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            loop {
+                match futures::ready!(self.inner.poll_next_unpin(cx)) {
+                    Some(NodeResult::PeerEvent(PeerEvent {
+                        event: PeerSignal::PeerListChanged(delta),
+                        ticket: _,
+                    })) => return Poll::Ready(Some(delta)),
+                    Some(_) => continue,
+                    None => return Poll::Ready(None),
+                }
+            }
+        }
+    }
 }
 
 pub mod remote_specialization {
@@ -946,6 +1690,7 @@ pub mod remote_specialization {
         pub(crate) inner: NodeRemote,
         pub(crate) peer: VirtualTargetType,
         pub(crate) username: Option<String>,
+        pub(crate) sender: PeerChannelSendHalf,
     }
 
     impl TargetLockedRemote for PeerRemote {
@@ -970,6 +1715,7 @@ mod tests {
     use crate::prefabs::client::single_connection::SingleClientServerConnectionKernel;
     use crate::prelude::ProtocolRemoteTargetExt;
     use crate::prelude::*;
+    use citadel_proto::auth::AuthenticationRequest;
     use rstest::rstest;
     use std::net::SocketAddr;
     use std::str::FromStr;
@@ -1001,6 +1747,8 @@ mod tests {
             }) = map_errors(message)?
             {
                 let mut path = None;
+                let mut network_complete = false;
+                let mut verification_progress = None;
                 // accept the transfer
                 handle
                     .accept()
@@ -1009,8 +1757,20 @@ mod tests {
                 use futures::StreamExt;
                 while let Some(status) = handle.next().await {
                     match status {
+                        ObjectTransferStatus::NetworkComplete => {
+                            network_complete = true;
+                        }
+
+                        ObjectTransferStatus::VerificationTick(verified, total) => {
+                            verification_progress = Some((verified, total));
+                        }
+
                         ObjectTransferStatus::ReceptionComplete => {
                             log::trace!(target: "citadel", "Server has finished receiving the file!");
+                            assert!(network_complete);
+                            // every chunk must be verified before the reception completes
+                            let (verified, total) = verification_progress.unwrap();
+                            assert_eq!(verified, total);
                             let cmp = include_bytes!("../../resources/TheBridge.pdf");
                             let streamed_data =
                                 tokio::fs::read(path.clone().unwrap()).await.unwrap();
@@ -1115,4 +1875,1019 @@ mod tests {
         assert!(client_success.load(Ordering::Relaxed));
         assert!(server_success.load(Ordering::Relaxed));
     }
+
+    // written against the trait alone, such that it applies to peers just the same
+    async fn send_file_through_handle<C: ConnectionHandle>(
+        conn: &mut C,
+        expected_cid: u64,
+    ) -> Result<(), NetworkError> {
+        assert_eq!(conn.local_cid(), expected_cid);
+        assert_eq!(conn.target_cid(), 0);
+        assert!(!conn.is_peer_connection());
+        assert_eq!(conn.stats().bytes_in_flight, 0);
+        let security_info = conn.security_info();
+        assert!(matches!(
+            security_info.security_level,
+            SecurityLevel::Standard
+        ));
+        conn.send_file("../resources/TheBridge.pdf").await
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test]
+    async fn test_c2s_connection_handle() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let server_success = &Arc::new(AtomicBool::new(false));
+        let (server, server_addr) = server_info(server_success.clone());
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            Uuid::new_v4(),
+            server_addr,
+            |connection, mut remote| async move {
+                send_file_through_handle(&mut remote, connection.cid).await?;
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+        let _ = futures::future::try_join(server, client).await.unwrap();
+
+        assert!(client_success.load(Ordering::Relaxed));
+        assert!(server_success.load(Ordering::Relaxed));
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test]
+    async fn test_c2s_file_transfer_multipath() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let server_success = &Arc::new(AtomicBool::new(false));
+        let (server, server_addr) = server_info(server_success.clone());
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless(
+            Uuid::new_v4(),
+            server_addr,
+            UdpMode::Enabled,
+            Default::default(),
+            |channel, mut remote| async move {
+                // keep the UDP channel alive so that the transfer is striped across both streams
+                let _udp_channel = channel.udp_channel_rx.unwrap().await.unwrap();
+                remote
+                    .send_file_with_custom_opts(
+                        "../resources/TheBridge.pdf",
+                        32 * 1024,
+                        TransferType::FileTransfer,
+                    )
+                    .await
+                    .unwrap();
+
+                let metrics = match remote
+                    .inner
+                    .clone()
+                    .send_callback(NodeRequest::GetMultipathMetrics)
+                    .await?
+                {
+                    NodeResult::MultipathMetrics(result) => result.metrics,
+                    other => panic!("Unexpected response: {other:?}"),
+                };
+
+                log::info!(target: "citadel", "Multipath metrics: {metrics:?}");
+                // the transfer was striped across both paths
+                assert_eq!(metrics.transfers, 1);
+                assert!(metrics.packets_via_primary > 0);
+                assert!(metrics.packets_via_udp > 0);
+                assert!(metrics.packets_retransmitted <= metrics.packets_via_udp);
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        let joined = futures::future::try_join(server, client);
+
+        let _ = joined.await.unwrap();
+
+        assert!(client_success.load(Ordering::Relaxed));
+        assert!(server_success.load(Ordering::Relaxed));
+    }
+
+    /// Cancels the inbound transfer on the first reception tick
+    pub struct CancellingReceiverKernel(Option<NodeRemote>, Arc<AtomicBool>);
+
+    #[async_trait]
+    impl NetKernel for CancellingReceiverKernel {
+        fn load_remote(&mut self, node_remote: NodeRemote) -> Result<(), NetworkError> {
+            self.0 = Some(node_remote);
+            Ok(())
+        }
+
+        async fn on_start(&self) -> Result<(), NetworkError> {
+            Ok(())
+        }
+
+        async fn on_node_event_received(&self, message: NodeResult) -> Result<(), NetworkError> {
+            if let NodeResult::ObjectTransferHandle(ObjectTransferHandle {
+                ticket: _,
+                mut handle,
+            }) = map_errors(message)?
+            {
+                handle
+                    .accept()
+                    .map_err(|err| NetworkError::msg(err.into_string()))?;
+
+                use futures::StreamExt;
+                while let Some(status) = handle.next().await {
+                    match status {
+                        ObjectTransferStatus::ReceptionTick(..) => {
+                            handle
+                                .cancel()
+                                .map_err(|err| NetworkError::msg(err.into_string()))?;
+                        }
+
+                        ObjectTransferStatus::Cancelled => {
+                            self.1.store(true, Ordering::Relaxed);
+                            self.0.clone().unwrap().shutdown().await?;
+                            break;
+                        }
+
+                        ObjectTransferStatus::ReceptionComplete => {
+                            panic!("The transfer should have been cancelled")
+                        }
+
+                        _ => {}
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        async fn on_stop(&mut self) -> Result<(), NetworkError> {
+            Ok(())
+        }
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test]
+    async fn test_c2s_file_transfer_cancelled_by_receiver() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let server_success = Arc::new(AtomicBool::new(false));
+        let port = crate::test_common::get_unused_tcp_port();
+        let server_addr = SocketAddr::from_str(&format!("127.0.0.1:{port}")).unwrap();
+        let server = crate::test_common::server_test_node(
+            server_addr,
+            CancellingReceiverKernel(None, server_success.clone()),
+            |_| {},
+        );
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            Uuid::new_v4(),
+            server_addr,
+            |_channel, mut remote| async move {
+                let res = remote
+                    .send_file_with_custom_opts(
+                        "../resources/TheBridge.pdf",
+                        32 * 1024,
+                        TransferType::FileTransfer,
+                    )
+                    .await;
+                assert!(res.is_err(), "The transfer should have been cancelled");
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+        let _ = futures::future::try_join(server, client).await.unwrap();
+
+        assert!(client_success.load(Ordering::Relaxed));
+        assert!(server_success.load(Ordering::Relaxed));
+    }
+
+    /// Pauses the inbound transfer on the first reception tick, then resumes it shortly after.
+    /// Shuts down once the object is received intact
+    pub struct PausingReceiverKernel(Option<NodeRemote>, Arc<AtomicBool>);
+
+    #[async_trait]
+    impl NetKernel for PausingReceiverKernel {
+        fn load_remote(&mut self, node_remote: NodeRemote) -> Result<(), NetworkError> {
+            self.0 = Some(node_remote);
+            Ok(())
+        }
+
+        async fn on_start(&self) -> Result<(), NetworkError> {
+            Ok(())
+        }
+
+        async fn on_node_event_received(&self, message: NodeResult) -> Result<(), NetworkError> {
+            if let NodeResult::ObjectTransferHandle(ObjectTransferHandle {
+                ticket: _,
+                mut handle,
+            }) = map_errors(message)?
+            {
+                handle
+                    .accept()
+                    .map_err(|err| NetworkError::msg(err.into_string()))?;
+
+                use futures::StreamExt;
+                let mut path = None;
+                let mut pause_requested = false;
+                let mut paused = false;
+                let mut resumed = false;
+                while let Some(status) = handle.next().await {
+                    match status {
+                        ObjectTransferStatus::ReceptionBeginning(file_path, _) => {
+                            path = Some(file_path);
+                        }
+
+                        ObjectTransferStatus::ReceptionTick(..) if !pause_requested => {
+                            pause_requested = true;
+                            handle
+                                .pause()
+                                .map_err(|err| NetworkError::msg(err.into_string()))?;
+                        }
+
+                        ObjectTransferStatus::Paused => {
+                            paused = true;
+                            tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+                            handle
+                                .resume()
+                                .map_err(|err| NetworkError::msg(err.into_string()))?;
+                        }
+
+                        ObjectTransferStatus::Resumed => {
+                            assert!(paused);
+                            resumed = true;
+                        }
+
+                        ObjectTransferStatus::ReceptionComplete => {
+                            assert!(resumed, "The transfer completed without resuming");
+                            let cmp = include_bytes!("../../resources/TheBridge.pdf");
+                            let streamed_data = tokio::fs::read(path.take().unwrap()).await?;
+                            assert_eq!(cmp, streamed_data.as_slice());
+                            self.1.store(true, Ordering::Relaxed);
+                            self.0.clone().unwrap().shutdown().await?;
+                            break;
+                        }
+
+                        ObjectTransferStatus::Cancelled | ObjectTransferStatus::Fail(_) => {
+                            panic!("The transfer should have resumed: {status:?}")
+                        }
+
+                        _ => {}
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        async fn on_stop(&mut self) -> Result<(), NetworkError> {
+            Ok(())
+        }
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test]
+    async fn test_c2s_file_transfer_paused_and_resumed_by_receiver() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let server_success = Arc::new(AtomicBool::new(false));
+        let port = crate::test_common::get_unused_tcp_port();
+        let server_addr = SocketAddr::from_str(&format!("127.0.0.1:{port}")).unwrap();
+        let server = crate::test_common::server_test_node(
+            server_addr,
+            PausingReceiverKernel(None, server_success.clone()),
+            |_| {},
+        );
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            Uuid::new_v4(),
+            server_addr,
+            |_channel, mut remote| async move {
+                remote
+                    .send_file_with_custom_opts(
+                        "../resources/TheBridge.pdf",
+                        32 * 1024,
+                        TransferType::FileTransfer,
+                    )
+                    .await?;
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+        let _ = futures::future::try_join(server, client).await.unwrap();
+
+        assert!(client_success.load(Ordering::Relaxed));
+        assert!(server_success.load(Ordering::Relaxed));
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test]
+    async fn test_c2s_shared_object_access() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let port = crate::test_common::get_unused_tcp_port();
+        let server_addr = SocketAddr::from_str(&format!("127.0.0.1:{port}")).unwrap();
+        let server = crate::test_common::server_test_node(
+            server_addr,
+            crate::prefabs::server::accept_file_transfer_kernel::AcceptFileTransferKernel,
+            |_| {},
+        );
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            Uuid::new_v4(),
+            server_addr,
+            |_channel, mut remote| async move {
+                let cid = remote.user().get_implicated_cid();
+                remote.send_file("../resources/TheBridge.pdf").await?;
+
+                // the owner always has access to its own objects
+                let path = remote.pull_shared_object(cid, "TheBridge.pdf").await?;
+                let cmp = include_bytes!("../../resources/TheBridge.pdf");
+                let streamed_data = tokio::fs::read(path).await.unwrap();
+                assert_eq!(cmp, streamed_data.as_slice());
+
+                // objects may only be shared with registered peers
+                assert!(remote
+                    .share_object("TheBridge.pdf", 12345, SharedObjectPermission::Read)
+                    .await
+                    .is_err());
+                assert!(remote
+                    .pull_shared_object(12345, "TheBridge.pdf")
+                    .await
+                    .is_err());
+
+                // the write is bound to the upload that follows it
+                remote
+                    .write_shared_object(cid, "../resources/TheBridge.pdf")
+                    .await?;
+                let path = remote.pull_shared_object(cid, "TheBridge.pdf").await?;
+                let streamed_data = tokio::fs::read(path).await.unwrap();
+                assert_eq!(cmp, streamed_data.as_slice());
+                assert!(remote
+                    .write_shared_object(12345, "../resources/TheBridge.pdf")
+                    .await
+                    .is_err());
+
+                remote.delete_shared_object(cid, "TheBridge.pdf").await?;
+                assert!(remote
+                    .pull_shared_object(cid, "TheBridge.pdf")
+                    .await
+                    .is_err());
+
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        tokio::select! {
+            res0 = server => { res0.unwrap(); },
+            res1 = client => { res1.unwrap(); }
+        }
+
+        assert!(client_success.load(Ordering::Relaxed));
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test]
+    async fn test_c2s_deferred_udp() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let port = crate::test_common::get_unused_tcp_port();
+        let server_addr = SocketAddr::from_str(&format!("127.0.0.1:{port}")).unwrap();
+        let server = crate::test_common::server_test_node(
+            server_addr,
+            crate::prefabs::server::empty::EmptyKernel,
+            |_| {},
+        );
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless(
+            Uuid::new_v4(),
+            server_addr,
+            UdpMode::Disabled,
+            Default::default(),
+            |connection, mut remote| async move {
+                assert!(connection.udp_channel_rx.is_none());
+                let _channel = remote.enable_udp().await?;
+                // the channel may only be enabled once
+                assert!(remote.enable_udp().await.is_err());
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        tokio::select! {
+            res0 = server => { res0.unwrap(); },
+            res1 = client => { res1.unwrap(); }
+        }
+
+        assert!(client_success.load(Ordering::Relaxed));
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test]
+    async fn test_c2s_peer_list_subscription() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let port = crate::test_common::get_unused_tcp_port();
+        let server_addr = SocketAddr::from_str(&format!("127.0.0.1:{port}")).unwrap();
+        let server = crate::test_common::server_test_node(
+            server_addr,
+            crate::prefabs::server::empty::EmptyKernel,
+            |_| {},
+        );
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            Uuid::new_v4(),
+            server_addr,
+            |_channel, mut remote| async move {
+                use futures::StreamExt;
+                let local_cid = remote.user().get_implicated_cid();
+                let mut deltas = remote.subscribe_peer_list(local_cid).await?;
+
+                // another account registers, then connects from the same node
+                let _ = remote
+                    .register_with_defaults(server_addr, "Peer List", "peer_list_user", "password")
+                    .await?;
+                let peer = remote
+                    .connect_with_defaults(AuthenticationRequest::credentialed(
+                        "peer_list_user",
+                        "password",
+                    ))
+                    .await?;
+
+                assert_eq!(
+                    deltas.next().await,
+                    Some(PeerListDelta::Registered {
+                        cid: peer.cid,
+                        username: "peer_list_user".to_string(),
+                    })
+                );
+                assert_eq!(
+                    deltas.next().await,
+                    Some(PeerListDelta::Online { cid: peer.cid })
+                );
+
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        tokio::select! {
+            res0 = server => { res0.unwrap(); },
+            res1 = client => { res1.unwrap(); }
+        }
+
+        assert!(client_success.load(Ordering::Relaxed));
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test]
+    async fn test_c2s_protocol_renegotiation() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let port = crate::test_common::get_unused_tcp_port();
+        let server_addr = SocketAddr::from_str(&format!("127.0.0.1:{port}")).unwrap();
+        let server = crate::test_common::server_test_node(
+            server_addr,
+            crate::prefabs::server::empty::EmptyKernel,
+            |_| {},
+        );
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            Uuid::new_v4(),
+            server_addr,
+            |_channel, mut remote| async move {
+                // an endpoint that has yet to support every feature
+                let outdated = ProtocolCapabilities {
+                    features: protocol_features::GROUP_COMPRESSION,
+                    ..ProtocolCapabilities::current()
+                };
+                assert_eq!(remote.renegotiate_protocol(outdated).await?, outdated);
+
+                // once upgraded, the same session adopts the new features
+                let current = ProtocolCapabilities::current();
+                let negotiated = remote.renegotiate_protocol(current).await?;
+                assert_eq!(negotiated, current);
+                assert!(negotiated.supports(protocol_features::STRIPED_TRANSFERS));
+
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        tokio::select! {
+            res0 = server => { res0.unwrap(); },
+            res1 = client => { res1.unwrap(); }
+        }
+
+        assert!(client_success.load(Ordering::Relaxed));
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test]
+    async fn test_c2s_ping() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let port = crate::test_common::get_unused_tcp_port();
+        let server_addr = SocketAddr::from_str(&format!("127.0.0.1:{port}")).unwrap();
+        let server = crate::test_common::server_test_node(
+            server_addr,
+            crate::prefabs::server::empty::EmptyKernel,
+            |_| {},
+        );
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            Uuid::new_v4(),
+            server_addr,
+            |_channel, mut remote| async move {
+                for _ in 0..3 {
+                    let result = remote.ping().await?;
+                    assert_eq!(result.peer_cid, 0);
+                    assert!(result.rtt > std::time::Duration::ZERO);
+                    assert!(result.remote_processing_time <= result.rtt);
+                }
+
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        tokio::select! {
+            res0 = server => { res0.unwrap(); },
+            res1 = client => { res1.unwrap(); }
+        }
+
+        assert!(client_success.load(Ordering::Relaxed));
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_c2s_named_channels() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let (server, server_addr) = crate::test_common::server_info_reactive(
+            |conn, mut remote| async move {
+                // the handler is spawned, since the future of the listener must be Sync
+                std::mem::drop(citadel_io::spawn(async move {
+                    use futures::StreamExt;
+                    let _conn = conn;
+                    let (tx, mut rx) = remote.open_channel("telemetry").await?.split();
+                    while let Some(message) = rx.next().await {
+                        tx.send_message(message.as_ref().into()).await?;
+                    }
+
+                    Ok::<_, NetworkError>(())
+                }));
+
+                Ok(())
+            },
+            |_| {},
+        );
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            Uuid::new_v4(),
+            server_addr,
+            |_channel, mut remote| async move {
+                use futures::StreamExt;
+                // the server opens the channel after the client may have sent through it
+                let telemetry = remote.open_channel("telemetry").await?;
+                assert_eq!(telemetry.name(), Some("telemetry"));
+                assert!(remote.open_channel("telemetry").await.is_err());
+                // the server never opens this channel, so nothing is echoed through it
+                let (control_tx, _control_rx) = remote.open_channel("control").await?.split();
+
+                let (telemetry_tx, mut telemetry_rx) = telemetry.split();
+                for idx in 0..10u8 {
+                    control_tx.send_message([u8::MAX; 16].into()).await?;
+                    telemetry_tx.send_message([idx; 16].into()).await?;
+                }
+
+                for idx in 0..10u8 {
+                    assert_eq!(telemetry_rx.next().await.unwrap().as_ref(), &[idx; 16]);
+                }
+
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        tokio::select! {
+            res0 = server => { res0.unwrap(); },
+            res1 = client => { res1.unwrap(); }
+        }
+
+        assert!(client_success.load(Ordering::Relaxed));
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_c2s_graceful_channel_close() {
+        citadel_logging::setup_log();
+        const MESSAGE_COUNT: usize = 100;
+        let server_success = &AtomicBool::new(false);
+        let (server, server_addr) = crate::test_common::server_info_reactive(
+            |conn, _remote| async move {
+                use futures::StreamExt;
+                let (_tx, rx) = conn.channel.split();
+                // the stream ends only once every message sent prior to the close is yielded
+                let received = rx.count().await;
+                assert_eq!(received, MESSAGE_COUNT);
+                server_success.store(true, Ordering::Relaxed);
+                Ok(())
+            },
+            |_| {},
+        );
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            Uuid::new_v4(),
+            server_addr,
+            |connection, remote| async move {
+                let (tx, _rx) = connection.channel.split();
+                for idx in 0..MESSAGE_COUNT {
+                    tx.send_message([idx as u8; 256].into()).await?;
+                }
+
+                tx.close().await?;
+                assert!(tx.send_message([0u8; 16].into()).await.is_err());
+                assert!(tx.close().await.is_err());
+
+                while !server_success.load(Ordering::Relaxed) {
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                }
+
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        tokio::select! {
+            res0 = server => { res0.unwrap(); },
+            res1 = client => { res1.unwrap(); }
+        }
+
+        assert!(server_success.load(Ordering::Relaxed));
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_c2s_send_sheds_load_under_backpressure() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let (server, server_addr) = crate::test_common::server_info_reactive(
+            |conn, _remote| async move {
+                // the channel is never read, such that the client exhausts the window
+                let _channel = conn.channel;
+                while !client_success.load(Ordering::Relaxed) {
+                    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+                }
+
+                Ok(())
+            },
+            |_| {},
+        );
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            Uuid::new_v4(),
+            server_addr,
+            |connection, remote| async move {
+                let (tx, _rx) = connection.channel.split();
+                assert!(tx.metrics().last_send_latency.is_none());
+                let message = vec![0u8; 1024 * 1024];
+                let mut became_full = false;
+                for _ in 0..64 {
+                    match tx.try_send(message.as_slice().into()) {
+                        Ok(()) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+                        Err(ChannelSendError::Full) => {
+                            became_full = true;
+                            break;
+                        }
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+
+                assert!(became_full);
+                let metrics = tx.metrics();
+                assert!(metrics.bytes_in_flight > 0);
+                assert!(metrics.last_send_latency.is_some());
+                let res = tx
+                    .send_timeout(
+                        message.as_slice().into(),
+                        std::time::Duration::from_millis(200),
+                    )
+                    .await;
+                assert!(matches!(res, Err(ChannelSendError::Timeout)));
+
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        tokio::select! {
+            res0 = server => { res0.unwrap(); },
+            res1 = client => { res1.unwrap(); }
+        }
+
+        assert!(client_success.load(Ordering::Relaxed));
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test]
+    async fn test_c2s_file_transfer_delta() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let port = crate::test_common::get_unused_tcp_port();
+        let server_addr = SocketAddr::from_str(&format!("127.0.0.1:{port}")).unwrap();
+        let server = crate::test_common::server_test_node(
+            server_addr,
+            crate::prefabs::server::accept_file_transfer_kernel::AcceptFileTransferKernel,
+            |_| {},
+        );
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            Uuid::new_v4(),
+            server_addr,
+            |_channel, mut remote| async move {
+                let cid = remote.user().get_implicated_cid();
+                // the server has no previous version, so, this falls back to a full transfer
+                remote.send_file_delta("../resources/TheBridge.pdf").await?;
+
+                let mut modified = include_bytes!("../../resources/TheBridge.pdf").to_vec();
+                let mid = modified.len() / 2;
+                modified.splice(mid..mid, b"a small edit".iter().copied());
+                let dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+                tokio::fs::create_dir_all(&dir).await.unwrap();
+                let modified_path = dir.join("TheBridge.pdf");
+                tokio::fs::write(&modified_path, &modified).await.unwrap();
+
+                remote.send_file_delta(modified_path).await?;
+
+                let path = remote.pull_shared_object(cid, "TheBridge.pdf").await?;
+                let streamed_data = tokio::fs::read(path).await.unwrap();
+                assert_eq!(modified, streamed_data);
+                let _ = tokio::fs::remove_dir_all(dir).await;
+
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        tokio::select! {
+            res0 = server => { res0.unwrap(); },
+            res1 = client => { res1.unwrap(); }
+        }
+
+        assert!(client_success.load(Ordering::Relaxed));
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test]
+    async fn test_c2s_file_transfer_deduplicated() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let port = crate::test_common::get_unused_tcp_port();
+        let server_addr = SocketAddr::from_str(&format!("127.0.0.1:{port}")).unwrap();
+        let server = crate::test_common::server_test_node(
+            server_addr,
+            crate::prefabs::server::accept_file_transfer_kernel::AcceptFileTransferKernel,
+            |builder| {
+                let _ = builder.with_server_misc_settings(ServerMiscSettings {
+                    chunk_cache_max_bytes: Some(16 * 1024 * 1024),
+                    ..Default::default()
+                });
+            },
+        );
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            Uuid::new_v4(),
+            server_addr,
+            |_channel, mut remote| async move {
+                let cid = remote.user().get_implicated_cid();
+                let node_remote = remote.inner.clone();
+                let deduplication_metrics = || {
+                    let mut node_remote = node_remote.clone();
+                    async move {
+                        match node_remote
+                            .send_callback(NodeRequest::GetDeduplicationMetrics)
+                            .await?
+                        {
+                            NodeResult::DeduplicationMetrics(result) => Ok(result.metrics),
+                            other => Err(NetworkError::Generic(format!(
+                                "Unexpected response: {other:?}"
+                            ))),
+                        }
+                    }
+                };
+
+                // the cache is empty, so, this is sent in full and populates the cache
+                remote
+                    .send_file_deduplicated("../resources/TheBridge.pdf")
+                    .await?;
+                let metrics = deduplication_metrics().await?;
+                assert_eq!(metrics.transfers, 1);
+                assert!(metrics.chunks > 0);
+                assert_eq!(metrics.cached_chunks, 0);
+
+                // identical content under another name is reconstructed from the cache
+                let original = include_bytes!("../../resources/TheBridge.pdf").to_vec();
+                let dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+                tokio::fs::create_dir_all(&dir).await.unwrap();
+                let copy_path = dir.join("TheBridgeCopy.pdf");
+                tokio::fs::write(&copy_path, &original).await.unwrap();
+
+                // the receiver caches the chunks once the object is stored, which may trail the
+                // completion of the transfer, so, the copy is resent until every chunk is a hit
+                let chunks = metrics.chunks;
+                let mut fully_cached = false;
+                for _ in 0..20 {
+                    let before = deduplication_metrics().await?;
+                    remote.send_file_deduplicated(copy_path.clone()).await?;
+                    let after = deduplication_metrics().await?;
+                    assert_eq!(after.transfers, before.transfers + 1);
+                    assert_eq!(after.chunks, before.chunks + chunks);
+                    if after.cached_chunks - before.cached_chunks == chunks {
+                        fully_cached = true;
+                        break;
+                    }
+
+                    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+                }
+                assert!(fully_cached);
+
+                let path = remote.pull_shared_object(cid, "TheBridgeCopy.pdf").await?;
+                let streamed_data = tokio::fs::read(path).await.unwrap();
+                assert_eq!(original, streamed_data);
+                let _ = tokio::fs::remove_dir_all(dir).await;
+
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        tokio::select! {
+            res0 = server => { res0.unwrap(); },
+            res1 = client => { res1.unwrap(); }
+        }
+
+        assert!(client_success.load(Ordering::Relaxed));
+    }
+
+    /// Only accepts PDFs, saving them into a chosen directory. PDFs whose name begins with
+    /// "infected" are refused by the scanner once received
+    pub struct PdfOnlyPolicyKernel(std::path::PathBuf);
+
+    pub struct NameScanner;
+
+    #[async_trait]
+    impl ObjectScanner for NameScanner {
+        async fn scan(&self, path: &std::path::Path) -> Result<(), String> {
+            let name = path.file_name().unwrap().to_string_lossy();
+            if name.starts_with("infected") {
+                Err(format!("{name} is infected"))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    #[async_trait]
+    impl NetKernel for PdfOnlyPolicyKernel {
+        fn load_remote(&mut self, _node_remote: NodeRemote) -> Result<(), NetworkError> {
+            Ok(())
+        }
+
+        async fn on_start(&self) -> Result<(), NetworkError> {
+            Ok(())
+        }
+
+        async fn on_inbound_transfer_proposed(
+            &self,
+            proposal: &InboundTransferProposal,
+        ) -> InboundTransferDecision {
+            if proposal.content_type != Some("application/pdf") {
+                return InboundTransferDecision::Reject("Only PDFs are accepted".to_string());
+            }
+
+            // the name is a single path component, so it cannot escape the directory
+            InboundTransferDecision::ScanThenAccept(
+                self.0.join(&proposal.name),
+                Arc::new(NameScanner),
+            )
+        }
+
+        async fn on_node_event_received(&self, _message: NodeResult) -> Result<(), NetworkError> {
+            Ok(())
+        }
+
+        async fn on_stop(&mut self) -> Result<(), NetworkError> {
+            Ok(())
+        }
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test]
+    async fn test_c2s_inbound_transfer_policy() {
+        citadel_logging::setup_log();
+        let client_success = &AtomicBool::new(false);
+        let dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+        let port = crate::test_common::get_unused_tcp_port();
+        let server_addr = SocketAddr::from_str(&format!("127.0.0.1:{port}")).unwrap();
+        let server = crate::test_common::server_test_node(
+            server_addr,
+            PdfOnlyPolicyKernel(dir.clone()),
+            |_| {},
+        );
+
+        let dir = &dir;
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            Uuid::new_v4(),
+            server_addr,
+            |_channel, mut remote| async move {
+                remote.send_file("../resources/TheBridge.pdf").await?;
+                let cmp = include_bytes!("../../resources/TheBridge.pdf");
+                let streamed_data = tokio::fs::read(dir.join("TheBridge.pdf")).await.unwrap();
+                assert_eq!(cmp, streamed_data.as_slice());
+
+                let err = remote.send_file("../resources/logo.png").await.unwrap_err();
+                assert!(err.into_string().contains("Only PDFs are accepted"));
+
+                let source_dir = std::env::temp_dir().join(Uuid::new_v4().to_string());
+                tokio::fs::create_dir_all(&source_dir).await.unwrap();
+                let infected_path = source_dir.join("infected.pdf");
+                tokio::fs::write(&infected_path, cmp).await.unwrap();
+                assert!(remote.send_file(infected_path).await.is_err());
+                assert!(!dir.join("infected.pdf").exists());
+
+                let _ = tokio::fs::remove_dir_all(source_dir).await;
+                let _ = tokio::fs::remove_dir_all(dir).await;
+                client_success.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        tokio::select! {
+            res0 = server => { res0.unwrap(); },
+            res1 = client => { res1.unwrap(); }
+        }
+
+        assert!(client_success.load(Ordering::Relaxed));
+    }
 }
@@ -0,0 +1,222 @@
+use crate::codec::{recv_decoded, send_encoded, BincodeCodec, Codec, TypedStream};
+use crate::prefabs::server::client_connect_listener::ClientConnectListenerKernel;
+use crate::prefabs::ClientServerRemote;
+use crate::prelude::*;
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Generates the [`TypedHandler`] implementation of an impl block of typed message handlers
+/// ```
+/// use citadel_sdk::prelude::*;
+/// use citadel_sdk::typed::{typed_handlers, TypedContext, TypedMessage};
+/// use serde::{Deserialize, Serialize};
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Ping(u64);
+/// impl TypedMessage for Ping {}
+///
+/// #[derive(Serialize, Deserialize)]
+/// struct Pong(u64);
+/// impl TypedMessage for Pong {}
+///
+/// struct PingService;
+///
+/// #[typed_handlers]
+/// impl PingService {
+///     async fn on_ping(&self, ctx: &TypedContext, ping: Ping) -> Result<(), NetworkError> {
+///         ctx.send(&Pong(ping.0)).await
+///     }
+/// }
+///
+/// let kernel = citadel_sdk::typed::typed_server_kernel(PingService);
+/// ```
+pub use citadel_sdk_macros::typed_handlers;
+
+/// A message that is dispatched to a handler by its type
+pub trait TypedMessage: Serialize + DeserializeOwned + Send + 'static {
+    /// Identifies the type on the wire. Both endpoints must agree on the tag of each type
+    fn type_tag() -> &'static str {
+        std::any::type_name::<Self>()
+    }
+}
+
+/// The wire format of a [`TypedMessage`]. Both the envelope and its payload are encoded using
+/// [`BincodeCodec`]
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TypedEnvelope {
+    pub tag: String,
+    pub payload: Vec<u8>,
+}
+
+impl TypedEnvelope {
+    pub fn new<T: TypedMessage>(message: &T) -> Result<Self, NetworkError> {
+        Ok(Self {
+            tag: T::type_tag().to_string(),
+            payload: BincodeCodec::encode(message)?,
+        })
+    }
+
+    /// Deserializes the payload as `T`
+    pub fn decode<T: TypedMessage>(&self) -> Result<T, NetworkError> {
+        BincodeCodec::decode(&self.payload)
+    }
+}
+
+/// Passed to each handler invocation
+pub struct TypedContext {
+    /// The CID of the endpoint that sent the message
+    pub peer_cid: u64,
+    pub remote: ClientServerRemote,
+    sender: PeerChannelSendHalf,
+}
+
+impl TypedContext {
+    /// Sends a typed message to the endpoint that sent the message being handled
+    pub async fn send<T: TypedMessage>(&self, message: &T) -> Result<(), NetworkError> {
+        send_typed(&self.sender, message).await
+    }
+}
+
+/// Dispatches inbound [`TypedEnvelope`]s to the matching handler. Rather than being implemented
+/// by hand, this is generated by [`typed_handlers`]
+#[async_trait]
+pub trait TypedHandler: Send + Sync + 'static {
+    async fn dispatch(
+        &self,
+        ctx: &TypedContext,
+        envelope: TypedEnvelope,
+    ) -> Result<(), NetworkError>;
+}
+
+/// Sends a typed message through the channel
+pub async fn send_typed<T: TypedMessage>(
+    sender: &PeerChannelSendHalf,
+    message: &T,
+) -> Result<(), NetworkError> {
+    send_encoded::<_, BincodeCodec>(sender, &TypedEnvelope::new(message)?).await
+}
+
+/// Receives the next typed message from the channel. Returns `None` once the channel closes
+pub async fn recv_typed<T: TypedMessage>(
+    receiver: &mut PeerChannelRecvHalf,
+) -> Option<Result<T, NetworkError>> {
+    recv_decoded::<TypedEnvelope, BincodeCodec>(receiver)
+        .await
+        .map(|envelope| envelope.and_then(|envelope| envelope.decode()))
+}
+
+/// Dispatches every message received through the connection to `handler` until the connection
+/// closes, or, until a handler fails
+pub async fn serve_typed_connection<H: TypedHandler>(
+    handler: &H,
+    conn: ConnectionSuccess,
+    remote: ClientServerRemote,
+) -> Result<(), NetworkError> {
+    let (sender, receiver) = conn.channel.split();
+    let mut envelopes = TypedStream::<TypedEnvelope, BincodeCodec>::new(receiver);
+    let ctx = TypedContext {
+        peer_cid: conn.cid,
+        remote,
+        sender,
+    };
+
+    while let Some(envelope) = envelopes.next().await {
+        handler.dispatch(&ctx, envelope?).await?;
+    }
+
+    Ok(())
+}
+
+/// Creates a server kernel that serves each client connection with `handler`
+pub fn typed_server_kernel<H: TypedHandler>(handler: H) -> Box<dyn NetKernel> {
+    let handler = Arc::new(handler);
+    Box::new(ClientConnectListenerKernel::new(move |conn, remote| {
+        let handler = handler.clone();
+        // each connection is served on its own task, such that the listener is not blocked
+        std::mem::drop(citadel_io::spawn(async move {
+            if let Err(err) = serve_typed_connection(&*handler, conn, remote).await {
+                log::warn!(target: "citadel", "Typed connection closed with error: {err:?}");
+            }
+        }));
+
+        futures::future::ready(Ok(()))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::prefabs::client::single_connection::SingleClientServerConnectionKernel;
+    use crate::prelude::*;
+    use crate::test_common::server_test_node;
+    use crate::typed::{
+        recv_typed, send_typed, typed_handlers, typed_server_kernel, TypedContext, TypedMessage,
+    };
+    use rstest::rstest;
+    use serde::{Deserialize, Serialize};
+    use std::net::SocketAddr;
+    use std::str::FromStr;
+    use uuid::Uuid;
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Add(u64, u64);
+    impl TypedMessage for Add {}
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Negate(i64);
+    impl TypedMessage for Negate {}
+
+    #[derive(Serialize, Deserialize, Debug, PartialEq)]
+    struct Answer(i64);
+    impl TypedMessage for Answer {}
+
+    struct Calculator;
+
+    #[typed_handlers]
+    impl Calculator {
+        async fn on_add(&self, ctx: &TypedContext, add: Add) -> Result<(), NetworkError> {
+            ctx.send(&Answer((add.0 + add.1) as i64)).await
+        }
+
+        async fn on_negate(&self, ctx: &TypedContext, negate: Negate) -> Result<(), NetworkError> {
+            ctx.send(&Answer(-negate.0)).await
+        }
+    }
+
+    #[rstest]
+    #[timeout(std::time::Duration::from_secs(90))]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_typed_handlers_dispatch_by_type() {
+        citadel_logging::setup_log();
+        let port = crate::test_common::get_unused_tcp_port();
+        let server_addr = SocketAddr::from_str(&format!("127.0.0.1:{port}")).unwrap();
+        let server = server_test_node(server_addr, typed_server_kernel(Calculator), |_| {});
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless_defaults(
+            Uuid::new_v4(),
+            server_addr,
+            |connection, remote| async move {
+                let (tx, mut rx) = connection.channel.split();
+                send_typed(&tx, &Add(2, 3)).await?;
+                assert_eq!(recv_typed::<Answer>(&mut rx).await.unwrap()?, Answer(5));
+                send_typed(&tx, &Negate(7)).await?;
+                assert_eq!(recv_typed::<Answer>(&mut rx).await.unwrap()?, Answer(-7));
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = NodeBuilder::default().build(client_kernel).unwrap();
+
+        tokio::select! {
+            res0 = server => {
+                res0.unwrap();
+                panic!("The server stopped before the client finished");
+            },
+            res1 = client => {
+                let _ = res1.unwrap();
+            }
+        }
+    }
+}
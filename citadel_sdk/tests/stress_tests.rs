@@ -97,6 +97,38 @@ mod tests {
         Ok(())
     }
 
+    /// Alternates between Perfect and BestEffort messages. Since BestEffort messages may overtake
+    /// enqueued Perfect messages, only the set of received indices is checked
+    async fn handle_send_receive_mixed_secrecy(
+        barrier: Arc<Barrier>,
+        channel: PeerChannel,
+        count: usize,
+    ) -> Result<(), NetworkError> {
+        let (tx, rx) = channel.split();
+
+        for idx in 0..count {
+            let secrecy_mode = if idx % 2 == 0 {
+                SecrecyMode::Perfect
+            } else {
+                SecrecyMode::BestEffort
+            };
+            tx.send_message_with_secrecy_mode(MessageTransfer::create(idx as u64), secrecy_mode)
+                .await?;
+        }
+
+        let mut received = rx
+            .take(count)
+            .map(|msg| MessageTransfer::receive(msg).idx)
+            .collect::<Vec<_>>()
+            .await;
+        received.sort_unstable();
+
+        assert_eq!(received, (0..count as u64).collect::<Vec<_>>());
+        let _ = barrier.wait().await;
+
+        Ok(())
+    }
+
     async fn handle_send_receive_group(
         barrier: Arc<Barrier>,
         channel: GroupChannel,
@@ -115,7 +147,9 @@ mod tests {
 
         while let Some(msg) = rx.next().await {
             match msg {
-                GroupBroadcastPayload::Message { payload, sender } => {
+                GroupBroadcastPayload::Message {
+                    payload, sender, ..
+                } => {
                     let cur_idx = counter.entry(sender).or_insert(0usize);
                     log::trace!(target: "citadel", "**~ Received message {} for {}~**", cur_idx, sender);
                     let msg = MessageTransfer::receive(payload);
@@ -128,6 +162,10 @@ mod tests {
                     }
                 }
 
+                GroupBroadcastPayload::EpochRotated { epoch } => {
+                    panic!("No members were removed, yet the epoch rotated to {epoch}");
+                }
+
                 GroupBroadcastPayload::Event { payload } => {
                     if let GroupBroadcast::MessageResponse(..) = &payload {
                     } else {
@@ -223,6 +261,130 @@ mod tests {
         assert!(SERVER_SUCCESS.load(Ordering::Relaxed));
     }
 
+    /// Re-keys repeatedly while both endpoints send, such that messages are encrypted under
+    /// versions that are concurrently being replaced. Every message must still arrive, in order
+    #[rstest]
+    #[case(200, 10)]
+    #[timeout(std::time::Duration::from_secs(240))]
+    #[tokio::test]
+    async fn stress_test_c2s_messaging_during_rekeys(
+        #[case] message_count: usize,
+        #[case] rekey_count: u32,
+    ) {
+        citadel_logging::setup_log();
+        citadel_sdk::test_common::TestBarrier::setup(2);
+        static CLIENT_SUCCESS: AtomicBool = AtomicBool::new(false);
+        static SERVER_SUCCESS: AtomicBool = AtomicBool::new(false);
+        CLIENT_SUCCESS.store(false, Ordering::Relaxed);
+        SERVER_SUCCESS.store(false, Ordering::Relaxed);
+
+        let (server, server_addr) = citadel_sdk::test_common::server_info_reactive(
+            move |conn, remote| async move {
+                handle_send_receive_e2e(get_barrier(), conn.channel, message_count).await?;
+                SERVER_SUCCESS.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+            |_| {},
+        );
+
+        let uuid = Uuid::new_v4();
+        let session_security = SessionSecuritySettingsBuilder::default()
+            .with_secrecy_mode(SecrecyMode::BestEffort)
+            .build()
+            .unwrap();
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless(
+            uuid,
+            server_addr,
+            UdpMode::Enabled,
+            session_security,
+            move |connection, remote| async move {
+                let mut rekey_remote = remote.clone();
+                let rekeys = async move {
+                    let mut last_version = 0;
+                    for _ in 0..rekey_count {
+                        let version = rekey_remote.rekey_now().await?;
+                        assert!(version > last_version);
+                        last_version = version;
+                    }
+
+                    Ok::<_, NetworkError>(())
+                };
+
+                let _ = futures::future::try_join(
+                    handle_send_receive_e2e(get_barrier(), connection.channel, message_count),
+                    rekeys,
+                )
+                .await?;
+                CLIENT_SUCCESS.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = spawn_handle!(NodeBuilder::default().build(client_kernel).unwrap());
+        let server = spawn_handle!(server);
+
+        let joined = futures::future::try_join(server, client);
+
+        let (_res0, _res1) = joined.await.unwrap();
+
+        assert!(CLIENT_SUCCESS.load(Ordering::Relaxed));
+        assert!(SERVER_SUCCESS.load(Ordering::Relaxed));
+    }
+
+    #[rstest]
+    #[case(SecrecyMode::Perfect)]
+    #[case(SecrecyMode::BestEffort)]
+    #[timeout(std::time::Duration::from_secs(240))]
+    #[tokio::test]
+    async fn stress_test_c2s_messaging_mixed_secrecy(#[case] default_secrecy_mode: SecrecyMode) {
+        citadel_logging::setup_log();
+        citadel_sdk::test_common::TestBarrier::setup(2);
+        const MESSAGE_COUNT: usize = 200;
+        static CLIENT_SUCCESS: AtomicBool = AtomicBool::new(false);
+        static SERVER_SUCCESS: AtomicBool = AtomicBool::new(false);
+        CLIENT_SUCCESS.store(false, Ordering::Relaxed);
+        SERVER_SUCCESS.store(false, Ordering::Relaxed);
+
+        let (server, server_addr) = citadel_sdk::test_common::server_info_reactive(
+            move |conn, remote| async move {
+                handle_send_receive_mixed_secrecy(get_barrier(), conn.channel, MESSAGE_COUNT)
+                    .await?;
+                SERVER_SUCCESS.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+            |_| {},
+        );
+
+        let session_security = SessionSecuritySettingsBuilder::default()
+            .with_secrecy_mode(default_secrecy_mode)
+            .build()
+            .unwrap();
+
+        let client_kernel = SingleClientServerConnectionKernel::new_passwordless(
+            Uuid::new_v4(),
+            server_addr,
+            UdpMode::Disabled,
+            session_security,
+            move |connection, remote| async move {
+                handle_send_receive_mixed_secrecy(get_barrier(), connection.channel, MESSAGE_COUNT)
+                    .await?;
+                CLIENT_SUCCESS.store(true, Ordering::Relaxed);
+                remote.shutdown_kernel().await
+            },
+        )
+        .unwrap();
+
+        let client = spawn_handle!(NodeBuilder::default().build(client_kernel).unwrap());
+        let server = spawn_handle!(server);
+
+        let (_res0, _res1) = futures::future::try_join(server, client).await.unwrap();
+
+        assert!(CLIENT_SUCCESS.load(Ordering::Relaxed));
+        assert!(SERVER_SUCCESS.load(Ordering::Relaxed));
+    }
+
     #[rstest]
     #[case(100, SecrecyMode::Perfect)]
     #[case(100, SecrecyMode::BestEffort)]
@@ -0,0 +1,76 @@
+//! Procedural macros re-exported by `citadel_sdk`
+#![deny(unused_results, unused_extern_crates)]
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{parse_macro_input, FnArg, ImplItem, ItemImpl};
+
+/// Implements `citadel_sdk::typed::TypedHandler` for the annotated inherent impl block. Every
+/// `async` method of the block is a handler, and must have the signature
+/// `async fn(&self, &TypedContext, M) -> Result<(), NetworkError>` where `M: TypedMessage`.
+/// Inbound messages are dispatched to the handler whose `M` matches the type tag of the message
+#[proc_macro_attribute]
+pub fn typed_handlers(_attr: TokenStream, item: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(item as ItemImpl);
+    match expand_typed_handlers(&item) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand_typed_handlers(item: &ItemImpl) -> syn::Result<TokenStream2> {
+    if let Some((_, path, _)) = &item.trait_ {
+        return Err(syn::Error::new(
+            path.span(),
+            "#[typed_handlers] must be placed on an inherent impl block",
+        ));
+    }
+
+    let mut handlers = Vec::new();
+    for method in item.items.iter().filter_map(|item| match item {
+        ImplItem::Fn(method) if method.sig.asyncness.is_some() => Some(method),
+        _ => None,
+    }) {
+        let inputs = method.sig.inputs.iter().collect::<Vec<_>>();
+        let message_ty = match inputs.as_slice() {
+            [FnArg::Receiver(_), FnArg::Typed(_), FnArg::Typed(message)] => &message.ty,
+            _ => {
+                return Err(syn::Error::new(
+                    method.sig.span(),
+                    "a typed handler must take (&self, &TypedContext, message)",
+                ))
+            }
+        };
+
+        let name = &method.sig.ident;
+        handlers.push(quote! {
+            if envelope.tag == <#message_ty as ::citadel_sdk::typed::TypedMessage>::type_tag() {
+                return self.#name(ctx, envelope.decode::<#message_ty>()?).await;
+            }
+        });
+    }
+
+    let self_ty = &item.self_ty;
+    let (impl_generics, _, where_clause) = item.generics.split_for_impl();
+
+    Ok(quote! {
+        #item
+
+        #[::citadel_sdk::async_trait]
+        impl #impl_generics ::citadel_sdk::typed::TypedHandler for #self_ty #where_clause {
+            async fn dispatch(
+                &self,
+                ctx: &::citadel_sdk::typed::TypedContext,
+                envelope: ::citadel_sdk::typed::TypedEnvelope,
+            ) -> ::std::result::Result<(), ::citadel_sdk::prelude::NetworkError> {
+                #(#handlers)*
+
+                ::std::result::Result::Err(::citadel_sdk::prelude::NetworkError::Generic(
+                    ::std::format!("No handler accepts messages of type {}", envelope.tag),
+                ))
+            }
+        }
+    })
+}
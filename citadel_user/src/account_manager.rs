@@ -1,15 +1,21 @@
+use crate::auth::credential_validator::{AccountPolicy, CredentialValidator, GroupPolicies};
+use crate::auth::oidc::OidcVerifier;
 use crate::auth::proposed_credentials::ProposedCredentials;
 use crate::backend::memory::MemoryBackend;
 use crate::backend::{BackendType, PersistenceHandler};
 use crate::client_account::{ClientNetworkAccount, MutualPeer};
 use crate::external_services::{ServicesConfig, ServicesHandler};
 use crate::misc::AccountError;
+use crate::permissions::AccountPermissions;
 use crate::prelude::{ConnectionInfo, UserIdentifier};
 use crate::server_misc_settings::ServerMiscSettings;
-use citadel_crypt::argon::argon_container::{ArgonDefaultServerSettings, ArgonSettings};
+use citadel_crypt::argon::argon_container::{
+    ArgonCostParameters, ArgonDefaultServerSettings, ArgonSettings,
+};
 use citadel_crypt::fcm::fcm_ratchet::ThinRatchet;
 use citadel_crypt::stacked_ratchet::Ratchet;
 use citadel_crypt::stacked_ratchet::StackedRatchet;
+use std::sync::Arc;
 
 /// The default manager for handling the list of users stored locally. It also allows for user creation, and is used especially
 /// for when creating a new user via the registration service.
@@ -19,6 +25,8 @@ pub struct AccountManager<R: Ratchet = StackedRatchet, Fcm: Ratchet = ThinRatche
     persistence_handler: PersistenceHandler<R, Fcm>,
     node_argon_settings: ArgonSettings,
     server_misc_settings: ServerMiscSettings,
+    oidc_verifier: Option<Arc<OidcVerifier>>,
+    credential_validator: Option<(Arc<dyn CredentialValidator>, GroupPolicies)>,
     backend_ty: BackendType,
 }
 
@@ -52,7 +60,11 @@ impl<R: Ratchet, Fcm: Ratchet> AccountManager<R, Fcm> {
             #[cfg(all(feature = "filesystem", not(target_family = "wasm")))]
             BackendType::Filesystem(dir) => {
                 use crate::backend::filesystem_backend::FilesystemBackend;
-                let backend = FilesystemBackend::from(dir.clone());
+                let chunk_cache_max_bytes = server_misc_settings
+                    .as_ref()
+                    .and_then(|settings| settings.chunk_cache_max_bytes);
+                let backend =
+                    FilesystemBackend::from(dir.clone()).with_chunk_cache(chunk_cache_max_bytes);
                 PersistenceHandler::create(backend).await?
             }
 
@@ -79,17 +91,89 @@ impl<R: Ratchet, Fcm: Ratchet> AccountManager<R, Fcm> {
 
         log::info!(target: "citadel", "Successfully established connection to backend {:?}...", backend_type);
 
+        let server_argon_settings = server_argon_settings.unwrap_or_default().enforce_minimums();
+        log::info!(target: "citadel", "Password hashing policy: {:?}", server_argon_settings);
+
+        let server_misc_settings = server_misc_settings.unwrap_or_default();
+        let oidc_verifier = server_misc_settings
+            .oidc
+            .as_ref()
+            .map(OidcVerifier::new)
+            .transpose()?
+            .map(Arc::new);
+
         let this = Self {
             backend_ty: backend_type,
             persistence_handler,
             services_handler,
-            node_argon_settings: server_argon_settings.unwrap_or_default().into(),
-            server_misc_settings: server_misc_settings.unwrap_or_default(),
+            node_argon_settings: server_argon_settings.into(),
+            server_misc_settings,
+            oidc_verifier,
+            credential_validator: None,
         };
 
         Ok(this)
     }
 
+    /// Returns the Argon2 cost parameters used to hash passwords on this node, for auditing.
+    /// Registering clients are required to hash their passwords under the same parameters
+    pub fn argon_policy(&self) -> ArgonCostParameters {
+        ArgonCostParameters::from(&self.node_argon_settings)
+    }
+
+    /// Returns the verifier of the ID tokens presented to this node, if OpenID Connect
+    /// authentication is enabled
+    pub fn oidc_verifier(&self) -> Option<&OidcVerifier> {
+        self.oidc_verifier.as_deref()
+    }
+
+    /// Validates the passwords of accounts registered with delegated credentials against the
+    /// directory of `credential_validator`, mapping the groups of each user onto the policy of
+    /// their sessions via `policies`
+    pub fn with_credential_validator(
+        mut self,
+        credential_validator: Arc<dyn CredentialValidator>,
+        policies: GroupPolicies,
+    ) -> Self {
+        self.credential_validator = Some((credential_validator, policies));
+        self
+    }
+
+    /// Returns the validator of delegated credentials, if set
+    pub fn credential_validator(&self) -> Option<&dyn CredentialValidator> {
+        self.credential_validator
+            .as_ref()
+            .map(|(validator, _)| validator.as_ref())
+    }
+
+    /// Checks the credentials a client presents when connecting to `cnac`, returning the policy
+    /// of the session
+    pub async fn validate_credentials(
+        &self,
+        cnac: &ClientNetworkAccount<R, Fcm>,
+        creds: ProposedCredentials,
+    ) -> Result<AccountPolicy, AccountError> {
+        if !cnac.is_delegated() {
+            cnac.validate_credentials(creds, self.oidc_verifier())
+                .await?;
+            return Ok(AccountPolicy::default());
+        }
+
+        if !creds.compare_username(cnac.get_username().as_bytes()) {
+            return Err(AccountError::InvalidUsername);
+        }
+
+        let user = creds
+            .validate_delegated(self.credential_validator())
+            .await?;
+        let policy = self
+            .credential_validator
+            .as_ref()
+            .map(|(_, policies)| policies.resolve(&user.groups))
+            .unwrap_or_default();
+        Ok(policy)
+    }
+
     /// Returns a reference to the services handler
     pub fn services_handler(&self) -> &ServicesHandler {
         &self.services_handler
@@ -109,7 +193,12 @@ impl<R: Ratchet, Fcm: Ratchet> AccountManager<R, Fcm> {
             .persistence_handler
             .get_cid_by_username(creds.username());
         let auth_store = creds
-            .derive_server_container(&self.node_argon_settings, self.get_misc_settings())
+            .derive_server_container(
+                &self.node_argon_settings,
+                self.get_misc_settings(),
+                self.oidc_verifier(),
+                self.credential_validator(),
+            )
             .await?;
         let pers = &self.persistence_handler;
 
@@ -204,6 +293,26 @@ impl<R: Ratchet, Fcm: Ratchet> AccountManager<R, Fcm> {
             .await
     }
 
+    /// Returns the permissions of `cid`
+    pub async fn get_account_permissions(
+        &self,
+        cid: u64,
+    ) -> Result<AccountPermissions, AccountError> {
+        self.persistence_handler.get_account_permissions(cid).await
+    }
+
+    /// Replaces the permissions of `cid`. Sessions of the account that are already connected
+    /// retain their previous permissions until they reconnect
+    pub async fn set_account_permissions(
+        &self,
+        cid: u64,
+        permissions: AccountPermissions,
+    ) -> Result<(), AccountError> {
+        self.persistence_handler
+            .set_account_permissions(cid, permissions)
+            .await
+    }
+
     /// Returns the number of accounts purged
     pub async fn purge(&self) -> Result<usize, AccountError> {
         self.persistence_handler.purge().await
@@ -0,0 +1,114 @@
+//! Delegates the validation of passwords to an external directory, such as LDAP or Active
+//! Directory, instead of the hashes stored by the node
+//!
+//! Clients registering with [`ProposedCredentials::new_delegated`] transmit their password within
+//! the encrypted payload of the handshake, since the directory requires the password itself. Each
+//! time the client connects, the directory is consulted anew, and the groups of the user are
+//! mapped onto the [`AccountPolicy`] of the session
+//!
+//! [`ProposedCredentials::new_delegated`]: crate::auth::proposed_credentials::ProposedCredentials::new_delegated
+use crate::misc::AccountError;
+use async_trait::async_trait;
+use citadel_crypt::prelude::SecBuffer;
+use std::collections::HashMap;
+
+/// Verifies the credentials of clients against an external directory
+#[async_trait]
+pub trait CredentialValidator: Send + Sync + 'static {
+    /// Verifies the password of `username`, returning the user as known by the directory. An
+    /// error denies the registration or connection
+    async fn validate(
+        &self,
+        username: &str,
+        password: &SecBuffer,
+    ) -> Result<ValidatedUser, AccountError>;
+}
+
+/// A user whose credentials were verified by the directory
+#[derive(Clone, Debug, Default)]
+pub struct ValidatedUser {
+    /// The display name of the user, if known to the directory
+    pub full_name: Option<String>,
+    /// The groups the user belongs to
+    pub groups: Vec<String>,
+}
+
+/// The permissions of a session
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct AccountPolicy {
+    /// If false, the session may neither send files to the node, nor to peers through the node
+    pub allow_file_transfer: bool,
+}
+
+impl Default for AccountPolicy {
+    fn default() -> Self {
+        Self {
+            allow_file_transfer: true,
+        }
+    }
+}
+
+impl AccountPolicy {
+    /// Grants each permission granted by either policy
+    pub fn union(self, other: Self) -> Self {
+        Self {
+            allow_file_transfer: self.allow_file_transfer || other.allow_file_transfer,
+        }
+    }
+}
+
+/// Maps the groups of validated users onto their policy
+#[derive(Clone, Debug, Default)]
+pub struct GroupPolicies {
+    default: AccountPolicy,
+    groups: HashMap<String, AccountPolicy>,
+}
+
+impl GroupPolicies {
+    /// `default` applies to users belonging to none of the mapped groups
+    pub fn new(default: AccountPolicy) -> Self {
+        Self {
+            default,
+            groups: HashMap::new(),
+        }
+    }
+
+    /// Applies `policy` to the members of `group`
+    pub fn with_group<T: Into<String>>(mut self, group: T, policy: AccountPolicy) -> Self {
+        let _ = self.groups.insert(group.into(), policy);
+        self
+    }
+
+    /// Returns the union of the policies of each mapped group in `groups`, or the default if none
+    /// are mapped
+    pub fn resolve(&self, groups: &[String]) -> AccountPolicy {
+        groups
+            .iter()
+            .filter_map(|group| self.groups.get(group).copied())
+            .reduce(AccountPolicy::union)
+            .unwrap_or(self.default)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::credential_validator::{AccountPolicy, GroupPolicies};
+
+    #[test]
+    fn test_resolve() {
+        let deny = AccountPolicy {
+            allow_file_transfer: false,
+        };
+        let policies = GroupPolicies::new(deny)
+            .with_group("engineering", AccountPolicy::default())
+            .with_group("contractors", deny);
+
+        assert_eq!(policies.resolve(&[]), deny);
+        assert_eq!(policies.resolve(&["sales".to_string()]), deny);
+        assert_eq!(policies.resolve(&["contractors".to_string()]), deny);
+        assert_eq!(
+            policies.resolve(&["contractors".to_string(), "engineering".to_string()]),
+            AccountPolicy::default()
+        );
+    }
+}
@@ -2,6 +2,10 @@
 use citadel_crypt::argon::argon_container::ArgonContainerType;
 use serde::{Deserialize, Serialize};
 
+/// For validating passwords against external directories
+pub mod credential_validator;
+/// For verifying the ID tokens of external identity providers
+pub mod oidc;
 /// For handling misc requirements
 pub mod proposed_credentials;
 
@@ -17,6 +21,14 @@ pub enum DeclaredAuthenticationMode {
         username: String,
         full_name: String,
     },
+    Oidc {
+        username: String,
+        full_name: String,
+    },
+    Delegated {
+        username: String,
+        full_name: String,
+    },
 }
 
 impl DeclaredAuthenticationMode {
@@ -24,6 +36,8 @@ impl DeclaredAuthenticationMode {
         match self {
             Self::Argon { username, .. } => username.as_str(),
             Self::Passwordless { username, .. } => username.as_str(),
+            Self::Oidc { username, .. } => username.as_str(),
+            Self::Delegated { username, .. } => username.as_str(),
         }
     }
 
@@ -31,19 +45,21 @@ impl DeclaredAuthenticationMode {
         match self {
             Self::Argon { full_name, .. } => full_name.as_str(),
             Self::Passwordless { full_name, .. } => full_name.as_str(),
+            Self::Oidc { full_name, .. } => full_name.as_str(),
+            Self::Delegated { full_name, .. } => full_name.as_str(),
         }
     }
 
     pub fn argon_container(&self) -> Option<&ArgonContainerType> {
         match self {
             Self::Argon { argon, .. } => Some(argon),
-            Self::Passwordless { .. } => None,
+            Self::Passwordless { .. } | Self::Oidc { .. } | Self::Delegated { .. } => None,
         }
     }
 
     pub fn is_passwordless(&self) -> bool {
         match self {
-            Self::Argon { .. } => false,
+            Self::Argon { .. } | Self::Oidc { .. } | Self::Delegated { .. } => false,
             Self::Passwordless { .. } => true,
         }
     }
@@ -0,0 +1,453 @@
+//! OpenID Connect ID tokens, used for SSO-backed registration and login
+//!
+//! The client obtains an ID token from an external identity provider, and presents it in place of
+//! a password. The server verifies the signature of the token against the keys of the provider,
+//! along with its issuer, audience and expiry. Accounts are keyed by the issuer and subject claims
+//! of the token, thus the CID of an SSO user is the same regardless of the device registering
+use crate::misc::AccountError;
+use crate::server_misc_settings::OidcSettings;
+use serde::Deserialize;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// The claims of an ID token relevant to the protocol
+#[derive(Deserialize, Debug, Clone)]
+pub struct OidcClaims {
+    /// The identity provider that issued the token
+    pub iss: String,
+    /// The identifier of the user, unique within the issuer
+    pub sub: String,
+    /// The clients the token was issued for
+    pub aud: Audience,
+    /// The time, in seconds since the unix epoch, after which the token is expired
+    pub exp: u64,
+    /// The time, in seconds since the unix epoch, before which the token is invalid
+    #[serde(default)]
+    pub nbf: Option<u64>,
+    /// The full name of the user, if shared by the identity provider
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
+/// The `aud` claim, which is either a single client ID, or several
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Audience {
+    Single(String),
+    Multiple(Vec<String>),
+}
+
+impl Audience {
+    /// Returns true if the token was issued for `client_id`
+    pub fn contains(&self, client_id: &str) -> bool {
+        match self {
+            Self::Single(aud) => aud == client_id,
+            Self::Multiple(auds) => auds.iter().any(|aud| aud == client_id),
+        }
+    }
+}
+
+impl OidcClaims {
+    /// Decodes the claims of `id_token` without verifying it. Used by the client, which needs the
+    /// claims to determine its username, yet has no reason to distrust its own token
+    pub fn decode_unverified(id_token: &str) -> Result<Self, AccountError> {
+        Ok(SplitToken::parse(id_token)?.claims)
+    }
+
+    /// The username of the account belonging to the holder of this token
+    pub fn username(&self) -> String {
+        username_for(&self.iss, &self.sub)
+    }
+
+    /// The full name of the user, falling back to the subject
+    pub fn full_name(&self) -> String {
+        self.name.clone().unwrap_or_else(|| self.sub.clone())
+    }
+}
+
+/// Maps the issuer and subject of an ID token onto the username, and thus the CID, of its account
+pub fn username_for(issuer: &str, subject: &str) -> String {
+    format!("{issuer}#{subject}")
+}
+
+#[derive(Deserialize)]
+struct JoseHeader {
+    alg: String,
+    #[serde(default)]
+    kid: Option<String>,
+}
+
+struct SplitToken<'a> {
+    header: JoseHeader,
+    claims: OidcClaims,
+    signing_input: &'a [u8],
+    signature: Vec<u8>,
+}
+
+impl<'a> SplitToken<'a> {
+    fn parse(id_token: &'a str) -> Result<Self, AccountError> {
+        let mut parts = id_token.trim().split('.');
+        let (header, claims, signature) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(header), Some(claims), Some(signature)) if parts.next().is_none() => {
+                (header, claims, signature)
+            }
+            _ => return Err(AccountError::msg("Malformed ID token")),
+        };
+
+        let signing_input = &id_token.trim().as_bytes()[..header.len() + 1 + claims.len()];
+
+        Ok(Self {
+            header: decode_json(header)?,
+            claims: decode_json(claims)?,
+            signing_input,
+            signature: decode_segment(signature)?,
+        })
+    }
+}
+
+fn decode_segment(segment: &str) -> Result<Vec<u8>, AccountError> {
+    base64::decode_config(segment, base64::URL_SAFE_NO_PAD)
+        .map_err(|err| AccountError::Generic(format!("Malformed ID token: {err}")))
+}
+
+fn decode_json<T: for<'de> Deserialize<'de>>(segment: &str) -> Result<T, AccountError> {
+    serde_json::from_slice(&decode_segment(segment)?)
+        .map_err(|err| AccountError::Generic(format!("Malformed ID token: {err}")))
+}
+
+#[derive(Deserialize)]
+struct JsonWebKeySet {
+    keys: Vec<JsonWebKey>,
+}
+
+#[derive(Deserialize)]
+struct JsonWebKey {
+    kty: String,
+    #[serde(default)]
+    kid: Option<String>,
+    #[serde(default)]
+    crv: Option<String>,
+    #[serde(default)]
+    n: Option<String>,
+    #[serde(default)]
+    e: Option<String>,
+    #[serde(default)]
+    x: Option<String>,
+    #[serde(default)]
+    y: Option<String>,
+}
+
+// the key material is only read by the signature checks of the `oidc` feature
+#[cfg_attr(not(feature = "oidc"), allow(dead_code))]
+enum VerificationKey {
+    /// RS256
+    Rsa { n: Vec<u8>, e: Vec<u8> },
+    /// ES256, as an uncompressed point
+    EcP256 { point: Vec<u8> },
+}
+
+impl VerificationKey {
+    fn from_jwk(jwk: JsonWebKey) -> Option<(Option<String>, Self)> {
+        let key = match (jwk.kty.as_str(), jwk.crv.as_deref()) {
+            ("RSA", _) => Self::Rsa {
+                n: decode_segment(jwk.n.as_deref()?).ok()?,
+                e: decode_segment(jwk.e.as_deref()?).ok()?,
+            },
+
+            ("EC", Some("P-256")) => {
+                let mut point = vec![0x04];
+                point.extend(decode_segment(jwk.x.as_deref()?).ok()?);
+                point.extend(decode_segment(jwk.y.as_deref()?).ok()?);
+                Self::EcP256 { point }
+            }
+
+            // unsupported key types are skipped, since providers may publish them alongside supported keys
+            _ => return None,
+        };
+
+        Some((jwk.kid, key))
+    }
+
+    fn supports(&self, alg: &str) -> bool {
+        matches!(
+            (self, alg),
+            (Self::Rsa { .. }, "RS256") | (Self::EcP256 { .. }, "ES256")
+        )
+    }
+
+    #[cfg(feature = "oidc")]
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        use ring::signature::{
+            RsaPublicKeyComponents, UnparsedPublicKey, ECDSA_P256_SHA256_FIXED,
+            RSA_PKCS1_2048_8192_SHA256,
+        };
+
+        match self {
+            Self::Rsa { n, e } => RsaPublicKeyComponents { n, e }
+                .verify(&RSA_PKCS1_2048_8192_SHA256, message, signature)
+                .is_ok(),
+            Self::EcP256 { point } => UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, point)
+                .verify(message, signature)
+                .is_ok(),
+        }
+    }
+
+    #[cfg(not(feature = "oidc"))]
+    fn verify(&self, _message: &[u8], _signature: &[u8]) -> bool {
+        false
+    }
+}
+
+/// Verifies the ID tokens presented to the node
+pub struct OidcVerifier {
+    issuer: String,
+    audience: String,
+    leeway: Duration,
+    keys: Vec<(Option<String>, VerificationKey)>,
+}
+
+impl OidcVerifier {
+    /// Loads the keys of the identity provider. Requires the `oidc` feature
+    pub fn new(settings: &OidcSettings) -> Result<Self, AccountError> {
+        if cfg!(not(feature = "oidc")) {
+            return Err(AccountError::msg(
+                "OIDC authentication requires the `oidc` feature",
+            ));
+        }
+
+        let jwks: JsonWebKeySet = serde_json::from_str(&settings.jwks)
+            .map_err(|err| AccountError::Generic(format!("Invalid JWKS: {err}")))?;
+        let keys = jwks
+            .keys
+            .into_iter()
+            .filter_map(VerificationKey::from_jwk)
+            .collect::<Vec<_>>();
+
+        if keys.is_empty() {
+            return Err(AccountError::msg("The JWKS holds no supported keys"));
+        }
+
+        Ok(Self {
+            issuer: settings.issuer.clone(),
+            audience: settings.audience.clone(),
+            leeway: settings.leeway,
+            keys,
+        })
+    }
+
+    /// Verifies the signature, issuer, audience and lifetime of `id_token`, returning its claims
+    pub fn verify(&self, id_token: &str) -> Result<OidcClaims, AccountError> {
+        let token = SplitToken::parse(id_token)?;
+        let alg = token.header.alg.as_str();
+
+        let verified = self
+            .keys
+            .iter()
+            .filter(|(kid, key)| {
+                key.supports(alg) && (token.header.kid.is_none() || *kid == token.header.kid)
+            })
+            .any(|(_, key)| key.verify(token.signing_input, &token.signature));
+
+        if !verified {
+            return Err(AccountError::msg(
+                "The signature of the ID token is invalid",
+            ));
+        }
+
+        let claims = token.claims;
+
+        if claims.iss != self.issuer {
+            return Err(AccountError::msg(
+                "The ID token was issued by an unknown provider",
+            ));
+        }
+
+        if !claims.aud.contains(&self.audience) {
+            return Err(AccountError::msg(
+                "The ID token was not issued for this node",
+            ));
+        }
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let leeway = self.leeway.as_secs();
+
+        if claims.exp.saturating_add(leeway) < now {
+            return Err(AccountError::msg("The ID token is expired"));
+        }
+
+        if claims.nbf.unwrap_or(0) > now.saturating_add(leeway) {
+            return Err(AccountError::msg("The ID token is not yet valid"));
+        }
+
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::oidc::{username_for, OidcClaims};
+
+    #[test]
+    fn test_decode_unverified() {
+        let encode = |json: &str| base64::encode_config(json, base64::URL_SAFE_NO_PAD);
+        let token = format!(
+            "{}.{}.{}",
+            encode(r#"{"alg":"RS256","kid":"1"}"#),
+            encode(
+                r#"{"iss":"https://idp.example.com","sub":"alice","aud":["citadel"],"exp":1,"name":"Alice"}"#
+            ),
+            encode("signature")
+        );
+
+        let claims = OidcClaims::decode_unverified(&token).unwrap();
+        assert!(claims.aud.contains("citadel"));
+        assert_eq!(claims.full_name(), "Alice");
+        assert_eq!(
+            claims.username(),
+            username_for("https://idp.example.com", "alice")
+        );
+
+        assert!(OidcClaims::decode_unverified("not.a-token").is_err());
+    }
+
+    #[cfg(feature = "oidc")]
+    mod verifier {
+        use crate::auth::oidc::OidcVerifier;
+        use crate::server_misc_settings::OidcSettings;
+        use ring::rand::SystemRandom;
+        use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+        use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+        const ISSUER: &str = "https://idp.example.com";
+        const AUDIENCE: &str = "citadel";
+
+        fn encode<T: AsRef<[u8]>>(input: T) -> String {
+            base64::encode_config(input, base64::URL_SAFE_NO_PAD)
+        }
+
+        fn generate_key() -> EcdsaKeyPair {
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(
+                &ECDSA_P256_SHA256_FIXED_SIGNING,
+                &SystemRandom::new(),
+            )
+            .unwrap();
+            EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref()).unwrap()
+        }
+
+        fn verifier(key: &EcdsaKeyPair) -> OidcVerifier {
+            // the public key is an uncompressed point: 0x04 || x || y
+            let point = key.public_key().as_ref();
+            let jwks = serde_json::json!({
+                "keys": [
+                    { "kty": "RSA", "kid": "unsupported" },
+                    {
+                        "kty": "EC",
+                        "crv": "P-256",
+                        "kid": "1",
+                        "x": encode(&point[1..33]),
+                        "y": encode(&point[33..65]),
+                    }
+                ]
+            });
+
+            OidcVerifier::new(&OidcSettings {
+                issuer: ISSUER.to_string(),
+                audience: AUDIENCE.to_string(),
+                jwks: jwks.to_string(),
+                leeway: Duration::from_secs(60),
+            })
+            .unwrap()
+        }
+
+        fn now() -> u64 {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        }
+
+        fn sign(key: &EcdsaKeyPair, kid: &str, claims: serde_json::Value) -> String {
+            let header = serde_json::json!({ "alg": "ES256", "kid": kid });
+            let signing_input = format!(
+                "{}.{}",
+                encode(header.to_string()),
+                encode(claims.to_string())
+            );
+            let signature = key
+                .sign(&SystemRandom::new(), signing_input.as_bytes())
+                .unwrap();
+            format!("{signing_input}.{}", encode(signature))
+        }
+
+        fn claims(iss: &str, aud: &str, exp: u64) -> serde_json::Value {
+            serde_json::json!({ "iss": iss, "sub": "alice", "aud": aud, "exp": exp })
+        }
+
+        #[test]
+        fn test_verify_valid_token() {
+            let key = generate_key();
+            let verifier = verifier(&key);
+            let token = sign(&key, "1", claims(ISSUER, AUDIENCE, now() + 3600));
+
+            let claims = verifier.verify(&token).unwrap();
+            assert_eq!(claims.sub, "alice");
+            assert!(claims.aud.contains(AUDIENCE));
+        }
+
+        #[test]
+        fn test_verify_rejects_wrong_issuer_or_audience() {
+            let key = generate_key();
+            let verifier = verifier(&key);
+            let exp = now() + 3600;
+
+            let token = sign(&key, "1", claims("https://evil.example.com", AUDIENCE, exp));
+            assert!(verifier.verify(&token).is_err());
+
+            let token = sign(&key, "1", claims(ISSUER, "another-client", exp));
+            assert!(verifier.verify(&token).is_err());
+        }
+
+        #[test]
+        fn test_verify_rejects_expired_token() {
+            let key = generate_key();
+            let verifier = verifier(&key);
+
+            // within the leeway
+            let token = sign(&key, "1", claims(ISSUER, AUDIENCE, now() - 30));
+            assert!(verifier.verify(&token).is_ok());
+
+            let token = sign(&key, "1", claims(ISSUER, AUDIENCE, now() - 3600));
+            assert!(verifier.verify(&token).is_err());
+
+            let mut not_yet_valid = claims(ISSUER, AUDIENCE, now() + 7200);
+            not_yet_valid["nbf"] = (now() + 3600).into();
+            let token = sign(&key, "1", not_yet_valid);
+            assert!(verifier.verify(&token).is_err());
+        }
+
+        #[test]
+        fn test_verify_rejects_bad_signature() {
+            let key = generate_key();
+            let verifier = verifier(&key);
+            let exp = now() + 3600;
+
+            // signed by a key outside the JWKS
+            let token = sign(&generate_key(), "1", claims(ISSUER, AUDIENCE, exp));
+            assert!(verifier.verify(&token).is_err());
+
+            // claims altered after signing
+            let token = sign(&key, "1", claims(ISSUER, AUDIENCE, exp));
+            let (_, signature) = token.rsplit_once('.').unwrap();
+            let (header, _) = token.split_once('.').unwrap();
+            let forged = claims(ISSUER, AUDIENCE, exp + 1);
+            let token = format!("{header}.{}.{signature}", encode(forged.to_string()));
+            assert!(verifier.verify(&token).is_err());
+
+            // naming a key absent from the JWKS
+            let token = sign(&key, "2", claims(ISSUER, AUDIENCE, exp));
+            assert!(verifier.verify(&token).is_err());
+        }
+    }
+}
@@ -1,9 +1,12 @@
+use crate::auth::credential_validator::{CredentialValidator, ValidatedUser};
+use crate::auth::oidc::{OidcClaims, OidcVerifier};
 use crate::auth::DeclaredAuthenticationMode;
 use crate::misc::AccountError;
 use crate::server_misc_settings::ServerMiscSettings;
 use bstr::ByteSlice;
 use citadel_crypt::argon::argon_container::{
-    ArgonContainerType, ArgonSettings, ArgonStatus, AsyncArgon, ServerArgonContainer,
+    ArgonContainerType, ArgonCostParameters, ArgonDefaultServerSettings, ArgonSettings,
+    ArgonStatus, AsyncArgon, ServerArgonContainer,
 };
 use citadel_crypt::prelude::SecBuffer;
 use rand::RngCore;
@@ -25,10 +28,34 @@ pub enum ProposedCredentials {
         /// Only existent if the new_register constructor is called. Serialization of this field is skipped since this is only used for clientside
         #[serde(skip)]
         clientside_only_registration_settings: Option<ArgonSettings>,
+        /// The transformed password, retained while registering in case the password must be
+        /// hashed once more under the server's policy. Only used clientside
+        #[serde(skip)]
+        clientside_only_registration_password: Option<SecBuffer>,
     },
 
     /// Denotes that credentials will not be used (passwordless)
     Disabled { username: String },
+
+    /// Denotes that an ID token issued by an OpenID Connect provider will be used
+    Oidc {
+        /// Username of the client, derived from the issuer and subject of the token
+        username: String,
+        /// Full name of the client, as claimed by the token
+        full_name: String,
+        /// The ID token, which the server verifies
+        id_token: SecBuffer,
+    },
+
+    /// Denotes that the server validates the password against an external directory
+    Delegated {
+        /// Username of the client
+        username: String,
+        /// Full name or alternative moniker
+        full_name: String,
+        /// Password (unhashed), since the directory requires it
+        password: SecBuffer,
+    },
 }
 
 // Clientside impls
@@ -43,12 +70,14 @@ impl ProposedCredentials {
         let (username, full_name, password_hashed) =
             Self::sanitize_and_prepare(username, full_name, password_raw.as_ref(), false);
 
-        let password_hashed = Self::argon_hash(password_hashed, settings).await?;
+        let password_hashed =
+            Self::argon_hash(Self::password_transform(password_hashed), settings).await?;
         Ok(Self::Enabled {
             username,
             password_hashed,
             full_name,
             clientside_only_registration_settings: None,
+            clientside_only_registration_password: None,
         })
     }
 
@@ -57,8 +86,42 @@ impl ProposedCredentials {
         Self::Disabled { username }
     }
 
+    /// Generates credentials backed by an ID token obtained from an OpenID Connect provider. The
+    /// same credentials are used for registering and connecting, since the account is created
+    /// the first time the token's subject connects to the node
+    pub fn new_oidc<T: Into<String>>(id_token: T) -> Result<Self, AccountError> {
+        let id_token = id_token.into();
+        let claims = OidcClaims::decode_unverified(&id_token)?;
+        Ok(Self::Oidc {
+            username: claims.username(),
+            full_name: claims.full_name(),
+            id_token: id_token.into_bytes().into(),
+        })
+    }
+
+    /// Generates credentials the server validates against its [`CredentialValidator`], such as
+    /// an LDAP or Active Directory server. The password is transmitted unhashed, albeit within the
+    /// encrypted handshake, since the directory requires it. Trims the username and full name.
+    /// Used for both registering and connecting
+    pub fn new_delegated<T: Into<String>, R: Into<String>>(
+        full_name: T,
+        username: R,
+        password: SecBuffer,
+    ) -> Self {
+        let (username, full_name, password) =
+            Self::sanitize_and_prepare(username, full_name, password.as_ref(), false);
+        Self::Delegated {
+            username,
+            full_name,
+            password,
+        }
+    }
+
     /// Generates the proper registration credentials. Trims the username, password, and full name, removing any whitespace from the ends. Should only be called client-side
     ///
+    /// The password is hashed under the default cost parameters. If the server advertises a more
+    /// costly policy while registering, the password is hashed once more under it
+    ///
     /// 'Whitespace' is defined according to the terms of the Unicode Derived Core Property White_Space.
     pub async fn new_register<T: Into<String> + Send, R: Into<String> + Send>(
         full_name: T,
@@ -67,6 +130,7 @@ impl ProposedCredentials {
     ) -> Result<Self, AccountError> {
         let (username, full_name, password_unhashed) =
             Self::sanitize_and_prepare(username, full_name, password_unhashed.as_ref(), true);
+        let argon_settings = ArgonDefaultServerSettings::default();
 
         // the secret will be stored in the settings which is stored in the CNAC locally clientside
         let secret = &mut [0u8; 32];
@@ -75,29 +139,86 @@ impl ProposedCredentials {
             rng.fill_bytes(secret);
         }
 
-        let settings = ArgonSettings::new_defaults_with_static_secret(
+        let settings = ArgonSettings::new_gen_salt(
             full_name.clone().into_bytes(),
+            argon_settings.lanes,
+            argon_settings.hash_length,
+            argon_settings.mem_cost,
+            argon_settings.time_cost,
             secret.to_vec(),
         );
-        let password_hashed = Self::argon_hash(password_unhashed, settings.clone()).await?;
+        let password_transformed = Self::password_transform(password_unhashed);
+        let password_hashed =
+            Self::argon_hash(password_transformed.clone(), settings.clone()).await?;
         Ok(Self::Enabled {
             username,
             password_hashed,
             full_name,
             clientside_only_registration_settings: Some(settings),
+            clientside_only_registration_password: Some(password_transformed),
         })
     }
 
+    /// Called while registering once the server advertises its policy. If the password was hashed
+    /// under parameters weaker than `policy`, it is hashed once more under the policy
+    pub async fn adopt_argon_policy(
+        self,
+        policy: &ArgonCostParameters,
+    ) -> Result<Self, AccountError> {
+        match self {
+            Self::Enabled {
+                username,
+                full_name,
+                clientside_only_registration_settings: Some(settings),
+                clientside_only_registration_password: Some(password_transformed),
+                ..
+            } if ArgonCostParameters::from(&settings)
+                .satisfies(policy)
+                .is_err() =>
+            {
+                log::info!(target: "citadel", "Hashing the password under the server's policy {policy:?}");
+                let settings = ArgonSettings::new_gen_salt(
+                    settings.ad.clone(),
+                    policy.lanes,
+                    policy.hash_length,
+                    policy.mem_cost,
+                    policy.time_cost,
+                    settings.secret.clone(),
+                );
+                let password_hashed =
+                    Self::argon_hash(password_transformed.clone(), settings.clone()).await?;
+                Ok(Self::Enabled {
+                    username,
+                    password_hashed,
+                    full_name,
+                    clientside_only_registration_settings: Some(settings),
+                    clientside_only_registration_password: Some(password_transformed),
+                })
+            }
+
+            this => Ok(this),
+        }
+    }
+
+    /// The cost parameters the password was hashed under while registering, which the server
+    /// checks against its policy
+    pub fn registration_argon_parameters(&self) -> Option<ArgonCostParameters> {
+        match self {
+            Self::Enabled {
+                clientside_only_registration_settings: Some(settings),
+                ..
+            } => Some(settings.into()),
+            _ => None,
+        }
+    }
+
     async fn argon_hash(
-        password_unhashed: SecBuffer,
+        password_transformed: SecBuffer,
         settings: ArgonSettings,
     ) -> Result<SecBuffer, AccountError> {
-        match AsyncArgon::hash(
-            Self::password_transform(password_unhashed),
-            settings.clone(),
-        )
-        .await
-        .map_err(|err| AccountError::Generic(err.message))?
+        match AsyncArgon::hash(password_transformed, settings.clone())
+            .await
+            .map_err(|err| AccountError::Generic(err.message))?
         {
             ArgonStatus::HashSuccess(ret) => Ok(ret),
             other => Err(AccountError::Generic(format!(
@@ -134,6 +255,7 @@ impl ProposedCredentials {
                 password_hashed,
                 full_name,
                 clientside_only_registration_settings,
+                ..
             } => (
                 username,
                 password_hashed,
@@ -141,6 +263,16 @@ impl ProposedCredentials {
                 clientside_only_registration_settings,
             ),
             Self::Disabled { username } => (username, SecBuffer::empty(), String::new(), None),
+            Self::Oidc {
+                username,
+                full_name,
+                ..
+            } => (username, SecBuffer::empty(), full_name, None),
+            Self::Delegated {
+                username,
+                full_name,
+                password,
+            } => (username, password, full_name, None),
         }
     }
 
@@ -171,6 +303,22 @@ impl ProposedCredentials {
                         .into(),
                 ),
             },
+            Self::Oidc {
+                username,
+                full_name,
+                ..
+            } => DeclaredAuthenticationMode::Oidc {
+                username,
+                full_name,
+            },
+            Self::Delegated {
+                username,
+                full_name,
+                ..
+            } => DeclaredAuthenticationMode::Delegated {
+                username,
+                full_name,
+            },
         }
     }
 
@@ -179,24 +327,75 @@ impl ProposedCredentials {
         matches!(self, Self::Disabled { .. })
     }
 
+    /// Returns true if backed by an OpenID Connect ID token
+    pub fn is_oidc(&self) -> bool {
+        matches!(self, Self::Oidc { .. })
+    }
+
+    /// Returns true if validated by the server's [`CredentialValidator`]
+    pub fn is_delegated(&self) -> bool {
+        matches!(self, Self::Delegated { .. })
+    }
+
     /// Returns the username or uuid of the client
     pub fn username(&self) -> &str {
         match self {
             ProposedCredentials::Enabled { username, .. }
-            | ProposedCredentials::Disabled { username } => username.as_str(),
+            | ProposedCredentials::Disabled { username }
+            | ProposedCredentials::Oidc { username, .. }
+            | ProposedCredentials::Delegated { username, .. } => username.as_str(),
         }
     }
 }
 
 // Serverside impls
 impl ProposedCredentials {
+    /// Ensures a password-based registration was hashed clientside under parameters at least as
+    /// costly as `policy`, which the client declares as `parameters`
+    pub fn check_argon_policy(
+        &self,
+        parameters: Option<&ArgonCostParameters>,
+        policy: &ArgonCostParameters,
+    ) -> Result<(), AccountError> {
+        if !matches!(self, Self::Enabled { .. }) {
+            return Ok(());
+        }
+
+        parameters
+            .ok_or_else(|| {
+                AccountError::msg("The registration does not declare its Argon2 parameters")
+            })?
+            .satisfies(policy)
+            .map_err(AccountError::Generic)
+    }
+
     /// Called when the server registers the client-provided credentials
     pub async fn derive_server_container(
         self,
         server_argon_settings: &ArgonSettings,
         server_misc_settings: &ServerMiscSettings,
+        oidc_verifier: Option<&OidcVerifier>,
+        credential_validator: Option<&dyn CredentialValidator>,
     ) -> Result<DeclaredAuthenticationMode, AccountError> {
         match self {
+            Self::Oidc { .. } => {
+                // the verified claims take precedence over those proposed by the client
+                let claims = self.verify_oidc(oidc_verifier)?;
+                Ok(DeclaredAuthenticationMode::Oidc {
+                    username: claims.username(),
+                    full_name: claims.full_name(),
+                })
+            }
+
+            Self::Delegated { .. } => {
+                let user = self.validate_delegated(credential_validator).await?;
+                let (username, _, full_name, _) = self.decompose();
+                Ok(DeclaredAuthenticationMode::Delegated {
+                    username,
+                    full_name: user.full_name.unwrap_or(full_name),
+                })
+            }
+
             Self::Disabled { .. } => {
                 if server_misc_settings.allow_passwordless {
                     Ok(self.into_auth_store())
@@ -243,6 +442,12 @@ impl ProposedCredentials {
             return Ok(());
         }
 
+        if self.is_oidc() || self.is_delegated() {
+            return Err(AccountError::msg(
+                "Account is not backed by an external identity provider",
+            ));
+        }
+
         let password_hashed = self.decompose().1;
 
         match argon_container {
@@ -273,12 +478,62 @@ impl ProposedCredentials {
         }
     }
 
+    /// Verifies the ID token of OIDC credentials, ensuring it belongs to the proposed username
+    pub fn verify_oidc(
+        &self,
+        oidc_verifier: Option<&OidcVerifier>,
+    ) -> Result<OidcClaims, AccountError> {
+        let (username, id_token) = match self {
+            Self::Oidc {
+                username, id_token, ..
+            } => (username, id_token),
+            _ => {
+                return Err(AccountError::msg(
+                    "Credentials are not backed by an ID token",
+                ))
+            }
+        };
+
+        let oidc_verifier = oidc_verifier.ok_or_else(|| {
+            AccountError::msg("This node does not support OpenID Connect authentication")
+        })?;
+        let id_token = std::str::from_utf8(id_token.as_ref())
+            .map_err(|_| AccountError::msg("Malformed ID token"))?;
+        let claims = oidc_verifier.verify(id_token)?;
+
+        if claims.username() != *username {
+            return Err(AccountError::InvalidUsername);
+        }
+
+        Ok(claims)
+    }
+
+    /// Validates delegated credentials against the directory of `credential_validator`
+    pub async fn validate_delegated(
+        &self,
+        credential_validator: Option<&dyn CredentialValidator>,
+    ) -> Result<ValidatedUser, AccountError> {
+        let credential_validator = credential_validator.ok_or_else(|| {
+            AccountError::msg("This node does not support delegated credential validation")
+        })?;
+
+        match self {
+            Self::Delegated {
+                username, password, ..
+            } => credential_validator.validate(username, password).await,
+            _ => Err(AccountError::msg(
+                "Credentials are not validated by a directory",
+            )),
+        }
+    }
+
     /// Compares usernames for equality
     pub fn compare_username(&self, other: &[u8]) -> bool {
         match self {
-            Self::Disabled { username } | Self::Enabled { username, .. } => {
-                username.as_bytes() == other
-            }
+            Self::Disabled { username }
+            | Self::Enabled { username, .. }
+            | Self::Oidc { username, .. }
+            | Self::Delegated { username, .. } => username.as_bytes() == other,
         }
     }
 }
@@ -0,0 +1,213 @@
+use crate::misc::AccountError;
+use citadel_crypt::chunk_dedup::ChunkHash;
+use parking_lot::Mutex;
+use std::collections::{HashSet, VecDeque};
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+/// A content-addressed store of the chunks of objects received via file transfers. The store is
+/// partitioned by the sender's cid, ensuring a client can neither probe for, nor load, chunks
+/// originating from another client. Once the store exceeds its budget, the oldest chunks are
+/// evicted first
+///
+/// The chunks held are indexed in memory, such that lookups and eviction need not scan the store.
+/// Loading and inserting chunks performs blocking I/O, hence must not be done on an async task
+pub struct ChunkCache {
+    root: PathBuf,
+    max_bytes: u64,
+    index: Mutex<ChunkIndex>,
+}
+
+#[derive(Default)]
+struct ChunkIndex {
+    // oldest first
+    order: VecDeque<(PathBuf, u64)>,
+    held: HashSet<PathBuf>,
+    total: u64,
+}
+
+impl ChunkIndex {
+    fn push(&mut self, path: PathBuf, len: u64) {
+        let _ = self.held.insert(path.clone());
+        self.order.push_back((path, len));
+        self.total += len;
+    }
+
+    /// Removes the oldest chunks from the index until it is within `max_bytes`, returning them
+    fn evict(&mut self, max_bytes: u64) -> Vec<PathBuf> {
+        let mut evicted = Vec::new();
+        while self.total > max_bytes {
+            match self.order.pop_front() {
+                Some((path, len)) => {
+                    let _ = self.held.remove(&path);
+                    self.total -= len;
+                    evicted.push(path);
+                }
+                None => break,
+            }
+        }
+
+        evicted
+    }
+}
+
+impl ChunkCache {
+    /// Indexes the chunks already held under `root`, oldest first
+    pub fn new(root: PathBuf, max_bytes: u64) -> Self {
+        let mut existing = Vec::new();
+        let partitions = std::fs::read_dir(&root).into_iter().flatten().flatten();
+        for partition in partitions {
+            let chunks = std::fs::read_dir(partition.path())
+                .into_iter()
+                .flatten()
+                .flatten();
+            for chunk in chunks {
+                if let Ok(metadata) = chunk.metadata() {
+                    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                    existing.push((modified, chunk.path(), metadata.len()));
+                }
+            }
+        }
+
+        existing.sort_by_key(|(modified, ..)| *modified);
+        let mut index = ChunkIndex::default();
+        for (_, path, len) in existing {
+            index.push(path, len);
+        }
+
+        let this = Self {
+            root,
+            max_bytes,
+            index: Mutex::new(index),
+        };
+
+        // the budget may have been lowered since the chunks were stored
+        let evicted = this.index.lock().evict(max_bytes);
+        remove_chunks(evicted);
+        this
+    }
+
+    /// Returns, for each hash, whether the chunk is held on behalf of `cid`
+    pub fn contains(&self, cid: u64, hashes: &[ChunkHash]) -> Vec<bool> {
+        let index = self.index.lock();
+        hashes
+            .iter()
+            .map(|hash| index.held.contains(&self.chunk_path(cid, hash)))
+            .collect()
+    }
+
+    pub fn load(&self, cid: u64, hash: &ChunkHash) -> Option<Vec<u8>> {
+        std::fs::read(self.chunk_path(cid, hash)).ok()
+    }
+
+    /// Stores `chunk`, whose hash is `hash`, on behalf of `cid`, then evicts the oldest chunks if
+    /// the budget is exceeded
+    pub fn insert(&self, cid: u64, hash: &ChunkHash, chunk: &[u8]) -> Result<(), AccountError> {
+        let path = self.chunk_path(cid, hash);
+        if self.index.lock().held.contains(&path) {
+            return Ok(());
+        }
+
+        std::fs::create_dir_all(self.root.join(cid.to_string()))
+            .and_then(|_| std::fs::write(&path, chunk))
+            .map_err(|err| AccountError::IoError(err.to_string()))?;
+
+        let evicted = {
+            let mut index = self.index.lock();
+            // another insertion of the same chunk may have raced this one
+            if !index.held.contains(&path) {
+                index.push(path, chunk.len() as u64);
+            }
+
+            index.evict(self.max_bytes)
+        };
+
+        if !evicted.is_empty() {
+            log::trace!(target: "citadel", "Evicted {} chunks from the cache", evicted.len());
+            remove_chunks(evicted);
+        }
+
+        Ok(())
+    }
+
+    fn chunk_path(&self, cid: u64, hash: &ChunkHash) -> PathBuf {
+        let name = hash
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        self.root.join(cid.to_string()).join(name)
+    }
+}
+
+fn remove_chunks(paths: Vec<PathBuf>) {
+    for path in paths {
+        if let Err(err) = std::fs::remove_file(&path) {
+            log::warn!(target: "citadel", "Unable to evict chunk {path:?}: {err}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::chunk_cache::ChunkCache;
+    use citadel_crypt::chunk_dedup::chunk_hashes;
+
+    fn insert_all(cache: &ChunkCache, cid: u64, content: &[u8], chunk_size: usize) {
+        for (chunk, hash) in content
+            .chunks(chunk_size)
+            .zip(chunk_hashes(content, chunk_size))
+        {
+            cache.insert(cid, &hash, chunk).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_cache_hits_are_partitioned_by_cid() {
+        let dir = std::env::temp_dir().join(format!("chunk_cache_{}", uuid::Uuid::new_v4()));
+        let cache = ChunkCache::new(dir.clone(), 1024);
+        let content = b"0123456789abcdef".to_vec();
+        let hashes = chunk_hashes(&content, 4);
+
+        assert_eq!(cache.contains(10, &hashes), vec![false; 4]);
+        insert_all(&cache, 10, &content, 4);
+        assert_eq!(cache.contains(10, &hashes), vec![true; 4]);
+        assert_eq!(cache.load(10, &hashes[1]).unwrap(), b"4567");
+
+        // another client can neither probe for, nor load, the chunks
+        assert_eq!(cache.contains(20, &hashes), vec![false; 4]);
+        assert!(cache.load(20, &hashes[1]).is_none());
+
+        // only the chunks held are hits
+        let modified = b"0123XXXX89abcdef".to_vec();
+        assert_eq!(
+            cache.contains(10, &chunk_hashes(&modified, 4)),
+            vec![true, false, true, true]
+        );
+
+        // the index is rebuilt from the chunks on disk
+        let reopened = ChunkCache::new(dir.clone(), 1024);
+        assert_eq!(reopened.contains(10, &hashes), vec![true; 4]);
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn test_cache_evicts_beyond_budget() {
+        let dir = std::env::temp_dir().join(format!("chunk_cache_{}", uuid::Uuid::new_v4()));
+        let cache = ChunkCache::new(dir.clone(), 8);
+        let content = b"0123456789abcdef".to_vec();
+        insert_all(&cache, 10, &content, 4);
+
+        // the oldest chunks are evicted, both from the index and from disk
+        let hashes = chunk_hashes(&content, 4);
+        assert_eq!(cache.contains(10, &hashes), vec![false, false, true, true]);
+        assert!(cache.load(10, &hashes[0]).is_none());
+        assert_eq!(
+            std::fs::read_dir(dir.join("10")).unwrap().count(),
+            2,
+            "Evicted chunks must be removed from disk"
+        );
+
+        let _ = std::fs::remove_dir_all(dir);
+    }
+}
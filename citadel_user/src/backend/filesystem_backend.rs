@@ -1,7 +1,8 @@
 use super::utils::StreamableTargetInformation;
 use crate::account_loader::load_cnac_files;
+use crate::backend::chunk_cache::ChunkCache;
 use crate::backend::memory::MemoryBackend;
-use crate::backend::utils::{ObjectTransferStatus, VirtualObjectMetadata};
+use crate::backend::utils::{ObjectTransferStatus, VirtualDirEntry, VirtualObjectMetadata};
 use crate::backend::BackendConnection;
 use crate::client_account::{ClientNetworkAccount, MutualPeer};
 use crate::directory_store::DirectoryStore;
@@ -9,6 +10,10 @@ use crate::misc::{AccountError, CNACMetadata};
 use crate::prelude::CNAC_SERIALIZED_EXTENSION;
 use crate::serialization::SyncIO;
 use async_trait::async_trait;
+use citadel_crypt::chunk_dedup::{
+    apply_deduplicated, hash_chunk, ChunkHash, ChunkSplitter, DEFAULT_CACHE_CHUNK_SIZE,
+};
+use citadel_crypt::delta_sync::{apply_delta, FileSignature};
 use citadel_crypt::misc::TransferType;
 use citadel_crypt::prelude::SecurityLevel;
 use citadel_crypt::stacked_ratchet::Ratchet;
@@ -24,8 +29,14 @@ pub struct FilesystemBackend<R: Ratchet, Fcm: Ratchet> {
     memory_backend: MemoryBackend<R, Fcm>,
     directory_store: Option<DirectoryStore>,
     home_dir: String,
+    chunk_cache_max_bytes: Option<u64>,
+    chunk_cache: Option<Arc<ChunkCache>>,
 }
 
+/// The number of received messages that may await caching before the rest of an inbound object
+/// is no longer cached
+const CHUNK_CACHE_BACKLOG: usize = 64;
+
 #[async_trait]
 impl<R: Ratchet, Fcm: Ratchet> BackendConnection<R, Fcm> for FilesystemBackend<R, Fcm> {
     async fn connect(&mut self) -> Result<(), AccountError> {
@@ -33,6 +44,12 @@ impl<R: Ratchet, Fcm: Ratchet> BackendConnection<R, Fcm> for FilesystemBackend<R
         let map = load_cnac_files(&directory_store)?;
         // ensure the in-memory database has the clients loaded
         *self.memory_backend.clients.get_mut() = map;
+        self.chunk_cache = self.chunk_cache_max_bytes.map(|max_bytes| {
+            Arc::new(ChunkCache::new(
+                PathBuf::from(&directory_store.chunk_cache_dir),
+                max_bytes,
+            ))
+        });
         self.directory_store = Some(directory_store);
 
         Ok(())
@@ -346,13 +363,56 @@ impl<R: Ratchet, Fcm: Ratchet> BackendConnection<R, Fcm> for FilesystemBackend<R
             TransferType::RemoteEncryptedVirtualFilesystem { .. }
         );
         let metadata = sink_metadata.get_metadata_file().clone();
-        let file_path = get_file_path(
-            sink_metadata.get_cid(),
+        let cid = sink_metadata.get_cid();
+        let file_path = match sink_metadata.get_destination() {
+            // only objects outside the RE-VFS may be redirected
+            Some(destination) if !is_virtual_file => {
+                if let Some(parent) = destination.parent() {
+                    tokio::fs::create_dir_all(parent)
+                        .await
+                        .map_err(|err| AccountError::IoError(err.to_string()))?;
+                }
+
+                destination.to_path_buf()
+            }
+
+            _ => {
+                get_file_path(
+                    cid,
+                    sink_metadata.get_transfer_type(),
+                    directory_store,
+                    Some(metadata.name.as_str()),
+                )
+                .await?
+            }
+        };
+
+        if matches!(
             sink_metadata.get_transfer_type(),
-            directory_store,
-            Some(metadata.name.as_str()),
-        )
-        .await?;
+            TransferType::DeltaSync { .. }
+        ) {
+            let _ = status_tx.send(ObjectTransferStatus::ReceptionBeginning(
+                file_path.clone(),
+                sink_metadata,
+            ));
+            return apply_streamed_delta(source, &file_path).await;
+        }
+
+        if matches!(
+            sink_metadata.get_transfer_type(),
+            TransferType::Deduplicated { .. }
+        ) {
+            let chunk_cache = self.chunk_cache.clone().ok_or_else(|| {
+                AccountError::msg(
+                    "Received a deduplicated transfer, yet, the chunk cache is disabled",
+                )
+            })?;
+            let _ = status_tx.send(ObjectTransferStatus::ReceptionBeginning(
+                file_path.clone(),
+                sink_metadata,
+            ));
+            return apply_streamed_deduplicated(source, &file_path, chunk_cache, cid).await;
+        }
 
         log::info!(target: "citadel", "Will stream object to {file_path:?}");
         let file = tokio::fs::File::create(&file_path)
@@ -364,11 +424,26 @@ impl<R: Ratchet, Fcm: Ratchet> BackendConnection<R, Fcm> for FilesystemBackend<R
             sink_metadata,
         ));
 
+        // the chunks are cached as they arrive, so that future transfers of identical content
+        // short-circuit
+        let mut cacher = self
+            .chunk_cache
+            .clone()
+            .filter(|_| !is_virtual_file)
+            .map(|chunk_cache| spawn_chunk_cacher(chunk_cache, cid));
+
         let mut size = 0;
         let mut writer = tokio::io::BufWriter::new(file);
         let mut reader = tokio_util::io::StreamReader::new(
             tokio_stream::wrappers::UnboundedReceiverStream::new(source).map(|r| {
                 size += r.len();
+                if let Some(tx) = cacher.as_ref().filter(|_| !r.is_empty()) {
+                    if tx.try_send(r.clone()).is_err() {
+                        log::trace!(target: "citadel", "The chunk cache fell behind; the rest of the object will not be cached");
+                        cacher = None;
+                    }
+                }
+
                 Ok(std::io::Cursor::new(r)) as Result<std::io::Cursor<Vec<u8>>, std::io::Error>
             }),
         );
@@ -382,15 +457,27 @@ impl<R: Ratchet, Fcm: Ratchet> BackendConnection<R, Fcm> for FilesystemBackend<R
                 .map_err(|err| AccountError::IoError(err.to_string()))?
         }
 
-        if let Err(err) = tokio::io::copy(&mut reader, &mut writer).await {
-            log::error!(target: "citadel", "Error while copying from reader to writer: {}", err);
+        let copied = tokio::io::copy(&mut reader, &mut writer).await;
+        drop(reader);
+        match copied {
+            // an empty message marks the end of the object, such that its last chunk is cached
+            Ok(_) => {
+                if let Some(tx) = cacher {
+                    let _ = tx.try_send(Vec::new());
+                }
+            }
+            Err(err) => {
+                log::error!(target: "citadel", "Error while copying from reader to writer: {}", err);
+            }
         }
 
         writer
             .into_inner()
             .sync_all()
             .await
-            .map_err(|err| AccountError::IoError(err.to_string()))
+            .map_err(|err| AccountError::IoError(err.to_string()))?;
+
+        Ok(())
     }
 
     async fn revfs_get_file_info(
@@ -444,9 +531,139 @@ impl<R: Ratchet, Fcm: Ratchet> BackendConnection<R, Fcm> for FilesystemBackend<R
 
         delete_paths(&[metadata_path, file_path]).await
     }
+
+    async fn revfs_create_dir(
+        &self,
+        cid: u64,
+        virtual_dir: std::path::PathBuf,
+    ) -> Result<(), AccountError> {
+        let directory_store = self.directory_store.as_ref().unwrap();
+        let dir_path = get_virtual_dir_path(cid, &virtual_dir, directory_store)?;
+        tokio::fs::create_dir_all(&dir_path)
+            .await
+            .map_err(|err| AccountError::IoError(err.to_string()))
+    }
+
+    async fn revfs_list(
+        &self,
+        cid: u64,
+        virtual_dir: std::path::PathBuf,
+    ) -> Result<Vec<VirtualDirEntry>, AccountError> {
+        let directory_store = self.directory_store.as_ref().unwrap();
+        let virtual_dir = crate::misc::prepare_virtual_path(virtual_dir);
+        let dir_path = get_virtual_dir_path(cid, &virtual_dir, directory_store)?;
+        let mut read_dir = match tokio::fs::read_dir(&dir_path).await {
+            Ok(read_dir) => read_dir,
+            // the root directory is only created once the first file is pushed
+            Err(err)
+                if err.kind() == std::io::ErrorKind::NotFound && virtual_dir.parent().is_none() =>
+            {
+                return Ok(Vec::new())
+            }
+            Err(err) => return Err(AccountError::IoError(err.to_string())),
+        };
+
+        let mut entries = Vec::new();
+        while let Some(entry) = read_dir
+            .next_entry()
+            .await
+            .map_err(|err| AccountError::IoError(err.to_string()))?
+        {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // metadata files are an implementation detail of this backend
+            if name.ends_with(crate::misc::VIRTUAL_FILE_METADATA_EXT) {
+                continue;
+            }
+
+            let is_dir = entry
+                .file_type()
+                .await
+                .map_err(|err| AccountError::IoError(err.to_string()))?
+                .is_dir();
+
+            let metadata = if is_dir {
+                None
+            } else {
+                tokio::fs::read(get_revfs_file_metadata_path(entry.path()))
+                    .await
+                    .ok()
+                    .and_then(VirtualObjectMetadata::deserialize_from)
+            };
+
+            entries.push(VirtualDirEntry {
+                virtual_path: virtual_dir.join(&name),
+                name,
+                is_dir,
+                metadata,
+            });
+        }
+
+        entries.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(entries)
+    }
+
+    async fn get_stored_object(
+        &self,
+        cid: u64,
+        name: &str,
+    ) -> Result<Box<dyn ObjectSource>, AccountError> {
+        let file_path = get_stored_object_path(cid, name, self.directory_store.as_ref().unwrap())?;
+        if !tokio::fs::try_exists(&file_path)
+            .await
+            .map_err(|err| AccountError::IoError(err.to_string()))?
+        {
+            return Err(AccountError::msg(format!("Object {name} does not exist")));
+        }
+
+        Ok(Box::new(file_path))
+    }
+
+    async fn delete_stored_object(&self, cid: u64, name: &str) -> Result<(), AccountError> {
+        let file_path = get_stored_object_path(cid, name, self.directory_store.as_ref().unwrap())?;
+        delete_paths(&[file_path]).await
+    }
+
+    async fn get_cached_chunks(
+        &self,
+        cid: u64,
+        hashes: &[ChunkHash],
+    ) -> Result<Option<Vec<bool>>, AccountError> {
+        match self.chunk_cache.as_ref() {
+            Some(chunk_cache) => Ok(Some(chunk_cache.contains(cid, hashes))),
+            None => Ok(None),
+        }
+    }
+
+    async fn get_object_signature(
+        &self,
+        cid: u64,
+        name: &str,
+        block_size: usize,
+    ) -> Result<Option<FileSignature>, AccountError> {
+        let file_path = get_stored_object_path(cid, name, self.directory_store.as_ref().unwrap())?;
+        let basis = match std::fs::File::open(&file_path) {
+            Ok(basis) => basis,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(AccountError::IoError(err.to_string())),
+        };
+
+        citadel_io::spawn_blocking(move || {
+            FileSignature::generate_from_reader(std::io::BufReader::new(basis), block_size)
+        })
+        .await
+        .map_err(|err| AccountError::IoError(err.message))?
+        .map(Some)
+        .map_err(|err| AccountError::IoError(err.into_string()))
+    }
 }
 
 impl<R: Ratchet, Fcm: Ratchet> FilesystemBackend<R, Fcm> {
+    /// Enables the chunk cache for deduplicated transfers, bounded to `max_bytes`
+    pub fn with_chunk_cache(mut self, max_bytes: Option<u64>) -> Self {
+        self.chunk_cache_max_bytes = max_bytes;
+        self
+    }
+
     async fn save_cnac_by_cid(&self, cid: u64) -> Result<(), AccountError> {
         let cnac = self
             .memory_backend
@@ -484,6 +701,8 @@ impl<R: Ratchet, Fcm: Ratchet> From<String> for FilesystemBackend<R, Fcm> {
             home_dir,
             memory_backend: MemoryBackend::default(),
             directory_store: None,
+            chunk_cache_max_bytes: None,
+            chunk_cache: None,
         }
     }
 }
@@ -496,7 +715,9 @@ async fn get_file_path(
     target_name: Option<&str>,
 ) -> Result<PathBuf, AccountError> {
     match transfer_type {
-        TransferType::FileTransfer => {
+        TransferType::FileTransfer
+        | TransferType::DeltaSync { .. }
+        | TransferType::Deduplicated { .. } => {
             // TODO: ensure for sources that come from bytes, the name is randomly generated
             // to prevent collisions
             let name = target_name.ok_or_else(|| {
@@ -536,6 +757,197 @@ async fn get_file_path(
     }
 }
 
+/// Reconstructs the object from the copy at `file_path` while the encoded delta streams from the
+/// sender, writing into a sibling file, which replaces the copy once verified
+async fn apply_streamed_delta(
+    source: UnboundedReceiver<Vec<u8>>,
+    file_path: &Path,
+) -> Result<(), AccountError> {
+    let basis_path = file_path.to_path_buf();
+    let reconstructed_path = sibling_path(file_path, ".delta");
+
+    let literal_len = citadel_io::spawn_blocking(move || {
+        let reconstruct = || -> Result<u64, AccountError> {
+            let basis = std::fs::File::open(&basis_path)
+                .map_err(|err| AccountError::IoError(err.to_string()))?;
+            let reconstructed = std::fs::File::create(&reconstructed_path)
+                .map_err(|err| AccountError::IoError(err.to_string()))?;
+            let literal_len = apply_delta(
+                ChannelReader::new(source),
+                std::io::BufReader::new(basis),
+                std::io::BufWriter::new(reconstructed),
+            )
+            .map_err(|err| AccountError::IoError(err.into_string()))?;
+            std::fs::rename(&reconstructed_path, &basis_path)
+                .map_err(|err| AccountError::IoError(err.to_string()))?;
+            Ok(literal_len)
+        };
+
+        let res = reconstruct();
+        if res.is_err() {
+            let _ = std::fs::remove_file(&reconstructed_path);
+        }
+
+        res
+    })
+    .await
+    .map_err(|err| AccountError::IoError(err.message))??;
+
+    log::info!(target: "citadel", "Reconstructed {file_path:?} from a delta with {literal_len} literal bytes");
+    Ok(())
+}
+
+/// Reconstructs the object streamed from the sender into a sibling file, which replaces any prior
+/// copy once verified. The chunks the object references are loaded from the chunk cache, while
+/// those transmitted verbatim are added to it
+async fn apply_streamed_deduplicated(
+    source: UnboundedReceiver<Vec<u8>>,
+    file_path: &Path,
+    chunk_cache: Arc<ChunkCache>,
+    cid: u64,
+) -> Result<(), AccountError> {
+    let target_path = file_path.to_path_buf();
+    let reconstructed_path = sibling_path(file_path, ".dedup");
+
+    let literal_len = citadel_io::spawn_blocking(move || {
+        let reconstruct = || -> Result<u64, AccountError> {
+            let reconstructed = std::fs::File::create(&reconstructed_path)
+                .map_err(|err| AccountError::IoError(err.to_string()))?;
+            let literal_len = apply_deduplicated(
+                ChannelReader::new(source),
+                std::io::BufWriter::new(reconstructed),
+                |hash| chunk_cache.load(cid, hash),
+                |hash, chunk| {
+                    if let Err(err) = chunk_cache.insert(cid, hash, chunk) {
+                        log::warn!(target: "citadel", "Unable to cache chunk: {err:?}");
+                    }
+                },
+            )
+            .map_err(|err| AccountError::IoError(err.into_string()))?;
+            std::fs::rename(&reconstructed_path, &target_path)
+                .map_err(|err| AccountError::IoError(err.to_string()))?;
+            Ok(literal_len)
+        };
+
+        let res = reconstruct();
+        if res.is_err() {
+            let _ = std::fs::remove_file(&reconstructed_path);
+        }
+
+        res
+    })
+    .await
+    .map_err(|err| AccountError::IoError(err.message))??;
+
+    log::info!(target: "citadel", "Reconstructed {file_path:?} from the chunk cache with {literal_len} literal bytes");
+    Ok(())
+}
+
+/// Spawns a blocking task that caches the chunks of the messages it is sent, up to an empty
+/// message marking the end of the object. Caching is best-effort: once the returned sender is
+/// full, the caller stops caching the object instead of buffering it
+fn spawn_chunk_cacher(
+    chunk_cache: Arc<ChunkCache>,
+    cid: u64,
+) -> std::sync::mpsc::SyncSender<Vec<u8>> {
+    let (tx, rx) = std::sync::mpsc::sync_channel::<Vec<u8>>(CHUNK_CACHE_BACKLOG);
+    std::mem::drop(citadel_io::spawn_blocking(move || {
+        let insert = |chunk: &[u8]| {
+            if let Err(err) = chunk_cache.insert(cid, &hash_chunk(chunk), chunk) {
+                log::warn!(target: "citadel", "Unable to cache chunk: {err:?}");
+            }
+        };
+
+        let mut splitter = ChunkSplitter::new(DEFAULT_CACHE_CHUNK_SIZE);
+        for bytes in rx {
+            if bytes.is_empty() {
+                // the trailing partial chunk is only cached once the object is complete
+                splitter.finish(insert);
+                return;
+            }
+
+            splitter.push(&bytes, &insert);
+        }
+    }));
+
+    tx
+}
+
+/// Reads the bytes of an inbound object as they are streamed from the sender. Reading blocks,
+/// hence must only be done on a blocking task
+struct ChannelReader {
+    source: UnboundedReceiver<Vec<u8>>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl ChannelReader {
+    fn new(source: UnboundedReceiver<Vec<u8>>) -> Self {
+        Self {
+            source,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+}
+
+impl std::io::Read for ChannelReader {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        while self.pos == self.buf.len() {
+            match self.source.blocking_recv() {
+                Some(buf) => {
+                    self.buf = buf;
+                    self.pos = 0;
+                }
+                None => return Ok(0),
+            }
+        }
+
+        let len = out.len().min(self.buf.len() - self.pos);
+        out[..len].copy_from_slice(&self.buf[self.pos..self.pos + len]);
+        self.pos += len;
+        Ok(len)
+    }
+}
+
+/// Returns the path of a file next to `path`, named with the given suffix
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_os_string();
+    sibling.push(suffix);
+    PathBuf::from(sibling)
+}
+
+fn get_stored_object_path(
+    cid: u64,
+    name: &str,
+    directory_store: &DirectoryStore,
+) -> Result<PathBuf, AccountError> {
+    let mut components = Path::new(name).components();
+    if !matches!(
+        (components.next(), components.next()),
+        (Some(std::path::Component::Normal(_)), None)
+    ) {
+        return Err(AccountError::msg(format!("Invalid object name {name}")));
+    }
+
+    let save_path = directory_store.file_transfer_dir.as_str();
+    Ok(PathBuf::from(format!("{save_path}{cid}")).join(name))
+}
+
+fn get_virtual_dir_path(
+    source_cid: u64,
+    virtual_dir: &Path,
+    directory_store: &DirectoryStore,
+) -> Result<PathBuf, AccountError> {
+    let virtual_dir = crate::misc::prepare_virtual_path(virtual_dir);
+    crate::misc::validate_virtual_directory(&virtual_dir)?;
+    let save_path = directory_store.virtual_dir.as_str();
+    Ok(PathBuf::from(format!(
+        "{save_path}{source_cid}{}",
+        virtual_dir.display()
+    )))
+}
+
 fn get_revfs_file_metadata_path<P: AsRef<Path>>(path: P) -> PathBuf {
     let mut metadata_path = format!("{}", path.as_ref().display());
     metadata_path.push_str(crate::misc::VIRTUAL_FILE_METADATA_EXT);
@@ -5,6 +5,8 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 
+use citadel_crypt::chunk_dedup::ChunkHash;
+use citadel_crypt::delta_sync::FileSignature;
 use citadel_crypt::fcm::fcm_ratchet::ThinRatchet;
 use citadel_crypt::stacked_ratchet::{Ratchet, StackedRatchet};
 
@@ -13,13 +15,18 @@ use crate::backend::mysql_backend::SqlConnectionOptions;
 #[cfg(all(feature = "redis", not(coverage)))]
 use crate::backend::redis_backend::RedisConnectionOptions;
 use crate::backend::utils::misc::StreamableTargetInformation;
-use crate::backend::utils::ObjectTransferStatus;
+use crate::backend::utils::{ObjectTransferStatus, SharedObjectPermission, VirtualDirEntry};
 use crate::client_account::{ClientNetworkAccount, MutualPeer};
 use crate::misc::{AccountError, CNACMetadata};
+use crate::permissions::AccountPermissions;
+use crate::serialization::SyncIO;
 use citadel_crypt::prelude::SecurityLevel;
 use citadel_crypt::streaming_crypt_scrambler::ObjectSource;
 use tokio::sync::mpsc::UnboundedSender;
 
+/// A content-addressed cache of received chunks, used by the filesystem backend
+#[cfg(all(feature = "filesystem", not(target_family = "wasm")))]
+pub mod chunk_cache;
 /// Implementation for the default filesystem backend
 #[cfg(all(feature = "filesystem", not(target_family = "wasm")))]
 pub mod filesystem_backend;
@@ -314,8 +321,258 @@ pub trait BackendConnection<R: Ratchet, Fcm: Ratchet>: Send + Sync {
             "The target does not support the RE-VFS protocol".into(),
         ))
     }
+    /// Creates a directory, and any missing parents, inside the virtual filesystem
+    #[allow(unused_variables)]
+    async fn revfs_create_dir(
+        &self,
+        cid: u64,
+        virtual_dir: std::path::PathBuf,
+    ) -> Result<(), AccountError> {
+        Err(AccountError::Generic(
+            "The target does not support the RE-VFS protocol".into(),
+        ))
+    }
+    /// Lists the files and directories directly inside `virtual_dir`
+    #[allow(unused_variables)]
+    async fn revfs_list(
+        &self,
+        cid: u64,
+        virtual_dir: std::path::PathBuf,
+    ) -> Result<Vec<VirtualDirEntry>, AccountError> {
+        Err(AccountError::Generic(
+            "The target does not support the RE-VFS protocol".into(),
+        ))
+    }
+    /// Grants `peer_cid` access to `virtual_dir`, and everything below it, inside the
+    /// virtual filesystem of `cid`
+    async fn revfs_grant_access(
+        &self,
+        cid: u64,
+        virtual_dir: std::path::PathBuf,
+        peer_cid: u64,
+    ) -> Result<(), AccountError> {
+        let virtual_dir = crate::misc::prepare_virtual_path(virtual_dir);
+        crate::misc::validate_virtual_directory(&virtual_dir)?;
+        if !self.hyperlan_peer_exists(cid, peer_cid).await? {
+            return Err(AccountError::msg(format!(
+                "Peer {peer_cid} is not registered to {cid}"
+            )));
+        }
+
+        self.store_byte_map_value(
+            cid,
+            peer_cid,
+            REVFS_GRANTS_KEY,
+            &virtual_dir.display().to_string(),
+            Vec::new(),
+        )
+        .await
+        .map(|_| ())
+    }
+    /// Revokes a grant given by [`BackendConnection::revfs_grant_access`]
+    async fn revfs_revoke_access(
+        &self,
+        cid: u64,
+        virtual_dir: std::path::PathBuf,
+        peer_cid: u64,
+    ) -> Result<(), AccountError> {
+        let virtual_dir = crate::misc::prepare_virtual_path(virtual_dir);
+        self.remove_byte_map_value(
+            cid,
+            peer_cid,
+            REVFS_GRANTS_KEY,
+            &virtual_dir.display().to_string(),
+        )
+        .await?
+        .map(|_| ())
+        .ok_or_else(|| {
+            AccountError::msg(format!(
+                "Peer {peer_cid} was not granted access to {virtual_dir:?}"
+            ))
+        })
+    }
+    /// Determines if `peer_cid` was granted access to `virtual_dir`, or one of its parents,
+    /// inside the virtual filesystem of `cid`
+    async fn revfs_has_access(
+        &self,
+        cid: u64,
+        virtual_dir: std::path::PathBuf,
+        peer_cid: u64,
+    ) -> Result<bool, AccountError> {
+        let virtual_dir = crate::misc::prepare_virtual_path(virtual_dir);
+        crate::misc::validate_virtual_directory(&virtual_dir)?;
+        let grants = self
+            .get_byte_map_values_by_key(cid, peer_cid, REVFS_GRANTS_KEY)
+            .await?;
+        Ok(grants
+            .keys()
+            .any(|granted| virtual_dir.starts_with(granted)))
+    }
+    /// Returns the cid whose virtual filesystem a request from `requester_cid` operates on.
+    /// Requests naming another owner are refused unless the owner granted the requester access
+    /// to `virtual_path`, or one of its parents
+    async fn revfs_resolve_owner(
+        &self,
+        requester_cid: u64,
+        owner_cid: Option<u64>,
+        virtual_path: std::path::PathBuf,
+    ) -> Result<u64, AccountError> {
+        match owner_cid.filter(|cid| *cid != requester_cid) {
+            Some(owner_cid) => {
+                if self
+                    .revfs_has_access(owner_cid, virtual_path.clone(), requester_cid)
+                    .await?
+                {
+                    Ok(owner_cid)
+                } else {
+                    Err(AccountError::msg(format!(
+                        "{owner_cid} has not granted access to {virtual_path:?}"
+                    )))
+                }
+            }
+
+            None => Ok(requester_cid),
+        }
+    }
+    /// Returns a named object previously uploaded by `cid` via a standard file transfer
+    #[allow(unused_variables)]
+    async fn get_stored_object(
+        &self,
+        cid: u64,
+        name: &str,
+    ) -> Result<Box<dyn ObjectSource>, AccountError> {
+        Err(AccountError::Generic(
+            "The target does not support stored objects".into(),
+        ))
+    }
+    /// Deletes a named object previously uploaded by `cid` via a standard file transfer
+    #[allow(unused_variables)]
+    async fn delete_stored_object(&self, cid: u64, name: &str) -> Result<(), AccountError> {
+        Err(AccountError::Generic(
+            "The target does not support stored objects".into(),
+        ))
+    }
+    /// Computes the block signature of the object `name` previously received from `cid`, allowing
+    /// the sender to transmit only the blocks that changed. Returns None if no such object exists,
+    /// in which case the sender falls back to a full transfer
+    #[allow(unused_variables)]
+    async fn get_object_signature(
+        &self,
+        cid: u64,
+        name: &str,
+        block_size: usize,
+    ) -> Result<Option<FileSignature>, AccountError> {
+        Ok(None)
+    }
+    /// Returns, for each chunk hash, whether the chunk is held in the chunk cache on behalf of
+    /// `cid`. Returns None if the backend does not cache chunks, in which case the sender falls
+    /// back to a full transfer
+    #[allow(unused_variables)]
+    async fn get_cached_chunks(
+        &self,
+        cid: u64,
+        hashes: &[ChunkHash],
+    ) -> Result<Option<Vec<bool>>, AccountError> {
+        Ok(None)
+    }
+    /// Shares the stored object `name` owned by `owner_cid` with `grantee_cid`. Granting
+    /// again replaces the previous permission
+    async fn share_object(
+        &self,
+        owner_cid: u64,
+        name: &str,
+        grantee_cid: u64,
+        permission: SharedObjectPermission,
+    ) -> Result<(), AccountError> {
+        if !self.hyperlan_peer_exists(owner_cid, grantee_cid).await? {
+            return Err(AccountError::msg(format!(
+                "Peer {grantee_cid} is not registered to {owner_cid}"
+            )));
+        }
+
+        // ensure the object exists before sharing it
+        let _ = self.get_stored_object(owner_cid, name).await?;
+        self.store_byte_map_value(
+            owner_cid,
+            grantee_cid,
+            SHARED_OBJECTS_KEY,
+            name,
+            permission.serialize_to_vector()?,
+        )
+        .await
+        .map(|_| ())
+    }
+    /// Revokes a grant given by [`BackendConnection::share_object`]
+    async fn unshare_object(
+        &self,
+        owner_cid: u64,
+        name: &str,
+        grantee_cid: u64,
+    ) -> Result<(), AccountError> {
+        self.remove_byte_map_value(owner_cid, grantee_cid, SHARED_OBJECTS_KEY, name)
+            .await?
+            .map(|_| ())
+            .ok_or_else(|| {
+                AccountError::msg(format!("Object {name} is not shared with {grantee_cid}"))
+            })
+    }
+    /// Returns the permission `requester_cid` has on the stored object `name` owned by `owner_cid`.
+    /// Owners always have full access to their own objects
+    async fn get_shared_object_permission(
+        &self,
+        owner_cid: u64,
+        name: &str,
+        requester_cid: u64,
+    ) -> Result<Option<SharedObjectPermission>, AccountError> {
+        if owner_cid == requester_cid {
+            return Ok(Some(SharedObjectPermission::ReadWrite));
+        }
+
+        self.get_byte_map_value(owner_cid, requester_cid, SHARED_OBJECTS_KEY, name)
+            .await?
+            .map(SharedObjectPermission::deserialize_from_owned_vector)
+            .transpose()
+    }
+    /// Returns the permissions of `cid`, or the default permissions if none were set
+    async fn get_account_permissions(&self, cid: u64) -> Result<AccountPermissions, AccountError> {
+        Ok(self
+            .get_byte_map_value(cid, 0, ACCOUNT_PERMISSIONS_KEY, ACCOUNT_PERMISSIONS_KEY)
+            .await?
+            .map(AccountPermissions::deserialize_from_owned_vector)
+            .transpose()?
+            .unwrap_or_default())
+    }
+    /// Replaces the permissions of `cid`
+    async fn set_account_permissions(
+        &self,
+        cid: u64,
+        permissions: AccountPermissions,
+    ) -> Result<(), AccountError> {
+        if !self.cid_is_registered(cid).await? {
+            return Err(AccountError::ClientNonExists(cid));
+        }
+
+        self.store_byte_map_value(
+            cid,
+            0,
+            ACCOUNT_PERMISSIONS_KEY,
+            ACCOUNT_PERMISSIONS_KEY,
+            permissions.serialize_to_vector()?,
+        )
+        .await
+        .map(|_| ())
+    }
 }
 
+/// The byte map key under which shared object grants are stored
+const SHARED_OBJECTS_KEY: &str = "_shared_objects";
+
+/// The byte map key under which RE-VFS access grants are stored
+const REVFS_GRANTS_KEY: &str = "_revfs_grants";
+
+/// The byte map key under which the permissions of an account are stored
+const ACCOUNT_PERMISSIONS_KEY: &str = "_account_permissions";
+
 /// This is what every C/NAC gets. This gets called before making I/O operations
 pub struct PersistenceHandler<R: Ratchet = StackedRatchet, Fcm: Ratchet = ThinRatchet> {
     inner: Arc<dyn BackendConnection<R, Fcm>>,
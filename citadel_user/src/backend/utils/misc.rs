@@ -1,6 +1,7 @@
 use crate::backend::utils::VirtualObjectMetadata;
 use citadel_crypt::misc::TransferType;
 use std::fmt::Debug;
+use std::path::Path;
 
 /// Used for determining location
 pub trait StreamableTargetInformation: Debug + Send + Sync + 'static {
@@ -12,4 +13,9 @@ pub trait StreamableTargetInformation: Debug + Send + Sync + 'static {
     /// Returns the
     fn get_transfer_type(&self) -> &TransferType;
     fn get_metadata_file(&self) -> &VirtualObjectMetadata;
+    /// Overrides the location the backend would otherwise choose, if the backend stores objects on
+    /// the local filesystem
+    fn get_destination(&self) -> Option<&Path> {
+        None
+    }
 }
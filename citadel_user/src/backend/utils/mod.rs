@@ -2,12 +2,15 @@ use crate::serialization::SyncIO;
 use futures::Stream;
 pub use misc::StreamableTargetInformation;
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::fmt::Formatter;
-use std::path::PathBuf;
+use std::path::{Component, Path, PathBuf};
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 use crate::misc::AccountError;
+use async_trait::async_trait;
 use citadel_crypt::misc::TransferType;
 use citadel_crypt::prelude::SecurityLevel;
 use std::sync::Arc;
@@ -39,7 +42,9 @@ impl VirtualObjectMetadata {
 
     pub fn get_security_level(&self) -> Option<SecurityLevel> {
         match &self.transfer_type {
-            TransferType::FileTransfer => None,
+            TransferType::FileTransfer
+            | TransferType::DeltaSync { .. }
+            | TransferType::Deduplicated { .. } => None,
             TransferType::RemoteEncryptedVirtualFilesystem { security_level, .. } => {
                 Some(*security_level)
             }
@@ -62,6 +67,226 @@ impl StreamableTargetInformation for VirtualObjectMetadata {
     }
 }
 
+/// The metadata of an inbound object that the local node chose to save at a specific path
+#[derive(Debug)]
+pub struct RedirectedObjectMetadata {
+    pub metadata: VirtualObjectMetadata,
+    pub destination: PathBuf,
+}
+
+impl RedirectedObjectMetadata {
+    /// Ensures the destination names a file, and does not traverse upwards via `..`. The
+    /// destination is chosen locally, but is commonly derived from the untrusted name of the object
+    pub fn check_destination(destination: &Path) -> Result<(), AccountError> {
+        let traverses_upwards = destination
+            .components()
+            .any(|component| component == Component::ParentDir);
+        if traverses_upwards || destination.file_name().is_none() {
+            return Err(AccountError::msg(format!(
+                "Invalid destination {}",
+                destination.display()
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+impl StreamableTargetInformation for RedirectedObjectMetadata {
+    fn get_target_name(&self) -> &String {
+        &self.metadata.name
+    }
+    fn get_cid(&self) -> u64 {
+        self.metadata.cid
+    }
+    fn get_transfer_type(&self) -> &TransferType {
+        &self.metadata.transfer_type
+    }
+    fn get_metadata_file(&self) -> &VirtualObjectMetadata {
+        &self.metadata
+    }
+    fn get_destination(&self) -> Option<&Path> {
+        Some(&self.destination)
+    }
+}
+
+/// Describes an object that an adjacent node proposes to send to this node
+#[derive(Debug, Clone)]
+pub struct InboundTransferProposal {
+    pub source_cid: u64,
+    /// The name of the object as chosen by the sender, and therefore untrusted. It is reduced to
+    /// its final path component, such that it may be joined onto a directory without escaping it.
+    /// Names without one, such as `..`, are replaced with [`UNNAMED_OBJECT`]
+    pub name: String,
+    pub size: usize,
+    /// Inferred from the extension of the name, if recognized
+    pub content_type: Option<&'static str>,
+    pub transfer_type: TransferType,
+}
+
+impl InboundTransferProposal {
+    pub fn new(source_cid: u64, metadata: &VirtualObjectMetadata) -> Self {
+        let name = sanitize_object_name(&metadata.name);
+        Self {
+            source_cid,
+            content_type: guess_content_type(&name),
+            name,
+            size: metadata.plaintext_length,
+            transfer_type: metadata.transfer_type.clone(),
+        }
+    }
+}
+
+/// The name given to proposed objects whose name has no final path component
+pub const UNNAMED_OBJECT: &str = "unnamed";
+
+/// Reduces `name` to a single [`Component::Normal`]
+fn sanitize_object_name(name: &str) -> String {
+    match Path::new(name).components().next_back() {
+        Some(Component::Normal(name)) => name.to_string_lossy().into_owned(),
+        _ => UNNAMED_OBJECT.to_string(),
+    }
+}
+
+fn guess_content_type(name: &str) -> Option<&'static str> {
+    let extension = Path::new(name).extension()?.to_str()?.to_ascii_lowercase();
+    let content_type = match extension.as_str() {
+        "txt" | "log" => "text/plain",
+        "html" | "htm" => "text/html",
+        "csv" => "text/csv",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "tar" => "application/x-tar",
+        "exe" | "dll" => "application/vnd.microsoft.portable-executable",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        _ => return None,
+    };
+
+    Some(content_type)
+}
+
+/// Inspects an inbound object once every byte has been received, but before the transfer
+/// completes. Useful for virus-scanning objects prior to accepting them
+#[async_trait]
+pub trait ObjectScanner: Send + Sync + 'static {
+    /// Returns the reason the object was refused, if it was refused
+    async fn scan(&self, path: &Path) -> Result<(), String>;
+}
+
+/// How the local node responds to an [`InboundTransferProposal`]
+#[derive(Clone)]
+#[allow(variant_size_differences)]
+pub enum InboundTransferDecision {
+    /// Leaves the decision to whoever holds the [`ObjectTransferHandler`]
+    Defer,
+    /// Saves the object wherever the backend would by default
+    Accept,
+    /// Saves the object at the given path
+    AcceptInto(PathBuf),
+    /// Declines the transfer. The reason is relayed to the sender
+    Reject(String),
+    /// Saves the object at the given path, but only completes the transfer if the scanner
+    /// approves of the object. Otherwise, the object is deleted and the transfer fails
+    ScanThenAccept(PathBuf, Arc<dyn ObjectScanner>),
+}
+
+impl std::fmt::Debug for InboundTransferDecision {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Defer => write!(f, "Defer"),
+            Self::Accept => write!(f, "Accept"),
+            Self::AcceptInto(path) => write!(f, "AcceptInto({path:?})"),
+            Self::Reject(reason) => write!(f, "Reject({reason:?})"),
+            Self::ScanThenAccept(path, _) => write!(f, "ScanThenAccept({path:?})"),
+        }
+    }
+}
+
+/// An entry inside a directory of the RE-VFS
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct VirtualDirEntry {
+    pub name: String,
+    pub virtual_path: PathBuf,
+    pub is_dir: bool,
+    /// The metadata declared when the file was pushed. None for directories
+    pub metadata: Option<VirtualObjectMetadata>,
+}
+
+/// Operations on the directory structure of the RE-VFS, executed by the node storing the data
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ReVFSDirectoryOperation {
+    /// Creates the directory and any missing parents
+    CreateDirectory { virtual_dir: PathBuf },
+    /// Lists the entries of a directory. If `owner_cid` is specified, the directory of the
+    /// owner is listed, assuming the owner granted access to the requester
+    List {
+        virtual_dir: PathBuf,
+        owner_cid: Option<u64>,
+    },
+    /// Grants `peer_cid` access to the virtual path and everything below it
+    GrantAccess { virtual_dir: PathBuf, peer_cid: u64 },
+    /// Revokes a grant previously given by [`ReVFSDirectoryOperation::GrantAccess`]
+    RevokeAccess { virtual_dir: PathBuf, peer_cid: u64 },
+}
+
+/// The access a grantee has to an object shared by its owner
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SharedObjectPermission {
+    /// The grantee may only pull the object
+    Read,
+    /// The grantee may pull, overwrite and delete the object
+    ReadWrite,
+}
+
+impl SharedObjectPermission {
+    /// Returns true if this permission is at least as strong as `required`
+    pub fn allows(&self, required: SharedObjectPermission) -> bool {
+        matches!(
+            (self, required),
+            (_, SharedObjectPermission::Read) | (SharedObjectPermission::ReadWrite, _)
+        )
+    }
+}
+
+/// Operations on named objects stored on the server, either by their owner, or, by a grantee
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum SharedObjectOperation {
+    /// Shares the object `name` owned by the requester with `grantee_cid`
+    Grant {
+        name: String,
+        grantee_cid: u64,
+        permission: SharedObjectPermission,
+    },
+    /// Revokes the grantee's access to the object `name` owned by the requester
+    Revoke { name: String, grantee_cid: u64 },
+    /// Pulls the object from the owner's storage
+    Read { owner_cid: u64, name: String },
+    /// Redirects the next upload of an object named `name` by the requester into the owner's
+    /// storage. The grant is checked once more when the upload begins
+    Write { owner_cid: u64, name: String },
+    /// Deletes the object from the owner's storage
+    Delete { owner_cid: u64, name: String },
+}
+
+/// Sent to a grantee when an owner shares, or stops sharing, an object
+#[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
+pub struct SharedObjectNotification {
+    pub owner_cid: u64,
+    pub name: String,
+    /// None if access was revoked
+    pub permission: Option<SharedObjectPermission>,
+}
+
 /// Used to keep track of file transfer progress for either
 /// sender or receiver orientation
 #[derive(Debug)]
@@ -71,14 +296,34 @@ pub struct ObjectTransferHandler {
     pub receiver: u64,
     pub is_revfs_pull: bool,
     pub orientation: ObjectTransferOrientation,
-    start_recv_tx: Option<tokio::sync::oneshot::Sender<bool>>,
+    proposal: Option<InboundTransferProposal>,
+    start_recv_tx: Option<tokio::sync::oneshot::Sender<InboundTransferDecision>>,
+    control_tx: UnboundedSender<ObjectTransferControl>,
+    progress_interval: Option<Duration>,
+    last_progress: Option<Instant>,
 }
 
 impl Stream for ObjectTransferHandler {
     type Item = ObjectTransferStatus;
 
     fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
-        Pin::new(&mut self.inner).poll_recv(cx)
+        loop {
+            match futures::ready!(Pin::new(&mut self.inner).poll_recv(cx)) {
+                Some(status) => {
+                    if let Some(progress) = status.progress() {
+                        if self.throttles(progress) {
+                            continue;
+                        }
+
+                        self.last_progress = Some(Instant::now());
+                    }
+
+                    return Poll::Ready(Some(status));
+                }
+
+                None => return Poll::Ready(None),
+            }
+        }
     }
 }
 
@@ -87,38 +332,105 @@ impl ObjectTransferHandler {
         source: u64,
         receiver: u64,
         orientation: ObjectTransferOrientation,
-        start_recv_tx: Option<tokio::sync::oneshot::Sender<bool>>,
+        start_recv_tx: Option<tokio::sync::oneshot::Sender<InboundTransferDecision>>,
+        proposal: Option<InboundTransferProposal>,
         is_revfs_pull: bool,
-    ) -> (Self, UnboundedSender<ObjectTransferStatus>) {
+    ) -> (
+        Self,
+        UnboundedSender<ObjectTransferStatus>,
+        UnboundedReceiver<ObjectTransferControl>,
+    ) {
         let (tx, inner) = unbounded_channel();
+        let (control_tx, control_rx) = unbounded_channel();
 
         let this = Self {
             inner,
             source,
             receiver,
             orientation,
+            proposal,
             start_recv_tx,
             is_revfs_pull,
+            control_tx,
+            progress_interval: None,
+            last_progress: None,
         };
 
-        (this, tx)
+        (this, tx, control_rx)
+    }
+
+    /// Yields at most one [`ObjectTransferStatus::TransferTick`] or
+    /// [`ObjectTransferStatus::ReceptionTick`] per `interval`. The ticks in between are skipped,
+    /// except for the tick of the final chunk. By default, a tick is yielded for every chunk
+    pub fn set_progress_interval(&mut self, interval: Duration) {
+        self.progress_interval = Some(interval);
+    }
+
+    fn throttles(&self, progress: &TransferProgress) -> bool {
+        match (self.progress_interval, self.last_progress) {
+            (Some(interval), Some(last_progress)) => {
+                !progress.is_last_chunk() && last_progress.elapsed() < interval
+            }
+            _ => false,
+        }
     }
 
     /// When the local handle type is for a Receiver,
     /// the receiver must accept the transfer before
     /// receiving the data
     pub fn accept(&mut self) -> Result<(), AccountError> {
-        self.respond(true)
+        self.respond(InboundTransferDecision::Accept)
+    }
+
+    /// Accepts the transfer, saving the object at `path` instead of
+    /// where the backend would by default
+    pub fn accept_into<T: Into<PathBuf>>(&mut self, path: T) -> Result<(), AccountError> {
+        self.respond(InboundTransferDecision::AcceptInto(path.into()))
     }
 
     /// When the local handle type is for a Receiver,
     /// the receiver can deny a request
     pub fn decline(&mut self) -> Result<(), AccountError> {
-        self.respond(false)
+        self.reject("The receiver declined the transfer")
+    }
+
+    /// Declines the transfer, relaying `reason` to the sender
+    pub fn reject<T: Into<String>>(&mut self, reason: T) -> Result<(), AccountError> {
+        self.respond(InboundTransferDecision::Reject(reason.into()))
+    }
+
+    /// The description of the inbound object. Only present for receivers
+    /// that must accept or decline the transfer
+    pub fn proposal(&self) -> Option<&InboundTransferProposal> {
+        self.proposal.as_ref()
+    }
+
+    /// Pauses the transfer. Either side may pause. The sender stops
+    /// transmitting once the group currently in-flight completes
+    pub fn pause(&self) -> Result<(), AccountError> {
+        self.send_control(ObjectTransferControl::Pause)
+    }
+
+    /// Resumes a previously paused transfer
+    pub fn resume(&self) -> Result<(), AccountError> {
+        self.send_control(ObjectTransferControl::Resume)
     }
 
-    fn respond(&mut self, accept: bool) -> Result<(), AccountError> {
-        if self.is_revfs_pull {
+    /// Cancels the transfer on both ends. Both handles will receive
+    /// [`ObjectTransferStatus::Cancelled`]
+    pub fn cancel(&self) -> Result<(), AccountError> {
+        self.send_control(ObjectTransferControl::Cancel)
+    }
+
+    fn send_control(&self, signal: ObjectTransferControl) -> Result<(), AccountError> {
+        self.control_tx
+            .send(signal)
+            .map_err(|_| AccountError::msg("The transfer is no longer active"))
+    }
+
+    /// Applies the decision to an inbound transfer. [`InboundTransferDecision::Defer`] is a no-op
+    pub fn respond(&mut self, decision: InboundTransferDecision) -> Result<(), AccountError> {
+        if self.is_revfs_pull || matches!(decision, InboundTransferDecision::Defer) {
             return Ok(());
         }
 
@@ -126,30 +438,150 @@ impl ObjectTransferHandler {
             self.start_recv_tx
                 .take()
                 .ok_or_else(|| AccountError::msg("Start_recv_tx already called"))?
-                .send(accept)
-                .map_err(|err| AccountError::msg(err.to_string()))
+                .send(decision)
+                .map_err(|_| AccountError::msg("The transfer is no longer active"))
         } else {
             Err(AccountError::msg("Local is not a receiver"))
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
 pub enum ObjectTransferOrientation {
     Receiver,
     Sender,
 }
 
+/// Imperative signals that either end of an in-flight transfer may issue
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub enum ObjectTransferControl {
+    Pause,
+    Resume,
+    Cancel,
+}
+
+/// The duration over which [`TransferProgress::bytes_per_sec`] is averaged
+pub const TRANSFER_RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// The progress of a transfer as of the most recently completed chunk
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransferProgress {
+    /// The index of the completed chunk, relative to the start of the object
+    pub chunk_index: usize,
+    pub total_chunks: usize,
+    pub bytes_transferred: u64,
+    pub total_bytes: u64,
+    /// Averaged over the chunks completed within the last [`TRANSFER_RATE_WINDOW`]
+    pub bytes_per_sec: f64,
+    /// The estimated time until the last chunk completes. `None` until a rate is known
+    pub eta: Option<Duration>,
+}
+
+impl TransferProgress {
+    pub fn percent(&self) -> f32 {
+        get_progress_percent(self.chunk_index + 1, self.total_chunks)
+    }
+
+    pub fn megabytes_per_sec(&self) -> f32 {
+        (self.bytes_per_sec / 1_000_000f64) as f32
+    }
+
+    pub fn is_last_chunk(&self) -> bool {
+        self.chunk_index + 1 >= self.total_chunks
+    }
+}
+
+/// Derives the [`TransferProgress`] of a single transfer from the completion of its chunks
+#[derive(Debug)]
+pub struct TransferProgressTracker {
+    total_chunks: usize,
+    total_bytes: u64,
+    bytes_transferred: u64,
+    /// The instant preceding the oldest completion in the window
+    window_start: Instant,
+    /// The completion instant and length of each chunk completed within the window
+    window: VecDeque<(Instant, u64)>,
+}
+
+impl TransferProgressTracker {
+    pub fn new(total_chunks: usize, total_bytes: u64) -> Self {
+        Self {
+            total_chunks,
+            total_bytes,
+            bytes_transferred: 0,
+            window_start: Instant::now(),
+            window: VecDeque::new(),
+        }
+    }
+
+    pub fn on_chunk_complete(&mut self, chunk_index: usize, len: u64) -> TransferProgress {
+        self.on_chunk_complete_at(Instant::now(), chunk_index, len)
+    }
+
+    fn on_chunk_complete_at(
+        &mut self,
+        now: Instant,
+        chunk_index: usize,
+        len: u64,
+    ) -> TransferProgress {
+        self.bytes_transferred += len;
+        self.window.push_back((now, len));
+
+        while self.window.len() > 1
+            && now.saturating_duration_since(self.window[0].0) > TRANSFER_RATE_WINDOW
+        {
+            if let Some((completed_at, _)) = self.window.pop_front() {
+                self.window_start = completed_at;
+            }
+        }
+
+        let elapsed = now
+            .saturating_duration_since(self.window_start)
+            .as_secs_f64();
+        let (bytes_per_sec, eta) = if elapsed > 0f64 {
+            let bytes_in_window: u64 = self.window.iter().map(|(_, len)| *len).sum();
+            let chunks_per_sec = self.window.len() as f64 / elapsed;
+            let remaining_chunks = self.total_chunks.saturating_sub(chunk_index + 1);
+            (
+                bytes_in_window as f64 / elapsed,
+                Some(Duration::from_secs_f64(
+                    remaining_chunks as f64 / chunks_per_sec,
+                )),
+            )
+        } else {
+            (0f64, None)
+        };
+
+        TransferProgress {
+            chunk_index,
+            total_chunks: self.total_chunks,
+            bytes_transferred: self.bytes_transferred,
+            total_bytes: self.total_bytes,
+            bytes_per_sec,
+            eta,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 #[allow(variant_size_differences)]
 pub enum ObjectTransferStatus {
     TransferBeginning,
     ReceptionBeginning(PathBuf, Arc<dyn StreamableTargetInformation>),
-    // relative group_id, total groups, Mb/s
-    TransferTick(usize, usize, f32),
-    ReceptionTick(usize, usize, f32),
+    TransferTick(TransferProgress),
+    ReceptionTick(TransferProgress),
+    /// Every group arrived over the network. Verification may still be in progress, and the
+    /// object is only complete once [`ObjectTransferStatus::ReceptionComplete`] is received
+    NetworkComplete,
+    /// Chunks processed, total chunks. Emitted once per received chunk. If the sender provided a
+    /// manifest, processed chunks were verified against it. Otherwise, they were only decompressed
+    /// and decrypted, relying on the per-packet AEAD for integrity
+    VerificationTick(usize, usize),
     TransferComplete,
     ReceptionComplete,
+    Paused,
+    Resumed,
+    Cancelled,
     Fail(String),
 }
 
@@ -157,17 +589,28 @@ impl ObjectTransferStatus {
     pub fn is_tick_type(&self) -> bool {
         matches!(
             self,
-            ObjectTransferStatus::TransferTick(_, _, _)
-                | ObjectTransferStatus::ReceptionTick(_, _, _)
+            ObjectTransferStatus::TransferTick(_)
+                | ObjectTransferStatus::ReceptionTick(_)
+                | ObjectTransferStatus::VerificationTick(_, _)
         )
     }
 
+    /// The progress carried by transfer and reception ticks
+    pub fn progress(&self) -> Option<&TransferProgress> {
+        match self {
+            ObjectTransferStatus::TransferTick(progress)
+            | ObjectTransferStatus::ReceptionTick(progress) => Some(progress),
+            _ => None,
+        }
+    }
+
     /// Even if an error, returns true if the file transfer is done
     pub fn is_finished_type(&self) -> bool {
         matches!(
             self,
             ObjectTransferStatus::TransferComplete
                 | ObjectTransferStatus::ReceptionComplete
+                | ObjectTransferStatus::Cancelled
                 | ObjectTransferStatus::Fail(_)
         )
     }
@@ -184,12 +627,15 @@ impl std::fmt::Display for ObjectTransferStatus {
                 write!(f, "Download for object {vfm:?} beginning")
             }
 
-            ObjectTransferStatus::TransferTick(relative_group_id, total_groups, transfer_rate) => {
-                print_tick(f, *relative_group_id, *total_groups, *transfer_rate)
+            ObjectTransferStatus::TransferTick(progress)
+            | ObjectTransferStatus::ReceptionTick(progress) => print_tick(f, progress),
+
+            ObjectTransferStatus::NetworkComplete => {
+                write!(f, "All groups received; verifying")
             }
 
-            ObjectTransferStatus::ReceptionTick(relative_group_id, total_groups, transfer_rate) => {
-                print_tick(f, *relative_group_id, *total_groups, *transfer_rate)
+            ObjectTransferStatus::VerificationTick(chunks_verified, total_chunks) => {
+                write!(f, "Verified {chunks_verified}/{total_chunks} chunks")
             }
 
             ObjectTransferStatus::TransferComplete => {
@@ -200,6 +646,18 @@ impl std::fmt::Display for ObjectTransferStatus {
                 write!(f, "Download complete")
             }
 
+            ObjectTransferStatus::Paused => {
+                write!(f, "Transfer paused")
+            }
+
+            ObjectTransferStatus::Resumed => {
+                write!(f, "Transfer resumed")
+            }
+
+            ObjectTransferStatus::Cancelled => {
+                write!(f, "Transfer cancelled")
+            }
+
             ObjectTransferStatus::Fail(reason) => {
                 write!(f, "Failure. Reason: {reason}")
             }
@@ -207,19 +665,20 @@ impl std::fmt::Display for ObjectTransferStatus {
     }
 }
 
-fn print_tick(
-    f: &mut Formatter<'_>,
-    relative_group_id: usize,
-    total_groups: usize,
-    transfer_rate: f32,
-) -> std::fmt::Result {
-    if can_print_progress(relative_group_id, total_groups) {
+fn print_tick(f: &mut Formatter<'_>, progress: &TransferProgress) -> std::fmt::Result {
+    if can_print_progress(progress.chunk_index, progress.total_chunks) {
         write!(
             f,
-            " ({}% @ {} MB/s) ",
-            get_progress_percent(relative_group_id, total_groups),
-            transfer_rate
-        )
+            " ({}% @ {} MB/s",
+            progress.percent(),
+            progress.megabytes_per_sec()
+        )?;
+
+        if let Some(eta) = progress.eta {
+            write!(f, ", ETA {}s", eta.as_secs())?;
+        }
+
+        write!(f, ") ")
     } else {
         write!(f, "...")
     }
@@ -244,3 +703,83 @@ fn can_print_progress(relative_group_id: usize, total_groups: usize) -> bool {
 fn get_progress_percent(relative_group_id: usize, total_groups: usize) -> f32 {
     100f32 * (relative_group_id as f32 / total_groups as f32)
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::backend::utils::{
+        InboundTransferProposal, RedirectedObjectMetadata, TransferProgressTracker,
+        VirtualObjectMetadata, TRANSFER_RATE_WINDOW, UNNAMED_OBJECT,
+    };
+    use citadel_crypt::misc::TransferType;
+    use std::path::Path;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_proposal_name_is_single_component() {
+        let proposal = |name: &str| {
+            let metadata = VirtualObjectMetadata {
+                name: name.to_string(),
+                date_created: String::new(),
+                author: String::new(),
+                plaintext_length: 0,
+                group_count: 0,
+                object_id: 0,
+                cid: 0,
+                transfer_type: TransferType::FileTransfer,
+            };
+            InboundTransferProposal::new(1, &metadata)
+        };
+
+        assert_eq!(proposal("report.pdf").name, "report.pdf");
+        assert_eq!(proposal("../../etc/passwd").name, "passwd");
+        assert_eq!(proposal("/etc/report.pdf").name, "report.pdf");
+        assert_eq!(
+            proposal("../../report.pdf").content_type,
+            Some("application/pdf")
+        );
+        for name in ["..", "/", "", "a/.."] {
+            assert_eq!(proposal(name).name, UNNAMED_OBJECT, "{name}");
+        }
+
+        let dir = Path::new("/tmp/received");
+        assert!(RedirectedObjectMetadata::check_destination(&dir.join("report.pdf")).is_ok());
+        assert!(RedirectedObjectMetadata::check_destination(&dir.join("../report.pdf")).is_err());
+        assert!(RedirectedObjectMetadata::check_destination(&dir.join("..")).is_err());
+    }
+
+    #[test]
+    fn test_progress_rate_spans_sliding_window() {
+        let mut tracker = TransferProgressTracker::new(100, 100_000);
+        let start = tracker.window_start;
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        // a slow start: one 1000-byte chunk per second
+        let mut progress = tracker.on_chunk_complete_at(at(1), 0, 1000);
+        assert_eq!(progress.bytes_per_sec, 1000f64);
+        assert_eq!(progress.eta, Some(Duration::from_secs(99)));
+        for idx in 1..10 {
+            progress = tracker.on_chunk_complete_at(at(idx + 1), idx as usize, 1000);
+        }
+
+        assert_eq!(progress.bytes_transferred, 10_000);
+        assert_eq!(progress.bytes_per_sec, 1000f64);
+        assert_eq!(progress.percent(), 10f32);
+
+        // the rate speeds up; once the slow completions leave the window, only the faster rate
+        // remains
+        for idx in 10..40 {
+            let now = at(10) + Duration::from_millis(250 * (idx - 9));
+            progress = tracker.on_chunk_complete_at(now, idx as usize, 1000);
+        }
+
+        assert!(tracker.window.len() as u64 <= TRANSFER_RATE_WINDOW.as_secs() * 4 + 1);
+        assert!((progress.bytes_per_sec - 4000f64).abs() < 1f64);
+        let eta = progress.eta.unwrap();
+        assert!(eta >= Duration::from_secs(14) && eta <= Duration::from_secs(16));
+        assert!(!progress.is_last_chunk());
+
+        let progress = tracker.on_chunk_complete_at(at(30), 99, 1000);
+        assert!(progress.is_last_chunk());
+        assert_eq!(progress.eta, Some(Duration::ZERO));
+    }
+}
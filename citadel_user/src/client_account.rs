@@ -12,6 +12,7 @@ use citadel_crypt::stacked_ratchet::Ratchet;
 use parking_lot::{RwLock, RwLockReadGuard, RwLockWriteGuard};
 use std::fmt::Formatter;
 
+use crate::auth::oidc::OidcVerifier;
 use crate::auth::proposed_credentials::ProposedCredentials;
 use crate::auth::DeclaredAuthenticationMode;
 use crate::serialization::SyncIO;
@@ -188,24 +189,33 @@ impl<R: Ratchet, Fcm: Ratchet> ClientNetworkAccount<R, Fcm> {
         self.read().auth_store.username().to_string()
     }
 
-    /// Checks the credentials for validity. Used for the login process.
+    /// Checks the credentials for validity. Used for the login process. `oidc_verifier` is
+    /// required for accounts backed by an OpenID Connect provider
     pub async fn validate_credentials(
         &self,
         creds: ProposedCredentials,
+        oidc_verifier: Option<&OidcVerifier>,
     ) -> Result<(), AccountError> {
-        let argon_container = {
-            let read = self.read();
-            let username = read.auth_store.username();
+        let argon_container =
+            {
+                let read = self.read();
+                let username = read.auth_store.username();
 
-            if !creds.compare_username(username.as_bytes()) {
-                return Err(AccountError::InvalidUsername);
-            }
+                if !creds.compare_username(username.as_bytes()) {
+                    return Err(AccountError::InvalidUsername);
+                }
 
-            match &read.auth_store {
-                DeclaredAuthenticationMode::Argon { argon, .. } => argon.clone(),
-                DeclaredAuthenticationMode::Passwordless { .. } => return Ok(()),
-            }
-        };
+                match &read.auth_store {
+                    DeclaredAuthenticationMode::Argon { argon, .. } => argon.clone(),
+                    DeclaredAuthenticationMode::Passwordless { .. } => return Ok(()),
+                    DeclaredAuthenticationMode::Oidc { .. } => {
+                        return creds.verify_oidc(oidc_verifier).map(|_| ())
+                    }
+                    DeclaredAuthenticationMode::Delegated { .. } => return Err(AccountError::msg(
+                        "Accounts validated by a directory are validated by the account manager",
+                    )),
+                }
+            };
 
         creds.validate_credentials(argon_container).await
     }
@@ -230,6 +240,21 @@ impl<R: Ratchet, Fcm: Ratchet> ClientNetworkAccount<R, Fcm> {
                 DeclaredAuthenticationMode::Passwordless { username, .. } => {
                     return Ok(ProposedCredentials::passwordless(username.clone()))
                 }
+                DeclaredAuthenticationMode::Oidc { .. } => {
+                    return Err(AccountError::msg(
+                        "Accounts backed by an OpenID Connect provider require a fresh ID token to connect",
+                    ))
+                }
+                DeclaredAuthenticationMode::Delegated {
+                    username,
+                    full_name,
+                } => {
+                    return Ok(ProposedCredentials::new_delegated(
+                        full_name.clone(),
+                        username.clone(),
+                        password_raw,
+                    ))
+                }
             }
         };
 
@@ -453,7 +478,7 @@ impl<R: Ratchet, Fcm: Ratchet> ClientNetworkAccount<R, Fcm> {
     }
 
     /// Returns the metadata for this CNAC
-    pub(crate) fn get_metadata(&self) -> CNACMetadata {
+    pub fn get_metadata(&self) -> CNACMetadata {
         let read = self.read();
         let cid = read.cid;
         let username = read.auth_store.username().to_string();
@@ -479,6 +504,14 @@ impl<R: Ratchet, Fcm: Ratchet> ClientNetworkAccount<R, Fcm> {
         self.inner.cid
     }
 
+    /// Returns true if the password is validated by an external directory
+    pub fn is_delegated(&self) -> bool {
+        matches!(
+            self.read().auth_store,
+            DeclaredAuthenticationMode::Delegated { .. }
+        )
+    }
+
     /// Returns true if passwordless
     pub fn passwordless(&self) -> bool {
         self.inner.passwordless
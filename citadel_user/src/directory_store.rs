@@ -18,6 +18,7 @@ pub enum BasePath {
     ConfigDir,
     VirtualDir,
     FileTransferDir,
+    ChunkCacheDir,
 }
 
 #[derive(Clone)]
@@ -39,6 +40,8 @@ pub struct DirectoryStore {
     pub virtual_dir: String,
     /// Directory for basic file transfer
     pub file_transfer_dir: String,
+    /// Directory for the content-addressed chunk cache
+    pub chunk_cache_dir: String,
 }
 
 impl DirectoryStore {
@@ -53,6 +56,7 @@ impl DirectoryStore {
             BasePath::ConfigDir => &self.config_dir,
             BasePath::VirtualDir => &self.virtual_dir,
             BasePath::FileTransferDir => &self.file_transfer_dir,
+            BasePath::ChunkCacheDir => &self.chunk_cache_dir,
         };
 
         PathBuf::from(append_to_path(base.clone(), file.as_ref()))
@@ -87,7 +91,8 @@ fn setup_directory(mut home_dir: String) -> Result<DirectoryStore, AccountError>
         server_dir: hyxe_server_dir,
         config_dir: append_to_path(home.clone(), "config/"),
         virtual_dir: append_to_path(home.clone(), "virtual/"),
-        file_transfer_dir: append_to_path(home, "transfers/"),
+        file_transfer_dir: append_to_path(home.clone(), "transfers/"),
+        chunk_cache_dir: append_to_path(home, "chunk_cache/"),
     };
 
     Ok(dirs)
@@ -109,6 +114,7 @@ pub fn setup_directories(home_dir: String) -> Result<DirectoryStore, AccountErro
         .and(mkdir(store.config_dir.as_str()))
         .and(mkdir(store.virtual_dir.as_str()))
         .and(mkdir(store.file_transfer_dir.as_str()))
+        .and(mkdir(store.chunk_cache_dir.as_str()))
         .map_err(|err| AccountError::IoError(err.to_string()))?;
 
     Ok(store)
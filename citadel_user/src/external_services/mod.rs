@@ -51,6 +51,17 @@ pub struct ServicesObject {
     pub google_auth_jwt: Option<JsonWebToken>,
     /// Google's real time database config
     pub rtdb: Option<RtdbConfig>,
+    /// Present if the account is under legal hold, and the node notifies held clients
+    #[serde(default)]
+    pub legal_hold: Option<LegalHoldNotice>,
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize, Copy, Clone, Debug, Eq, PartialEq)]
+/// Informs a client that the node archives its messages
+pub struct LegalHoldNotice {
+    /// If true, the plaintext of the messages the client sends to the node is archived. The
+    /// metadata of every message is archived regardless
+    pub archives_plaintext: bool,
 }
 
 #[derive(serde::Deserialize, serde::Serialize, Default, Debug, Clone)]
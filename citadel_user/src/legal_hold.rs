@@ -0,0 +1,322 @@
+//! Sealed, append-only archives of the messages of accounts under legal hold
+//!
+//! Each account under hold is archived into its own file. Every [`ArchiveRecord`] is sealed with a
+//! keyed hash over the record and the seal of the record before it, where the first seal is bound
+//! to the CID of the account. Altering, reordering, removing or inserting a record, or moving
+//! records between archives, therefore breaks the chain of seals, which [`verify_archive`]
+//! detects. Only a holder of the seal key may produce a valid chain, though truncating the tail
+//! of an archive remains undetectable without an out-of-band copy of the latest seal, which
+//! [`LegalHoldArchive::append`] returns
+//!
+//! The plaintext of a record is never written to disk as-is. It is encrypted with
+//! ChaCha20-Poly1305 under a key derived from the seal key, and the seal covers the ciphertext.
+//! Holders of the seal key may thus both verify and read an archive, whereas anyone else with
+//! access to the disk only learns the metadata of the archived messages
+use crate::misc::AccountError;
+use crate::serialization::SyncIO;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+
+const SEAL_DOMAIN: &[u8] = b"citadel-legal-hold-v1";
+const PLAINTEXT_DOMAIN: &[u8] = b"citadel-legal-hold-plaintext-v1";
+
+/// The seal of a record, chaining it to every record before it
+pub type Seal = [u8; 32];
+
+/// A single archived message
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+pub struct ArchiveRecord {
+    /// The time the node handled the message, in nanoseconds
+    pub timestamp: i64,
+    /// The CID of the sender
+    pub sender: u64,
+    /// The CID of the recipient, or zero if the message was sent to the node itself
+    pub recipient: u64,
+    /// The length of the message as seen by the node
+    pub len: u64,
+    /// The plaintext of the message. Only present for messages decrypted by the node itself, since
+    /// end-to-end encrypted messages are opaque to the node. Encrypted once written to disk
+    pub plaintext: Option<Vec<u8>>,
+}
+
+// an [`ArchiveRecord`] as written to disk
+#[derive(Serialize, Deserialize)]
+struct StoredRecord {
+    timestamp: i64,
+    sender: u64,
+    recipient: u64,
+    len: u64,
+    plaintext: Option<EncryptedPlaintext>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct EncryptedPlaintext {
+    nonce: [u8; 12],
+    ciphertext: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SealedRecord {
+    record: StoredRecord,
+    seal: Seal,
+}
+
+/// Appends records to the archives within a directory
+pub struct LegalHoldArchive {
+    directory: PathBuf,
+    seal_key: Vec<u8>,
+    // the latest seal of each archive appended to since the archive was opened
+    seals: HashMap<u64, Seal>,
+}
+
+impl LegalHoldArchive {
+    pub fn new(directory: PathBuf, seal_key: Vec<u8>) -> Self {
+        Self {
+            directory,
+            seal_key,
+            seals: HashMap::new(),
+        }
+    }
+
+    /// Returns the path of the archive of `cid`
+    pub fn archive_path(&self, cid: u64) -> PathBuf {
+        archive_path(&self.directory, cid)
+    }
+
+    /// Seals and appends `record` to the archive of `cid`, returning the new seal. The first
+    /// append to an existing archive verifies the archive to resume its chain, failing if the
+    /// archive was tampered with
+    pub async fn append(&mut self, cid: u64, record: ArchiveRecord) -> Result<Seal, AccountError> {
+        let path = self.archive_path(cid);
+        let prev = match self.seals.get(&cid) {
+            Some(seal) => *seal,
+            None => {
+                tokio::fs::create_dir_all(&self.directory)
+                    .await
+                    .map_err(|err| AccountError::IoError(err.to_string()))?;
+                if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+                    verify_archive(&path, cid, &self.seal_key).await?.1
+                } else {
+                    genesis_seal(&self.seal_key, cid)
+                }
+            }
+        };
+
+        let record = encrypt_record(&self.seal_key, cid, record)?;
+        let serialized = record.serialize_to_vector()?;
+        let seal = seal(&self.seal_key, &prev, &serialized);
+        let frame = SealedRecord { record, seal }.serialize_to_vector()?;
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(|err| AccountError::IoError(err.to_string()))?;
+        let mut buf = Vec::with_capacity(4 + frame.len());
+        buf.extend_from_slice(&(frame.len() as u32).to_be_bytes());
+        buf.extend_from_slice(&frame);
+        file.write_all(&buf)
+            .await
+            .map_err(|err| AccountError::IoError(err.to_string()))?;
+        file.sync_data()
+            .await
+            .map_err(|err| AccountError::IoError(err.to_string()))?;
+
+        let _ = self.seals.insert(cid, seal);
+        Ok(seal)
+    }
+}
+
+/// Returns the path of the archive of `cid` within `directory`
+pub fn archive_path(directory: &Path, cid: u64) -> PathBuf {
+    directory.join(format!("{cid}.archive"))
+}
+
+/// Reads the archive of `cid` at `path`, verifying its chain of seals. Returns the records
+/// alongside the latest seal
+pub async fn verify_archive(
+    path: &Path,
+    cid: u64,
+    seal_key: &[u8],
+) -> Result<(Vec<ArchiveRecord>, Seal), AccountError> {
+    let contents = tokio::fs::read(path)
+        .await
+        .map_err(|err| AccountError::IoError(err.to_string()))?;
+    let mut prev = genesis_seal(seal_key, cid);
+    let mut records = Vec::new();
+    let mut remaining = contents.as_slice();
+
+    while !remaining.is_empty() {
+        if remaining.len() < 4 {
+            return Err(AccountError::msg("Archive ends with a truncated record"));
+        }
+
+        let (len, rest) = remaining.split_at(4);
+        let len = u32::from_be_bytes([len[0], len[1], len[2], len[3]]) as usize;
+        if rest.len() < len {
+            return Err(AccountError::msg("Archive ends with a truncated record"));
+        }
+
+        let (frame, rest) = rest.split_at(len);
+        let sealed = SealedRecord::deserialize_from_vector(frame)?;
+        let expected = seal(seal_key, &prev, &sealed.record.serialize_to_vector()?);
+        if expected != sealed.seal {
+            return Err(AccountError::msg(format!(
+                "The seal of record {} of the archive of {cid} is invalid",
+                records.len()
+            )));
+        }
+
+        prev = sealed.seal;
+        records.push(decrypt_record(seal_key, cid, sealed.record)?);
+        remaining = rest;
+    }
+
+    Ok((records, prev))
+}
+
+fn encrypt_record(
+    seal_key: &[u8],
+    cid: u64,
+    record: ArchiveRecord,
+) -> Result<StoredRecord, AccountError> {
+    let plaintext = match record.plaintext {
+        Some(plaintext) => {
+            let mut nonce = [0u8; 12];
+            rand::thread_rng().fill_bytes(&mut nonce);
+            let ciphertext = plaintext_cipher(seal_key)
+                .encrypt(
+                    Nonce::from_slice(&nonce),
+                    Payload {
+                        msg: &plaintext,
+                        aad: &cid.to_be_bytes(),
+                    },
+                )
+                .map_err(|_| AccountError::msg("Unable to encrypt the archived plaintext"))?;
+            Some(EncryptedPlaintext { nonce, ciphertext })
+        }
+        None => None,
+    };
+
+    Ok(StoredRecord {
+        timestamp: record.timestamp,
+        sender: record.sender,
+        recipient: record.recipient,
+        len: record.len,
+        plaintext,
+    })
+}
+
+fn decrypt_record(
+    seal_key: &[u8],
+    cid: u64,
+    record: StoredRecord,
+) -> Result<ArchiveRecord, AccountError> {
+    let plaintext = match record.plaintext {
+        Some(encrypted) => Some(
+            plaintext_cipher(seal_key)
+                .decrypt(
+                    Nonce::from_slice(&encrypted.nonce),
+                    Payload {
+                        msg: &encrypted.ciphertext,
+                        aad: &cid.to_be_bytes(),
+                    },
+                )
+                .map_err(|_| AccountError::msg("Unable to decrypt the archived plaintext"))?,
+        ),
+        None => None,
+    };
+
+    Ok(ArchiveRecord {
+        timestamp: record.timestamp,
+        sender: record.sender,
+        recipient: record.recipient,
+        len: record.len,
+        plaintext,
+    })
+}
+
+fn plaintext_cipher(seal_key: &[u8]) -> ChaCha20Poly1305 {
+    let mut hasher = Sha3_256::default();
+    hasher.update(PLAINTEXT_DOMAIN);
+    hasher.update(seal_key);
+    let key: [u8; 32] = hasher.finalize().into();
+    ChaCha20Poly1305::new(Key::from_slice(&key))
+}
+
+fn genesis_seal(seal_key: &[u8], cid: u64) -> Seal {
+    let mut hasher = Sha3_256::default();
+    hasher.update(SEAL_DOMAIN);
+    hasher.update(seal_key);
+    hasher.update(cid.to_be_bytes());
+    hasher.finalize().into()
+}
+
+fn seal(seal_key: &[u8], prev: &Seal, record: &[u8]) -> Seal {
+    let mut hasher = Sha3_256::default();
+    hasher.update(SEAL_DOMAIN);
+    hasher.update(seal_key);
+    hasher.update(prev);
+    hasher.update(record);
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::legal_hold::{verify_archive, ArchiveRecord, LegalHoldArchive};
+
+    fn record(sender: u64, plaintext: Option<&[u8]>) -> ArchiveRecord {
+        ArchiveRecord {
+            timestamp: 1,
+            sender,
+            recipient: 0,
+            len: 5,
+            plaintext: plaintext.map(|plaintext| plaintext.to_vec()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_archive_detects_tampering() {
+        let dir = std::env::temp_dir().join(format!("legal_hold_{}", uuid::Uuid::new_v4()));
+        let key = b"seal key".to_vec();
+        let mut archive = LegalHoldArchive::new(dir.clone(), key.clone());
+        let _ = archive
+            .append(10, record(10, Some(b"hello")))
+            .await
+            .unwrap();
+        let _ = archive.append(10, record(20, None)).await.unwrap();
+
+        // reopening the archive resumes the chain
+        let mut archive = LegalHoldArchive::new(dir.clone(), key.clone());
+        let latest = archive.append(10, record(10, None)).await.unwrap();
+
+        let path = archive.archive_path(10);
+        let (records, seal) = verify_archive(&path, 10, &key).await.unwrap();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0], record(10, Some(b"hello")));
+        assert_eq!(seal, latest);
+
+        // the wrong key, or the archive of another account, fails verification
+        assert!(verify_archive(&path, 10, b"other key").await.is_err());
+        assert!(verify_archive(&path, 11, &key).await.is_err());
+
+        // the plaintext is encrypted at rest
+        let mut contents = tokio::fs::read(&path).await.unwrap();
+        assert!(!contents.windows(5).any(|window| window == b"hello"));
+
+        // altering the first record fails verification
+        let first_len = u32::from_be_bytes([contents[0], contents[1], contents[2], contents[3]]);
+        contents[4 + first_len as usize / 2] ^= 1;
+        tokio::fs::write(&path, contents).await.unwrap();
+        assert!(verify_archive(&path, 10, &key).await.is_err());
+
+        let _ = tokio::fs::remove_dir_all(dir).await;
+    }
+}
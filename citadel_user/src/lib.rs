@@ -62,8 +62,13 @@ pub mod backend;
 pub mod directory_store;
 /// For services
 pub mod external_services;
+/// Sealed archives of the messages of accounts under legal hold
+#[cfg(all(feature = "filesystem", not(target_family = "wasm")))]
+pub mod legal_hold;
 /// For errors
 pub mod misc;
+/// The permissions of accounts
+pub mod permissions;
 /// Contains basic subroutines for serialization
 pub mod serialization;
 ///
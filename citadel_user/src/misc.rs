@@ -190,6 +190,22 @@ pub fn validate_virtual_path<R: AsRef<Path>>(virtual_path: R) -> Result<(), Acco
     Ok(())
 }
 
+/// Ensures that the virtual directory is rooted and does not escape the owner's RE-VFS
+pub fn validate_virtual_directory<R: AsRef<Path>>(virtual_dir: R) -> Result<(), AccountError> {
+    let virtual_dir = virtual_dir.as_ref();
+    if !virtual_dir.has_root()
+        || virtual_dir
+            .components()
+            .any(|component| matches!(component, std::path::Component::ParentDir))
+    {
+        return Err(AccountError::IoError(format!(
+            "Path {virtual_dir:?} is not a valid remote encrypted virtual directory"
+        )));
+    }
+
+    Ok(())
+}
+
 // The goal of this function is to ensure that the provided virtual path is appropriate for
 // the local operating system
 pub fn prepare_virtual_path<P: AsRef<Path>>(path: P) -> PathBuf {
@@ -213,7 +229,7 @@ pub const VIRTUAL_FILE_METADATA_EXT: &str = ".vxe";
 
 #[cfg(test)]
 mod tests {
-    use crate::misc::{prepare_virtual_path, validate_virtual_path};
+    use crate::misc::{prepare_virtual_path, validate_virtual_directory, validate_virtual_path};
     use rstest::rstest;
     use std::path::PathBuf;
 
@@ -241,4 +257,22 @@ mod tests {
         let formatted = prepare_virtual_path(virtual_dir);
         assert!(validate_virtual_path(formatted).is_err());
     }
+
+    #[rstest]
+    #[case("/")]
+    #[case("/hello/world/")]
+    #[case("/hello/world")]
+    fn test_virtual_directory_okay(#[case] good_dir: &str) {
+        let formatted = prepare_virtual_path(good_dir);
+        assert!(validate_virtual_directory(formatted).is_ok());
+    }
+
+    #[rstest]
+    #[case("hello/world")]
+    #[case("/hello/../../world")]
+    #[case("/..")]
+    fn test_virtual_directory_bad(#[case] bad_dir: &str) {
+        let formatted = prepare_virtual_path(bad_dir);
+        assert!(validate_virtual_directory(formatted).is_err());
+    }
 }
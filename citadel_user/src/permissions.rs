@@ -0,0 +1,79 @@
+//! The permissions of an account, allowing nodes to offer tiered service plans. Permissions are
+//! stored in the backend of the node, and are enforced by the node as it processes the packets of
+//! the account
+use crate::auth::credential_validator::AccountPolicy;
+use citadel_crypt::prelude::SecurityLevel;
+use serde::{Deserialize, Serialize};
+
+/// What an account may do while connected to the node. Unless set otherwise, an account may do
+/// everything
+#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+pub struct AccountPermissions {
+    /// If false, the account may not create message groups
+    pub can_create_groups: bool,
+    /// If false, the account may neither send files to the node, nor to peers through the node
+    pub can_file_transfer: bool,
+    /// If false, the account may neither register nor connect to peers
+    pub can_p2p: bool,
+    /// The highest security level the account may connect with
+    pub max_security_level: SecurityLevel,
+}
+
+impl Default for AccountPermissions {
+    fn default() -> Self {
+        Self {
+            can_create_groups: true,
+            can_file_transfer: true,
+            can_p2p: true,
+            max_security_level: SecurityLevel::Custom(u8::MAX),
+        }
+    }
+}
+
+impl AccountPermissions {
+    /// Revokes each permission denied by `policy`, the policy of a directory-backed account
+    pub fn restrict(self, policy: AccountPolicy) -> Self {
+        Self {
+            can_file_transfer: self.can_file_transfer && policy.allow_file_transfer,
+            ..self
+        }
+    }
+
+    /// Returns true if the account may connect with `security_level`
+    pub fn permits_security_level(&self, security_level: SecurityLevel) -> bool {
+        security_level.value() <= self.max_security_level.value()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::credential_validator::AccountPolicy;
+    use crate::permissions::AccountPermissions;
+    use citadel_crypt::prelude::SecurityLevel;
+
+    #[test]
+    fn test_restrict() {
+        let permissions = AccountPermissions {
+            can_p2p: false,
+            max_security_level: SecurityLevel::High,
+            ..Default::default()
+        };
+
+        assert!(permissions.permits_security_level(SecurityLevel::Standard));
+        assert!(permissions.permits_security_level(SecurityLevel::High));
+        assert!(!permissions.permits_security_level(SecurityLevel::Extreme));
+        assert!(AccountPermissions::default().permits_security_level(SecurityLevel::Custom(200)));
+
+        let restricted = permissions.restrict(AccountPolicy {
+            allow_file_transfer: false,
+        });
+        assert!(!restricted.can_file_transfer);
+        assert!(!restricted.can_p2p);
+        assert!(restricted.can_create_groups);
+        assert!(
+            permissions
+                .restrict(AccountPolicy::default())
+                .can_file_transfer
+        );
+    }
+}
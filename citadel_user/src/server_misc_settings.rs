@@ -1,14 +1,227 @@
+use std::collections::{HashMap, HashSet};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
 /// Miscellaneous settings for a node serving connections
 #[derive(Clone)]
 pub struct ServerMiscSettings {
     /// If enabled, allows inbound connections to use no credentials when logging-in
     pub allow_passwordless: bool,
+    /// If set, objects received via file transfers are split into content-addressed chunks and
+    /// cached, up to the given number of bytes, allowing repeated transfers of identical content
+    /// from the same sender to transmit only the chunk hashes. Only supported by the filesystem backend
+    pub chunk_cache_max_bytes: Option<u64>,
+    /// If set, each session is monitored for packet handlers that stop making progress. Disabled
+    /// by default
+    pub session_watchdog: Option<SessionWatchdogSettings>,
+    /// The maximum time each pre-connect stage may stall before the session, or peer key exchange, is reaped
+    pub provisional_timeouts: ProvisionalTimeouts,
+    /// If enabled, the message groups and pending consent requests of each client are saved to the
+    /// backend when the server shuts down, and restored when it next starts. Requires a persistent backend
+    pub snapshot_peer_layer: bool,
+    /// Bounds the number of inbound packets handled concurrently
+    pub packet_processing: PacketProcessingLimits,
+    /// If set, clients must solve a proof-of-work puzzle bound to their address before the node
+    /// performs the key exchange of a registration or connection. Clients that predate the
+    /// challenge are unable to register or connect to the node while enabled
+    pub handshake_challenge: Option<HandshakeChallengeSettings>,
+    /// If set, the key exchanges of registrations and connections run on the blocking thread pool
+    /// instead of the async workers. If `None`, they run inline
+    pub crypto_offload: Option<CryptoOffloadSettings>,
+    /// If set, the node serves as a relay for the peers of a parent deployment. A relay holds no
+    /// accounts: clients connect passwordlessly, and only relay their peer-to-peer traffic through
+    /// the node, subject to the given bandwidth limits
+    pub relay: Option<RelaySettings>,
+    /// If set, the messages of the given accounts are archived for compliance. Requires the
+    /// filesystem feature
+    pub legal_hold: Option<LegalHoldSettings>,
+    /// If set, the node is one of several sharing a deployment behind a load balancer. Clients
+    /// registering to the node receive an affinity token naming it, which they present when
+    /// reconnecting such that they are routed back to the node holding their account
+    pub session_affinity: Option<SessionAffinitySettings>,
+    /// If set, clients may register and connect using an ID token issued by the given OpenID
+    /// Connect provider instead of a password. Requires the `oidc` feature
+    pub oidc: Option<OidcSettings>,
 }
 
 impl Default for ServerMiscSettings {
     fn default() -> Self {
         Self {
             allow_passwordless: true,
+            chunk_cache_max_bytes: None,
+            session_watchdog: None,
+            provisional_timeouts: ProvisionalTimeouts::default(),
+            snapshot_peer_layer: false,
+            packet_processing: PacketProcessingLimits::default(),
+            handshake_challenge: None,
+            crypto_offload: Some(CryptoOffloadSettings::default()),
+            relay: None,
+            legal_hold: None,
+            session_affinity: None,
+            oidc: None,
+        }
+    }
+}
+
+/// Determines when a session is considered hung, and what happens once it is
+#[derive(Clone, Copy, Debug)]
+pub struct SessionWatchdogSettings {
+    /// How long a single inbound packet may be handled before the session is considered hung
+    pub stall_timeout: Duration,
+    /// If true, hung sessions are terminated. Otherwise, only a diagnostic is logged
+    pub terminate_hung_sessions: bool,
+}
+
+impl Default for SessionWatchdogSettings {
+    fn default() -> Self {
+        Self {
+            stall_timeout: Duration::from_secs(30),
+            terminate_hung_sessions: false,
+        }
+    }
+}
+
+/// Bounds how long a session may remain in each stage before it becomes connected. Each timeout
+/// is measured from the last packet that advanced the stage, ensuring half-open sessions created by
+/// port scanners or crashed clients do not accumulate
+#[derive(Clone, Copy, Debug)]
+pub struct ProvisionalTimeouts {
+    /// The maximum time a session may stall while registering
+    pub register: Duration,
+    /// The maximum time a session may stall before, or while, connecting
+    pub connect: Duration,
+    /// The maximum time a peer key exchange may take before its pending state is dropped
+    pub peer_key_exchange: Duration,
+}
+
+impl Default for ProvisionalTimeouts {
+    fn default() -> Self {
+        Self {
+            register: Duration::from_secs(20),
+            connect: Duration::from_secs(20),
+            peer_key_exchange: Duration::from_secs(20),
+        }
+    }
+}
+
+/// Bounds the number of inbound packets handled concurrently, such that a single chatty session
+/// cannot starve the other sessions of the node
+#[derive(Clone, Copy, Debug)]
+pub struct PacketProcessingLimits {
+    /// The maximum number of packets of a single session handled concurrently
+    pub max_concurrency_per_session: usize,
+    /// The maximum number of packets handled concurrently across every session of the node. Once
+    /// exhausted, packets are admitted in the order they arrived, regardless of their session. If
+    /// `None`, only the limit of each session applies
+    pub max_concurrency: Option<usize>,
+    /// The maximum number of packets deprioritized by the node's packet filter handled
+    /// concurrently across every session of the node
+    pub max_deprioritized_concurrency: usize,
+}
+
+impl Default for PacketProcessingLimits {
+    fn default() -> Self {
+        Self {
+            max_concurrency_per_session: 32,
+            max_concurrency: Some(1024),
+            max_deprioritized_concurrency: 8,
         }
     }
 }
+
+/// Determines the work a client must perform before the node commits to a post-quantum key exchange
+#[derive(Clone, Copy, Debug)]
+pub struct HandshakeChallengeSettings {
+    /// The number of leading zero bits the client's solution must hash to. Each additional bit
+    /// doubles the expected work of the client, while verification always costs a single hash
+    pub difficulty: u8,
+    /// The period after which the node stops recognizing the challenges it issued, between one and
+    /// two epochs after issuing them
+    pub epoch: Duration,
+}
+
+impl Default for HandshakeChallengeSettings {
+    fn default() -> Self {
+        Self {
+            difficulty: 16,
+            epoch: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Bounds the key exchanges offloaded onto the blocking thread pool
+#[derive(Clone, Copy, Debug)]
+pub struct CryptoOffloadSettings {
+    /// The maximum number of key exchanges computed concurrently across every session of the node.
+    /// Once exhausted, key exchanges queue in the order they arrived
+    pub max_concurrency: usize,
+}
+
+impl Default for CryptoOffloadSettings {
+    fn default() -> Self {
+        Self {
+            max_concurrency: 16,
+        }
+    }
+}
+
+/// Bounds the peer-to-peer traffic a relay forwards on behalf of each session
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RelaySettings {
+    /// The maximum number of bytes relayed per second for a single session. Packets beyond the
+    /// limit are dropped. If `None`, the rate is unbounded
+    pub max_bytes_per_second: Option<u64>,
+    /// The maximum number of bytes relayed over the lifetime of a single session. Once reached,
+    /// every further packet of the session is dropped. If `None`, the total is unbounded
+    pub max_bytes_per_session: Option<u64>,
+}
+
+/// Determines the accounts whose messages are archived, and what is archived. The metadata of
+/// each message sent or received by an account under hold is always archived. Since the node
+/// cannot decrypt messages end-to-end encrypted between peers, plaintext is only archived for
+/// messages sent to the node itself
+#[derive(Clone, Debug)]
+pub struct LegalHoldSettings {
+    /// The CIDs of the accounts under hold
+    pub accounts: HashSet<u64>,
+    /// The directory holding the archives, one per account
+    pub directory: PathBuf,
+    /// The key sealing each archive, which is required to verify the archive. Archived plaintext
+    /// is encrypted under a key derived from it, hence it must be kept secret
+    pub seal_key: Vec<u8>,
+    /// If true, the plaintext of messages sent to the node is archived, encrypted, alongside their
+    /// metadata
+    pub archive_plaintext: bool,
+    /// If true, clients under hold are notified of what is archived once they connect
+    pub notify_clients: bool,
+}
+
+/// Identifies the node within a load-balanced deployment. The affinity token a client presents is
+/// carried in the header of its first pre-connect packet, which is authenticated yet unencrypted,
+/// thus a load balancer sharing the secret may route by the token without terminating the session
+#[derive(Clone, Debug)]
+pub struct SessionAffinitySettings {
+    /// The ID of this node, unique within the deployment
+    pub node_id: u32,
+    /// The secret authenticating the tokens, shared by every node of the deployment
+    pub secret: Vec<u8>,
+    /// The addresses of the other nodes of the deployment. If a client presents a token naming one
+    /// of them, the node acts as a router, redirecting the client to the node holding its account
+    pub nodes: HashMap<u32, SocketAddr>,
+}
+
+/// Identifies the OpenID Connect provider trusted by the node. An ID token is accepted if it is
+/// signed by one of the provider's keys, was issued by the provider for this node, and is unexpired
+#[derive(Clone, Debug)]
+pub struct OidcSettings {
+    /// The `iss` claim of the provider's tokens
+    pub issuer: String,
+    /// The client ID the node is registered under with the provider, which tokens must contain in
+    /// their `aud` claim
+    pub audience: String,
+    /// The JSON Web Key Set of the provider, as published at its `jwks_uri`
+    pub jwks: String,
+    /// The tolerated clock skew between the node and the provider
+    pub leeway: Duration,
+}
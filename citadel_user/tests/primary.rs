@@ -1,6 +1,7 @@
 #[cfg(test)]
 mod tests {
 
+    use citadel_crypt::argon::argon_container::ArgonCostParameters;
     use citadel_crypt::prelude::{ConstructorOpts, SecBuffer};
     use citadel_crypt::stacked_ratchet::constructor::{
         BobToAliceTransferType, StackedRatchetConstructor,
@@ -16,6 +17,7 @@ mod tests {
 
     use citadel_pqcrypto::prelude::algorithm_dictionary::EncryptionAlgorithm;
     use citadel_user::misc::{AccountError, CNACMetadata};
+    use citadel_user::permissions::AccountPermissions;
     use citadel_user::prelude::{ConnectionInfo, MutualPeer};
     use std::collections::HashMap;
     use std::net::SocketAddr;
@@ -723,6 +725,68 @@ mod tests {
         .await
     }
 
+    #[tokio::test]
+    async fn test_account_permissions() -> Result<(), AccountError> {
+        test_harness(|container, _pers_cl, _pers_se| async move {
+            let (client, _server) = container.create_cnac(USERNAME, PASSWORD, FULL_NAME).await;
+            let acc_mgr = &container.server_acc_mgr;
+            let cid = client.get_cid();
+
+            let permissions = acc_mgr.get_account_permissions(cid).await?;
+            assert!(permissions.can_p2p && permissions.can_file_transfer);
+
+            acc_mgr
+                .set_account_permissions(
+                    cid,
+                    AccountPermissions {
+                        can_p2p: false,
+                        ..Default::default()
+                    },
+                )
+                .await?;
+            let permissions = acc_mgr.get_account_permissions(cid).await?;
+            assert!(!permissions.can_p2p && permissions.can_file_transfer);
+
+            assert!(acc_mgr
+                .set_account_permissions(cid + 1, AccountPermissions::default())
+                .await
+                .is_err());
+            Ok(())
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn test_argon_policy_adoption() -> Result<(), AccountError> {
+        test_harness(|container, _pers_cl, _pers_se| async move {
+            let policy = container.server_acc_mgr.argon_policy();
+            let creds =
+                ProposedCredentials::new_register(FULL_NAME, USERNAME, PASSWORD.into()).await?;
+            creds.check_argon_policy(creds.registration_argon_parameters().as_ref(), &policy)?;
+            assert!(creds.check_argon_policy(None, &policy).is_err());
+
+            // a more costly policy requires the password to be hashed once more
+            let stricter = ArgonCostParameters {
+                time_cost: policy.time_cost + 1,
+                ..policy
+            };
+            assert!(creds
+                .check_argon_policy(creds.registration_argon_parameters().as_ref(), &stricter)
+                .is_err());
+            let adopted = creds.clone().adopt_argon_policy(&stricter).await?;
+            assert_eq!(adopted.registration_argon_parameters(), Some(stricter));
+            adopted
+                .check_argon_policy(adopted.registration_argon_parameters().as_ref(), &stricter)?;
+            assert_ne!(adopted.clone().decompose().1, creds.decompose().1);
+
+            // passwordless registrations are not hashed
+            let passwordless = ProposedCredentials::passwordless(USERNAME.to_string());
+            passwordless.check_argon_policy(None, &stricter)?;
+            Ok(())
+        })
+        .await
+    }
+
     #[tokio::test]
     async fn test_register_p2p() -> Result<(), AccountError> {
         test_harness(|container, pers_cl, pers_se| async move {
@@ -3,6 +3,8 @@
 #[cfg(not(target_family = "wasm"))]
 pub mod exports {
     pub use openssl;
+    pub use quinn::crypto::rustls::HandshakeData as QuicHandshakeData;
+    pub use quinn::ClientConfig as QuinnClientConfig;
     pub use quinn::{Accept, Connecting, Connection, Endpoint, RecvStream, SendStream};
     pub use rustls::ClientConfig;
     pub use rustls::{Certificate, PrivateKey};
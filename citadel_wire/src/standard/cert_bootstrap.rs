@@ -0,0 +1,245 @@
+//! Bootstrapping trust in the certificate of a self-signed server via a short pre-shared code
+//!
+//! Before the TLS handshake, a client holding the code runs SPAKE2 (RFC 9382) over P-256 with the
+//! server. The server binds the DER of its certificate into the transcript, and proves knowledge
+//! of the code via a confirmation over the transcript. Once the client verifies the confirmation,
+//! the certificate is authentic, and is pinned such that the TLS handshake, along with each later
+//! connection, is verified against it. A passive attacker learns nothing of the code, while an
+//! active attacker gains a single guess of the code per attempt
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ec::{EcGroup, EcPoint, PointConversionForm};
+use openssl::nid::Nid;
+
+/// The blinding points of SPAKE2 for P-256 (RFC 9382, section 6)
+const SPAKE2_M: [u8; 33] = [
+    0x02, 0x88, 0x6e, 0x2f, 0x97, 0xac, 0xe4, 0x6e, 0x55, 0xba, 0x9d, 0xd7, 0x24, 0x25, 0x79, 0xf2,
+    0x99, 0x3b, 0x64, 0xe1, 0x6e, 0xf3, 0xdc, 0xab, 0x95, 0xaf, 0xd4, 0x97, 0x33, 0x3d, 0x8f, 0xa1,
+    0x2f,
+];
+const SPAKE2_N: [u8; 33] = [
+    0x03, 0xd8, 0xbb, 0xd6, 0xc6, 0x39, 0xc6, 0x29, 0x37, 0xb0, 0x4d, 0x99, 0x7f, 0x38, 0xc3, 0x77,
+    0x07, 0x19, 0xc6, 0x29, 0xd7, 0x01, 0x4d, 0x49, 0xa2, 0x4b, 0x4f, 0x98, 0xba, 0xa1, 0x29, 0x2b,
+    0x49,
+];
+const CODE_DOMAIN: &[u8] = b"citadel-cert-bootstrap-code";
+const CONFIRMATION_DOMAIN: &[u8] = b"citadel-cert-bootstrap-server-confirmation";
+
+/// The client half of the exchange, created once the server advertises the bootstrap
+pub struct CertBootstrapClient {
+    x: BigNum,
+    w: BigNum,
+    client_share: Vec<u8>,
+}
+
+impl CertBootstrapClient {
+    /// Returns the client along with the share to send to the server
+    pub fn start(code: &str) -> Result<(Self, Vec<u8>), anyhow::Error> {
+        let mut params = Spake2Params::new(code)?;
+        let (x, client_share) = params.share(&SPAKE2_M)?;
+        let this = Self {
+            x,
+            w: params.w,
+            client_share: client_share.clone(),
+        };
+
+        Ok((this, client_share))
+    }
+
+    /// Verifies that the server knows the code, and that `cert_der` is the certificate it bound
+    /// into the exchange. Only then may `cert_der` be trusted
+    pub fn finish(
+        self,
+        server_share: &[u8],
+        cert_der: &[u8],
+        confirmation: &[u8],
+    ) -> Result<(), anyhow::Error> {
+        let mut params = Spake2Params::new_with_w(self.w)?;
+        let shared_point = params.shared_point(&self.x, server_share, &SPAKE2_N)?;
+        let expected =
+            params.confirmation(&self.client_share, server_share, &shared_point, cert_der);
+
+        if expected.len() == confirmation.len() && openssl::memcmp::eq(&expected, confirmation) {
+            Ok(())
+        } else {
+            Err(anyhow::Error::msg(
+                "The server failed to prove knowledge of the bootstrap code",
+            ))
+        }
+    }
+}
+
+/// The server half of the exchange, answering each client with the certificate of the server
+#[derive(Clone)]
+pub struct CertBootstrapResponder {
+    code: String,
+    cert_der: Vec<u8>,
+}
+
+impl CertBootstrapResponder {
+    pub fn new<T: Into<String>>(code: T, cert_der: Vec<u8>) -> Self {
+        Self {
+            code: code.into(),
+            cert_der,
+        }
+    }
+
+    /// The certificate the server binds into each exchange
+    pub fn cert_der(&self) -> &[u8] {
+        &self.cert_der
+    }
+
+    /// Returns the share of the server along with its confirmation, given the share of a client
+    pub fn respond(&self, client_share: &[u8]) -> Result<(Vec<u8>, Vec<u8>), anyhow::Error> {
+        let mut params = Spake2Params::new(&self.code)?;
+        let (y, server_share) = params.share(&SPAKE2_N)?;
+        let shared_point = params.shared_point(&y, client_share, &SPAKE2_M)?;
+        let confirmation =
+            params.confirmation(client_share, &server_share, &shared_point, &self.cert_der);
+        Ok((server_share, confirmation))
+    }
+}
+
+struct Spake2Params {
+    group: EcGroup,
+    order: BigNum,
+    ctx: BigNumContext,
+    w: BigNum,
+}
+
+impl Spake2Params {
+    fn new(code: &str) -> Result<Self, anyhow::Error> {
+        let digest = openssl::sha::sha256(&[CODE_DOMAIN, code.as_bytes()].concat());
+        Self::new_with_w(BigNum::from_slice(&digest)?)
+    }
+
+    fn new_with_w(w: BigNum) -> Result<Self, anyhow::Error> {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1)?;
+        let mut ctx = BigNumContext::new()?;
+        let mut order = BigNum::new()?;
+        group.order(&mut order, &mut ctx)?;
+        let mut reduced = BigNum::new()?;
+        reduced.nnmod(&w, &order, &mut ctx)?;
+
+        Ok(Self {
+            group,
+            order,
+            ctx,
+            w: reduced,
+        })
+    }
+
+    /// Returns a random scalar along with the share `scalar * G + w * blinding`
+    fn share(&mut self, blinding: &[u8]) -> Result<(BigNum, Vec<u8>), anyhow::Error> {
+        let mut scalar = BigNum::new()?;
+        while scalar.num_bits() == 0 {
+            self.order.rand_range(&mut scalar)?;
+        }
+
+        let mut public = EcPoint::new(&self.group)?;
+        public.mul_generator2(&self.group, &scalar, &mut self.ctx)?;
+        let blinded = self.blinded(blinding)?;
+        let mut share = EcPoint::new(&self.group)?;
+        share.add(&self.group, &public, &blinded, &mut self.ctx)?;
+
+        let share = share.to_bytes(
+            &self.group,
+            PointConversionForm::UNCOMPRESSED,
+            &mut self.ctx,
+        )?;
+        Ok((scalar, share))
+    }
+
+    /// Returns `scalar * (peer_share - w * peer_blinding)`
+    fn shared_point(
+        &mut self,
+        scalar: &BigNum,
+        peer_share: &[u8],
+        peer_blinding: &[u8],
+    ) -> Result<Vec<u8>, anyhow::Error> {
+        let peer_share = EcPoint::from_bytes(&self.group, peer_share, &mut self.ctx)?;
+        if peer_share.is_infinity(&self.group) {
+            return Err(anyhow::Error::msg("Invalid bootstrap share"));
+        }
+
+        let mut unblinding = self.blinded(peer_blinding)?;
+        unblinding.invert2(&self.group, &mut self.ctx)?;
+        let mut unblinded = EcPoint::new(&self.group)?;
+        unblinded.add(&self.group, &peer_share, &unblinding, &mut self.ctx)?;
+        let mut shared_point = EcPoint::new(&self.group)?;
+        shared_point.mul2(&self.group, &unblinded, scalar, &mut self.ctx)?;
+        if shared_point.is_infinity(&self.group) {
+            return Err(anyhow::Error::msg("Invalid bootstrap share"));
+        }
+
+        Ok(shared_point.to_bytes(
+            &self.group,
+            PointConversionForm::UNCOMPRESSED,
+            &mut self.ctx,
+        )?)
+    }
+
+    fn blinded(&mut self, blinding: &[u8]) -> Result<EcPoint, anyhow::Error> {
+        let blinding = EcPoint::from_bytes(&self.group, blinding, &mut self.ctx)?;
+        let mut blinded = EcPoint::new(&self.group)?;
+        blinded.mul2(&self.group, &blinding, &self.w, &mut self.ctx)?;
+        Ok(blinded)
+    }
+
+    /// Hashes the transcript of the exchange, each entry prefixed by its length
+    fn confirmation(
+        &self,
+        client_share: &[u8],
+        server_share: &[u8],
+        shared_point: &[u8],
+        cert_der: &[u8],
+    ) -> Vec<u8> {
+        let w = self.w.to_vec();
+        let mut transcript = CONFIRMATION_DOMAIN.to_vec();
+        for entry in [
+            client_share,
+            server_share,
+            shared_point,
+            w.as_slice(),
+            cert_der,
+        ] {
+            transcript.extend_from_slice(&(entry.len() as u64).to_le_bytes());
+            transcript.extend_from_slice(entry);
+        }
+
+        openssl::sha::sha256(&transcript).to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cert_bootstrap::{CertBootstrapClient, CertBootstrapResponder};
+
+    #[test]
+    fn test_bootstrap() {
+        let cert_der = crate::quic::generate_self_signed_cert().unwrap().0;
+        let responder = CertBootstrapResponder::new("428-913", cert_der.clone());
+
+        let (client, client_share) = CertBootstrapClient::start("428-913").unwrap();
+        let (server_share, confirmation) = responder.respond(&client_share).unwrap();
+        client
+            .finish(&server_share, &cert_der, &confirmation)
+            .unwrap();
+
+        // a substituted certificate is detected
+        let (client, client_share) = CertBootstrapClient::start("428-913").unwrap();
+        let (server_share, confirmation) = responder.respond(&client_share).unwrap();
+        let other_cert_der = crate::quic::generate_self_signed_cert().unwrap().0;
+        assert!(client
+            .finish(&server_share, &other_cert_der, &confirmation)
+            .is_err());
+
+        // as is a server that does not know the code
+        let (client, client_share) = CertBootstrapClient::start("428-914").unwrap();
+        let (server_share, confirmation) = responder.respond(&client_share).unwrap();
+        assert!(client
+            .finish(&server_share, &cert_der, &confirmation)
+            .is_err());
+
+        assert!(responder.respond(&[4u8; 65]).is_err());
+    }
+}
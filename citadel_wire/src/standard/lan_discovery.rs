@@ -0,0 +1,407 @@
+//! Discovery of Citadel nodes on the local network through mDNS/DNS-SD
+//!
+//! Nodes advertise the [`SERVICE_TYPE`] service on the mDNS multicast group. Browsing nodes
+//! periodically query for the service, and advertising nodes answer each query with a PTR record
+//! naming their instance, an SRV record holding the port they listen on, and a TXT record holding
+//! their properties. The address of a discovered node is the source address of its answer combined
+//! with the port of its SRV record, hence no A records are exchanged. Once an advertising node
+//! stops, it multicasts its records with a TTL of zero, announcing its departure
+//!
+//! Only the subset of DNS required by DNS-SD is implemented. Unrelated mDNS traffic on the group is
+//! ignored
+use crate::socket_helpers::get_reuse_udp_socket;
+use citadel_io::UdpSocket;
+use std::collections::BTreeMap;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::Duration;
+
+/// The DNS-SD service type under which Citadel nodes are advertised
+pub const SERVICE_TYPE: &str = "_citadel._udp.local";
+pub const MDNS_ADDR: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+pub const MDNS_PORT: u16 = 5353;
+/// The TTL of advertised records, in seconds
+pub const RECORD_TTL: u32 = 120;
+
+const TYPE_PTR: u16 = 12;
+const TYPE_TXT: u16 = 16;
+const TYPE_SRV: u16 = 33;
+const TYPE_ANY: u16 = 255;
+const CLASS_IN: u16 = 1;
+// tells receivers the record replaces any cached record of the same name and type
+const CLASS_CACHE_FLUSH: u16 = 0x8000;
+const FLAG_RESPONSE: u16 = 0x8000;
+const FLAG_AUTHORITATIVE: u16 = 0x0400;
+const MAX_POINTER_JUMPS: usize = 16;
+const MAX_MDNS_PACKET_LEN: usize = 9000;
+/// How often a probe re-sends its query and advertisement, covering for lost multicast packets
+const PROBE_INTERVAL: Duration = Duration::from_millis(150);
+
+/// The records advertised by a node
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct LanAdvertisement {
+    /// Uniquely names the node on the local network. Must be a single DNS label of at most 63 bytes
+    pub instance: String,
+    /// The port the node accepts connections on
+    pub port: u16,
+    /// Arbitrary key/value pairs published alongside the node
+    pub properties: BTreeMap<String, String>,
+}
+
+/// A node found on the local network
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DiscoveredNode {
+    pub instance: String,
+    pub addr: SocketAddr,
+    pub properties: BTreeMap<String, String>,
+}
+
+/// The parts of an mDNS message relevant to discovery
+#[derive(Debug, Default, Eq, PartialEq)]
+pub struct MdnsMessage {
+    /// True if the message queries for [`SERVICE_TYPE`]
+    pub queries_service: bool,
+    /// Nodes announced by the message
+    pub discovered: Vec<DiscoveredNode>,
+    /// Instances whose records were announced with a TTL of zero
+    pub departed: Vec<String>,
+}
+
+/// Binds a socket to the mDNS port that receives the multicast traffic of the local network
+pub fn bind_mdns_socket() -> Result<UdpSocket, anyhow::Error> {
+    let socket = get_reuse_udp_socket(SocketAddr::from((Ipv4Addr::UNSPECIFIED, MDNS_PORT)))?;
+    socket.join_multicast_v4(MDNS_ADDR, Ipv4Addr::UNSPECIFIED)?;
+    // allows multiple nodes on the same host to discover each other
+    socket.set_multicast_loop_v4(true)?;
+    Ok(socket)
+}
+
+/// The destination of every mDNS message
+pub fn mdns_group() -> SocketAddr {
+    SocketAddr::from((MDNS_ADDR, MDNS_PORT))
+}
+
+/// Advertises `advertisement` while querying the local network for the node advertising
+/// `peer_instance`. Returns the addr the peer was discovered at, or None if the peer did not
+/// answer within `timeout`, in which case it is not on the local network
+pub async fn probe_peer(
+    advertisement: &LanAdvertisement,
+    peer_instance: &str,
+    timeout: Duration,
+) -> Result<Option<SocketAddr>, anyhow::Error> {
+    let socket = bind_mdns_socket()?;
+    let group = mdns_group();
+    let mut interval = tokio::time::interval(PROBE_INTERVAL);
+    let mut buf = vec![0u8; MAX_MDNS_PACKET_LEN];
+
+    let search = async {
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    // the query is sent first, such that a peer answers it before it finds this node
+                    let _ = socket.send_to(&encode_query(), group).await?;
+                    let _ = socket.send_to(&encode_response(advertisement, RECORD_TTL), group).await?;
+                }
+
+                res = socket.recv_from(&mut buf) => {
+                    let (len, source) = res?;
+                    let message = if let Some(message) = parse_message(&buf[..len], source) {
+                        message
+                    } else {
+                        continue;
+                    };
+
+                    if message.queries_service {
+                        let _ = socket.send_to(&encode_response(advertisement, RECORD_TTL), group).await?;
+                    }
+
+                    if let Some(node) = message.discovered.into_iter().find(|node| node.instance == peer_instance) {
+                        return Ok::<_, anyhow::Error>(node.addr);
+                    }
+                }
+            }
+        }
+    };
+
+    let peer_addr = tokio::time::timeout(timeout, search)
+        .await
+        .ok()
+        .transpose()?;
+    let _ = socket
+        .send_to(&encode_response(advertisement, 0), group)
+        .await?;
+    Ok(peer_addr)
+}
+
+/// Encodes a query for [`SERVICE_TYPE`]
+pub fn encode_query() -> Vec<u8> {
+    let mut packet = Vec::with_capacity(64);
+    encode_header(&mut packet, 0, 1, 0);
+    encode_name(&mut packet, None);
+    push_u16(&mut packet, TYPE_PTR);
+    push_u16(&mut packet, CLASS_IN);
+    packet
+}
+
+/// Encodes the records of `advertisement`. A `ttl` of zero announces the departure of the node
+pub fn encode_response(advertisement: &LanAdvertisement, ttl: u32) -> Vec<u8> {
+    let instance = Some(advertisement.instance.as_str());
+    let mut packet = Vec::with_capacity(256);
+    encode_header(&mut packet, FLAG_RESPONSE | FLAG_AUTHORITATIVE, 0, 3);
+
+    encode_name(&mut packet, None);
+    encode_record_header(&mut packet, TYPE_PTR, CLASS_IN, ttl);
+    encode_rdata(&mut packet, |rdata| encode_name(rdata, instance));
+
+    encode_name(&mut packet, instance);
+    encode_record_header(&mut packet, TYPE_SRV, CLASS_IN | CLASS_CACHE_FLUSH, ttl);
+    encode_rdata(&mut packet, |rdata| {
+        // priority and weight are meaningless with a single target
+        push_u16(rdata, 0);
+        push_u16(rdata, 0);
+        push_u16(rdata, advertisement.port);
+        encode_name(rdata, instance);
+    });
+
+    encode_name(&mut packet, instance);
+    encode_record_header(&mut packet, TYPE_TXT, CLASS_IN | CLASS_CACHE_FLUSH, ttl);
+    encode_rdata(&mut packet, |rdata| {
+        for (key, value) in &advertisement.properties {
+            let entry = format!("{key}={value}");
+            let len = entry.len().min(u8::MAX as usize);
+            rdata.push(len as u8);
+            rdata.extend_from_slice(&entry.as_bytes()[..len]);
+        }
+
+        // a TXT record holds at least one string
+        if advertisement.properties.is_empty() {
+            rdata.push(0);
+        }
+    });
+
+    packet
+}
+
+/// Parses an mDNS message received from `source`. Returns None if the message is malformed
+pub fn parse_message(packet: &[u8], source: SocketAddr) -> Option<MdnsMessage> {
+    let flags = read_u16(packet, 2)?;
+    let questions = read_u16(packet, 4)?;
+    let records = read_u16(packet, 6)? as usize
+        + read_u16(packet, 8)? as usize
+        + read_u16(packet, 10)? as usize;
+
+    let mut message = MdnsMessage::default();
+    let mut pos = 12;
+
+    for _ in 0..questions {
+        let (name, next) = decode_name(packet, pos)?;
+        let record_type = read_u16(packet, next)?;
+        pos = next + 4;
+        if flags & FLAG_RESPONSE == 0
+            && is_service(&name)
+            && (record_type == TYPE_PTR || record_type == TYPE_ANY)
+        {
+            message.queries_service = true;
+        }
+    }
+
+    let mut ports = BTreeMap::new();
+    let mut properties = BTreeMap::new();
+
+    for _ in 0..records {
+        let (name, next) = decode_name(packet, pos)?;
+        let record_type = read_u16(packet, next)?;
+        let ttl = read_u32(packet, next + 4)?;
+        let rdata_len = read_u16(packet, next + 8)? as usize;
+        let rdata = next + 10;
+        pos = rdata + rdata_len;
+        if pos > packet.len() {
+            return None;
+        }
+
+        match record_type {
+            TYPE_PTR if is_service(&name) => {
+                let (target, _) = decode_name(packet, rdata)?;
+                if let Some(instance) = instance_of(&target) {
+                    if ttl == 0 {
+                        message.departed.push(instance);
+                    }
+                }
+            }
+
+            TYPE_SRV if ttl != 0 => {
+                if let Some(instance) = instance_of(&name) {
+                    let _ = ports.insert(instance, read_u16(packet, rdata + 4)?);
+                }
+            }
+
+            TYPE_TXT if ttl != 0 => {
+                if let Some(instance) = instance_of(&name) {
+                    let _ = properties.insert(instance, decode_txt(&packet[rdata..pos]));
+                }
+            }
+
+            _ => {}
+        }
+    }
+
+    for (instance, port) in ports {
+        let properties = properties.remove(&instance).unwrap_or_default();
+        message.discovered.push(DiscoveredNode {
+            instance,
+            addr: SocketAddr::new(source.ip(), port),
+            properties,
+        });
+    }
+
+    Some(message)
+}
+
+fn encode_header(packet: &mut Vec<u8>, flags: u16, questions: u16, answers: u16) {
+    // mDNS messages sent to the group carry an id of zero
+    push_u16(packet, 0);
+    push_u16(packet, flags);
+    push_u16(packet, questions);
+    push_u16(packet, answers);
+    push_u16(packet, 0);
+    push_u16(packet, 0);
+}
+
+fn encode_record_header(packet: &mut Vec<u8>, record_type: u16, class: u16, ttl: u32) {
+    push_u16(packet, record_type);
+    push_u16(packet, class);
+    packet.extend_from_slice(&ttl.to_be_bytes());
+}
+
+fn encode_rdata(packet: &mut Vec<u8>, encode: impl FnOnce(&mut Vec<u8>)) {
+    let mut rdata = Vec::new();
+    encode(&mut rdata);
+    push_u16(packet, rdata.len() as u16);
+    packet.extend_from_slice(&rdata);
+}
+
+// encodes the service name, prefixed by the instance label if present
+fn encode_name(packet: &mut Vec<u8>, instance: Option<&str>) {
+    for label in instance.into_iter().chain(SERVICE_TYPE.split('.')) {
+        let len = label.len().min(63);
+        packet.push(len as u8);
+        packet.extend_from_slice(&label.as_bytes()[..len]);
+    }
+    packet.push(0);
+}
+
+// returns the labels of the name at `pos`, and the position after the name
+fn decode_name(packet: &[u8], mut pos: usize) -> Option<(Vec<String>, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    let mut jumps = 0;
+
+    loop {
+        let len = *packet.get(pos)? as usize;
+        if len & 0xC0 == 0xC0 {
+            // a compressed name continues at the offset of the pointer
+            jumps += 1;
+            if jumps > MAX_POINTER_JUMPS {
+                return None;
+            }
+
+            let offset = ((len & 0x3F) << 8) | *packet.get(pos + 1)? as usize;
+            end.get_or_insert(pos + 2);
+            pos = offset;
+        } else if len == 0 {
+            return Some((labels, end.unwrap_or(pos + 1)));
+        } else {
+            let label = packet.get(pos + 1..pos + 1 + len)?;
+            labels.push(String::from_utf8_lossy(label).into_owned());
+            pos += 1 + len;
+        }
+    }
+}
+
+fn decode_txt(mut rdata: &[u8]) -> BTreeMap<String, String> {
+    let mut properties = BTreeMap::new();
+    while let Some((len, rest)) = rdata.split_first() {
+        let len = (*len as usize).min(rest.len());
+        let entry = String::from_utf8_lossy(&rest[..len]);
+        if let Some((key, value)) = entry.split_once('=') {
+            let _ = properties.insert(key.to_string(), value.to_string());
+        }
+        rdata = &rest[len..];
+    }
+    properties
+}
+
+fn is_service(labels: &[String]) -> bool {
+    let service = SERVICE_TYPE.split('.');
+    labels.len() == service.clone().count()
+        && labels
+            .iter()
+            .zip(service)
+            .all(|(label, expected)| label.eq_ignore_ascii_case(expected))
+}
+
+// returns the instance label of a name within the service
+fn instance_of(labels: &[String]) -> Option<String> {
+    let (instance, service) = labels.split_first()?;
+    is_service(service).then(|| instance.clone())
+}
+
+fn push_u16(packet: &mut Vec<u8>, value: u16) {
+    packet.extend_from_slice(&value.to_be_bytes());
+}
+
+fn read_u16(packet: &[u8], pos: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        packet.get(pos..pos + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(packet: &[u8], pos: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        packet.get(pos..pos + 4)?.try_into().ok()?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lan_discovery::{
+        encode_query, encode_response, parse_message, DiscoveredNode, LanAdvertisement, RECORD_TTL,
+    };
+    use std::collections::BTreeMap;
+    use std::net::SocketAddr;
+
+    #[test]
+    fn test_response_round_trip() {
+        let source: SocketAddr = "192.168.1.20:5353".parse().unwrap();
+        let query = parse_message(&encode_query(), source).unwrap();
+        assert!(query.queries_service);
+        assert!(query.discovered.is_empty());
+
+        let advertisement = LanAdvertisement {
+            instance: "alice-laptop".to_string(),
+            port: 25021,
+            properties: BTreeMap::from([("node_type".to_string(), "peer".to_string())]),
+        };
+
+        let response = parse_message(&encode_response(&advertisement, RECORD_TTL), source).unwrap();
+        assert!(!response.queries_service);
+        assert!(response.departed.is_empty());
+        assert_eq!(
+            response.discovered,
+            vec![DiscoveredNode {
+                instance: advertisement.instance.clone(),
+                addr: "192.168.1.20:25021".parse().unwrap(),
+                properties: advertisement.properties.clone(),
+            }]
+        );
+
+        let goodbye = parse_message(&encode_response(&advertisement, 0), source).unwrap();
+        assert!(goodbye.discovered.is_empty());
+        assert_eq!(goodbye.departed, vec![advertisement.instance]);
+
+        // truncated messages are rejected instead of panicking
+        let truncated = encode_response(&Default::default(), RECORD_TTL);
+        for len in 0..truncated.len() {
+            let _ = parse_message(&truncated[..len], source);
+        }
+    }
+}
@@ -1,6 +1,13 @@
+pub mod cert_bootstrap;
+#[cfg(feature = "lan-discovery")]
+pub mod lan_discovery;
 pub mod misc;
 pub mod nat_identification;
+pub mod nat_pmp_handler;
 pub mod quic;
+pub mod server_identity;
+pub mod server_key_pinning;
 pub mod socket_helpers;
+pub mod stun_server;
 pub mod tls;
 pub mod upnp_handler;
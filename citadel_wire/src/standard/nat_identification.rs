@@ -15,6 +15,9 @@ use std::sync::Arc;
 use std::time::Duration;
 
 use citadel_io::UdpSocket;
+use stun::addr::MappedAddress;
+use stun::agent::TransactionId;
+use stun::attributes::{ATTR_CHANGED_ADDRESS, ATTR_CHANGE_REQUEST, ATTR_OTHER_ADDRESS};
 use stun::client::ClientBuilder;
 use stun::message::{Getter, Message, BINDING_REQUEST};
 use stun::xoraddr::XorMappedAddress;
@@ -29,6 +32,14 @@ const V4_BIND_ADDR: &str = "0.0.0.0:0";
 const IDENTIFY_TIMEOUT: Duration = Duration::from_millis(4500);
 pub(crate) const MAX_PORT_DELTA_FOR_PREDICTION: usize = 30;
 pub(crate) const MAX_LAST_OCTET_DELTA_FOR_PREDICTION: usize = 2;
+/// Each behavior test waits this long for a response before retransmitting
+const BEHAVIOR_REQUEST_TIMEOUT: Duration = Duration::from_millis(400);
+const BEHAVIOR_REQUEST_ATTEMPTS: usize = 2;
+/// The flags of the CHANGE-REQUEST attribute (RFC 5780, section 7.2)
+const CHANGE_IP: u32 = 0x04;
+const CHANGE_PORT: u32 = 0x02;
+/// The number of sequential local ports the port allocation of a NAT is probed from
+const PORT_ALLOCATION_PROBES: u16 = 4;
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub enum NatType {
@@ -59,6 +70,139 @@ pub enum TraversalTypeRequired {
     TURN,
 }
 
+/// How a NAT maps internal addrs onto external addrs (RFC 4787, section 4.1)
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Default)]
+pub enum MappingBehavior {
+    /// The same external addr is used regardless of the destination
+    EndpointIndependent,
+    /// A new external addr is used for each destination IP
+    AddressDependent,
+    /// A new external addr is used for each destination IP and port
+    AddressAndPortDependent,
+    /// The STUN servers do not support RFC 5780, or did not respond
+    #[default]
+    Unknown,
+}
+
+/// Which inbound packets a NAT forwards to an internal addr (RFC 4787, section 5)
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Default)]
+pub enum FilteringBehavior {
+    /// Packets from any addr are forwarded
+    EndpointIndependent,
+    /// Only packets from IPs the internal addr has sent to are forwarded
+    AddressDependent,
+    /// Only packets from addrs (IP and port) the internal addr has sent to are forwarded
+    AddressAndPortDependent,
+    /// The STUN servers do not support RFC 5780, or did not respond
+    #[default]
+    Unknown,
+}
+
+/// The mapping and filtering behavior of the NAT which the local node is behind, as discovered
+/// via the tests of RFC 5780. Complements [`NatType`], which predicts the external addrs of the
+/// NAT, but cannot tell which inbound packets the NAT drops
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize, Default)]
+pub struct NatBehavior {
+    pub mapping: MappingBehavior,
+    pub filtering: FilteringBehavior,
+}
+
+impl NatBehavior {
+    /// Discovers the behavior of the NAT which the local node is behind, using the first STUN
+    /// server that advertises an alternate addr. If no server does, both behaviors are unknown
+    pub async fn discover(stun_servers: Option<Vec<String>>) -> Result<Self, FirewallError> {
+        match tokio::time::timeout(IDENTIFY_TIMEOUT, discover_nat_behavior(stun_servers)).await {
+            Ok(res) => res.map_err(|err| FirewallError::HolePunch(err.to_string())),
+            Err(_elapsed) => {
+                log::warn!(target: "citadel", "Timeout on NAT behavior discovery occurred");
+                Ok(Self::default())
+            }
+        }
+    }
+
+    /// Returns false if traversal between the local node and the peer is certain to fail due to
+    /// filtering. A node whose addr cannot be predicted is only reachable once it contacts the
+    /// other node first, yet, its packets then arrive from a port the other node never sent to,
+    /// which a NAT filtering by addr and port drops
+    pub fn admits_traversal_with(
+        &self,
+        local_nat_info: &NatType,
+        peer_behavior: &NatBehavior,
+        peer_nat_info: &NatType,
+    ) -> bool {
+        let (local_required, peer_required) =
+            local_nat_info.traversal_type_required_with(peer_nat_info);
+        let blocks = |required: &TraversalTypeRequired, behavior: &NatBehavior| {
+            *required == TraversalTypeRequired::TURN
+                && behavior.filtering == FilteringBehavior::AddressAndPortDependent
+        };
+
+        !(blocks(&peer_required, self) || blocks(&local_required, peer_behavior))
+    }
+}
+
+/// How a NAT allocates external ports to successive mappings, as measured by sending binding
+/// requests to the same STUN server from sequential local ports. A symmetric NAT allocates a new
+/// external port for each destination, thus, its next ports are predictable if allocated at a
+/// consistent delta
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct PortAllocation {
+    /// The external addr allocated to the last probe
+    pub last_external_addr: SocketAddr,
+    /// The difference between successively allocated external ports, which may be negative
+    pub delta: i32,
+}
+
+impl PortAllocation {
+    /// Measures the port allocation of the NAT which the local node is behind, using the first
+    /// STUN server that answers every probe. Returns None if the allocation is not predictable
+    pub async fn measure(stun_servers: Option<Vec<String>>) -> Result<Option<Self>, FirewallError> {
+        match tokio::time::timeout(IDENTIFY_TIMEOUT, measure_port_allocation(stun_servers)).await {
+            Ok(res) => res.map_err(|err| FirewallError::HolePunch(err.to_string())),
+            Err(_elapsed) => {
+                log::warn!(target: "citadel", "Timeout on port allocation measurement occurred");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Computes the allocation from the external addrs of the probes, in the order sent. Since
+    /// other traffic behind the NAT may claim ports in between probes, the delta shared by the
+    /// majority of successive probes is used
+    fn from_external_addrs(external_addrs: &[SocketAddr]) -> Option<Self> {
+        let last_external_addr = *external_addrs.last()?;
+        if external_addrs
+            .iter()
+            .any(|addr| addr.ip() != last_external_addr.ip())
+        {
+            return None;
+        }
+
+        let deltas = external_addrs
+            .iter()
+            .tuple_windows()
+            .map(|(prev, next)| next.port() as i32 - prev.port() as i32)
+            .collect::<Vec<i32>>();
+        let (delta, occurrences) = deltas
+            .iter()
+            .map(|delta| {
+                (
+                    *delta,
+                    deltas.iter().filter(|other| *other == delta).count(),
+                )
+            })
+            .max_by_key(|(_, occurrences)| *occurrences)?;
+
+        let predictable = occurrences * 2 > deltas.len()
+            && delta != 0
+            && delta.unsigned_abs() as usize <= MAX_PORT_DELTA_FOR_PREDICTION;
+        predictable.then_some(Self {
+            last_external_addr,
+            delta,
+        })
+    }
+}
+
 // we only need to check the NAT type once per node
 lazy_static::lazy_static! {
     pub static ref LOCALHOST_TESTING_NAT_TYPE: citadel_io::Mutex<Option<NatType>> = citadel_io::Mutex::new(None);
@@ -186,6 +330,61 @@ impl NatType {
         this != TraversalTypeRequired::TURN || other != TraversalTypeRequired::TURN
     }
 
+    /// Returns true if both nodes have an internal address within the same private subnet, in which
+    /// case they may reach each other directly, without traversing their NATs. Since netmasks are
+    /// not exchanged, a /24 is assumed for IPv4 and a /64 for IPv6. Unlike NAT traversal, this holds
+    /// even if both nodes sit behind the same NAT that does not support hairpinning
+    pub fn shares_lan_with(&self, other: &NatType) -> bool {
+        match (self.ip_addr_info(), other.ip_addr_info()) {
+            (Some(this), Some(other)) => same_private_subnet(this.internal_ip, other.internal_ip),
+            _ => false,
+        }
+    }
+
+    /// Returns true if both nodes were observed at the same external IP, as is the case for nodes
+    /// behind the same NAT
+    pub fn shares_external_ip_with(&self, other: &NatType) -> bool {
+        let other_ips = other.external_ips();
+        self.external_ips().iter().any(|ip| other_ips.contains(ip))
+    }
+
+    fn external_ips(&self) -> Vec<IpAddr> {
+        match self {
+            NatType::EIM(addr, ..) | NatType::EDM(addr, ..) | NatType::EDMRandomPort(addr, ..) => {
+                vec![addr.ip()]
+            }
+            NatType::PortPreserved(ip, ..) => vec![*ip],
+            NatType::EDMRandomIp(ips, ..) | NatType::EDMRandomIPPortPreserved(ips, ..) => {
+                ips.clone()
+            }
+            NatType::Unknown => Vec::new(),
+        }
+    }
+
+    /// Returns true if the NAT allocates a new external port for each destination
+    pub fn is_symmetric(&self) -> bool {
+        matches!(self, NatType::EDM(..) | NatType::EDMRandomPort(..))
+    }
+
+    /// Refines a symmetric NAT type with the [`PortAllocation`] measured behind it. Ports which
+    /// seemed random across STUN servers become predictable once the NAT is found to allocate
+    /// successive ports at a consistent delta
+    pub fn with_port_allocation(&self, allocation: Option<PortAllocation>) -> Self {
+        match (self, allocation) {
+            (
+                NatType::EDM(_, ip_info, _, v6) | NatType::EDMRandomPort(_, ip_info, _, v6),
+                Some(allocation),
+            ) => NatType::EDM(
+                allocation.last_external_addr,
+                ip_info.clone(),
+                allocation.delta,
+                *v6,
+            ),
+
+            _ => self.clone(),
+        }
+    }
+
     pub fn ip_addr_info(&self) -> Option<&IpAddressInfo> {
         match self {
             NatType::EIM(_, ip, _)
@@ -234,6 +433,23 @@ impl NatType {
     }
 }
 
+fn same_private_subnet(this: IpAddr, other: IpAddr) -> bool {
+    match (this, other) {
+        (IpAddr::V4(this), IpAddr::V4(other)) => {
+            (this.is_private() || this.is_link_local()) && this.octets()[..3] == other.octets()[..3]
+        }
+
+        (IpAddr::V6(this), IpAddr::V6(other)) => {
+            let segments = this.segments();
+            // unique local (fc00::/7) or link local (fe80::/10)
+            let is_private = segments[0] & 0xfe00 == 0xfc00 || segments[0] & 0xffc0 == 0xfe80;
+            is_private && segments[..4] == other.segments()[..4]
+        }
+
+        _ => false,
+    }
+}
+
 fn average_delta<T: Ord + Copy + Sized + Sub>(vals: impl AsRef<[T]>) -> usize
 where
     usize: From<<T as Sub>::Output>,
@@ -440,9 +656,228 @@ async fn get_nat_type(stun_servers: Option<Vec<String>>) -> Result<NatType, anyh
     Ok(nat_type)
 }
 
+async fn measure_port_allocation(
+    stun_servers: Option<Vec<String>>,
+) -> Result<Option<PortAllocation>, anyhow::Error> {
+    let stun_servers = if let Some(stun_servers) = &stun_servers {
+        Cow::Owned(stun_servers.iter().map(|r| r.as_str()).collect())
+    } else {
+        Cow::Borrowed(&STUN_SERVERS as &[&str])
+    };
+
+    for server in stun_servers.iter() {
+        let server_addr = match tokio::net::lookup_host(server)
+            .await
+            .ok()
+            .and_then(|mut addrs| addrs.find(SocketAddr::is_ipv4))
+        {
+            Some(addr) => addr,
+            None => continue,
+        };
+
+        // every probe stays bound until the last one is sent, such that no port is reused
+        let first_socket = UdpSocket::bind(V4_BIND_ADDR).await?;
+        let base_port = first_socket.local_addr()?.port();
+        let mut sockets = vec![first_socket];
+        for offset in 1..PORT_ALLOCATION_PROBES {
+            let sequential_addr = SocketAddr::from(([0, 0, 0, 0], base_port.wrapping_add(offset)));
+            let socket = match UdpSocket::bind(sequential_addr).await {
+                Ok(socket) => socket,
+                // the sequential port is taken; the allocation still shows in the external ports
+                Err(_) => UdpSocket::bind(V4_BIND_ADDR).await?,
+            };
+            sockets.push(socket);
+        }
+
+        let mut external_addrs = Vec::with_capacity(sockets.len());
+        for socket in &sockets {
+            match binding_request(socket, server_addr, 0).await? {
+                Some(response) => external_addrs.push(response.mapped),
+                None => break,
+            }
+        }
+
+        if external_addrs.len() != sockets.len() {
+            log::trace!(target: "citadel", "STUN server {server} did not answer every port allocation probe");
+            continue;
+        }
+
+        let allocation = PortAllocation::from_external_addrs(&external_addrs);
+        log::trace!(target: "citadel", "Port allocation: {:?} (external addrs: {:?})", allocation, external_addrs);
+        return Ok(allocation);
+    }
+
+    Ok(None)
+}
+
+/// Performs the mapping and filtering tests of RFC 5780 (sections 4.3 and 4.4)
+async fn discover_nat_behavior(
+    stun_servers: Option<Vec<String>>,
+) -> Result<NatBehavior, anyhow::Error> {
+    let stun_servers = if let Some(stun_servers) = &stun_servers {
+        Cow::Owned(stun_servers.iter().map(|r| r.as_str()).collect())
+    } else {
+        Cow::Borrowed(&STUN_SERVERS as &[&str])
+    };
+
+    for server in stun_servers.iter() {
+        let server_addr = match tokio::net::lookup_host(server)
+            .await
+            .ok()
+            .and_then(|mut addrs| addrs.find(SocketAddr::is_ipv4))
+        {
+            Some(addr) => addr,
+            None => continue,
+        };
+
+        let mapping_socket = UdpSocket::bind(V4_BIND_ADDR).await?;
+        let primary = match binding_request(&mapping_socket, server_addr, 0).await? {
+            Some(primary) => primary,
+            None => continue,
+        };
+
+        let other_addr = match primary.other_addr {
+            Some(other_addr) => other_addr,
+            None => {
+                log::trace!(target: "citadel", "STUN server {server} does not support RFC 5780");
+                continue;
+            }
+        };
+
+        let alternate_ip = SocketAddr::new(other_addr.ip(), server_addr.port());
+        let alternate_ip_mapped = binding_request(&mapping_socket, alternate_ip, 0)
+            .await?
+            .map(|response| response.mapped);
+        let alternate_addr_mapped = binding_request(&mapping_socket, other_addr, 0)
+            .await?
+            .map(|response| response.mapped);
+        let mapping = classify_mapping(primary.mapped, alternate_ip_mapped, alternate_addr_mapped);
+
+        // the mapping tests sent packets to the alternate addr, opening the filter of the NAT
+        // towards it. Thus, the filtering tests require a fresh mapping
+        let filtering_socket = UdpSocket::bind(V4_BIND_ADDR).await?;
+        let filtering = if binding_request(&filtering_socket, server_addr, 0)
+            .await?
+            .is_some()
+        {
+            let changed_addr_response =
+                binding_request(&filtering_socket, server_addr, CHANGE_IP | CHANGE_PORT)
+                    .await?
+                    .is_some();
+            let changed_port_response =
+                binding_request(&filtering_socket, server_addr, CHANGE_PORT)
+                    .await?
+                    .is_some();
+            classify_filtering(changed_addr_response, changed_port_response)
+        } else {
+            FilteringBehavior::Unknown
+        };
+
+        let behavior = NatBehavior { mapping, filtering };
+        log::trace!(target: "citadel", "NAT behavior: {:?}", behavior);
+        return Ok(behavior);
+    }
+
+    Ok(NatBehavior::default())
+}
+
+/// `primary` is the external addr observed by the primary addr of the STUN server,
+/// `alternate_ip` the one observed by its alternate IP on the primary port, and
+/// `alternate_addr` the one observed by its alternate IP on the alternate port
+fn classify_mapping(
+    primary: SocketAddr,
+    alternate_ip: Option<SocketAddr>,
+    alternate_addr: Option<SocketAddr>,
+) -> MappingBehavior {
+    match (alternate_ip, alternate_addr) {
+        (Some(alternate_ip), _) if alternate_ip == primary => MappingBehavior::EndpointIndependent,
+        (Some(alternate_ip), Some(alternate_addr)) if alternate_ip == alternate_addr => {
+            MappingBehavior::AddressDependent
+        }
+        (Some(_), Some(_)) => MappingBehavior::AddressAndPortDependent,
+        _ => MappingBehavior::Unknown,
+    }
+}
+
+/// `changed_addr_response` is true if the response sent from the alternate IP and port of the
+/// STUN server arrived, and `changed_port_response` if the one sent from the alternate port did
+fn classify_filtering(
+    changed_addr_response: bool,
+    changed_port_response: bool,
+) -> FilteringBehavior {
+    if changed_addr_response {
+        FilteringBehavior::EndpointIndependent
+    } else if changed_port_response {
+        FilteringBehavior::AddressDependent
+    } else {
+        FilteringBehavior::AddressAndPortDependent
+    }
+}
+
+struct BindingResponse {
+    mapped: SocketAddr,
+    other_addr: Option<SocketAddr>,
+}
+
+/// Sends a binding request to `server`, asking it to respond from the addr implied by
+/// `change_request`. Returns None if no response arrives
+async fn binding_request(
+    socket: &UdpSocket,
+    server: SocketAddr,
+    change_request: u32,
+) -> Result<Option<BindingResponse>, anyhow::Error> {
+    let mut request = Message::new();
+    request.build(&[Box::<TransactionId>::default(), Box::new(BINDING_REQUEST)])?;
+    if change_request != 0 {
+        request.add(ATTR_CHANGE_REQUEST, &change_request.to_be_bytes());
+    }
+
+    let mut buf = [0u8; 1280];
+
+    for _ in 0..BEHAVIOR_REQUEST_ATTEMPTS {
+        let _ = socket.send_to(&request.raw, server).await?;
+
+        // the response may arrive from any addr of the server, thus only the transaction is matched
+        let recv_response = async {
+            loop {
+                let (len, _) = socket.recv_from(&mut buf).await?;
+                let mut response = Message::new();
+                if response.unmarshal_binary(&buf[..len]).is_ok()
+                    && response.transaction_id == request.transaction_id
+                {
+                    return Ok::<_, anyhow::Error>(response);
+                }
+            }
+        };
+
+        if let Ok(response) = tokio::time::timeout(BEHAVIOR_REQUEST_TIMEOUT, recv_response).await {
+            let response = response?;
+            let mut xor_addr = XorMappedAddress::default();
+            xor_addr.get_from(&response)?;
+
+            // servers predating RFC 5780 advertise their alternate addr as CHANGED-ADDRESS
+            let mut other_addr = MappedAddress::default();
+            let has_other_addr = [ATTR_OTHER_ADDRESS, ATTR_CHANGED_ADDRESS]
+                .into_iter()
+                .any(|attr| other_addr.get_from_as(&response, attr).is_ok());
+
+            return Ok(Some(BindingResponse {
+                mapped: SocketAddr::new(xor_addr.ip, xor_addr.port),
+                other_addr: has_other_addr.then(|| SocketAddr::new(other_addr.ip, other_addr.port)),
+            }));
+        }
+    }
+
+    Ok(None)
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::nat_identification::NatType;
+    use crate::nat_identification::{
+        classify_filtering, classify_mapping, FilteringBehavior, MappingBehavior, NatBehavior,
+        NatType, PortAllocation, TraversalTypeRequired,
+    };
+    use async_ip::IpAddressInfo;
     use std::net::{IpAddr, SocketAddr};
     use std::str::FromStr;
 
@@ -454,6 +889,59 @@ mod tests {
         log::trace!(target: "citadel", "NAT Type: {:?} | Reaching this node will require: {:?} NAT traversal | Hypothetical connect scenario", nat_type, traversal_type);
     }
 
+    #[test]
+    fn test_classify_behavior() {
+        let addr = |port: u16| Some(SocketAddr::from(([123, 100, 200, 100], port)));
+        let primary = addr(4000).unwrap();
+
+        assert_eq!(
+            classify_mapping(primary, addr(4000), addr(4000)),
+            MappingBehavior::EndpointIndependent
+        );
+        assert_eq!(
+            classify_mapping(primary, addr(4001), addr(4001)),
+            MappingBehavior::AddressDependent
+        );
+        assert_eq!(
+            classify_mapping(primary, addr(4001), addr(4002)),
+            MappingBehavior::AddressAndPortDependent
+        );
+        assert_eq!(
+            classify_mapping(primary, None, addr(4002)),
+            MappingBehavior::Unknown
+        );
+
+        assert_eq!(
+            classify_filtering(true, true),
+            FilteringBehavior::EndpointIndependent
+        );
+        assert_eq!(
+            classify_filtering(false, true),
+            FilteringBehavior::AddressDependent
+        );
+        assert_eq!(
+            classify_filtering(false, false),
+            FilteringBehavior::AddressAndPortDependent
+        );
+    }
+
+    #[test]
+    fn test_admits_traversal() {
+        let eim = NatType::EIM(SocketAddr::from_str("127.0.0.1:1234").unwrap(), None, false);
+        let random_ip = NatType::EDMRandomIp(vec![], None, false);
+        let port_restricted = NatBehavior {
+            mapping: MappingBehavior::EndpointIndependent,
+            filtering: FilteringBehavior::AddressAndPortDependent,
+        };
+        let unknown = NatBehavior::default();
+
+        assert!(unknown.admits_traversal_with(&eim, &unknown, &random_ip));
+        assert!(unknown.admits_traversal_with(&eim, &port_restricted, &random_ip));
+        assert!(port_restricted.admits_traversal_with(&eim, &unknown, &eim));
+        assert!(!port_restricted.admits_traversal_with(&eim, &unknown, &random_ip));
+        assert!(!unknown.admits_traversal_with(&random_ip, &port_restricted, &eim));
+    }
+
     #[test]
     fn test_average_delta_computation() {
         assert_average_delta_inner(vec![70, 10, 50, 30], 20);
@@ -463,6 +951,51 @@ mod tests {
         assert_average_delta_inner(vec![2, 4, 6, 8, 10, 12], 2);
     }
 
+    #[test]
+    fn test_port_allocation() {
+        let probes = |ports: &[u16]| {
+            ports
+                .iter()
+                .map(|port| SocketAddr::from(([123, 100, 200, 100], *port)))
+                .collect::<Vec<_>>()
+        };
+
+        let allocation =
+            PortAllocation::from_external_addrs(&probes(&[1000, 1004, 1008, 1012])).unwrap();
+        assert_eq!(allocation.delta, 4);
+        assert_eq!(allocation.last_external_addr.port(), 1012);
+        // a port claimed by other traffic in between probes does not hide the delta
+        let allocation =
+            PortAllocation::from_external_addrs(&probes(&[1000, 998, 994, 992])).unwrap();
+        assert_eq!(allocation.delta, -2);
+        // random, preserved and overly spread ports are not predictable
+        assert!(PortAllocation::from_external_addrs(&probes(&[1000, 1700, 1300, 5000])).is_none());
+        assert!(PortAllocation::from_external_addrs(&probes(&[1000, 1000, 1000, 1000])).is_none());
+        assert!(PortAllocation::from_external_addrs(&probes(&[1000, 1100, 1200, 1300])).is_none());
+
+        let random_port = NatType::EDMRandomPort(
+            SocketAddr::from(([123, 100, 200, 100], 1000)),
+            None,
+            vec![1000, 1700, 1300],
+            false,
+        );
+        assert_eq!(
+            random_port.traversal_type_required(),
+            TraversalTypeRequired::TURN
+        );
+        let refined = random_port.with_port_allocation(Some(allocation));
+        assert_eq!(
+            refined.traversal_type_required(),
+            TraversalTypeRequired::Delta(-2)
+        );
+        // only symmetric NATs are refined
+        let eim = NatType::EIM(SocketAddr::from(([123, 100, 200, 100], 1000)), None, false);
+        assert!(matches!(
+            eim.with_port_allocation(Some(allocation)),
+            NatType::EIM(..)
+        ));
+    }
+
     fn assert_average_delta_inner(ports: Vec<u16>, expected: u16) {
         let dummy_nat_type = NatType::EDMRandomPort(
             SocketAddr::from_str("127.0.0.1:1234").unwrap(),
@@ -476,6 +1009,31 @@ mod tests {
         )
     }
 
+    #[test]
+    fn test_shares_lan() {
+        let with_internal_ip = |internal_ip: &str| {
+            NatType::EDMRandomIp(
+                vec![],
+                Some(IpAddressInfo {
+                    internal_ip: IpAddr::from_str(internal_ip).unwrap(),
+                    external_ipv6: None,
+                }),
+                false,
+            )
+        };
+
+        let lan = with_internal_ip("192.168.1.20");
+        assert!(lan.shares_lan_with(&with_internal_ip("192.168.1.31")));
+        assert!(!lan.shares_lan_with(&with_internal_ip("192.168.2.31")));
+        assert!(!lan.shares_lan_with(&NatType::Unknown));
+        // public addresses are never assumed to share a LAN
+        let public = with_internal_ip("123.100.200.100");
+        assert!(!public.shares_lan_with(&with_internal_ip("123.100.200.101")));
+        let v6 = with_internal_ip("fd00:1:2:3::10");
+        assert!(v6.shares_lan_with(&with_internal_ip("fd00:1:2:3::20")));
+        assert!(!v6.shares_lan_with(&lan));
+    }
+
     #[test]
     fn test_nat_traversal_compat() {
         let dummy_addr = SocketAddr::from_str("127.0.0.1:1234").unwrap();
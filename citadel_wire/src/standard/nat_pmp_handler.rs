@@ -0,0 +1,367 @@
+//! Port mapping via PCP (RFC 6887) and its predecessor NAT-PMP (RFC 6886)
+//!
+//! Many routers that do not implement UPnP IGD instead accept mapping requests at UDP port 5351 of
+//! the default gateway. PCP is attempted first. If the router only speaks NAT-PMP, it answers with
+//! an unsupported version result, after which the request is repeated using NAT-PMP
+use crate::error::FirewallError;
+use citadel_io::UdpSocket;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use tokio::time::{Duration, Instant};
+
+const SERVER_PORT: u16 = 5351;
+const PCP_VERSION: u8 = 2;
+const NAT_PMP_VERSION: u8 = 0;
+const RESPONSE_BIT: u8 = 0x80;
+/// The MAP opcode of PCP, and the map UDP opcode of NAT-PMP
+const OPCODE_MAP: u8 = 1;
+const NAT_PMP_OPCODE_EXTERNAL_ADDR: u8 = 0;
+const PROTOCOL_UDP: u8 = 17;
+/// Both protocols share this result code
+const RESULT_UNSUPPORTED_VERSION: u16 = 1;
+const PCP_MAP_LEN: usize = 60;
+const NAT_PMP_MAP_LEN: usize = 16;
+const NAT_PMP_EXTERNAL_ADDR_LEN: usize = 12;
+/// The first retransmission interval mandated by both RFCs, doubling thereafter
+const INITIAL_RETRANSMIT: Duration = Duration::from_millis(250);
+
+/// The protocol a mapping was obtained through, needed to later remove it
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MappingProtocol {
+    Pcp,
+    NatPmp,
+}
+
+pub struct NatPmpHandler {
+    gateway: Ipv4Addr,
+    local_ip_address: Ipv4Addr,
+    timeout: Duration,
+}
+
+impl NatPmpHandler {
+    /// Locates the default gateway. Unlike UPnP, no discovery takes place, hence the gateway is
+    /// not known to support either protocol until a mapping is requested
+    ///
+    /// `timeout`: bounds each request. If None, uses the default (2 seconds)
+    pub async fn new(timeout: Option<Duration>) -> Result<Self, FirewallError> {
+        let local_ip_address = match async_ip::get_internal_ip(false).await {
+            Some(IpAddr::V4(ip)) => ip,
+            Some(IpAddr::V6(_)) => {
+                return Err(FirewallError::UPNP(
+                    "Detected LAN IPv6. Not yet implemented".to_string(),
+                ))
+            }
+            None => return Err(FirewallError::LocalIPAddrFail),
+        };
+
+        let gateway = default_gateway().unwrap_or_else(|| {
+            // assume the router sits at the first addr of the /24, as is most common
+            let octets = local_ip_address.octets();
+            Ipv4Addr::new(octets[0], octets[1], octets[2], 1)
+        });
+
+        Ok(Self {
+            gateway,
+            local_ip_address,
+            timeout: timeout.unwrap_or(Duration::from_millis(2000)),
+        })
+    }
+
+    pub fn get_default_gateway(&self) -> &Ipv4Addr {
+        &self.gateway
+    }
+
+    pub fn get_local_ip(&self) -> &Ipv4Addr {
+        &self.local_ip_address
+    }
+
+    /// Maps any external port to `local_port`, returning the external addr of the mapping alongside
+    /// the protocol used. `lease_duration` is in seconds, and must be nonzero
+    pub async fn open_any_firewall_port(
+        &self,
+        lease_duration: u32,
+        local_port: u16,
+    ) -> Result<(SocketAddr, MappingProtocol), FirewallError> {
+        match self.map_pcp(lease_duration, local_port, 0).await {
+            Err(FirewallError::NotApplicable) => {
+                log::trace!(target: "citadel", "Gateway {} does not support PCP; falling back to NAT-PMP", self.gateway);
+                self.map_nat_pmp(lease_duration, local_port, 0)
+                    .await
+                    .map(|addr| (addr, MappingProtocol::NatPmp))
+            }
+
+            res => res.map(|addr| (addr, MappingProtocol::Pcp)),
+        }
+    }
+
+    pub async fn close_firewall_port(
+        &self,
+        protocol: MappingProtocol,
+        local_port: u16,
+    ) -> Result<(), FirewallError> {
+        // a lease of zero deletes the mapping
+        match protocol {
+            MappingProtocol::Pcp => self.map_pcp(0, local_port, 0).await.map(|_| ()),
+            MappingProtocol::NatPmp => self.map_nat_pmp(0, local_port, 0).await.map(|_| ()),
+        }
+    }
+
+    async fn map_pcp(
+        &self,
+        lease_duration: u32,
+        local_port: u16,
+        external_port: u16,
+    ) -> Result<SocketAddr, FirewallError> {
+        let mut nonce = [0u8; 12];
+        nonce.copy_from_slice(&uuid::Uuid::new_v4().as_bytes()[..12]);
+        let request = encode_pcp_map(
+            self.local_ip_address,
+            nonce,
+            lease_duration,
+            local_port,
+            external_port,
+        );
+        let response = self.transact(&request).await?;
+        decode_pcp_map(&response, nonce)
+    }
+
+    async fn map_nat_pmp(
+        &self,
+        lease_duration: u32,
+        local_port: u16,
+        external_port: u16,
+    ) -> Result<SocketAddr, FirewallError> {
+        // NAT-PMP does not return the external ip alongside the mapping
+        let response = self
+            .transact(&[NAT_PMP_VERSION, NAT_PMP_OPCODE_EXTERNAL_ADDR])
+            .await?;
+        let external_ip = decode_nat_pmp_external_addr(&response)?;
+        let request = encode_nat_pmp_map(lease_duration, local_port, external_port);
+        let response = self.transact(&request).await?;
+        decode_nat_pmp_map(&response).map(|port| SocketAddr::new(IpAddr::V4(external_ip), port))
+    }
+
+    /// Sends `request` to the gateway, retransmitting until a response arrives or the timeout elapses
+    async fn transact(&self, request: &[u8]) -> Result<Vec<u8>, FirewallError> {
+        let socket = UdpSocket::bind(SocketAddr::new(IpAddr::V4(self.local_ip_address), 0))
+            .await
+            .map_err(|err| FirewallError::UPNP(err.to_string()))?;
+        socket
+            .connect(SocketAddr::new(IpAddr::V4(self.gateway), SERVER_PORT))
+            .await
+            .map_err(|err| FirewallError::UPNP(err.to_string()))?;
+
+        let deadline = Instant::now() + self.timeout;
+        let mut retransmit = INITIAL_RETRANSMIT;
+        let buf = &mut [0u8; 1100];
+
+        while Instant::now() < deadline {
+            let _ = socket
+                .send(request)
+                .await
+                .map_err(|err| FirewallError::UPNP(err.to_string()))?;
+            let wait = std::cmp::min(
+                retransmit,
+                deadline.saturating_duration_since(Instant::now()),
+            );
+            match tokio::time::timeout(wait, socket.recv(buf)).await {
+                Ok(Ok(len)) => return Ok(buf[..len].to_vec()),
+                // the gateway refused the connection, since it does not listen on the port
+                Ok(Err(err)) => return Err(FirewallError::UPNP(err.to_string())),
+                Err(_elapsed) => {}
+            }
+
+            retransmit *= 2;
+        }
+
+        Err(FirewallError::UPNP(format!(
+            "Gateway {} did not respond to the port mapping request",
+            self.gateway
+        )))
+    }
+}
+
+fn encode_pcp_map(
+    client_ip: Ipv4Addr,
+    nonce: [u8; 12],
+    lease_duration: u32,
+    local_port: u16,
+    external_port: u16,
+) -> [u8; PCP_MAP_LEN] {
+    let mut packet = [0u8; PCP_MAP_LEN];
+    packet[0] = PCP_VERSION;
+    packet[1] = OPCODE_MAP;
+    packet[4..8].copy_from_slice(&lease_duration.to_be_bytes());
+    packet[8..24].copy_from_slice(&client_ip.to_ipv6_mapped().octets());
+    packet[24..36].copy_from_slice(&nonce);
+    packet[36] = PROTOCOL_UDP;
+    packet[40..42].copy_from_slice(&local_port.to_be_bytes());
+    packet[42..44].copy_from_slice(&external_port.to_be_bytes());
+    // no suggested external ip: the v4-mapped unspecified addr
+    packet[44..60].copy_from_slice(&Ipv4Addr::UNSPECIFIED.to_ipv6_mapped().octets());
+    packet
+}
+
+/// Returns [`FirewallError::NotApplicable`] if the gateway does not speak PCP
+fn decode_pcp_map(packet: &[u8], nonce: [u8; 12]) -> Result<SocketAddr, FirewallError> {
+    // NAT-PMP gateways respond to unknown versions with a NAT-PMP header
+    if packet.len() >= 4
+        && packet[0] == NAT_PMP_VERSION
+        && u16::from_be_bytes([packet[2], packet[3]]) == RESULT_UNSUPPORTED_VERSION
+    {
+        return Err(FirewallError::NotApplicable);
+    }
+
+    if packet.len() < 4 || packet[0] != PCP_VERSION || packet[1] != RESPONSE_BIT | OPCODE_MAP {
+        return Err(FirewallError::UPNP("Invalid PCP response".to_string()));
+    }
+
+    match packet[3] as u16 {
+        0 => {}
+        RESULT_UNSUPPORTED_VERSION => return Err(FirewallError::NotApplicable),
+        code => {
+            return Err(FirewallError::UPNP(format!(
+                "Gateway refused the PCP mapping (result code {code})"
+            )))
+        }
+    }
+
+    if packet.len() < PCP_MAP_LEN || packet[24..36] != nonce {
+        return Err(FirewallError::UPNP("Invalid PCP response".to_string()));
+    }
+
+    let external_port = u16::from_be_bytes([packet[42], packet[43]]);
+    let mut external_ip = [0u8; 16];
+    external_ip.copy_from_slice(&packet[44..60]);
+    let external_ip = Ipv6Addr::from(external_ip);
+    let external_ip = external_ip
+        .to_ipv4_mapped()
+        .map(IpAddr::V4)
+        .unwrap_or(IpAddr::V6(external_ip));
+    Ok(SocketAddr::new(external_ip, external_port))
+}
+
+fn encode_nat_pmp_map(lease_duration: u32, local_port: u16, external_port: u16) -> [u8; 12] {
+    let mut packet = [0u8; 12];
+    packet[0] = NAT_PMP_VERSION;
+    packet[1] = OPCODE_MAP;
+    packet[4..6].copy_from_slice(&local_port.to_be_bytes());
+    packet[6..8].copy_from_slice(&external_port.to_be_bytes());
+    packet[8..12].copy_from_slice(&lease_duration.to_be_bytes());
+    packet
+}
+
+fn check_nat_pmp_header(packet: &[u8], opcode: u8, len: usize) -> Result<(), FirewallError> {
+    if packet.len() < len || packet[0] != NAT_PMP_VERSION || packet[1] != RESPONSE_BIT | opcode {
+        return Err(FirewallError::UPNP("Invalid NAT-PMP response".to_string()));
+    }
+
+    match u16::from_be_bytes([packet[2], packet[3]]) {
+        0 => Ok(()),
+        code => Err(FirewallError::UPNP(format!(
+            "Gateway refused the NAT-PMP request (result code {code})"
+        ))),
+    }
+}
+
+fn decode_nat_pmp_external_addr(packet: &[u8]) -> Result<Ipv4Addr, FirewallError> {
+    check_nat_pmp_header(
+        packet,
+        NAT_PMP_OPCODE_EXTERNAL_ADDR,
+        NAT_PMP_EXTERNAL_ADDR_LEN,
+    )?;
+    Ok(Ipv4Addr::new(packet[8], packet[9], packet[10], packet[11]))
+}
+
+fn decode_nat_pmp_map(packet: &[u8]) -> Result<u16, FirewallError> {
+    check_nat_pmp_header(packet, OPCODE_MAP, NAT_PMP_MAP_LEN)?;
+    Ok(u16::from_be_bytes([packet[10], packet[11]]))
+}
+
+/// Reads the default route from the kernel routing table
+#[cfg(target_os = "linux")]
+fn default_gateway() -> Option<Ipv4Addr> {
+    let table = std::fs::read_to_string("/proc/net/route").ok()?;
+    parse_default_gateway(&table)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn default_gateway() -> Option<Ipv4Addr> {
+    None
+}
+
+/// Each line of the table holds the interface, destination and gateway, with addrs in
+/// little-endian hex. The default route has a destination of zero
+#[allow(dead_code)]
+fn parse_default_gateway(table: &str) -> Option<Ipv4Addr> {
+    table.lines().skip(1).find_map(|line| {
+        let mut fields = line.split_whitespace().skip(1);
+        let destination = fields.next()?;
+        let gateway = u32::from_str_radix(fields.next()?, 16).ok()?;
+        (destination == "00000000" && gateway != 0).then(|| Ipv4Addr::from(gateway.to_le_bytes()))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::FirewallError;
+    use crate::nat_pmp_handler::{
+        decode_nat_pmp_external_addr, decode_nat_pmp_map, decode_pcp_map, encode_nat_pmp_map,
+        encode_pcp_map, parse_default_gateway,
+    };
+    use std::net::{Ipv4Addr, SocketAddr};
+
+    #[test]
+    fn test_pcp_map() {
+        let nonce = [7u8; 12];
+        let request = encode_pcp_map(Ipv4Addr::new(192, 168, 1, 20), nonce, 600, 4000, 0);
+        assert_eq!(&request[..2], &[2, 1]);
+        assert_eq!(&request[20..24], &[192, 168, 1, 20]);
+
+        // the gateway echoes the request, filling in the assigned addr
+        let mut response = request;
+        response[1] |= 0x80;
+        response[42..44].copy_from_slice(&40000u16.to_be_bytes());
+        response[56..60].copy_from_slice(&[123, 100, 200, 100]);
+        assert_eq!(
+            decode_pcp_map(&response, nonce).unwrap(),
+            SocketAddr::from(([123, 100, 200, 100], 40000))
+        );
+        assert!(decode_pcp_map(&response, [8u8; 12]).is_err());
+
+        // a NAT-PMP gateway rejects the PCP version
+        assert!(matches!(
+            decode_pcp_map(&[0, 0x81, 0, 1, 0, 0, 0, 0], nonce),
+            Err(FirewallError::NotApplicable)
+        ));
+    }
+
+    #[test]
+    fn test_nat_pmp_map() {
+        let request = encode_nat_pmp_map(600, 4000, 0);
+        assert_eq!(request, [0, 1, 0, 0, 15, 160, 0, 0, 0, 0, 2, 88]);
+
+        let external_addr = [0, 128, 0, 0, 0, 0, 0, 1, 123, 100, 200, 100];
+        assert_eq!(
+            decode_nat_pmp_external_addr(&external_addr).unwrap(),
+            Ipv4Addr::new(123, 100, 200, 100)
+        );
+
+        let map = [0, 129, 0, 0, 0, 0, 0, 1, 15, 160, 156, 64, 0, 0, 2, 88];
+        assert_eq!(decode_nat_pmp_map(&map).unwrap(), 40000);
+
+        // result code 2: not authorized
+        let refused = [0, 129, 0, 2, 0, 0, 0, 1, 15, 160, 0, 0, 0, 0, 0, 0];
+        assert!(decode_nat_pmp_map(&refused).is_err());
+    }
+
+    #[test]
+    fn test_parse_default_gateway() {
+        let table = "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\n\
+                     eth0\t0001A8C0\t00000000\t0001\t0\t0\t0\t00FFFFFF\n\
+                     eth0\t00000000\t0101A8C0\t0003\t0\t0\t0\t00000000\n";
+        assert_eq!(
+            parse_default_gateway(table),
+            Some(Ipv4Addr::new(192, 168, 1, 1))
+        );
+    }
+}
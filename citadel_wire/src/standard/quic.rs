@@ -88,11 +88,14 @@ pub trait QuicEndpointListener {
                 .listener()
                 .await
                 .ok_or_else(|| anyhow::Error::msg(QUIC_LISTENER_DIED))?;
-            let conn = connecting.await?;
+            let peer_addr = connecting.remote_address();
+            let conn = connecting
+                .await
+                .map_err(|err| QuicAcceptError { peer_addr, err })?;
             let (sink, stream) = conn
                 .accept_bi()
                 .await
-                .map_err(|err| anyhow::Error::msg(err.to_string()))?;
+                .map_err(|err| QuicAcceptError { peer_addr, err })?;
 
             Ok((conn, sink, stream))
         })
@@ -101,6 +104,25 @@ pub trait QuicEndpointListener {
 
 pub const QUIC_LISTENER_DIED: &str = "No QUIC connections available";
 
+/// An inbound connection failed before its first bidirectional stream was accepted
+#[derive(Debug)]
+pub struct QuicAcceptError {
+    pub peer_addr: SocketAddr,
+    pub err: quinn::ConnectionError,
+}
+
+impl std::fmt::Display for QuicAcceptError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "QUIC handshake with {} failed: {}",
+            self.peer_addr, self.err
+        )
+    }
+}
+
+impl std::error::Error for QuicAcceptError {}
+
 impl QuicEndpointConnector for QuicNode {
     fn endpoint(&self) -> &Endpoint {
         &self.endpoint
@@ -0,0 +1,132 @@
+//! Rotation of the key of a self-signed server without resetting the trust of its clients
+//!
+//! Clients pin the certificate of a self-signed server (see [`ServerKeyPinning`]), thus a server
+//! presenting a new certificate is refused. To rotate its key, the server first generates its
+//! upcoming [`ServerIdentity`], then signs the upcoming certificate with its current key via
+//! [`ServerIdentity::announce_rotation`]. The server publishes the resulting
+//! [`KeyRotationAnnouncement`] to each connecting client ahead of the cutover. Clients verify the
+//! announcement against the certificate they pinned, and once the server presents the upcoming
+//! certificate, pin it in place of the current one. Publishing the announcement after the cutover
+//! as well lets clients that did not connect in between rotate their pin
+//!
+//! [`ServerKeyPinning`]: crate::server_key_pinning::ServerKeyPinning
+use crate::tls::TLSQUICInterop;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::{Signer, Verifier};
+use openssl::x509::X509;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+const ROTATION_DOMAIN: &[u8] = b"citadel-server-key-rotation";
+
+/// The certificate and private key identifying a self-signed server across restarts
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ServerIdentity {
+    pub cert_der: Vec<u8>,
+    pub priv_key_der: Vec<u8>,
+}
+
+impl ServerIdentity {
+    /// Generates a new self-signed identity
+    pub fn generate() -> Result<Self, anyhow::Error> {
+        let (cert_der, priv_key_der) = crate::quic::generate_self_signed_cert()?;
+        Ok(Self {
+            cert_der,
+            priv_key_der,
+        })
+    }
+
+    /// Loads the identity saved at `path`
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, anyhow::Error> {
+        Ok(bincode2::deserialize(&std::fs::read(path)?)?)
+    }
+
+    /// Saves the identity to `path`. The file holds the private key, and must be kept secret
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), anyhow::Error> {
+        std::fs::write(path, bincode2::serialize(self)?)?;
+        Ok(())
+    }
+
+    /// Signs the certificate of `upcoming` with the key of this identity, such that clients
+    /// pinning this identity accept `upcoming` once the server presents it
+    pub fn announce_rotation(
+        &self,
+        upcoming: &ServerIdentity,
+    ) -> Result<KeyRotationAnnouncement, anyhow::Error> {
+        Ok(KeyRotationAnnouncement {
+            upcoming_cert_der: upcoming.cert_der.clone(),
+            signature: self.sign(ROTATION_DOMAIN, &upcoming.cert_der)?,
+        })
+    }
+
+    /// Signs `message` with the key of this identity. `domain` separates the purposes for which
+    /// the key signs, such that a signature issued for one purpose is never valid for another
+    pub fn sign(&self, domain: &[u8], message: &[u8]) -> Result<Vec<u8>, anyhow::Error> {
+        let key = PKey::private_key_from_pkcs8(&self.priv_key_der)?;
+        let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+        Ok(signer.sign_oneshot_to_vec(&[domain, message].concat())?)
+    }
+
+    /// Returns the TLS config presenting this identity
+    pub fn tls_interop(&self) -> Result<TLSQUICInterop, anyhow::Error> {
+        crate::tls::create_server_config_from_der(&self.cert_der, &self.priv_key_der)
+    }
+}
+
+/// The upcoming certificate of a server, signed by the key of its current certificate
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct KeyRotationAnnouncement {
+    pub upcoming_cert_der: Vec<u8>,
+    signature: Vec<u8>,
+}
+
+impl KeyRotationAnnouncement {
+    /// Verifies that the key of `current_cert_der` signed the upcoming certificate
+    pub fn verify(&self, current_cert_der: &[u8]) -> Result<(), anyhow::Error> {
+        if verify_signature(
+            current_cert_der,
+            ROTATION_DOMAIN,
+            &self.upcoming_cert_der,
+            &self.signature,
+        )? {
+            Ok(())
+        } else {
+            Err(anyhow::Error::msg(
+                "The key rotation was not signed by the current key of the server",
+            ))
+        }
+    }
+}
+
+/// Returns true if the key of `cert_der` signed `message` under `domain` via [`ServerIdentity::sign`]
+pub fn verify_signature(
+    cert_der: &[u8],
+    domain: &[u8],
+    message: &[u8],
+    signature: &[u8],
+) -> Result<bool, anyhow::Error> {
+    let key = X509::from_der(cert_der)?.public_key()?;
+    let mut verifier = Verifier::new(MessageDigest::sha256(), &key)?;
+    Ok(verifier.verify_oneshot(signature, &[domain, message].concat())?)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::server_identity::ServerIdentity;
+
+    #[test]
+    fn test_rotation_announcement() {
+        let current = ServerIdentity::generate().unwrap();
+        let upcoming = ServerIdentity::generate().unwrap();
+        let announcement = current.announce_rotation(&upcoming).unwrap();
+        announcement.verify(&current.cert_der).unwrap();
+        assert!(announcement.verify(&upcoming.cert_der).is_err());
+
+        // an announcement signed by a key other than the current one is rejected
+        let forged = upcoming.announce_rotation(&upcoming).unwrap();
+        assert!(forged.verify(&current.cert_der).is_err());
+
+        let _ = upcoming.tls_interop().unwrap();
+    }
+}
@@ -0,0 +1,356 @@
+//! Trust-on-first-use pinning of the certificates presented by self-signed servers
+//!
+//! Clients skip verifying the certificate of a self-signed server, since no CA vouches for it.
+//! With pinning enabled, the certificate a server presents on the first connection is stored
+//! and compared against the certificate presented on each later connection. Since the TLS
+//! handshake proves possession of the private key of the presented certificate, an unchanged
+//! certificate implies an unchanged server key. Once the certificate changes, the
+//! [`ServerKeyChangePolicy`] decides whether the connection proceeds, unless the change was
+//! announced by the server via a [`KeyRotationAnnouncement`]
+use crate::server_identity::KeyRotationAnnouncement;
+use citadel_io::Mutex;
+use rustls::client::{ServerCertVerified, ServerCertVerifier};
+use rustls::{Certificate, ServerName};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+/// Stores the certificate first presented by each server, keyed by the addr of the server
+pub trait PinnedCertStore: Send + Sync + 'static {
+    fn load(&self, server: &str) -> Option<Vec<u8>>;
+    fn store(&self, server: &str, cert_der: Vec<u8>) -> Result<(), anyhow::Error>;
+}
+
+/// Pins certificates for the lifetime of the process
+#[derive(Default)]
+pub struct InMemoryPinnedCertStore {
+    certs: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl PinnedCertStore for InMemoryPinnedCertStore {
+    fn load(&self, server: &str) -> Option<Vec<u8>> {
+        self.certs.lock().get(server).cloned()
+    }
+
+    fn store(&self, server: &str, cert_der: Vec<u8>) -> Result<(), anyhow::Error> {
+        let _ = self.certs.lock().insert(server.to_string(), cert_der);
+        Ok(())
+    }
+}
+
+/// Pins certificates across restarts by persisting them to a single file
+pub struct FilePinnedCertStore {
+    path: PathBuf,
+    certs: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl FilePinnedCertStore {
+    /// Loads the certificates pinned at `path`, if the file exists
+    pub fn open<P: Into<PathBuf>>(path: P) -> Result<Self, anyhow::Error> {
+        let path = path.into();
+        let certs = if path.exists() {
+            bincode2::deserialize(&std::fs::read(&path)?)?
+        } else {
+            HashMap::new()
+        };
+
+        Ok(Self {
+            path,
+            certs: Mutex::new(certs),
+        })
+    }
+}
+
+impl PinnedCertStore for FilePinnedCertStore {
+    fn load(&self, server: &str) -> Option<Vec<u8>> {
+        self.certs.lock().get(server).cloned()
+    }
+
+    fn store(&self, server: &str, cert_der: Vec<u8>) -> Result<(), anyhow::Error> {
+        let mut certs = self.certs.lock();
+        let _ = certs.insert(server.to_string(), cert_der);
+        std::fs::write(&self.path, bincode2::serialize(&*certs)?)?;
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub enum ServerKeyChangePolicy {
+    /// A warning is logged, yet the connection proceeds. The originally pinned certificate is kept
+    Warn,
+    /// The connection is refused
+    #[default]
+    Refuse,
+}
+
+#[derive(Clone)]
+pub struct ServerKeyPinning {
+    store: Arc<dyn PinnedCertStore>,
+    policy: ServerKeyChangePolicy,
+}
+
+impl ServerKeyPinning {
+    pub fn new<T: PinnedCertStore>(store: T, policy: ServerKeyChangePolicy) -> Self {
+        Self {
+            store: Arc::new(store),
+            policy,
+        }
+    }
+
+    /// Pins certificates for the lifetime of the process
+    pub fn in_memory(policy: ServerKeyChangePolicy) -> Self {
+        Self::new(InMemoryPinnedCertStore::default(), policy)
+    }
+
+    /// Returns true if a certificate is pinned for `server`
+    pub fn is_pinned(&self, server: SocketAddr) -> bool {
+        self.store.load(&server.to_string()).is_some()
+    }
+
+    /// Pins `cert_der` for `server` ahead of the first connection, such as once the certificate
+    /// was authenticated via [`CertBootstrapClient`]
+    ///
+    /// [`CertBootstrapClient`]: crate::cert_bootstrap::CertBootstrapClient
+    pub fn pin(&self, server: SocketAddr, cert_der: Vec<u8>) -> Result<(), anyhow::Error> {
+        self.store.store(&server.to_string(), cert_der)
+    }
+
+    /// Returns a client config that pins the certificate of the self-signed server at `server`
+    pub fn rustls_client_config(&self, server: SocketAddr) -> rustls::ClientConfig {
+        self.rustls_client_config_with_rotation(server, None)
+    }
+
+    /// Like [`Self::rustls_client_config`], yet also consumes the key rotation announced by the
+    /// server. A rotation signed by the key of the pinned certificate is remembered until the
+    /// server presents the upcoming certificate, which is then pinned in place of the current one
+    pub fn rustls_client_config_with_rotation(
+        &self,
+        server: SocketAddr,
+        rotation: Option<KeyRotationAnnouncement>,
+    ) -> rustls::ClientConfig {
+        let verifier = TofuServerVerification {
+            server: server.to_string(),
+            pinning: self.clone(),
+            rotation,
+        };
+
+        let mut cfg = rustls::ClientConfig::builder()
+            .with_safe_defaults()
+            .with_custom_certificate_verifier(Arc::new(verifier))
+            .with_no_client_auth();
+
+        cfg.enable_sni = true;
+        cfg
+    }
+}
+
+struct TofuServerVerification {
+    server: String,
+    pinning: ServerKeyPinning,
+    rotation: Option<KeyRotationAnnouncement>,
+}
+
+impl TofuServerVerification {
+    // the upcoming certificate is stored alongside the pinned certificate
+    fn upcoming_key(&self) -> String {
+        format!("{}/upcoming", self.server)
+    }
+
+    // returns the announced rotation, if signed by the key of the pinned certificate
+    fn verified_rotation(&self, pinned: &[u8]) -> Option<&KeyRotationAnnouncement> {
+        let rotation = self.rotation.as_ref()?;
+        match rotation.verify(pinned) {
+            Ok(()) => Some(rotation),
+            Err(err) => {
+                log::warn!(target: "citadel", "Ignoring the key rotation announced by {}: {:?}", self.server, err);
+                None
+            }
+        }
+    }
+}
+
+impl ServerCertVerifier for TofuServerVerification {
+    fn verify_server_cert(
+        &self,
+        end_entity: &Certificate,
+        _intermediates: &[Certificate],
+        _server_name: &ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        match self.pinning.store.load(&self.server) {
+            None => {
+                log::info!(target: "citadel", "Pinning the certificate of {} on first use", self.server);
+                self.pinning
+                    .store
+                    .store(&self.server, end_entity.0.clone())
+                    .map_err(|err| rustls::Error::General(err.to_string()))?;
+                Ok(ServerCertVerified::assertion())
+            }
+
+            Some(pinned) if pinned == end_entity.0 => {
+                if let Some(rotation) = self.verified_rotation(&pinned) {
+                    self.pinning
+                        .store
+                        .store(&self.upcoming_key(), rotation.upcoming_cert_der.clone())
+                        .map_err(|err| rustls::Error::General(err.to_string()))?;
+                }
+
+                Ok(ServerCertVerified::assertion())
+            }
+
+            Some(pinned)
+                if self
+                    .verified_rotation(&pinned)
+                    .map(|rotation| rotation.upcoming_cert_der == end_entity.0)
+                    .unwrap_or(false)
+                    || self.pinning.store.load(&self.upcoming_key()).as_ref()
+                        == Some(&end_entity.0) =>
+            {
+                log::info!(target: "citadel", "Pinning the rotated certificate of {}", self.server);
+                self.pinning
+                    .store
+                    .store(&self.server, end_entity.0.clone())
+                    .map_err(|err| rustls::Error::General(err.to_string()))?;
+                Ok(ServerCertVerified::assertion())
+            }
+
+            Some(_) => match self.pinning.policy {
+                ServerKeyChangePolicy::Warn => {
+                    log::warn!(target: "citadel", "The certificate of {} changed since it was pinned", self.server);
+                    Ok(ServerCertVerified::assertion())
+                }
+
+                ServerKeyChangePolicy::Refuse => {
+                    log::error!(target: "citadel", "The certificate of {} changed since it was pinned. Refusing to connect", self.server);
+                    Err(rustls::Error::General(format!(
+                        "The certificate of {} changed since it was pinned",
+                        self.server
+                    )))
+                }
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::quic::{generate_self_signed_cert, SELF_SIGNED_DOMAIN};
+    use crate::server_identity::{KeyRotationAnnouncement, ServerIdentity};
+    use crate::server_key_pinning::{
+        FilePinnedCertStore, ServerKeyChangePolicy, ServerKeyPinning, TofuServerVerification,
+    };
+    use rustls::client::ServerCertVerifier;
+    use rustls::{Certificate, ServerName};
+    use std::time::SystemTime;
+
+    fn verify(pinning: &ServerKeyPinning, cert: &Certificate) -> bool {
+        verify_with_rotation(pinning, cert, None)
+    }
+
+    fn verify_with_rotation(
+        pinning: &ServerKeyPinning,
+        cert: &Certificate,
+        rotation: Option<KeyRotationAnnouncement>,
+    ) -> bool {
+        let verifier = TofuServerVerification {
+            server: "127.0.0.1:25021".to_string(),
+            pinning: pinning.clone(),
+            rotation,
+        };
+
+        verifier
+            .verify_server_cert(
+                cert,
+                &[],
+                &ServerName::try_from(SELF_SIGNED_DOMAIN).unwrap(),
+                &mut std::iter::empty(),
+                &[],
+                SystemTime::now(),
+            )
+            .is_ok()
+    }
+
+    #[test]
+    fn test_pin_on_first_use() {
+        let original = Certificate(generate_self_signed_cert().unwrap().0);
+        let changed = Certificate(generate_self_signed_cert().unwrap().0);
+
+        let refusing = ServerKeyPinning::in_memory(ServerKeyChangePolicy::Refuse);
+        assert!(verify(&refusing, &original));
+        assert!(verify(&refusing, &original));
+        assert!(!verify(&refusing, &changed));
+
+        let warning = ServerKeyPinning::in_memory(ServerKeyChangePolicy::Warn);
+        assert!(verify(&warning, &original));
+        assert!(verify(&warning, &changed));
+        // the original certificate stays pinned
+        assert_eq!(warning.store.load("127.0.0.1:25021").unwrap(), original.0);
+    }
+
+    #[test]
+    fn test_file_store_persists() {
+        let path = std::env::temp_dir().join(format!("pinned_certs_{}", uuid::Uuid::new_v4()));
+        let original = Certificate(generate_self_signed_cert().unwrap().0);
+        let changed = Certificate(generate_self_signed_cert().unwrap().0);
+
+        let pinning = ServerKeyPinning::new(
+            FilePinnedCertStore::open(&path).unwrap(),
+            ServerKeyChangePolicy::Refuse,
+        );
+        assert!(verify(&pinning, &original));
+
+        // a restarted client still knows the original certificate
+        let pinning = ServerKeyPinning::new(
+            FilePinnedCertStore::open(&path).unwrap(),
+            ServerKeyChangePolicy::Refuse,
+        );
+        assert!(!verify(&pinning, &changed));
+        assert!(verify(&pinning, &original));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_announced_rotation() {
+        let current = ServerIdentity::generate().unwrap();
+        let upcoming = ServerIdentity::generate().unwrap();
+        let announcement = current.announce_rotation(&upcoming).unwrap();
+        let (current_cert, upcoming_cert) = (
+            Certificate(current.cert_der.clone()),
+            Certificate(upcoming.cert_der.clone()),
+        );
+
+        // announced ahead of the cutover
+        let pinning = ServerKeyPinning::in_memory(ServerKeyChangePolicy::Refuse);
+        assert!(verify(&pinning, &current_cert));
+        assert!(verify_with_rotation(
+            &pinning,
+            &current_cert,
+            Some(announcement.clone())
+        ));
+        assert!(verify(&pinning, &upcoming_cert));
+        assert!(!verify(&pinning, &current_cert));
+
+        // announced at the cutover
+        let pinning = ServerKeyPinning::in_memory(ServerKeyChangePolicy::Refuse);
+        assert!(verify(&pinning, &current_cert));
+        assert!(verify_with_rotation(
+            &pinning,
+            &upcoming_cert,
+            Some(announcement)
+        ));
+
+        // an announcement not signed by the pinned key is ignored
+        let pinning = ServerKeyPinning::in_memory(ServerKeyChangePolicy::Refuse);
+        let forged = upcoming.announce_rotation(&upcoming).unwrap();
+        assert!(verify(&pinning, &current_cert));
+        assert!(!verify_with_rotation(
+            &pinning,
+            &upcoming_cert,
+            Some(forged)
+        ));
+    }
+}
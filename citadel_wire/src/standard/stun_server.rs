@@ -0,0 +1,115 @@
+//! A lightweight STUN responder (RFC 8489), allowing a server to act as the STUN server of its own
+//! clients such that a single-server deployment needs no third-party STUN infrastructure. Only
+//! binding requests are answered, each with the addr the request was received from
+//!
+//! NAT identification expects three STUN servers, thus clients of a single-server deployment list
+//! the responder's addr thrice. Since each request then targets the same destination, NATs whose
+//! mapping depends on the destination cannot be told apart from those whose mapping does not
+use crate::socket_helpers::get_udp_socket;
+use citadel_io::UdpSocket;
+use std::io;
+use std::net::SocketAddr;
+use stun::fingerprint::FINGERPRINT;
+use stun::message::{Message, BINDING_REQUEST, BINDING_SUCCESS};
+use stun::xoraddr::XorMappedAddress;
+
+/// Requests larger than the minimum IPv6 MTU are not expected from clients
+const MAX_REQUEST_LEN: usize = 1280;
+
+/// Answers STUN binding requests received on its UDP socket
+pub struct StunServer {
+    socket: UdpSocket,
+}
+
+impl StunServer {
+    /// Binds the responder to `bind_addr`
+    pub fn bind(bind_addr: SocketAddr) -> io::Result<Self> {
+        let socket = get_udp_socket(bind_addr)
+            .map_err(|err| io::Error::new(io::ErrorKind::AddrNotAvailable, err.to_string()))?;
+        Ok(Self { socket })
+    }
+
+    /// Returns the addr the responder is bound to
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Answers binding requests until the socket fails. Malformed requests are ignored
+    pub async fn serve(&self) -> io::Result<()> {
+        let mut buf = [0u8; MAX_REQUEST_LEN];
+
+        loop {
+            let (len, peer_addr) = self.socket.recv_from(&mut buf).await?;
+            match binding_response(&buf[..len], peer_addr) {
+                Some(response) => {
+                    let _ = self.socket.send_to(&response, peer_addr).await?;
+                }
+
+                None => {
+                    log::trace!(target: "citadel", "Ignoring invalid STUN request from {peer_addr}")
+                }
+            }
+        }
+    }
+}
+
+/// Returns the response to `packet` if it is a binding request, mapping `peer_addr`
+fn binding_response(packet: &[u8], peer_addr: SocketAddr) -> Option<Vec<u8>> {
+    let mut request = Message::new();
+    request.unmarshal_binary(packet).ok()?;
+
+    if request.typ != BINDING_REQUEST {
+        return None;
+    }
+
+    let mut response = Message::new();
+    response
+        .build(&[
+            Box::new(request.transaction_id),
+            Box::new(BINDING_SUCCESS),
+            Box::new(XorMappedAddress {
+                ip: peer_addr.ip(),
+                port: peer_addr.port(),
+            }),
+            Box::new(FINGERPRINT),
+        ])
+        .ok()?;
+
+    Some(response.raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stun_server::StunServer;
+    use citadel_io::UdpSocket;
+    use stun::agent::TransactionId;
+    use stun::message::{Getter, Message, BINDING_REQUEST, BINDING_SUCCESS};
+    use stun::xoraddr::XorMappedAddress;
+
+    #[tokio::test]
+    async fn test_binding_request() {
+        citadel_logging::setup_log();
+        let server = StunServer::bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        let server_addr = server.local_addr().unwrap();
+        let _server = tokio::task::spawn(async move { server.serve().await });
+
+        let client = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut request = Message::new();
+        request
+            .build(&[Box::<TransactionId>::default(), Box::new(BINDING_REQUEST)])
+            .unwrap();
+        let _ = client.send_to(&request.raw, server_addr).await.unwrap();
+
+        let mut buf = [0u8; 512];
+        let (len, _) = client.recv_from(&mut buf).await.unwrap();
+        let mut response = Message::new();
+        response.unmarshal_binary(&buf[..len]).unwrap();
+        assert_eq!(response.typ, BINDING_SUCCESS);
+        assert_eq!(response.transaction_id, request.transaction_id);
+
+        let mut xor_addr = XorMappedAddress::default();
+        xor_addr.get_from(&response).unwrap();
+        let mapped = client.local_addr().unwrap();
+        assert_eq!((xor_addr.ip, xor_addr.port), (mapped.ip(), mapped.port()));
+    }
+}
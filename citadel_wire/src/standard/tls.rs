@@ -61,8 +61,16 @@ pub fn client_config_to_tls_connector(config: Arc<ClientConfig>) -> TlsConnector
 
 pub fn create_server_self_signed_config() -> Result<TLSQUICInterop, anyhow::Error> {
     let (cert_der, priv_key_der) = generate_self_signed_cert()?;
+    create_server_config_from_der(&cert_der, &priv_key_der)
+}
+
+/// Creates a server config from a single certificate and its private key, both DER-encoded
+pub fn create_server_config_from_der(
+    cert_der: &[u8],
+    priv_key_der: &[u8],
+) -> Result<TLSQUICInterop, anyhow::Error> {
     let (quic_chain, quic_priv_key) =
-        crate::misc::cert_and_priv_key_der_to_quic_keys(&cert_der, &priv_key_der)?;
+        crate::misc::cert_and_priv_key_der_to_quic_keys(cert_der, priv_key_der)?;
     let quic_chain = vec![quic_chain];
 
     // the server won't verify clients. The clients verify the server
@@ -0,0 +1,198 @@
+//! ICE-like prioritization of the addrs targeted during traversal (RFC 8445, section 5.1.2)
+//!
+//! Each addr of the peer is a candidate, whose type reflects how it was learned. Candidates are
+//! probed in order of priority, and once the first candidate is verified, the hole puncher waits
+//! for the nomination window to elapse before selecting the verified candidate of the highest
+//! priority. Thus, the selected path is chosen by quality, not merely by which reply arrived first
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// How the addr of a candidate was learned
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum CandidateType {
+    /// An addr bound directly by the peer, such as its LAN addr, or a global IPv6 addr
+    Host,
+    /// An addr of the peer as translated by its NAT, whether observed via STUN, predicted, or
+    /// explicitly mapped on its router
+    ServerReflexive,
+    /// An addr of a relay forwarding packets to the peer
+    Relayed,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Candidate {
+    pub addr: SocketAddr,
+    pub candidate_type: CandidateType,
+}
+
+/// Determines the priority of candidates, and how long to wait for better candidates
+#[derive(Copy, Clone, Debug)]
+pub struct CandidatePolicy {
+    pub host_preference: u8,
+    pub server_reflexive_preference: u8,
+    pub relayed_preference: u8,
+    /// If true, IPv6 candidates are preferred over IPv4 candidates of the same type
+    pub prefer_ipv6: bool,
+    /// How long to wait for candidates of a higher priority once the first candidate is verified.
+    /// If zero, the first verified candidate is selected
+    pub nomination_window: Duration,
+}
+
+impl Default for CandidatePolicy {
+    /// Uses the type preferences recommended by RFC 8445
+    fn default() -> Self {
+        Self {
+            host_preference: 126,
+            server_reflexive_preference: 100,
+            relayed_preference: 0,
+            prefer_ipv6: true,
+            nomination_window: Duration::from_millis(100),
+        }
+    }
+}
+
+impl CandidatePolicy {
+    /// Computes the priority of `candidate`, where higher is better. Since each hole puncher
+    /// has a single component, the component ID is omitted
+    pub fn priority(&self, candidate: &Candidate) -> u32 {
+        let type_preference = match candidate.candidate_type {
+            CandidateType::Host => self.host_preference,
+            CandidateType::ServerReflexive => self.server_reflexive_preference,
+            CandidateType::Relayed => self.relayed_preference,
+        };
+
+        let local_preference: u32 = if candidate.addr.is_ipv6() == self.prefer_ipv6 {
+            65535
+        } else {
+            0
+        };
+
+        ((type_preference as u32) << 24) | (local_preference << 8)
+    }
+
+    /// Returns the addrs of `candidates` from the highest to the lowest priority. Candidates of
+    /// equal priority retain their order, and duplicate addrs retain only their best candidate
+    pub fn prioritize(&self, mut candidates: Vec<Candidate>) -> Vec<SocketAddr> {
+        candidates.sort_by_key(|candidate| std::cmp::Reverse(self.priority(candidate)));
+        let mut addrs: Vec<SocketAddr> = Vec::with_capacity(candidates.len());
+        for candidate in candidates {
+            if !addrs.contains(&candidate.addr) {
+                addrs.push(candidate.addr);
+            }
+        }
+
+        addrs
+    }
+}
+
+/// Restricts and orders the IP versions of the addrs targeted during traversal. Unlike
+/// [`CandidatePolicy::prefer_ipv6`], which only orders candidates of the same type, the preferred
+/// IP version is probed before any candidate of the other version
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum DualStackPolicy {
+    /// IPv6 candidates are probed ahead of IPv4 candidates
+    V6First,
+    /// IPv4 candidates are probed ahead of IPv6 candidates
+    V4First,
+    /// Only IPv6 candidates are probed, and the local socket is bound to IPv6
+    V6Only,
+    /// Only IPv4 candidates are probed, and the local socket is bound to IPv4
+    V4Only,
+}
+
+impl DualStackPolicy {
+    /// Returns true if candidates of the IP version of `addr` are probed
+    pub fn admits(&self, addr: &SocketAddr) -> bool {
+        match self {
+            Self::V6Only => addr.is_ipv6(),
+            Self::V4Only => addr.is_ipv4(),
+            Self::V6First | Self::V4First => true,
+        }
+    }
+
+    /// Removes the addrs not admitted, then moves the addrs of the preferred IP version ahead of
+    /// the others. Addrs of the same IP version retain their order
+    pub fn apply(&self, mut addrs: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        addrs.retain(|addr| self.admits(addr));
+        let prefers_ipv6 = matches!(self, Self::V6First | Self::V6Only);
+        addrs.sort_by_key(|addr| addr.is_ipv6() != prefers_ipv6);
+        addrs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::udp_traversal::candidate_priority::{
+        Candidate, CandidatePolicy, CandidateType, DualStackPolicy,
+    };
+    use std::net::SocketAddr;
+
+    #[test]
+    fn test_prioritize() {
+        let candidate = |addr: &str, candidate_type| Candidate {
+            addr: addr.parse::<SocketAddr>().unwrap(),
+            candidate_type,
+        };
+
+        let candidates = vec![
+            candidate("123.100.200.100:4000", CandidateType::ServerReflexive),
+            candidate("10.0.0.2:4000", CandidateType::Host),
+            candidate("[2001:db8::2]:4000", CandidateType::Host),
+            candidate("123.100.200.100:4000", CandidateType::Relayed),
+            candidate("[2001:db8::9]:4000", CandidateType::ServerReflexive),
+        ];
+
+        let policy = CandidatePolicy::default();
+        assert_eq!(
+            policy.prioritize(candidates.clone()),
+            vec![
+                "[2001:db8::2]:4000".parse::<SocketAddr>().unwrap(),
+                "10.0.0.2:4000".parse().unwrap(),
+                "[2001:db8::9]:4000".parse().unwrap(),
+                "123.100.200.100:4000".parse().unwrap(),
+            ]
+        );
+
+        let policy = CandidatePolicy {
+            prefer_ipv6: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            policy.prioritize(candidates)[..2],
+            [
+                "10.0.0.2:4000".parse::<SocketAddr>().unwrap(),
+                "[2001:db8::2]:4000".parse().unwrap(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dual_stack_policy() {
+        let addrs = [
+            "10.0.0.2:4000",
+            "[2001:db8::2]:4000",
+            "123.100.200.100:4000",
+            "[2001:db8::9]:4000",
+        ]
+        .iter()
+        .map(|addr| addr.parse::<SocketAddr>().unwrap())
+        .collect::<Vec<_>>();
+
+        assert_eq!(
+            DualStackPolicy::V6First.apply(addrs.clone()),
+            vec![addrs[1], addrs[3], addrs[0], addrs[2]]
+        );
+        assert_eq!(
+            DualStackPolicy::V4First.apply(addrs.clone()),
+            vec![addrs[0], addrs[2], addrs[1], addrs[3]]
+        );
+        assert_eq!(
+            DualStackPolicy::V6Only.apply(addrs.clone()),
+            vec![addrs[1], addrs[3]]
+        );
+        assert_eq!(
+            DualStackPolicy::V4Only.apply(addrs.clone()),
+            vec![addrs[0], addrs[2]]
+        );
+    }
+}
@@ -1,8 +1,13 @@
 #![cfg_attr(feature = "localhost-testing-loopback-only", allow(unreachable_code))]
-use crate::nat_identification::NatType;
+use crate::nat_identification::{NatBehavior, NatType};
+use crate::nat_pmp_handler::NatPmpHandler;
+use crate::udp_traversal::candidate_priority::{Candidate, CandidatePolicy, CandidateType};
+use crate::upnp_handler::UPnPHandler;
 use async_ip::IpAddressInfo;
 use citadel_io::UdpSocket;
-use std::net::{IpAddr, SocketAddr};
+use igd::PortMappingProtocol;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::time::Duration;
 
 #[derive(Debug)]
 pub struct HolePunchConfig {
@@ -13,6 +18,12 @@ pub struct HolePunchConfig {
 }
 
 const SPREAD: u16 = 6;
+/// Bounds the search for a gateway, since traversal waits on it
+const GATEWAY_SEARCH_TIMEOUT: Duration = Duration::from_millis(1500);
+/// PCP and NAT-PMP gateways are not searched for, and answer promptly if present
+const PCP_REQUEST_TIMEOUT: Duration = Duration::from_millis(500);
+/// Mappings expire, such that those left behind by a node that went offline do not linger
+const PORT_MAPPING_LEASE_SECS: u32 = 600;
 
 impl HolePunchConfig {
     /// The expectation is that both `local_nat_info` and `peer_nat_info`
@@ -37,9 +48,12 @@ impl HolePunchConfig {
         #[cfg(not(feature = "localhost-testing"))]
         {
             if !local_nat_info.stun_compatible(peer_nat_info) {
-                return Err(anyhow::Error::msg(
-                    "This cannot be called if STUN is not compatible",
-                ));
+                return Self::lan_only(
+                    local_nat_info,
+                    peer_nat_info,
+                    first_local_socket,
+                    peer_declared_internal_port,
+                );
             }
         }
 
@@ -60,7 +74,8 @@ impl HolePunchConfig {
                 // add the default external addr
                 bands.push(AddrBand {
                     necessary_ip: direct_addr.ip(),
-                    anticipated_ports: vec![peer_declared_internal_port]
+                    anticipated_ports: vec![peer_declared_internal_port],
+                    candidate_type: CandidateType::ServerReflexive
                 });
 
                 let locally_bound_sockets = Self::generate_local_sockets(local_nat_info, first_local_socket)?;
@@ -78,7 +93,8 @@ impl HolePunchConfig {
 
                 bands.push(AddrBand {
                     necessary_ip: *direct_addr,
-                    anticipated_ports: vec![peer_declared_internal_port]
+                    anticipated_ports: vec![peer_declared_internal_port],
+                    candidate_type: CandidateType::ServerReflexive
                 });
 
                 let locally_bound_sockets = Self::generate_local_sockets(local_nat_info, first_local_socket)?;
@@ -91,7 +107,7 @@ impl HolePunchConfig {
 
             NatType::EDM(last_external_addr, other_addrs, delta, _) => {
                 let mut bands = Vec::new();
-                Self::generate_predict_ports_config(*delta as _, *last_external_addr, &mut bands, other_addrs.clone(), peer_declared_internal_port, local_nat_info);
+                Self::generate_predict_ports_config(*delta, *last_external_addr, &mut bands, other_addrs.clone(), peer_declared_internal_port, local_nat_info);
                 let locally_bound_sockets = Self::generate_local_sockets(local_nat_info, first_local_socket)?;
 
                 Ok(Self {
@@ -103,7 +119,7 @@ impl HolePunchConfig {
             NatType::EDMRandomPort(last_external_addr, other_addrs, ..) => {
                 let mut bands = Vec::new();
                 let delta = peer_average_delta_opt.ok_or_else(||anyhow::Error::msg("Expected acceptable average delta"))?;
-                Self::generate_predict_ports_config(delta, *last_external_addr, &mut bands, other_addrs.clone(), peer_declared_internal_port, local_nat_info);
+                Self::generate_predict_ports_config(delta as _, *last_external_addr, &mut bands, other_addrs.clone(), peer_declared_internal_port, local_nat_info);
                 let locally_bound_sockets = Self::generate_local_sockets(local_nat_info, first_local_socket)?;
 
                 Ok(Self {
@@ -132,7 +148,7 @@ impl HolePunchConfig {
                     Self::new(local_nat_info, &simulated_peer_nat, first_local_socket, peer_declared_internal_port)
                 } else {
                     Ok(Self {
-                        bands: vec![],
+                        bands: Self::lan_band(local_nat_info, peer_nat_info, peer_declared_internal_port).into_iter().collect(),
                         locally_bound_sockets: Some(vec![first_local_socket])
                     })
                 }
@@ -153,7 +169,7 @@ impl HolePunchConfig {
                         for _ in 0..4 {
                             // increment the last octet by 1
                             octets[3] = octets[3].wrapping_add(1);
-                            bands.push(AddrBand { necessary_ip: IpAddr::from(octets), anticipated_ports: vec![peer_declared_internal_port] })
+                            bands.push(AddrBand { necessary_ip: IpAddr::from(octets), anticipated_ports: vec![peer_declared_internal_port], candidate_type: CandidateType::ServerReflexive })
                         }
 
                         Ok(Self {
@@ -174,6 +190,186 @@ impl HolePunchConfig {
         }
     }
 
+    /// Like [`Self::new`], yet also consumes the behavior of both NATs as discovered via
+    /// [`NatBehavior::discover`], skipping traversal that the filtering of either NAT is known
+    /// to defeat
+    pub fn new_with_behavior(
+        local_nat_info: &NatType,
+        local_nat_behavior: &NatBehavior,
+        peer_nat_info: &NatType,
+        peer_nat_behavior: &NatBehavior,
+        first_local_socket: UdpSocket,
+        peer_declared_internal_port: u16,
+    ) -> Result<Self, anyhow::Error> {
+        #[cfg(not(feature = "localhost-testing"))]
+        {
+            if !local_nat_behavior.admits_traversal_with(
+                local_nat_info,
+                peer_nat_behavior,
+                peer_nat_info,
+            ) {
+                log::trace!(target: "citadel", "NAT filtering precludes traversal (local: {:?} | peer: {:?})", local_nat_behavior, peer_nat_behavior);
+                return Self::lan_only(
+                    local_nat_info,
+                    peer_nat_info,
+                    first_local_socket,
+                    peer_declared_internal_port,
+                );
+            }
+        }
+
+        #[cfg(feature = "localhost-testing")]
+        let _ = (local_nat_behavior, peer_nat_behavior);
+
+        Self::new(
+            local_nat_info,
+            peer_nat_info,
+            first_local_socket,
+            peer_declared_internal_port,
+        )
+    }
+
+    /// Used when the NATs cannot be traversed. Peers on the same LAN may still connect directly
+    /// through their internal addrs
+    #[cfg_attr(feature = "localhost-testing", allow(dead_code))]
+    fn lan_only(
+        local_nat_info: &NatType,
+        peer_nat_info: &NatType,
+        first_local_socket: UdpSocket,
+        peer_declared_internal_port: u16,
+    ) -> Result<Self, anyhow::Error> {
+        if let Some(lan_band) =
+            Self::lan_band(local_nat_info, peer_nat_info, peer_declared_internal_port)
+        {
+            Ok(Self {
+                bands: vec![lan_band],
+                locally_bound_sockets: Some(vec![first_local_socket]),
+            })
+        } else {
+            Err(anyhow::Error::msg(
+                "This cannot be called if STUN is not compatible",
+            ))
+        }
+    }
+
+    /// Requests that the local router forward an external port to `socket`, returning the external
+    /// address of the mapping. UPnP IGD is attempted first, followed by PCP and NAT-PMP. Returns
+    /// None if no gateway grants a mapping, or if the gateway is itself behind another NAT (e.g.,
+    /// CGNAT), in which case the mapping is unreachable
+    pub async fn request_port_mapping(socket: &UdpSocket) -> Option<SocketAddr> {
+        let local_port = socket.local_addr().ok()?.port();
+        match Self::request_upnp_mapping(local_port).await {
+            Some(mapped_addr) => Some(mapped_addr),
+            None => Self::request_pcp_mapping(local_port).await,
+        }
+    }
+
+    async fn request_upnp_mapping(local_port: u16) -> Option<SocketAddr> {
+        let handler = match UPnPHandler::new(Some(GATEWAY_SEARCH_TIMEOUT)).await {
+            Ok(handler) => handler,
+            Err(err) => {
+                log::trace!(target: "citadel", "No UPnP gateway found: {}", err.to_string());
+                return None;
+            }
+        };
+
+        let external_ip = handler.get_external_ip().await.ok()?;
+        if !is_globally_routable(external_ip) {
+            log::trace!(target: "citadel", "UPnP gateway is behind another NAT ({external_ip}); skipping port mapping");
+            return None;
+        }
+
+        match handler
+            .open_any_firewall_port(
+                PortMappingProtocol::UDP,
+                Some(PORT_MAPPING_LEASE_SECS),
+                "Citadel",
+                None,
+                local_port,
+            )
+            .await
+        {
+            Ok(external_port) => {
+                let mapped_addr = SocketAddr::new(IpAddr::V4(external_ip), external_port);
+                log::trace!(target: "citadel", "[UPnP] Mapped {mapped_addr} to local port {local_port}");
+                Some(mapped_addr)
+            }
+
+            Err(err) => {
+                log::warn!(target: "citadel", "UPnP gateway refused the port mapping: {}", err.to_string());
+                None
+            }
+        }
+    }
+
+    async fn request_pcp_mapping(local_port: u16) -> Option<SocketAddr> {
+        let handler = NatPmpHandler::new(Some(PCP_REQUEST_TIMEOUT)).await.ok()?;
+        let (mapped_addr, protocol) = match handler
+            .open_any_firewall_port(PORT_MAPPING_LEASE_SECS, local_port)
+            .await
+        {
+            Ok(mapping) => mapping,
+            Err(err) => {
+                log::trace!(target: "citadel", "No PCP/NAT-PMP mapping obtained: {}", err.to_string());
+                return None;
+            }
+        };
+
+        if let IpAddr::V4(external_ip) = mapped_addr.ip() {
+            if !is_globally_routable(external_ip) {
+                log::trace!(target: "citadel", "{protocol:?} gateway is behind another NAT ({external_ip}); releasing port mapping");
+                let _ = handler.close_firewall_port(protocol, local_port).await;
+                return None;
+            }
+        }
+
+        log::trace!(target: "citadel", "[{protocol:?}] Mapped {mapped_addr} to local port {local_port}");
+        Some(mapped_addr)
+    }
+
+    /// Builds the config once either node mapped a port on its router, short-circuiting traversal
+    /// to a direct connection. If the peer mapped a port, it is the only addr targeted. Otherwise,
+    /// only the local node mapped a port, and no addrs are targeted: the packets of the peer arrive
+    /// through the mapping, and are answered at their observed addr
+    pub fn from_port_mapping(
+        peer_mapped_addr: Option<SocketAddr>,
+        first_local_socket: UdpSocket,
+    ) -> Self {
+        let bands = peer_mapped_addr
+            .map(|addr| AddrBand {
+                necessary_ip: addr.ip(),
+                anticipated_ports: vec![addr.port()],
+                candidate_type: CandidateType::ServerReflexive,
+            })
+            .into_iter()
+            .collect();
+
+        Self {
+            bands,
+            locally_bound_sockets: Some(vec![first_local_socket]),
+        }
+    }
+
+    /// Builds the config once either node discovered the other on the LAN, skipping traversal in
+    /// favor of a direct connection. If the peer was discovered locally, its internal addr is the
+    /// only addr targeted. Otherwise, only the peer discovered this node, and no addrs are
+    /// targeted: the packets of the peer arrive directly, and are answered at their observed addr
+    pub fn from_lan_addr(peer_lan_addr: Option<SocketAddr>, first_local_socket: UdpSocket) -> Self {
+        let bands = peer_lan_addr
+            .map(|addr| AddrBand {
+                necessary_ip: addr.ip(),
+                anticipated_ports: vec![addr.port()],
+                candidate_type: CandidateType::Host,
+            })
+            .into_iter()
+            .collect();
+
+        Self {
+            bands,
+            locally_bound_sockets: Some(vec![first_local_socket]),
+        }
+    }
+
     // `first_local_socket` is needed since it contains information vital for the adjacent node to connect,
     // especially if behind the same LAN. For maximum likelihood of NAT traversal, it is recommended that if
     // ipv6_is_enabled, the first_local_socket is also v6
@@ -212,7 +408,7 @@ impl HolePunchConfig {
     }
 
     fn generate_predict_ports_config(
-        delta: u16,
+        delta: i32,
         last_external_addr: SocketAddr,
         bands: &mut Vec<AddrBand>,
         other_addrs: Option<IpAddressInfo>,
@@ -225,7 +421,8 @@ impl HolePunchConfig {
         // 6 * 30 = 180 possible addresses
         // const SPREAD: u16 = 6;
         // if delta is zero (which would be odd), assume max of 1
-        let delta = Self::check_delta(delta);
+        let descending = delta < 0;
+        let delta = Self::check_delta(delta.unsigned_abs().try_into().unwrap_or(u16::MAX));
         let mut ports_to_target_count = std::cmp::max(SPREAD * delta, 1);
 
         if matches!(local_nat_type, NatType::PortPreserved(..)) {
@@ -236,10 +433,17 @@ impl HolePunchConfig {
 
         // Note: with this type, it does NOT matter "where" the peer is bound to. To *predict* the port,
         // we take the port of the last_external_addr, then, begin incrementing at one above it to
-        // ports_to_target_count
-        let beginning_port = last_external_addr.port().wrapping_add(1);
-        let ending_port = beginning_port.wrapping_add(ports_to_target_count);
-        let ports = (beginning_port..ending_port).collect::<Vec<u16>>();
+        // ports_to_target_count. A NAT allocating ports downwards is followed downwards instead
+        let last_port = last_external_addr.port();
+        let ports = (1..=ports_to_target_count)
+            .map(|offset| {
+                if descending {
+                    last_port.wrapping_sub(offset)
+                } else {
+                    last_port.wrapping_add(offset)
+                }
+            })
+            .collect::<Vec<u16>>();
 
         Self::load_alternate_bands(
             bands,
@@ -254,9 +458,28 @@ impl HolePunchConfig {
         bands.push(AddrBand {
             necessary_ip: last_external_addr.ip(),
             anticipated_ports: ports,
+            candidate_type: CandidateType::ServerReflexive,
         });
     }
 
+    /// Returns a band targeting the internal addr of the peer if both nodes share a LAN. The
+    /// internal port declared by the peer is reachable directly, as no NAT sits between the nodes
+    fn lan_band(
+        local_nat_info: &NatType,
+        peer_nat_info: &NatType,
+        peer_declared_internal_port: u16,
+    ) -> Option<AddrBand> {
+        if !local_nat_info.shares_lan_with(peer_nat_info) {
+            return None;
+        }
+
+        Some(AddrBand {
+            necessary_ip: peer_nat_info.ip_addr_info()?.internal_ip,
+            anticipated_ports: vec![peer_declared_internal_port],
+            candidate_type: CandidateType::Host,
+        })
+    }
+
     /// Loads alternate bands, assuming port preservation mostly accounting for internal LANS
     fn load_alternate_bands(
         bands: &mut Vec<AddrBand>,
@@ -276,6 +499,7 @@ impl HolePunchConfig {
                     // prior to sending its information here. This means it must first bind to a new UDP socket
                     // before sending over its information)
                     anticipated_ports: anticipated_ports.clone(),
+                    candidate_type: CandidateType::Host,
                 });
             }
 
@@ -287,6 +511,7 @@ impl HolePunchConfig {
                         // (v6 addresses have no need for predictive NAT traversal). We can then assume a 1:1 port
                         // mapping from the peer declared internal port to its external port
                         anticipated_ports,
+                        candidate_type: CandidateType::Host,
                     });
                 }
             }
@@ -294,10 +519,50 @@ impl HolePunchConfig {
     }
 }
 
+/// Excludes private, loopback and shared (RFC 6598) addrs, which are not reachable from the internet
+fn is_globally_routable(ip: Ipv4Addr) -> bool {
+    let octets = ip.octets();
+    let shared = octets[0] == 100 && (octets[1] & 0b1100_0000) == 64;
+    !(ip.is_private() || ip.is_loopback() || ip.is_link_local() || ip.is_unspecified() || shared)
+}
+
 #[derive(Debug, Clone)]
 pub struct AddrBand {
     necessary_ip: IpAddr,
     anticipated_ports: Vec<u16>,
+    candidate_type: CandidateType,
+}
+
+impl HolePunchConfig {
+    /// Returns the addrs of each band, from the highest to the lowest priority under `policy`
+    pub fn into_prioritized_addrs(self, policy: &CandidatePolicy) -> Vec<SocketAddr> {
+        let candidates = self
+            .bands
+            .into_iter()
+            .flat_map(|band| {
+                let candidate_type = band.candidate_type;
+                band.map(move |addr| Candidate {
+                    addr,
+                    candidate_type,
+                })
+            })
+            .collect();
+        policy.prioritize(candidates)
+    }
+
+    /// Like [`Self::into_prioritized_addrs`], yet leaves the bound sockets in place
+    pub(crate) fn prioritized_addrs(&self, policy: &CandidatePolicy) -> Vec<SocketAddr> {
+        HolePunchConfig {
+            bands: self.bands.clone(),
+            locally_bound_sockets: None,
+        }
+        .into_prioritized_addrs(policy)
+    }
+
+    /// The first socket bound for traversal, if not yet taken
+    pub(crate) fn first_socket(&self) -> Option<&UdpSocket> {
+        self.locally_bound_sockets.as_ref()?.first()
+    }
 }
 
 impl IntoIterator for HolePunchConfig {
@@ -329,8 +594,9 @@ impl Iterator for AddrBand {
 
 #[cfg(test)]
 mod tests {
-    use crate::nat_identification::NatType;
-    use crate::udp_traversal::hole_punch_config::HolePunchConfig;
+    use crate::nat_identification::{NatType, PortAllocation};
+    use crate::udp_traversal::candidate_priority::CandidatePolicy;
+    use crate::udp_traversal::hole_punch_config::{is_globally_routable, HolePunchConfig};
     use crate::udp_traversal::udp_hole_puncher::get_optimal_bind_socket;
     use async_ip::IpAddressInfo;
     use std::net::{IpAddr, SocketAddr};
@@ -427,6 +693,121 @@ mod tests {
         inner_test(random_ip_port_preserved_ok, random_ip_port_preserved_err);
     }
 
+    #[tokio::test]
+    async fn test_lan_band_without_stun_compat() {
+        let on_lan = |internal_ip: [u8; 4]| {
+            NatType::EDMRandomIp(
+                vec![IpAddr::from([123, 100, 200, 100])],
+                Some(IpAddressInfo {
+                    internal_ip: IpAddr::from(internal_ip),
+                    external_ipv6: None,
+                }),
+                false,
+            )
+        };
+
+        let local = &on_lan([192, 168, 1, 20]);
+        let peer = &on_lan([192, 168, 1, 31]);
+        assert!(!local.stun_compatible(peer));
+
+        let socket = get_optimal_bind_socket(local, peer).unwrap();
+        let config = HolePunchConfig::new(local, peer, socket, 4000).unwrap();
+        assert_eq!(
+            config.into_iter().collect::<Vec<_>>(),
+            vec![SocketAddr::from(([192, 168, 1, 31], 4000))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_host_candidates_are_prioritized() {
+        let peer = &NatType::EIM(
+            SocketAddr::from(([123, 100, 200, 100], 5000)),
+            Some(IpAddressInfo {
+                internal_ip: IpAddr::from([192, 168, 1, 31]),
+                external_ipv6: None,
+            }),
+            false,
+        );
+        let local = &NatType::EIM(SocketAddr::from(([123, 100, 200, 101], 5000)), None, false);
+
+        let socket = get_optimal_bind_socket(local, peer).unwrap();
+        let config = HolePunchConfig::new(local, peer, socket, 4000).unwrap();
+        assert_eq!(
+            config.into_prioritized_addrs(&CandidatePolicy::default()),
+            vec![
+                SocketAddr::from(([192, 168, 1, 31], 4000)),
+                SocketAddr::from(([123, 100, 200, 100], 4000)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_port_mapping_short_circuits() {
+        let socket = crate::socket_helpers::get_udp_socket("0.0.0.0:0").unwrap();
+        let mapped = SocketAddr::from(([123, 100, 200, 100], 40000));
+        let config = HolePunchConfig::from_port_mapping(Some(mapped), socket);
+        assert_eq!(config.into_iter().collect::<Vec<_>>(), vec![mapped]);
+
+        let socket = crate::socket_helpers::get_udp_socket("0.0.0.0:0").unwrap();
+        let config = HolePunchConfig::from_port_mapping(None, socket);
+        assert_eq!(config.into_iter().count(), 0);
+
+        assert!(is_globally_routable([123, 100, 200, 100].into()));
+        assert!(!is_globally_routable([192, 168, 1, 1].into()));
+        assert!(!is_globally_routable([100, 64, 0, 1].into()));
+        assert!(is_globally_routable([100, 128, 0, 1].into()));
+    }
+
+    #[tokio::test]
+    async fn test_lan_discovery_short_circuits() {
+        let local = &NatType::EIM(SocketAddr::from(([123, 100, 200, 100], 5000)), None, false);
+        let peer = &NatType::EDM(
+            SocketAddr::from(([123, 100, 200, 100], 6000)),
+            None,
+            1,
+            false,
+        );
+        let remote_peer = &NatType::PortPreserved([123, 100, 200, 101].into(), None, false);
+        assert!(local.shares_external_ip_with(peer));
+        assert!(!local.shares_external_ip_with(remote_peer));
+        assert!(!local.shares_external_ip_with(&NatType::Unknown));
+
+        let socket = crate::socket_helpers::get_udp_socket("0.0.0.0:0").unwrap();
+        let lan_addr = SocketAddr::from(([192, 168, 1, 31], 4000));
+        let config = HolePunchConfig::from_lan_addr(Some(lan_addr), socket);
+        assert_eq!(config.into_iter().collect::<Vec<_>>(), vec![lan_addr]);
+
+        let socket = crate::socket_helpers::get_udp_socket("0.0.0.0:0").unwrap();
+        let config = HolePunchConfig::from_lan_addr(None, socket);
+        assert_eq!(config.into_iter().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_predicted_peer_ports() {
+        let local = &NatType::EIM(SocketAddr::from(([123, 100, 200, 101], 5000)), None, false);
+        // ports seemed random across STUN servers, yet are allocated downwards at a delta of 2
+        let peer = NatType::EDMRandomPort(
+            SocketAddr::from(([123, 100, 200, 100], 40000)),
+            None,
+            vec![40000, 41700, 40300],
+            false,
+        )
+        .with_port_allocation(Some(PortAllocation {
+            last_external_addr: SocketAddr::from(([123, 100, 200, 100], 40000)),
+            delta: -2,
+        }));
+
+        let socket = get_optimal_bind_socket(local, &peer).unwrap();
+        let ports = HolePunchConfig::new(local, &peer, socket, 4000)
+            .unwrap()
+            .into_iter()
+            .map(|addr| addr.port())
+            .collect::<Vec<_>>();
+        assert_eq!(ports.len(), 12);
+        assert!(ports.contains(&39999) && ports.contains(&39988));
+        assert!(ports.iter().all(|port| *port < 40000));
+    }
+
     fn inner_test(local_nat_type: &NatType, peer_nat_type: &NatType) {
         assert!(local_nat_type.stun_compatible(peer_nat_type));
         let initial_socket_local = get_optimal_bind_socket(local_nat_type, peer_nat_type).unwrap();
@@ -1,5 +1,10 @@
+use crate::udp_traversal::candidate_priority::{CandidatePolicy, DualStackPolicy};
+use crate::udp_traversal::linear::method3::Method3Config;
+use crate::udp_traversal::linear::LinearUdpHolePunchImpl;
+use crate::udp_traversal::progress::{HolePunchEvent, HolePunchObserver};
 use bytes::BytesMut;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// Stores the functions relevant to encrypting and decrypting hole-punch related packets
 #[derive(Clone)]
@@ -10,6 +15,18 @@ pub struct HolePunchConfigContainer {
     decrypt_packet: CryptFunction<Option<BytesMut>>,
     // custom STUN servers
     stun_servers: Option<Vec<String>>,
+    // whether a port mapping is requested on the local router before traversal
+    port_mapping: bool,
+    // whether a peer behind the same external IP is probed for on the LAN before traversal
+    lan_discovery: bool,
+    // whether the sockets punched alongside the winner are kept
+    multipath: bool,
+    candidate_policy: CandidatePolicy,
+    dual_stack_policy: Option<DualStackPolicy>,
+    method3_config: Method3Config,
+    // methods attempted, in order, once the built-in methods fail
+    traversal_methods: Vec<Arc<dyn LinearUdpHolePunchImpl>>,
+    progress_observer: Option<HolePunchObserver>,
 }
 
 type CryptFunction<T> = Arc<dyn for<'a> Fn(&'a [u8]) -> T + Send + Sync + 'static>;
@@ -26,6 +43,14 @@ impl HolePunchConfigContainer {
             generate_packet: Arc::new(_generate_packet),
             decrypt_packet: Arc::new(_decrypt_packet),
             stun_servers,
+            port_mapping: false,
+            lan_discovery: false,
+            multipath: false,
+            candidate_policy: CandidatePolicy::default(),
+            dual_stack_policy: None,
+            method3_config: Method3Config::default(),
+            traversal_methods: Vec::new(),
+            progress_observer: None,
         }
     }
 
@@ -54,6 +79,128 @@ impl HolePunchConfigContainer {
     pub fn take_stun_servers(&mut self) -> Option<Vec<String>> {
         self.stun_servers.take()
     }
+
+    /// Requests a port mapping on the local router via UPnP, PCP or NAT-PMP before traversal. If either node
+    /// obtains a mapping, traversal short-circuits to a direct connection through it. Disabled by
+    /// default, since searching for a gateway delays traversal on networks without one
+    pub fn with_port_mapping(mut self, enabled: bool) -> Self {
+        self.port_mapping = enabled;
+        self
+    }
+
+    pub fn port_mapping_enabled(&self) -> bool {
+        self.port_mapping
+    }
+
+    /// Probes the LAN via mDNS for a peer observed at the same external IP before traversal. If
+    /// either node finds the other, traversal is skipped in favor of a direct connection to its
+    /// internal addr. Both nodes must enable the probe. Disabled by default, since an absent peer
+    /// delays traversal until the probe times out. Without the `lan-discovery` feature, the probe
+    /// is never run
+    pub fn with_lan_discovery(mut self, enabled: bool) -> Self {
+        self.lan_discovery = enabled;
+        self
+    }
+
+    pub fn lan_discovery_enabled(&self) -> bool {
+        self.lan_discovery
+    }
+
+    /// Once a winner is chosen, punches a few more sockets through to the peer from the same host,
+    /// returned in [`HolePunchedUdpSocket::secondary_paths`]. Only NATs that preserve ports let
+    /// these through, and none delay the winner by more than a couple of seconds. Both nodes must
+    /// enable multi-path mode, since the nodes agree on the sockets each holds. Disabled by default
+    ///
+    /// [`HolePunchedUdpSocket::secondary_paths`]: crate::udp_traversal::targetted_udp_socket_addr::HolePunchedUdpSocket::secondary_paths
+    pub fn with_multipath(mut self, enabled: bool) -> Self {
+        self.multipath = enabled;
+        self
+    }
+
+    pub fn multipath_enabled(&self) -> bool {
+        self.multipath
+    }
+
+    /// Sets the policy by which the addrs of the peer are prioritized. See [`CandidatePolicy`]
+    pub fn with_candidate_policy(mut self, candidate_policy: CandidatePolicy) -> Self {
+        self.candidate_policy = candidate_policy;
+        self
+    }
+
+    pub fn candidate_policy(&self) -> &CandidatePolicy {
+        &self.candidate_policy
+    }
+
+    /// Restricts and orders the IP versions targeted during traversal. See [`DualStackPolicy`].
+    /// If unset, addrs of both IP versions are ordered by the [`CandidatePolicy`] alone
+    pub fn with_dual_stack_policy(mut self, dual_stack_policy: DualStackPolicy) -> Self {
+        self.dual_stack_policy = Some(dual_stack_policy);
+        self
+    }
+
+    pub fn dual_stack_policy(&self) -> Option<DualStackPolicy> {
+        self.dual_stack_policy
+    }
+
+    /// Sets the timing and packet budget of [`Method3`]. See [`Method3Config`]
+    ///
+    /// [`Method3`]: crate::udp_traversal::linear::method3::Method3
+    pub fn with_method3_config(mut self, method3_config: Method3Config) -> Self {
+        self.method3_config = method3_config;
+        self
+    }
+
+    /// Caps the number of packets [`Method3`] sends to each endpoint per second, and adds up to
+    /// `jitter` of random delay between each barrage. See [`Method3Config::max_packets_per_second`]
+    ///
+    /// [`Method3`]: crate::udp_traversal::linear::method3::Method3
+    pub fn with_barrage_pacing(mut self, max_packets_per_second: u32, jitter: Duration) -> Self {
+        self.method3_config.max_packets_per_second = Some(max_packets_per_second);
+        self.method3_config.barrage_jitter = jitter;
+        self
+    }
+
+    pub fn method3_config(&self) -> &Method3Config {
+        &self.method3_config
+    }
+
+    /// Registers a traversal method, attempted from each bound socket once the built-in methods, and
+    /// any method registered before it, fail. Both nodes must register the same methods in the same
+    /// order, since the methods of each node run concurrently
+    pub fn with_traversal_method(mut self, method: impl LinearUdpHolePunchImpl) -> Self {
+        self.traversal_methods.push(Arc::new(method));
+        self
+    }
+
+    pub fn traversal_methods(&self) -> &[Arc<dyn LinearUdpHolePunchImpl>] {
+        &self.traversal_methods
+    }
+
+    /// Reports the progress of the hole punch to `observer`. See [`HolePunchEvent`]
+    pub fn with_progress_observer(mut self, observer: HolePunchObserver) -> Self {
+        self.progress_observer = Some(observer);
+        self
+    }
+
+    /// Adopts every setting of `settings` besides the packet crypto and the STUN servers, such
+    /// that a node may configure its hole punches once, ahead of the sessions that key them
+    pub fn with_settings_of(mut self, settings: &Self) -> Self {
+        self.port_mapping = settings.port_mapping;
+        self.lan_discovery = settings.lan_discovery;
+        self.multipath = settings.multipath;
+        self.candidate_policy = settings.candidate_policy;
+        self.dual_stack_policy = settings.dual_stack_policy;
+        self.method3_config = settings.method3_config;
+        self.traversal_methods = settings.traversal_methods.clone();
+        self.progress_observer = settings.progress_observer.clone();
+        self
+    }
+
+    pub(crate) fn notify(&self, event: HolePunchEvent) {
+        if let Some(observer) = self.progress_observer.as_ref() {
+            let _ = observer.send(event);
+        }
+    }
 }
 
 impl Default for HolePunchConfigContainer {
@@ -63,6 +210,14 @@ impl Default for HolePunchConfigContainer {
             generate_packet: Arc::new(|input| BytesMut::from(input)),
             decrypt_packet: Arc::new(|input| Some(BytesMut::from(input))),
             stun_servers: None,
+            port_mapping: false,
+            lan_discovery: false,
+            multipath: false,
+            candidate_policy: CandidatePolicy::default(),
+            dual_stack_policy: None,
+            method3_config: Method3Config::default(),
+            traversal_methods: Vec::new(),
+            progress_observer: None,
         }
     }
 }
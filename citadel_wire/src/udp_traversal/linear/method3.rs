@@ -4,11 +4,12 @@ use std::net::SocketAddr;
 use citadel_io::UdpSocket;
 use serde::{Deserialize, Serialize};
 use tokio::sync::Mutex as TokioMutex;
-use tokio::time::Duration;
+use tokio::time::{Duration, Instant};
 
 use crate::error::FirewallError;
 use crate::socket_helpers::ensure_ipv6;
 use crate::udp_traversal::linear::encrypted_config_container::HolePunchConfigContainer;
+use crate::udp_traversal::progress::HolePunchEvent;
 use crate::udp_traversal::targetted_udp_socket_addr::TargettedSocketAddr;
 use crate::udp_traversal::HolePunchID;
 use citadel_io::Mutex;
@@ -25,11 +26,91 @@ pub struct Method3 {
     observed_addrs_on_syn: Mutex<HashMap<HolePunchID, TargettedSocketAddr>>,
 }
 
+/// The timing and packet budget of [`Method3`]. High-latency links may extend the receive window,
+/// while constrained links may reduce the number of packets sent per endpoint
+#[derive(Copy, Clone, Debug)]
+pub struct Method3Config {
+    /// How long to await the hole punch before failing
+    pub recv_timeout: Duration,
+    /// The interval between each round of packets sent to the endpoints
+    pub barrage_interval: Duration,
+    /// The number of packets sent to each endpoint per barrage, where each packet has a greater
+    /// TTL than the one before it
+    pub packets_per_barrage: u32,
+    /// The TTL of the first packet of each barrage
+    pub ttl_init: u32,
+    /// The amount the TTL increases by with each packet of a barrage
+    pub delta_ttl: u32,
+    /// If true, the hop count to the peer is measured before traversal, from which the TTL of the
+    /// first packet of each barrage is derived via [`Self::with_measured_hops`] in place of
+    /// `ttl_init`. Both nodes must enable the measurement, since each measures on behalf of the other
+    pub adaptive_ttl: bool,
+    /// The upper bound of a random delay added to each barrage interval, such that the packets do
+    /// not arrive at the fixed cadence that some firewalls flag as a scan
+    pub barrage_jitter: Duration,
+    /// The maximum number of packets sent to each endpoint per second. The barrage interval is
+    /// stretched as needed to respect it, keeping the barrage beneath the rate limiters of NATs
+    /// and firewalls along the path. `None` leaves the barrage interval as-is
+    pub max_packets_per_second: Option<u32>,
+}
+
+impl Method3Config {
+    /// Derives the TTL of the first packet of each barrage from the hop count to the peer, such that
+    /// the packet escapes the local network, yet expires before reaching the NAT of the peer, which
+    /// thus does not blacklist this node before the peer's own packets open its side of the hole.
+    /// The TTL of the last packet of each barrage is unchanged
+    pub fn with_measured_hops(mut self, hops: u32) -> Self {
+        let ttl_max = self.ttl_init + self.delta_ttl * self.packets_per_barrage.saturating_sub(1);
+        let ttl_init = hops
+            .saturating_sub(2)
+            .clamp(MIN_SHORT_TTL, ttl_max.max(MIN_SHORT_TTL));
+        if self.packets_per_barrage > 1 {
+            self.delta_ttl = ttl_max.saturating_sub(ttl_init) / (self.packets_per_barrage - 1);
+        }
+        self.ttl_init = ttl_init;
+        self
+    }
+
+    /// Returns the interval between each barrage, before jitter, after applying
+    /// `max_packets_per_second`. Since each barrage sends one packet to each endpoint, the rate per
+    /// endpoint is the inverse of this interval
+    pub fn paced_interval(&self) -> Duration {
+        match self.max_packets_per_second {
+            Some(pps) => self
+                .barrage_interval
+                .max(Duration::from_secs(1) / pps.max(1)),
+            None => self.barrage_interval,
+        }
+    }
+}
+
+/// A TTL of 1 never leaves the host's subnet
+const MIN_SHORT_TTL: u32 = 2;
+/// The hop count is measured by probing each TTL from 1 through this
+const MAX_PROBE_TTL: u32 = 32;
+
+impl Default for Method3Config {
+    fn default() -> Self {
+        Self {
+            recv_timeout: Duration::from_millis(2000),
+            barrage_interval: Duration::from_millis(20),
+            packets_per_barrage: 2,
+            ttl_init: 20,
+            delta_ttl: 60,
+            adaptive_ttl: false,
+            barrage_jitter: Duration::ZERO,
+            max_packets_per_second: None,
+        }
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 enum NatPacket {
     Syn(HolePunchID, u32, RelativeNodeType, SocketAddr),
     // contains the local bind addr of candidate for socket identification
     SynAck(HolePunchID, RelativeNodeType, SocketAddr),
+    // sent with the TTL it contains while measuring the hop count
+    TtlProbe(u32),
 }
 
 impl Method3 {
@@ -59,6 +140,72 @@ impl Method3 {
         }
     }
 
+    /// Measures the hop count from the peer to this node. Both nodes must probe concurrently: each
+    /// sends a probe with every TTL from 1 through [`MAX_PROBE_TTL`] to each endpoint of the other,
+    /// one TTL per [`Method3Config::paced_interval`] (plus jitter), and returns the least TTL at
+    /// which a probe of the other arrived within `window` of the last probe. Each node reports the
+    /// result to the other, which passes it to [`Method3Config::with_measured_hops`]. Returns `None`
+    /// if no probe arrived, e.g., since a NAT dropped the unsolicited probes
+    ///
+    /// The result is an upper bound on the hop count: the probes of the peer are filtered by the NAT
+    /// of this node until a probe of this node opens a mapping, so the short probes of the peer may
+    /// be dropped even if they would have arrived. Pacing the probes keeps both nodes stepping
+    /// through the TTLs in lockstep, which keeps this overestimate to the hop count of the local
+    /// network of this node
+    pub async fn probe_hop_count(
+        socket: &UdpSocket,
+        endpoints: &[SocketAddr],
+        encryptor: &HolePunchConfigContainer,
+        window: Duration,
+    ) -> Option<u32> {
+        let default_ttl = socket.ttl().ok();
+        let socket_wrapper = &UdpWrapper::new(socket);
+        let config = encryptor.method3_config();
+        let interval = config.paced_interval();
+
+        let sender = async move {
+            for ttl in 1..=MAX_PROBE_TTL {
+                if ttl != 1 {
+                    tokio::time::sleep(jittered(interval, config.barrage_jitter)).await;
+                }
+
+                let packet = encryptor
+                    .generate_packet(&bincode2::serialize(&NatPacket::TtlProbe(ttl)).unwrap());
+                for endpoint in endpoints {
+                    if let Err(err) = socket_wrapper.send(&packet, *endpoint, Some(ttl)).await {
+                        log::trace!(target: "citadel", "Unable to send TTL probe to {endpoint}: {err:?}");
+                    }
+                }
+            }
+        };
+
+        let receiver = async move {
+            let buf = &mut [0u8; 4096];
+            let deadline = Instant::now() + interval * MAX_PROBE_TTL + window;
+            let mut hops: Option<u32> = None;
+            while let Ok(Ok((len, _))) =
+                tokio::time::timeout_at(deadline, socket.recv_from(buf)).await
+            {
+                let packet = encryptor
+                    .decrypt_packet(&buf[..len])
+                    .and_then(|packet| bincode2::deserialize(&packet).ok());
+                if let Some(NatPacket::TtlProbe(ttl)) = packet {
+                    hops = Some(hops.map_or(ttl, |hops| hops.min(ttl)));
+                }
+            }
+
+            hops
+        };
+
+        let ((), hops) = tokio::join!(sender, receiver);
+        if let Some(default_ttl) = default_ttl {
+            let _ = socket.set_ttl(default_ttl);
+        }
+
+        log::trace!(target: "citadel", "Measured hop count from the peer: {hops:?}");
+        hops
+    }
+
     pub(crate) fn get_peer_external_addr_from_peer_hole_punch_id(
         &self,
         id: HolePunchID,
@@ -91,15 +238,16 @@ impl Method3 {
 
         let socket_wrapper = &UdpWrapper::new(socket);
 
-        const MILLIS_DELTA: u64 = 20;
+        let config = encryptor.method3_config();
         let packet_send_params = &SendPacketBarrageParams {
-            ttl_init: 20,
-            delta_ttl: Some(60),
+            ttl_init: config.ttl_init,
+            delta_ttl: Some(config.delta_ttl),
             socket: socket_wrapper,
             endpoints,
             encryptor,
-            millis_delta: MILLIS_DELTA,
-            count: 2,
+            barrage_interval: config.paced_interval(),
+            barrage_jitter: config.barrage_jitter,
+            count: config.packets_per_barrage,
             unique_id: *unique_id,
             this_node_type,
         };
@@ -107,13 +255,12 @@ impl Method3 {
         let receiver_task = async move {
             // we are only interested in the first receiver to receive a value
             if let Ok(res) = tokio::time::timeout(
-                Duration::from_millis(2000),
+                config.recv_timeout,
                 Self::recv_until(
                     socket_wrapper,
                     encryptor,
                     unique_id,
                     observed_addrs_on_syn,
-                    MILLIS_DELTA,
                     this_node_type,
                     packet_send_params,
                 ),
@@ -167,21 +314,32 @@ impl Method3 {
             socket,
             endpoints,
             encryptor,
-            millis_delta,
+            barrage_interval,
+            barrage_jitter,
             count,
             unique_id,
             this_node_type,
         } = params;
 
-        let mut sleep = tokio::time::interval(Duration::from_millis(*millis_delta));
         let delta_ttl = delta_ttl.unwrap_or(0);
         let ttls = (0..*count)
             .map(|idx| ttl_init + (idx * delta_ttl))
             .collect::<Vec<u32>>();
 
+        if syn_received_addr.is_none() {
+            for endpoint in endpoints.iter() {
+                encryptor.notify(HolePunchEvent::SynSent {
+                    local_id: *unique_id,
+                    candidate: *endpoint,
+                });
+            }
+        }
+
         // fan-out all packets from a singular source to multiple consumers using the ttls specified
-        for ttl in ttls {
-            let _ = sleep.tick().await;
+        for (idx, ttl) in ttls.into_iter().enumerate() {
+            if idx != 0 {
+                tokio::time::sleep(jittered(*barrage_interval, *barrage_jitter)).await;
+            }
 
             for endpoint in endpoints.iter() {
                 let packet_ty = if let Some(syn_addr) = syn_received_addr {
@@ -213,9 +371,8 @@ impl Method3 {
     async fn recv_until(
         socket: &UdpWrapper<'_>,
         encryptor: &HolePunchConfigContainer,
-        _unique_id: &HolePunchID,
+        unique_id: &HolePunchID,
         observed_addrs_on_syn: &Mutex<HashMap<HolePunchID, TargettedSocketAddr>>,
-        _millis_delta: u64,
         this_node_type: RelativeNodeType,
         send_packet_params: &SendPacketBarrageParams<'_>,
     ) -> Result<TargettedSocketAddr, FirewallError> {
@@ -223,8 +380,28 @@ impl Method3 {
         log::trace!(target: "citadel", "[Hole-punch] Listening on {:?}", socket.socket.local_addr().unwrap());
 
         let mut has_received_syn = false;
+        // once a candidate is verified, others are awaited until the nomination deadline, such that
+        // the candidate of the highest priority (i.e., the lowest rank in the endpoints) is selected
+        let nomination_window = encryptor.candidate_policy().nomination_window;
+        let mut nominated: Option<(usize, TargettedSocketAddr)> = None;
+        let mut nomination_deadline = None;
         //let mut recv_from_required = None;
-        while let Ok((len, peer_external_addr)) = socket.recv_from(buf).await {
+        loop {
+            let recv = match nomination_deadline {
+                Some(deadline) => {
+                    match tokio::time::timeout_at(deadline, socket.recv_from(buf)).await {
+                        Ok(recv) => recv,
+                        Err(_elapsed) => break,
+                    }
+                }
+                None => socket.recv_from(buf).await,
+            };
+
+            let (len, peer_external_addr) = match recv {
+                Ok(recv) => recv,
+                Err(_) => break,
+            };
+
             log::trace!(target: "citadel", "[UDP Hole-punch] RECV packet from {:?} | {:?}", &peer_external_addr, &buf[..len]);
             let packet = match encryptor.decrypt_packet(&buf[..len]) {
                 Some(plaintext) => plaintext,
@@ -306,18 +483,46 @@ impl Method3 {
                         adjacent_unique_id,
                     );
                     log::trace!(target: "citadel", "***UDP Hole-punch to {:?} success!***", &hole_punched_addr);
-                    socket.stop_outgoing_traffic().await;
+                    encryptor.notify(HolePunchEvent::SynAckReceived {
+                        local_id: *unique_id,
+                        peer_id: adjacent_unique_id,
+                        candidate: address_we_sent_to,
+                    });
+                    let endpoints = send_packet_params.endpoints;
+                    let rank = endpoints
+                        .iter()
+                        .position(|addr| *addr == address_we_sent_to)
+                        .unwrap_or(endpoints.len());
+                    if nominated
+                        .as_ref()
+                        .map(|(best_rank, _)| rank < *best_rank)
+                        .unwrap_or(true)
+                    {
+                        nominated = Some((rank, hole_punched_addr));
+                    }
 
-                    return Ok(hole_punched_addr);
+                    if rank == 0 || nomination_window.is_zero() {
+                        break;
+                    }
+
+                    if nomination_deadline.is_none() {
+                        nomination_deadline = Some(Instant::now() + nomination_window);
+                    }
                 }
 
+                // a straggler from the measurement of the hop count
+                Ok(NatPacket::TtlProbe(_)) => {}
+
                 Err(err) => {
                     log::warn!(target: "citadel", "Unable to deserialize packet {:?} from {:?}: {:?}", &packet[..], peer_external_addr, err);
                 }
             }
         }
 
-        Err(FirewallError::HolePunch("Socket recv error".to_string()))
+        socket.stop_outgoing_traffic().await;
+        nominated
+            .map(|(_, hole_punched_addr)| hole_punched_addr)
+            .ok_or_else(|| FirewallError::HolePunch("Socket recv error".to_string()))
     }
 }
 
@@ -384,8 +589,82 @@ struct SendPacketBarrageParams<'a> {
     socket: &'a UdpWrapper<'a>,
     endpoints: &'a Vec<SocketAddr>,
     encryptor: &'a HolePunchConfigContainer,
-    millis_delta: u64,
+    barrage_interval: Duration,
+    barrage_jitter: Duration,
     count: u32,
     unique_id: HolePunchID,
     this_node_type: RelativeNodeType,
 }
+
+/// Adds a uniformly random delay of at most `jitter` to `interval`
+fn jittered(interval: Duration, jitter: Duration) -> Duration {
+    if jitter.is_zero() {
+        interval
+    } else {
+        interval + jitter.mul_f64(rand::random::<f64>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::udp_traversal::linear::method3::{
+        jittered, Method3Config, MAX_PROBE_TTL, MIN_SHORT_TTL,
+    };
+    use std::time::Duration;
+
+    #[test]
+    fn test_with_measured_hops() {
+        let config = Method3Config::default().with_measured_hops(12);
+        assert_eq!(config.ttl_init, 10);
+        // the TTL of the last packet is unchanged
+        assert_eq!(config.ttl_init + config.delta_ttl, 80);
+
+        // a peer on the same host still yields a TTL escaping the subnet
+        assert_eq!(Method3Config::default().with_measured_hops(1).ttl_init, 2);
+    }
+
+    #[test]
+    fn test_measured_hops_clamping() {
+        // the short TTL never drops below MIN_SHORT_TTL
+        for hops in [0, 1, 2, 3, 4] {
+            let config = Method3Config::default().with_measured_hops(hops);
+            assert_eq!(config.ttl_init, MIN_SHORT_TTL.max(hops.saturating_sub(2)));
+        }
+
+        // nor rises above the TTL of the last packet, which is then sent at the same TTL
+        let config = Method3Config::default().with_measured_hops(MAX_PROBE_TTL * 10);
+        assert_eq!(config.ttl_init, 80);
+        assert_eq!(config.delta_ttl, 0);
+
+        // a single packet per barrage is clamped to its own TTL
+        let config = Method3Config {
+            packets_per_barrage: 1,
+            ..Default::default()
+        }
+        .with_measured_hops(MAX_PROBE_TTL);
+        assert_eq!(config.ttl_init, 20);
+    }
+
+    #[test]
+    fn test_paced_interval() {
+        let config = Method3Config {
+            max_packets_per_second: Some(10),
+            ..Default::default()
+        };
+        assert_eq!(config.paced_interval(), Duration::from_millis(100));
+
+        // a limit looser than the barrage interval leaves it unchanged
+        let config = Method3Config {
+            max_packets_per_second: Some(1000),
+            ..Default::default()
+        };
+        assert_eq!(config.paced_interval(), config.barrage_interval);
+
+        let interval = Duration::from_millis(20);
+        let jitter = Duration::from_millis(5);
+        for _ in 0..32 {
+            let delay = jittered(interval, jitter);
+            assert!(delay >= interval && delay <= interval + jitter);
+        }
+    }
+}
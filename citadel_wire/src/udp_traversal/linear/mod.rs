@@ -1,5 +1,7 @@
 use std::net::SocketAddr;
+use std::sync::Arc;
 
+use async_trait::async_trait;
 use citadel_io::UdpSocket;
 use either::Either;
 use igd::PortMappingProtocol;
@@ -18,6 +20,35 @@ pub mod encrypted_config_container;
 pub mod method1;
 pub mod method3;
 
+/// A method of traversing the NATs between two nodes from a single bound socket. Methods are
+/// registered via [`HolePunchConfigContainer::with_traversal_method`], and each
+/// [`SingleUDPHolePuncher`] attempts them in order once [`Method3`] fails. Both nodes execute the
+/// same method concurrently, each from every one of its bound sockets
+#[async_trait]
+pub trait LinearUdpHolePunchImpl: Send + Sync + 'static {
+    /// Identifies the method in logs
+    fn name(&self) -> &str;
+
+    /// Attempts to reach the peer at any of `endpoints`, ordered from the most to the least
+    /// preferred, through `socket`. `local_id` identifies the calling hole puncher. On success, the
+    /// returned addr must carry the [`HolePunchID`] of the peer's hole puncher that was reached,
+    /// since the nodes agree on the final socket by the IDs of both hole punchers
+    async fn execute(
+        &self,
+        socket: &UdpSocket,
+        endpoints: &[SocketAddr],
+        local_id: HolePunchID,
+    ) -> Result<TargettedSocketAddr, FirewallError>;
+
+    /// Returns the addr through which the hole puncher `local_id` reached `peer_id`, if any. Called
+    /// when the peer succeeded first, yet this node's attempt has not yet finished, or failed. If
+    /// `None` is returned, the peer's choice of socket cannot be honored
+    fn recover(&self, local_id: HolePunchID, peer_id: HolePunchID) -> Option<TargettedSocketAddr> {
+        let _ = (local_id, peer_id);
+        None
+    }
+}
+
 /// Whereas UDP hole punching usually entails the connection between two peers (p2p),
 /// linear UDP hole punching is the process of punching a hole through the firewall to allow
 /// the server to reach a single client behind a NAT. The pre-process begins with sending a SYN followed
@@ -32,6 +63,8 @@ pub mod method3;
 /// cellular NAT, is connecting to a globally-routable server
 pub struct SingleUDPHolePuncher {
     method3: (bool, Method3),
+    // the registered methods, and the number of them attempted so far
+    custom_methods: (usize, Vec<Arc<dyn LinearUdpHolePunchImpl>>),
     upnp_handler: (bool, Option<UPnPHandler>),
     socket: Option<UdpSocket>,
     possible_endpoints: Vec<SocketAddr>,
@@ -51,10 +84,12 @@ impl SingleUDPHolePuncher {
         let unique_id = HolePunchID::new();
         log::trace!(target: "citadel", "Setting up single-udp hole-puncher. Local bind addr: {:?} | Peer Addrs to ping: {:?} | [id = {:?}]", local_bind_addr, peer_addrs_to_ping, unique_id);
 
+        let custom_methods = encrypted_config_container.traversal_methods().to_vec();
         let method3 = Method3::new(relative_node_type, encrypted_config_container, unique_id);
 
         Ok(Self {
             method3: (false, method3),
+            custom_methods: (0, custom_methods),
             upnp_handler: (false, None),
             socket: Some(local_socket),
             possible_endpoints: peer_addrs_to_ping,
@@ -71,7 +106,7 @@ impl SingleUDPHolePuncher {
     pub async fn try_method(
         &mut self,
         method: NatTraversalMethod,
-        mut kill_switch: tokio::sync::broadcast::Receiver<(HolePunchID, HolePunchID)>,
+        kill_switch: &mut tokio::sync::broadcast::Receiver<(HolePunchID, HolePunchID)>,
         post_kill_rebuild: tokio::sync::mpsc::UnboundedSender<Option<HolePunchedUdpSocket>>,
     ) -> Result<HolePunchedUdpSocket, FirewallError> {
         match method {
@@ -113,24 +148,43 @@ impl SingleUDPHolePuncher {
                     socket: self.socket.take().ok_or_else(|| {
                         FirewallError::HolePunch("UDP socket not loaded".to_string())
                     })?,
+                    mapped_addr: None,
+                    secondary_paths: Vec::new(),
                 })
             }
 
-            NatTraversalMethod::Method3 => {
-                self.method3.0 = true;
+            NatTraversalMethod::Method3 | NatTraversalMethod::Custom(_) => {
+                if let NatTraversalMethod::Custom(idx) = method {
+                    self.custom_methods.0 = self.custom_methods.0.max(idx as usize + 1);
+                } else {
+                    self.method3.0 = true;
+                }
+
                 let this_local_id = self.unique_id;
 
                 let this = &*self;
                 let process = async move {
-                    this.method3
-                        .1
-                        .execute(
-                            this.socket.as_ref().ok_or_else(|| {
-                                FirewallError::HolePunch("UDP socket not loaded".to_string())
-                            })?,
-                            &this.possible_endpoints,
-                        )
-                        .await
+                    let socket = this.socket.as_ref().ok_or_else(|| {
+                        FirewallError::HolePunch("UDP socket not loaded".to_string())
+                    })?;
+
+                    if let NatTraversalMethod::Custom(idx) = method {
+                        let custom_method =
+                            this.custom_methods.1.get(idx as usize).ok_or_else(|| {
+                                FirewallError::HolePunch(format!(
+                                    "Traversal method {idx} not registered"
+                                ))
+                            })?;
+                        log::trace!(target: "citadel", "Attempting traversal method {} [id = {:?}]", custom_method.name(), this_local_id);
+                        custom_method
+                            .execute(socket, &this.possible_endpoints, this_local_id)
+                            .await
+                    } else {
+                        this.method3
+                            .1
+                            .execute(socket, &this.possible_endpoints)
+                            .await
+                    }
                 };
 
                 let kill_listener = async move {
@@ -153,6 +207,8 @@ impl SingleUDPHolePuncher {
                     Either::Right(addr) => Ok(HolePunchedUdpSocket {
                         socket: self.socket.take().unwrap(),
                         addr,
+                        mapped_addr: None,
+                        secondary_paths: Vec::new(),
                     }),
 
                     Either::Left(id_opt) => {
@@ -190,11 +246,18 @@ impl SingleUDPHolePuncher {
                         receive_address: self.peer_external_addr(),
                         unique_id,
                     },
+                    mapped_addr: None,
+                    secondary_paths: Vec::new(),
                 })
             }
         }
     }
 
+    /// The candidate addrs of the peer targeted by this hole puncher
+    pub fn endpoints(&self) -> &[SocketAddr] {
+        &self.possible_endpoints
+    }
+
     fn peer_external_addr(&self) -> SocketAddr {
         self.possible_endpoints[0]
     }
@@ -204,14 +267,18 @@ impl SingleUDPHolePuncher {
         self.possible_endpoints.get(0).cloned()
     }
 
-    /// returns None if all techniques have been exhausted
+    /// returns None if all techniques have been exhausted. UPnP is not included, since port
+    /// mappings are requested before traversal begins
     pub fn get_next_method(&self) -> Option<NatTraversalMethod> {
         if !self.method3.0 {
             return Some(NatTraversalMethod::Method3);
         }
 
-        if !self.upnp_handler.0 {
-            return Some(NatTraversalMethod::UPnP);
+        let (attempted, custom_methods) = &self.custom_methods;
+        if *attempted < custom_methods.len() {
+            return u8::try_from(*attempted)
+                .ok()
+                .map(NatTraversalMethod::Custom);
         }
 
         None
@@ -226,12 +293,23 @@ impl SingleUDPHolePuncher {
         &mut self,
         remote_id: HolePunchID,
     ) -> Option<HolePunchedUdpSocket> {
+        let local_id = self.unique_id;
         let addr = self
             .method3
             .1
-            .get_peer_external_addr_from_peer_hole_punch_id(remote_id)?;
+            .get_peer_external_addr_from_peer_hole_punch_id(remote_id)
+            .or_else(|| {
+                self.custom_methods.1[..self.custom_methods.0]
+                    .iter()
+                    .find_map(|method| method.recover(local_id, remote_id))
+            })?;
         let socket = self.socket.take()?;
-        Some(HolePunchedUdpSocket { addr, socket })
+        Some(HolePunchedUdpSocket {
+            addr,
+            socket,
+            mapped_addr: None,
+            secondary_paths: Vec::new(),
+        })
     }
 
     /// this should only be called when the adjacent node verified that the connection occurred
@@ -240,7 +318,12 @@ impl SingleUDPHolePuncher {
         addr: TargettedSocketAddr,
     ) -> Option<HolePunchedUdpSocket> {
         let socket = self.socket.take()?;
-        Some(HolePunchedUdpSocket { addr, socket })
+        Some(HolePunchedUdpSocket {
+            addr,
+            socket,
+            mapped_addr: None,
+            secondary_paths: Vec::new(),
+        })
     }
 }
 
@@ -5,6 +5,12 @@ use uuid::Uuid;
 /// Linear hole-punching
 pub mod linear;
 
+pub mod candidate_priority;
+
+pub mod progress;
+
+pub mod repunch;
+
 pub mod targetted_udp_socket_addr;
 
 pub mod udp_hole_puncher;
@@ -18,6 +24,10 @@ pub enum NatTraversalMethod {
     Method3,
     // none needed
     None,
+    /// The method registered at the given index via [`HolePunchConfigContainer::with_traversal_method`]
+    ///
+    /// [`HolePunchConfigContainer::with_traversal_method`]: crate::udp_traversal::linear::encrypted_config_container::HolePunchConfigContainer::with_traversal_method
+    Custom(u8),
 }
 
 impl Display for NatTraversalMethod {
@@ -32,6 +42,7 @@ impl NatTraversalMethod {
             NatTraversalMethod::UPnP => 0,
             NatTraversalMethod::Method3 => 3,
             NatTraversalMethod::None => 7,
+            NatTraversalMethod::Custom(idx) => 8u8.saturating_add(idx),
         }
     }
 
@@ -40,6 +51,7 @@ impl NatTraversalMethod {
             0 => Some(NatTraversalMethod::UPnP),
             3 => Some(NatTraversalMethod::Method3),
             7 => Some(NatTraversalMethod::None),
+            8.. => Some(NatTraversalMethod::Custom(byte - 8)),
             _ => None,
         }
     }
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::net::SocketAddr;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::Duration;
 
 use futures::stream::FuturesUnordered;
 use futures::{Future, StreamExt};
@@ -10,9 +11,11 @@ use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc::UnboundedReceiver;
 
 use crate::error::FirewallError;
+use crate::socket_helpers::get_udp_socket;
 use crate::udp_traversal::hole_punch_config::HolePunchConfig;
 use crate::udp_traversal::linear::encrypted_config_container::HolePunchConfigContainer;
 use crate::udp_traversal::linear::SingleUDPHolePuncher;
+use crate::udp_traversal::progress::HolePunchEvent;
 use crate::udp_traversal::targetted_udp_socket_addr::HolePunchedUdpSocket;
 use crate::udp_traversal::{HolePunchID, NatTraversalMethod};
 use netbeam::reliable_conn::ReliableOrderedStreamToTarget;
@@ -20,6 +23,11 @@ use netbeam::sync::network_endpoint::NetworkEndpoint;
 use netbeam::sync::subscription::Subscribable;
 use netbeam::sync::RelativeNodeType;
 
+/// The number of sockets punched through to the peer alongside the winner in multi-path mode
+const SECONDARY_PATHS: usize = 2;
+/// Bounds the time spent punching secondary paths, which must not delay the winner for long
+const SECONDARY_PATH_TIMEOUT: Duration = Duration::from_millis(1500);
+
 /// Punches a hole using IPv4/6 addrs. IPv6 is more traversal-friendly since IP-translation between external and internal is not needed (unless the NAT admins are evil)
 ///
 /// allows the inclusion of a "breadth" variable to allow opening multiple ports for traversing across multiple ports
@@ -53,9 +61,21 @@ impl DualStackUdpHolePuncher {
             .locally_bound_sockets
             .take()
             .ok_or_else(|| anyhow::Error::msg("sockets already taken"))?;
-        let addrs_to_ping: &Vec<SocketAddr> = &hole_punch_config.into_iter().collect();
+        let mut addrs_to_ping =
+            hole_punch_config.into_prioritized_addrs(encrypted_config_container.candidate_policy());
+        if let Some(dual_stack_policy) = encrypted_config_container.dual_stack_policy() {
+            let candidate_count = addrs_to_ping.len();
+            addrs_to_ping = dual_stack_policy.apply(addrs_to_ping);
+            if addrs_to_ping.is_empty() && candidate_count != 0 {
+                return Err(anyhow::Error::msg(format!(
+                    "No candidates of the peer are admitted by {dual_stack_policy:?}"
+                )));
+            }
+        }
+        let addrs_to_ping = &addrs_to_ping;
 
         // each individual hole puncher fans-out from 1 bound socket to n many peer addrs (determined by addrs_to_ping)
+        let config = encrypted_config_container.clone();
         for socket in sockets {
             // TODO: ensure only *some* of the addrs in addrs_to_ping get passed (MAX 2)
             let hole_puncher = SingleUDPHolePuncher::new(
@@ -69,7 +89,7 @@ impl DualStackUdpHolePuncher {
 
         // TODO: Setup concurrent UPnP AND NAT-PMP async https://docs.rs/natpmp/latest/natpmp/struct.NatpmpAsync.html
         let task = async move {
-            tokio::task::spawn(drive(hole_punchers, relative_node_type, napp))
+            tokio::task::spawn(drive(hole_punchers, relative_node_type, napp, config))
                 .await
                 .map_err(|err| anyhow::Error::msg(format!("panic in hole puncher: {:?}", err)))?
         };
@@ -96,6 +116,7 @@ async fn drive(
     hole_punchers: Vec<SingleUDPHolePuncher>,
     node_type: RelativeNodeType,
     ref app: NetworkEndpoint,
+    ref config: HolePunchConfigContainer,
 ) -> Result<HolePunchedUdpSocket, anyhow::Error> {
     // We use a single mutex to resolve timing/priority conflicts automatically
     // Which ever node FIRST can set the value will "win"
@@ -144,19 +165,38 @@ async fn drive(
     let loser_value_set = &citadel_io::Mutex::new(None);
 
     let mut futures = FuturesUnordered::new();
-    for (kill_switch_rx, mut hole_puncher) in hole_punchers
-        .into_iter()
-        .map(|r| (kill_signal_tx.subscribe(), r))
-    {
+    for mut hole_puncher in hole_punchers {
+        let mut kill_switch_rx = kill_signal_tx.subscribe();
         futures.push(async move {
-            let res = hole_puncher
-                .try_method(
-                    NatTraversalMethod::Method3,
-                    kill_switch_rx,
-                    post_rebuild_tx.clone(),
-                )
-                .await;
-            (res, hole_puncher)
+            // each method is attempted in order until one succeeds, or the kill switch is called
+            let mut method = NatTraversalMethod::Method3;
+            loop {
+                let res = hole_puncher
+                    .try_method(method, &mut kill_switch_rx, post_rebuild_tx.clone())
+                    .await;
+
+                let next_method = match &res {
+                    Ok(_) | Err(FirewallError::Skip) => None,
+                    Err(err) => {
+                        let next_method = hole_puncher.get_next_method();
+                        log::trace!(target: "citadel", "Traversal method {method} failed for {:?}: {:?}. Next method: {:?}", hole_puncher.get_unique_id(), err, next_method);
+                        for candidate in hole_puncher.endpoints() {
+                            config.notify(HolePunchEvent::CandidateFailed {
+                                local_id: hole_puncher.get_unique_id(),
+                                candidate: *candidate,
+                                method,
+                                reason: err.to_string(),
+                            });
+                        }
+                        next_method
+                    }
+                };
+
+                match next_method {
+                    Some(next_method) => method = next_method,
+                    None => return (res, hole_puncher),
+                }
+            }
         });
     }
 
@@ -271,7 +311,9 @@ async fn drive(
                             log::trace!(target: "citadel", "*** Local won! Will command other side to use ({:?}, {:?})", peer_unique_id, local_id);
                             *net_lock = Some(());
                             socket.cleanse()?;
+                            let addr = socket.addr;
                             submit_final_candidate(socket)?;
+                            config.notify(HolePunchEvent::WinnerChosen { local_id, addr });
                             // Hold the mutex to prevent the other side from accessing the data. It will need to end via the other means
                             send(DualStackCandidate::MutexSet(peer_unique_id, local_id), conn)
                                 .await?;
@@ -318,7 +360,12 @@ async fn drive(
                 assert!(loser_value_set.lock().replace((local, remote)).is_none());
                 let hole_punched_socket = assert_rebuild_ready(local, remote).await?;
                 hole_punched_socket.cleanse()?;
+                let addr = hole_punched_socket.addr;
                 submit_final_candidate(hole_punched_socket)?;
+                config.notify(HolePunchEvent::WinnerChosen {
+                    local_id: local,
+                    addr,
+                });
                 // return here. The winner must exit last
                 send(DualStackCandidate::WinnerCanEnd, conn).await?;
                 signal_done()
@@ -344,12 +391,116 @@ async fn drive(
 
     log::trace!(target: "citadel", "*** ENDING DualStack ***");
 
-    let sock = final_candidate_rx.await?;
+    let mut sock = final_candidate_rx.await?;
     sock.cleanse()?;
 
+    if config.multipath_enabled() {
+        sock.secondary_paths = punch_secondary_paths(conn, &sock, node_type, config).await?;
+        log::trace!(target: "citadel", "*** Keeping {} secondary paths", sock.secondary_paths.len());
+    }
+
     Ok(sock)
 }
 
+/// Punches [`SECONDARY_PATHS`] more sockets through to the peer, returning those the peer holds as
+/// well in the same order on both nodes. Each fresh socket targets the port of its counterpart at
+/// the IP through which `primary` reached the peer, hence secondary paths are only punched through
+/// NATs that preserve ports across bindings of the same host
+async fn punch_secondary_paths<V: ReliableOrderedStreamToTarget>(
+    conn: &V,
+    primary: &HolePunchedUdpSocket,
+    node_type: RelativeNodeType,
+    config: &HolePunchConfigContainer,
+) -> Result<Vec<HolePunchedUdpSocket>, anyhow::Error> {
+    let bind_ip = primary.socket.local_addr()?.ip();
+    let sockets = (0..SECONDARY_PATHS)
+        .map(|_| get_udp_socket(SocketAddr::new(bind_ip, 0)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let local_ports = sockets
+        .iter()
+        .map(|socket| Ok(socket.local_addr()?.port()))
+        .collect::<Result<Vec<u16>, anyhow::Error>>()?;
+    send(&local_ports, conn).await?;
+    let peer_ports: Vec<u16> = receive(conn).await?;
+
+    let peer_ip = primary.addr.send_address.ip();
+    let mut hole_punchers = Vec::new();
+    for (socket, peer_port) in sockets.into_iter().zip(peer_ports) {
+        hole_punchers.push(SingleUDPHolePuncher::new(
+            node_type,
+            config.clone(),
+            socket,
+            vec![SocketAddr::new(peer_ip, peer_port)],
+        )?);
+    }
+
+    // no winner is chosen among these, thus the kill switch is never called. Its sender must
+    // outlive the attempts nonetheless, else each attempt takes the closed channel as a kill signal
+    let (kill_switch_tx, _) = tokio::sync::broadcast::channel(1);
+    let (post_rebuild_tx, _) = tokio::sync::mpsc::unbounded_channel();
+    let candidates = futures::future::join_all(hole_punchers.iter_mut().map(|hole_puncher| {
+        let mut kill_switch_rx = kill_switch_tx.subscribe();
+        let post_rebuild_tx = post_rebuild_tx.clone();
+        async move {
+            let res = tokio::time::timeout(
+                SECONDARY_PATH_TIMEOUT,
+                hole_puncher.try_method(
+                    NatTraversalMethod::Method3,
+                    &mut kill_switch_rx,
+                    post_rebuild_tx,
+                ),
+            )
+            .await;
+            match res {
+                Ok(Ok(socket)) => Some(socket),
+                Ok(Err(err)) => {
+                    log::trace!(target: "citadel", "Unable to punch secondary path: {err:?}");
+                    None
+                }
+                Err(_) => {
+                    log::trace!(target: "citadel", "Timed out punching secondary path");
+                    None
+                }
+            }
+        }
+    }))
+    .await;
+
+    let candidates = hole_punchers
+        .iter()
+        .map(SingleUDPHolePuncher::get_unique_id)
+        .zip(candidates)
+        .filter_map(|(local_id, socket)| {
+            socket.map(|socket| (local_id, socket.addr.unique_id, socket))
+        })
+        .collect();
+    agree_on_secondary_paths(conn, candidates).await
+}
+
+/// Returns the sockets that the peer holds as well, each keyed by the IDs of the local and the
+/// peer's hole puncher
+async fn agree_on_secondary_paths<V: ReliableOrderedStreamToTarget>(
+    conn: &V,
+    candidates: Vec<(HolePunchID, HolePunchID, HolePunchedUdpSocket)>,
+) -> Result<Vec<HolePunchedUdpSocket>, anyhow::Error> {
+    let local_pairs: Vec<(HolePunchID, HolePunchID)> = candidates
+        .iter()
+        .map(|(local_id, peer_id, _)| (*local_id, *peer_id))
+        .collect();
+    send(&local_pairs, conn).await?;
+    let peer_pairs: Vec<(HolePunchID, HolePunchID)> = receive(conn).await?;
+
+    let mut agreed = Vec::new();
+    for (local_id, peer_id, socket) in candidates {
+        if peer_pairs.contains(&(peer_id, local_id)) {
+            socket.cleanse()?;
+            agreed.push(socket);
+        }
+    }
+
+    Ok(agreed)
+}
+
 async fn send<R: Serialize, V: ReliableOrderedStreamToTarget>(
     ref input: R,
     conn: &V,
@@ -0,0 +1,42 @@
+//! Progress of a hole punch, reported per candidate
+//!
+//! Once an observer is registered via [`HolePunchConfigContainer::with_progress_observer`], each
+//! hole puncher reports the lifecycle of every candidate addr of the peer it targets, followed by
+//! the socket both nodes agreed upon. Events are dropped if the observer's receiver is gone, thus
+//! observing never stalls the traversal
+//!
+//! [`HolePunchConfigContainer::with_progress_observer`]: crate::udp_traversal::linear::encrypted_config_container::HolePunchConfigContainer::with_progress_observer
+use crate::udp_traversal::targetted_udp_socket_addr::TargettedSocketAddr;
+use crate::udp_traversal::{HolePunchID, NatTraversalMethod};
+use std::net::SocketAddr;
+use tokio::sync::mpsc::UnboundedSender;
+
+pub type HolePunchObserver = UnboundedSender<HolePunchEvent>;
+
+#[derive(Clone, Debug)]
+pub enum HolePunchEvent {
+    /// The hole puncher `local_id` began sending SYNs to `candidate`
+    SynSent {
+        local_id: HolePunchID,
+        candidate: SocketAddr,
+    },
+    /// The hole puncher `local_id` received a SYN-ACK for the SYN it sent to `candidate`, proving
+    /// that packets traverse the NATs in both directions
+    SynAckReceived {
+        local_id: HolePunchID,
+        peer_id: HolePunchID,
+        candidate: SocketAddr,
+    },
+    /// `method` failed to reach `candidate` from the hole puncher `local_id`
+    CandidateFailed {
+        local_id: HolePunchID,
+        candidate: SocketAddr,
+        method: NatTraversalMethod,
+        reason: String,
+    },
+    /// Both nodes agreed upon the socket of the hole puncher `local_id`, ending the hole punch
+    WinnerChosen {
+        local_id: HolePunchID,
+        addr: TargettedSocketAddr,
+    },
+}
@@ -0,0 +1,219 @@
+//! Re-punching of a UDP path whose NAT bindings died
+//!
+//! Both nodes keep the [`NetworkEndpoint`] that punched the original hole, and register a
+//! [`UdpRepuncher`] over it. The owner of the punched socket judges whether the bindings died,
+//! typically by whether the keep-alives it sends through the UDP channel are still answered, since
+//! only the channel reads the socket. Once they die, that node asks the peer to re-punch, and both
+//! run a fresh traversal over the endpoint, swapping the new socket in place of the old one
+//!
+//! If multi-path mode punched secondary paths alongside the dead one, these are handed to the
+//! repuncher as spares, and each re-punch fails over to the next spare before traversing afresh
+use crate::udp_traversal::linear::encrypted_config_container::HolePunchConfigContainer;
+use crate::udp_traversal::targetted_udp_socket_addr::HolePunchedUdpSocket;
+use crate::udp_traversal::udp_hole_puncher::EndpointHolePunchExt;
+use netbeam::multiplex::OwnedMultiplexedSubscription;
+use netbeam::reliable_conn::ReliableOrderedStreamToTargetExt;
+use netbeam::sync::network_endpoint::NetworkEndpoint;
+use netbeam::sync::subscription::Subscribable;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Well below the UDP binding lifetime of common NATs, which is seldom under 30 seconds
+const SPARE_KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Sent by each node once per re-punch. The node that found the bindings dead sends it first, and
+/// the peer answers with its own
+#[derive(Serialize, Deserialize)]
+struct RepunchRequest;
+
+/// Coordinates re-punching the UDP path between two nodes over the endpoint that first punched it
+pub struct UdpRepuncher {
+    conn: NetworkEndpoint,
+    encrypted_config: HolePunchConfigContainer,
+    control: OwnedMultiplexedSubscription,
+    spare_paths: Arc<citadel_io::Mutex<VecDeque<HolePunchedUdpSocket>>>,
+    spare_keep_alive: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl UdpRepuncher {
+    /// Opens the control stream over which re-punches are requested. Both nodes must register
+    /// concurrently, and before either re-punches
+    pub async fn register(
+        conn: NetworkEndpoint,
+        encrypted_config: HolePunchConfigContainer,
+    ) -> Result<Self, anyhow::Error> {
+        let control = conn.initiate_subscription().await?;
+        Ok(Self {
+            conn,
+            encrypted_config,
+            control,
+            spare_paths: Default::default(),
+            spare_keep_alive: None,
+        })
+    }
+
+    /// Holds `spare_paths`, typically the [`HolePunchedUdpSocket::secondary_paths`] of the dead
+    /// socket, to fail over to in order before any fresh traversal. Both nodes must pass the paths
+    /// they agreed upon, in the same order. Until taken, the bindings of each are kept alive
+    pub fn with_spare_paths(mut self, spare_paths: Vec<HolePunchedUdpSocket>) -> Self {
+        if spare_paths.is_empty() {
+            return self;
+        }
+
+        *self.spare_paths.lock() = spare_paths.into();
+        let spare_paths = self.spare_paths.clone();
+        let encrypted_config = self.encrypted_config.clone();
+        let keep_alive = async move {
+            // the spares were just punched, thus need no keep-alive until the interval elapses
+            let mut interval = tokio::time::interval_at(
+                tokio::time::Instant::now() + SPARE_KEEP_ALIVE_INTERVAL,
+                SPARE_KEEP_ALIVE_INTERVAL,
+            );
+            loop {
+                let _ = interval.tick().await;
+                let packet = encrypted_config.generate_packet(b"");
+                for spare in spare_paths.lock().iter() {
+                    if let Err(err) = spare.socket.try_send_to(&packet, spare.addr.send_address) {
+                        log::trace!(target: "citadel", "Unable to keep spare UDP path alive: {err:?}");
+                    }
+                }
+            }
+        };
+
+        self.spare_keep_alive = Some(tokio::task::spawn(keep_alive));
+        self
+    }
+
+    /// Resolves once the peer asks to re-punch, after which [`Self::repunch`] must be called with
+    /// `requested_by_peer` set. Cancel safe, hence may be raced against the UDP channel
+    pub async fn recv_request(&self) -> Result<(), anyhow::Error> {
+        let _: RepunchRequest = self.control.recv_serialized().await?;
+        Ok(())
+    }
+
+    /// Punches a fresh hole to the peer, returning the socket to swap in place of the dead one.
+    /// Unless the peer asked first, the peer is asked to join. Until this returns, the UDP channel
+    /// must neither read from nor write to the dead socket, which the caller should then drop
+    pub async fn repunch(
+        &self,
+        requested_by_peer: bool,
+    ) -> Result<HolePunchedUdpSocket, anyhow::Error> {
+        self.control.send_serialized(RepunchRequest).await?;
+        if !requested_by_peer {
+            // should both nodes find the bindings dead at once, each request answers the other
+            self.recv_request().await?;
+        }
+
+        // both nodes hold the same spares, thus take them in lockstep
+        let spare = self.spare_paths.lock().pop_front();
+        if let Some(spare) = spare {
+            log::trace!(target: "citadel", "Failing over to spare UDP path {} (requested by peer: {requested_by_peer})", spare.addr);
+            // drops the keep-alives the peer sent while the path was spare
+            spare.cleanse()?;
+            return Ok(spare);
+        }
+
+        log::trace!(target: "citadel", "Re-punching UDP path (requested by peer: {requested_by_peer})");
+        self.conn
+            .begin_udp_hole_punch(self.encrypted_config.clone())
+            .await
+    }
+}
+
+impl Drop for UdpRepuncher {
+    fn drop(&mut self) {
+        if let Some(keep_alive) = self.spare_keep_alive.take() {
+            keep_alive.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::udp_traversal::linear::encrypted_config_container::HolePunchConfigContainer;
+    use crate::udp_traversal::repunch::UdpRepuncher;
+    use crate::udp_traversal::udp_hole_puncher::EndpointHolePunchExt;
+    use netbeam::sync::network_endpoint::NetworkEndpoint;
+    use netbeam::sync::test_utils::create_streams_with_addrs;
+
+    #[tokio::test]
+    async fn test_repunch() {
+        citadel_logging::setup_log();
+        let (server_stream, client_stream) = create_streams_with_addrs().await;
+
+        let node = |conn: NetworkEndpoint, initiator: bool| async move {
+            let socket = conn.begin_udp_hole_punch(Default::default()).await.unwrap();
+            let repuncher = UdpRepuncher::register(conn, Default::default())
+                .await
+                .unwrap();
+            // the initiator finds the binding dead, while the other node learns of it from the peer
+            if !initiator {
+                repuncher.recv_request().await.unwrap();
+            }
+            let fresh = repuncher.repunch(!initiator).await.unwrap();
+            (socket.socket.local_addr().unwrap(), fresh)
+        };
+
+        let (server, client) = tokio::join!(
+            citadel_io::spawn(node(server_stream, true)),
+            citadel_io::spawn(node(client_stream, false))
+        );
+        let ((server_dead_addr, server), (client_dead_addr, client)) =
+            (server.unwrap(), client.unwrap());
+        assert_ne!(server.socket.local_addr().unwrap(), server_dead_addr);
+        assert_ne!(client.socket.local_addr().unwrap(), client_dead_addr);
+
+        // the fresh hole carries traffic
+        let buf = &mut [0u8; 64];
+        let payload = b"after re-punch" as &[u8];
+        let _ = server
+            .socket
+            .send_to(payload, server.addr.send_address)
+            .await
+            .unwrap();
+        let len = client.socket.recv(buf).await.unwrap();
+        assert_eq!(&buf[..len], payload);
+    }
+
+    #[tokio::test]
+    async fn test_repunch_fails_over_to_spare_path() {
+        citadel_logging::setup_log();
+        let (server_stream, client_stream) = create_streams_with_addrs().await;
+        let config = HolePunchConfigContainer::default().with_multipath(true);
+
+        let node = |conn: NetworkEndpoint, config: HolePunchConfigContainer, initiator: bool| async move {
+            let mut socket = conn.begin_udp_hole_punch(config.clone()).await.unwrap();
+            let spare_paths = std::mem::take(&mut socket.secondary_paths);
+            let spare_addr = spare_paths[0].socket.local_addr().unwrap();
+            let repuncher = UdpRepuncher::register(conn, config)
+                .await
+                .unwrap()
+                .with_spare_paths(spare_paths);
+            if !initiator {
+                repuncher.recv_request().await.unwrap();
+            }
+            (spare_addr, repuncher.repunch(!initiator).await.unwrap())
+        };
+
+        let (server, client) = tokio::join!(
+            citadel_io::spawn(node(server_stream, config.clone(), true)),
+            citadel_io::spawn(node(client_stream, config, false))
+        );
+        let ((server_spare_addr, server), (client_spare_addr, client)) =
+            (server.unwrap(), client.unwrap());
+        assert_eq!(server.socket.local_addr().unwrap(), server_spare_addr);
+        assert_eq!(client.socket.local_addr().unwrap(), client_spare_addr);
+
+        let buf = &mut [0u8; 64];
+        let payload = b"after failover" as &[u8];
+        let _ = server
+            .socket
+            .send_to(payload, server.addr.send_address)
+            .await
+            .unwrap();
+        let len = client.socket.recv(buf).await.unwrap();
+        assert_eq!(&buf[..len], payload);
+    }
+}
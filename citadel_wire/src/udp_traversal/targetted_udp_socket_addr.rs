@@ -58,10 +58,23 @@ impl Display for TargettedSocketAddr {
     }
 }
 
+/// A socket punched through to the peer. The NAT bindings of the hole expire once the socket idles,
+/// hence the owner of the socket must send keep-alives through it, as the UDP channel of a session
+/// does with the same ratchet that encrypts its other packets
 #[derive(Debug)]
 pub struct HolePunchedUdpSocket {
     pub socket: UdpSocket,
     pub addr: TargettedSocketAddr,
+    /// The external addr at which the local router forwards packets to `socket`, if a port mapping
+    /// was obtained via UPnP, PCP or NAT-PMP. Unlike the addr observed by the peer, it is reachable
+    /// by any node, hence may be advertised to other peers
+    pub mapped_addr: Option<SocketAddr>,
+    /// The other sockets punched through to the peer, if multi-path mode is enabled via
+    /// [`HolePunchConfigContainer::with_multipath`]. Each is also held by the peer, in the same
+    /// order, thus may carry UDP traffic alongside this socket, or replace it once its bindings die
+    ///
+    /// [`HolePunchConfigContainer::with_multipath`]: crate::udp_traversal::linear::encrypted_config_container::HolePunchConfigContainer::with_multipath
+    pub secondary_paths: Vec<HolePunchedUdpSocket>,
 }
 
 impl HolePunchedUdpSocket {
@@ -1,6 +1,10 @@
-use crate::nat_identification::NatType;
+#[cfg(feature = "lan-discovery")]
+use crate::lan_discovery::LanAdvertisement;
+use crate::nat_identification::{NatBehavior, NatType, PortAllocation};
+use crate::udp_traversal::candidate_priority::DualStackPolicy;
 use crate::udp_traversal::hole_punch_config::HolePunchConfig;
 use crate::udp_traversal::linear::encrypted_config_container::HolePunchConfigContainer;
+use crate::udp_traversal::linear::method3::Method3;
 use crate::udp_traversal::multi::DualStackUdpHolePuncher;
 use crate::udp_traversal::targetted_udp_socket_addr::HolePunchedUdpSocket;
 use citadel_io::UdpSocket;
@@ -8,6 +12,8 @@ use futures::Future;
 use netbeam::reliable_conn::ReliableOrderedStreamToTargetExt;
 use netbeam::sync::network_endpoint::NetworkEndpoint;
 use netbeam::sync::subscription::Subscribable;
+#[cfg(feature = "lan-discovery")]
+use std::net::SocketAddr;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 use std::time::Duration;
@@ -17,6 +23,11 @@ pub struct UdpHolePuncher<'a> {
 }
 
 const DEFAULT_TIMEOUT: Duration = Duration::from_millis(6000);
+/// Bounds the wait for a peer on the LAN, since traversal waits on it
+#[cfg(feature = "lan-discovery")]
+const LAN_PROBE_TIMEOUT: Duration = Duration::from_millis(750);
+/// Bounds the wait for the TTL probes of the peer
+const TTL_PROBE_WINDOW: Duration = Duration::from_millis(500);
 
 impl<'a> UdpHolePuncher<'a> {
     pub fn new(
@@ -64,29 +75,108 @@ async fn driver(
     // create stream
     let stream = &(conn.initiate_subscription().await?);
     let stun_servers = encrypted_config_container.take_stun_servers();
-    let local_nat_type = &(NatType::identify(stun_servers)
-        .await
-        .map_err(|err| anyhow::Error::msg(err.to_string()))?);
+    let (local_nat_type, local_nat_behavior) = tokio::join!(
+        NatType::identify(stun_servers.clone()),
+        NatBehavior::discover(stun_servers.clone())
+    );
+    let local_nat_type = &(local_nat_type.map_err(|err| anyhow::Error::msg(err.to_string()))?);
+    // the behavior only narrows down the traversal attempted, thus its absence is not fatal
+    let local_nat_behavior = &(local_nat_behavior.unwrap_or_else(|err| {
+        log::warn!(target: "citadel", "Unable to discover NAT behavior: {:?}", err);
+        NatBehavior::default()
+    }));
 
-    stream.send_serialized(local_nat_type).await?;
-    let peer_nat_type = &(stream.recv_serialized::<NatType>().await?);
+    // the instance is unique per traversal, such that only the peer of this traversal is found
+    let local_lan_instance = (cfg!(feature = "lan-discovery")
+        && encrypted_config_container.lan_discovery_enabled())
+    .then(|| uuid::Uuid::new_v4().as_simple().to_string());
 
-    log::trace!(target: "citadel", "[driver] Local NAT type: {:?} | Peer NAT type: {:?}", local_nat_type, peer_nat_type);
-    let local_initial_socket = get_optimal_bind_socket(local_nat_type, peer_nat_type)?;
+    stream
+        .send_serialized((local_nat_type, local_nat_behavior, &local_lan_instance))
+        .await?;
+    let (peer_nat_type, peer_nat_behavior, peer_lan_instance) = &(stream
+        .recv_serialized::<(NatType, NatBehavior, Option<String>)>()
+        .await?);
+
+    log::trace!(target: "citadel", "[driver] Local NAT type: {:?} ({:?}) | Peer NAT type: {:?} ({:?})", local_nat_type, local_nat_behavior, peer_nat_type, peer_nat_behavior);
+    let local_initial_socket = match encrypted_config_container.dual_stack_policy() {
+        Some(DualStackPolicy::V4Only) => crate::socket_helpers::get_udp_socket("0.0.0.0:0")?,
+        Some(DualStackPolicy::V6Only) => crate::socket_helpers::get_udp_socket("[::]:0")?,
+        _ => get_optimal_bind_socket(local_nat_type, peer_nat_type)?,
+    };
     let internal_bind_port = local_initial_socket.local_addr()?.port();
 
-    // exchange internal bind port, also synchronizing the beginning of the hole punch process
-    // while doing so
-    let peer_internal_bind_port = conn.sync_exchange_payload(internal_bind_port).await?;
+    // a peer at the same external IP may share the LAN, in which case no NAT sits between the nodes
+    let peer_lan_addr = match (local_lan_instance, peer_lan_instance) {
+        #[cfg(feature = "lan-discovery")]
+        (Some(local_instance), Some(peer_instance))
+            if local_nat_type.shares_external_ip_with(peer_nat_type) =>
+        {
+            probe_lan(conn, local_instance, peer_instance, internal_bind_port).await?
+        }
+
+        _ => None,
+    };
+
+    let local_mapped_addr =
+        if peer_lan_addr.is_none() && encrypted_config_container.port_mapping_enabled() {
+            HolePunchConfig::request_port_mapping(&local_initial_socket).await
+        } else {
+            None
+        };
+
+    // a symmetric NAT allocates a new port per destination. Measuring its allocation right before
+    // traversal lets the peer predict the ports allocated next
+    let local_port_allocation = if peer_lan_addr.is_none()
+        && local_mapped_addr.is_none()
+        && local_nat_type.is_symmetric()
+    {
+        PortAllocation::measure(stun_servers)
+            .await
+            .unwrap_or_else(|err| {
+                log::warn!(target: "citadel", "Unable to measure port allocation: {:?}", err);
+                None
+            })
+    } else {
+        None
+    };
+
+    // exchange internal bind port, any port mapping and port allocation, also synchronizing the beginning of
+    // the hole punch process while doing so
+    let (peer_internal_bind_port, peer_mapped_addr, peer_port_allocation) = conn
+        .sync_exchange_payload((internal_bind_port, local_mapped_addr, local_port_allocation))
+        .await?;
 
     // the next functions takes everything insofar obtained into account without causing collisions with any existing
     // connections (e.g., no conflicts with the primary stream existing in conn)
-    let hole_punch_config = HolePunchConfig::new(
-        local_nat_type,
-        peer_nat_type,
-        local_initial_socket,
-        peer_internal_bind_port,
-    )?;
+    let hole_punch_config = if let Some(peer_lan_addr) = peer_lan_addr {
+        log::trace!(target: "citadel", "[driver] Peer discovered on the LAN ({:?}); will connect directly", peer_lan_addr);
+        HolePunchConfig::from_lan_addr(peer_lan_addr, local_initial_socket)
+    } else if local_mapped_addr.is_some() || peer_mapped_addr.is_some() {
+        log::trace!(target: "citadel", "[driver] Port mapped (local: {:?} | peer: {:?}); will connect directly", local_mapped_addr, peer_mapped_addr);
+        HolePunchConfig::from_port_mapping(peer_mapped_addr, local_initial_socket)
+    } else {
+        log::trace!(target: "citadel", "[driver] Port allocation (local: {:?} | peer: {:?})", local_port_allocation, peer_port_allocation);
+        HolePunchConfig::new_with_behavior(
+            &local_nat_type.with_port_allocation(local_port_allocation),
+            local_nat_behavior,
+            &peer_nat_type.with_port_allocation(peer_port_allocation),
+            peer_nat_behavior,
+            local_initial_socket,
+            peer_internal_bind_port,
+        )?
+    };
+
+    // a direct connection needs no TTL tuning
+    let encrypted_config_container = if encrypted_config_container.method3_config().adaptive_ttl
+        && peer_lan_addr.is_none()
+        && local_mapped_addr.is_none()
+        && peer_mapped_addr.is_none()
+    {
+        adapt_ttl(conn, encrypted_config_container, &hole_punch_config).await?
+    } else {
+        encrypted_config_container
+    };
 
     let conn = conn.clone();
     log::trace!(target: "citadel", "[driver] Synchronized; will now execute dualstack hole-puncher ... config: {:?}", hole_punch_config);
@@ -97,13 +187,85 @@ async fn driver(
         conn,
     )?
     .await;
-    res.map_err(|err| {
+    res.map(|mut hole_punched_socket| {
+        hole_punched_socket.mapped_addr = local_mapped_addr;
+        hole_punched_socket
+    })
+    .map_err(|err| {
         anyhow::Error::msg(format!(
             "**HOLE-PUNCH-ERR**: {err:?} | local_nat_type: {local_nat_type:?} | peer_nat_type: {peer_nat_type:?}",
         ))
     })
 }
 
+/// Probes the LAN for the peer, returning Some if either node discovered the other, along with the
+/// internal addr of the peer if this node discovered it. Both nodes exchange their results, such
+/// that both skip traversal, even if only one node received the advertisement of the other
+#[cfg(feature = "lan-discovery")]
+async fn probe_lan(
+    conn: &NetworkEndpoint,
+    local_instance: String,
+    peer_instance: &str,
+    internal_bind_port: u16,
+) -> Result<Option<Option<SocketAddr>>, anyhow::Error> {
+    let advertisement = LanAdvertisement {
+        instance: local_instance,
+        port: internal_bind_port,
+        properties: Default::default(),
+    };
+
+    let local_found =
+        crate::lan_discovery::probe_peer(&advertisement, peer_instance, LAN_PROBE_TIMEOUT)
+            .await
+            .unwrap_or_else(|err| {
+                log::warn!(target: "citadel", "Unable to probe the LAN: {:?}", err);
+                None
+            });
+    let peer_found: Option<SocketAddr> = conn.sync_exchange_payload(local_found).await?;
+
+    Ok((local_found.is_some() || peer_found.is_some()).then_some(local_found))
+}
+
+/// Measures the hop count to the peer alongside the peer, returning the config with the short TTL
+/// of [`Method3`] derived from it. The config is unchanged if the peer's probes did not arrive
+///
+/// [`Method3`]: crate::udp_traversal::linear::method3::Method3
+async fn adapt_ttl(
+    conn: &NetworkEndpoint,
+    encrypted_config_container: HolePunchConfigContainer,
+    hole_punch_config: &HolePunchConfig,
+) -> Result<HolePunchConfigContainer, anyhow::Error> {
+    let hops_from_peer = match hole_punch_config.first_socket() {
+        Some(socket) => {
+            let endpoints =
+                hole_punch_config.prioritized_addrs(encrypted_config_container.candidate_policy());
+            Method3::probe_hop_count(
+                socket,
+                &endpoints,
+                &encrypted_config_container,
+                TTL_PROBE_WINDOW,
+            )
+            .await
+        }
+
+        None => None,
+    };
+
+    // the hops from the peer to this node are the hops the peer's packets travel, and vice versa
+    let hops_to_peer: Option<u32> = conn.sync_exchange_payload(hops_from_peer).await?;
+    Ok(match hops_to_peer {
+        Some(hops) => {
+            let method3_config = encrypted_config_container
+                .method3_config()
+                .with_measured_hops(hops);
+            log::trace!(target: "citadel", "[driver] Measured {hops} hops to the peer; short TTL: {}", method3_config.ttl_init);
+            encrypted_config_container.with_method3_config(method3_config)
+        }
+
+        None => encrypted_config_container,
+    })
+}
+
 /// since the NAT traversal process always ensures that both public-facing and loopback
 /// cases are covered, we can start by binding to 0.0.0.0, knowing that 127.0.0.1 will
 /// also be covered automatically
@@ -166,6 +328,8 @@ impl EndpointHolePunchExt for NetworkEndpoint {
 
 #[cfg(test)]
 mod tests {
+    use crate::udp_traversal::linear::encrypted_config_container::HolePunchConfigContainer;
+    use crate::udp_traversal::progress::HolePunchEvent;
     use crate::udp_traversal::udp_hole_puncher::EndpointHolePunchExt;
     use netbeam::sync::test_utils::create_streams_with_addrs_and_lag;
     use rstest::rstest;
@@ -220,4 +384,84 @@ mod tests {
         //assert_eq!(res0.addr.receive_address, addr);
         log::trace!(target: "citadel", "D");
     }
+
+    #[tokio::test]
+    async fn test_hole_punch_progress() {
+        citadel_logging::setup_log();
+
+        let (server_stream, client_stream) = create_streams_with_addrs_and_lag(0).await;
+        let (observer, mut events) = tokio::sync::mpsc::unbounded_channel();
+        let config = HolePunchConfigContainer::default().with_progress_observer(observer);
+
+        let server =
+            citadel_io::spawn(
+                async move { server_stream.begin_udp_hole_punch(config).await.unwrap() },
+            );
+        let client = citadel_io::spawn(async move {
+            client_stream
+                .begin_udp_hole_punch(Default::default())
+                .await
+                .unwrap()
+        });
+        let (res0, res1) = tokio::join!(server, client);
+        let res0 = res0.unwrap();
+        let _ = res1.unwrap();
+
+        let mut syn_sent = false;
+        let mut winner = None;
+        while let Ok(event) = events.try_recv() {
+            match event {
+                HolePunchEvent::SynSent { .. } => syn_sent = true,
+                HolePunchEvent::WinnerChosen { addr, .. } => winner = Some(addr),
+                _ => {}
+            }
+        }
+
+        assert!(syn_sent);
+        assert_eq!(winner, Some(res0.addr));
+    }
+
+    #[tokio::test]
+    async fn test_multipath() {
+        citadel_logging::setup_log();
+
+        let (server_stream, client_stream) = create_streams_with_addrs_and_lag(0).await;
+        let config = HolePunchConfigContainer::default().with_multipath(true);
+        let config2 = config.clone();
+
+        let server =
+            citadel_io::spawn(
+                async move { server_stream.begin_udp_hole_punch(config).await.unwrap() },
+            );
+        let client =
+            citadel_io::spawn(
+                async move { client_stream.begin_udp_hole_punch(config2).await.unwrap() },
+            );
+        let (res0, res1) = tokio::join!(server, client);
+        let (res0, res1) = (res0.unwrap(), res1.unwrap());
+
+        // both nodes agree on the secondary paths, in the same order
+        assert!(!res0.secondary_paths.is_empty());
+        assert_eq!(res0.secondary_paths.len(), res1.secondary_paths.len());
+        let buf = &mut [0u8; 64];
+        for (server, client) in res0.secondary_paths.iter().zip(&res1.secondary_paths) {
+            assert_ne!(
+                server.socket.local_addr().unwrap(),
+                res0.socket.local_addr().unwrap()
+            );
+            assert_eq!(
+                server.addr.send_address.port(),
+                client.socket.local_addr().unwrap().port()
+            );
+
+            let payload = b"secondary path" as &[u8];
+            let _ = server
+                .socket
+                .send_to(payload, server.addr.send_address)
+                .await
+                .unwrap();
+            let len = client.socket.recv(buf).await.unwrap();
+            assert_eq!(&buf[..len], payload);
+        }
+    }
 }
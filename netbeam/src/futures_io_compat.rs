@@ -0,0 +1,186 @@
+//! Runs netbeam over byte streams implementing the `futures` I/O traits
+//!
+//! [`FuturesIoStream`] adapts any [`AsyncRead`] + [`AsyncWrite`] byte stream into a
+//! [`ReliableOrderedStreamToTarget`], such that the synchronization primitives and NAT traversal
+//! drivers built upon netbeam run over transports outside of tokio. This crate does not depend on
+//! any such transport: for instance, a libp2p substream may be wrapped directly, but establishing
+//! the connection and negotiating the substream is left to the caller
+//!
+//! Since byte streams carry bytes rather than packets, each packet is framed with a length prefix.
+//! Both ends of the stream must therefore use a [`FuturesIoStream`]
+use crate::reliable_conn::{ConnAddr, ReliableOrderedStreamToTarget};
+use async_trait::async_trait;
+use bytes::{Buf, Bytes, BytesMut};
+use futures::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadHalf, WriteHalf};
+use std::net::SocketAddr;
+use tokio::sync::Mutex;
+
+/// The largest packet accepted from the remote end
+const MAX_PACKET_LEN: usize = 64 * 1024 * 1024;
+const LENGTH_PREFIX_LEN: usize = 4;
+const READ_CHUNK_LEN: usize = 8192;
+
+/// A packet-oriented stream over a `futures` byte stream
+pub struct FuturesIoStream<T> {
+    reader: Mutex<FramedReader<T>>,
+    writer: Mutex<WriteHalf<T>>,
+    local_addr: SocketAddr,
+    peer_addr: SocketAddr,
+}
+
+struct FramedReader<T> {
+    reader: ReadHalf<T>,
+    // bytes read, but not yet returned as a packet. Kept across calls to recv such that a
+    // cancelled recv does not lose a partially read packet
+    buffer: BytesMut,
+}
+
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> FuturesIoStream<T> {
+    /// Wraps `stream`. Since the byte stream may not be addressed by sockets, the socket addresses
+    /// are supplied by the caller, typically from the endpoint of the underlying connection. If
+    /// the connection is relayed, `peer_addr` should be the address of the peer, not of the relay
+    pub fn new(stream: T, local_addr: SocketAddr, peer_addr: SocketAddr) -> Self {
+        let (reader, writer) = stream.split();
+        Self {
+            reader: Mutex::new(FramedReader {
+                reader,
+                buffer: BytesMut::new(),
+            }),
+            writer: Mutex::new(writer),
+            local_addr,
+            peer_addr,
+        }
+    }
+}
+
+#[async_trait]
+impl<T: AsyncRead + AsyncWrite + Send + Unpin> ReliableOrderedStreamToTarget
+    for FuturesIoStream<T>
+{
+    /// Not cancel safe: dropping the future mid-packet desynchronizes the framing
+    async fn send_to_peer(&self, input: &[u8]) -> std::io::Result<()> {
+        if input.len() > MAX_PACKET_LEN {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "Packet exceeds the maximum length",
+            ));
+        }
+
+        let mut writer = self.writer.lock().await;
+        writer
+            .write_all(&(input.len() as u32).to_be_bytes())
+            .await?;
+        writer.write_all(input).await?;
+        writer.flush().await
+    }
+
+    /// Cancel safe: the bytes of a partially received packet are retained for the next call
+    async fn recv(&self) -> std::io::Result<Bytes> {
+        let mut state = self.reader.lock().await;
+        let FramedReader { reader, buffer } = &mut *state;
+        loop {
+            if let Some(packet) = take_packet(buffer)? {
+                return Ok(packet);
+            }
+
+            let mut chunk = [0u8; READ_CHUNK_LEN];
+            let read = reader.read(&mut chunk).await?;
+            if read == 0 {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::UnexpectedEof,
+                    "Stream ended",
+                ));
+            }
+
+            buffer.extend_from_slice(&chunk[..read]);
+        }
+    }
+}
+
+/// Splits the next complete packet off the front of `buffer`, if one was fully received
+fn take_packet(buffer: &mut BytesMut) -> std::io::Result<Option<Bytes>> {
+    if buffer.len() < LENGTH_PREFIX_LEN {
+        return Ok(None);
+    }
+
+    let mut len = [0u8; LENGTH_PREFIX_LEN];
+    len.copy_from_slice(&buffer[..LENGTH_PREFIX_LEN]);
+    let len = u32::from_be_bytes(len) as usize;
+    if len > MAX_PACKET_LEN {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Packet exceeds the maximum length",
+        ));
+    }
+
+    let frame_len = LENGTH_PREFIX_LEN + len;
+    if buffer.len() < frame_len {
+        buffer.reserve(frame_len - buffer.len());
+        return Ok(None);
+    }
+
+    buffer.advance(LENGTH_PREFIX_LEN);
+    Ok(Some(buffer.split_to(len).freeze()))
+}
+
+impl<T> ConnAddr for FuturesIoStream<T> {
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+
+    fn peer_addr(&self) -> std::io::Result<SocketAddr> {
+        Ok(self.peer_addr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::futures_io_compat::FuturesIoStream;
+    use crate::reliable_conn::{ConnAddr, ReliableOrderedStreamToTarget};
+    use std::time::Duration;
+    use tokio::io::AsyncWriteExt;
+    use tokio_util::compat::TokioAsyncReadCompatExt;
+
+    #[tokio::test]
+    async fn test_packets_preserve_boundaries() {
+        let (a, b) = tokio::io::duplex(64);
+        let local = "127.0.0.1:4001".parse().unwrap();
+        let remote = "127.0.0.1:4002".parse().unwrap();
+        let a = FuturesIoStream::new(a.compat(), local, remote);
+        let b = FuturesIoStream::new(b.compat(), remote, local);
+        assert_eq!(a.peer_addr().unwrap(), remote);
+
+        let large = vec![9u8; 1000];
+        let sender = async {
+            a.send_to_peer(b"hello").await.unwrap();
+            a.send_to_peer(&[]).await.unwrap();
+            a.send_to_peer(&large).await.unwrap();
+        };
+        let receiver = async {
+            assert_eq!(b.recv().await.unwrap().as_ref(), b"hello");
+            assert!(b.recv().await.unwrap().is_empty());
+            assert_eq!(b.recv().await.unwrap().as_ref(), large.as_slice());
+        };
+        tokio::join!(sender, receiver);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_recv_keeps_partial_packet() {
+        let (mut a, b) = tokio::io::duplex(64);
+        let local = "127.0.0.1:4001".parse().unwrap();
+        let remote = "127.0.0.1:4002".parse().unwrap();
+        let b = FuturesIoStream::new(b.compat(), remote, local);
+
+        a.write_all(&5u32.to_be_bytes()).await.unwrap();
+        a.write_all(b"he").await.unwrap();
+        assert!(tokio::time::timeout(Duration::from_millis(100), b.recv())
+            .await
+            .is_err());
+
+        a.write_all(b"llo").await.unwrap();
+        a.write_all(&2u32.to_be_bytes()).await.unwrap();
+        a.write_all(b"hi").await.unwrap();
+        assert_eq!(b.recv().await.unwrap().as_ref(), b"hello");
+        assert_eq!(b.recv().await.unwrap().as_ref(), b"hi");
+    }
+}
@@ -3,6 +3,8 @@
 use std::future::Future;
 use std::pin::Pin;
 
+#[cfg(feature = "futures-io-compat")]
+pub mod futures_io_compat;
 pub mod reliable_conn;
 pub mod sync;
 pub mod time_tracker;